@@ -31,6 +31,7 @@
 
 #include <algorithm>
 #include <cmath>
+#include <clocale>
 #include <cstdio>
 #include <iomanip>
 #include <iostream>
@@ -245,6 +246,10 @@ void tidyup()
 // Global main function
 int main(int argc, char* argv[])
 {
+	// Numbers in the output and in angle arguments always use '.' as decimal separator,
+	// regardless of the user's locale
+	setlocale(LC_NUMERIC, "C");
+
 	// At least we need one argument or option
 	if (argc < 2) {
 		syntax();
@@ -0,0 +1,50 @@
+//! Throughput of grid lookups
+//!
+//! Run with `cargo bench --bench lookup`. Prints the time per lookup for nearest neighbour and
+//! cell queries on a 5° x 5° grid, without and with a rotated orientation.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use opendaff::{EquiangularGrid, GridLookup, Orientation};
+
+const LOOKUPS: usize = 1 << 22;
+
+fn measure(name: &str, mut lookup: impl FnMut(f32, f32) -> usize) {
+    // A source circling the listener while rising and falling
+    let directions: Vec<(f32, f32)> = (0..4096)
+        .map(|i| {
+            let t = i as f32 * 0.173;
+            (t.rem_euclid(360.0) - 180.0, 80.0 * (t * 0.01).sin())
+        })
+        .collect();
+    let start = Instant::now();
+    let mut sum = 0usize;
+    for i in 0..LOOKUPS {
+        let (azimuth, elevation) = directions[i % directions.len()];
+        sum = sum.wrapping_add(lookup(black_box(azimuth), black_box(elevation)));
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "{:<24} {:>8.2} ns/lookup",
+        name,
+        elapsed.as_nanos() as f64 / LOOKUPS as f64
+    );
+}
+
+fn main() {
+    let grid = EquiangularGrid::with_resolution(5.0, 5.0).unwrap();
+    let rotated = Orientation {
+        yaw: 30.0,
+        pitch: 10.0,
+        roll: 0.0,
+    };
+    for (label, orientation) in [("", Orientation::default()), (" (rotated)", rotated)] {
+        let lookup = GridLookup::new(&grid, orientation).unwrap();
+        measure(&format!("nearest_neighbour{}", label), |a, e| {
+            lookup.nearest_neighbour(a, e)
+        });
+        measure(&format!("cell{}", label), |a, e| lookup.cell(a, e)[0]);
+    }
+}
@@ -2,6 +2,11 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    // The C++ library is not available on wasm32, the crate uses the pure-Rust parser there
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
+        return;
+    }
+
     // Get the build output directory
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
 
@@ -16,7 +21,18 @@ fn main() {
     println!("cargo:rustc-link-search=native=/usr/local/lib");
     println!("cargo:rustc-link-search=native=/usr/lib");
 
-    // Link the wrapper library
+    // Link the wrapper library. With the abort-on-exception feature, it is compiled here with
+    // DAFFRUST_ABORT_ON_EXCEPTION defined, as the CMake option does, and linked statically
+    #[cfg(feature = "abort-on-exception")]
+    cc::Build::new()
+        .cpp(true)
+        .std("c++17")
+        .file("daff_rust_wrapper.cpp")
+        .include(&manifest_dir)
+        .include(PathBuf::from(&manifest_dir).join("../../include"))
+        .define("DAFFRUST_ABORT_ON_EXCEPTION", None)
+        .compile("daffrustwrapper");
+    #[cfg(not(feature = "abort-on-exception"))]
     println!("cargo:rustc-link-lib=dylib=daffrustwrapper");
 
     // Also link the main DAFF library
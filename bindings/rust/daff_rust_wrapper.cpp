@@ -13,6 +13,11 @@
 
 #include <DAFF.h>
 
+#include <algorithm>
+#include <cmath>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
 #include <cstring>
 #include <string>
 #include <vector>
@@ -30,473 +35,813 @@ const char* RustDAFF_GetLastError()
 	return g_lastError.c_str();
 }
 
+namespace
+{
+	// Every entry point runs its body through Guarded(), so that no C++ exception unwinds
+	// into the caller: exceptions are reported through RustDAFF_GetLastError() and the
+	// entry point returns its failure value. Define DAFFRUST_ABORT_ON_EXCEPTION to abort
+	// instead, e.g. to get a core dump at the point of failure.
+	template<typename T, typename F>
+	T Guarded(T failure, F&& body) noexcept
+	{
+		try {
+			return body();
+		} catch (const std::exception& e) {
+			SetLastError(std::string("Exception in libDAFF: ") + e.what());
+		} catch (...) {
+			SetLastError("Unknown exception in libDAFF");
+		}
+#ifdef DAFFRUST_ABORT_ON_EXCEPTION
+		std::fprintf(stderr, "%s\n", g_lastError.c_str());
+		std::abort();
+#endif
+		return failure;
+	}
+
+	template<typename F>
+	void Guarded(F&& body) noexcept
+	{
+		Guarded(0, [&] {
+			body();
+			return 0;
+		});
+	}
+
+	// Failure value that also sets the last error
+	template<typename T>
+	T Fail(T failure, const std::string& error)
+	{
+		SetLastError(error);
+		return failure;
+	}
+
+	// Reader of a handle with an opened file, or nullptr with the last error set.
+	// libDAFF asserts that a file is opened on every property, metadata and content access.
+	// Data deserialized from memory counts as opened file, so isValid() is checked instead of
+	// isFileOpened().
+	DAFFReader* OpenedReader(RustDAFFReaderHandle handle)
+	{
+		if (!handle)
+			return Fail<DAFFReader*>(nullptr, "Invalid handle");
+		DAFFReader* reader = static_cast<DAFFReader*>(handle);
+		if (!reader->isValid())
+			return Fail<DAFFReader*>(nullptr, "No file opened");
+		return reader;
+	}
+
+	// Little endian integer at an offset of a buffer
+	uint64_t ReadLE(const unsigned char* data, size_t offset, size_t bytes)
+	{
+		uint64_t value = 0;
+		for (size_t i = 0; i < bytes; i++)
+			value |= static_cast<uint64_t>(data[offset + i]) << (8 * i);
+		return value;
+	}
+
+	// Check that the file header, the file block table and all file blocks lie within a
+	// buffer, deserialize() copies them without knowing the buffer size
+	bool CheckBlocks(const unsigned char* data, size_t size)
+	{
+		const size_t headerSize = 10;      // Signature, version and number of file blocks
+		const size_t entrySize = 20;       // ID, offset and size
+		const size_t mainHeaderSize = 60;  // Copied in full, whatever its block size
+		if (size < headerSize)
+			return Fail(false, "DAFF data too short");
+		const int32_t numBlocks = static_cast<int32_t>(ReadLE(data, 6, 4));
+		if (numBlocks <= 0 || static_cast<uint64_t>(numBlocks) > (size - headerSize) / entrySize)
+			return Fail(false, "Invalid file block table");
+		for (int32_t i = 0; i < numBlocks; i++) {
+			const size_t entry = headerSize + static_cast<size_t>(i) * entrySize;
+			const int32_t id = static_cast<int32_t>(ReadLE(data, entry, 4));
+			const uint64_t offset = ReadLE(data, entry + 4, 8);
+			uint64_t blockSize = ReadLE(data, entry + 12, 8);
+			if (id == 0x0001)  // Main header
+				blockSize = std::max<uint64_t>(blockSize, mainHeaderSize);
+			if (offset > size || blockSize > size - offset)
+				return Fail(false, "File block " + std::to_string(i) + " exceeds the DAFF data");
+		}
+		return true;
+	}
+
+	// Check a record index before it is passed to libDAFF, which asserts on invalid indices
+	bool CheckRecordIndex(const DAFFContent* content, int recordIndex)
+	{
+		if (recordIndex < 0 || recordIndex >= content->getProperties()->getNumberOfRecords())
+			return Fail(false, "Invalid record index " + std::to_string(recordIndex));
+		return true;
+	}
+
+	bool CheckIndices(const DAFFContent* content, int recordIndex, int channel)
+	{
+		if (!CheckRecordIndex(content, recordIndex))
+			return false;
+		if (channel < 0 || channel >= content->getProperties()->getNumberOfChannels())
+			return Fail(false, "Invalid channel " + std::to_string(channel));
+		return true;
+	}
+
+	// Shared implementation of the per-content-type entry points
+	template<typename Content>
+	int NearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
+	{
+		if (!content)
+			return Fail(-1, "Invalid content handle");
+		return Guarded(-1, [&] {
+			int recordIndex = -1;
+			static_cast<Content*>(content)->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi),
+																 static_cast<float>(theta), recordIndex);
+			return recordIndex;
+		});
+	}
+
+	template<typename Content>
+	bool RecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
+	{
+		if (!content || !alpha || !beta)
+			return Fail(false, "Invalid content handle or output pointer");
+		return Guarded(false, [&] {
+			Content* c = static_cast<Content*>(content);
+			if (!CheckRecordIndex(c, recordIndex))
+				return false;
+			float fAlpha, fBeta;
+			if (c->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta) != DAFF_NO_ERROR)
+				return Fail(false, "Failed to get record coordinates");
+			*alpha = fAlpha;
+			*beta = fBeta;
+			return true;
+		});
+	}
+
+	template<typename Content>
+	bool Frequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize)
+	{
+		if (!content || !frequencies)
+			return Fail(false, "Invalid content handle or output buffer");
+		return Guarded(false, [&] {
+			const std::vector<float>& vfFreqs = static_cast<Content*>(content)->getFrequencies();
+			if (bufferSize < (int)vfFreqs.size())
+				return Fail(false, "Frequency buffer too small");
+			std::copy(vfFreqs.begin(), vfFreqs.end(), frequencies);
+			return true;
+		});
+	}
+
+	template<typename Content>
+	RustDAFFContentHandle ContentOfType(RustDAFFReaderHandle handle, int contentType, const char* error)
+	{
+		return Guarded<RustDAFFContentHandle>(nullptr, [&]() -> RustDAFFContentHandle {
+			DAFFReader* reader = OpenedReader(handle);
+			if (!reader)
+				return nullptr;
+			if (reader->getProperties()->getContentType() != contentType)
+				return Fail<RustDAFFContentHandle>(nullptr, error);
+			return static_cast<RustDAFFContentHandle>(dynamic_cast<Content*>(reader->getContent()));
+		});
+	}
+
+	// Property of the opened file, or the failure value
+	template<typename T, typename F>
+	T Property(RustDAFFReaderHandle handle, T failure, F&& getter)
+	{
+		return Guarded(failure, [&] {
+			DAFFReader* reader = OpenedReader(handle);
+			return reader ? static_cast<T>(getter(reader->getProperties())) : failure;
+		});
+	}
+
+	// Metadata of the opened file if it has the key, otherwise nullptr with the last error set
+	const DAFFMetadata* MetadataWithKey(RustDAFFReaderHandle handle, const char* key)
+	{
+		if (!key)
+			return Fail<const DAFFMetadata*>(nullptr, "Invalid key");
+		DAFFReader* reader = OpenedReader(handle);
+		if (!reader)
+			return nullptr;
+		const DAFFMetadata* metadata = reader->getMetadata();
+		if (!metadata->hasKey(key))
+			return Fail<const DAFFMetadata*>(nullptr, "Metadata key not found: " + std::string(key));
+		return metadata;
+	}
+
+	// Metadata of a record of the opened file, or nullptr with the last error set
+	const DAFFMetadata* RecordMetadata(RustDAFFReaderHandle handle, int recordIndex)
+	{
+		DAFFReader* reader = OpenedReader(handle);
+		if (!reader)
+			return nullptr;
+		const DAFFContent* content = reader->getContent();
+		if (!CheckRecordIndex(content, recordIndex))
+			return nullptr;
+		return content->getRecordMetadata(recordIndex);
+	}
+
+	// Metadata of a record if it has the key, otherwise nullptr with the last error set
+	const DAFFMetadata* RecordMetadataWithKey(RustDAFFReaderHandle handle, int recordIndex, const char* key)
+	{
+		if (!key)
+			return Fail<const DAFFMetadata*>(nullptr, "Invalid key");
+		const DAFFMetadata* metadata = RecordMetadata(handle, recordIndex);
+		if (!metadata)
+			return nullptr;
+		if (!metadata->hasKey(key))
+			return Fail<const DAFFMetadata*>(nullptr, "Record metadata key not found: " + std::string(key));
+		return metadata;
+	}
+}  // namespace
+
 // Reader operations
 RustDAFFReaderHandle RustDAFF_Create()
 {
-	try {
-		DAFFReader* reader = DAFFReader::create();
-		return static_cast<RustDAFFReaderHandle>(reader);
-	} catch (const std::exception& e) {
-		SetLastError(e.what());
-		return nullptr;
-	}
+	return Guarded<RustDAFFReaderHandle>(nullptr,
+										 [] { return static_cast<RustDAFFReaderHandle>(DAFFReader::create()); });
 }
 
 void RustDAFF_Destroy(RustDAFFReaderHandle handle)
 {
-	if (handle) {
-		DAFFReader* reader = static_cast<DAFFReader*>(handle);
-		delete reader;
-	}
+	if (handle)
+		Guarded([&] { delete static_cast<DAFFReader*>(handle); });
 }
 
 bool RustDAFF_OpenFile(RustDAFFReaderHandle handle, const char* filename)
 {
-	if (!handle || !filename) {
-		SetLastError("Invalid handle or filename");
-		return false;
-	}
-	try {
+	if (!handle || !filename)
+		return Fail(false, "Invalid handle or filename");
+	return Guarded(false, [&] {
 		DAFFReader* reader = static_cast<DAFFReader*>(handle);
+		if (reader->isValid())
+			return Fail(false, "A file is already opened");
 		int result = reader->openFile(filename);
-		if (result != DAFF_NO_ERROR) {
-			SetLastError("Failed to open file: " + std::string(filename));
+		if (result != DAFF_NO_ERROR)
+			return Fail(false, "Failed to open file: " + std::string(filename));
+		return true;
+	});
+}
+
+bool RustDAFF_OpenBytes(RustDAFFReaderHandle handle, const unsigned char* data, size_t size)
+{
+	if (!handle || (!data && size > 0))
+		return Fail(false, "Invalid handle or data");
+	return Guarded(false, [&] {
+		DAFFReader* reader = static_cast<DAFFReader*>(handle);
+		if (reader->isValid())
+			return Fail(false, "A file is already opened");
+		if (!CheckBlocks(data, size))
 			return false;
-		}
+		// deserialize() takes a mutable buffer but only copies from it
+		char* buffer = const_cast<char*>(reinterpret_cast<const char*>(data));
+		if (reader->deserialize(buffer) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to read DAFF data from memory");
 		return true;
-	} catch (const std::exception& e) {
-		SetLastError(e.what());
-		return false;
-	}
+	});
 }
 
 void RustDAFF_Close(RustDAFFReaderHandle handle)
 {
-	if (handle) {
-		DAFFReader* reader = static_cast<DAFFReader*>(handle);
-		reader->closeFile();
-	}
+	if (handle)
+		Guarded([&] { static_cast<DAFFReader*>(handle)->closeFile(); });
 }
 
 bool RustDAFF_IsValid(RustDAFFReaderHandle handle)
 {
 	if (!handle)
 		return false;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->isFileOpened();
+	return Guarded(false, [&] { return static_cast<DAFFReader*>(handle)->isValid(); });
 }
 
 // File properties
 int RustDAFF_GetContentType(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getContentType();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getContentType(); });
 }
 
 int RustDAFF_GetQuantization(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getQuantization();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getQuantization(); });
 }
 
 int RustDAFF_GetNumChannels(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getNumberOfChannels();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getNumberOfChannels(); });
 }
 
 int RustDAFF_GetNumRecords(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getNumberOfRecords();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getNumberOfRecords(); });
+}
+
+int RustDAFF_GetFileFormatVersion(RustDAFFReaderHandle handle)
+{
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getFileFormatVersion(); });
 }
 
 float RustDAFF_GetAlphaResolution(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1.0f;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getAlphaResolution();
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getAlphaResolution(); });
 }
 
 float RustDAFF_GetBetaResolution(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1.0f;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getBetaResolution();
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getBetaResolution(); });
 }
 
 int RustDAFF_GetAlphaPoints(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getAlphaPoints();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getAlphaPoints(); });
 }
 
 int RustDAFF_GetBetaPoints(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getProperties()->getBetaPoints();
+	return Property(handle, -1, [](DAFFProperties* p) { return p->getBetaPoints(); });
+}
+
+float RustDAFF_GetAlphaStart(RustDAFFReaderHandle handle)
+{
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getAlphaStart(); });
+}
+
+float RustDAFF_GetAlphaEnd(RustDAFFReaderHandle handle)
+{
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getAlphaEnd(); });
+}
+
+float RustDAFF_GetBetaStart(RustDAFFReaderHandle handle)
+{
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getBetaStart(); });
+}
+
+float RustDAFF_GetBetaEnd(RustDAFFReaderHandle handle)
+{
+	return Property(handle, -1.0f, [](DAFFProperties* p) { return p->getBetaEnd(); });
 }
 
 int RustDAFF_GetOrientationYPR(RustDAFFReaderHandle handle, float* yaw, float* pitch, float* roll)
 {
-	if (!handle || !yaw || !pitch || !roll)
-		return -1;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	DAFFOrientationYPR o;
-	reader->getProperties()->getOrientation(o);
-	*yaw = o.fYawAngleDeg;
-	*pitch = o.fPitchAngleDeg;
-	*roll = o.fRollAngleDeg;
-	return 0;
+	if (!yaw || !pitch || !roll)
+		return Fail(-1, "Invalid output pointer");
+	return Property(handle, -1, [&](DAFFProperties* p) {
+		DAFFOrientationYPR o;
+		p->getOrientation(o);
+		*yaw = o.fYawAngleDeg;
+		*pitch = o.fPitchAngleDeg;
+		*roll = o.fRollAngleDeg;
+		return 0;
+	});
 }
 
 // Metadata operations
 bool RustDAFF_HasMetadata(RustDAFFReaderHandle handle, const char* key)
 {
-	if (!handle || !key)
+	if (!key)
 		return false;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	return reader->getMetadata()->hasKey(key);
+	return Guarded(false, [&] {
+		DAFFReader* reader = OpenedReader(handle);
+		return reader && reader->getMetadata()->hasKey(key);
+	});
 }
 
 const char* RustDAFF_GetMetadataString(RustDAFFReaderHandle handle, const char* key)
 {
-	if (!handle || !key)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (!reader->getMetadata()->hasKey(key))
-		return nullptr;
-	static thread_local std::string value;
-	value = reader->getMetadata()->getKeyString(key);
-	return value.c_str();
+	return Guarded<const char*>(nullptr, [&]() -> const char* {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		if (!metadata)
+			return nullptr;
+		static thread_local std::string value;
+		value = metadata->getKeyString(key);
+		return value.c_str();
+	});
 }
 
 bool RustDAFF_GetMetadataFloat(RustDAFFReaderHandle handle, const char* key, float* value)
 {
-	if (!handle || !key || !value)
-		return false;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (!reader->getMetadata()->hasKey(key))
-		return false;
-	*value = static_cast<float>(reader->getMetadata()->getKeyFloat(key));
-	return true;
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		if (!metadata)
+			return false;
+		*value = static_cast<float>(metadata->getKeyFloat(key));
+		return true;
+	});
 }
 
 bool RustDAFF_GetMetadataBool(RustDAFFReaderHandle handle, const char* key, bool* value)
 {
-	if (!handle || !key || !value)
-		return false;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (!reader->getMetadata()->hasKey(key))
-		return false;
-	*value = reader->getMetadata()->getKeyBool(key);
-	return true;
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		if (!metadata)
+			return false;
+		*value = metadata->getKeyBool(key);
+		return true;
+	});
+}
+
+bool RustDAFF_GetMetadataInt(RustDAFFReaderHandle handle, const char* key, int* value)
+{
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		if (!metadata)
+			return false;
+		if (metadata->getKeyType(key) != DAFFMetadata::DAFF_INT)
+			return Fail(false, "Metadata key is not an integer: " + std::string(key));
+		*value = metadata->getKeyInt(key);
+		return true;
+	});
+}
+
+int RustDAFF_GetMetadataType(RustDAFFReaderHandle handle, const char* key)
+{
+	return Guarded(-1, [&] {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		return metadata ? metadata->getKeyType(key) : -1;
+	});
+}
+
+bool RustDAFF_GetMetadataDouble(RustDAFFReaderHandle handle, const char* key, double* value)
+{
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = MetadataWithKey(handle, key);
+		if (!metadata)
+			return false;
+		*value = metadata->getKeyFloat(key);
+		return true;
+	});
+}
+
+int RustDAFF_GetNumMetadataKeys(RustDAFFReaderHandle handle)
+{
+	return Guarded(-1, [&] {
+		DAFFReader* reader = OpenedReader(handle);
+		if (!reader)
+			return -1;
+		std::vector<std::string> vsKeys;
+		reader->getMetadata()->getKeys(vsKeys);
+		return (int)vsKeys.size();
+	});
+}
+
+const char* RustDAFF_GetMetadataKey(RustDAFFReaderHandle handle, int index)
+{
+	return Guarded<const char*>(nullptr, [&]() -> const char* {
+		DAFFReader* reader = OpenedReader(handle);
+		if (!reader)
+			return nullptr;
+		std::vector<std::string> vsKeys;
+		reader->getMetadata()->getKeys(vsKeys);
+		if (index < 0 || index >= (int)vsKeys.size())
+			return Fail<const char*>(nullptr, "Invalid metadata key index " + std::to_string(index));
+		static thread_local std::string key;
+		key = vsKeys[index];
+		return key.c_str();
+	});
+}
+
+// Per-record metadata operations
+int RustDAFF_GetNumRecordMetadataKeys(RustDAFFReaderHandle handle, int recordIndex)
+{
+	return Guarded(-1, [&] {
+		const DAFFMetadata* metadata = RecordMetadata(handle, recordIndex);
+		if (!metadata)
+			return -1;
+		std::vector<std::string> vsKeys;
+		metadata->getKeys(vsKeys);
+		return (int)vsKeys.size();
+	});
+}
+
+const char* RustDAFF_GetRecordMetadataKey(RustDAFFReaderHandle handle, int recordIndex, int index)
+{
+	return Guarded<const char*>(nullptr, [&]() -> const char* {
+		const DAFFMetadata* metadata = RecordMetadata(handle, recordIndex);
+		if (!metadata)
+			return nullptr;
+		std::vector<std::string> vsKeys;
+		metadata->getKeys(vsKeys);
+		if (index < 0 || index >= (int)vsKeys.size())
+			return Fail<const char*>(nullptr, "Invalid metadata key index " + std::to_string(index));
+		static thread_local std::string key;
+		key = vsKeys[index];
+		return key.c_str();
+	});
+}
+
+int RustDAFF_GetRecordMetadataType(RustDAFFReaderHandle handle, int recordIndex, const char* key)
+{
+	return Guarded(-1, [&] {
+		const DAFFMetadata* metadata = RecordMetadataWithKey(handle, recordIndex, key);
+		return metadata ? metadata->getKeyType(key) : -1;
+	});
+}
+
+const char* RustDAFF_GetRecordMetadataString(RustDAFFReaderHandle handle, int recordIndex, const char* key)
+{
+	return Guarded<const char*>(nullptr, [&]() -> const char* {
+		const DAFFMetadata* metadata = RecordMetadataWithKey(handle, recordIndex, key);
+		if (!metadata)
+			return nullptr;
+		static thread_local std::string value;
+		value = metadata->getKeyString(key);
+		return value.c_str();
+	});
+}
+
+bool RustDAFF_GetRecordMetadataBool(RustDAFFReaderHandle handle, int recordIndex, const char* key, bool* value)
+{
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = RecordMetadataWithKey(handle, recordIndex, key);
+		if (!metadata)
+			return false;
+		*value = metadata->getKeyBool(key);
+		return true;
+	});
+}
+
+bool RustDAFF_GetRecordMetadataInt(RustDAFFReaderHandle handle, int recordIndex, const char* key, int* value)
+{
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = RecordMetadataWithKey(handle, recordIndex, key);
+		if (!metadata)
+			return false;
+		if (metadata->getKeyType(key) != DAFFMetadata::DAFF_INT)
+			return Fail(false, "Metadata key is not an integer: " + std::string(key));
+		*value = metadata->getKeyInt(key);
+		return true;
+	});
+}
+
+bool RustDAFF_GetRecordMetadataDouble(RustDAFFReaderHandle handle, int recordIndex, const char* key, double* value)
+{
+	if (!value)
+		return Fail(false, "Invalid output pointer");
+	return Guarded(false, [&] {
+		const DAFFMetadata* metadata = RecordMetadataWithKey(handle, recordIndex, key);
+		if (!metadata)
+			return false;
+		*value = metadata->getKeyFloat(key);
+		return true;
+	});
 }
 
 // Content access - Impulse Response (IR)
 RustDAFFContentHandle RustDAFF_GetContentIR(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (reader->getProperties()->getContentType() != DAFF_IMPULSE_RESPONSE)
-		return nullptr;
-	return static_cast<RustDAFFContentHandle>(dynamic_cast<DAFFContentIR*>(reader->getContent()));
+	return ContentOfType<DAFFContentIR>(handle, DAFF_IMPULSE_RESPONSE, "Not an IR content type");
 }
 
 int RustDAFF_ContentIR_GetFilterLength(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
-	return ir->getFilterLength();
+	return Guarded(-1, [&] { return static_cast<DAFFContentIR*>(content)->getFilterLength(); });
 }
 
 int RustDAFF_ContentIR_GetSamplerate(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
-	return ir->getSamplerate();
+	return Guarded(-1, [&] { return (int)static_cast<DAFFContentIR*>(content)->getSamplerate(); });
 }
 
 int RustDAFF_ContentIR_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
 {
-	if (!content)
-		return -1;
-	DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
-	int recordIndex;
-	ir->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi), static_cast<float>(theta), recordIndex);
-	return recordIndex;
+	return NearestNeighbour<DAFFContentIR>(content, phi, theta);
 }
 
 bool RustDAFF_ContentIR_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
 {
-	if (!content || !alpha || !beta)
-		return false;
-	DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
-	float fAlpha, fBeta;
-	ir->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta);
-	*alpha = fAlpha;
-	*beta = fBeta;
-	return true;
+	return RecordCoords<DAFFContentIR>(content, recordIndex, alpha, beta);
 }
 
 bool RustDAFF_ContentIR_GetFilterCoeffs(RustDAFFContentHandle content, int recordIndex, int channel, float* coeffs,
 										int bufferSize)
 {
 	if (!content || !coeffs)
-		return false;
-	DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
-	if (bufferSize < ir->getFilterLength())
-		return false;
-	ir->getFilterCoeffs(recordIndex, channel, coeffs);
-	return true;
+		return Fail(false, "Invalid content handle or output buffer");
+	return Guarded(false, [&] {
+		DAFFContentIR* ir = static_cast<DAFFContentIR*>(content);
+		if (!CheckIndices(ir, recordIndex, channel))
+			return false;
+		if (bufferSize < ir->getFilterLength())
+			return Fail(false, "Filter coefficient buffer too small");
+		if (ir->getFilterCoeffs(recordIndex, channel, coeffs) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to get filter coefficients");
+		return true;
+	});
 }
 
 // Content access - Magnitude Spectrum (MS)
 RustDAFFContentHandle RustDAFF_GetContentMS(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (reader->getProperties()->getContentType() != DAFF_MAGNITUDE_SPECTRUM)
-		return nullptr;
-	return static_cast<RustDAFFContentHandle>(dynamic_cast<DAFFContentMS*>(reader->getContent()));
+	return ContentOfType<DAFFContentMS>(handle, DAFF_MAGNITUDE_SPECTRUM, "Not an MS content type");
 }
 
 int RustDAFF_ContentMS_GetNumFrequencies(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentMS* ms = static_cast<DAFFContentMS*>(content);
-	return ms->getNumFrequencies();
+	return Guarded(-1, [&] { return static_cast<DAFFContentMS*>(content)->getNumFrequencies(); });
+}
+
+bool RustDAFF_ContentMS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize)
+{
+	return Frequencies<DAFFContentMS>(content, frequencies, bufferSize);
 }
 
 int RustDAFF_ContentMS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
 {
-	if (!content)
-		return -1;
-	DAFFContentMS* ms = static_cast<DAFFContentMS*>(content);
-	int recordIndex;
-	ms->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi), static_cast<float>(theta), recordIndex);
-	return recordIndex;
+	return NearestNeighbour<DAFFContentMS>(content, phi, theta);
 }
 
 bool RustDAFF_ContentMS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
 {
-	if (!content || !alpha || !beta)
-		return false;
-	DAFFContentMS* ms = static_cast<DAFFContentMS*>(content);
-	float fAlpha, fBeta;
-	ms->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta);
-	*alpha = fAlpha;
-	*beta = fBeta;
-	return true;
+	return RecordCoords<DAFFContentMS>(content, recordIndex, alpha, beta);
 }
 
 bool RustDAFF_ContentMS_GetMagnitudes(RustDAFFContentHandle content, int recordIndex, int channel, float* magnitudes,
 									  int bufferSize)
 {
 	if (!content || !magnitudes)
-		return false;
-	DAFFContentMS* ms = static_cast<DAFFContentMS*>(content);
-	if (bufferSize < ms->getNumFrequencies())
-		return false;
-	ms->getMagnitudes(recordIndex, channel, magnitudes);
-	return true;
+		return Fail(false, "Invalid content handle or output buffer");
+	return Guarded(false, [&] {
+		DAFFContentMS* ms = static_cast<DAFFContentMS*>(content);
+		if (!CheckIndices(ms, recordIndex, channel))
+			return false;
+		if (bufferSize < ms->getNumFrequencies())
+			return Fail(false, "Magnitude buffer too small");
+		if (ms->getMagnitudes(recordIndex, channel, magnitudes) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to get magnitudes");
+		return true;
+	});
 }
 
 // Content access - Phase Spectrum (PS)
 RustDAFFContentHandle RustDAFF_GetContentPS(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (reader->getProperties()->getContentType() != DAFF_PHASE_SPECTRUM)
-		return nullptr;
-	return static_cast<RustDAFFContentHandle>(dynamic_cast<DAFFContentPS*>(reader->getContent()));
+	return ContentOfType<DAFFContentPS>(handle, DAFF_PHASE_SPECTRUM, "Not a PS content type");
 }
 
 int RustDAFF_ContentPS_GetNumFrequencies(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentPS* ps = static_cast<DAFFContentPS*>(content);
-	return ps->getNumFrequencies();
+	return Guarded(-1, [&] { return static_cast<DAFFContentPS*>(content)->getNumFrequencies(); });
+}
+
+bool RustDAFF_ContentPS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize)
+{
+	return Frequencies<DAFFContentPS>(content, frequencies, bufferSize);
 }
 
 int RustDAFF_ContentPS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
 {
-	if (!content)
-		return -1;
-	DAFFContentPS* ps = static_cast<DAFFContentPS*>(content);
-	int recordIndex;
-	ps->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi), static_cast<float>(theta), recordIndex);
-	return recordIndex;
+	return NearestNeighbour<DAFFContentPS>(content, phi, theta);
 }
 
 bool RustDAFF_ContentPS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
 {
-	if (!content || !alpha || !beta)
-		return false;
-	DAFFContentPS* ps = static_cast<DAFFContentPS*>(content);
-	float fAlpha, fBeta;
-	ps->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta);
-	*alpha = fAlpha;
-	*beta = fBeta;
-	return true;
+	return RecordCoords<DAFFContentPS>(content, recordIndex, alpha, beta);
 }
 
 bool RustDAFF_ContentPS_GetPhases(RustDAFFContentHandle content, int recordIndex, int channel, float* phases,
 								  int bufferSize)
 {
 	if (!content || !phases)
-		return false;
-	DAFFContentPS* ps = static_cast<DAFFContentPS*>(content);
-	if (bufferSize < ps->getNumFrequencies())
-		return false;
-	ps->getPhases(recordIndex, channel, phases);
-	return true;
+		return Fail(false, "Invalid content handle or output buffer");
+	return Guarded(false, [&] {
+		DAFFContentPS* ps = static_cast<DAFFContentPS*>(content);
+		if (!CheckIndices(ps, recordIndex, channel))
+			return false;
+		if (bufferSize < ps->getNumFrequencies())
+			return Fail(false, "Phase buffer too small");
+		if (ps->getPhases(recordIndex, channel, phases) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to get phases");
+		return true;
+	});
 }
 
 // Content access - Magnitude-Phase Spectrum (MPS)
 RustDAFFContentHandle RustDAFF_GetContentMPS(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (reader->getProperties()->getContentType() != DAFF_MAGNITUDE_PHASE_SPECTRUM)
-		return nullptr;
-	return static_cast<RustDAFFContentHandle>(dynamic_cast<DAFFContentMPS*>(reader->getContent()));
+	return ContentOfType<DAFFContentMPS>(handle, DAFF_MAGNITUDE_PHASE_SPECTRUM, "Not an MPS content type");
 }
 
 int RustDAFF_ContentMPS_GetNumFrequencies(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentMPS* mps = static_cast<DAFFContentMPS*>(content);
-	return mps->getNumFrequencies();
+	return Guarded(-1, [&] { return static_cast<DAFFContentMPS*>(content)->getNumFrequencies(); });
+}
+
+bool RustDAFF_ContentMPS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize)
+{
+	return Frequencies<DAFFContentMPS>(content, frequencies, bufferSize);
 }
 
 int RustDAFF_ContentMPS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
 {
-	if (!content)
-		return -1;
-	DAFFContentMPS* mps = static_cast<DAFFContentMPS*>(content);
-	int recordIndex;
-	mps->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi), static_cast<float>(theta), recordIndex);
-	return recordIndex;
+	return NearestNeighbour<DAFFContentMPS>(content, phi, theta);
 }
 
 bool RustDAFF_ContentMPS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
 {
-	if (!content || !alpha || !beta)
-		return false;
-	DAFFContentMPS* mps = static_cast<DAFFContentMPS*>(content);
-	float fAlpha, fBeta;
-	mps->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta);
-	*alpha = fAlpha;
-	*beta = fBeta;
-	return true;
+	return RecordCoords<DAFFContentMPS>(content, recordIndex, alpha, beta);
 }
 
 bool RustDAFF_ContentMPS_GetCoefficients(RustDAFFContentHandle content, int recordIndex, int channel, float* magnitudes,
 										 float* phases, int bufferSize)
 {
 	if (!content || !magnitudes || !phases)
-		return false;
-	DAFFContentMPS* mps = static_cast<DAFFContentMPS*>(content);
-	int numFreqs = mps->getNumFrequencies();
-	if (bufferSize < numFreqs)
-		return false;
-
-	// Allocate temporary buffer for interleaved data (Mag[0], Ph[0], Mag[1], Ph[1], ...)
-	std::vector<float> interleaved(numFreqs * 2);
-	int result = mps->getCoefficientsMP(recordIndex, channel, interleaved.data());
-	if (result != DAFF_NO_ERROR)
-		return false;
-
-	// De-interleave into separate magnitude and phase arrays
-	for (int i = 0; i < numFreqs; i++) {
-		magnitudes[i] = interleaved[i * 2];
-		phases[i] = interleaved[i * 2 + 1];
-	}
+		return Fail(false, "Invalid content handle or output buffer");
+	return Guarded(false, [&] {
+		DAFFContentMPS* mps = static_cast<DAFFContentMPS*>(content);
+		if (!CheckIndices(mps, recordIndex, channel))
+			return false;
+		int numFreqs = mps->getNumFrequencies();
+		if (bufferSize < numFreqs)
+			return Fail(false, "Coefficient buffer too small");
+
+		// Fetch the stored real/imaginary pairs (Re[0], Im[0], Re[1], Im[1], ...)
+		// Note: getCoefficientsMP() touches one pair beyond the last frequency, so it is avoided here
+		std::vector<float> interleaved(numFreqs * 2);
+		if (mps->getCoefficientsRI(recordIndex, channel, interleaved.data()) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to get coefficients");
+
+		// Convert into separate magnitude and phase arrays
+		for (int i = 0; i < numFreqs; i++) {
+			magnitudes[i] = std::hypot(interleaved[i * 2], interleaved[i * 2 + 1]);
+			phases[i] = std::atan2(interleaved[i * 2 + 1], interleaved[i * 2]);
+		}
 
-	return true;
+		return true;
+	});
 }
 
 // Content access - DFT
 RustDAFFContentHandle RustDAFF_GetContentDFT(RustDAFFReaderHandle handle)
 {
-	if (!handle)
-		return nullptr;
-	DAFFReader* reader = static_cast<DAFFReader*>(handle);
-	if (reader->getProperties()->getContentType() != DAFF_DFT_SPECTRUM)
-		return nullptr;
-	return static_cast<RustDAFFContentHandle>(dynamic_cast<DAFFContentDFT*>(reader->getContent()));
+	return ContentOfType<DAFFContentDFT>(handle, DAFF_DFT_SPECTRUM, "Not a DFT content type");
 }
 
 int RustDAFF_ContentDFT_GetNumDFTCoeffs(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
-	return dft->getNumDFTCoeffs();
+	return Guarded(-1, [&] { return static_cast<DAFFContentDFT*>(content)->getNumDFTCoeffs(); });
 }
 
 bool RustDAFF_ContentDFT_IsSymmetric(RustDAFFContentHandle content)
 {
 	if (!content)
 		return false;
-	DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
-	return dft->isSymmetric();
+	return Guarded(false, [&] { return static_cast<DAFFContentDFT*>(content)->isSymmetric(); });
 }
 
-int RustDAFF_ContentDFT_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
+int RustDAFF_ContentDFT_GetTransformSize(RustDAFFContentHandle content)
 {
 	if (!content)
 		return -1;
-	DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
-	int recordIndex;
-	dft->getNearestNeighbour(DAFF_OBJECT_VIEW, static_cast<float>(phi), static_cast<float>(theta), recordIndex);
-	return recordIndex;
+	return Guarded(-1, [&] { return static_cast<DAFFContentDFT*>(content)->getTransformSize(); });
+}
+
+double RustDAFF_ContentDFT_GetSamplerate(RustDAFFContentHandle content)
+{
+	if (!content)
+		return -1.0;
+	return Guarded(-1.0, [&] { return (double)static_cast<DAFFContentDFT*>(content)->getSamplerate(); });
+}
+
+int RustDAFF_ContentDFT_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta)
+{
+	return NearestNeighbour<DAFFContentDFT>(content, phi, theta);
 }
 
 bool RustDAFF_ContentDFT_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha, double* beta)
 {
-	if (!content || !alpha || !beta)
-		return false;
-	DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
-	float fAlpha, fBeta;
-	dft->getRecordCoords(recordIndex, DAFF_DATA_VIEW, fAlpha, fBeta);
-	*alpha = fAlpha;
-	*beta = fBeta;
-	return true;
+	return RecordCoords<DAFFContentDFT>(content, recordIndex, alpha, beta);
 }
 
 bool RustDAFF_ContentDFT_GetDFTCoeffs(RustDAFFContentHandle content, int recordIndex, int channel, float* coeffs,
 									  int bufferSize)
 {
 	if (!content || !coeffs)
-		return false;
-	DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
-	if (bufferSize < dft->getNumDFTCoeffs() * 2)
-		return false;  // DFT coeffs are complex (real, imag)
-	dft->getDFTCoeffs(recordIndex, channel, coeffs);
-	return true;
+		return Fail(false, "Invalid content handle or output buffer");
+	return Guarded(false, [&] {
+		DAFFContentDFT* dft = static_cast<DAFFContentDFT*>(content);
+		if (!CheckIndices(dft, recordIndex, channel))
+			return false;
+		if (bufferSize < dft->getNumDFTCoeffs() * 2)
+			return Fail(false, "DFT coefficient buffer too small");  // DFT coeffs are complex (real, imag)
+		if (dft->getDFTCoeffs(recordIndex, channel, coeffs) != DAFF_NO_ERROR)
+			return Fail(false, "Failed to get DFT coefficients");
+		return true;
+	});
 }
@@ -40,6 +40,7 @@ DAFFRUST_API const char* RustDAFF_GetLastError();
 DAFFRUST_API RustDAFFReaderHandle RustDAFF_Create();
 DAFFRUST_API void RustDAFF_Destroy(RustDAFFReaderHandle handle);
 DAFFRUST_API bool RustDAFF_OpenFile(RustDAFFReaderHandle handle, const char* filename);
+DAFFRUST_API bool RustDAFF_OpenBytes(RustDAFFReaderHandle handle, const unsigned char* data, size_t size);
 DAFFRUST_API void RustDAFF_Close(RustDAFFReaderHandle handle);
 DAFFRUST_API bool RustDAFF_IsValid(RustDAFFReaderHandle handle);
 
@@ -48,10 +49,15 @@ DAFFRUST_API int RustDAFF_GetContentType(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetQuantization(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetNumChannels(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetNumRecords(RustDAFFReaderHandle handle);
+DAFFRUST_API int RustDAFF_GetFileFormatVersion(RustDAFFReaderHandle handle);
 DAFFRUST_API float RustDAFF_GetAlphaResolution(RustDAFFReaderHandle handle);
 DAFFRUST_API float RustDAFF_GetBetaResolution(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetAlphaPoints(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetBetaPoints(RustDAFFReaderHandle handle);
+DAFFRUST_API float RustDAFF_GetAlphaStart(RustDAFFReaderHandle handle);
+DAFFRUST_API float RustDAFF_GetAlphaEnd(RustDAFFReaderHandle handle);
+DAFFRUST_API float RustDAFF_GetBetaStart(RustDAFFReaderHandle handle);
+DAFFRUST_API float RustDAFF_GetBetaEnd(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_GetOrientationYPR(RustDAFFReaderHandle handle, float* yaw, float* pitch, float* roll);
 
 // Metadata operations
@@ -59,6 +65,23 @@ DAFFRUST_API bool RustDAFF_HasMetadata(RustDAFFReaderHandle handle, const char*
 DAFFRUST_API const char* RustDAFF_GetMetadataString(RustDAFFReaderHandle handle, const char* key);
 DAFFRUST_API bool RustDAFF_GetMetadataFloat(RustDAFFReaderHandle handle, const char* key, float* value);
 DAFFRUST_API bool RustDAFF_GetMetadataBool(RustDAFFReaderHandle handle, const char* key, bool* value);
+DAFFRUST_API bool RustDAFF_GetMetadataInt(RustDAFFReaderHandle handle, const char* key, int* value);
+DAFFRUST_API int RustDAFF_GetMetadataType(RustDAFFReaderHandle handle, const char* key);
+DAFFRUST_API bool RustDAFF_GetMetadataDouble(RustDAFFReaderHandle handle, const char* key, double* value);
+DAFFRUST_API int RustDAFF_GetNumMetadataKeys(RustDAFFReaderHandle handle);
+DAFFRUST_API const char* RustDAFF_GetMetadataKey(RustDAFFReaderHandle handle, int index);
+
+// Per-record metadata operations (DAFF 1.7), records without metadata have no keys
+DAFFRUST_API int RustDAFF_GetNumRecordMetadataKeys(RustDAFFReaderHandle handle, int recordIndex);
+DAFFRUST_API const char* RustDAFF_GetRecordMetadataKey(RustDAFFReaderHandle handle, int recordIndex, int index);
+DAFFRUST_API int RustDAFF_GetRecordMetadataType(RustDAFFReaderHandle handle, int recordIndex, const char* key);
+DAFFRUST_API const char* RustDAFF_GetRecordMetadataString(RustDAFFReaderHandle handle, int recordIndex, const char* key);
+DAFFRUST_API bool RustDAFF_GetRecordMetadataBool(RustDAFFReaderHandle handle, int recordIndex, const char* key,
+												 bool* value);
+DAFFRUST_API bool RustDAFF_GetRecordMetadataInt(RustDAFFReaderHandle handle, int recordIndex, const char* key,
+												int* value);
+DAFFRUST_API bool RustDAFF_GetRecordMetadataDouble(RustDAFFReaderHandle handle, int recordIndex, const char* key,
+												   double* value);
 
 // Content access - Impulse Response (IR)
 DAFFRUST_API RustDAFFContentHandle RustDAFF_GetContentIR(RustDAFFReaderHandle handle);
@@ -73,6 +96,7 @@ DAFFRUST_API bool RustDAFF_ContentIR_GetFilterCoeffs(RustDAFFContentHandle conte
 // Content access - Magnitude Spectrum (MS)
 DAFFRUST_API RustDAFFContentHandle RustDAFF_GetContentMS(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_ContentMS_GetNumFrequencies(RustDAFFContentHandle content);
+DAFFRUST_API bool RustDAFF_ContentMS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize);
 DAFFRUST_API int RustDAFF_ContentMS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta);
 DAFFRUST_API bool RustDAFF_ContentMS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha,
 													 double* beta);
@@ -82,6 +106,7 @@ DAFFRUST_API bool RustDAFF_ContentMS_GetMagnitudes(RustDAFFContentHandle content
 // Content access - Phase Spectrum (PS)
 DAFFRUST_API RustDAFFContentHandle RustDAFF_GetContentPS(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_ContentPS_GetNumFrequencies(RustDAFFContentHandle content);
+DAFFRUST_API bool RustDAFF_ContentPS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize);
 DAFFRUST_API int RustDAFF_ContentPS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta);
 DAFFRUST_API bool RustDAFF_ContentPS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha,
 													 double* beta);
@@ -91,6 +116,7 @@ DAFFRUST_API bool RustDAFF_ContentPS_GetPhases(RustDAFFContentHandle content, in
 // Content access - Magnitude-Phase Spectrum (MPS)
 DAFFRUST_API RustDAFFContentHandle RustDAFF_GetContentMPS(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_ContentMPS_GetNumFrequencies(RustDAFFContentHandle content);
+DAFFRUST_API bool RustDAFF_ContentMPS_GetFrequencies(RustDAFFContentHandle content, float* frequencies, int bufferSize);
 DAFFRUST_API int RustDAFF_ContentMPS_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta);
 DAFFRUST_API bool RustDAFF_ContentMPS_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha,
 													  double* beta);
@@ -101,6 +127,8 @@ DAFFRUST_API bool RustDAFF_ContentMPS_GetCoefficients(RustDAFFContentHandle cont
 DAFFRUST_API RustDAFFContentHandle RustDAFF_GetContentDFT(RustDAFFReaderHandle handle);
 DAFFRUST_API int RustDAFF_ContentDFT_GetNumDFTCoeffs(RustDAFFContentHandle content);
 DAFFRUST_API bool RustDAFF_ContentDFT_IsSymmetric(RustDAFFContentHandle content);
+DAFFRUST_API int RustDAFF_ContentDFT_GetTransformSize(RustDAFFContentHandle content);
+DAFFRUST_API double RustDAFF_ContentDFT_GetSamplerate(RustDAFFContentHandle content);
 DAFFRUST_API int RustDAFF_ContentDFT_GetNearestNeighbour(RustDAFFContentHandle content, double phi, double theta);
 DAFFRUST_API bool RustDAFF_ContentDFT_GetRecordCoords(RustDAFFContentHandle content, int recordIndex, double* alpha,
 													  double* beta);
@@ -12,15 +12,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     let filename = &args[1];
 
-    // Create reader and open file
-    let mut reader = Reader::new()?;
     println!("Opening file: {}", filename);
-    reader.open_file(filename)?;
-
-    if !reader.is_valid() {
-        eprintln!("File is not valid!");
-        std::process::exit(1);
-    }
+    let reader = Reader::open(filename)?;
 
     // Print file properties
     println!("\n=== File Properties ===");
@@ -0,0 +1,35 @@
+//! DAFF conversion service
+//!
+//! Serves `/inspect`, `/convert` and `/extract` over HTTP, optionally running the stages of a
+//! daff-batch pipeline file on converted uploads:
+//!
+//! ```text
+//! cargo run --example conversion_service --features service -- 127.0.0.1:8080 pipeline.toml
+//! curl --data-binary @hrir.daff 'http://127.0.0.1:8080/extract?azimuth=30&elevation=0'
+//! ```
+
+use opendaff::pipeline::Batch;
+use opendaff::service::{self, ServiceConfig};
+use std::env;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: {} <address> [pipeline.toml]", args[0]);
+        std::process::exit(1);
+    }
+    let config = match args.get(2) {
+        Some(path) => ServiceConfig::from_batch(&Batch::load(path)?),
+        None => ServiceConfig::default(),
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_io()
+        .build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&args[1]).await?;
+        println!("Listening on {}", listener.local_addr()?);
+        axum::serve(listener, service::router(config)).await
+    })?;
+    Ok(())
+}
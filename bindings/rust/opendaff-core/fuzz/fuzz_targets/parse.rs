@@ -0,0 +1,27 @@
+//! Parses arbitrary bytes and decodes everything the parser accepts
+//!
+//! Run with `cargo fuzz run parse` in `opendaff-core`. Neither parsing nor decoding may panic
+//! or allocate beyond the limits of the parser.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opendaff_core::{swap_byte_order, ParsedFile};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(file) = ParsedFile::new(data) else {
+        return;
+    };
+    // Validated files decode completely
+    let mut values = vec![0.0; file.elements_per_record()];
+    for record in 0..file.num_records() {
+        for channel in 0..file.num_channels() {
+            file.read_channel(record, channel, &mut values).unwrap();
+        }
+    }
+    file.metadata().unwrap();
+    assert!(file.channel(file.num_records(), 0).is_err());
+
+    let swapped = swap_byte_order(data).unwrap();
+    assert_eq!(swap_byte_order(&swapped).unwrap(), data);
+});
@@ -0,0 +1,34 @@
+//! Constants of the DAFF 1.7 file format
+//!
+//! See the `layout` module of the `opendaff` crate for a description of the format.
+
+/// Version number of DAFF 1.7, the version the parser reads
+pub const CURRENT_VERSION: i32 = 170;
+
+/// ID of the main header block
+pub const MAIN_HEADER_ID: i32 = 0x0001;
+/// ID of the content header block
+pub const CONTENT_HEADER_ID: i32 = 0x0002;
+/// ID of the record descriptor block
+pub const RECORD_DESC_ID: i32 = 0x0003;
+/// ID of the data block
+pub const DATA_ID: i32 = 0x0004;
+/// ID of the metadata block
+pub const METADATA_ID: i32 = 0x0005;
+
+/// Size of the file header: signature, version and number of file blocks
+pub const FILE_HEADER_SIZE: u64 = 2 + 4 + 4;
+/// Size of a file block table entry: ID, offset and size
+pub const FILE_BLOCK_ENTRY_SIZE: u64 = 4 + 8 + 8;
+/// Size of the main header
+pub const MAIN_HEADER_SIZE: u64 = 15 * 4;
+/// Size of an impulse response record channel descriptor
+pub const IR_DESC_SIZE: u64 = 4 + 8 + 4 + 4;
+/// Size of a record channel descriptor of all other content types
+pub const DEFAULT_DESC_SIZE: u64 = 4 + 8;
+
+/// Largest number of values per record channel the parser accepts
+///
+/// About six minutes of impulse response at 44.1 kHz. Bounds the buffers allocated for
+/// decoded records, so corrupt headers cannot exhaust the memory.
+pub const MAX_ELEMENTS_PER_RECORD: usize = 1 << 24;
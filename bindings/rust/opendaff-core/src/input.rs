@@ -0,0 +1,228 @@
+//! Decoding of the values stored in DAFF data
+//!
+//! Shared with the `opendaff` crate, not part of the public API.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::parser::ByteOrder;
+use crate::{Error, Metadata, MetadataType, MetadataValue, Quantization, Result, Section};
+
+/// Parse a metadata set: number of keys, then type, NUL-terminated key and value per key
+pub fn decode_metadata(input: &mut Input) -> Result<Metadata> {
+    let mut metadata = Metadata::new();
+    for _ in 0..input.count()? {
+        let value_type = input.i32()?;
+        let key = input.string()?;
+        let value = match MetadataType::from_i32(value_type) {
+            Some(MetadataType::Bool) => MetadataValue::Bool(input.i32()? != 0),
+            Some(MetadataType::Int) => MetadataValue::Int(input.i32()?),
+            Some(MetadataType::Float) => MetadataValue::Float(input.f64()?),
+            Some(MetadataType::String) => MetadataValue::String(input.string()?),
+            None => return Err(Error::invalid_metadata(&key, "unknown value type")),
+        };
+        metadata.insert(key, value);
+    }
+    Ok(metadata)
+}
+
+/// Reader over a byte slice, little endian unless stated otherwise
+///
+/// Readers of a [section](Input::section) report errors as [`Error::Corrupt`], with the
+/// offset of the offending field.
+pub struct Input<'a> {
+    bytes: &'a [u8],
+    byte_order: ByteOrder,
+    section: Option<Section>,
+    offset: usize,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::with_byte_order(bytes, ByteOrder::LittleEndian)
+    }
+
+    pub fn with_byte_order(bytes: &'a [u8], byte_order: ByteOrder) -> Self {
+        Self {
+            bytes,
+            byte_order,
+            section: None,
+            offset: 0,
+        }
+    }
+
+    /// Reader over a section of DAFF data, `range` being the bytes of the section
+    pub fn section(
+        data: &'a [u8],
+        range: Range<usize>,
+        section: Section,
+        byte_order: ByteOrder,
+    ) -> Self {
+        Self {
+            offset: range.start,
+            bytes: &data[range],
+            byte_order,
+            section: Some(section),
+        }
+    }
+
+    /// Offset of the next field from the start of the data, for sections
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Error about the field at `offset`
+    pub fn error(&self, offset: usize, message: impl Into<String>) -> Error {
+        match self.section {
+            Some(section) => Error::corrupt(section, offset, message),
+            None => Error::new(message),
+        }
+    }
+
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(self.error(self.offset, "Unexpected end of data"));
+        }
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        self.offset += count;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Bytes of a number, reordered to little endian
+    fn number<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = self.array()?;
+        if self.byte_order == ByteOrder::BigEndian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        self.number().map(i32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        self.number().map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        self.number().map(f32::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Result<f64> {
+        self.number().map(f64::from_le_bytes)
+    }
+
+    /// Length of a sequence stored as u64, checked against the remaining data so corrupt
+    /// lengths cannot trigger huge allocations
+    pub fn sequence_len(&mut self, element_size: usize) -> Result<usize> {
+        let at = self.offset;
+        let len = usize::try_from(self.u64()?).map_err(|_| self.error(at, "Invalid length"))?;
+        if len.saturating_mul(element_size) > self.bytes.len() {
+            return Err(self.error(at, "Unexpected end of data"));
+        }
+        Ok(len)
+    }
+
+    /// Length-prefixed sequence of f32 values
+    pub fn f32s(&mut self) -> Result<Vec<f32>> {
+        let len = self.sequence_len(4)?;
+        (0..len).map(|_| self.f32()).collect()
+    }
+
+    /// Length-prefixed sequence of f64 values
+    pub fn f64s(&mut self) -> Result<Vec<f64>> {
+        let len = self.sequence_len(8)?;
+        (0..len).map(|_| self.f64()).collect()
+    }
+
+    /// Whether all data has been consumed
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Number of bytes not consumed yet
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Non-negative count
+    pub fn count(&mut self) -> Result<usize> {
+        let at = self.offset;
+        usize::try_from(self.i32()?).map_err(|_| self.error(at, "Negative count"))
+    }
+
+    /// NUL-terminated UTF-8 string
+    pub fn string(&mut self) -> Result<String> {
+        let at = self.offset;
+        let Some(end) = self.bytes.iter().position(|&b| b == 0) else {
+            return Err(self.error(at, "Unterminated string"));
+        };
+        let string = String::from_utf8(self.take(end)?.to_vec())
+            .map_err(|_| self.error(at, "String is not valid UTF-8"))?;
+        self.take(1)?;
+        Ok(string)
+    }
+}
+
+/// Convert stored channel data back to floats, filling `data` (at most as many values as are
+/// stored)
+pub fn decode_into(
+    bytes: &[u8],
+    quantization: Quantization,
+    byte_order: ByteOrder,
+    data: &mut [f32],
+) {
+    let big_endian = byte_order == ByteOrder::BigEndian;
+    match quantization {
+        Quantization::Float32 => {
+            for (x, b) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+                let b = [b[0], b[1], b[2], b[3]];
+                *x = if big_endian {
+                    f32::from_be_bytes(b)
+                } else {
+                    f32::from_le_bytes(b)
+                };
+            }
+        }
+        Quantization::Int16 => {
+            for (x, b) in data.iter_mut().zip(bytes.chunks_exact(2)) {
+                let b = [b[0], b[1]];
+                let value = if big_endian {
+                    i16::from_be_bytes(b)
+                } else {
+                    i16::from_le_bytes(b)
+                };
+                *x = value as f32 / 32767.0;
+            }
+        }
+        // Shift the 24-bit value into the upper bytes to sign-extend it
+        Quantization::Int24 => {
+            for (x, b) in data.iter_mut().zip(bytes.chunks_exact(3)) {
+                let value = if big_endian {
+                    i32::from_be_bytes([b[0], b[1], b[2], 0])
+                } else {
+                    i32::from_le_bytes([0, b[0], b[1], b[2]])
+                };
+                *x = (value >> 8) as f32 / 8388607.0;
+            }
+        }
+    }
+}
+
+/// Size of a stored value in bytes
+pub fn quantization_size(quantization: Quantization) -> usize {
+    match quantization {
+        Quantization::Int16 => 2,
+        Quantization::Int24 => 3,
+        Quantization::Float32 => 4,
+    }
+}
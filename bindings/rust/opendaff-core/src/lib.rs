@@ -0,0 +1,395 @@
+//! `no_std` core of the OpenDAFF Rust bindings
+//!
+//! Parses DAFF files in memory with nothing but `core` and `alloc`, so directional audio
+//! datasets, e.g. HRIRs, can be consumed on embedded audio DSP boards without an operating
+//! system. [`ParsedFile`] works on any byte storage, such as a dataset in flash memory:
+//!
+//! ```no_run
+//! # fn main() -> opendaff_core::Result<()> {
+//! # static HRIR: &[u8] = &[];
+//! let file = opendaff_core::ParsedFile::new(HRIR)?;
+//! let mut samples = [0.0; 256];
+//! file.read_channel(0, 0, &mut samples[..file.elements_per_record()])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The [`opendaff`](https://docs.rs/opendaff) crate builds on this crate and re-exports its
+//! types; it adds the C++ reader, writing, grids and processing.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+pub mod format;
+#[doc(hidden)]
+pub mod input;
+mod parser;
+
+pub use parser::{swap_byte_order, ByteOrder, Layout, MainHeader, ParsedFile};
+
+/// Result type of the parser
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Error reading DAFF data
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The data is not valid DAFF data, or a requested record does not exist
+    Malformed(String),
+    /// Metadata key or value that cannot be represented
+    InvalidMetadata {
+        /// Offending key
+        key: String,
+        /// Description of the violation
+        reason: String,
+    },
+    /// A field of the data has an invalid value, or a block lies outside of the data
+    Corrupt {
+        /// Section holding the field
+        section: Section,
+        /// Offset of the field from the start of the data in bytes
+        offset: usize,
+        /// Description of the defect
+        reason: String,
+    },
+}
+
+impl Error {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Error::Malformed(message.into())
+    }
+
+    pub(crate) fn invalid_metadata(key: &str, reason: impl Into<String>) -> Self {
+        Error::InvalidMetadata {
+            key: key.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn corrupt(section: Section, offset: usize, reason: impl Into<String>) -> Self {
+        Error::Corrupt {
+            section,
+            offset,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed(message) => write!(f, "{}", message),
+            Error::InvalidMetadata { key, reason } => {
+                write!(f, "invalid metadata '{}': {}", key, reason)
+            }
+            Error::Corrupt {
+                section,
+                offset,
+                reason,
+            } => write!(f, "{} in the {} at byte {}", reason, section, offset),
+        }
+    }
+}
+
+/// Section of DAFF data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Section {
+    /// Signature, file format version and number of file blocks
+    FileHeader,
+    /// File block table
+    BlockTable,
+    /// Main header block
+    MainHeader,
+    /// Content header block
+    ContentHeader,
+    /// Record descriptor block
+    RecordDescriptors,
+    /// Data block
+    Data,
+    /// Metadata block
+    Metadata,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::FileHeader => write!(f, "file header"),
+            Section::BlockTable => write!(f, "file block table"),
+            Section::MainHeader => write!(f, "main header"),
+            Section::ContentHeader => write!(f, "content header"),
+            Section::RecordDescriptors => write!(f, "record descriptors"),
+            Section::Data => write!(f, "data block"),
+            Section::Metadata => write!(f, "metadata"),
+        }
+    }
+}
+
+/// Content types supported by DAFF files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ContentType {
+    /// Impulse response
+    ImpulseResponse = 0,
+    /// Magnitude spectrum
+    MagnitudeSpectrum = 1,
+    /// Phase spectrum
+    PhaseSpectrum = 2,
+    /// Magnitude-phase spectrum
+    MagnitudePhaseSpectrum = 3,
+    /// DFT coefficients
+    DftSpectrum = 4,
+}
+
+impl ContentType {
+    /// Content type with the number stored in the main header
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(ContentType::ImpulseResponse),
+            1 => Some(ContentType::MagnitudeSpectrum),
+            2 => Some(ContentType::PhaseSpectrum),
+            3 => Some(ContentType::MagnitudePhaseSpectrum),
+            4 => Some(ContentType::DftSpectrum),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentType::ImpulseResponse => write!(f, "Impulse Response"),
+            ContentType::MagnitudeSpectrum => write!(f, "Magnitude Spectrum"),
+            ContentType::PhaseSpectrum => write!(f, "Phase Spectrum"),
+            ContentType::MagnitudePhaseSpectrum => write!(f, "Magnitude-Phase Spectrum"),
+            ContentType::DftSpectrum => write!(f, "DFT Spectrum"),
+        }
+    }
+}
+
+/// Quantization type for DAFF data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Quantization {
+    /// 16-bit signed integer
+    Int16 = 0,
+    /// 24-bit signed integer
+    Int24 = 1,
+    /// 32-bit float
+    Float32 = 2,
+}
+
+impl Quantization {
+    /// Quantization with the number stored in the main header
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(Quantization::Int16),
+            1 => Some(Quantization::Int24),
+            2 => Some(Quantization::Float32),
+            _ => None,
+        }
+    }
+}
+
+/// Value type of a metadata key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum MetadataType {
+    /// Boolean
+    Bool = 0,
+    /// Integer number
+    Int = 1,
+    /// Floating-point number
+    Float = 2,
+    /// String
+    String = 3,
+}
+
+impl MetadataType {
+    /// Value type with the number stored in metadata sets
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(MetadataType::Bool),
+            1 => Some(MetadataType::Int),
+            2 => Some(MetadataType::Float),
+            3 => Some(MetadataType::String),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MetadataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataType::Bool => write!(f, "Bool"),
+            MetadataType::Int => write!(f, "Int"),
+            MetadataType::Float => write!(f, "Float"),
+            MetadataType::String => write!(f, "String"),
+        }
+    }
+}
+
+/// Orientation in yaw-pitch-roll (degrees)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Orientation {
+    /// Yaw angle in degrees
+    pub yaw: f32,
+    /// Pitch angle in degrees
+    pub pitch: f32,
+    /// Roll angle in degrees
+    pub roll: f32,
+}
+
+/// Content-specific header information of a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentHeader {
+    /// Impulse responses sampled at the given rate (Hz)
+    ImpulseResponse {
+        /// Sample rate in Hz
+        samplerate: f64,
+    },
+    /// Magnitude spectra at discrete support frequencies (Hz)
+    MagnitudeSpectrum {
+        /// Support frequencies in Hz
+        frequencies: Vec<f32>,
+    },
+    /// Phase spectra at discrete support frequencies (Hz)
+    PhaseSpectrum {
+        /// Support frequencies in Hz
+        frequencies: Vec<f32>,
+    },
+    /// Complex (magnitude-phase) spectra at discrete support frequencies (Hz)
+    MagnitudePhaseSpectrum {
+        /// Support frequencies in Hz
+        frequencies: Vec<f32>,
+    },
+    /// DFT coefficients
+    DftSpectrum {
+        /// Sample rate in Hz
+        samplerate: f64,
+        /// DFT transform size (symmetric spectra store `transform_size / 2 + 1` coefficients)
+        transform_size: usize,
+    },
+}
+
+impl ContentHeader {
+    /// Content type described by this header
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            ContentHeader::ImpulseResponse { .. } => ContentType::ImpulseResponse,
+            ContentHeader::MagnitudeSpectrum { .. } => ContentType::MagnitudeSpectrum,
+            ContentHeader::PhaseSpectrum { .. } => ContentType::PhaseSpectrum,
+            ContentHeader::MagnitudePhaseSpectrum { .. } => ContentType::MagnitudePhaseSpectrum,
+            ContentHeader::DftSpectrum { .. } => ContentType::DftSpectrum,
+        }
+    }
+}
+
+/// A typed metadata value
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    /// Boolean
+    Bool(bool),
+    /// Integer number
+    Int(i32),
+    /// Floating-point number
+    Float(f64),
+    /// String
+    String(String),
+}
+
+impl MetadataValue {
+    /// Type of the value
+    pub fn value_type(&self) -> MetadataType {
+        match self {
+            MetadataValue::Bool(_) => MetadataType::Bool,
+            MetadataValue::Int(_) => MetadataType::Int,
+            MetadataValue::Float(_) => MetadataType::Float,
+            MetadataValue::String(_) => MetadataType::String,
+        }
+    }
+
+    /// The value if it is a boolean
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            MetadataValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value if it is an integer
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            MetadataValue::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value if it is a number, converting integers like the DAFF reader does
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            MetadataValue::Int(value) => Some(value as f64),
+            MetadataValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value if it is a string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MetadataValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+impl From<i32> for MetadataValue {
+    fn from(value: i32) -> Self {
+        MetadataValue::Int(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Float(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::String(value.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::String(value)
+    }
+}
+
+impl fmt::Display for MetadataValue {
+    /// Formats the value like the DAFF library (booleans as `yes`/`no`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataValue::Bool(value) => write!(f, "{}", if *value { "yes" } else { "no" }),
+            MetadataValue::Int(value) => write!(f, "{}", value),
+            MetadataValue::Float(value) => write!(f, "{}", value),
+            MetadataValue::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Metadata set by (upper case) key name
+pub type Metadata = BTreeMap<String, MetadataValue>;
@@ -0,0 +1,941 @@
+//! Parser for DAFF data in memory
+//!
+//! DAFF files are little endian. Files written byte-swapped, e.g. by tools on a big-endian
+//! host that skipped the conversion, are recognized by their file header and read as well;
+//! [`swap_byte_order`] converts them. Values are decoded independently of the byte order of
+//! the host.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Range, RangeBounds};
+
+use crate::format::{self as daff, CURRENT_VERSION};
+use crate::input::{decode_into, decode_metadata, quantization_size, Input};
+use crate::{
+    ContentHeader, ContentType, Error, Metadata, MetadataType, Orientation, Quantization, Result,
+    Section,
+};
+
+/// Byte order of the values in DAFF data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ByteOrder {
+    /// Least significant byte first, as specified by the file format
+    LittleEndian,
+    /// Most significant byte first
+    BigEndian,
+}
+
+impl ByteOrder {
+    /// Byte order of the host
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            ByteOrder::BigEndian
+        } else {
+            ByteOrder::LittleEndian
+        }
+    }
+
+    /// The other byte order
+    pub fn swapped(self) -> Self {
+        match self {
+            ByteOrder::LittleEndian => ByteOrder::BigEndian,
+            ByteOrder::BigEndian => ByteOrder::LittleEndian,
+        }
+    }
+
+    /// Byte order of DAFF data, detected from the file format version in its file header
+    ///
+    /// `None` if the data does not start with a file header of the current version in either
+    /// byte order.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let [b'F', b'W', a, b, c, d, ..] = *bytes else {
+            return None;
+        };
+        if i32::from_le_bytes([a, b, c, d]) == CURRENT_VERSION {
+            Some(ByteOrder::LittleEndian)
+        } else if i32::from_be_bytes([a, b, c, d]) == CURRENT_VERSION {
+            Some(ByteOrder::BigEndian)
+        } else {
+            None
+        }
+    }
+}
+
+/// Equiangular sampling grid as stored in the main header
+///
+/// All angles are in degrees and refer to the data view (alpha, beta).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MainHeader {
+    /// Number of points in alpha direction
+    pub alpha_points: usize,
+    /// First alpha angle
+    pub alpha_start: f32,
+    /// Last alpha angle
+    pub alpha_end: f32,
+    /// Number of points in beta direction (including the poles)
+    pub beta_points: usize,
+    /// First beta angle (0° = south pole)
+    pub beta_start: f32,
+    /// Last beta angle (180° = north pole)
+    pub beta_end: f32,
+    /// Default orientation (yaw-pitch-roll in degrees)
+    pub orientation: Orientation,
+}
+
+impl MainHeader {
+    /// Number of records of the grid (a single record per pole)
+    pub fn num_records(&self) -> usize {
+        let south_pole = self.beta_start == 0.0;
+        let north_pole = self.beta_end == 180.0 && (self.beta_points > 1 || !south_pole);
+        let poles = south_pole as usize + north_pole as usize;
+        let rings = self.beta_points.saturating_sub(poles);
+        poles.min(self.beta_points) + rings.saturating_mul(self.alpha_points)
+    }
+}
+
+/// Headers of a DAFF file and the locations of its blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    /// Byte order the data is stored in
+    pub byte_order: ByteOrder,
+    /// Content type and content-specific header values
+    pub header: ContentHeader,
+    /// Quantization of the stored data
+    pub quantization: Quantization,
+    /// Grid and orientation
+    pub main: MainHeader,
+    /// Number of channels
+    pub num_channels: usize,
+    /// Number of records
+    pub num_records: usize,
+    /// Values per record channel as stored, complex values counting once
+    pub stored_elements: usize,
+    /// Size of a record channel descriptor
+    pub desc_size: usize,
+    /// Bytes of the record descriptor block
+    pub descriptors: Range<usize>,
+    /// Bytes of the data block
+    pub data: Range<usize>,
+    /// Bytes of the metadata block, `None` if the file has none
+    pub metadata: Option<Range<usize>>,
+}
+
+impl Layout {
+    /// Parse and validate the headers and record descriptors of DAFF data
+    ///
+    /// Every header field, record descriptor and metadata set is checked, so records can be
+    /// decoded without further validation. Errors of the data are reported as
+    /// [`Error::Corrupt`] with the offset of the offending field.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let byte_order = ByteOrder::detect(bytes).unwrap_or(ByteOrder::LittleEndian);
+        if bytes.get(..2) != Some(b"FW") {
+            return Err(Error::new("Not a DAFF file"));
+        }
+        let header = 0..bytes.len().min(daff::FILE_HEADER_SIZE as usize);
+        let mut input = Input::section(bytes, header, Section::FileHeader, byte_order);
+        input.take(2)?;
+        let at = input.offset();
+        let version = input.i32()?;
+        if (1..CURRENT_VERSION).contains(&version) {
+            return Err(input.error(
+                at,
+                format!(
+                    "File format version {} predates DAFF 1.7 and has to be upgraded first",
+                    version
+                ),
+            ));
+        } else if version != CURRENT_VERSION {
+            return Err(input.error(at, format!("Unsupported file format version {}", version)));
+        }
+        let at = input.offset();
+        let num_blocks = positive(&mut input, "file blocks")?;
+        let table = num_blocks
+            .checked_mul(daff::FILE_BLOCK_ENTRY_SIZE as usize)
+            .and_then(|size| size.checked_add(daff::FILE_HEADER_SIZE as usize))
+            .filter(|&end| end <= bytes.len())
+            .map(|end| daff::FILE_HEADER_SIZE as usize..end)
+            .ok_or_else(|| {
+                input.error(at, format!("{} file blocks exceed the data", num_blocks))
+            })?;
+
+        let mut input = Input::section(bytes, table.clone(), Section::BlockTable, byte_order);
+        let mut blocks: Vec<(i32, Range<usize>)> = Vec::new();
+        while !input.is_empty() {
+            let at = input.offset();
+            let id = input.i32()?;
+            let offset = input.u64()?;
+            let size = input.u64()?;
+            let range = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(size).ok())
+                .and_then(|(offset, size)| Some(offset..offset.checked_add(size)?))
+                .filter(|range| range.end <= bytes.len())
+                .ok_or_else(|| input.error(at, format!("File block {} exceeds the data", id)))?;
+            blocks.push((id, range));
+        }
+        // Sorted by ID and by position, duplicates and overlaps are neighbours
+        let entry = |index: usize| table.start + index * daff::FILE_BLOCK_ENTRY_SIZE as usize;
+        let mut order: Vec<usize> = (0..blocks.len()).collect();
+        order.sort_by_key(|&index| (blocks[index].0, index));
+        for pair in order.windows(2) {
+            if blocks[pair[0]].0 == blocks[pair[1]].0 {
+                let reason = format!("Duplicate file block {}", blocks[pair[1]].0);
+                return Err(Error::corrupt(Section::BlockTable, entry(pair[1]), reason));
+            }
+        }
+        order.retain(|&index| !blocks[index].1.is_empty());
+        order.sort_by_key(|&index| blocks[index].1.start);
+        let mut end = table.end;
+        for index in order {
+            let (id, range) = &blocks[index];
+            if range.start < end {
+                let reason = format!("File block {} overlaps the preceding data", id);
+                return Err(Error::corrupt(Section::BlockTable, entry(index), reason));
+            }
+            end = range.end;
+        }
+        let block = |id: i32, name: &str| {
+            blocks
+                .iter()
+                .find(|(block_id, _)| *block_id == id)
+                .map(|(_, range)| range.clone())
+                .ok_or_else(|| {
+                    Error::corrupt(
+                        Section::BlockTable,
+                        table.start,
+                        format!("File has no {} block", name),
+                    )
+                })
+        };
+
+        let range = block(daff::MAIN_HEADER_ID, "main header")?;
+        let mut input = Input::section(bytes, range, Section::MainHeader, byte_order);
+        let at = input.offset();
+        let value = input.i32()?;
+        let content_type = ContentType::from_i32(value)
+            .ok_or_else(|| input.error(at, format!("Unknown content type {}", value)))?;
+        let at = input.offset();
+        let value = input.i32()?;
+        let quantization = Quantization::from_i32(value)
+            .ok_or_else(|| input.error(at, format!("Unknown quantization {}", value)))?;
+        let num_channels = positive(&mut input, "channels")?;
+        let records_at = input.offset();
+        let num_records = positive(&mut input, "records")?;
+        let at = input.offset();
+        let stored_elements = positive(&mut input, "elements per record")?;
+        if stored_elements > daff::MAX_ELEMENTS_PER_RECORD {
+            return Err(input.error(
+                at,
+                format!("{} elements per record exceed the limit", stored_elements),
+            ));
+        }
+        let metadata_at = input.offset();
+        let metadata_index = input.i32()?;
+        let alpha_points = positive(&mut input, "alpha points")?;
+        let alpha_start = bounded(&mut input, "Alpha start", 0.0..360.0)?;
+        let alpha_end = bounded(&mut input, "Alpha end", 0.0..=360.0)?;
+        let beta_points = positive(&mut input, "beta points")?;
+        let beta_start = bounded(&mut input, "Beta start", 0.0..=180.0)?;
+        let at = input.offset();
+        let beta_end = bounded(&mut input, "Beta end", 0.0..=180.0)?;
+        if beta_end < beta_start {
+            return Err(input.error(at, "Beta end lies below beta start"));
+        }
+        let orientation = Orientation {
+            yaw: bounded(&mut input, "Yaw", f32::MIN..=f32::MAX)?,
+            pitch: bounded(&mut input, "Pitch", f32::MIN..=f32::MAX)?,
+            roll: bounded(&mut input, "Roll", f32::MIN..=f32::MAX)?,
+        };
+        let main = MainHeader {
+            alpha_points,
+            alpha_start,
+            alpha_end,
+            beta_points,
+            beta_start,
+            beta_end,
+            orientation,
+        };
+        if main.num_records() != num_records {
+            return Err(input.error(
+                records_at,
+                format!(
+                    "{} records do not match the {} records of the grid",
+                    num_records,
+                    main.num_records()
+                ),
+            ));
+        }
+
+        let range = block(daff::CONTENT_HEADER_ID, "content header")?;
+        let mut input = Input::section(bytes, range, Section::ContentHeader, byte_order);
+        let samplerate = |input: &mut Input| -> Result<f64> {
+            let at = input.offset();
+            let samplerate = input.f32()?;
+            if !(samplerate.is_finite() && samplerate >= 0.0) {
+                return Err(input.error(at, format!("Invalid sample rate {}", samplerate)));
+            }
+            Ok(samplerate as f64)
+        };
+        let frequencies = |input: &mut Input| -> Result<Vec<f32>> {
+            let at = input.offset();
+            let count = positive(input, "support frequencies")?;
+            if count != stored_elements {
+                return Err(input.error(
+                    at,
+                    format!(
+                        "{} support frequencies do not match {} elements per record",
+                        count, stored_elements
+                    ),
+                ));
+            }
+            (0..count)
+                .map(|_| bounded(input, "Support frequency", 0.0..=f32::MAX))
+                .collect()
+        };
+        let header = match content_type {
+            ContentType::ImpulseResponse => {
+                let samplerate = samplerate(&mut input)?;
+                for name in ["Minimum filter offset", "Maximum effective filter length"] {
+                    let at = input.offset();
+                    let value = input.i32()?;
+                    if usize::try_from(value).map_or(true, |value| value > stored_elements) {
+                        return Err(input.error(at, format!("{} {} is invalid", name, value)));
+                    }
+                }
+                ContentHeader::ImpulseResponse { samplerate }
+            }
+            ContentType::MagnitudeSpectrum => {
+                input.f32()?;
+                ContentHeader::MagnitudeSpectrum {
+                    frequencies: frequencies(&mut input)?,
+                }
+            }
+            ContentType::PhaseSpectrum => ContentHeader::PhaseSpectrum {
+                frequencies: frequencies(&mut input)?,
+            },
+            ContentType::MagnitudePhaseSpectrum => {
+                input.f32()?;
+                ContentHeader::MagnitudePhaseSpectrum {
+                    frequencies: frequencies(&mut input)?,
+                }
+            }
+            ContentType::DftSpectrum => {
+                let at = input.offset();
+                let coefficients = input.i32()?;
+                let transform_size = positive(&mut input, "DFT coefficients")?;
+                let symmetric = transform_size / 2 + 1;
+                if usize::try_from(coefficients) != Ok(stored_elements)
+                    || (stored_elements != transform_size && stored_elements != symmetric)
+                {
+                    return Err(input.error(
+                        at,
+                        format!(
+                            "{} DFT coefficients do not match a transform size of {}",
+                            coefficients, transform_size
+                        ),
+                    ));
+                }
+                ContentHeader::DftSpectrum {
+                    transform_size,
+                    samplerate: samplerate(&mut input)?,
+                }
+            }
+        };
+
+        let desc_size = if content_type == ContentType::ImpulseResponse {
+            daff::IR_DESC_SIZE
+        } else {
+            daff::DEFAULT_DESC_SIZE
+        } as usize;
+        let descriptors = block(daff::RECORD_DESC_ID, "record descriptor")?;
+        let count = num_records.saturating_mul(num_channels);
+        if count.saturating_mul(desc_size) > descriptors.len() {
+            return Err(Error::corrupt(
+                Section::RecordDescriptors,
+                descriptors.start,
+                format!(
+                    "Block holds {} of {} record descriptors",
+                    descriptors.len() / desc_size,
+                    count
+                ),
+            ));
+        }
+
+        // Metadata sets are counted to check the references to them
+        let metadata = block(daff::METADATA_ID, "metadata").ok();
+        let mut sets = 0;
+        if let Some(range) = metadata.clone() {
+            let mut input = Input::section(bytes, range, Section::Metadata, byte_order);
+            while !input.is_empty() {
+                decode_metadata(&mut input)?;
+                sets += 1;
+            }
+        }
+        let reference = |section: Section, at: usize, index: i32| {
+            if index < -1 || index >= sets {
+                let reason = format!("Invalid metadata set index {}", index);
+                return Err(Error::corrupt(section, at, reason));
+            }
+            Ok(())
+        };
+        reference(Section::MainHeader, metadata_at, metadata_index)?;
+
+        let layout = Self {
+            byte_order,
+            header,
+            quantization,
+            main,
+            num_channels,
+            num_records,
+            stored_elements,
+            desc_size,
+            descriptors: descriptors.clone(),
+            data: block(daff::DATA_ID, "data")?,
+            metadata,
+        };
+        let mut payloads = Vec::new();
+        for index in 0..count {
+            let start = descriptors.start + index * desc_size;
+            let mut input = Input::section(
+                bytes,
+                start..start + desc_size,
+                Section::RecordDescriptors,
+                byte_order,
+            );
+            reference(Section::RecordDescriptors, start, input.i32()?)?;
+            let (_, payload) = layout.payload(bytes, index / num_channels, index % num_channels)?;
+            let start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+            if !payload.is_empty() {
+                payloads.push(start..start + payload.len());
+            }
+        }
+        merge_payloads(payloads, quantization_size(layout.payload_quantization()))?;
+        Ok(layout)
+    }
+
+    /// Whether records store complex values as real/imaginary pairs
+    fn is_complex(&self) -> bool {
+        matches!(
+            self.header,
+            ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. }
+        )
+    }
+
+    /// Values per record channel as returned, complex values counting twice
+    pub fn elements_per_record(&self) -> usize {
+        self.stored_elements * if self.is_complex() { 2 } else { 1 }
+    }
+
+    /// Quantization of the payloads; spectra are always stored as 32-bit floats
+    pub fn payload_quantization(&self) -> Quantization {
+        match self.header {
+            ContentHeader::ImpulseResponse { .. } => self.quantization,
+            _ => Quantization::Float32,
+        }
+    }
+
+    /// Leading zeros and stored payload of a record channel
+    pub fn payload<'a>(
+        &self,
+        bytes: &'a [u8],
+        record: usize,
+        channel: usize,
+    ) -> Result<(usize, &'a [u8])> {
+        if record >= self.num_records || channel >= self.num_channels {
+            return Err(Error::new(format!(
+                "Invalid record {} or channel {}",
+                record, channel
+            )));
+        }
+        if self.descriptors.end.max(self.data.end) > bytes.len() {
+            return Err(Error::new("Data does not match the layout"));
+        }
+        let start =
+            self.descriptors.start + (record * self.num_channels + channel) * self.desc_size;
+        let mut input = Input::section(
+            bytes,
+            start..start + self.desc_size,
+            Section::RecordDescriptors,
+            self.byte_order,
+        );
+        let _metadata_index = input.i32()?;
+        let offset = input.u64()?;
+        let (leading_zeros, length) = match self.header {
+            ContentHeader::ImpulseResponse { .. } => (input.count()?, input.count()?),
+            _ => (0, self.elements_per_record()),
+        };
+        let invalid = || {
+            input.error(
+                start,
+                format!(
+                    "Record {} channel {} exceeds the data block",
+                    record, channel
+                ),
+            )
+        };
+        if leading_zeros.saturating_add(length) > self.elements_per_record() {
+            return Err(invalid());
+        }
+        let size = length * quantization_size(self.payload_quantization());
+        let offset = usize::try_from(offset).map_err(|_| invalid())?;
+        if offset > self.data.len() || size > self.data.len() - offset {
+            return Err(invalid());
+        }
+        let start = self.data.start + offset;
+        Ok((leading_zeros, &bytes[start..start + size]))
+    }
+
+    /// Decode a record channel from its leading zeros and stored payload, as returned by
+    /// [`payload`](Layout::payload), into `data`
+    ///
+    /// `data` must hold [`elements_per_record`](Layout::elements_per_record) values. Samples
+    /// of impulse responses outside of the stored range are set to zero.
+    pub fn decode_payload(
+        &self,
+        leading_zeros: usize,
+        payload: &[u8],
+        data: &mut [f32],
+    ) -> Result<()> {
+        if data.len() != self.elements_per_record() {
+            return Err(Error::new(format!(
+                "Buffer holds {} values, records have {}",
+                data.len(),
+                self.elements_per_record()
+            )));
+        }
+        let quantization = self.payload_quantization();
+        let size = quantization_size(quantization);
+        let end = leading_zeros.saturating_add(payload.len() / size);
+        if payload.len() % size != 0 || end > data.len() {
+            return Err(Error::new("Payload does not match the record layout"));
+        }
+        data[..leading_zeros].fill(0.0);
+        decode_into(
+            payload,
+            quantization,
+            self.byte_order,
+            &mut data[leading_zeros..end],
+        );
+        data[end..].fill(0.0);
+        Ok(())
+    }
+}
+
+/// Merge overlapping payloads of records
+///
+/// Records with equal data may share their payloads, or parts of them, but their values
+/// must not overlap partially.
+fn merge_payloads(mut payloads: Vec<Range<usize>>, value_size: usize) -> Result<Vec<Range<usize>>> {
+    payloads.sort_by_key(|payload| payload.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for payload in payloads {
+        match merged.last_mut() {
+            Some(last) if payload.start < last.end => {
+                if (payload.start - last.start) % value_size != 0 {
+                    let reason = "Values of record payloads overlap partially";
+                    return Err(Error::corrupt(Section::Data, payload.start, reason));
+                }
+                last.end = last.end.max(payload.end);
+            }
+            _ => merged.push(payload),
+        }
+    }
+    Ok(merged)
+}
+
+/// Count that has to be positive, e.g. the number of channels
+fn positive(input: &mut Input, name: &str) -> Result<usize> {
+    let at = input.offset();
+    match input.count()? {
+        0 => Err(input.error(at, format!("File has no {}", name))),
+        count => Ok(count),
+    }
+}
+
+/// Float within a range, e.g. an angle
+fn bounded(input: &mut Input, name: &str, range: impl RangeBounds<f32>) -> Result<f32> {
+    let at = input.offset();
+    let value = input.f32()?;
+    if !range.contains(&value) {
+        return Err(input.error(at, format!("{} {} is out of range", name, value)));
+    }
+    Ok(value)
+}
+
+/// DAFF data in memory, with its headers parsed
+///
+/// Record data is decoded from the storage when it is requested.
+#[derive(Debug, Clone)]
+pub struct ParsedFile<S = Vec<u8>> {
+    data: S,
+    layout: Layout,
+}
+
+impl<S: AsRef<[u8]>> ParsedFile<S> {
+    /// Parse the headers of DAFF data, validating every header field and record descriptor
+    ///
+    /// Damaged data is reported as [`Error::Corrupt`] with the section and byte offset of the
+    /// first invalid field.
+    pub fn new(data: S) -> Result<Self> {
+        let layout = Layout::parse(data.as_ref())?;
+        Ok(Self { data, layout })
+    }
+
+    /// The underlying data
+    pub fn bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// The storage of the data
+    pub fn get_ref(&self) -> &S {
+        &self.data
+    }
+
+    /// Give back the storage of the data
+    pub fn into_inner(self) -> S {
+        self.data
+    }
+
+    /// Headers and block locations
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Content type and content-specific header values
+    pub fn header(&self) -> &ContentHeader {
+        &self.layout.header
+    }
+
+    /// Content type of the file
+    pub fn content_type(&self) -> ContentType {
+        self.layout.header.content_type()
+    }
+
+    /// Byte order the data is stored in
+    pub fn byte_order(&self) -> ByteOrder {
+        self.layout.byte_order
+    }
+
+    /// Quantization of the stored data
+    pub fn quantization(&self) -> Quantization {
+        self.layout.quantization
+    }
+
+    /// Grid and orientation from the main header
+    pub fn main_header(&self) -> &MainHeader {
+        &self.layout.main
+    }
+
+    /// Default orientation (yaw-pitch-roll in degrees)
+    pub fn orientation(&self) -> Orientation {
+        self.layout.main.orientation
+    }
+
+    /// Number of channels
+    pub fn num_channels(&self) -> usize {
+        self.layout.num_channels
+    }
+
+    /// Number of records
+    pub fn num_records(&self) -> usize {
+        self.layout.num_records
+    }
+
+    /// Number of values per record and channel, with interleaved real/imaginary values for
+    /// MPS and DFT content
+    pub fn elements_per_record(&self) -> usize {
+        self.layout.elements_per_record()
+    }
+
+    /// Data of one record channel
+    ///
+    /// Borrows the values straight from the data where the file stores them as aligned
+    /// 32-bit floats in the byte order of the host covering the whole record; otherwise
+    /// decodes them into a new vector.
+    pub fn channel(&self, record: usize, channel: usize) -> Result<Cow<'_, [f32]>> {
+        let (leading_zeros, payload) = self.layout.payload(self.data.as_ref(), record, channel)?;
+        let elements = self.elements_per_record();
+        if self.layout.byte_order == ByteOrder::native()
+            && self.layout.payload_quantization() == Quantization::Float32
+            && leading_zeros == 0
+            && payload.len() == 4 * elements
+        {
+            // Safety: every bit pattern is a valid f32, and the prefix check below ensures
+            // the values are aligned
+            let (prefix, values, _) = unsafe { payload.align_to::<f32>() };
+            if prefix.is_empty() && values.len() == elements {
+                return Ok(Cow::Borrowed(values));
+            }
+        }
+        let mut data = vec![0.0; elements];
+        self.read_channel(record, channel, &mut data)?;
+        Ok(Cow::Owned(data))
+    }
+
+    /// Decode one record channel into `data` without allocating
+    ///
+    /// `data` must hold [`elements_per_record`](ParsedFile::elements_per_record) values.
+    /// Samples of impulse responses outside of the stored range are set to zero.
+    pub fn read_channel(&self, record: usize, channel: usize, data: &mut [f32]) -> Result<()> {
+        let (leading_zeros, payload) = self.layout.payload(self.data.as_ref(), record, channel)?;
+        self.layout.decode_payload(leading_zeros, payload, data)
+    }
+
+    /// Global metadata, with keys in upper case like the C++ reader reports them
+    pub fn metadata(&self) -> Result<Metadata> {
+        let Some(range) = self.layout.metadata.clone() else {
+            return Ok(Metadata::new());
+        };
+        if range.is_empty() {
+            return Ok(Metadata::new());
+        }
+        // Sets are stored one after another, the global set first
+        let mut input = Input::with_byte_order(&self.data.as_ref()[range], self.layout.byte_order);
+        Ok(decode_metadata(&mut input)?
+            .into_iter()
+            .map(|(key, value)| (key.to_ascii_uppercase(), value))
+            .collect())
+    }
+}
+
+/// Convert DAFF data to the other byte order
+///
+/// Turns byte-swapped files into regular little-endian ones, e.g. so the C++ library can read
+/// them, and the other way around. Every header field, record descriptor, sample and
+/// metadata value is swapped; strings are kept.
+pub fn swap_byte_order(bytes: &[u8]) -> Result<Vec<u8>> {
+    let layout = Layout::parse(bytes)?;
+    let byte_order = layout.byte_order;
+    let mut swapped = bytes.to_vec();
+    let mut reverse = |start: usize, sizes: &[usize]| {
+        let mut at = start;
+        for &size in sizes {
+            swapped[at..at + size].reverse();
+            at += size;
+        }
+    };
+
+    // Parsing the layout checked the file header and block table
+    reverse(2, &[4, 4]);
+    let mut input = Input::with_byte_order(&bytes[6..], byte_order);
+    let mut headers = Vec::new();
+    for i in 0..input.count()? {
+        let id = input.i32()?;
+        let offset = input.u64()? as usize;
+        let size = input.u64()? as usize;
+        reverse(10 + i * 20, &[4, 8, 8]);
+        if id == daff::MAIN_HEADER_ID || id == daff::CONTENT_HEADER_ID {
+            headers.push(offset..offset + size / 4 * 4);
+        }
+    }
+    // Both headers consist of 32-bit fields only
+    for header in headers {
+        for at in header.step_by(4) {
+            reverse(at, &[4]);
+        }
+    }
+
+    let descriptor: &[usize] = match layout.header {
+        ContentHeader::ImpulseResponse { .. } => &[4, 8, 4, 4],
+        _ => &[4, 8],
+    };
+    let value_size = quantization_size(layout.payload_quantization());
+    let mut payloads = Vec::new();
+    for record in 0..layout.num_records {
+        for channel in 0..layout.num_channels {
+            let (_, payload) = layout.payload(bytes, record, channel)?;
+            let start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+            if !payload.is_empty() {
+                payloads.push(start..start + payload.len());
+            }
+            let index = record * layout.num_channels + channel;
+            reverse(
+                layout.descriptors.start + index * layout.desc_size,
+                descriptor,
+            );
+        }
+    }
+    // Every value is swapped once, also where records share their payloads
+    for payload in merge_payloads(payloads, value_size)? {
+        reverse(payload.start, &vec![value_size; payload.len() / value_size]);
+    }
+
+    if let Some(range) = layout.metadata.clone() {
+        let end = range.end;
+        let mut input = Input::with_byte_order(&bytes[range], byte_order);
+        while !input.is_empty() {
+            reverse(end - input.len(), &[4]);
+            for _ in 0..input.count()? {
+                reverse(end - input.len(), &[4]);
+                let value_type = input.i32()?;
+                input.string()?;
+                let size = match MetadataType::from_i32(value_type) {
+                    Some(MetadataType::Bool | MetadataType::Int) => 4,
+                    Some(MetadataType::Float) => 8,
+                    Some(MetadataType::String) => {
+                        input.string()?;
+                        continue;
+                    }
+                    None => return Err(Error::new("Unknown metadata value type")),
+                };
+                let at = end - input.len();
+                input.take(size)?;
+                reverse(at, &[size]);
+            }
+        }
+    }
+    Ok(swapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    /// A file with one record of one impulse response channel, built field by field
+    fn minimal_file() -> Vec<u8> {
+        let mut bytes = b"FW".to_vec();
+        let push = |bytes: &mut Vec<u8>, fields: &[&[u8]]| {
+            fields
+                .iter()
+                .for_each(|field| bytes.extend_from_slice(field))
+        };
+        push(
+            &mut bytes,
+            &[&CURRENT_VERSION.to_le_bytes(), &4i32.to_le_bytes()],
+        );
+        let blocks = [
+            (daff::MAIN_HEADER_ID, 90, 60),
+            (daff::CONTENT_HEADER_ID, 150, 12),
+            (daff::RECORD_DESC_ID, 162, 20),
+            (daff::DATA_ID, 182, 8),
+        ];
+        for (id, offset, size) in blocks {
+            push(
+                &mut bytes,
+                &[
+                    &id.to_le_bytes(),
+                    &u64::to_le_bytes(offset),
+                    &u64::to_le_bytes(size),
+                ],
+            );
+        }
+        // Impulse responses as floats, one channel, one record, two samples, no metadata
+        for field in [0, 2, 1, 1, 2, -1] {
+            push(&mut bytes, &[&i32::to_le_bytes(field)]);
+        }
+        // A single direction at the north pole, no rotation
+        let alpha = [0.0f32.to_le_bytes(), 0.0f32.to_le_bytes()];
+        let beta = [90.0f32.to_le_bytes(), 90.0f32.to_le_bytes()];
+        push(&mut bytes, &[&1i32.to_le_bytes(), &alpha[0], &alpha[1]]);
+        push(&mut bytes, &[&1i32.to_le_bytes(), &beta[0], &beta[1]]);
+        push(&mut bytes, &[&[0; 12]]);
+        // Sample rate, minimum filter offset and maximum effective filter length
+        push(
+            &mut bytes,
+            &[
+                &44100.0f32.to_le_bytes(),
+                &0i32.to_le_bytes(),
+                &2i32.to_le_bytes(),
+            ],
+        );
+        push(
+            &mut bytes,
+            &[
+                &(-1i32).to_le_bytes(),
+                &0u64.to_le_bytes(),
+                &0i32.to_le_bytes(),
+                &2i32.to_le_bytes(),
+            ],
+        );
+        push(
+            &mut bytes,
+            &[&0.5f32.to_le_bytes(), &(-0.25f32).to_le_bytes()],
+        );
+        bytes
+    }
+
+    #[test]
+    fn test_minimal_file() {
+        let bytes = minimal_file();
+        let file = ParsedFile::new(&bytes[..]).unwrap();
+        assert_eq!(
+            *file.header(),
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0
+            }
+        );
+        assert_eq!((file.num_channels(), file.num_records()), (1, 1));
+        assert_eq!(file.main_header().beta_start, 90.0);
+        assert_eq!(*file.channel(0, 0).unwrap(), [0.5, -0.25]);
+        assert!(file.channel(1, 0).is_err());
+        assert!(file.metadata().unwrap().is_empty());
+
+        let swapped = swap_byte_order(&bytes).unwrap();
+        let file = ParsedFile::new(swapped.as_slice()).unwrap();
+        assert_eq!(file.byte_order(), ByteOrder::BigEndian);
+        let mut data = [0.0; 2];
+        file.read_channel(0, 0, &mut data).unwrap();
+        assert_eq!(data, [0.5, -0.25]);
+        assert_eq!(swap_byte_order(&swapped).unwrap(), bytes);
+
+        assert!(ParsedFile::new(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ParsedFile::new(&b"FW"[..]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_fields() {
+        let bytes = minimal_file();
+        let corrupt = |at: usize, field: &[u8]| {
+            let mut corrupt = bytes.clone();
+            corrupt[at..at + field.len()].copy_from_slice(field);
+            match ParsedFile::new(corrupt.as_slice()).unwrap_err() {
+                Error::Corrupt {
+                    section, offset, ..
+                } => (section, offset),
+                error => panic!("{}", error),
+            }
+        };
+        // File block table: data block starting within the record descriptors
+        assert_eq!(
+            corrupt(74, &170u64.to_le_bytes()),
+            (Section::BlockTable, 70)
+        );
+        assert_eq!(corrupt(70, &3i32.to_le_bytes()), (Section::BlockTable, 70));
+        // Main header: content type, record count, elements per record and alpha start
+        assert_eq!(corrupt(90, &7i32.to_le_bytes()), (Section::MainHeader, 90));
+        assert_eq!(
+            corrupt(102, &2i32.to_le_bytes()),
+            (Section::MainHeader, 102)
+        );
+        let elements = (daff::MAX_ELEMENTS_PER_RECORD as i32 + 1).to_le_bytes();
+        assert_eq!(corrupt(106, &elements), (Section::MainHeader, 106));
+        assert_eq!(
+            corrupt(118, &f32::NAN.to_le_bytes()),
+            (Section::MainHeader, 118)
+        );
+        // Content header: maximum effective filter length
+        assert_eq!(
+            corrupt(158, &3i32.to_le_bytes()),
+            (Section::ContentHeader, 158)
+        );
+        // Record descriptor: metadata set index without metadata, samples beyond the data
+        assert_eq!(
+            corrupt(162, &0i32.to_le_bytes()),
+            (Section::RecordDescriptors, 162)
+        );
+        assert_eq!(
+            corrupt(166, &1u64.to_le_bytes()),
+            (Section::RecordDescriptors, 162)
+        );
+
+        let error = Error::corrupt(Section::MainHeader, 90, "Unknown content type 7");
+        assert_eq!(
+            error.to_string(),
+            "Unknown content type 7 in the main header at byte 90"
+        );
+    }
+}
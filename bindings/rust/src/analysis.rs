@@ -0,0 +1,1921 @@
+//! Statistical analysis across datasets
+//!
+//! Functions in this module look at many records (and usually many datasets, e.g. one per
+//! subject) at once, like the principal component models used for HRTF compression and
+//! personalization.
+//!
+//! [`lufs`] and the [`LoudnessMeter`] behind it measure the loudness of rendered output after
+//! ITU-R BS.1770, e.g. to play a stimulus at the same level through different HRTF sets in a
+//! listening test.
+//!
+//! [`channel_balance`] compares the channels of a single dataset over all directions, e.g. to
+//! find the calibration errors of a microphone.
+
+use std::borrow::Cow;
+use std::f64::consts::TAU;
+
+use crate::decibel::{self, MAGNITUDE_FLOOR};
+use crate::grid::SH_REGULARIZATION;
+use crate::sh;
+use crate::{
+    ContentHeader, ContentType, Dataset, Error, MagnitudeUnit, Record, Result, ShCoefficients,
+};
+
+/// Maximum number of subspace iterations when computing the principal components
+const MAX_ITERATIONS: usize = 1000;
+
+/// Principal component basis of log-magnitude spectra
+///
+/// The model works on magnitudes in decibels: a spectrum is approximated by the mean spectrum
+/// plus a weighted sum of the components.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcaBasis {
+    /// Support frequencies in Hz
+    pub frequencies: Vec<f32>,
+    /// Mean log-magnitude spectrum in dB
+    pub mean: Vec<f32>,
+    /// Orthonormal components, sorted by decreasing explained variance
+    pub components: Vec<Vec<f32>>,
+    /// Variance explained by each component (dB²)
+    pub explained_variance: Vec<f32>,
+    /// Total variance of the training spectra (dB²)
+    pub total_variance: f32,
+}
+
+impl PcaBasis {
+    /// Number of components
+    pub fn num_components(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Fraction of the total variance explained by each component
+    pub fn explained_variance_ratio(&self) -> Vec<f32> {
+        self.explained_variance
+            .iter()
+            .map(|v| {
+                if self.total_variance > 0.0 {
+                    v / self.total_variance
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Component weights of a (linear) magnitude spectrum
+    pub fn project(&self, magnitudes: &[f32]) -> Result<Vec<f32>> {
+        if magnitudes.len() != self.mean.len() {
+            return Err(Error::new(format!(
+                "Spectrum has {} frequencies, the basis {}",
+                magnitudes.len(),
+                self.mean.len()
+            )));
+        }
+        let mut centered = magnitudes.to_vec();
+        decibel::to_db_slice(&mut centered);
+        for (value, mean) in centered.iter_mut().zip(&self.mean) {
+            *value -= mean;
+        }
+        Ok(self
+            .components
+            .iter()
+            .map(|c| c.iter().zip(&centered).map(|(a, b)| a * b).sum())
+            .collect())
+    }
+
+    /// (Linear) magnitude spectrum described by the given component weights
+    ///
+    /// Missing weights are taken as zero, surplus weights are ignored.
+    pub fn reconstruct(&self, weights: &[f32]) -> Vec<f32> {
+        let mut db = self.mean.clone();
+        for (component, &weight) in self.components.iter().zip(weights) {
+            for (value, c) in db.iter_mut().zip(component) {
+                *value += weight * c;
+            }
+        }
+        decibel::from_db_slice(&mut db);
+        db
+    }
+
+    /// Project every record and channel of a dataset onto the basis
+    ///
+    /// The returned records hold the component weights per channel instead of spectra.
+    pub fn project_dataset(&self, dataset: &Dataset) -> Result<Vec<Record>> {
+        spectrum_frequencies(&dataset.header)?;
+        let unit = dataset.magnitude_unit();
+        dataset
+            .records
+            .iter()
+            .map(|record| {
+                let channels = record
+                    .channels
+                    .iter()
+                    .map(|data| self.project(&magnitudes(dataset, unit, data)))
+                    .collect::<Result<_>>()?;
+                Ok(Record {
+                    alpha: record.alpha,
+                    beta: record.beta,
+                    channels,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Compute a principal component basis over the magnitude spectra of several datasets
+///
+/// Every record and channel of every dataset is one observation. Magnitude spectra are used
+/// as linear magnitudes (converted according to their [`MagnitudeUnit`], so datasets in dB
+/// and linear ones can be mixed), complex spectra (magnitude-phase and DFT) by their absolute
+/// values. All datasets must share the same support frequencies (or DFT size).
+pub fn pca_basis(datasets: &[Dataset], n_components: usize) -> Result<PcaBasis> {
+    let first = datasets
+        .first()
+        .ok_or_else(|| Error::new("No datasets given"))?;
+    let frequencies = spectrum_frequencies(&first.header)?;
+    if datasets
+        .iter()
+        .any(|d| spectrum_frequencies(&d.header).ok() != Some(frequencies.clone()))
+    {
+        return Err(Error::new("Datasets have different frequency supports"));
+    }
+
+    let observations: Vec<Vec<f32>> = datasets
+        .iter()
+        .flat_map(|d| {
+            let unit = d.magnitude_unit();
+            d.records
+                .iter()
+                .flat_map(move |r| r.channels.iter().map(move |data| magnitudes(d, unit, data)))
+        })
+        .map(|mut m| {
+            decibel::to_db_slice(&mut m);
+            m
+        })
+        .collect();
+    let bins = observations.first().map_or(0, Vec::len);
+    if observations.len() < 2 || bins == 0 {
+        return Err(Error::new("At least two non-empty spectra are needed"));
+    }
+    if observations.iter().any(|o| o.len() != bins) {
+        return Err(Error::new("Spectra have different lengths"));
+    }
+    let n_components = n_components.min(bins);
+
+    let count = observations.len() as f64;
+    let mut mean = vec![0.0f64; bins];
+    for o in &observations {
+        for (m, &x) in mean.iter_mut().zip(o) {
+            *m += x as f64 / count;
+        }
+    }
+    let mut covariance = vec![vec![0.0f64; bins]; bins];
+    for o in &observations {
+        let centered: Vec<f64> = o.iter().zip(&mean).map(|(&x, m)| x as f64 - m).collect();
+        for (row, &ci) in covariance.iter_mut().zip(&centered) {
+            for (value, &cj) in row.iter_mut().zip(&centered) {
+                *value += ci * cj / count;
+            }
+        }
+    }
+    let total_variance: f64 = (0..bins).map(|i| covariance[i][i]).sum();
+    let (components, variances) = leading_eigenvectors(&covariance, n_components);
+
+    Ok(PcaBasis {
+        frequencies,
+        mean: mean.into_iter().map(|m| m as f32).collect(),
+        components: components
+            .into_iter()
+            .map(|c| c.into_iter().map(|x| x as f32).collect())
+            .collect(),
+        explained_variance: variances.into_iter().map(|v| v as f32).collect(),
+        total_variance: total_variance as f32,
+    })
+}
+
+/// Method reconstructing a record from the other records of a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interp {
+    /// Data of the closest record
+    NearestNeighbour,
+    /// Average of the closest records, weighted by their inverse squared great-circle
+    /// distance
+    InverseDistance {
+        /// Number of records taken into account
+        neighbours: usize,
+    },
+    /// Spherical harmonic expansion of the given order (see [`ShCoefficients`])
+    SphericalHarmonics {
+        /// Maximum spherical harmonic order
+        order: usize,
+    },
+}
+
+/// Reconstruction error of one record, see [`interpolation_error`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectionError {
+    /// Alpha angle in degrees (data view)
+    pub alpha: f32,
+    /// Beta angle in degrees (data view)
+    pub beta: f32,
+    /// Normalized squared error per channel in dB
+    pub channels: Vec<f32>,
+}
+
+/// Leave-one-out reconstruction errors of all records of a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpolationReport {
+    /// Errors in storage order
+    pub directions: Vec<DirectionError>,
+}
+
+impl InterpolationReport {
+    /// Average error over all directions and channels in dB (mean of the linear errors)
+    pub fn mean_db(&self) -> f32 {
+        let errors: Vec<f32> = self.errors().collect();
+        if errors.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let mean = errors
+            .iter()
+            .map(|&e| 10f64.powf(e as f64 / 10.0))
+            .sum::<f64>()
+            / errors.len() as f64;
+        (10.0 * mean.log10()) as f32
+    }
+
+    /// Largest error over all directions and channels in dB
+    pub fn max_db(&self) -> f32 {
+        self.errors().fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    /// Direction with the largest error of any channel
+    pub fn worst(&self) -> Option<&DirectionError> {
+        self.directions.iter().max_by(|a, b| {
+            let max =
+                |d: &DirectionError| d.channels.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            max(a).total_cmp(&max(b))
+        })
+    }
+
+    fn errors(&self) -> impl Iterator<Item = f32> + '_ {
+        self.directions
+            .iter()
+            .flat_map(|d| d.channels.iter().copied())
+    }
+}
+
+/// Leave-one-out interpolation error of every record
+///
+/// Each record is left out in turn, reconstructed from the remaining records with the given
+/// method and compared with the original. The error is the squared difference relative to
+/// the energy of the record, `10·log10(Σ|x̂ - x|² / Σ|x|²)` per channel (complex spectra
+/// compare their complex values), so -20 dB means that the reconstruction deviates by 10%.
+/// Comparing the reports of several grid resolutions or methods shows which one represents a
+/// dataset adequately.
+///
+/// Spherical harmonic fits use the closed-form leave-one-out residuals of the regularized
+/// least-squares fit instead of refitting for every record. Magnitude spectra in dB are
+/// compared as linear magnitudes. Phase spectra are not supported, since their values wrap
+/// around.
+pub fn interpolation_error(dataset: &Dataset, method: Interp) -> Result<InterpolationReport> {
+    if dataset.content_type() == ContentType::PhaseSpectrum {
+        return Err(Error::new(
+            "Interpolation errors of phase spectra are not supported",
+        ));
+    }
+    if dataset.records.len() < 2 {
+        return Err(Error::new(
+            "Interpolation errors require at least two records",
+        ));
+    }
+
+    let dataset = linear_magnitudes(dataset);
+    let dataset = dataset.as_ref();
+    let estimates = match method {
+        Interp::SphericalHarmonics { order } => leave_one_out_sh(dataset, order)?,
+        _ => weighted_estimates(&dataset.records, &dataset.records, true, method)?,
+    };
+    Ok(report(&dataset.records, &estimates))
+}
+
+/// Error of reconstructing every record of a dataset from the records of a sparser one
+///
+/// Like [`interpolation_error`], but the records of `reference` are interpolated from the
+/// records of `sparse`, e.g. a subsampled version of it. Both datasets need the same content,
+/// channels and elements; magnitude spectra may differ in their [`MagnitudeUnit`].
+pub fn reconstruction_error(
+    reference: &Dataset,
+    sparse: &Dataset,
+    method: Interp,
+) -> Result<InterpolationReport> {
+    if reference.content_type() == ContentType::PhaseSpectrum {
+        return Err(Error::new(
+            "Interpolation errors of phase spectra are not supported",
+        ));
+    }
+    if reference.header != sparse.header
+        || reference.num_channels() != sparse.num_channels()
+        || reference.elements_per_record() != sparse.elements_per_record()
+    {
+        return Err(Error::new(
+            "Datasets differ in their content, channels or elements",
+        ));
+    }
+    if sparse.records.is_empty() {
+        return Err(Error::new("No records to interpolate from"));
+    }
+    let (reference, sparse) = (linear_magnitudes(reference), linear_magnitudes(sparse));
+    let (reference, sparse) = (reference.as_ref(), sparse.as_ref());
+
+    let estimates = match method {
+        Interp::SphericalHarmonics { order } => {
+            let expansion = ShCoefficients::fit(sparse, order)?;
+            reference
+                .records
+                .iter()
+                .map(|r| {
+                    let basis = sh::real_basis(order, r.alpha, r.beta);
+                    (0..expansion.num_channels())
+                        .map(|c| {
+                            (0..expansion.elements_per_record())
+                                .map(|e| sh::evaluate(&basis, expansion.coefficients(c, e)))
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+        _ => weighted_estimates(&sparse.records, &reference.records, false, method)?,
+    };
+    Ok(report(&reference.records, &estimates))
+}
+
+/// Normalized squared errors of the estimates of the given records
+fn report(records: &[Record], estimates: &[Vec<Vec<f64>>]) -> InterpolationReport {
+    let directions = records
+        .iter()
+        .zip(estimates)
+        .map(|(record, estimate)| DirectionError {
+            alpha: record.alpha,
+            beta: record.beta,
+            channels: record
+                .channels
+                .iter()
+                .zip(estimate)
+                .map(|(data, estimate)| {
+                    let energy: f64 = data.iter().map(|&x| (x as f64).powi(2)).sum();
+                    let error: f64 = data
+                        .iter()
+                        .zip(estimate)
+                        .map(|(&x, y)| (y - x as f64).powi(2))
+                        .sum();
+                    (10.0 * (error / energy).log10()) as f32
+                })
+                .collect(),
+        })
+        .collect();
+    InterpolationReport { directions }
+}
+
+/// Representation of complex spectra that [`interpolate`] interpolates
+///
+/// Interpolating magnitudes alone discards the phase and with it the interaural time
+/// difference, while real and imaginary parts of records with different delays partly cancel
+/// each other. Unwrapping the phases along frequency turns delays into phase slopes that
+/// interpolate smoothly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectrumDomain {
+    /// Magnitudes only; the phases are taken from the closest record
+    Magnitude,
+    /// Real and imaginary parts
+    #[default]
+    Complex,
+    /// Magnitudes and phases separately, with the phases unwrapped along frequency
+    UnwrappedPhase,
+}
+
+/// Data of every channel of a dataset interpolated towards a direction (alpha, beta in
+/// degrees, data view)
+///
+/// The closest records are combined with the given method. For magnitude-phase and DFT
+/// content `domain` selects what is interpolated; phase spectra are unwrapped along
+/// frequency with [`SpectrumDomain::UnwrappedPhase`] and interpolated as stored otherwise.
+/// Impulse responses are interpolated as stored, magnitude spectra as linear magnitudes. The
+/// result has the layout and unit of [`Record::channels`], phases of phase spectra are
+/// wrapped to ±π again.
+///
+/// ```
+/// use opendaff::analysis::{self, Interp, SpectrumDomain};
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// // A delay that grows towards the side, as seen by one ear
+/// let frequencies: Vec<f32> = (1..=16).map(|k| 1000.0 * k as f32).collect();
+/// let delay = |alpha: f32| 0.3e-3 * alpha.to_radians().sin().max(0.0);
+/// let dataset = Dataset::from_fn(
+///     ContentHeader::MagnitudePhaseSpectrum { frequencies: frequencies.clone() },
+///     EquiangularGrid::with_resolution(30.0, 30.0)?,
+///     1,
+///     |alpha, _, _| {
+///         frequencies
+///             .iter()
+///             .flat_map(|f| {
+///                 let phase = -std::f32::consts::TAU * f * delay(alpha);
+///                 [phase.cos(), phase.sin()]
+///             })
+///             .collect()
+///     },
+/// );
+/// let method = Interp::InverseDistance { neighbours: 2 };
+/// let channels = analysis::interpolate(&dataset, 45.0, 90.0, method, SpectrumDomain::UnwrappedPhase)?;
+/// // The magnitudes stay at 1 instead of dropping where the neighbours are out of phase
+/// let magnitude = channels[0][30].hypot(channels[0][31]);
+/// assert!((magnitude - 1.0).abs() < 1e-3);
+/// # Ok(())
+/// # }
+/// ```
+pub fn interpolate(
+    dataset: &Dataset,
+    alpha: f32,
+    beta: f32,
+    method: Interp,
+    domain: SpectrumDomain,
+) -> Result<Vec<Vec<f32>>> {
+    Ok(interpolate_many(dataset, &[(alpha, beta)], method, domain)?.remove(0))
+}
+
+/// [`interpolate`] at several directions, sharing the per-record preparation and the
+/// spherical harmonic fit between them
+pub(crate) fn interpolate_many(
+    dataset: &Dataset,
+    directions: &[(f32, f32)],
+    method: Interp,
+    domain: SpectrumDomain,
+) -> Result<Vec<Vec<Vec<f32>>>> {
+    let linear = linear_magnitudes(dataset);
+    let mut channels = interpolate_stored(&linear, directions, method, domain)?;
+    if let Cow::Owned(_) = linear {
+        for data in channels.iter_mut().flatten() {
+            decibel::to_db_slice(data);
+        }
+    }
+    Ok(channels)
+}
+
+/// [`interpolate_many`] of the data as stored
+fn interpolate_stored(
+    dataset: &Dataset,
+    directions: &[(f32, f32)],
+    method: Interp,
+    domain: SpectrumDomain,
+) -> Result<Vec<Vec<Vec<f32>>>> {
+    if dataset.records.is_empty() {
+        return Err(Error::new("Interpolation requires at least one record"));
+    }
+    let complex = matches!(
+        dataset.content_type(),
+        ContentType::MagnitudePhaseSpectrum | ContentType::DftSpectrum
+    );
+    let phases = dataset.content_type() == ContentType::PhaseSpectrum;
+    let domain = match domain {
+        SpectrumDomain::UnwrappedPhase if phases => SpectrumDomain::UnwrappedPhase,
+        domain if complex => domain,
+        _ => SpectrumDomain::Complex,
+    };
+    // Values that are interpolated linearly, per record and channel
+    let features = |data: &[f32]| -> Vec<f64> {
+        match domain {
+            SpectrumDomain::Complex => data.iter().map(|&x| x as f64).collect(),
+            SpectrumDomain::Magnitude => data
+                .chunks(2)
+                .map(|c| (c[0] as f64).hypot(c[1] as f64))
+                .collect(),
+            SpectrumDomain::UnwrappedPhase if phases => {
+                unwrap_phases(data.iter().map(|&p| p as f64).collect())
+            }
+            SpectrumDomain::UnwrappedPhase => {
+                let magnitudes = data.chunks(2).map(|c| (c[0] as f64).hypot(c[1] as f64));
+                let angles = data
+                    .chunks(2)
+                    .map(|c| (c[1] as f64).atan2(c[0] as f64))
+                    .collect();
+                magnitudes.chain(unwrap_phases(angles)).collect()
+            }
+        }
+    };
+    let features: Vec<Vec<Vec<f64>>> = dataset
+        .records
+        .iter()
+        .map(|r| r.channels.iter().map(|c| features(c)).collect())
+        .collect();
+
+    let vectors: Vec<[f64; 3]> = dataset
+        .records
+        .iter()
+        .map(|r| unit_vector(r.alpha, r.beta))
+        .collect();
+    let fit = match method {
+        Interp::SphericalHarmonics { order } => {
+            let sampled: Vec<(f32, f32)> =
+                dataset.records.iter().map(|r| (r.alpha, r.beta)).collect();
+            let fit = sh::Fit::new(order, &sampled, SH_REGULARIZATION)?;
+            // Coefficients per channel and element, fitted once for all directions
+            let mut values = vec![0.0f64; dataset.records.len()];
+            let coefficients: Vec<Vec<Vec<f64>>> = (0..dataset.num_channels())
+                .map(|channel| {
+                    (0..features[0][channel].len())
+                        .map(|element| {
+                            for (value, record) in values.iter_mut().zip(&features) {
+                                *value = record[channel][element];
+                            }
+                            fit.coefficients(&values)
+                        })
+                        .collect()
+                })
+                .collect();
+            Some((order, coefficients))
+        }
+        _ => None,
+    };
+
+    directions
+        .iter()
+        .map(|&(alpha, beta)| {
+            let target = unit_vector(alpha, beta);
+            let estimates: Vec<Vec<f64>> = match &fit {
+                Some((order, coefficients)) => {
+                    let basis = sh::real_basis(*order, alpha, beta);
+                    coefficients
+                        .iter()
+                        .map(|channel| channel.iter().map(|c| sh::evaluate(&basis, c)).collect())
+                        .collect()
+                }
+                None => {
+                    let closest = closest_weights(&vectors, target, None, method)?;
+                    (0..dataset.num_channels())
+                        .map(|channel| {
+                            let mut estimate = vec![0.0f64; features[0][channel].len()];
+                            for &(j, w) in &closest {
+                                for (e, x) in estimate.iter_mut().zip(&features[j][channel]) {
+                                    *e += w * x;
+                                }
+                            }
+                            estimate
+                        })
+                        .collect()
+                }
+            };
+
+            let nearest = closest_weights(&vectors, target, None, Interp::NearestNeighbour)?[0].0;
+            Ok(estimates
+                .into_iter()
+                .enumerate()
+                .map(|(channel, estimate)| match domain {
+                    SpectrumDomain::Complex => estimate.iter().map(|&x| x as f32).collect(),
+                    SpectrumDomain::Magnitude => estimate
+                        .iter()
+                        .zip(dataset.records[nearest].channels[channel].chunks(2))
+                        .flat_map(|(&m, c)| {
+                            let phase = (c[1] as f64).atan2(c[0] as f64);
+                            [(m * phase.cos()) as f32, (m * phase.sin()) as f32]
+                        })
+                        .collect(),
+                    SpectrumDomain::UnwrappedPhase if phases => estimate
+                        .iter()
+                        .map(|&p| (p - TAU * (p / TAU).round()) as f32)
+                        .collect(),
+                    SpectrumDomain::UnwrappedPhase => {
+                        let (magnitudes, angles) = estimate.split_at(estimate.len() / 2);
+                        magnitudes
+                            .iter()
+                            .zip(angles)
+                            .flat_map(|(&m, &p)| [(m * p.cos()) as f32, (m * p.sin()) as f32])
+                            .collect()
+                    }
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// Phases along frequency with jumps of more than π removed
+fn unwrap_phases(mut phases: Vec<f64>) -> Vec<f64> {
+    for k in 1..phases.len() {
+        let step = phases[k] - phases[k - 1];
+        phases[k] -= TAU * (step / TAU).round();
+    }
+    phases
+}
+
+/// Kind of a phase discontinuity between neighbouring records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseIssue {
+    /// The stored phases differ by about a multiple of 2π: the values are consistent, but one
+    /// of them is wrapped differently, so interpolating the stored values goes wrong
+    Wrap,
+    /// The phases differ by more than the threshold even after unwrapping
+    Jump,
+}
+
+/// Phase discontinuity between two neighbouring records, see [`phase_continuity`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseDiscontinuity {
+    /// Record indices of the neighbours
+    pub records: (usize, usize),
+    /// Channel index
+    pub channel: usize,
+    /// Support frequency in Hz
+    pub frequency: f32,
+    /// Phase of the second record minus phase of the first one in radians, as stored
+    pub difference: f32,
+    /// Wrap or jump
+    pub issue: PhaseIssue,
+}
+
+/// Phase discontinuities between neighbouring records of a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseContinuityReport {
+    /// Number of neighbouring record pairs that were compared
+    pub pairs_checked: usize,
+    /// Discontinuities by record pair, channel and frequency
+    pub discontinuities: Vec<PhaseDiscontinuity>,
+}
+
+impl PhaseContinuityReport {
+    /// Whether no discontinuity was found
+    pub fn is_continuous(&self) -> bool {
+        self.discontinuities.is_empty()
+    }
+
+    /// Record pairs with at least one discontinuity, in the order they were found
+    pub fn offending_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for d in &self.discontinuities {
+            if !pairs.contains(&d.records) {
+                pairs.push(d.records);
+            }
+        }
+        pairs
+    }
+}
+
+/// Neighbours of every record compared by [`phase_continuity`] on non-equiangular grids
+const CONTINUITY_NEIGHBOURS: usize = 4;
+
+/// Check the phases of neighbouring records for wraps and jumps
+///
+/// Interpolating between directions assumes that phases change smoothly. Every pair of
+/// neighbouring records (adjacent points of an equiangular grid, otherwise the closest
+/// records of each record) is compared per channel and frequency. A difference of about a
+/// multiple of 2π is reported as a [`Wrap`](PhaseIssue::Wrap), a difference of more than
+/// `max_jump` radians after unwrapping as a [`Jump`](PhaseIssue::Jump).
+///
+/// Phase spectra are checked as stored. For magnitude-phase and DFT content the phases are
+/// taken from the complex values, so they are always wrapped to ±π and only jumps are
+/// reported; frequencies where either record has no energy are skipped.
+pub fn phase_continuity(dataset: &Dataset, max_jump: f32) -> Result<PhaseContinuityReport> {
+    let frequencies = match &dataset.header {
+        ContentHeader::PhaseSpectrum { frequencies } => frequencies.clone(),
+        header @ (ContentHeader::MagnitudePhaseSpectrum { .. }
+        | ContentHeader::DftSpectrum { .. }) => spectrum_frequencies(header)?,
+        _ => {
+            return Err(Error::new(format!(
+                "Phase continuity requires phase or complex spectra, not {}",
+                dataset.content_type()
+            )))
+        }
+    };
+    if !(max_jump > 0.0 && max_jump < std::f32::consts::PI) {
+        return Err(Error::new(format!(
+            "The jump threshold of {} rad has to be between 0 and π",
+            max_jump
+        )));
+    }
+    let stored = dataset.content_type() == ContentType::PhaseSpectrum;
+    // Phase per channel and frequency, `None` without energy
+    let phases = |record: &Record| -> Vec<Vec<Option<f32>>> {
+        record
+            .channels
+            .iter()
+            .map(|data| {
+                if stored {
+                    data.iter().copied().map(Some).collect()
+                } else {
+                    data.chunks(2)
+                        .map(|c| (c[0].hypot(c[1]) > MAGNITUDE_FLOOR).then(|| c[1].atan2(c[0])))
+                        .collect()
+                }
+            })
+            .collect()
+    };
+    let phases: Vec<_> = dataset.records.iter().map(phases).collect();
+
+    let pairs = neighbour_pairs(dataset);
+    let mut discontinuities = Vec::new();
+    for &(a, b) in &pairs {
+        for (channel, (first, second)) in phases[a].iter().zip(&phases[b]).enumerate() {
+            for (k, (x, y)) in first.iter().zip(second).enumerate() {
+                let (Some(x), Some(y)) = (x, y) else {
+                    continue;
+                };
+                let difference = y - x;
+                let turns = (difference / std::f32::consts::TAU).round();
+                let unwrapped = difference - turns * std::f32::consts::TAU;
+                let issue = if unwrapped.abs() > max_jump {
+                    PhaseIssue::Jump
+                } else if turns != 0.0 {
+                    PhaseIssue::Wrap
+                } else {
+                    continue;
+                };
+                discontinuities.push(PhaseDiscontinuity {
+                    records: (a, b),
+                    channel,
+                    frequency: frequencies.get(k).copied().unwrap_or(f32::NAN),
+                    difference,
+                    issue,
+                });
+            }
+        }
+    }
+    Ok(PhaseContinuityReport {
+        pairs_checked: pairs.len(),
+        discontinuities,
+    })
+}
+
+/// Systematic level offsets between the channels of a dataset, see [`channel_balance`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelBalance {
+    /// Level of every channel relative to the mean of all channels in dB, averaged over all
+    /// directions
+    pub offsets_db: Vec<f32>,
+    /// Standard deviation of every channel's relative level over the directions in dB
+    pub spread_db: Vec<f32>,
+    /// Confidence between 0 and 1 that the offset of every channel is systematic rather than
+    /// the chance result of direction-dependent level differences
+    pub confidence: Vec<f32>,
+    /// Number of directions compared
+    pub directions: usize,
+}
+
+impl ChannelBalance {
+    /// Whether all offsets are within `tolerance_db` or not significant at the `confidence`
+    /// level
+    pub fn is_balanced(&self, tolerance_db: f32, confidence: f32) -> bool {
+        self.offsets_db
+            .iter()
+            .zip(&self.confidence)
+            .all(|(offset, c)| offset.abs() <= tolerance_db || *c < confidence)
+    }
+
+    /// Remove the offsets from a dataset by applying the opposite gain to every channel
+    ///
+    /// The dataset has to have the content type and the channels of the analyzed one. The
+    /// mean level of all channels stays unchanged.
+    pub fn correct(&self, dataset: &mut Dataset) -> Result<()> {
+        if dataset.num_channels() != self.offsets_db.len() {
+            return Err(Error::new(format!(
+                "The balance of {} channels cannot correct a dataset with {} channels",
+                self.offsets_db.len(),
+                dataset.num_channels()
+            )));
+        }
+        if dataset.content_type() == ContentType::PhaseSpectrum {
+            return Err(Error::new("Phase spectra have no level to correct"));
+        }
+        let decibels = dataset.content_type() == ContentType::MagnitudeSpectrum
+            && dataset.magnitude_unit() == Some(MagnitudeUnit::Decibel);
+        for record in &mut dataset.records {
+            for (data, offset) in record.channels.iter_mut().zip(&self.offsets_db) {
+                if decibels {
+                    data.iter_mut().for_each(|x| *x -= offset);
+                } else {
+                    let gain = 10f32.powf(-offset / 20.0);
+                    data.iter_mut().for_each(|x| *x *= gain);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Detect systematic level offsets between the channels of a dataset
+///
+/// The channels of a dummy head or a spherical microphone array see the same sound field
+/// from different positions, so their levels differ per direction but agree on average over
+/// the sphere. A constant offset instead points at a calibration error of a microphone. The
+/// energy of every record and channel (of the impulse response or over all frequencies) is
+/// compared to the mean of all channels in dB and averaged over the directions with the
+/// [quadrature weights](crate::grid::quadrature_weights) of the grid, or equally on irregular
+/// grids. Records in which a channel has no energy are skipped.
+///
+/// The confidence of an offset follows from its standard error over the directions: offsets
+/// far above the spread of the directional level differences are reported with a confidence
+/// close to 1. Use [`ChannelBalance::correct`] to remove them:
+///
+/// ```no_run
+/// use opendaff::{analysis, Dataset};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// # let mut dataset: Dataset = unimplemented!();
+/// let balance = analysis::channel_balance(&dataset)?;
+/// if !balance.is_balanced(0.5, 0.99) {
+///     balance.correct(&mut dataset)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Phase spectra and datasets with fewer than two channels are not supported.
+pub fn channel_balance(dataset: &Dataset) -> Result<ChannelBalance> {
+    if dataset.content_type() == ContentType::PhaseSpectrum {
+        return Err(Error::new("Phase spectra have no level to balance"));
+    }
+    let num_channels = dataset.num_channels();
+    if num_channels < 2 {
+        return Err(Error::new(format!(
+            "Channel balance requires at least two channels, not {}",
+            num_channels
+        )));
+    }
+    let weights = crate::grid::quadrature_weights(dataset)
+        .unwrap_or_else(|_| vec![1.0; dataset.records.len()]);
+    let unit = dataset.magnitude_unit();
+
+    // Level of every channel relative to the mean of all channels, per direction
+    let mut relative = Vec::new();
+    for (record, &weight) in dataset.records.iter().zip(&weights) {
+        let levels: Vec<f64> = record
+            .channels
+            .iter()
+            .map(|data| {
+                let energy: f64 = match dataset.header {
+                    ContentHeader::ImpulseResponse { .. } => {
+                        data.iter().map(|&x| x as f64 * x as f64).sum()
+                    }
+                    _ => magnitudes(dataset, unit, data)
+                        .iter()
+                        .map(|&m| m as f64 * m as f64)
+                        .sum(),
+                };
+                10.0 * energy.log10()
+            })
+            .collect();
+        if weight > 0.0 && levels.iter().all(|level| level.is_finite()) {
+            let mean = levels.iter().sum::<f64>() / num_channels as f64;
+            relative.push((weight, levels.iter().map(|l| l - mean).collect::<Vec<_>>()));
+        }
+    }
+    if relative.is_empty() {
+        return Err(Error::new("No record has energy in all channels"));
+    }
+
+    let total: f64 = relative.iter().map(|(w, _)| w).sum();
+    // Effective number of independent directions of the weighted mean
+    let effective = total * total / relative.iter().map(|(w, _)| w * w).sum::<f64>();
+    let mut balance = ChannelBalance {
+        offsets_db: Vec::with_capacity(num_channels),
+        spread_db: Vec::with_capacity(num_channels),
+        confidence: Vec::with_capacity(num_channels),
+        directions: relative.len(),
+    };
+    for channel in 0..num_channels {
+        let offset = relative.iter().map(|(w, l)| w * l[channel]).sum::<f64>() / total;
+        let variance = relative
+            .iter()
+            .map(|(w, l)| w * (l[channel] - offset).powi(2))
+            .sum::<f64>()
+            / total;
+        let standard_error = (variance / effective).sqrt();
+        let confidence = if standard_error > 0.0 {
+            erf(offset.abs() / (standard_error * std::f64::consts::SQRT_2))
+        } else if offset.abs() > 1e-9 {
+            1.0
+        } else {
+            0.0
+        };
+        balance.offsets_db.push(offset as f32);
+        balance.spread_db.push(variance.sqrt() as f32);
+        balance.confidence.push(confidence as f32);
+    }
+    Ok(balance)
+}
+
+/// Integrated loudness of a stream of rendered blocks in LUFS, after ITU-R BS.1770-4
+///
+/// Every block holds the same number of channels, e.g. the left and right ear signals
+/// returned by [`audition::render`](crate::audition::render), which may be of any length.
+/// All channels are weighted equally, as for the front channels of a loudspeaker setup.
+/// Silence yields negative infinity.
+///
+/// ```no_run
+/// use opendaff::{analysis, audition, Dataset};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// # let (stimulus, datasets): (Vec<f32>, Vec<Dataset>) = unimplemented!();
+/// // Gains that play the stimulus at -23 LUFS through every HRTF set
+/// let gains_db = datasets
+///     .iter()
+///     .map(|dataset| {
+///         let output = audition::render(&stimulus, dataset, |_| (30.0, 0.0), &Default::default())?;
+///         Ok(-23.0 - analysis::lufs([output], 48000.0)?)
+///     })
+///     .collect::<opendaff::Result<Vec<f32>>>()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn lufs<I, B, C>(blocks: I, samplerate: f64) -> Result<f32>
+where
+    I: IntoIterator<Item = B>,
+    B: AsRef<[C]>,
+    C: AsRef<[f32]>,
+{
+    let mut meter: Option<LoudnessMeter> = None;
+    for block in blocks {
+        let channels = block.as_ref();
+        let meter = match &mut meter {
+            Some(meter) => meter,
+            None => meter.insert(LoudnessMeter::new(samplerate, channels.len())?),
+        };
+        meter.process(channels)?;
+    }
+    Ok(meter.map_or(f32::NEG_INFINITY, |meter| meter.integrated()))
+}
+
+/// Gated loudness measurement after ITU-R BS.1770-4, fed block by block
+///
+/// The channels are K-weighted and their mean square is taken over 400 ms gating blocks
+/// overlapping by 75 %. The integrated loudness averages the blocks above the absolute gate
+/// of -70 LUFS and the relative gate 10 LU below the loudness of those.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    /// Samples per 100 ms step between gating blocks
+    step: usize,
+    /// Samples of the current step seen so far
+    position: usize,
+    /// Summed K-weighted energy of all channels in the current step
+    energy: f64,
+    /// Mean summed energy of every completed step
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    /// Meter for signals with the given sampling rate (Hz) and number of channels
+    pub fn new(samplerate: f64, channels: usize) -> Result<Self> {
+        let step = (samplerate / 10.0).round();
+        if !(samplerate.is_finite() && step >= 1.0) {
+            return Err(Error::new(format!("Invalid sampling rate {}", samplerate)));
+        }
+        if channels == 0 {
+            return Err(Error::new("Loudness needs at least one channel"));
+        }
+        Ok(Self {
+            filters: vec![k_weighting(samplerate); channels],
+            step: step as usize,
+            position: 0,
+            energy: 0.0,
+            steps: Vec::new(),
+        })
+    }
+
+    /// Add the next block of samples, one slice per channel of equal length
+    pub fn process<C: AsRef<[f32]>>(&mut self, channels: &[C]) -> Result<()> {
+        if channels.len() != self.filters.len() {
+            return Err(Error::new(format!(
+                "Block has {} channels, the meter {}",
+                channels.len(),
+                self.filters.len()
+            )));
+        }
+        let length = channels.first().map_or(0, |c| c.as_ref().len());
+        if channels.iter().any(|c| c.as_ref().len() != length) {
+            return Err(Error::new("The channels of a block differ in length"));
+        }
+        for n in 0..length {
+            for (channel, filters) in channels.iter().zip(&mut self.filters) {
+                let x = channel.as_ref()[n] as f64;
+                let shelved = filters[0].process(x);
+                let y = filters[1].process(shelved);
+                self.energy += y * y;
+            }
+            self.position += 1;
+            if self.position == self.step {
+                self.steps.push(self.energy / self.step as f64);
+                self.position = 0;
+                self.energy = 0.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Integrated loudness in LUFS of everything processed so far
+    ///
+    /// Negative infinity until a gating block above the absolute gate is complete.
+    pub fn integrated(&self) -> f32 {
+        const ABSOLUTE_GATE: f64 = -70.0;
+        const RELATIVE_GATE: f64 = -10.0;
+        let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(4)
+            .map(|steps| steps.iter().sum::<f64>() / 4.0)
+            .filter(|&energy| loudness(energy) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+            let (sum, count) = blocks.fold((0.0, 0), |(sum, count), e| (sum + e, count + 1));
+            sum / count as f64
+        };
+        let gate = loudness(mean(&mut blocks.iter().copied())) + RELATIVE_GATE;
+        let gated = mean(&mut blocks.iter().copied().filter(|&e| loudness(e) > gate));
+        loudness(gated) as f32
+    }
+
+    /// Clear the measurement, e.g. before the next stimulus
+    pub fn reset(&mut self) {
+        for filters in &mut self.filters {
+            for filter in filters {
+                filter.state = [0.0; 2];
+            }
+        }
+        self.position = 0;
+        self.energy = 0.0;
+        self.steps.clear();
+    }
+}
+
+/// Second-order IIR section in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    /// Feedback coefficients `a1` and `a2`, `a0` normalized to 1
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Pre-filter (high shelf) and RLB high-pass of the K-weighting for a sampling rate
+///
+/// The analog prototypes are those of BS.1770, so the coefficients match the tabulated ones
+/// at 48 kHz.
+fn k_weighting(samplerate: f64) -> [Biquad; 2] {
+    let shelf = {
+        let k = (std::f64::consts::PI * 1681.974450955533 / samplerate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    };
+    let highpass = {
+        let k = (std::f64::consts::PI * 38.13547087602444 / samplerate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    };
+    [shelf, highpass]
+}
+
+/// Pairs of neighbouring records (lower index first)
+fn neighbour_pairs(dataset: &Dataset) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    match dataset.grid.equiangular() {
+        Some(grid) if grid.num_records() == dataset.records.len() => {
+            let positions: Vec<(usize, usize)> = grid.layout().collect();
+            let index: std::collections::HashMap<(usize, usize), usize> =
+                positions.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+            let full_circle = grid.alpha_span() == 360.0;
+            for (i, &(ring, a)) in positions.iter().enumerate() {
+                // Next point on the ring
+                if !grid.is_pole_ring(ring) && grid.alpha_points > 1 {
+                    let next = if a + 1 < grid.alpha_points {
+                        Some(a + 1)
+                    } else {
+                        full_circle.then_some(0)
+                    };
+                    if let Some(next) = next {
+                        pairs.push((i, index[&(ring, next)]));
+                    }
+                }
+                // Same alpha on the next ring; poles neighbour the whole ring
+                if ring + 1 < grid.beta_points {
+                    if grid.is_pole_ring(ring) {
+                        pairs.extend((0..grid.alpha_points).map(|a| (i, index[&(ring + 1, a)])));
+                    } else if grid.is_pole_ring(ring + 1) {
+                        pairs.push((i, index[&(ring + 1, 0)]));
+                    } else {
+                        pairs.push((i, index[&(ring + 1, a)]));
+                    }
+                }
+            }
+        }
+        _ => {
+            let vectors: Vec<[f64; 3]> = dataset
+                .records
+                .iter()
+                .map(|r| unit_vector(r.alpha, r.beta))
+                .collect();
+            for (i, a) in vectors.iter().enumerate() {
+                let mut closest: Vec<(usize, f64)> = vectors
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(j, b)| (j, -(a[0] * b[0] + a[1] * b[1] + a[2] * b[2])))
+                    .collect();
+                if closest.len() > CONTINUITY_NEIGHBOURS {
+                    closest
+                        .select_nth_unstable_by(CONTINUITY_NEIGHBOURS, |x, y| x.1.total_cmp(&y.1));
+                    closest.truncate(CONTINUITY_NEIGHBOURS);
+                }
+                pairs.extend(closest.into_iter().map(|(j, _)| (i.min(j), i.max(j))));
+            }
+        }
+    }
+    for pair in &mut pairs {
+        *pair = (pair.0.min(pair.1), pair.0.max(pair.1));
+    }
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+/// Estimates of the target records from the closest source records (nearest neighbour or
+/// inverse distance weighting)
+///
+/// With `leave_one_out`, sources and targets are the same records and every record is
+/// estimated from the others.
+fn weighted_estimates(
+    sources: &[Record],
+    targets: &[Record],
+    leave_one_out: bool,
+    method: Interp,
+) -> Result<Vec<Vec<Vec<f64>>>> {
+    let vectors: Vec<[f64; 3]> = sources
+        .iter()
+        .map(|r| unit_vector(r.alpha, r.beta))
+        .collect();
+    let targets = targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            let skip = leave_one_out.then_some(i);
+            let closest = closest_weights(
+                &vectors,
+                unit_vector(target.alpha, target.beta),
+                skip,
+                method,
+            )?;
+            Ok(target
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(channel, data)| {
+                    let mut estimate = vec![0.0f64; data.len()];
+                    for &(j, w) in &closest {
+                        for (e, &x) in estimate.iter_mut().zip(&sources[j].channels[channel]) {
+                            *e += w * x as f64;
+                        }
+                    }
+                    estimate
+                })
+                .collect())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(targets)
+}
+
+/// Closest records to a direction and their normalized weights (nearest neighbour or inverse
+/// distance weighting), optionally skipping one record
+fn closest_weights(
+    vectors: &[[f64; 3]],
+    target: [f64; 3],
+    skip: Option<usize>,
+    method: Interp,
+) -> Result<Vec<(usize, f64)>> {
+    let (neighbours, inverse_distance) = match method {
+        Interp::NearestNeighbour => (1, false),
+        Interp::InverseDistance { neighbours: 0 } => {
+            return Err(Error::new("At least one neighbour is required"))
+        }
+        Interp::InverseDistance { neighbours } => (neighbours, true),
+        Interp::SphericalHarmonics { .. } => unreachable!("not a weighted method"),
+    };
+    let mut closest: Vec<(usize, f64)> = vectors
+        .iter()
+        .enumerate()
+        .filter(|&(j, _)| Some(j) != skip)
+        .map(|(j, b)| {
+            let cos = target[0] * b[0] + target[1] * b[1] + target[2] * b[2];
+            (j, cos.clamp(-1.0, 1.0).acos())
+        })
+        .collect();
+    if closest.len() > neighbours {
+        closest.select_nth_unstable_by(neighbours, |x, y| x.1.total_cmp(&y.1));
+        closest.truncate(neighbours);
+    }
+
+    // Coinciding records reproduce the record exactly
+    for (_, weight) in &mut closest {
+        *weight = if inverse_distance {
+            1.0 / weight.max(1e-9).powi(2)
+        } else {
+            1.0
+        };
+    }
+    let total: f64 = closest.iter().map(|&(_, w)| w).sum();
+    for (_, weight) in &mut closest {
+        *weight /= total;
+    }
+    Ok(closest)
+}
+
+/// Unit vector of a data view direction
+fn unit_vector(alpha: f32, beta: f32) -> [f64; 3] {
+    let (alpha, beta) = ((alpha as f64).to_radians(), (beta as f64).to_radians());
+    [
+        beta.sin() * alpha.cos(),
+        beta.sin() * alpha.sin(),
+        -beta.cos(),
+    ]
+}
+
+/// Leave-one-out spherical harmonic estimates for every record
+fn leave_one_out_sh(dataset: &Dataset, order: usize) -> Result<Vec<Vec<Vec<f64>>>> {
+    let directions: Vec<(f32, f32)> = dataset.records.iter().map(|r| (r.alpha, r.beta)).collect();
+    let fit = sh::Fit::new(order, &directions, SH_REGULARIZATION)?;
+    let leverages = fit.leverages();
+
+    let mut estimates: Vec<Vec<Vec<f64>>> = dataset
+        .records
+        .iter()
+        .map(|r| r.channels.iter().map(|c| vec![0.0; c.len()]).collect())
+        .collect();
+    let mut values = vec![0.0f64; dataset.records.len()];
+    for channel in 0..dataset.num_channels() {
+        for element in 0..dataset.elements_per_record() {
+            for (value, record) in values.iter_mut().zip(&dataset.records) {
+                *value = record.channels[channel][element] as f64;
+            }
+            let fitted = fit.fitted(&fit.coefficients(&values));
+            for (i, estimate) in estimates.iter_mut().enumerate() {
+                let residual = (values[i] - fitted[i]) / (1.0 - leverages[i]).max(1e-12);
+                estimate[channel][element] = values[i] - residual;
+            }
+        }
+    }
+    Ok(estimates)
+}
+
+/// Support frequencies of a spectral dataset (DFT bins for DFT content)
+fn spectrum_frequencies(header: &ContentHeader) -> Result<Vec<f32>> {
+    match header {
+        ContentHeader::MagnitudeSpectrum { frequencies }
+        | ContentHeader::MagnitudePhaseSpectrum { frequencies } => Ok(frequencies.clone()),
+        ContentHeader::DftSpectrum {
+            samplerate,
+            transform_size,
+        } => Ok((0..=transform_size / 2)
+            .map(|k| (k as f64 * samplerate / *transform_size as f64) as f32)
+            .collect()),
+        _ => Err(Error::new(
+            "Magnitude spectra, magnitude-phase spectra or DFT content required",
+        )),
+    }
+}
+
+/// The dataset with magnitude spectra in dB converted to linear magnitudes, borrowed if
+/// there is nothing to convert
+fn linear_magnitudes(dataset: &Dataset) -> Cow<'_, Dataset> {
+    if dataset.content_type() != ContentType::MagnitudeSpectrum
+        || dataset.magnitude_unit() != Some(MagnitudeUnit::Decibel)
+    {
+        return Cow::Borrowed(dataset);
+    }
+    let mut linear = dataset.clone();
+    for data in linear.records.iter_mut().flat_map(|r| &mut r.channels) {
+        decibel::from_db_slice(data);
+    }
+    linear.set_magnitude_unit(MagnitudeUnit::Linear);
+    Cow::Owned(linear)
+}
+
+/// Linear magnitudes of a channel's data, magnitude spectra stored in `unit`
+fn magnitudes(dataset: &Dataset, unit: Option<MagnitudeUnit>, data: &[f32]) -> Vec<f32> {
+    match dataset.header {
+        ContentHeader::MagnitudeSpectrum { .. } => {
+            let mut magnitudes = data.to_vec();
+            if let Some(unit) = unit {
+                unit.to_linear(&mut magnitudes);
+            }
+            magnitudes
+        }
+        _ => {
+            let mut magnitudes = vec![0.0; data.len() / 2];
+            decibel::complex_magnitudes(data, &mut magnitudes);
+            magnitudes
+        }
+    }
+}
+
+/// Error function, after Abramowitz and Stegun 7.1.26 (absolute error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = [1.061_405_429, -1.453_152_027, 1.421_413_741, -0.284_496_736]
+        .iter()
+        .fold(0.0, |p, c| (p + c) * t);
+    let y = 1.0 - (polynomial + 0.254_829_592) * t * (-x * x).exp();
+    y.copysign(x)
+}
+
+/// Leading eigenvectors and eigenvalues of a symmetric positive semi-definite matrix
+/// (orthogonal iteration), sorted by decreasing eigenvalue
+fn leading_eigenvectors(matrix: &[Vec<f64>], count: usize) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let n = matrix.len();
+    // Deterministic, non-degenerate start vectors
+    let mut basis: Vec<Vec<f64>> = (0..count)
+        .map(|k| {
+            (0..n)
+                .map(|i| {
+                    if i == k {
+                        1.0
+                    } else {
+                        1e-3 * (((i + 1) * (k + 2)) % 7) as f64
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    orthonormalize(&mut basis);
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut next: Vec<Vec<f64>> = basis.iter().map(|v| multiply(matrix, v)).collect();
+        orthonormalize(&mut next);
+        let converged = next
+            .iter()
+            .zip(&basis)
+            .all(|(a, b)| 1.0 - dot(a, b).abs() < 1e-12);
+        basis = next;
+        if converged {
+            break;
+        }
+    }
+
+    let mut pairs: Vec<(Vec<f64>, f64)> = basis
+        .into_iter()
+        .map(|mut v| {
+            let value = dot(&v, &multiply(matrix, &v));
+            // Fix the sign for reproducible results: largest element positive
+            let largest = v
+                .iter()
+                .copied()
+                .fold(0.0f64, |a, x| if x.abs() > a.abs() { x } else { a });
+            if largest < 0.0 {
+                v.iter_mut().for_each(|x| *x = -*x);
+            }
+            (v, value)
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.1.total_cmp(&a.1));
+    pairs.into_iter().unzip()
+}
+
+fn multiply(matrix: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    matrix.iter().map(|row| dot(row, v)).collect()
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Modified Gram-Schmidt; vectors that become (numerically) zero are replaced by unit vectors
+fn orthonormalize(vectors: &mut [Vec<f64>]) {
+    for i in 0..vectors.len() {
+        let (done, rest) = vectors.split_at_mut(i);
+        let v = &mut rest[0];
+        for u in done.iter() {
+            let projection = dot(v, u);
+            v.iter_mut().zip(u).for_each(|(x, y)| *x -= projection * y);
+        }
+        let norm = dot(v, v).sqrt();
+        if norm > 1e-300 {
+            v.iter_mut().for_each(|x| *x /= norm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ring;
+    use crate::EquiangularGrid;
+
+    fn subject(gain: f32) -> Dataset {
+        let grid = ring(8);
+        // Two spectral shapes: a direction-dependent tilt and a subject-dependent notch
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0],
+            },
+            grid,
+            2,
+            |alpha, _, _| {
+                let tilt = alpha.to_radians().cos();
+                [0.0, 0.2, 0.4, 0.6, 0.8, 1.0]
+                    .iter()
+                    .zip([0.0, 0.0, 0.0, 0.0, 1.0, 0.0])
+                    .map(|(slope, notch)| 10f32.powf((6.0 * tilt * slope - gain * notch) / 20.0))
+                    .collect()
+            },
+        )
+    }
+
+    #[test]
+    fn test_pca_captures_low_rank_structure() {
+        let datasets = [subject(0.0), subject(6.0), subject(12.0)];
+        let basis = pca_basis(&datasets, 3).unwrap();
+
+        let ratio = basis.explained_variance_ratio();
+        assert!(ratio[0] + ratio[1] > 0.999);
+        assert!(ratio[2] < 1e-3);
+        for (i, a) in basis.components.iter().enumerate() {
+            for (j, b) in basis.components.iter().enumerate() {
+                let d: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                assert!((d - if i == j { 1.0 } else { 0.0 }).abs() < 1e-4);
+            }
+        }
+
+        // Two components reconstruct any record of the training data
+        let record = &datasets[1].records[3].channels[0];
+        let weights = basis.project(record).unwrap();
+        let reconstructed = basis.reconstruct(&weights[..2]);
+        for (a, b) in reconstructed.iter().zip(record) {
+            assert!((a / b - 1.0).abs() < 1e-3);
+        }
+
+        let projected = basis.project_dataset(&datasets[2]).unwrap();
+        assert_eq!(projected.len(), 8);
+        assert_eq!(projected[0].channels[1].len(), 3);
+    }
+
+    #[test]
+    fn test_pca_rejects_impulse_responses() {
+        let mut dataset = subject(0.0);
+        dataset.header = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        assert!(pca_basis(&[dataset], 2).is_err());
+        assert!(pca_basis(&[], 2).is_err());
+    }
+
+    fn balloon(resolution: f32) -> Dataset {
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0, 2000.0],
+            },
+            EquiangularGrid::with_resolution(resolution, resolution).unwrap(),
+            1,
+            |alpha, beta, _| {
+                let front = alpha.to_radians().cos() * beta.to_radians().sin();
+                vec![1.0 + 0.5 * front, 1.0 + 0.5 * front * front]
+            },
+        )
+    }
+
+    #[test]
+    fn test_interpolation_error() {
+        let coarse = balloon(30.0);
+        let nearest = interpolation_error(&coarse, Interp::NearestNeighbour).unwrap();
+        assert_eq!(nearest.directions.len(), coarse.num_records());
+        assert_eq!(
+            (nearest.directions[5].alpha, nearest.directions[5].beta),
+            (coarse.records[5].alpha, coarse.records[5].beta)
+        );
+
+        // Finer grids and better methods reconstruct the smooth balloon more closely
+        let fine = interpolation_error(&balloon(10.0), Interp::NearestNeighbour).unwrap();
+        assert!(fine.mean_db() < nearest.mean_db() - 6.0);
+        let idw = interpolation_error(&coarse, Interp::InverseDistance { neighbours: 4 }).unwrap();
+        assert!(idw.mean_db() < nearest.mean_db());
+        // The balloon is band-limited to order 2
+        let sh = interpolation_error(&coarse, Interp::SphericalHarmonics { order: 3 }).unwrap();
+        assert!(sh.max_db() < -40.0);
+        assert!(nearest.max_db() >= nearest.mean_db());
+        let worst = nearest.worst().unwrap();
+        assert_eq!(
+            worst.channels.iter().copied().fold(f32::MIN, f32::max),
+            nearest.max_db()
+        );
+
+        assert!(interpolation_error(&coarse, Interp::InverseDistance { neighbours: 0 }).is_err());
+        let mut phases = coarse.clone();
+        phases.header = ContentHeader::PhaseSpectrum {
+            frequencies: vec![1000.0, 2000.0],
+        };
+        assert!(interpolation_error(&phases, Interp::NearestNeighbour).is_err());
+    }
+
+    #[test]
+    fn test_reconstruction_error() {
+        let dense = balloon(10.0);
+        let coarse = balloon(30.0);
+        let method = Interp::InverseDistance { neighbours: 4 };
+        let report = reconstruction_error(&dense, &coarse, method).unwrap();
+        assert_eq!(report.directions.len(), dense.num_records());
+        // Records shared by both grids are reproduced
+        assert!(report.directions[0].channels[0] < -100.0);
+        assert!(report.mean_db() < -10.0);
+
+        let sh = reconstruction_error(&dense, &coarse, Interp::SphericalHarmonics { order: 2 });
+        assert!(sh.unwrap().max_db() < -40.0);
+
+        let mut other = coarse.clone();
+        other.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![500.0, 2000.0],
+        };
+        assert!(reconstruction_error(&dense, &other, method).is_err());
+    }
+
+    #[test]
+    fn test_magnitudes_in_decibels() {
+        let linear = balloon(30.0);
+        let mut levels = linear.clone();
+        for data in levels.records.iter_mut().flat_map(|r| &mut r.channels) {
+            decibel::to_db_slice(data);
+        }
+        // Detected from the negative levels
+        assert_eq!(levels.magnitude_unit(), Some(MagnitudeUnit::Decibel));
+
+        let method = Interp::InverseDistance { neighbours: 3 };
+        let expected = interpolation_error(&linear, method).unwrap();
+        let report = interpolation_error(&levels, method).unwrap();
+        assert!((report.mean_db() - expected.mean_db()).abs() < 1e-3);
+        let reference = balloon(15.0);
+        let expected = reconstruction_error(&reference, &linear, method).unwrap();
+        let report = reconstruction_error(&reference, &levels, method).unwrap();
+        assert!((report.max_db() - expected.max_db()).abs() < 1e-3);
+
+        let expected = interpolate(&linear, 45.0, 60.0, method, SpectrumDomain::Complex).unwrap();
+        let channels = interpolate(&levels, 45.0, 60.0, method, SpectrumDomain::Complex).unwrap();
+        for (level, magnitude) in channels[0].iter().zip(&expected[0]) {
+            assert!((level - 20.0 * magnitude.log10()).abs() < 1e-3);
+        }
+
+        // Datasets in both units share a basis
+        let mut subject_levels = subject(6.0);
+        for data in subject_levels
+            .records
+            .iter_mut()
+            .flat_map(|r| &mut r.channels)
+        {
+            decibel::to_db_slice(data);
+        }
+        subject_levels.set_magnitude_unit(MagnitudeUnit::Decibel);
+        let expected = pca_basis(&[subject(0.0), subject(6.0)], 2).unwrap();
+        let basis = pca_basis(&[subject(0.0), subject_levels], 2).unwrap();
+        for (a, b) in basis.mean.iter().zip(&expected.mean) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_sh_interpolation_error_matches_refit() {
+        // Noisy data, so that leaving a record out actually changes the fit
+        let mut dataset = balloon(45.0);
+        for (i, record) in dataset.records.iter_mut().enumerate() {
+            record.channels[0].truncate(1);
+            record.channels[0][0] += 0.1 * (i as f32 * 1.7).sin();
+        }
+        let order = 2;
+        let report = interpolation_error(&dataset, Interp::SphericalHarmonics { order }).unwrap();
+
+        for left_out in [0, 7, 20] {
+            let mut others = dataset.clone();
+            others.records.remove(left_out);
+            others.grid = crate::Grid::Irregular;
+            let expansion = crate::ShCoefficients::fit(&others, order).unwrap();
+            let record = &dataset.records[left_out];
+            let basis = sh::real_basis(order, record.alpha, record.beta);
+            let x = record.channels[0][0] as f64;
+            let error = (sh::evaluate(&basis, expansion.coefficients(0, 0)) - x).powi(2) / (x * x);
+
+            // Up to the slightly different regularization of the refit
+            let reported = 10f64.powf(report.directions[left_out].channels[0] as f64 / 10.0);
+            assert!((error / reported - 1.0).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_phase_continuity() {
+        let grid = EquiangularGrid::with_resolution(30.0, 30.0).unwrap();
+        // Phase growing with the distance from the front, i.e. a smooth delay
+        let mut phases = Dataset::from_fn(
+            ContentHeader::PhaseSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            grid,
+            1,
+            |alpha, beta, _| {
+                let delay = 0.5 * (1.0 - alpha.to_radians().cos() * beta.to_radians().sin());
+                vec![-delay, -2.0 * delay]
+            },
+        );
+        let report = phase_continuity(&phases, 1.0).unwrap();
+        assert!(report.is_continuous());
+        // 12 pairs per ring, 11 between neighbouring rings, 12 to each pole
+        assert_eq!(report.pairs_checked, 5 * 12 + 4 * 12 + 2 * 12);
+
+        // A differently wrapped record and a jump
+        phases.records[20].channels[0][1] += std::f32::consts::TAU;
+        phases.records[40].channels[0][0] += 2.0;
+        let report = phase_continuity(&phases, 1.0).unwrap();
+        assert!(!report.is_continuous());
+        let neighbours = |record| {
+            report
+                .discontinuities
+                .iter()
+                .filter(|d| d.records.0 == record || d.records.1 == record)
+                .collect::<Vec<_>>()
+        };
+        let wraps = neighbours(20);
+        assert_eq!(wraps.len(), 4);
+        assert!(wraps
+            .iter()
+            .all(|d| d.issue == PhaseIssue::Wrap && d.frequency == 1000.0));
+        let jumps = neighbours(40);
+        assert_eq!(jumps.len(), 4);
+        assert!(jumps
+            .iter()
+            .all(|d| d.issue == PhaseIssue::Jump && d.frequency == 500.0));
+        assert_eq!(report.offending_pairs().len(), 8);
+
+        // Complex spectra wrap by nature; only the jump remains
+        let mut complex = Dataset::from_fn(
+            ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            grid,
+            1,
+            |_, _, _| Vec::new(),
+        );
+        for (record, source) in complex.records.iter_mut().zip(&phases.records) {
+            record.channels[0] = source.channels[0]
+                .iter()
+                .flat_map(|p| [p.cos(), p.sin()])
+                .collect();
+        }
+        complex.grid = crate::Grid::Irregular;
+        let report = phase_continuity(&complex, 1.0).unwrap();
+        assert!(report
+            .discontinuities
+            .iter()
+            .all(|d| d.issue == PhaseIssue::Jump && (d.records.0 == 40 || d.records.1 == 40)));
+        assert!(!report.is_continuous());
+
+        assert!(phase_continuity(&subject(0.0), 1.0).is_err());
+        assert!(phase_continuity(&phases, 4.0).is_err());
+    }
+
+    /// Unit magnitudes with a delay that grows towards the side
+    fn delayed(content: ContentHeader) -> Dataset {
+        let frequencies: Vec<f32> = (1..=16).map(|k| 1000.0 * k as f32).collect();
+        let complex = content.content_type() != ContentType::PhaseSpectrum;
+        Dataset::from_fn(
+            content,
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| {
+                let delay = 0.3e-3 * alpha.to_radians().sin().max(0.0);
+                frequencies
+                    .iter()
+                    .flat_map(|f| {
+                        let phase = -std::f32::consts::TAU * f * delay;
+                        let phase =
+                            phase - std::f32::consts::TAU * (phase / std::f32::consts::TAU).round();
+                        if complex {
+                            vec![phase.cos(), phase.sin()]
+                        } else {
+                            vec![phase]
+                        }
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let frequencies: Vec<f32> = (1..=16).map(|k| 1000.0 * k as f32).collect();
+        let dataset = delayed(ContentHeader::MagnitudePhaseSpectrum {
+            frequencies: frequencies.clone(),
+        });
+        let method = Interp::InverseDistance { neighbours: 2 };
+        let at = |domain| interpolate(&dataset, 40.0, 90.0, method, domain).unwrap()[0].clone();
+        let bin = |data: &[f32], k: usize| {
+            (
+                data[2 * k].hypot(data[2 * k + 1]),
+                data[2 * k + 1].atan2(data[2 * k]),
+            )
+        };
+
+        // Real and imaginary parts of records out of phase cancel
+        let complex = at(SpectrumDomain::Complex);
+        assert!(bin(&complex, 15).0 < 0.9);
+        // Magnitudes alone keep the level, but take the phase of the closest record
+        let magnitude = at(SpectrumDomain::Magnitude);
+        assert!((bin(&magnitude, 15).0 - 1.0).abs() < 1e-4);
+        // Records at alpha 30° and 60° on the horizontal ring
+        let closest = &dataset.records[26].channels[0];
+        assert_eq!(
+            (dataset.records[26].alpha, dataset.records[26].beta),
+            (30.0, 90.0)
+        );
+        assert!((bin(&magnitude, 15).1 - bin(closest, 15).1).abs() < 1e-4);
+        // Unwrapped phases keep the level and move the delay between the neighbours
+        let unwrapped = at(SpectrumDomain::UnwrappedPhase);
+        let delay = |data: &[f32]| {
+            let phases: Vec<f64> = (0..16).map(|k| bin(data, k).1 as f64).collect();
+            -unwrap_phases(phases)[15] / (TAU * 16000.0)
+        };
+        assert!((bin(&unwrapped, 15).0 - 1.0).abs() < 1e-4);
+        let (near, far) = (delay(closest), delay(&dataset.records[27].channels[0]));
+        assert!(delay(&unwrapped) > near && delay(&unwrapped) < far);
+
+        // Phase spectra are unwrapped along frequency and wrapped again
+        let phases = delayed(ContentHeader::PhaseSpectrum { frequencies });
+        let result =
+            interpolate(&phases, 40.0, 90.0, method, SpectrumDomain::UnwrappedPhase).unwrap();
+        assert!(result[0]
+            .iter()
+            .all(|p| p.abs() <= std::f32::consts::PI + 1e-6));
+        for (k, p) in result[0].iter().enumerate() {
+            let (m, q) = bin(&unwrapped, k);
+            assert!((m - 1.0).abs() < 1e-4);
+            assert!((p - q).abs() < 1e-3, "{} {} {}", k, p, q);
+        }
+
+        // Magnitude spectra, directly on a record and with spherical harmonics
+        let balloon = balloon(30.0);
+        let record = &balloon.records[7];
+        let exact = interpolate(
+            &balloon,
+            record.alpha,
+            record.beta,
+            Interp::NearestNeighbour,
+            SpectrumDomain::Magnitude,
+        )
+        .unwrap();
+        assert_eq!(exact, record.channels);
+        let smooth = interpolate(
+            &balloon,
+            record.alpha,
+            record.beta,
+            Interp::SphericalHarmonics { order: 2 },
+            SpectrumDomain::default(),
+        )
+        .unwrap();
+        assert!((smooth[0][0] - record.channels[0][0]).abs() < 0.05);
+    }
+
+    fn binaural(header: ContentHeader, calibration_db: f32) -> Dataset {
+        let gain = 10f32.powf(calibration_db / 20.0);
+        Dataset::from_fn(
+            header,
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            move |alpha, beta, channel| {
+                // Interaural level differences that cancel out over the sphere
+                let lateral = 0.6 * alpha.to_radians().sin() * beta.to_radians().sin();
+                let level = if channel == 0 {
+                    1.0 + lateral
+                } else {
+                    (1.0 - lateral) * gain
+                };
+                vec![level, 0.5 * level, 0.25 * level]
+            },
+        )
+    }
+
+    #[test]
+    fn test_channel_balance() {
+        let ir = ContentHeader::ImpulseResponse {
+            samplerate: 48000.0,
+        };
+        let dataset = binaural(ir.clone(), 0.0);
+        let balanced = channel_balance(&dataset).unwrap();
+        assert_eq!(balanced.directions, dataset.num_records());
+        assert!(balanced.offsets_db.iter().all(|o| o.abs() < 1e-3));
+        assert!(balanced.spread_db[0] > 1.0);
+        assert!(balanced.is_balanced(0.1, 0.99));
+
+        let mut dataset = binaural(ir, 4.0);
+        let balance = channel_balance(&dataset).unwrap();
+        assert!((balance.offsets_db[0] + 2.0).abs() < 1e-3);
+        assert!((balance.offsets_db[1] - 2.0).abs() < 1e-3);
+        assert!(balance.confidence.iter().all(|&c| c > 0.99));
+        assert!(!balance.is_balanced(0.5, 0.99));
+        assert!(balance.is_balanced(2.5, 0.99));
+        balance.correct(&mut dataset).unwrap();
+        let corrected = channel_balance(&dataset).unwrap();
+        assert!(corrected.offsets_db.iter().all(|o| o.abs() < 1e-3));
+
+        // Levels in dB are shifted rather than scaled
+        let mut spectra = binaural(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![500.0, 1000.0, 2000.0],
+            },
+            -3.0,
+        );
+        for data in spectra.records.iter_mut().flat_map(|r| &mut r.channels) {
+            decibel::to_db_slice(data);
+        }
+        spectra.set_magnitude_unit(MagnitudeUnit::Decibel);
+        let balance = channel_balance(&spectra).unwrap();
+        assert!((balance.offsets_db[1] + 1.5).abs() < 1e-3);
+        balance.correct(&mut spectra).unwrap();
+        assert_eq!(spectra.magnitude_unit(), Some(MagnitudeUnit::Decibel));
+        let corrected = channel_balance(&spectra).unwrap();
+        assert!(corrected.offsets_db.iter().all(|o| o.abs() < 1e-3));
+
+        let mut single = balloon(30.0);
+        assert!(channel_balance(&single).is_err());
+        assert!(balance.correct(&mut single).is_err());
+        let phases = Dataset::from_fn(
+            ContentHeader::PhaseSpectrum {
+                frequencies: vec![1000.0],
+            },
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            2,
+            |_, _, _| vec![0.0],
+        );
+        assert!(channel_balance(&phases).is_err());
+    }
+
+    #[test]
+    fn test_lufs() {
+        let samplerate = 48000.0;
+        let sine = |frequency: f64, amplitude: f64, seconds: f64| -> Vec<f32> {
+            (0..(seconds * samplerate) as usize)
+                .map(|n| (amplitude * (TAU * frequency * n as f64 / samplerate).sin()) as f32)
+                .collect()
+        };
+        // A full-scale 997 Hz sine in one channel reads -3.01 LUFS (BS.1770-4, table 1)
+        let tone = sine(997.0, 1.0, 5.0);
+        let silence = vec![0.0; tone.len()];
+        let loudness = lufs([[tone.clone(), silence.clone()]], samplerate).unwrap();
+        assert!((loudness + 3.01).abs() < 0.05, "{}", loudness);
+        // Both channels add up, and streaming in odd blocks does not change the result
+        let stereo: Vec<[&[f32]; 2]> = tone.chunks(1234).map(|c| [c, c]).collect();
+        let loudness = lufs(stereo, samplerate).unwrap();
+        assert!((loudness - 0.0).abs() < 0.05, "{}", loudness);
+        // -20 dB is 20 LU quieter
+        let quiet = sine(997.0, 0.1, 5.0);
+        let loudness = lufs([[quiet, silence.clone()]], samplerate).unwrap();
+        assert!((loudness + 23.01).abs() < 0.05, "{}", loudness);
+
+        // The relative gate ignores long passages of near silence, apart from the gating
+        // blocks overlapping the tone (-10 LUFS without it)
+        let mut gated = tone.clone();
+        gated.extend(sine(997.0, 0.001, 20.0));
+        let loudness = lufs([[gated]], samplerate).unwrap();
+        assert!((loudness + 3.01).abs() < 0.2, "{}", loudness);
+
+        assert_eq!(lufs([[silence]], samplerate).unwrap(), f32::NEG_INFINITY);
+        assert_eq!(
+            lufs(Vec::<[Vec<f32>; 1]>::new(), samplerate).unwrap(),
+            f32::NEG_INFINITY
+        );
+        assert!(lufs([[vec![0.0f32; 10]]], 0.0).is_err());
+        let mut meter = LoudnessMeter::new(44100.0, 2).unwrap();
+        assert!(meter.process(&[&tone[..10]]).is_err());
+        assert!(meter.process(&[&tone[..10], &tone[..5]]).is_err());
+        meter.process(&[&tone, &tone]).unwrap();
+        assert!(meter.integrated() > -1.0);
+        meter.reset();
+        assert_eq!(meter.integrated(), f32::NEG_INFINITY);
+    }
+}
@@ -0,0 +1,347 @@
+//! Offline binaural previews for judging datasets by ear
+//!
+//! [`render_to_wav`] convolves a mono signal with the impulse responses of a dataset while a
+//! source moves along a trajectory, and writes the result as a stereo WAV file. It follows
+//! what a head-tracked renderer does: the direction is evaluated once per block, quantized to
+//! the records with a [`DirectionQuantizer`], and filters are crossfaded over one block when
+//! the selected record changes. No realtime audio stack is involved.
+
+use std::path::Path;
+
+use crate::render::{self, ChannelMap, DirectionQuantizer};
+use crate::trajectory::Motion;
+use crate::wav::{self, Wav};
+use crate::{ContentHeader, Dataset, Error, Result};
+
+/// Settings of the offline renderer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditionOptions {
+    /// Number of samples rendered with one filter (and length of the crossfades)
+    pub block_size: usize,
+    /// Hysteresis of the direction quantizer in degrees
+    pub hysteresis: f32,
+    /// Ear channels of the dataset, inferred from its channel labels if `None`
+    pub channels: Option<ChannelMap>,
+}
+
+impl Default for AuditionOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 512,
+            hysteresis: 1.0,
+            channels: None,
+        }
+    }
+}
+
+/// Render a mono WAV file along a trajectory and write a binaural stereo WAV file
+///
+/// `trajectory` is a keyframed [`Trajectory`](crate::trajectory::Trajectory) or a closure
+/// mapping the time in seconds to the object view direction (azimuth, elevation in degrees)
+/// of the source. Distances are not rendered. Multi-channel input is mixed down to mono. The sampling rate
+/// of the input must match the dataset; the output is written as 32-bit float.
+///
+/// ```no_run
+/// use opendaff::trajectory::Trajectory;
+/// use opendaff::{audition, Dataset, Reader};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let reader = Reader::open("hrir.daff")?;
+/// let dataset = Dataset::from_reader(&reader)?;
+/// // Half a turn per second around the head
+/// audition::render_to_wav("speech.wav", &dataset, |t| ((180.0 * t) as f32, 0.0), "preview.wav")?;
+/// // A scripted path
+/// let trajectory = Trajectory::load("flyover.json")?;
+/// audition::render_to_wav("speech.wav", &dataset, &trajectory, "flyover.wav")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn render_to_wav<T>(
+    input_wav: impl AsRef<Path>,
+    dataset: &Dataset,
+    trajectory: T,
+    output_wav: impl AsRef<Path>,
+) -> Result<()>
+where
+    T: Motion,
+{
+    render_to_wav_with(
+        input_wav,
+        dataset,
+        trajectory,
+        output_wav,
+        &AuditionOptions::default(),
+    )
+}
+
+/// Like [`render_to_wav`] with explicit settings
+pub fn render_to_wav_with<T>(
+    input_wav: impl AsRef<Path>,
+    dataset: &Dataset,
+    trajectory: T,
+    output_wav: impl AsRef<Path>,
+    options: &AuditionOptions,
+) -> Result<()>
+where
+    T: Motion,
+{
+    let input = wav::read(input_wav.as_ref())?;
+    let samplerate = samplerate(dataset)?;
+    if input.samplerate as f64 != samplerate {
+        return Err(Error::new(format!(
+            "The input is sampled at {} Hz, the dataset at {} Hz",
+            input.samplerate, samplerate
+        )));
+    }
+    let [left, right] = render(&input.mono(), dataset, trajectory, options)?;
+    wav::write(
+        output_wav.as_ref(),
+        &Wav {
+            samplerate: input.samplerate,
+            channels: vec![left, right],
+        },
+    )
+}
+
+/// Render a mono signal along a trajectory, returning the left and right ear signals
+///
+/// The signal is assumed to be sampled at the rate of the dataset. The output is longer than
+/// the input by the filter length minus one sample. Subnormal filter taps, input samples and
+/// partial sums are flushed to zero while rendering (see [`render::flush_denormals`]).
+pub fn render<T>(
+    signal: &[f32],
+    dataset: &Dataset,
+    trajectory: T,
+    options: &AuditionOptions,
+) -> Result<[Vec<f32>; 2]>
+where
+    T: Motion,
+{
+    let samplerate = samplerate(dataset)?;
+    if options.block_size == 0 {
+        return Err(Error::new("The block size must be positive"));
+    }
+    let channels = match &options.channels {
+        Some(channels) => channels.clone(),
+        None => ChannelMap::from_dataset(dataset),
+    };
+    channels.ears()?;
+    let mut quantizer = DirectionQuantizer::from_dataset(dataset, options.hysteresis)?;
+
+    let filter_length = dataset.elements_per_record();
+    let length = (signal.len() + filter_length).saturating_sub(1);
+    let mut output = [vec![0.0f32; length], vec![0.0f32; length]];
+    let mut current: Option<usize> = None;
+    let mut faded = Vec::with_capacity(options.block_size);
+    // Ear filters of the current and the previous record, without subnormal taps
+    let mut filters: [Vec<f32>; 2] = Default::default();
+    let mut old_filters: [Vec<f32>; 2] = Default::default();
+
+    for (index, block) in signal.chunks(options.block_size).enumerate() {
+        let start = index * options.block_size;
+        let center = (start as f64 + block.len() as f64 / 2.0) / samplerate;
+        let (azimuth, elevation) = trajectory.direction(center);
+        let previous = current;
+        if let Some(change) = quantizer.update(azimuth, elevation) {
+            current = Some(change.to);
+        }
+        let record = current.expect("the first update selects a record");
+        let changed = previous != Some(record);
+        if changed {
+            std::mem::swap(&mut filters, &mut old_filters);
+            let ears = render::ear_channels(&dataset.records[record], &channels)?;
+            for (filter, ear) in filters.iter_mut().zip([ears.0, ears.1]) {
+                filter.clear();
+                filter.extend_from_slice(ear);
+                render::flush_denormals(filter);
+            }
+        }
+
+        if !changed || previous.is_none() {
+            convolve_add(block, &filters[0], &mut output[0][start..]);
+            convolve_add(block, &filters[1], &mut output[1][start..]);
+        } else {
+            // Crossfade on the input side: fade the block out through the previous
+            // filters and in through the new ones
+            let step = 1.0 / block.len() as f32;
+            faded.clear();
+            faded.extend(
+                block
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| x * (i as f32 + 0.5) * step),
+            );
+            convolve_add(&faded, &filters[0], &mut output[0][start..]);
+            convolve_add(&faded, &filters[1], &mut output[1][start..]);
+            for (f, x) in faded.iter_mut().zip(block) {
+                *f = x - *f;
+            }
+            convolve_add(&faded, &old_filters[0], &mut output[0][start..]);
+            convolve_add(&faded, &old_filters[1], &mut output[1][start..]);
+        }
+        // Partial sums of the samples this block contributed to
+        let end = (start + block.len() + filter_length)
+            .saturating_sub(1)
+            .min(length);
+        for channel in &mut output {
+            render::flush_denormals(&mut channel[start..end]);
+        }
+    }
+    Ok(output)
+}
+
+/// Sampling rate of an impulse response dataset
+fn samplerate(dataset: &Dataset) -> Result<f64> {
+    match dataset.header {
+        ContentHeader::ImpulseResponse { samplerate } => Ok(samplerate),
+        _ => Err(Error::new(format!(
+            "Auditioning requires impulse responses, not {}",
+            dataset.content_type()
+        ))),
+    }
+}
+
+/// Add the full convolution of `block` and `filter` to `output`
+fn convolve_add(block: &[f32], filter: &[f32], output: &mut [f32]) {
+    for (i, &x) in block.iter().enumerate() {
+        // Subnormal samples count as silence
+        if x == 0.0 || x.is_subnormal() {
+            continue;
+        }
+        for (y, &h) in output[i..].iter_mut().zip(filter) {
+            *y += x * h;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{self, MetadataValue};
+    use crate::test_util::{ring, temp_path};
+    use crate::trajectory::{Keyframe, Trajectory};
+
+    /// Horizontal ring of four directions; the left ear hears each direction with a delay of
+    /// its record index, the right ear with a fixed gain
+    fn dataset() -> Dataset {
+        let grid = ring(4);
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 1000.0 },
+            grid,
+            2,
+            |alpha, _, channel| {
+                let mut filter = vec![0.0; 4];
+                if channel == 0 {
+                    filter[0] = 0.5;
+                } else {
+                    filter[(alpha / 90.0).round() as usize] = 1.0;
+                }
+                filter
+            },
+        );
+        for (channel, label) in ["Right", "Left"].iter().enumerate() {
+            dataset.metadata.insert(
+                metadata::channel_label_key(channel),
+                MetadataValue::String(label.to_string()),
+            );
+        }
+        dataset
+    }
+
+    #[test]
+    fn test_render_follows_trajectory() {
+        let dataset = dataset();
+        let options = AuditionOptions {
+            block_size: 4,
+            ..AuditionOptions::default()
+        };
+        let signal = vec![1.0; 12];
+        // Front for the first two blocks, then to the left (alpha 90°)
+        let [left, right] = render(
+            &signal,
+            &dataset,
+            |t| if t < 0.008 { (0.0, 0.0) } else { (90.0, 0.0) },
+            &options,
+        )
+        .unwrap();
+        assert_eq!(left.len(), 15);
+        assert!(right[..12].iter().all(|&y| (y - 0.5).abs() < 1e-6));
+
+        // Without delay during the first blocks, then crossfading to a delay of one sample
+        assert_eq!(left[..8], [1.0; 8]);
+        let expected = [0.875, 0.75, 0.75, 0.75, 0.875, 0.0, 0.0];
+        assert!(left[8..]
+            .iter()
+            .zip(expected)
+            .all(|(y, e)| (y - e).abs() < 1e-6));
+        assert_eq!(right[12..], [0.0; 3]);
+
+        // The same path as keyframes, evaluated at the block centers
+        let trajectory = Trajectory::new(vec![
+            Keyframe::new(0.006, 0.0, 0.0),
+            Keyframe::new(0.010, 90.0, 0.0),
+        ])
+        .unwrap();
+        assert_eq!(
+            render(&signal, &dataset, &trajectory, &options).unwrap(),
+            [left, right]
+        );
+
+        let unlabeled = Dataset {
+            metadata: Default::default(),
+            ..dataset.clone()
+        };
+        assert!(render(&signal, &unlabeled, |_| (0.0, 0.0), &options).is_err());
+        let options = AuditionOptions {
+            channels: Some(ChannelMap::from_dataset(&dataset)),
+            ..options
+        };
+        assert!(render(&signal, &unlabeled, |_| (0.0, 0.0), &options).is_ok());
+        let spectrum = Dataset {
+            header: ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1.0, 2.0, 3.0, 4.0],
+            },
+            ..dataset
+        };
+        assert!(render(&signal, &spectrum, |_| (0.0, 0.0), &options).is_err());
+    }
+
+    #[test]
+    fn test_render_flushes_denormals() {
+        let tiny = f32::MIN_POSITIVE / 4.0;
+        let mut dataset = dataset();
+        for record in &mut dataset.records {
+            record.channels[0][3] = tiny;
+        }
+        let signal = [1.0, tiny, 0.5, f32::MIN_POSITIVE];
+        let [left, right] = render(&signal, &dataset, |_| (0.0, 0.0), &Default::default()).unwrap();
+        assert!(left.iter().chain(&right).all(|x| !x.is_subnormal()));
+        assert_eq!(right[..3], [0.5, 0.0, 0.25]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "file system access")]
+    fn test_render_to_wav() {
+        let input = temp_path("audition-in.wav");
+        let output = temp_path("audition-out.wav");
+        let signal = Wav {
+            samplerate: 1000,
+            channels: vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 0.0]],
+        };
+        wav::write(&input, &signal).unwrap();
+
+        let mut dataset = dataset();
+        render_to_wav(&input, &dataset, |_| (270.0, 0.0), &output).unwrap();
+        let rendered = wav::read(&output).unwrap();
+        assert_eq!(rendered.samplerate, 1000);
+        assert_eq!(rendered.channels[0], [0.0, 0.0, 0.0, 0.5, 0.0, 0.0]);
+        assert_eq!(rendered.channels[1], [0.25, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        dataset.header = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        assert!(render_to_wav(&input, &dataset, |_| (0.0, 0.0), &output).is_err());
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+}
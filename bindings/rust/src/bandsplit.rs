@@ -0,0 +1,551 @@
+//! Band-split storage of impulse responses
+//!
+//! Above a few hundred hertz, head-related impulse responses decay within a few milliseconds,
+//! and below that their directional detail is coarse. [`split`] exploits both: it divides
+//! every impulse response at a crossover frequency into a short high-band head on the full
+//! grid and a long low-band tail on a coarser grid. Stored as two DAFF files with
+//! [`BandSplit::write`], they take a fraction of the memory of the original dataset.
+//!
+//! ```no_run
+//! use opendaff::bandsplit::{self, SplitOptions};
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! # let dataset: opendaff::Dataset = unimplemented!();
+//! let options = SplitOptions {
+//!     crossover: 1500.0,
+//!     head_length: 64,
+//!     ..SplitOptions::default()
+//! };
+//! bandsplit::split(&dataset, &options)?.write("hrir.low.daff", "hrir.high.daff")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `mmap` feature, a renderer opens both files with a `Recombiner`, which maps each
+//! record of the high band to the closest record of the low band and adds them up. The bands
+//! add up to the original responses wherever the low band has a record and the high band is
+//! not truncated. Renderers that process the bands separately, e.g. with a short convolution
+//! per source for the high band and a shared one for the low band, read them with
+//! `Recombiner::high_band` and `Recombiner::low_band`.
+
+#[cfg(feature = "mmap")]
+use std::borrow::Cow;
+use std::f64::consts::PI;
+use std::path::Path;
+
+#[cfg(feature = "mmap")]
+use crate::grid::EquiangularGrid;
+use crate::grid::{self, Downsampling, Grid};
+#[cfg(feature = "mmap")]
+use crate::lookup::GridLookup;
+#[cfg(feature = "mmap")]
+use crate::mapped::MappedFile;
+use crate::metadata::MetadataValue;
+use crate::writer;
+use crate::{ContentHeader, Dataset, Error, Result};
+#[cfg(feature = "mmap")]
+use crate::{Orientation, Record};
+
+/// Metadata key naming the band a file holds, `low` or `high`
+pub const BAND_KEY: &str = "BAND_SPLIT";
+
+/// Metadata key of the crossover frequency in Hz
+pub const CROSSOVER_KEY: &str = "BAND_SPLIT_CROSSOVER";
+
+/// Number of zero crossings on each side of the crossover kernel
+const CROSSOVER_ZERO_CROSSINGS: f64 = 4.0;
+
+/// Parameters of [`split`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplitOptions {
+    /// Crossover frequency in Hz
+    pub crossover: f64,
+    /// Samples of the high band that are kept
+    pub head_length: usize,
+    /// Alpha resolution of the low band in degrees
+    pub low_alpha_resolution: f32,
+    /// Beta resolution of the low band in degrees
+    pub low_beta_resolution: f32,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        Self {
+            crossover: 1500.0,
+            head_length: 128,
+            low_alpha_resolution: 10.0,
+            low_beta_resolution: 10.0,
+        }
+    }
+}
+
+/// Low and high band of a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandSplit {
+    /// Full-length low band on the coarse grid
+    pub low: Dataset,
+    /// Truncated high band on the grid of the original dataset
+    pub high: Dataset,
+}
+
+impl BandSplit {
+    /// Write both bands to DAFF files
+    pub fn write(&self, low: impl AsRef<Path>, high: impl AsRef<Path>) -> Result<()> {
+        writer::write_dataset(low, &self.low)?;
+        writer::write_dataset(high, &self.high)
+    }
+}
+
+/// Split the impulse responses of a dataset into a low and a high band
+///
+/// The crossover is a linear-phase low-pass without delay, and the high band is the
+/// difference to it, so both bands add up to the original responses exactly. The high band
+/// is then truncated to [`head_length`](SplitOptions::head_length) samples and the low band
+/// reduced to the coarse grid with [`grid::downsample_grid`], picking the nearest records.
+/// Both bands carry the metadata of the dataset plus [`BAND_KEY`] and [`CROSSOVER_KEY`].
+pub fn split(dataset: &Dataset, options: &SplitOptions) -> Result<BandSplit> {
+    let ContentHeader::ImpulseResponse { samplerate } = dataset.header else {
+        return Err(Error::new(
+            "Band splitting is only supported for impulse responses",
+        ));
+    };
+    if !(options.crossover > 0.0 && options.crossover < samplerate / 2.0) {
+        return Err(Error::new(format!(
+            "Crossover frequency {} Hz must lie between 0 Hz and the Nyquist frequency",
+            options.crossover
+        )));
+    }
+    if options.head_length == 0 {
+        return Err(Error::new("The high band needs a head length"));
+    }
+    if !matches!(dataset.grid, Grid::Equiangular(_)) {
+        return Err(Error::new("Band splitting requires an equiangular grid"));
+    }
+
+    let kernel = lowpass_kernel(options.crossover / (samplerate / 2.0));
+    let mut low = dataset.clone();
+    let mut high = dataset.clone();
+    for (low, high) in low.records.iter_mut().zip(&mut high.records) {
+        for (low, high) in low.channels.iter_mut().zip(&mut high.channels) {
+            *low = convolve_centered(high, &kernel);
+            for (high, low) in high.iter_mut().zip(low.iter()) {
+                *high -= low;
+            }
+            high.truncate(options.head_length);
+        }
+    }
+    let low = grid::downsample_grid(
+        &low,
+        options.low_alpha_resolution,
+        options.low_beta_resolution,
+        Downsampling::Nearest,
+    )?;
+
+    let mut bands = BandSplit { low, high };
+    for (band, name) in [(&mut bands.low, "low"), (&mut bands.high, "high")] {
+        band.metadata
+            .insert(BAND_KEY.into(), MetadataValue::String(name.into()));
+        band.metadata.insert(
+            CROSSOVER_KEY.into(),
+            MetadataValue::Float(options.crossover),
+        );
+    }
+    Ok(bands)
+}
+
+/// Both bands of a band-split dataset, recombined on request
+///
+/// The files are [mapped](MappedFile), so only the records in use are loaded.
+///
+/// ```no_run
+/// use opendaff::bandsplit::Recombiner;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let hrir = Recombiner::open("hrir.low.daff", "hrir.high.daff")?;
+/// let mut ir = vec![0.0; hrir.filter_length()];
+/// hrir.read_channel(0, 0, &mut ir)?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct Recombiner {
+    low: MappedFile,
+    high: MappedFile,
+    crossover: f64,
+    /// Low band record closest to every high band record
+    low_records: Vec<usize>,
+}
+
+#[cfg(feature = "mmap")]
+impl Recombiner {
+    /// Open the files of a band-split dataset, as written by [`BandSplit::write`]
+    pub fn open(low: impl AsRef<Path>, high: impl AsRef<Path>) -> Result<Self> {
+        let (low, high) = (MappedFile::open(low)?, MappedFile::open(high)?);
+        let mut crossovers = [0.0; 2];
+        for ((file, name), crossover) in [(&low, "low"), (&high, "high")]
+            .into_iter()
+            .zip(&mut crossovers)
+        {
+            let metadata = file.metadata()?;
+            if metadata.get(BAND_KEY) != Some(&MetadataValue::String(name.into())) {
+                return Err(Error::new(format!(
+                    "'{}' does not hold the {} band of a band-split dataset",
+                    file.path().display(),
+                    name
+                )));
+            }
+            *crossover = match metadata.get(CROSSOVER_KEY) {
+                Some(&MetadataValue::Float(value)) => value,
+                Some(&MetadataValue::Int(value)) => value as f64,
+                _ => {
+                    return Err(Error::new(format!(
+                        "'{}' has no crossover frequency",
+                        file.path().display()
+                    )))
+                }
+            };
+        }
+
+        let mismatch = if crossovers[0] != crossovers[1] {
+            Some("crossover frequency")
+        } else if low.header() != high.header() {
+            Some("sample rate")
+        } else if low.num_channels() != high.num_channels() {
+            Some("number of channels")
+        } else if low.orientation() != high.orientation() {
+            Some("orientation")
+        } else {
+            None
+        };
+        if let Some(mismatch) = mismatch {
+            return Err(Error::new(format!(
+                "The bands '{}' and '{}' differ in their {}",
+                low.path().display(),
+                high.path().display(),
+                mismatch
+            )));
+        }
+
+        // Both grids are in the data view of the same orientation
+        let lookup = GridLookup::new(low.grid(), Orientation::default())?;
+        let low_records = (0..high.num_records())
+            .map(|index| {
+                let (alpha, beta) = high
+                    .grid()
+                    .record_coords(index)
+                    .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+                let (azimuth, elevation) =
+                    grid::to_object_view(&Orientation::default(), alpha, beta);
+                Ok(lookup.nearest_neighbour(azimuth, elevation))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            crossover: crossovers[0],
+            low,
+            high,
+            low_records,
+        })
+    }
+
+    /// Crossover frequency in Hz
+    pub fn crossover(&self) -> f64 {
+        self.crossover
+    }
+
+    /// Grid of the recombined records, i.e. of the high band
+    pub fn grid(&self) -> &EquiangularGrid {
+        self.high.grid()
+    }
+
+    /// Number of channels
+    pub fn num_channels(&self) -> usize {
+        self.high.num_channels()
+    }
+
+    /// Number of recombined records
+    pub fn num_records(&self) -> usize {
+        self.high.num_records()
+    }
+
+    /// Length of the recombined impulse responses
+    pub fn filter_length(&self) -> usize {
+        self.low
+            .elements_per_record()
+            .max(self.high.elements_per_record())
+    }
+
+    /// Record of the low band that is added to a record
+    pub fn low_record(&self, record: usize) -> Option<usize> {
+        self.low_records.get(record).copied()
+    }
+
+    /// High band of a record channel
+    pub fn high_band(&self, record: usize, channel: usize) -> Result<Cow<'_, [f32]>> {
+        Ok(self.high.channel(record, channel)?)
+    }
+
+    /// Low band added to a record channel, from the closest record of the coarse grid
+    pub fn low_band(&self, record: usize, channel: usize) -> Result<Cow<'_, [f32]>> {
+        let low = self
+            .low_record(record)
+            .ok_or_else(|| Error::new(format!("Invalid record index {}", record)))?;
+        Ok(self.low.channel(low, channel)?)
+    }
+
+    /// Recombine a record channel into `data`, which must hold
+    /// [`filter_length`](Recombiner::filter_length) samples
+    pub fn read_channel(&self, record: usize, channel: usize, data: &mut [f32]) -> Result<()> {
+        if data.len() != self.filter_length() {
+            return Err(Error::new(format!(
+                "Buffer holds {} samples, recombined responses have {}",
+                data.len(),
+                self.filter_length()
+            )));
+        }
+        data.fill(0.0);
+        for band in [
+            self.low_band(record, channel)?,
+            self.high_band(record, channel)?,
+        ] {
+            for (sample, value) in data.iter_mut().zip(band.iter()) {
+                *sample += value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recombine all records into a dataset on the grid of the high band
+    pub fn to_dataset(&self) -> Result<Dataset> {
+        let mut metadata = self.high.metadata()?;
+        metadata.remove(BAND_KEY);
+        metadata.remove(CROSSOVER_KEY);
+        let grid = *self.grid();
+        let records = (0..self.num_records())
+            .map(|index| {
+                let (alpha, beta) = grid
+                    .record_coords(index)
+                    .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+                let channels = (0..self.num_channels())
+                    .map(|channel| {
+                        let mut data = vec![0.0; self.filter_length()];
+                        self.read_channel(index, channel, &mut data)?;
+                        Ok(data)
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Record {
+                    alpha,
+                    beta,
+                    channels,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Dataset {
+            header: self.high.header().clone(),
+            quantization: self.high.quantization(),
+            grid: Grid::Equiangular(grid),
+            orientation: self.high.orientation(),
+            metadata,
+            records,
+        })
+    }
+}
+
+/// Blackman-windowed sinc low-pass with unit gain at 0 Hz, centered on its middle tap
+///
+/// `cutoff` is relative to the Nyquist frequency.
+fn lowpass_kernel(cutoff: f64) -> Vec<f64> {
+    let half_width = (CROSSOVER_ZERO_CROSSINGS / cutoff).ceil();
+    let taps: Vec<f64> = (-(half_width as i64)..=half_width as i64)
+        .map(|k| {
+            let x = k as f64;
+            let window = 0.42
+                + 0.5 * (PI * x / (half_width + 1.0)).cos()
+                + 0.08 * (2.0 * PI * x / (half_width + 1.0)).cos();
+            let sinc = if k == 0 {
+                1.0
+            } else {
+                (PI * cutoff * x).sin() / (PI * cutoff * x)
+            };
+            cutoff * sinc * window
+        })
+        .collect();
+    let gain: f64 = taps.iter().sum();
+    taps.into_iter().map(|tap| tap / gain).collect()
+}
+
+/// Convolution with a kernel centered on its middle tap, keeping the length of the signal
+fn convolve_centered(signal: &[f32], kernel: &[f64]) -> Vec<f32> {
+    let half = (kernel.len() / 2) as isize;
+    (0..signal.len() as isize)
+        .map(|n| {
+            kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(k, tap)| {
+                    let index = usize::try_from(n + half - k as isize).ok()?;
+                    Some(signal.get(index).copied()? as f64 * tap)
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::EquiangularGrid;
+    use crate::Quantization;
+
+    /// Decaying impulse responses whose onset depends on the direction
+    fn dataset() -> Dataset {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            EquiangularGrid::with_resolution(15.0, 30.0).unwrap(),
+            2,
+            |alpha, beta, channel| {
+                let onset = ((alpha + beta) / 30.0) as usize + channel;
+                (0..256usize)
+                    .map(|n| match n.checked_sub(onset) {
+                        Some(t) => (0.9f32).powi(t as i32) * (0.7 * t as f32).cos(),
+                        None => 0.0,
+                    })
+                    .collect()
+            },
+        );
+        dataset.quantization = Quantization::Float32;
+        dataset
+    }
+
+    #[test]
+    fn test_bands_add_up() {
+        let dataset = dataset();
+        let options = SplitOptions {
+            crossover: 2000.0,
+            head_length: 256,
+            low_alpha_resolution: 15.0,
+            low_beta_resolution: 30.0,
+        };
+        let bands = split(&dataset, &options).unwrap();
+        assert_eq!(bands.low.records.len(), dataset.records.len());
+        for ((original, low), high) in dataset
+            .records
+            .iter()
+            .zip(&bands.low.records)
+            .zip(&bands.high.records)
+        {
+            for ((original, low), high) in original
+                .channels
+                .iter()
+                .zip(&low.channels)
+                .zip(&high.channels)
+            {
+                for ((o, l), h) in original.iter().zip(low).zip(high) {
+                    assert!((o - (l + h)).abs() < 1e-6);
+                }
+            }
+        }
+
+        // The low band holds hardly any energy above the crossover: its sample-to-sample
+        // differences are small compared to those of the original
+        let roughness =
+            |signal: &[f32]| -> f32 { signal.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum() };
+        let original = &dataset.records[3].channels[0];
+        let low = &bands.low.records[3].channels[0];
+        assert!(roughness(low) < 0.05 * roughness(original));
+        assert_eq!(
+            bands.high.metadata.get(BAND_KEY),
+            Some(&MetadataValue::String("high".into()))
+        );
+
+        let mut spectrum = dataset.clone();
+        spectrum.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(split(&spectrum, &options).is_err());
+        for invalid in [
+            SplitOptions {
+                crossover: 24000.0,
+                ..options
+            },
+            SplitOptions {
+                head_length: 0,
+                ..options
+            },
+            SplitOptions {
+                low_alpha_resolution: 7.0,
+                ..options
+            },
+        ] {
+            assert!(split(&dataset, &invalid).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_recombine() {
+        use crate::test_util::temp_path;
+
+        let dataset = dataset();
+        let options = SplitOptions {
+            crossover: 1500.0,
+            head_length: 96,
+            low_alpha_resolution: 45.0,
+            low_beta_resolution: 60.0,
+        };
+        let bands = split(&dataset, &options).unwrap();
+        assert_eq!(bands.high.elements_per_record(), 96);
+        let (low_path, high_path) = (temp_path("bands.low.daff"), temp_path("bands.high.daff"));
+        bands.write(&low_path, &high_path).unwrap();
+
+        let recombiner = Recombiner::open(&low_path, &high_path).unwrap();
+        assert_eq!(recombiner.crossover(), 1500.0);
+        assert_eq!(recombiner.filter_length(), 256);
+        assert_eq!(recombiner.num_records(), dataset.records.len());
+        let recombined = recombiner.to_dataset().unwrap();
+        assert_eq!(recombined.grid, dataset.grid);
+        assert!(recombined.metadata.is_empty());
+
+        // Records on the coarse grid are exact up to the truncation of the high band
+        let coarse = bands.low.grid.equiangular().unwrap();
+        for index in 0..recombiner.num_records() {
+            let low = recombiner.low_record(index).unwrap();
+            let (alpha, beta) = dataset
+                .grid
+                .equiangular()
+                .unwrap()
+                .record_coords(index)
+                .unwrap();
+            let (low_alpha, low_beta) = coarse.record_coords(low).unwrap();
+            if grid::angle_eq(alpha, low_alpha) && grid::angle_eq(beta, low_beta) {
+                let original = &dataset.records[index].channels[1];
+                let high = recombiner.high_band(index, 1).unwrap();
+                let ir = &recombined.records[index].channels[1];
+                for n in 0..96 {
+                    assert!((ir[n] - original[n]).abs() < 1e-5);
+                }
+                let tail: Vec<f32> = (96..256).map(|n| original[n] - ir[n]).collect();
+                assert_eq!(high.len(), 96);
+                assert!(tail.iter().all(|v| v.abs() < 0.05));
+            }
+        }
+
+        // The bands have to belong together
+        assert!(Recombiner::open(&high_path, &low_path).is_err());
+        let other = split(
+            &dataset,
+            &SplitOptions {
+                crossover: 1000.0,
+                ..options
+            },
+        )
+        .unwrap();
+        writer::write_dataset(&high_path, &other.high).unwrap();
+        assert!(Recombiner::open(&low_path, &high_path).is_err());
+        std::fs::remove_file(low_path).unwrap();
+        std::fs::remove_file(high_path).unwrap();
+    }
+}
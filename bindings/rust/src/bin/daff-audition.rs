@@ -0,0 +1,84 @@
+//! daff-audition: render a binaural preview of an impulse response dataset
+//!
+//! Moves a mono source around the listener at constant speed, or along a trajectory loaded
+//! from a JSON or CSV file, and writes the result as a stereo WAV file, see
+//! `opendaff::audition` and `opendaff::trajectory`.
+
+use std::env;
+use std::process::ExitCode;
+
+use opendaff::trajectory::Trajectory;
+use opendaff::{audition, Dataset, Reader};
+
+const USAGE: &str = "Usage: daff-audition [--speed DEG_PER_S] [--elevation DEG] \
+                     [--trajectory FILE] <input.wav> <dataset.daff> <output.wav>";
+
+fn main() -> ExitCode {
+    let mut speed = 90.0f64;
+    let mut elevation = 0.0f32;
+    let mut trajectory = None;
+    let mut paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" | "-s" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(value) => speed = value,
+                None => {
+                    eprintln!("--speed requires a number\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--elevation" | "-e" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(value) => elevation = value,
+                None => {
+                    eprintln!("--elevation requires a number\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--trajectory" | "-t" => match args.next() {
+                Some(path) => trajectory = Some(path),
+                None => {
+                    eprintln!("--trajectory requires a file\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if !arg.starts_with('-') => paths.push(arg),
+            _ => {
+                eprintln!("Unexpected argument '{}'\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let [input, daff, output] = paths.as_slice() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let result = Reader::new()
+        .and_then(|mut reader| {
+            reader.open_file(daff)?;
+            Dataset::from_reader(&reader)
+        })
+        .and_then(|dataset| match &trajectory {
+            Some(path) => {
+                let trajectory = Trajectory::load(path)?;
+                audition::render_to_wav(input, &dataset, &trajectory, output)
+            }
+            None => {
+                let circle = |t: f64| (((speed * t) % 360.0) as f32, elevation);
+                audition::render_to_wav(input, &dataset, circle, output)
+            }
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
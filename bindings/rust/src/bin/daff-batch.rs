@@ -0,0 +1,11 @@
+//! daff-batch: run a declarative processing pipeline over many DAFF files
+//!
+//! See the `opendaff::pipeline` module for the pipeline file format.
+
+use std::process::ExitCode;
+
+use opendaff::pipeline::{self, StageRegistry};
+
+fn main() -> ExitCode {
+    pipeline::batch_main(&StageRegistry::default())
+}
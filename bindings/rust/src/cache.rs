@@ -0,0 +1,325 @@
+//! Content-addressed cache of processed datasets
+//!
+//! A [`Store`] keeps the results of processing in a directory, keyed by a fingerprint of the
+//! source file and of the [`Pipeline`] applied to it. Running the same pipeline over an
+//! unchanged file again returns the stored result instead of processing it, so repeated
+//! [batch runs](crate::pipeline::Batch) only process inputs that changed (or all of them after
+//! the pipeline changed). Fingerprints cover content, not file names or modification times:
+//! renamed or touched files still hit the cache, edited files miss it.
+//!
+//! ```no_run
+//! use opendaff::cache::Store;
+//! use opendaff::pipeline::{Pipeline, Stage};
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! let store = Store::open("cache")?;
+//! let pipeline = Pipeline::new().stage(Stage::Normalize { peak_db: -1.0 });
+//! let processed = store.process("hrir.daff", &pipeline)?; // processes the file
+//! let again = store.process("hrir.daff", &pipeline)?; // loads the stored result
+//! assert_eq!(processed, again);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Results are stored as DAFF files with 32-bit float data, so they are returned exactly as
+//! processed. Datasets that cannot be written to DAFF files, e.g. on a non-equiangular grid,
+//! are not cached.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+use crate::pipeline::Pipeline;
+use crate::writer;
+use crate::{Dataset, Error, Quantization, Reader, Result};
+
+/// Results written to temporary files so far, makes their names unique
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// SHA-256 hash over the contents of a file (`sha256:<hex>`)
+pub fn fingerprint(path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+    let error = |e: io::Error| Error::new(format!("Failed to read '{}': {}", path.display(), e));
+    let mut file = File::open(path).map_err(error)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer).map_err(error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("sha256:{}", hex(&hasher.finalize())))
+}
+
+/// Cache key of a source and the processing applied to it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key(String);
+
+impl Key {
+    /// Key of a source fingerprint and a pipeline fingerprint
+    ///
+    /// Both can be any strings identifying their content, e.g. from [`fingerprint`] and
+    /// [`Pipeline::fingerprint`].
+    pub fn new(source: &str, pipeline: &str) -> Self {
+        let mut hasher = Sha256::new();
+        for part in [source, pipeline] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        Key(hex(&hasher.finalize()))
+    }
+
+    /// Key of a file processed by a pipeline
+    pub fn for_file(path: impl AsRef<Path>, pipeline: &Pipeline) -> Result<Self> {
+        Ok(Self::new(&fingerprint(path)?, &pipeline.fingerprint()))
+    }
+
+    /// The key as hexadecimal string, which names the file of the stored result
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Directory of processed datasets, see the [module documentation](self)
+///
+/// Stores can be shared by threads and processes: results are written to temporary files
+/// first and then renamed, so readers never see partial files.
+#[derive(Debug, Clone)]
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    /// Open the store in a directory, creating the directory if needed
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        fs::create_dir_all(root)
+            .map_err(|e| Error::new(format!("Failed to create '{}': {}", root.display(), e)))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Directory of the store
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The stored result for a key, `None` if there is none
+    pub fn get(&self, key: &Key) -> Result<Option<Dataset>> {
+        for quantization in QUANTIZATIONS {
+            let path = self.path(key, quantization);
+            if !path.is_file() {
+                continue;
+            }
+            let reader = Reader::open(path)?;
+            let mut dataset = Dataset::from_reader(&reader)?;
+            dataset.quantization = quantization;
+            return Ok(Some(dataset));
+        }
+        Ok(None)
+    }
+
+    /// Store a result under a key, replacing an earlier one
+    ///
+    /// Fails for datasets that cannot be written to DAFF files.
+    pub fn put(&self, key: &Key, dataset: &Dataset) -> Result<()> {
+        // Values are kept exactly, the quantization is restored from the file name
+        let mut exact = dataset.clone();
+        exact.quantization = Quantization::Float32;
+        let pending = self.root.join(format!(
+            "{}.{}-{}.tmp",
+            key.as_str(),
+            std::process::id(),
+            PENDING.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = writer::write_dataset(&pending, &exact) {
+            let _ = fs::remove_file(&pending);
+            return Err(e);
+        }
+
+        self.remove(key)?;
+        let path = self.path(key, dataset.quantization);
+        fs::rename(&pending, &path).map_err(|e| {
+            let _ = fs::remove_file(&pending);
+            Error::new(format!("Failed to store '{}': {}", path.display(), e))
+        })
+    }
+
+    /// Remove the result stored under a key; returns whether there was one
+    pub fn remove(&self, key: &Key) -> Result<bool> {
+        let mut removed = false;
+        for quantization in QUANTIZATIONS {
+            let path = self.path(key, quantization);
+            match fs::remove_file(&path) {
+                Ok(()) => removed = true,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(Error::new(format!(
+                        "Failed to remove '{}': {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Load a file and run a pipeline on it, or return the stored result of an earlier run
+    ///
+    /// New results are stored, unless they cannot be written to DAFF files.
+    pub fn process(&self, input: impl AsRef<Path>, pipeline: &Pipeline) -> Result<Dataset> {
+        let input = input.as_ref();
+        let key = Key::for_file(input, pipeline)?;
+        if let Some(dataset) = self.get(&key)? {
+            return Ok(dataset);
+        }
+
+        let mut reader = Reader::open(input)?;
+        let mut dataset = Dataset::from_reader(&reader)?;
+        reader.close();
+
+        pipeline.run(&mut dataset)?;
+        if dataset.grid.equiangular().is_some() {
+            self.put(&key, &dataset)?;
+        }
+        Ok(dataset)
+    }
+
+    /// File of a result with the given quantization
+    fn path(&self, key: &Key, quantization: Quantization) -> PathBuf {
+        let suffix = match quantization {
+            Quantization::Int16 => "int16",
+            Quantization::Int24 => "int24",
+            Quantization::Float32 => "float32",
+        };
+        self.root.join(format!("{}-{}.daff", key.as_str(), suffix))
+    }
+}
+
+const QUANTIZATIONS: [Quantization; 3] = [
+    Quantization::Int16,
+    Quantization::Int24,
+    Quantization::Float32,
+];
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{PipelineStage, Stage};
+    use crate::test_util::temp_path;
+    use crate::{ContentHeader, EquiangularGrid};
+    use std::fmt;
+    use std::sync::Arc;
+
+    /// Counts how often it was applied
+    struct Counter(Arc<AtomicUsize>);
+
+    impl fmt::Display for Counter {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "count")
+        }
+    }
+
+    impl PipelineStage for Counter {
+        fn apply(&self, _: &mut Dataset) -> Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn dataset(gain: f32) -> Dataset {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            1,
+            |alpha, beta, _| vec![gain, alpha / 3600.0, beta / 1800.0, 0.1],
+        );
+        dataset.quantization = Quantization::Int16;
+        dataset
+    }
+
+    #[test]
+    fn test_keys() {
+        let pipeline = Pipeline::new().stage(Stage::Normalize { peak_db: -1.0 });
+        let other = Pipeline::new().stage(Stage::Normalize { peak_db: -3.0 });
+        assert_eq!(pipeline.fingerprint(), pipeline.clone().fingerprint());
+        assert_ne!(pipeline.fingerprint(), other.fingerprint());
+        assert_ne!(
+            pipeline.fingerprint(),
+            pipeline.clone().reproducibility_hash(true).fingerprint()
+        );
+
+        let key = Key::new("a", &pipeline.fingerprint());
+        assert_eq!(key, Key::new("a", &pipeline.fingerprint()));
+        assert_ne!(key, Key::new("b", &pipeline.fingerprint()));
+        assert_ne!(Key::new("ab", "c"), Key::new("a", "bc"));
+        assert_eq!(key.as_str().len(), 64);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_store() {
+        let root = temp_path("cache-store");
+        let _ = fs::remove_dir_all(&root);
+        let store = Store::open(&root).unwrap();
+        let key = Key::new("source", "pipeline");
+        assert_eq!(store.get(&key).unwrap(), None);
+
+        // Values between the 16 bit steps survive, and so does the quantization
+        let dataset = dataset(0.123456);
+        store.put(&key, &dataset).unwrap();
+        assert_eq!(store.get(&key).unwrap(), Some(dataset.clone()));
+
+        let mut replacement = dataset.clone();
+        replacement.quantization = Quantization::Float32;
+        store.put(&key, &replacement).unwrap();
+        assert_eq!(store.get(&key).unwrap(), Some(replacement));
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+
+        assert!(store.remove(&key).unwrap());
+        assert!(!store.remove(&key).unwrap());
+        assert_eq!(store.get(&key).unwrap(), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_process() {
+        let root = temp_path("cache-process");
+        let _ = fs::remove_dir_all(&root);
+        let input = temp_path("cache-input.daff");
+        writer::write_dataset(&input, &dataset(0.5)).unwrap();
+
+        let applied = Arc::new(AtomicUsize::new(0));
+        let pipeline = Pipeline::new()
+            .stage(Stage::Normalize { peak_db: -6.0 })
+            .stage(Counter(Arc::clone(&applied)));
+        let store = Store::open(&root).unwrap();
+        let processed = store.process(&input, &pipeline).unwrap();
+        assert_eq!(applied.load(Ordering::Relaxed), 1);
+        assert_eq!(store.process(&input, &pipeline).unwrap(), processed);
+        assert_eq!(applied.load(Ordering::Relaxed), 1);
+
+        // A changed input is processed again
+        writer::write_dataset(&input, &dataset(0.25)).unwrap();
+        let changed = store.process(&input, &pipeline).unwrap();
+        assert_eq!(applied.load(Ordering::Relaxed), 2);
+        assert_ne!(changed, processed);
+
+        fs::remove_file(input).unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+//! Records as polars DataFrames
+//!
+//! [`to_polars`] turns the records of a content into a [`DataFrame`] with one row per record
+//! direction and channel and one column per sample or frequency, so exploratory analysis,
+//! e.g. in an evcxr notebook, is one call away:
+//!
+//! ```no_run
+//! use opendaff::dataframe::{self, Selection};
+//! use opendaff::grid::Region;
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! let reader = opendaff::Reader::open("hrtf.ms.daff")?;
+//! let selection = Selection {
+//!     region: Some(Region::Cone { azimuth: 0.0, elevation: 0.0, half_angle: 30.0 }),
+//!     channels: Some(vec![0]),
+//!     ..Selection::default()
+//! };
+//! let frame = dataframe::to_polars(&reader.content_ms()?, &selection)?;
+//! println!("{}", frame);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The first columns describe the row: `record`, the direction in the data view (`alpha`,
+//! `beta`) and the object view (`azimuth`, `elevation`) in degrees, and `channel`. The value
+//! columns are named like the columns of the CSV export: sample indices for impulse
+//! responses, frequencies in Hz for magnitude and phase spectra, and `re(…)` and `im(…)`
+//! pairs for complex spectra (MPS by frequency, DFT by bin).
+
+use std::ops::Range;
+
+use polars::prelude::{Column, DataFrame};
+
+use crate::dataset::RecordReader;
+use crate::export::{self, ExportOptions};
+use crate::grid::{self, Region};
+use crate::{
+    ContentDFT, ContentHeader, ContentIR, ContentMPS, ContentMS, ContentPS, Error, Reader, Result,
+};
+
+/// Content objects that can be converted with [`to_polars`]
+///
+/// Implemented for all content types of a reader.
+pub trait Content: private::Sealed {}
+
+mod private {
+    pub trait Sealed {
+        fn reader(&self) -> &crate::Reader;
+    }
+}
+
+macro_rules! content {
+    ($($content:ident),*) => {
+        $(
+            impl private::Sealed for $content<'_> {
+                fn reader(&self) -> &Reader {
+                    self.reader
+                }
+            }
+
+            impl Content for $content<'_> {}
+        )*
+    };
+}
+
+content!(ContentIR, ContentMS, ContentPS, ContentMPS, ContentDFT);
+
+/// Records, channels and values converted by [`to_polars`], everything by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    /// Only records whose direction lies within the region
+    pub region: Option<Region>,
+    /// Only these records, in this order
+    pub records: Option<Vec<usize>>,
+    /// Only these channels, in this order
+    pub channels: Option<Vec<usize>>,
+    /// Only these samples, frequencies or DFT bins
+    pub elements: Option<Range<usize>>,
+}
+
+/// Convert the selected records of a content to a DataFrame
+///
+/// Returns one row per selected record and channel, see the [module documentation](self) for
+/// the columns. Fails if the selection refers to records, channels or elements the content
+/// does not have.
+pub fn to_polars<C: Content>(content: &C, selection: &Selection) -> Result<DataFrame> {
+    let reader = content.reader();
+    let records = RecordReader::new(reader)?;
+    let header = records.header()?;
+    let orientation = reader.orientation()?;
+    let num_channels = reader.num_channels().max(0) as usize;
+
+    // Complex spectra store two values (real, imaginary) per element
+    let width = match header {
+        ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => 2,
+        _ => 1,
+    };
+    let values_per_channel = records.values_per_channel();
+    let num_elements = values_per_channel / width;
+    let elements = selection.elements.clone().unwrap_or(0..num_elements);
+    if elements.start > elements.end || elements.end > num_elements {
+        return Err(Error::new(format!(
+            "Elements {:?} out of range (0..{})",
+            elements, num_elements
+        )));
+    }
+    let values = elements.start * width..elements.end * width;
+
+    let indices: Vec<usize> = match &selection.records {
+        Some(indices) => indices.clone(),
+        None => (0..records.num_records()).collect(),
+    };
+    if let Some(index) = indices.iter().find(|&&r| r >= records.num_records()) {
+        return Err(Error::new(format!(
+            "Record {} out of range (0..{})",
+            index,
+            records.num_records()
+        )));
+    }
+    let channels: Vec<usize> = match &selection.channels {
+        Some(channels) => channels.clone(),
+        None => (0..num_channels).collect(),
+    };
+    if let Some(channel) = channels.iter().find(|&&c| c >= num_channels) {
+        return Err(Error::new(format!(
+            "Channel {} out of range (0..{})",
+            channel, num_channels
+        )));
+    }
+
+    let mut record_column = Vec::new();
+    let mut alpha_column = Vec::new();
+    let mut beta_column = Vec::new();
+    let mut azimuth_column = Vec::new();
+    let mut elevation_column = Vec::new();
+    let mut channel_column = Vec::new();
+    let mut value_columns = vec![Vec::new(); values.len()];
+    for index in indices {
+        let record = records.record(index)?;
+        if let Some(region) = &selection.region {
+            if !region.contains(&orientation, record.alpha, record.beta) {
+                continue;
+            }
+        }
+        let (azimuth, elevation) = grid::to_object_view(&orientation, record.alpha, record.beta);
+        for &channel in &channels {
+            record_column.push(index as u32);
+            alpha_column.push(record.alpha);
+            beta_column.push(record.beta);
+            azimuth_column.push(azimuth);
+            elevation_column.push(elevation);
+            channel_column.push(channel as u32);
+            for (column, &value) in value_columns
+                .iter_mut()
+                .zip(&record.channels[channel][values.clone()])
+            {
+                column.push(value);
+            }
+        }
+    }
+
+    let labels = export::element_labels(&header, values_per_channel, &ExportOptions::default());
+    let mut columns = vec![
+        Column::new("record".into(), record_column),
+        Column::new("alpha".into(), alpha_column),
+        Column::new("beta".into(), beta_column),
+        Column::new("azimuth".into(), azimuth_column),
+        Column::new("elevation".into(), elevation_column),
+        Column::new("channel".into(), channel_column),
+    ];
+    columns.extend(
+        labels[values]
+            .iter()
+            .zip(value_columns)
+            .map(|(label, column)| Column::new(label.into(), column)),
+    );
+    DataFrame::new(columns)
+        .map_err(|e| Error::new(format!("Failed to create the DataFrame: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::EquiangularGrid;
+    use crate::test_util::temp_path;
+    use crate::{writer, Dataset};
+
+    fn open(name: &str, header: ContentHeader) -> (Reader, std::path::PathBuf) {
+        let values = match header {
+            ContentHeader::ImpulseResponse { .. } => 4,
+            _ => 6,
+        };
+        let dataset = Dataset::from_fn(
+            header,
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            2,
+            |alpha, beta, channel| {
+                (0..values)
+                    .map(|i| (alpha + beta) / 1000.0 + channel as f32 * 0.5 + i as f32 * 0.125)
+                    .collect()
+            },
+        );
+        let path = temp_path(&format!("{}.daff", name));
+        writer::write_dataset(&path, &dataset).unwrap();
+        (Reader::open(&path).unwrap(), path)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads through the C++ library")]
+    fn test_impulse_responses() {
+        let (reader, path) = open(
+            "dataframe-ir",
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+        );
+        let ir = reader.content_ir().unwrap();
+        let frame = to_polars(&ir, &Selection::default()).unwrap();
+        let num_records = reader.num_records() as usize;
+        assert_eq!(frame.shape(), (num_records * 2, 6 + 4));
+        let names: Vec<&str> = frame.get_column_names_str();
+        assert_eq!(
+            names[..6],
+            ["record", "alpha", "beta", "azimuth", "elevation", "channel"]
+        );
+        assert_eq!(names[6..], ["0", "1", "2", "3"]);
+
+        let selection = Selection {
+            records: Some(vec![3, 1]),
+            channels: Some(vec![1]),
+            elements: Some(1..3),
+            ..Selection::default()
+        };
+        let frame = to_polars(&ir, &selection).unwrap();
+        assert_eq!(frame.shape(), (2, 6 + 2));
+        let record = frame.column("record").unwrap().u32().unwrap();
+        assert_eq!(record.get(0), Some(3));
+        assert_eq!(record.get(1), Some(1));
+        let (alpha, beta) = ir.record_coords(3).unwrap();
+        let expected = ir.filter_coeffs(3, 1).unwrap()[2];
+        let values = frame.column("2").unwrap().f32().unwrap();
+        assert_eq!(values.get(0), Some(expected));
+        assert_eq!(
+            frame.column("alpha").unwrap().f32().unwrap().get(0),
+            Some(alpha as f32)
+        );
+        assert_eq!(
+            frame.column("beta").unwrap().f32().unwrap().get(0),
+            Some(beta as f32)
+        );
+
+        for selection in [
+            Selection {
+                records: Some(vec![num_records]),
+                ..Selection::default()
+            },
+            Selection {
+                channels: Some(vec![2]),
+                ..Selection::default()
+            },
+            Selection {
+                elements: Some(2..5),
+                ..Selection::default()
+            },
+        ] {
+            assert!(to_polars(&ir, &selection).is_err());
+        }
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads through the C++ library")]
+    fn test_spectra_and_region() {
+        let (reader, path) = open(
+            "dataframe-mps",
+            ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: vec![250.0, 1000.0, 4000.0],
+            },
+        );
+        let mps = reader.content_mps().unwrap();
+        let selection = Selection {
+            region: Some(Region::Window {
+                alpha_start: 0.0,
+                alpha_end: 0.0,
+                beta_start: 0.0,
+                beta_end: 180.0,
+            }),
+            elements: Some(1..2),
+            ..Selection::default()
+        };
+        let frame = to_polars(&mps, &selection).unwrap();
+        let names: Vec<&str> = frame.get_column_names_str();
+        assert_eq!(names[6..], ["re(1000)", "im(1000)"]);
+        // Records at alpha = 0° from the south to the north pole, both channels each
+        assert_eq!(frame.height(), 3 * 2);
+        let alpha = frame.column("alpha").unwrap().f32().unwrap();
+        assert!(alpha.into_iter().all(|alpha| alpha == Some(0.0)));
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,543 @@
+//! Owned, in-memory DAFF datasets
+//!
+//! A [`Dataset`] holds a copy of all records of a DAFF file, independent of the reader it was
+//! loaded from. It is the common currency for processing functions (see [`crate::dsp`]).
+
+pub use opendaff_core::ContentHeader;
+
+use std::fmt;
+
+use crate::decibel;
+use crate::grid::{EquiangularGrid, Grid};
+use crate::metadata::{self, Metadata, MetadataValue};
+use crate::{
+    ContentDFT, ContentIR, ContentMPS, ContentMS, ContentPS, ContentType, Error, Orientation,
+    Quantization, Reader, Result,
+};
+
+/// Metadata key declaring the unit of the magnitudes of MS and MPS content
+pub const MAGNITUDE_UNIT_KEY: &str = "MAGNITUDE_UNIT";
+
+/// Unit of the magnitudes of magnitude spectra and magnitude-phase spectra
+///
+/// The DAFF format does not define it, and files in the wild hold linear magnitudes as well as
+/// levels in dB. The unit is declared by the [`MAGNITUDE_UNIT_KEY`] metadata entry (see
+/// [`MagnitudeUnit::name`]); without it, magnitudes are taken as levels in dB if any of them
+/// is negative, which linear magnitudes never are, and as linear magnitudes otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MagnitudeUnit {
+    /// Linear magnitudes, e.g. of a transfer function
+    Linear,
+    /// Levels in dB re 1
+    Decibel,
+    /// Linear sound pressures relative to a reference pressure, e.g. the free-field pressure
+    /// at the center of the head for HRTFs
+    PressureNormalized,
+}
+
+impl MagnitudeUnit {
+    /// Name of the unit in the metadata: `linear`, `dB` or `pressure-normalized`
+    pub fn name(self) -> &'static str {
+        match self {
+            MagnitudeUnit::Linear => "linear",
+            MagnitudeUnit::Decibel => "dB",
+            MagnitudeUnit::PressureNormalized => "pressure-normalized",
+        }
+    }
+
+    /// Look up a unit by its name (case-insensitive), also accepting `decibel` and `pressure`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "linear" => Some(MagnitudeUnit::Linear),
+            "db" | "decibel" => Some(MagnitudeUnit::Decibel),
+            "pressure-normalized" | "pressure" => Some(MagnitudeUnit::PressureNormalized),
+            _ => None,
+        }
+    }
+
+    /// Unit declared by a metadata set, `None` if the entry is missing or unknown
+    pub fn declared(metadata: &Metadata) -> Option<Self> {
+        metadata
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(MAGNITUDE_UNIT_KEY))
+            .and_then(|(_, value)| match value {
+                MetadataValue::String(name) => Self::from_name(name),
+                _ => None,
+            })
+    }
+
+    /// Unit of magnitudes without declaration: [`MagnitudeUnit::Decibel`] if any of them is
+    /// negative, [`MagnitudeUnit::Linear`] otherwise
+    pub fn detect<'a>(magnitudes: impl IntoIterator<Item = &'a f32>) -> Self {
+        if magnitudes.into_iter().any(|&m| m < 0.0) {
+            MagnitudeUnit::Decibel
+        } else {
+            MagnitudeUnit::Linear
+        }
+    }
+
+    /// Whether magnitudes in this unit are linear (on any scale)
+    pub fn is_linear(self) -> bool {
+        self != MagnitudeUnit::Decibel
+    }
+
+    /// Convert magnitudes in this unit to linear magnitudes in place
+    pub fn to_linear(self, magnitudes: &mut [f32]) {
+        if self == MagnitudeUnit::Decibel {
+            decibel::from_db_slice(magnitudes);
+        }
+    }
+
+    /// Convert linear magnitudes to this unit in place
+    pub fn convert_linear(self, magnitudes: &mut [f32]) {
+        if self == MagnitudeUnit::Decibel {
+            decibel::to_db_slice(magnitudes);
+        }
+    }
+}
+
+impl fmt::Display for MagnitudeUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single record: its direction and one data vector per channel
+///
+/// The layout of the channel data depends on the content type: samples for IR, magnitudes
+/// for MS, phases for PS and interleaved real/imaginary values for MPS and DFT content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Alpha angle in degrees (data view)
+    pub alpha: f32,
+    /// Beta angle in degrees (data view)
+    pub beta: f32,
+    /// Data vector per channel
+    pub channels: Vec<Vec<f32>>,
+}
+
+/// Immutable copy of a range of impulse response records
+///
+/// Created by [`ContentIR::snapshot`](crate::ContentIR::snapshot). Unlike the content
+/// object it does not borrow the reader, so it is `Send + Sync` and can be shared with
+/// worker threads, e.g. behind an [`Arc`](std::sync::Arc).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrSnapshot {
+    samplerate: f64,
+    filter_length: usize,
+    first_record: usize,
+    records: Vec<Record>,
+}
+
+impl IrSnapshot {
+    pub(crate) fn new(
+        samplerate: f64,
+        filter_length: usize,
+        first_record: usize,
+        records: Vec<Record>,
+    ) -> Self {
+        Self {
+            samplerate,
+            filter_length,
+            first_record,
+            records,
+        }
+    }
+
+    /// Sampling rate in Hz
+    pub fn samplerate(&self) -> f64 {
+        self.samplerate
+    }
+
+    /// Filter length in samples
+    pub fn filter_length(&self) -> usize {
+        self.filter_length
+    }
+
+    /// Record indices (in the file) covered by the snapshot
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.first_record..self.first_record + self.records.len()
+    }
+
+    /// Captured records in storage order
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Record by its index in the file, `None` outside the captured range
+    pub fn record(&self, record_index: usize) -> Option<&Record> {
+        record_index
+            .checked_sub(self.first_record)
+            .and_then(|i| self.records.get(i))
+    }
+
+    /// Filter coefficients by record index in the file and channel
+    pub fn filter_coeffs(&self, record_index: usize, channel: usize) -> Option<&[f32]> {
+        self.record(record_index)?
+            .channels
+            .get(channel)
+            .map(Vec::as_slice)
+    }
+}
+
+/// Owned copy of the content of a DAFF file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    /// Content type and content-specific header values
+    pub header: ContentHeader,
+    /// Quantization of the stored data
+    pub quantization: Quantization,
+    /// Sampling layout of the records
+    pub grid: Grid,
+    /// Default orientation (yaw-pitch-roll in degrees)
+    pub orientation: Orientation,
+    /// Global metadata
+    pub metadata: Metadata,
+    /// Records in storage order
+    pub records: Vec<Record>,
+}
+
+impl Dataset {
+    /// Create a dataset on the given grid, generating the data of every record and channel
+    ///
+    /// The closure receives the data view coordinates (alpha, beta) in degrees and the
+    /// channel index.
+    pub fn from_fn<F>(
+        header: ContentHeader,
+        grid: EquiangularGrid,
+        num_channels: usize,
+        mut f: F,
+    ) -> Self
+    where
+        F: FnMut(f32, f32, usize) -> Vec<f32>,
+    {
+        let records = grid
+            .directions()
+            .map(|(alpha, beta)| Record {
+                alpha,
+                beta,
+                channels: (0..num_channels).map(|c| f(alpha, beta, c)).collect(),
+            })
+            .collect();
+
+        Self {
+            header,
+            quantization: Quantization::Float32,
+            grid: grid.into(),
+            orientation: Orientation::default(),
+            metadata: Metadata::new(),
+            records,
+        }
+    }
+
+    /// Load all records of the file currently opened by `reader`
+    ///
+    /// Magnitude-phase spectra with magnitudes in dB are converted to complex values like all
+    /// others, and the metadata of the dataset declares linear magnitudes.
+    pub fn from_reader(reader: &Reader) -> Result<Self> {
+        if !reader.is_valid() {
+            return Err(Error::Closed);
+        }
+
+        let quantization = reader
+            .quantization()
+            .ok_or_else(|| Error::new("Unknown quantization"))?;
+        let content = RecordReader::new(reader)?;
+        let records = (0..content.num_records())
+            .map(|r| content.record(r))
+            .collect::<Result<_>>()?;
+        let header = content.header()?;
+
+        let mut dataset = Self {
+            header,
+            quantization,
+            grid: reader.grid()?.into(),
+            orientation: reader.orientation()?,
+            metadata: metadata::read_all(reader)?,
+            records,
+        };
+        if content.converts_magnitudes() {
+            dataset.set_magnitude_unit(MagnitudeUnit::Linear);
+        }
+        Ok(dataset)
+    }
+
+    /// Content type of the dataset
+    pub fn content_type(&self) -> ContentType {
+        self.header.content_type()
+    }
+
+    /// Number of records
+    pub fn num_records(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Number of channels (taken from the first record)
+    pub fn num_channels(&self) -> usize {
+        self.records.first().map_or(0, |r| r.channels.len())
+    }
+
+    /// Number of elements per record and channel (taken from the first record)
+    pub fn elements_per_record(&self) -> usize {
+        self.records
+            .first()
+            .and_then(|r| r.channels.first())
+            .map_or(0, Vec::len)
+    }
+
+    /// Unit of the magnitudes of magnitude spectra and magnitude-phase spectra, `None` for
+    /// other content
+    ///
+    /// Declared by the metadata or detected from the records, see [`MagnitudeUnit`]. The
+    /// records of magnitude-phase spectra hold complex values, so their magnitudes are linear
+    /// unless declared otherwise.
+    pub fn magnitude_unit(&self) -> Option<MagnitudeUnit> {
+        match self.content_type() {
+            ContentType::MagnitudeSpectrum => {
+                Some(MagnitudeUnit::declared(&self.metadata).unwrap_or_else(|| {
+                    MagnitudeUnit::detect(
+                        self.records
+                            .iter()
+                            .flat_map(|r| r.channels.iter().flatten()),
+                    )
+                }))
+            }
+            ContentType::MagnitudePhaseSpectrum => {
+                Some(MagnitudeUnit::declared(&self.metadata).unwrap_or(MagnitudeUnit::Linear))
+            }
+            _ => None,
+        }
+    }
+
+    /// Declare the unit of the magnitudes in the metadata, see [`MagnitudeUnit`]
+    ///
+    /// Only the declaration changes, not the records.
+    pub fn set_magnitude_unit(&mut self, unit: MagnitudeUnit) {
+        self.metadata
+            .retain(|key, _| !key.eq_ignore_ascii_case(MAGNITUDE_UNIT_KEY));
+        self.metadata.insert(
+            MAGNITUDE_UNIT_KEY.to_string(),
+            MetadataValue::String(unit.name().to_string()),
+        );
+    }
+}
+
+/// Record-by-record access to the content of the file opened by a reader
+pub(crate) struct RecordReader<'a> {
+    content: Content<'a>,
+    num_records: usize,
+    num_channels: i32,
+    /// Unit of the stored magnitudes of MPS content, converted to complex values
+    mps_unit: MagnitudeUnit,
+}
+
+enum Content<'a> {
+    Ir(ContentIR<'a>),
+    Ms(ContentMS<'a>),
+    Ps(ContentPS<'a>),
+    Mps(ContentMPS<'a>),
+    Dft(ContentDFT<'a>),
+}
+
+impl<'a> RecordReader<'a> {
+    pub(crate) fn new(reader: &'a Reader) -> Result<Self> {
+        if !reader.is_valid() {
+            return Err(Error::Closed);
+        }
+        let content = match reader.content_type() {
+            ContentType::ImpulseResponse => Content::Ir(reader.content_ir()?),
+            ContentType::MagnitudeSpectrum => Content::Ms(reader.content_ms()?),
+            ContentType::PhaseSpectrum => Content::Ps(reader.content_ps()?),
+            ContentType::MagnitudePhaseSpectrum => Content::Mps(reader.content_mps()?),
+            ContentType::DftSpectrum => Content::Dft(reader.content_dft()?),
+        };
+        let mps_unit = match content {
+            Content::Mps(_) => reader.magnitude_unit()?.unwrap_or(MagnitudeUnit::Linear),
+            _ => MagnitudeUnit::Linear,
+        };
+        Ok(Self {
+            content,
+            num_records: reader.num_records().max(0) as usize,
+            num_channels: reader.num_channels(),
+            mps_unit,
+        })
+    }
+
+    /// Whether the records hold linear values of magnitudes stored in another unit
+    pub(crate) fn converts_magnitudes(&self) -> bool {
+        !self.mps_unit.is_linear()
+    }
+
+    pub(crate) fn num_records(&self) -> usize {
+        self.num_records
+    }
+
+    /// Number of values per channel of every record, counting real and imaginary parts of
+    /// complex spectra
+    #[cfg(feature = "polars")]
+    pub(crate) fn values_per_channel(&self) -> usize {
+        let count = match &self.content {
+            Content::Ir(ir) => ir.filter_length(),
+            Content::Ms(ms) => ms.num_frequencies(),
+            Content::Ps(ps) => ps.num_frequencies(),
+            Content::Mps(mps) => mps.num_frequencies() * 2,
+            Content::Dft(dft) => dft.num_dft_coeffs() * 2,
+        };
+        count.max(0) as usize
+    }
+
+    /// Content header of the file
+    pub(crate) fn header(&self) -> Result<ContentHeader> {
+        Ok(match &self.content {
+            Content::Ir(ir) => ContentHeader::ImpulseResponse {
+                samplerate: ir.samplerate() as f64,
+            },
+            Content::Ms(ms) => ContentHeader::MagnitudeSpectrum {
+                frequencies: ms.frequencies()?,
+            },
+            Content::Ps(ps) => ContentHeader::PhaseSpectrum {
+                frequencies: ps.frequencies()?,
+            },
+            Content::Mps(mps) => ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: mps.frequencies()?,
+            },
+            Content::Dft(dft) => ContentHeader::DftSpectrum {
+                samplerate: dft.samplerate(),
+                transform_size: dft.transform_size().max(0) as usize,
+            },
+        })
+    }
+
+    /// Direction and data of a record, in the layout of [`Record`]
+    pub(crate) fn record(&self, index: usize) -> Result<Record> {
+        let r = index as i32;
+        let channels = 0..self.num_channels;
+        let ((alpha, beta), channels) = match &self.content {
+            Content::Ir(ir) => (
+                ir.record_coords(r)?,
+                channels
+                    .map(|c| ir.filter_coeffs(r, c))
+                    .collect::<Result<_>>()?,
+            ),
+            Content::Ms(ms) => (
+                ms.record_coords(r)?,
+                channels
+                    .map(|c| ms.magnitudes(r, c))
+                    .collect::<Result<_>>()?,
+            ),
+            Content::Ps(ps) => (
+                ps.record_coords(r)?,
+                channels.map(|c| ps.phases(r, c)).collect::<Result<_>>()?,
+            ),
+            Content::Mps(mps) => (
+                mps.record_coords(r)?,
+                channels
+                    .map(|c| {
+                        let (mut magnitudes, phases) = mps.coefficients(r, c)?;
+                        self.mps_unit.to_linear(&mut magnitudes);
+                        Ok(magnitudes
+                            .iter()
+                            .zip(&phases)
+                            .flat_map(|(m, p)| [m * p.cos(), m * p.sin()])
+                            .collect())
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            Content::Dft(dft) => (
+                dft.record_coords(r)?,
+                channels
+                    .map(|c| dft.dft_coeffs(r, c))
+                    .collect::<Result<_>>()?,
+            ),
+        };
+        Ok(Record {
+            alpha: alpha as f32,
+            beta: beta as f32,
+            channels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magnitude_unit() {
+        for unit in [
+            MagnitudeUnit::Linear,
+            MagnitudeUnit::Decibel,
+            MagnitudeUnit::PressureNormalized,
+        ] {
+            assert_eq!(MagnitudeUnit::from_name(unit.name()), Some(unit));
+            assert_eq!(unit.to_string(), unit.name());
+        }
+        assert_eq!(
+            MagnitudeUnit::from_name(" DB "),
+            Some(MagnitudeUnit::Decibel)
+        );
+        assert_eq!(MagnitudeUnit::from_name("Pa"), None);
+
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0, 2000.0],
+            },
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            1,
+            |alpha, _, _| vec![1.0, alpha / 90.0],
+        );
+        assert_eq!(dataset.magnitude_unit(), Some(MagnitudeUnit::Linear));
+        dataset.records[2].channels[0][0] = -3.0;
+        assert_eq!(dataset.magnitude_unit(), Some(MagnitudeUnit::Decibel));
+
+        // Declarations take precedence and are found case-insensitively
+        dataset.metadata.insert(
+            "magnitude_unit".into(),
+            MetadataValue::String("pressure".into()),
+        );
+        assert_eq!(
+            dataset.magnitude_unit(),
+            Some(MagnitudeUnit::PressureNormalized)
+        );
+        dataset.set_magnitude_unit(MagnitudeUnit::Linear);
+        assert_eq!(dataset.metadata.len(), 1);
+        assert_eq!(dataset.magnitude_unit(), Some(MagnitudeUnit::Linear));
+
+        let mut magnitudes = [0.5, 1.0];
+        MagnitudeUnit::Decibel.convert_linear(&mut magnitudes);
+        assert!((magnitudes[0] + 6.0206).abs() < 1e-3);
+        MagnitudeUnit::Decibel.to_linear(&mut magnitudes);
+        assert!((magnitudes[0] - 0.5).abs() < 1e-5);
+
+        dataset.header = ContentHeader::PhaseSpectrum {
+            frequencies: vec![1000.0, 2000.0],
+        };
+        assert_eq!(dataset.magnitude_unit(), None);
+    }
+
+    #[test]
+    fn test_from_fn_follows_grid() {
+        let grid = EquiangularGrid {
+            alpha_points: 4,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points: 3,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        };
+        let dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            grid,
+            2,
+            |alpha, _, c| vec![alpha + c as f32; 8],
+        );
+
+        assert_eq!(dataset.content_type(), ContentType::ImpulseResponse);
+        assert_eq!(dataset.num_records(), grid.num_records());
+        assert_eq!(dataset.num_channels(), 2);
+        assert_eq!(dataset.elements_per_record(), 8);
+        assert_eq!(dataset.records[2].channels[1][0], 91.0);
+    }
+}
@@ -0,0 +1,476 @@
+//! Record-by-record comparison of datasets
+//!
+//! [`diff`] compares a dataset with a reference, e.g. the result of a conversion or a
+//! transcoding round trip with the original, and reports the deviations per record and
+//! channel together with the file properties that differ. [`Tolerances`] decide which
+//! deviations are acceptable.
+
+use std::f64::consts::TAU;
+use std::fmt;
+use std::path::Path;
+
+use crate::grid;
+use crate::{ContentType, Dataset, Error, Quantization, Reader, Result};
+
+/// Acceptable deviations of a compared dataset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances {
+    /// Largest absolute error of any value
+    pub max_abs: f64,
+    /// Largest RMS error of any channel of a record
+    pub rms: f64,
+    /// Largest difference of the record directions in degrees
+    pub angle: f32,
+}
+
+impl Default for Tolerances {
+    /// Exact values; directions may differ by rounding errors
+    fn default() -> Self {
+        Self {
+            max_abs: 0.0,
+            rms: 0.0,
+            angle: 1e-3,
+        }
+    }
+}
+
+impl Tolerances {
+    /// Rounding to the given quantization, with room for ±1 LSB of dither
+    ///
+    /// Integer quantization covers the range [-1, 1], so 16 bit allow an error of
+    /// 1.5 / 32767 (half a step of rounding and one step of dither).
+    pub fn quantization(quantization: Quantization) -> Self {
+        let step = match quantization {
+            Quantization::Int16 => 1.0 / 32767.0,
+            Quantization::Int24 => 1.0 / 8388607.0,
+            Quantization::Float32 => 0.0,
+        };
+        Self {
+            max_abs: 1.5 * step,
+            rms: 1.5 * step,
+            ..Self::default()
+        }
+    }
+}
+
+/// Deviation of one channel of a record
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChannelStats {
+    /// Largest absolute error of any value
+    pub max_abs_error: f64,
+    /// Root mean square of the errors
+    pub rms_error: f64,
+    /// Root mean square of the reference values, for relating the errors to the level
+    pub reference_rms: f64,
+}
+
+impl ChannelStats {
+    fn new(reference: &[f32], other: &[f32], phases: bool) -> Self {
+        let mut stats = Self::default();
+        for (&x, &y) in reference.iter().zip(other) {
+            let mut error = (y as f64 - x as f64).abs();
+            if phases {
+                // Phases that differ by whole turns are equal
+                error = (error - TAU * (error / TAU).round()).abs();
+            }
+            stats.max_abs_error = stats.max_abs_error.max(error);
+            stats.rms_error += error * error;
+            stats.reference_rms += x as f64 * x as f64;
+        }
+        let count = reference.len().max(1) as f64;
+        stats.rms_error = (stats.rms_error / count).sqrt();
+        stats.reference_rms = (stats.reference_rms / count).sqrt();
+        stats
+    }
+
+    fn within(&self, tolerances: &Tolerances) -> bool {
+        self.max_abs_error <= tolerances.max_abs && self.rms_error <= tolerances.rms
+    }
+
+    /// Combine with the statistics of further values; `count` is the number of values each
+    /// side represents
+    fn merge(&self, other: &Self, (count, other_count): (usize, usize)) -> Self {
+        let total = (count + other_count).max(1) as f64;
+        let mean_square =
+            |a: f64, b: f64| ((a * a * count as f64 + b * b * other_count as f64) / total).sqrt();
+        Self {
+            max_abs_error: self.max_abs_error.max(other.max_abs_error),
+            rms_error: mean_square(self.rms_error, other.rms_error),
+            reference_rms: mean_square(self.reference_rms, other.reference_rms),
+        }
+    }
+}
+
+/// Deviations of one record
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordDiff {
+    /// Record index in storage order
+    pub index: usize,
+    /// Alpha angle of the reference record in degrees (data view)
+    pub alpha: f32,
+    /// Beta angle of the reference record in degrees (data view)
+    pub beta: f32,
+    /// Angle between the directions of both records in degrees
+    pub angle_error: f32,
+    /// Deviation per channel
+    pub channels: Vec<ChannelStats>,
+}
+
+/// File property that differs between the datasets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyMismatch {
+    /// Name of the property, e.g. `samplerate`, `grid` or `metadata 'DESCRIPTION'`
+    pub property: String,
+    /// Value of the reference, empty if it is missing
+    pub reference: String,
+    /// Value of the compared dataset, empty if it is missing
+    pub other: String,
+}
+
+impl fmt::Display for PropertyMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: '{}' vs. '{}'",
+            self.property, self.reference, self.other
+        )
+    }
+}
+
+/// Result of comparing two datasets, see [`diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    /// Tolerances the report was created with
+    pub tolerances: Tolerances,
+    /// Header values, grid, orientation and metadata that differ
+    pub properties: Vec<PropertyMismatch>,
+    /// Deviations of every record in storage order
+    pub records: Vec<RecordDiff>,
+    /// Deviations per channel over all records
+    pub channels: Vec<ChannelStats>,
+}
+
+impl DiffReport {
+    /// Whether all properties match and all records are within the tolerances
+    pub fn is_within_tolerance(&self) -> bool {
+        self.properties.is_empty() && self.failing_records().is_empty()
+    }
+
+    /// Records exceeding the tolerances in any channel or in their direction
+    pub fn failing_records(&self) -> Vec<&RecordDiff> {
+        self.records
+            .iter()
+            .filter(|r| {
+                r.angle_error > self.tolerances.angle
+                    || r.channels.iter().any(|c| !c.within(&self.tolerances))
+            })
+            .collect()
+    }
+
+    /// Largest absolute error over all records and channels
+    pub fn max_abs_error(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(|c| c.max_abs_error)
+            .fold(0.0, f64::max)
+    }
+
+    /// RMS error over all records and channels
+    pub fn rms_error(&self) -> f64 {
+        let count = self.channels.len().max(1) as f64;
+        (self
+            .channels
+            .iter()
+            .map(|c| c.rms_error.powi(2))
+            .sum::<f64>()
+            / count)
+            .sqrt()
+    }
+}
+
+/// Compare a dataset with a reference, record by record
+///
+/// Both datasets need the same content type, number of records, channels and elements per
+/// record; otherwise there is nothing to compare value by value and an error is returned.
+/// Records are paired in storage order. Phase spectra compare their phases modulo 2π, all
+/// other content its stored values (real and imaginary parts for DFT content). The
+/// quantization is not compared, since it is expected to change in transcoding round trips;
+/// [`Tolerances::quantization`] accounts for its rounding.
+///
+/// ```no_run
+/// use opendaff::diff::{self, Tolerances};
+/// use opendaff::writer::{self, Dither};
+/// use opendaff::Quantization;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// writer::transcode("hrir.daff", "hrir16.daff", Quantization::Int16, Dither::None)?;
+/// let report = diff::diff_files("hrir.daff", "hrir16.daff", &Tolerances::quantization(Quantization::Int16))?;
+/// assert!(report.is_within_tolerance(), "largest error {}", report.max_abs_error());
+/// # Ok(())
+/// # }
+/// ```
+pub fn diff(reference: &Dataset, other: &Dataset, tolerances: &Tolerances) -> Result<DiffReport> {
+    let shape = |d: &Dataset| {
+        (
+            d.content_type(),
+            d.num_records(),
+            d.num_channels(),
+            d.elements_per_record(),
+        )
+    };
+    if shape(reference) != shape(other) {
+        let describe =
+            |(content, records, channels, elements): (ContentType, usize, usize, usize)| {
+                format!(
+                    "{} with {} records of {} channels x {} elements",
+                    content, records, channels, elements
+                )
+            };
+        return Err(Error::new(format!(
+            "Cannot compare {} with {}",
+            describe(shape(reference)),
+            describe(shape(other))
+        )));
+    }
+
+    let mut properties = Vec::new();
+    let mut compare = |property: String, a: String, b: String| {
+        if a != b {
+            properties.push(PropertyMismatch {
+                property,
+                reference: a,
+                other: b,
+            });
+        }
+    };
+    compare(
+        "header".to_string(),
+        format!("{:?}", reference.header),
+        format!("{:?}", other.header),
+    );
+    compare(
+        "grid".to_string(),
+        reference.grid.to_string(),
+        other.grid.to_string(),
+    );
+    let orientation = |d: &Dataset| {
+        format!(
+            "yaw {}°, pitch {}°, roll {}°",
+            d.orientation.yaw, d.orientation.pitch, d.orientation.roll
+        )
+    };
+    compare(
+        "orientation".to_string(),
+        orientation(reference),
+        orientation(other),
+    );
+    let keys: std::collections::BTreeSet<&String> = reference
+        .metadata
+        .keys()
+        .chain(other.metadata.keys())
+        .collect();
+    for key in keys {
+        let value = |d: &Dataset| d.metadata.get(key).map_or(String::new(), |v| v.to_string());
+        compare(
+            format!("metadata '{}'", key),
+            value(reference),
+            value(other),
+        );
+    }
+
+    let phases = reference.content_type() == ContentType::PhaseSpectrum;
+    let records: Vec<RecordDiff> = reference
+        .records
+        .iter()
+        .zip(&other.records)
+        .enumerate()
+        .map(|(index, (a, b))| RecordDiff {
+            index,
+            alpha: a.alpha,
+            beta: a.beta,
+            angle_error: angle_between((a.alpha, a.beta), (b.alpha, b.beta)),
+            channels: a
+                .channels
+                .iter()
+                .zip(&b.channels)
+                .map(|(x, y)| ChannelStats::new(x, y, phases))
+                .collect(),
+        })
+        .collect();
+
+    let elements = reference.elements_per_record();
+    let channels = (0..reference.num_channels())
+        .map(|channel| {
+            records
+                .iter()
+                .enumerate()
+                .fold(ChannelStats::default(), |total, (i, record)| {
+                    total.merge(&record.channels[channel], (i * elements, elements))
+                })
+        })
+        .collect();
+
+    Ok(DiffReport {
+        tolerances: *tolerances,
+        properties,
+        records,
+        channels,
+    })
+}
+
+/// Load two DAFF files and [`diff`] them
+pub fn diff_files(
+    reference: impl AsRef<Path>,
+    other: impl AsRef<Path>,
+    tolerances: &Tolerances,
+) -> Result<DiffReport> {
+    let load = |path: &Path| {
+        let reader = Reader::open(path)?;
+        Dataset::from_reader(&reader).map_err(|e| match e {
+            Error::Message(m) => Error::new(format!("{}: {}", path.display(), m)),
+            e => e,
+        })
+    };
+    diff(
+        &load(reference.as_ref())?,
+        &load(other.as_ref())?,
+        tolerances,
+    )
+}
+
+/// Great-circle angle between two data view directions in degrees
+fn angle_between((a_alpha, a_beta): (f32, f32), (b_alpha, b_beta): (f32, f32)) -> f32 {
+    if grid::angle_eq(a_alpha, b_alpha) && grid::angle_eq(a_beta, b_beta) {
+        return 0.0;
+    }
+    let vector = |alpha: f32, beta: f32| {
+        let (sa, ca) = (alpha as f64).to_radians().sin_cos();
+        let (sb, cb) = (beta as f64).to_radians().sin_cos();
+        [sb * ca, sb * sa, -cb]
+    };
+    let (a, b) = (vector(a_alpha, a_beta), vector(b_alpha, b_beta));
+    let dot: f64 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+    dot.clamp(-1.0, 1.0).acos().to_degrees() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::writer::{self, Dither};
+    use crate::{ContentHeader, EquiangularGrid, MetadataValue};
+
+    fn dataset(header: ContentHeader) -> Dataset {
+        Dataset::from_fn(
+            header,
+            EquiangularGrid::with_resolution(45.0, 45.0).unwrap(),
+            2,
+            |alpha, beta, channel| {
+                vec![
+                    (alpha / 720.0) - 0.25,
+                    beta / 360.0,
+                    channel as f32 * 0.5 - 0.25,
+                ]
+            },
+        )
+    }
+
+    fn impulse_responses() -> Dataset {
+        dataset(ContentHeader::ImpulseResponse {
+            samplerate: 48000.0,
+        })
+    }
+
+    #[test]
+    fn test_diff_identical_and_modified() {
+        let reference = impulse_responses();
+        let report = diff(&reference, &reference, &Tolerances::default()).unwrap();
+        assert!(report.is_within_tolerance());
+        assert_eq!(report.records.len(), reference.num_records());
+        assert_eq!(report.max_abs_error(), 0.0);
+
+        let mut other = reference.clone();
+        other.records[3].channels[1][2] += 0.01;
+        other.metadata.insert(
+            "DESCRIPTION".to_string(),
+            MetadataValue::String("changed".to_string()),
+        );
+        let report = diff(&reference, &other, &Tolerances::default()).unwrap();
+        assert!(!report.is_within_tolerance());
+        let failing = report.failing_records();
+        assert_eq!(failing.len(), 1);
+        assert_eq!(failing[0].index, 3);
+        assert!((failing[0].channels[1].max_abs_error - 0.01).abs() < 1e-6);
+        assert!((failing[0].channels[1].rms_error - 0.01 / 3f64.sqrt()).abs() < 1e-6);
+        assert_eq!(failing[0].channels[0].max_abs_error, 0.0);
+        assert!((report.channels[1].max_abs_error - 0.01).abs() < 1e-6);
+        let values = (reference.num_records() * 3) as f64;
+        assert!((report.channels[1].rms_error - (1e-4 / values).sqrt()).abs() < 1e-6);
+        assert_eq!(report.channels[0].max_abs_error, 0.0);
+        assert_eq!(report.properties.len(), 1);
+        assert_eq!(report.properties[0].property, "metadata 'DESCRIPTION'");
+        assert_eq!(report.properties[0].reference, "");
+
+        let loose = Tolerances {
+            max_abs: 0.02,
+            rms: 0.02,
+            ..Tolerances::default()
+        };
+        other.metadata.clear();
+        assert!(diff(&reference, &other, &loose)
+            .unwrap()
+            .is_within_tolerance());
+
+        other.records[0].alpha = 10.0;
+        other.records[0].beta = 10.0;
+        let report = diff(&reference, &other, &loose).unwrap();
+        assert!((report.records[0].angle_error - 10.0).abs() < 1e-3);
+        assert!(!report.is_within_tolerance());
+
+        other.records.pop();
+        assert!(diff(&reference, &other, &loose).is_err());
+        let spectra = dataset(ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![100.0, 1000.0, 10000.0],
+        });
+        assert!(diff(&reference, &spectra, &loose).is_err());
+    }
+
+    #[test]
+    fn test_diff_phases_modulo_turns() {
+        let frequencies = vec![100.0, 1000.0, 10000.0];
+        let reference = dataset(ContentHeader::PhaseSpectrum { frequencies });
+        let mut other = reference.clone();
+        other.records[2].channels[0][1] += std::f32::consts::TAU;
+        let report = diff(
+            &reference,
+            &other,
+            &Tolerances::quantization(Quantization::Int24),
+        )
+        .unwrap();
+        assert!(report.max_abs_error() < 1e-6);
+        assert!(report.is_within_tolerance());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_diff_files_transcoding_round_trip() {
+        let original = temp_path("diff_original.daff");
+        let transcoded = temp_path("diff_int16.daff");
+        writer::write_dataset(&original, &impulse_responses()).unwrap();
+        for dither in [Dither::None, Dither::Tpdf { seed: 7 }] {
+            writer::transcode(&original, &transcoded, Quantization::Int16, dither).unwrap();
+            let report = diff_files(
+                &original,
+                &transcoded,
+                &Tolerances::quantization(Quantization::Int16),
+            )
+            .unwrap();
+            assert!(report.is_within_tolerance(), "{:?}", report.properties);
+            assert!(report.max_abs_error() > 0.0);
+            let exact = diff_files(&original, &transcoded, &Tolerances::default()).unwrap();
+            assert!(!exact.is_within_tolerance());
+        }
+        std::fs::remove_file(original).unwrap();
+        std::fs::remove_file(transcoded).unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+//! Fast per-band gain lookups for geometrical acoustics
+//!
+//! Ray and beam tracers query the directivity of a source for every emitted ray and
+//! frequency band. A [`DirectivityTable`] copies the magnitudes of one channel of a magnitude
+//! spectrum into dense per-band tables, so that each lookup is a bilinear interpolation over
+//! four table entries without allocations, I/O or calls into the C++ library.
+
+use crate::grid::{self, EquiangularGrid};
+use crate::writer::{put_f32, put_u64, Input};
+use crate::{ContentHeader, Dataset, Error, Orientation, Result};
+
+/// Per-band gains of a magnitude spectrum on its equiangular grid
+///
+/// The gains are linear magnitudes as stored in the file. Lookups interpolate bilinearly
+/// between the neighbouring grid points in alpha and beta; outside of partial grids the
+/// nearest border value is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectivityTable {
+    frequencies: Vec<f32>,
+    /// `None` for the identity orientation, which needs no rotation
+    orientation: Option<Orientation>,
+    alpha_start: f32,
+    alpha_span: f32,
+    alpha_points: usize,
+    full_circle: bool,
+    /// Inverse alpha resolution, 0 for a single alpha point
+    alpha_scale: f32,
+    beta_start: f32,
+    beta_points: usize,
+    /// Inverse beta resolution, 0 for a single ring
+    beta_scale: f32,
+    /// Gains per band, ring and alpha point; pole records are repeated along their ring
+    gains: Vec<f32>,
+}
+
+impl DirectivityTable {
+    /// Build the table for one channel of a magnitude spectrum dataset
+    pub fn from_dataset(dataset: &Dataset, channel: usize) -> Result<Self> {
+        let ContentHeader::MagnitudeSpectrum { frequencies } = &dataset.header else {
+            return Err(Error::new(format!(
+                "Directivity tables require a magnitude spectrum, not {}",
+                dataset.content_type()
+            )));
+        };
+        let grid = dataset
+            .grid
+            .equiangular()
+            .ok_or_else(|| Error::new("Directivity tables require an equiangular grid"))?;
+        if channel >= dataset.num_channels() {
+            return Err(Error::new(format!(
+                "Channel {} out of range (dataset has {} channels)",
+                channel,
+                dataset.num_channels()
+            )));
+        }
+        Self::new(
+            grid,
+            dataset.orientation,
+            frequencies.clone(),
+            dataset
+                .records
+                .iter()
+                .map(|r| r.channels[channel].as_slice()),
+        )
+    }
+
+    /// Build the table from the magnitudes of all records in storage order
+    pub(crate) fn new<'a>(
+        grid: &EquiangularGrid,
+        orientation: Orientation,
+        frequencies: Vec<f32>,
+        records: impl IntoIterator<Item = &'a [f32]>,
+    ) -> Result<Self> {
+        if grid.alpha_points == 0 || grid.beta_points == 0 {
+            return Err(Error::new("Directivity tables require a non-empty grid"));
+        }
+        let records: Vec<&[f32]> = records.into_iter().collect();
+        if records.len() != grid.num_records() {
+            return Err(Error::new(format!(
+                "Expected {} records, got {}",
+                grid.num_records(),
+                records.len()
+            )));
+        }
+        let num_bands = frequencies.len();
+        if let Some(record) = records.iter().find(|r| r.len() != num_bands) {
+            return Err(Error::new(format!(
+                "Expected {} magnitudes per record, got {}",
+                num_bands,
+                record.len()
+            )));
+        }
+
+        let (alpha_points, beta_points) = (grid.alpha_points, grid.beta_points);
+        let south_pole = grid.has_south_pole();
+        let north_pole = grid.has_north_pole();
+        let record_index = |ring: usize, alpha: usize| {
+            if south_pole && ring == 0 {
+                0
+            } else if north_pole && ring + 1 == beta_points {
+                records.len() - 1
+            } else {
+                south_pole as usize + (ring - south_pole as usize) * alpha_points + alpha
+            }
+        };
+        let ring_size = alpha_points;
+        let band_size = beta_points * ring_size;
+        let mut gains = vec![0.0; num_bands * band_size];
+        for ring in 0..beta_points {
+            for alpha in 0..alpha_points {
+                let record = records[record_index(ring, alpha)];
+                for (band, &gain) in record.iter().enumerate() {
+                    gains[band * band_size + ring * ring_size + alpha] = gain;
+                }
+            }
+        }
+
+        let inverse = |resolution: f32| {
+            if resolution > 0.0 {
+                1.0 / resolution
+            } else {
+                0.0
+            }
+        };
+        Ok(Self {
+            frequencies,
+            orientation: (orientation != Orientation::default()).then_some(orientation),
+            alpha_start: grid.alpha_start,
+            alpha_span: grid.alpha_span(),
+            alpha_points,
+            full_circle: grid.alpha_span() == 360.0,
+            alpha_scale: inverse(grid.alpha_resolution()),
+            beta_start: grid.beta_start,
+            beta_points,
+            beta_scale: inverse(grid.beta_resolution()),
+            gains,
+        })
+    }
+
+    /// Serialize the table (little endian), see [`LookupIndex`](crate::index::LookupIndex)
+    pub(crate) fn encode(&self, bytes: &mut Vec<u8>) {
+        put_u64(bytes, self.frequencies.len() as u64);
+        self.frequencies.iter().for_each(|&f| put_f32(bytes, f));
+        let orientation = self.orientation.unwrap_or_default();
+        for angle in [orientation.yaw, orientation.pitch, orientation.roll] {
+            put_f32(bytes, angle);
+        }
+        put_f32(bytes, self.alpha_start);
+        put_f32(bytes, self.alpha_span);
+        put_u64(bytes, self.alpha_points as u64);
+        put_f32(bytes, self.alpha_scale);
+        put_f32(bytes, self.beta_start);
+        put_u64(bytes, self.beta_points as u64);
+        put_f32(bytes, self.beta_scale);
+        put_u64(bytes, self.gains.len() as u64);
+        self.gains.iter().for_each(|&g| put_f32(bytes, g));
+    }
+
+    /// Restore a table written by [`encode`](DirectivityTable::encode)
+    pub(crate) fn decode(input: &mut Input) -> Result<Self> {
+        let count = |value: u64| {
+            usize::try_from(value).map_err(|_| Error::new("Invalid directivity table size"))
+        };
+        let frequencies = input.f32s()?;
+        let orientation = Orientation {
+            yaw: input.f32()?,
+            pitch: input.f32()?,
+            roll: input.f32()?,
+        };
+        let alpha_start = input.f32()?;
+        let alpha_span = input.f32()?;
+        let alpha_points = count(input.u64()?)?;
+        let alpha_scale = input.f32()?;
+        let beta_start = input.f32()?;
+        let beta_points = count(input.u64()?)?;
+        let beta_scale = input.f32()?;
+        let gains = input.f32s()?;
+        if alpha_points == 0
+            || beta_points == 0
+            || Some(gains.len())
+                != frequencies
+                    .len()
+                    .checked_mul(alpha_points)
+                    .and_then(|n| n.checked_mul(beta_points))
+        {
+            return Err(Error::new("Inconsistent directivity table"));
+        }
+        Ok(Self {
+            frequencies,
+            orientation: (orientation != Orientation::default()).then_some(orientation),
+            alpha_start,
+            alpha_span,
+            alpha_points,
+            full_circle: alpha_span == 360.0,
+            alpha_scale,
+            beta_start,
+            beta_points,
+            beta_scale,
+            gains,
+        })
+    }
+
+    /// Support frequencies of the bands in Hz
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    /// Number of frequency bands
+    pub fn num_bands(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    /// Interpolated gain of a band towards an object view direction (azimuth `phi`,
+    /// elevation `theta` in degrees)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `band` is not less than [`num_bands`](DirectivityTable::num_bands).
+    #[inline]
+    pub fn band_gain(&self, phi: f32, theta: f32, band: usize) -> f32 {
+        assert!(
+            band < self.num_bands(),
+            "band {} out of range ({} bands)",
+            band,
+            self.num_bands()
+        );
+        let (alpha, beta) = match &self.orientation {
+            None => (phi, theta + 90.0),
+            Some(orientation) => grid::to_data_view(orientation, phi, theta),
+        };
+        let ring_size = self.alpha_points;
+        let table =
+            &self.gains[band * self.beta_points * ring_size..][..self.beta_points * ring_size];
+
+        let b =
+            ((beta - self.beta_start) * self.beta_scale).clamp(0.0, (self.beta_points - 1) as f32);
+        let b0 = b as usize;
+        let b1 = (b0 + 1).min(self.beta_points - 1);
+        let wb = b - b0 as f32;
+
+        let offset = (alpha - self.alpha_start).rem_euclid(360.0);
+        let (a0, a1, wa) = if self.full_circle {
+            let a = offset * self.alpha_scale;
+            let a0 = a as usize % ring_size;
+            (a0, (a0 + 1) % ring_size, a.fract())
+        } else {
+            // Beyond the covered range, snap to the closer end
+            let offset = if offset <= self.alpha_span {
+                offset
+            } else if offset - self.alpha_span < 360.0 - offset {
+                self.alpha_span
+            } else {
+                0.0
+            };
+            let a = (offset * self.alpha_scale).min((ring_size - 1) as f32);
+            let a0 = a as usize;
+            (a0, (a0 + 1).min(ring_size - 1), a - a0 as f32)
+        };
+
+        let lower = table[b0 * ring_size + a0] * (1.0 - wa) + table[b0 * ring_size + a1] * wa;
+        let upper = table[b1 * ring_size + a0] * (1.0 - wa) + table[b1 * ring_size + a1] * wa;
+        lower * (1.0 - wb) + upper * wb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn dataset(grid: EquiangularGrid) -> Dataset {
+        // Band 0 grows with alpha, band 1 with beta
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            grid,
+            1,
+            |alpha, beta, _| vec![alpha / 360.0, beta / 180.0],
+        )
+    }
+
+    #[test]
+    fn test_band_gain_interpolates() {
+        let table = DirectivityTable::from_dataset(
+            &dataset(EquiangularGrid::with_resolution(30.0, 30.0).unwrap()),
+            0,
+        )
+        .unwrap();
+        assert_eq!(table.num_bands(), 2);
+        assert_eq!(table.frequencies(), [500.0, 1000.0]);
+
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        assert!(close(table.band_gain(60.0, 0.0, 0), 60.0 / 360.0));
+        assert!(close(table.band_gain(45.0, 10.0, 0), 45.0 / 360.0));
+        assert!(close(table.band_gain(-30.0, 0.0, 0), 330.0 / 360.0));
+        // Between the last alpha point and the first one
+        assert!(close(table.band_gain(345.0, 0.0, 0), 0.5 * 330.0 / 360.0));
+        assert!(close(table.band_gain(0.0, -45.0, 1), 0.25));
+        // The poles are single records
+        assert!(close(table.band_gain(123.0, 90.0, 1), 1.0));
+        assert!(close(table.band_gain(17.0, -90.0, 0), 0.0));
+    }
+
+    #[test]
+    fn test_band_gain_partial_grid_and_orientation() {
+        let grid = EquiangularGrid {
+            alpha_points: 3,
+            alpha_start: 300.0,
+            alpha_end: 60.0,
+            beta_points: 1,
+            beta_start: 90.0,
+            beta_end: 90.0,
+        };
+        let table = DirectivityTable::from_dataset(&dataset(grid), 0).unwrap();
+        // Halfway between the points at 300° and 0°
+        assert!((table.band_gain(-30.0, 0.0, 0) - 150.0 / 360.0).abs() < 1e-5);
+        // Outside the range the closer border is used
+        assert_eq!(table.band_gain(90.0, 0.0, 0), 60.0 / 360.0);
+        assert_eq!(table.band_gain(250.0, 20.0, 0), 300.0 / 360.0);
+
+        let mut rotated = dataset(EquiangularGrid::with_resolution(10.0, 10.0).unwrap());
+        rotated.orientation.yaw = 90.0;
+        let table = DirectivityTable::from_dataset(&rotated, 0).unwrap();
+        let (alpha, _) = grid::to_data_view(&rotated.orientation, 30.0, 0.0);
+        assert!((table.band_gain(30.0, 0.0, 0) - alpha / 360.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_from_dataset_errors() {
+        let dataset = dataset(EquiangularGrid::with_resolution(30.0, 30.0).unwrap());
+        assert!(DirectivityTable::from_dataset(&dataset, 1).is_err());
+        let phases = Dataset {
+            header: ContentHeader::PhaseSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            ..dataset
+        };
+        assert!(DirectivityTable::from_dataset(&phases, 0).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_content_ms_band_gain() {
+        let path = temp_path("directivity.daff");
+        let dataset = dataset(EquiangularGrid::with_resolution(30.0, 30.0).unwrap());
+        let records: Vec<_> = dataset.records.iter().map(|r| r.channels.clone()).collect();
+        crate::MsWriterBuilder::new(*dataset.grid.equiangular().unwrap(), 1, vec![500.0, 1000.0])
+            .write(&path, &records)
+            .unwrap();
+
+        let reader = crate::Reader::open(&path).unwrap();
+        let content = reader.content_ms().unwrap();
+        let table = DirectivityTable::from_dataset(&dataset, 0).unwrap();
+        assert_eq!(content.directivity_table(0).unwrap(), table);
+        for (phi, theta) in [(0.0, 0.0), (75.0, -20.0), (200.0, 60.0)] {
+            let gain = content.band_gain(phi, theta, 1).unwrap();
+            assert_eq!(gain, table.band_gain(phi as f32, theta as f32, 1));
+        }
+        assert!(content.band_gain(0.0, 0.0, 2).is_err());
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_band_out_of_range() {
+        let dataset = dataset(EquiangularGrid::with_resolution(30.0, 30.0).unwrap());
+        DirectivityTable::from_dataset(&dataset, 0)
+            .unwrap()
+            .band_gain(0.0, 0.0, 2);
+    }
+}
@@ -0,0 +1,1260 @@
+//! Signal processing on owned datasets
+//!
+//! The functions in this module operate in place on a [`Dataset`] and keep all records and
+//! channels consistent with each other.
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::grid;
+use crate::metadata::{self, Metadata};
+use crate::render;
+use crate::writer;
+use crate::{ContentHeader, ContentType, Dataset, Error, Quantization, Reader, Result};
+
+/// Number of zero crossings on each side of the resampling kernel
+const RESAMPLE_ZERO_CROSSINGS: usize = 16;
+
+/// Relative kernel weight below which neighbours are ignored by spatial smoothing
+const SMOOTHING_CUTOFF: f64 = 1e-6;
+
+/// Alignment of impulse responses when changing their length
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Alignment {
+    /// Keep the start of the responses, zero-pad or truncate at the end
+    #[default]
+    Start,
+    /// Shift all responses by a common offset so that the earliest peak of the dataset lands
+    /// at sample index `lead`, then zero-pad or truncate at the end
+    ///
+    /// The offset is shared by all records and channels, so interaural and inter-direction
+    /// delays are preserved.
+    Peak {
+        /// Number of samples in front of the earliest peak
+        lead: usize,
+    },
+}
+
+/// Bring all impulse responses of a dataset to the same filter length
+///
+/// Shorter responses are zero-padded, longer ones are truncated. Only impulse response
+/// content is supported.
+pub fn pad_to(dataset: &mut Dataset, length: usize, alignment: Alignment) -> Result<()> {
+    if dataset.content_type() != ContentType::ImpulseResponse {
+        return Err(Error::new(
+            "Zero-padding is only supported for impulse responses",
+        ));
+    }
+    if length == 0 {
+        return Err(Error::new("Filter length must be greater than zero"));
+    }
+
+    // Positive shift moves samples towards later times
+    let shift = match alignment {
+        Alignment::Start => 0,
+        Alignment::Peak { lead } => match earliest_peak(dataset) {
+            Some(peak) => lead as isize - peak as isize,
+            None => 0,
+        },
+    };
+
+    for channel in dataset
+        .records
+        .iter_mut()
+        .flat_map(|record| record.channels.iter_mut())
+    {
+        *channel = shifted(channel, shift, length);
+    }
+
+    Ok(())
+}
+
+/// Apply a time window to all impulse responses, with a length chosen per direction
+///
+/// `length` receives the object view direction of a record (azimuth and elevation in
+/// degrees) and the channel index and returns the window length in samples. Samples from
+/// that length on are set to zero, and the last `fade` samples before it are faded out with a
+/// half Hann window, so reflections of the measurement setup are removed without truncation
+/// artifacts. Windows shorter than `fade` are faded over their whole length; the filter
+/// length of the dataset does not change. Only impulse response content is supported.
+///
+/// Measured HRIRs usually need a shorter window where the ear lies in the shadow of the head,
+/// since the direct sound is weak there and reflections dominate sooner:
+///
+/// ```
+/// use opendaff::{dsp, ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// # let mut hrir = Dataset::from_fn(
+/// #     ContentHeader::ImpulseResponse { samplerate: 48000.0 },
+/// #     EquiangularGrid::with_resolution(30.0, 30.0)?,
+/// #     2,
+/// #     |_, _, _| vec![1.0; 512],
+/// # );
+/// // Channel 0 is the left ear, which is shadowed for sources on the right (negative azimuth)
+/// dsp::window(&mut hrir, 32, |azimuth, _, channel| {
+///     let contralateral = if channel == 0 { -azimuth } else { azimuth };
+///     if contralateral.to_radians().sin() > 0.5 { 192 } else { 384 }
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn window<F>(dataset: &mut Dataset, fade: usize, mut length: F) -> Result<()>
+where
+    F: FnMut(f32, f32, usize) -> usize,
+{
+    if dataset.content_type() != ContentType::ImpulseResponse {
+        return Err(Error::new(
+            "Windowing is only supported for impulse responses",
+        ));
+    }
+
+    for record in &mut dataset.records {
+        let (azimuth, elevation) =
+            grid::to_object_view(&dataset.orientation, record.alpha, record.beta);
+        for (channel, samples) in record.channels.iter_mut().enumerate() {
+            let end = length(azimuth, elevation, channel).min(samples.len());
+            let fade = fade.min(end);
+            for (i, sample) in samples[end - fade..end].iter_mut().enumerate() {
+                // Half Hann window falling from (almost) 1 to (almost) 0
+                let phase = PI * (i + 1) as f64 / (fade + 1) as f64;
+                *sample *= (0.5 + 0.5 * phase.cos()) as f32;
+            }
+            samples[end..].fill(0.0);
+        }
+    }
+    Ok(())
+}
+
+/// Resample all impulse responses of a dataset to a new sampling rate
+///
+/// Uses band-limited interpolation with a Blackman-windowed sinc kernel. When downsampling,
+/// the kernel also acts as anti-aliasing low-pass at the new Nyquist frequency. The filter
+/// length is scaled by the rate ratio (rounded up). Only impulse response content is
+/// supported.
+pub fn resample(dataset: &mut Dataset, samplerate: f64) -> Result<()> {
+    let ContentHeader::ImpulseResponse {
+        samplerate: ref mut current,
+    } = dataset.header
+    else {
+        return Err(Error::new(
+            "Resampling is only supported for impulse responses",
+        ));
+    };
+    if !(samplerate.is_finite() && samplerate > 0.0) {
+        return Err(Error::new(format!("Invalid sampling rate {}", samplerate)));
+    }
+    let ratio = samplerate / *current;
+    *current = samplerate;
+    if ratio == 1.0 {
+        return Ok(());
+    }
+
+    for channel in dataset
+        .records
+        .iter_mut()
+        .flat_map(|record| record.channels.iter_mut())
+    {
+        *channel = resampled(channel, ratio);
+    }
+    Ok(())
+}
+
+/// Scale a dataset so that its largest absolute value reaches `peak_db` (dB re 1)
+///
+/// A single gain is applied to all records and channels, so directional and interaural level
+/// differences are preserved. Impulse responses are scaled by their sample peak, spectra by
+/// their largest magnitude. Returns the applied linear gain (1 for all-zero datasets). Phase
+/// spectra cannot be normalized.
+pub fn normalize(dataset: &mut Dataset, peak_db: f32) -> Result<f32> {
+    let complex = match dataset.header {
+        ContentHeader::ImpulseResponse { .. } | ContentHeader::MagnitudeSpectrum { .. } => false,
+        ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => true,
+        ContentHeader::PhaseSpectrum { .. } => {
+            return Err(Error::new("Phase spectra cannot be normalized"))
+        }
+    };
+    if !peak_db.is_finite() {
+        return Err(Error::new(format!("Invalid peak level {} dB", peak_db)));
+    }
+
+    let channels = || {
+        dataset
+            .records
+            .iter()
+            .flat_map(|record| record.channels.iter())
+    };
+    let peak = if complex {
+        channels()
+            .flat_map(|c| c.chunks(2).map(|v| v[0].hypot(v[1])))
+            .fold(0.0f32, f32::max)
+    } else {
+        channels().flatten().fold(0.0f32, |a, x| a.max(x.abs()))
+    };
+    if peak == 0.0 {
+        return Ok(1.0);
+    }
+
+    let gain = 10f32.powf(peak_db / 20.0) / peak;
+    for value in dataset
+        .records
+        .iter_mut()
+        .flat_map(|record| record.channels.iter_mut())
+        .flatten()
+    {
+        *value *= gain;
+    }
+    Ok(gain)
+}
+
+/// Normalize all records relative to the record closest to a reference direction (azimuth
+/// and elevation in degrees, object view), e.g. on-axis at 0°/0°
+///
+/// Simulation engines expect source directivities relative to the on-axis response, while
+/// measurements carry the absolute response of the source in every direction. Every channel
+/// is divided by the same channel of the reference record: magnitude spectra per frequency
+/// (levels in dB are subtracted instead, see [`MagnitudeUnit`](crate::MagnitudeUnit)),
+/// magnitude-phase and DFT spectra as complex values, which also removes the phase of the
+/// reference, and phases of phase spectra are subtracted and wrapped to ±π. The reference
+/// record becomes flat; values where the reference is zero are set to zero. Returns the index
+/// of the reference record.
+///
+/// Impulse responses are not supported; convert them with [`to_magnitude_spectrum`] first.
+pub fn normalize_to_direction(
+    dataset: &mut Dataset,
+    azimuth: f32,
+    elevation: f32,
+) -> Result<usize> {
+    if dataset.content_type() == ContentType::ImpulseResponse {
+        return Err(Error::new(
+            "Impulse responses cannot be normalized to a direction, convert them to spectra first",
+        ));
+    }
+    if !(azimuth.is_finite() && elevation.is_finite()) {
+        return Err(Error::new(format!(
+            "Invalid reference direction {}°/{}°",
+            azimuth, elevation
+        )));
+    }
+    let (alpha, beta) = grid::to_data_view(&dataset.orientation, azimuth, elevation);
+    let target = unit_vector(alpha, beta);
+    let reference_index = dataset
+        .records
+        .iter()
+        .map(|r| {
+            let v = unit_vector(r.alpha, r.beta);
+            v[0] * target[0] + v[1] * target[1] + v[2] * target[2]
+        })
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+        .ok_or_else(|| Error::new("The dataset has no records"))?;
+
+    let decibels = dataset.magnitude_unit() == Some(crate::MagnitudeUnit::Decibel);
+    let reference = dataset.records[reference_index].channels.clone();
+    for record in &mut dataset.records {
+        for (data, reference) in record.channels.iter_mut().zip(&reference) {
+            match dataset.header {
+                ContentHeader::MagnitudeSpectrum { .. } if decibels => {
+                    for (x, r) in data.iter_mut().zip(reference) {
+                        *x -= r;
+                    }
+                }
+                ContentHeader::MagnitudeSpectrum { .. } => {
+                    for (x, &r) in data.iter_mut().zip(reference) {
+                        *x = if r != 0.0 { *x / r } else { 0.0 };
+                    }
+                }
+                ContentHeader::PhaseSpectrum { .. } => {
+                    for (x, r) in data.iter_mut().zip(reference) {
+                        let difference = (*x - r) as f64;
+                        *x = (difference - 2.0 * PI * (difference / (2.0 * PI)).round()) as f32;
+                    }
+                }
+                _ => {
+                    for (x, r) in data.chunks_exact_mut(2).zip(reference.chunks_exact(2)) {
+                        let (re, im) = (x[0] as f64, x[1] as f64);
+                        let (r_re, r_im) = (r[0] as f64, r[1] as f64);
+                        let energy = r_re * r_re + r_im * r_im;
+                        if energy > 0.0 {
+                            x[0] = ((re * r_re + im * r_im) / energy) as f32;
+                            x[1] = ((im * r_re - re * r_im) / energy) as f32;
+                        } else {
+                            x.fill(0.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(reference_index)
+}
+
+/// Set subnormal values of all records to zero, returning how many were flushed
+///
+/// Measured impulse responses often decay into subnormal numbers, which slow down every
+/// convolution that touches them. They are far below any audible level, so flushing them
+/// once before rendering changes nothing but the CPU load. See also
+/// [`render::flush_denormals`](crate::render::flush_denormals).
+pub fn flush_denormals(dataset: &mut Dataset) -> usize {
+    dataset
+        .records
+        .iter_mut()
+        .flat_map(|record| record.channels.iter_mut())
+        .map(|channel| render::flush_denormals(channel))
+        .sum()
+}
+
+/// Keep only the given channels of a dataset, in the given order
+///
+/// E.g. `&[0]` extracts the left ear of a binaural dataset, `&[1, 0]` swaps the ears.
+/// Channel labels move along with their channels. Write the result to a new file with
+/// [`writer::write_dataset`](crate::writer::write_dataset).
+pub fn select_channels(dataset: &mut Dataset, channels: &[usize]) -> Result<()> {
+    if channels.is_empty() {
+        return Err(Error::new("At least one channel has to be selected"));
+    }
+    let num_channels = dataset.num_channels();
+    if let Some(&channel) = channels.iter().find(|&&c| c >= num_channels) {
+        return Err(Error::new(format!(
+            "Cannot select channel {} of {} channels",
+            channel, num_channels
+        )));
+    }
+
+    for record in &mut dataset.records {
+        record.channels = channels
+            .iter()
+            .map(|&c| record.channels[c].clone())
+            .collect();
+    }
+    relabel_channels(&mut dataset.metadata, num_channels, |new| {
+        channels.get(new).copied()
+    });
+    Ok(())
+}
+
+/// Mix all channels of a dataset down to a single channel
+///
+/// Every channel is weighted with its (linear) gain from `gains`, e.g. `&[0.5, 0.5]` for the
+/// average of both ears. Impulse responses and complex spectra (MPS and DFT) are mixed
+/// sample by sample; magnitude and phase spectra cannot be summed without their phase or
+/// magnitude. Channel labels are removed.
+pub fn mixdown(dataset: &mut Dataset, gains: &[f32]) -> Result<()> {
+    if matches!(
+        dataset.content_type(),
+        ContentType::MagnitudeSpectrum | ContentType::PhaseSpectrum
+    ) {
+        return Err(Error::new(
+            "Magnitude and phase spectra cannot be mixed linearly",
+        ));
+    }
+    let num_channels = dataset.num_channels();
+    if gains.len() != num_channels {
+        return Err(Error::new(format!(
+            "Expected {} channel gains, got {}",
+            num_channels,
+            gains.len()
+        )));
+    }
+    if gains.iter().any(|g| !g.is_finite()) {
+        return Err(Error::new("Channel gains must be finite"));
+    }
+
+    let elements = dataset.elements_per_record();
+    for record in &mut dataset.records {
+        let mut mix = vec![0.0f32; elements];
+        for (channel, &gain) in record.channels.iter().zip(gains) {
+            for (m, &x) in mix.iter_mut().zip(channel) {
+                *m += gain * x;
+            }
+        }
+        record.channels = vec![mix];
+    }
+    relabel_channels(&mut dataset.metadata, num_channels, |_| None);
+    Ok(())
+}
+
+/// Move the channel labels to new channel numbers, `source` maps a new channel to the old one
+fn relabel_channels<F>(metadata: &mut Metadata, num_channels: usize, source: F)
+where
+    F: Fn(usize) -> Option<usize>,
+{
+    let labels: Vec<_> = (0..num_channels)
+        .map(|c| metadata.remove(&metadata::channel_label_key(c)))
+        .collect();
+    for new in 0.. {
+        let Some(old) = source(new) else {
+            break;
+        };
+        if let Some(label) = &labels[old] {
+            metadata.insert(metadata::channel_label_key(new), label.clone());
+        }
+    }
+}
+
+/// Smooth the directivity balloon of a spectral dataset over the sphere
+///
+/// Every record is replaced by the solid-angle weighted average of its neighbours under a
+/// spherical Gaussian kernel (von Mises-Fisher) with a width of `kernel_angle` degrees.
+/// Magnitude spectra are averaged in the power domain, complex spectra (MPS and DFT) as
+/// complex values. Afterwards each channel and frequency is rescaled so that the total
+/// radiated power `Σ wᵢ·|xᵢ|²` stays unchanged, which regularizes noisy measurements without
+/// altering the sound power of the source. A kernel angle of zero leaves the dataset as is.
+///
+/// Requires a grid with [quadrature weights](grid::quadrature_weights). The cost grows with
+/// the square of the number of records.
+pub fn spatial_smooth(dataset: &mut Dataset, kernel_angle: f32) -> Result<()> {
+    let complex = match dataset.header {
+        ContentHeader::MagnitudeSpectrum { .. } => false,
+        ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => true,
+        _ => {
+            return Err(Error::new(
+                "Spatial smoothing requires magnitude, magnitude-phase or DFT spectra",
+            ))
+        }
+    };
+    if !(kernel_angle.is_finite() && kernel_angle >= 0.0) {
+        return Err(Error::new(format!(
+            "Invalid smoothing kernel angle {}",
+            kernel_angle
+        )));
+    }
+    if kernel_angle == 0.0 || dataset.records.is_empty() {
+        return Ok(());
+    }
+    let weights = grid::quadrature_weights(dataset)?;
+
+    // exp(κ·(cos d - 1)) falls off like a Gaussian with standard deviation 1/√κ
+    let concentration = 1.0 / (kernel_angle as f64).to_radians().powi(2);
+    let min_cos = 1.0 + SMOOTHING_CUTOFF.ln() / concentration;
+    let vectors: Vec<[f64; 3]> = dataset
+        .records
+        .iter()
+        .map(|r| unit_vector(r.alpha, r.beta))
+        .collect();
+    let kernels: Vec<Vec<(usize, f64)>> = vectors
+        .iter()
+        .map(|a| {
+            let mut kernel: Vec<(usize, f64)> = vectors
+                .iter()
+                .zip(&weights)
+                .enumerate()
+                .filter_map(|(j, (b, &w))| {
+                    let cos = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+                    (cos >= min_cos).then(|| (j, w * (concentration * (cos - 1.0)).exp()))
+                })
+                .collect();
+            let sum: f64 = kernel.iter().map(|&(_, k)| k).sum();
+            for (_, k) in &mut kernel {
+                *k /= sum;
+            }
+            kernel
+        })
+        .collect();
+
+    let num_channels = dataset.num_channels();
+    let elements = dataset.elements_per_record();
+    let bins = if complex { elements / 2 } else { elements };
+    for channel in 0..num_channels {
+        // Power-domain values for magnitudes, interleaved complex values otherwise
+        let values: Vec<Vec<f64>> = dataset
+            .records
+            .iter()
+            .map(|r| {
+                let data = r.channels[channel].iter().map(|&x| x as f64);
+                if complex {
+                    data.collect()
+                } else {
+                    data.map(|x| x * x).collect()
+                }
+            })
+            .collect();
+        let power = |v: &[f64], bin: usize| {
+            if complex {
+                v[2 * bin].powi(2) + v[2 * bin + 1].powi(2)
+            } else {
+                v[bin]
+            }
+        };
+        let total_power = |values: &[Vec<f64>], bin: usize| -> f64 {
+            values
+                .iter()
+                .zip(&weights)
+                .map(|(v, w)| w * power(v, bin))
+                .sum()
+        };
+
+        let smoothed: Vec<Vec<f64>> = kernels
+            .iter()
+            .map(|kernel| {
+                let mut sum = vec![0.0f64; elements];
+                for &(j, k) in kernel {
+                    for (s, x) in sum.iter_mut().zip(&values[j]) {
+                        *s += k * x;
+                    }
+                }
+                sum
+            })
+            .collect();
+        let gains: Vec<f64> = (0..bins)
+            .map(|bin| {
+                let after = total_power(&smoothed, bin);
+                if after > 0.0 {
+                    total_power(&values, bin) / after
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        for (record, values) in dataset.records.iter_mut().zip(&smoothed) {
+            let data = &mut record.channels[channel];
+            if complex {
+                for (i, (x, v)) in data.iter_mut().zip(values).enumerate() {
+                    *x = (v * gains[i / 2].sqrt()) as f32;
+                }
+            } else {
+                for ((x, v), g) in data.iter_mut().zip(values).zip(&gains) {
+                    *x = (v * g).sqrt() as f32;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cartesian unit vector of a data view direction
+fn unit_vector(alpha: f32, beta: f32) -> [f64; 3] {
+    let (alpha, beta) = ((alpha as f64).to_radians(), (beta as f64).to_radians());
+    [
+        beta.sin() * alpha.cos(),
+        beta.sin() * alpha.sin(),
+        -beta.cos(),
+    ]
+}
+
+/// Nominal octave band centre frequencies in Hz (IEC 61260)
+const OCTAVE_BANDS: [f32; 10] = [
+    31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Nominal third-octave band centre frequencies in Hz (IEC 61260)
+const THIRD_OCTAVE_BANDS: [f32; 31] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0,
+    500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0,
+    8000.0, 10000.0, 12500.0, 16000.0, 20000.0,
+];
+
+/// Support frequencies of the magnitude spectra computed by [`to_magnitude_spectrum`]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FrequencySupport {
+    /// Octave bands from 31.5 Hz to 16 kHz
+    Octave,
+    /// Third-octave bands from 20 Hz to 20 kHz
+    #[default]
+    ThirdOctave,
+    /// Band centre frequencies in Hz, strictly increasing
+    Custom(Vec<f32>),
+}
+
+impl FrequencySupport {
+    /// Band centre frequencies below the Nyquist frequency of the given sampling rate
+    ///
+    /// Standard bands above the Nyquist frequency are left out, custom bands must all lie
+    /// below it.
+    pub fn frequencies(&self, samplerate: f64) -> Result<Vec<f32>> {
+        let nyquist = samplerate / 2.0;
+        let frequencies: Vec<f32> = match self {
+            FrequencySupport::Octave => OCTAVE_BANDS.to_vec(),
+            FrequencySupport::ThirdOctave => THIRD_OCTAVE_BANDS.to_vec(),
+            FrequencySupport::Custom(frequencies) => {
+                if frequencies.windows(2).any(|w| w[0] >= w[1])
+                    || frequencies
+                        .iter()
+                        .any(|&f| f.is_nan() || f <= 0.0 || f as f64 >= nyquist)
+                {
+                    return Err(Error::new(format!(
+                        "Band frequencies must be positive, strictly increasing and below {} Hz",
+                        nyquist
+                    )));
+                }
+                return if frequencies.is_empty() {
+                    Err(Error::new("At least one band frequency is required"))
+                } else {
+                    Ok(frequencies.clone())
+                };
+            }
+        };
+        Ok(frequencies
+            .into_iter()
+            .filter(|&f| (f as f64) < nyquist)
+            .collect())
+    }
+}
+
+/// Replace the impulse responses of a dataset by their magnitude spectra
+///
+/// Every response is transformed with an FFT (zero-padded to the next power of two) and the
+/// power of the bins between the geometric midpoints of neighbouring bands is averaged into
+/// one magnitude per band. Bands too narrow to contain a bin take the magnitude interpolated
+/// at their centre frequency. The magnitudes are those of the transfer function, i.e. a unit
+/// impulse yields 1 in every band, and are stored as 32-bit floats.
+///
+/// Grid, orientation, metadata and channel labels are kept.
+pub fn to_magnitude_spectrum(dataset: &mut Dataset, support: &FrequencySupport) -> Result<()> {
+    let ContentHeader::ImpulseResponse { samplerate } = dataset.header else {
+        return Err(Error::new(format!(
+            "Magnitude spectra can only be computed from impulse responses, not {}",
+            dataset.content_type()
+        )));
+    };
+    let frequencies = support.frequencies(samplerate)?;
+    if frequencies.is_empty() {
+        return Err(Error::new(format!(
+            "No band lies below the Nyquist frequency of {} Hz",
+            samplerate / 2.0
+        )));
+    }
+
+    let size = dataset
+        .records
+        .iter()
+        .flat_map(|record| record.channels.iter())
+        .map(Vec::len)
+        .max()
+        .unwrap_or(1)
+        .max(2)
+        .next_power_of_two();
+    // Lower and upper edge of every band in bins
+    let resolution = samplerate / size as f64;
+    let edge = |a: f32, b: f32| ((a as f64) * (b as f64)).sqrt() / resolution;
+    let edges: Vec<(f64, f64)> = (0..frequencies.len())
+        .map(|i| {
+            let f = frequencies[i];
+            let lower = match i {
+                0 if frequencies.len() > 1 => edge(f, f * f / frequencies[1]),
+                0 => f as f64 / std::f64::consts::SQRT_2 / resolution,
+                _ => edge(frequencies[i - 1], f),
+            };
+            let upper = match frequencies.get(i + 1) {
+                Some(&next) => edge(f, next),
+                None if i > 0 => edge(f, f * f / frequencies[i - 1]),
+                None => f as f64 * std::f64::consts::SQRT_2 / resolution,
+            };
+            (lower, upper.min((size / 2) as f64))
+        })
+        .collect();
+
+    let mut buffer = vec![(0.0f64, 0.0f64); size];
+    for channel in dataset
+        .records
+        .iter_mut()
+        .flat_map(|record| record.channels.iter_mut())
+    {
+        buffer.fill((0.0, 0.0));
+        for (b, &x) in buffer.iter_mut().zip(channel.iter()) {
+            b.0 = x as f64;
+        }
+        fft(&mut buffer);
+        let power: Vec<f64> = buffer[..=size / 2]
+            .iter()
+            .map(|(re, im)| re * re + im * im)
+            .collect();
+
+        *channel = edges
+            .iter()
+            .zip(&frequencies)
+            .map(|(&(lower, upper), &f)| {
+                let (first, last) = (lower.ceil() as usize, upper.floor() as usize);
+                if first <= last {
+                    let bins = &power[first..=last];
+                    (bins.iter().sum::<f64>() / bins.len() as f64).sqrt() as f32
+                } else {
+                    let position = f as f64 / resolution;
+                    let k = (position as usize).min(size / 2 - 1);
+                    let t = position - k as f64;
+                    (power[k].sqrt() * (1.0 - t) + power[k + 1].sqrt() * t) as f32
+                }
+            })
+            .collect();
+    }
+    dataset.header = ContentHeader::MagnitudeSpectrum { frequencies };
+    dataset.quantization = Quantization::Float32;
+    Ok(())
+}
+
+/// Convert an impulse response file to a magnitude spectrum file, see
+/// [`to_magnitude_spectrum`]
+///
+/// ```no_run
+/// use opendaff::dsp::{self, FrequencySupport};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// dsp::convert_to_magnitude_spectrum("loudspeaker_ir.daff", "loudspeaker_ms.daff", &FrequencySupport::ThirdOctave)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn convert_to_magnitude_spectrum(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    support: &FrequencySupport,
+) -> Result<()> {
+    let input = input.as_ref();
+    let mut reader = Reader::open(input)?;
+    let mut dataset = Dataset::from_reader(&reader)?;
+    reader.close();
+
+    to_magnitude_spectrum(&mut dataset, support).map_err(|e| match e {
+        Error::Message(m) => Error::new(format!("{}: {}", input.display(), m)),
+        e => e,
+    })?;
+    writer::write_dataset(output, &dataset)
+}
+
+/// In-place radix-2 FFT of (real, imaginary) pairs, the length must be a power of two
+fn fft(buffer: &mut [(f64, f64)]) {
+    let n = buffer.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let (sin, cos) = (-2.0 * PI / length as f64).sin_cos();
+        for start in (0..n).step_by(length) {
+            let mut w = (1.0, 0.0);
+            for k in 0..length / 2 {
+                let (a, b) = (buffer[start + k], buffer[start + k + length / 2]);
+                let t = (b.0 * w.0 - b.1 * w.1, b.0 * w.1 + b.1 * w.0);
+                buffer[start + k] = (a.0 + t.0, a.1 + t.1);
+                buffer[start + k + length / 2] = (a.0 - t.0, a.1 - t.1);
+                w = (w.0 * cos - w.1 * sin, w.0 * sin + w.1 * cos);
+            }
+        }
+        length <<= 1;
+    }
+}
+
+/// Smallest sample index of the absolute maximum over all records and channels
+fn earliest_peak(dataset: &Dataset) -> Option<usize> {
+    dataset
+        .records
+        .iter()
+        .flat_map(|record| record.channels.iter())
+        .filter_map(|channel| {
+            let (index, value) = channel
+                .iter()
+                .enumerate()
+                .fold((0, 0.0f32), |best, (i, x)| {
+                    if x.abs() > best.1 {
+                        (i, x.abs())
+                    } else {
+                        best
+                    }
+                });
+            (value > 0.0).then_some(index)
+        })
+        .min()
+}
+
+fn shifted(samples: &[f32], shift: isize, length: usize) -> Vec<f32> {
+    let mut output = vec![0.0f32; length];
+    for (i, &x) in samples.iter().enumerate() {
+        let target = i as isize + shift;
+        if target >= 0 && (target as usize) < length {
+            output[target as usize] = x;
+        }
+    }
+    output
+}
+
+fn resampled(samples: &[f32], ratio: f64) -> Vec<f32> {
+    // Tolerance against rounding errors in the rate ratio (441 * 48000 / 44100 > 480)
+    let length = (samples.len() as f64 * ratio - 1e-9).ceil() as usize;
+    // Cutoff relative to the input Nyquist frequency
+    let cutoff = ratio.min(1.0);
+    let half_width = RESAMPLE_ZERO_CROSSINGS as f64 / cutoff;
+
+    (0..length)
+        .map(|n| {
+            let t = n as f64 / ratio;
+            let first = (t - half_width).ceil().max(0.0) as usize;
+            let last = ((t + half_width).floor() as usize).min(samples.len().saturating_sub(1));
+            (first..=last)
+                .filter(|&k| k < samples.len())
+                .map(|k| {
+                    let x = t - k as f64;
+                    let window = 0.42
+                        + 0.5 * (PI * x / half_width).cos()
+                        + 0.08 * (2.0 * PI * x / half_width).cos();
+                    samples[k] as f64 * cutoff * sinc(cutoff * x) * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ring, temp_path};
+    use crate::{ContentHeader, EquiangularGrid};
+
+    fn dataset(f: impl FnMut(f32, f32, usize) -> Vec<f32>) -> Dataset {
+        let grid = ring(4);
+        Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            grid,
+            2,
+            f,
+        )
+    }
+
+    fn impulse(length: usize, position: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; length];
+        samples[position] = 1.0;
+        samples
+    }
+
+    #[test]
+    fn test_pad_to_start() {
+        let mut data = dataset(|_, _, _| impulse(4, 1));
+        pad_to(&mut data, 8, Alignment::Start).unwrap();
+        assert_eq!(data.elements_per_record(), 8);
+        assert_eq!(data.records[0].channels[0], impulse(8, 1));
+
+        pad_to(&mut data, 2, Alignment::Start).unwrap();
+        assert_eq!(data.records[3].channels[1], impulse(2, 1));
+    }
+
+    #[test]
+    fn test_pad_to_peak_keeps_relative_delays() {
+        // Channel 1 arrives three samples after channel 0
+        let mut data = dataset(|_, _, c| impulse(16, 10 + 3 * c));
+        pad_to(&mut data, 8, Alignment::Peak { lead: 2 }).unwrap();
+        assert_eq!(data.records[0].channels[0], impulse(8, 2));
+        assert_eq!(data.records[0].channels[1], impulse(8, 5));
+    }
+
+    #[test]
+    fn test_pad_to_rejects_spectra() {
+        let mut data = dataset(|_, _, _| vec![1.0; 4]);
+        data.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![100.0, 200.0, 400.0, 800.0],
+        };
+        assert!(pad_to(&mut data, 8, Alignment::Start).is_err());
+    }
+
+    #[test]
+    fn test_resample() {
+        // A slow sine survives resampling with its frequency unchanged
+        let sine = |rate: f64, n: usize| (2.0 * PI * 1000.0 * n as f64 / rate).sin() as f32;
+        let mut data = dataset(|_, _, _| (0..441).map(|n| sine(44100.0, n)).collect());
+        resample(&mut data, 48000.0).unwrap();
+        assert_eq!(
+            data.header,
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0
+            }
+        );
+        assert_eq!(data.elements_per_record(), 480);
+        // Away from the edges, where the kernel is truncated
+        for n in 40..440 {
+            assert!((data.records[1].channels[0][n] - sine(48000.0, n)).abs() < 1e-3);
+        }
+
+        // Downsampling removes content above the new Nyquist frequency
+        let mut data = dataset(|_, _, _| {
+            (0..400)
+                .map(|n| if n % 2 == 0 { 1.0 } else { -1.0 })
+                .collect()
+        });
+        resample(&mut data, 22050.0).unwrap();
+        assert_eq!(data.elements_per_record(), 200);
+        assert!(data.records[0].channels[0][50..150]
+            .iter()
+            .all(|x| x.abs() < 1e-2));
+    }
+
+    #[test]
+    fn test_normalize_keeps_level_differences() {
+        let mut data = dataset(|_, _, c| vec![0.0, 0.5 / (c + 1) as f32, -0.25]);
+        let gain = normalize(&mut data, -6.0).unwrap();
+        assert!((gain - 2.0 * 10f32.powf(-0.3)).abs() < 1e-6);
+        assert!((data.records[0].channels[0][1] - 10f32.powf(-0.3)).abs() < 1e-6);
+        assert!((data.records[0].channels[1][1] - 0.5 * 10f32.powf(-0.3)).abs() < 1e-6);
+
+        data.header = ContentHeader::PhaseSpectrum {
+            frequencies: vec![100.0, 200.0, 400.0],
+        };
+        assert!(normalize(&mut data, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_window() {
+        let mut data = dataset(|_, _, _| vec![1.0; 16]);
+        // Records at azimuth 0°, 90°, 180° and 270° (-90°)
+        window(&mut data, 4, |azimuth, _, channel| {
+            if channel == 1 && azimuth < -45.0 {
+                6
+            } else {
+                12
+            }
+        })
+        .unwrap();
+        for record in &data.records {
+            let (azimuth, _) = grid::to_object_view(&data.orientation, record.alpha, record.beta);
+            for (channel, samples) in record.channels.iter().enumerate() {
+                let end = if channel == 1 && azimuth < -45.0 {
+                    6
+                } else {
+                    12
+                };
+                assert!(samples[..end - 4].iter().all(|&x| x == 1.0));
+                assert!(samples[end - 4..end].windows(2).all(|w| w[0] > w[1]));
+                assert!(samples[end - 4..end].iter().all(|&x| x > 0.0 && x < 1.0));
+                assert!(samples[end..].iter().all(|&x| x == 0.0));
+                assert_eq!(samples.len(), 16);
+            }
+        }
+        assert!(data.records.iter().any(|r| r.channels[1][8] == 0.0));
+
+        // Windows shorter than the fade and longer than the filter
+        let mut data = dataset(|_, _, _| vec![1.0; 8]);
+        window(
+            &mut data,
+            4,
+            |_, _, channel| if channel == 0 { 2 } else { 100 },
+        )
+        .unwrap();
+        let record = &data.records[0];
+        assert!(record.channels[0][0] < 1.0 && record.channels[0][2] == 0.0);
+        assert_eq!(record.channels[1][..4], [1.0; 4]);
+        assert!(record.channels[1][7] > 0.0);
+
+        data.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(window(&mut data, 0, |_, _, _| 4).is_err());
+    }
+
+    #[test]
+    fn test_normalize_to_direction() {
+        let grid = EquiangularGrid::with_resolution(30.0, 30.0).unwrap();
+        let cardioid =
+            |alpha: f32, beta: f32| 1.0 + 0.5 * alpha.to_radians().cos() * beta.to_radians().sin();
+        let spectrum = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![500.0, 1000.0],
+        };
+        let mut data = Dataset::from_fn(spectrum.clone(), grid, 2, |alpha, beta, c| {
+            vec![
+                cardioid(alpha, beta),
+                (c + 1) as f32 * cardioid(alpha, beta),
+            ]
+        });
+        let front = normalize_to_direction(&mut data, 0.0, 0.0).unwrap();
+        let record = &data.records[front];
+        assert_eq!((record.alpha, record.beta), (0.0, 90.0));
+        assert!(record.channels.iter().flatten().all(|&x| x == 1.0));
+        let back = data
+            .records
+            .iter()
+            .find(|r| (r.alpha, r.beta) == (180.0, 90.0))
+            .unwrap();
+        assert!((back.channels[1][1] - 0.5 / 1.5).abs() < 1e-6);
+
+        // Levels in dB are subtracted
+        let mut levels = Dataset::from_fn(spectrum, grid, 1, |alpha, beta, _| {
+            vec![20.0 * cardioid(alpha, beta).log10(), -3.0]
+        });
+        normalize_to_direction(&mut levels, 180.0, 0.0).unwrap();
+        let front = levels
+            .records
+            .iter()
+            .find(|r| (r.alpha, r.beta) == (0.0, 90.0))
+            .unwrap();
+        assert!((front.channels[0][0] - 20.0 * 3f32.log10()).abs() < 1e-4);
+        assert_eq!(front.channels[0][1], 0.0);
+
+        // Complex spectra lose the phase of the reference
+        let mut complex = Dataset::from_fn(
+            ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: vec![1000.0],
+            },
+            grid,
+            1,
+            |alpha, beta, _| {
+                let (m, p) = (cardioid(alpha, beta), alpha.to_radians());
+                vec![m * p.cos(), m * p.sin()]
+            },
+        );
+        let reference = normalize_to_direction(&mut complex, 0.0, 0.0).unwrap();
+        let value = &complex.records[reference].channels[0];
+        assert!((value[0] - 1.0).abs() < 1e-6 && value[1].abs() < 1e-6);
+        let side = complex
+            .records
+            .iter()
+            .find(|r| (r.alpha, r.beta) == (90.0, 90.0))
+            .unwrap();
+        let value = &side.channels[0];
+        assert!(value[0].abs() < 1e-6 && (value[1] - 1.0 / 1.5).abs() < 1e-6);
+
+        let mut phases = Dataset::from_fn(
+            ContentHeader::PhaseSpectrum {
+                frequencies: vec![1000.0],
+            },
+            grid,
+            1,
+            |alpha, _, _| vec![alpha.to_radians() - 3.0],
+        );
+        normalize_to_direction(&mut phases, 0.0, 0.0).unwrap();
+        assert!(phases
+            .records
+            .iter()
+            .flat_map(|r| r.channels.iter().flatten())
+            .all(|p| p.abs() <= std::f32::consts::PI));
+
+        let mut impulses = dataset(|_, _, _| vec![1.0, 0.0]);
+        assert!(normalize_to_direction(&mut impulses, 0.0, 0.0).is_err());
+        assert!(normalize_to_direction(&mut data, f32::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_spatial_smooth() {
+        let grid = EquiangularGrid::with_resolution(10.0, 10.0).unwrap();
+        // Smooth cardioid with direction-dependent measurement noise
+        let cardioid =
+            |alpha: f32, beta: f32| 1.0 + 0.5 * alpha.to_radians().cos() * beta.to_radians().sin();
+        let noise = |alpha: f32, beta: f32| 0.2 * (alpha * 12.9898 + beta * 78.233).sin();
+        let mut data = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            grid,
+            1,
+            |alpha, beta, _| {
+                let m = cardioid(alpha, beta);
+                vec![m, m + noise(alpha, beta)]
+            },
+        );
+        let weights = grid::quadrature_weights(&data).unwrap();
+        let power = |data: &Dataset, bin: usize| -> f64 {
+            data.records
+                .iter()
+                .zip(&weights)
+                .map(|(r, w)| w * (r.channels[0][bin] as f64).powi(2))
+                .sum()
+        };
+        let error = |data: &Dataset, bin: usize| -> f32 {
+            data.records
+                .iter()
+                .map(|r| (r.channels[0][bin] - cardioid(r.alpha, r.beta)).abs())
+                .fold(0.0, f32::max)
+        };
+        let before = [power(&data, 0), power(&data, 1)];
+        let noisy_error = error(&data, 1);
+
+        let original = data.clone();
+        spatial_smooth(&mut data, 0.0).unwrap();
+        assert_eq!(data, original);
+
+        spatial_smooth(&mut data, 15.0).unwrap();
+        for (bin, before) in before.iter().enumerate() {
+            assert!((power(&data, bin) / before - 1.0).abs() < 1e-4);
+        }
+        assert!(error(&data, 1) < 0.5 * noisy_error);
+        // The broad cardioid itself is only slightly flattened
+        assert!(error(&data, 0) < 0.05);
+        // Repeated pole records stay identical
+        let south: Vec<_> = data.records.iter().filter(|r| r.beta == 0.0).collect();
+        assert!(south.windows(2).all(|w| w[0].channels == w[1].channels));
+
+        assert!(spatial_smooth(&mut data, -1.0).is_err());
+        assert!(spatial_smooth(&mut dataset(|_, _, _| vec![1.0; 4]), 10.0).is_err());
+    }
+
+    #[test]
+    fn test_spatial_smooth_complex() {
+        let grid = EquiangularGrid::with_resolution(15.0, 15.0).unwrap();
+        // Opposite phases in the two hemispheres partly cancel when averaged
+        let mut data = Dataset::from_fn(
+            ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: vec![1000.0],
+            },
+            grid,
+            2,
+            |_, beta, channel| {
+                let sign = if beta < 90.0 { 1.0 } else { -1.0 };
+                vec![sign, channel as f32]
+            },
+        );
+        let weights = grid::quadrature_weights(&data).unwrap();
+        let power = |data: &Dataset, channel: usize| -> f64 {
+            data.records
+                .iter()
+                .zip(&weights)
+                .map(|(r, w)| {
+                    let v = &r.channels[channel];
+                    w * (v[0] as f64).hypot(v[1] as f64).powi(2)
+                })
+                .sum()
+        };
+        let before = [power(&data, 0), power(&data, 1)];
+        spatial_smooth(&mut data, 30.0).unwrap();
+        for (channel, before) in before.iter().enumerate() {
+            assert!((power(&data, channel) / before - 1.0).abs() < 1e-4);
+        }
+        let equator = data.records.iter().find(|r| r.beta == 90.0).unwrap();
+        assert!(equator.channels[0][0].abs() < 0.5);
+    }
+
+    #[test]
+    fn test_flush_denormals() {
+        let tail = f32::MIN_POSITIVE / 8.0;
+        let mut data = dataset(|_, _, _| vec![1.0, 0.5, tail, 0.0]);
+        assert_eq!(flush_denormals(&mut data), 4 * 2);
+        assert!(data
+            .records
+            .iter()
+            .all(|r| r.channels.iter().all(|c| c == &[1.0, 0.5, 0.0, 0.0])));
+        assert_eq!(flush_denormals(&mut data), 0);
+    }
+
+    fn labelled(data: &mut Dataset) {
+        for (channel, label) in ["Left ear", "Right ear"].into_iter().enumerate() {
+            data.metadata.insert(
+                metadata::channel_label_key(channel),
+                crate::MetadataValue::String(label.into()),
+            );
+        }
+    }
+
+    #[test]
+    fn test_select_channels() {
+        let mut data = dataset(|_, _, c| vec![c as f32; 3]);
+        labelled(&mut data);
+        let mut swapped = data.clone();
+        select_channels(&mut swapped, &[1, 0]).unwrap();
+        assert_eq!(swapped.records[2].channels, [vec![1.0; 3], vec![0.0; 3]]);
+        assert_eq!(
+            swapped.metadata[&metadata::channel_label_key(0)],
+            crate::MetadataValue::String("Right ear".into())
+        );
+
+        select_channels(&mut data, &[0]).unwrap();
+        assert_eq!(data.num_channels(), 1);
+        assert_eq!(data.records[0].channels, [vec![0.0; 3]]);
+        assert!(data.metadata.contains_key(&metadata::channel_label_key(0)));
+        assert!(!data.metadata.contains_key(&metadata::channel_label_key(1)));
+
+        assert!(select_channels(&mut data, &[]).is_err());
+        assert!(select_channels(&mut data, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_mixdown() {
+        let mut data = dataset(|_, _, c| vec![1.0 + c as f32, -1.0]);
+        labelled(&mut data);
+        assert!(mixdown(&mut data, &[0.5]).is_err());
+        assert!(mixdown(&mut data, &[0.5, f32::NAN]).is_err());
+        mixdown(&mut data, &[0.5, 0.25]).unwrap();
+        assert_eq!(data.num_channels(), 1);
+        assert_eq!(data.records[1].channels, [vec![1.0, -0.75]]);
+        assert!(!data.metadata.contains_key(&metadata::channel_label_key(0)));
+
+        data.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![100.0, 200.0],
+        };
+        assert!(mixdown(&mut data, &[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_to_magnitude_spectrum() {
+        // Short responses have no bins inside the lowest bands
+        let mut data = dataset(|_, _, channel| impulse(8, 3 + channel));
+        labelled(&mut data);
+        data.quantization = Quantization::Int16;
+        to_magnitude_spectrum(&mut data, &FrequencySupport::ThirdOctave).unwrap();
+        let ContentHeader::MagnitudeSpectrum { frequencies } = &data.header else {
+            panic!("{:?}", data.header);
+        };
+        assert_eq!(frequencies, &THIRD_OCTAVE_BANDS);
+        assert_eq!(data.quantization, Quantization::Float32);
+        assert_eq!(data.metadata.len(), 2);
+        for channel in data.records.iter().flat_map(|r| &r.channels) {
+            assert!(channel.iter().all(|&m| (m - 1.0).abs() < 1e-6));
+        }
+        assert!(to_magnitude_spectrum(&mut data, &FrequencySupport::Octave).is_err());
+
+        // Two-tap average: |H(f)| = cos(πf/fs), falling towards the Nyquist frequency
+        let mut data = dataset(|_, _, _| {
+            let mut samples = vec![0.0; 1024];
+            samples[..2].fill(0.5);
+            samples
+        });
+        to_magnitude_spectrum(&mut data, &FrequencySupport::Octave).unwrap();
+        let magnitudes = &data.records[0].channels[0];
+        assert_eq!(magnitudes.len(), OCTAVE_BANDS.len());
+        assert!((magnitudes[0] - 1.0).abs() < 1e-3);
+        assert!((magnitudes[5] - (PI * 1000.0 / 44100.0).cos() as f32).abs() < 1e-2);
+        assert!(magnitudes.windows(2).all(|w| w[1] < w[0]));
+        assert!(magnitudes[9] > 0.2 && magnitudes[9] < 0.7);
+
+        // Bands above the Nyquist frequency
+        let mut data = dataset(|_, _, _| impulse(16, 0));
+        data.header = ContentHeader::ImpulseResponse {
+            samplerate: 16000.0,
+        };
+        let custom = FrequencySupport::Custom(vec![100.0, 1000.0, 9000.0]);
+        assert!(to_magnitude_spectrum(&mut data, &custom).is_err());
+        let custom = FrequencySupport::Custom(vec![1000.0, 100.0]);
+        assert!(to_magnitude_spectrum(&mut data, &custom).is_err());
+        to_magnitude_spectrum(&mut data, &FrequencySupport::Octave).unwrap();
+        assert_eq!(data.elements_per_record(), 8);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_convert_to_magnitude_spectrum() {
+        let ir_path = temp_path("convert_ir.daff");
+        let ms_path = temp_path("convert_ms.daff");
+        writer::write_dataset(&ir_path, &dataset(|_, _, _| impulse(32, 4))).unwrap();
+
+        convert_to_magnitude_spectrum(&ir_path, &ms_path, &FrequencySupport::Octave).unwrap();
+        let mut reader = Reader::open(&ms_path).unwrap();
+        let converted = Dataset::from_reader(&reader).unwrap();
+        reader.close();
+        assert_eq!(converted.content_type(), ContentType::MagnitudeSpectrum);
+        assert_eq!(converted.num_records(), 4);
+        assert_eq!(converted.num_channels(), 2);
+        assert!(converted.records[1].channels[1]
+            .iter()
+            .all(|&m| (m - 1.0).abs() < 1e-6));
+
+        // Spectra cannot be converted again
+        assert!(
+            convert_to_magnitude_spectrum(&ms_path, &ir_path, &FrequencySupport::Octave).is_err()
+        );
+        std::fs::remove_file(ir_path).unwrap();
+        std::fs::remove_file(ms_path).unwrap();
+    }
+}
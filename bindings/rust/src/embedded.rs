@@ -0,0 +1,75 @@
+//! DAFF files embedded in the binary
+//!
+//! Small default datasets, e.g. a generic HRTF, can ship inside an application instead of
+//! next to it. [`include_daff!`](crate::include_daff) embeds a file at compile time with
+//! `include_bytes!`; the data is parsed at first use and shared from then on:
+//!
+//! ```ignore
+//! use opendaff::embedded::EmbeddedDataset;
+//!
+//! static HRTF: EmbeddedDataset = opendaff::include_daff!("assets/hrtf.daff");
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! let ir = HRTF.reader()?.content_ir()?;
+//! let record = ir.nearest_neighbour(30.0, 0.0);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::OnceLock;
+
+use crate::{Reader, Result};
+
+/// A DAFF file embedded in the binary, opened at first use
+///
+/// Usually created with [`include_daff!`](crate::include_daff). Can be stored in a `static`
+/// and shared between threads.
+pub struct EmbeddedDataset {
+    bytes: &'static [u8],
+    reader: OnceLock<Result<Reader>>,
+}
+
+impl EmbeddedDataset {
+    /// Embedded dataset over the contents of a DAFF file
+    pub const fn new(bytes: &'static [u8]) -> Self {
+        Self {
+            bytes,
+            reader: OnceLock::new(),
+        }
+    }
+
+    /// Contents of the embedded file
+    pub fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// Reader over the embedded file, opened on the first call
+    ///
+    /// Invalid data is reported by every call.
+    pub fn reader(&self) -> Result<&Reader> {
+        self.reader
+            .get_or_init(|| {
+                let mut reader = Reader::new()?;
+                reader.open_bytes(self.bytes)?;
+                Ok(reader)
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    /// Whether the embedded file has been opened yet
+    pub fn is_open(&self) -> bool {
+        self.reader.get().is_some_and(|reader| reader.is_ok())
+    }
+}
+
+/// Embed a DAFF file in the binary as an [`EmbeddedDataset`](crate::embedded::EmbeddedDataset)
+///
+/// The path is resolved like with `include_bytes!`, relative to the file the macro is used in.
+/// The file is only parsed at first use, so the macro can initialize a `static`.
+#[macro_export]
+macro_rules! include_daff {
+    ($path:expr $(,)?) => {
+        $crate::embedded::EmbeddedDataset::new(::core::include_bytes!($path))
+    };
+}
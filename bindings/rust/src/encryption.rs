@@ -0,0 +1,223 @@
+//! Encrypted DAFF containers
+//!
+//! Commercial HRTF sets are distributed encrypted, so that only licensees holding the key can
+//! use them. [`encrypt`] seals the data block of a DAFF file with XChaCha20-Poly1305; the
+//! headers, record descriptors and metadata stay readable, e.g. for catalogs listing the
+//! license terms, but are authenticated along with the data, so no part of the container can
+//! be modified unnoticed. Files are encrypted as they are written with
+//! [`WriterBuilder::encryption_key`](crate::writer::WriterBuilder::encryption_key) and
+//! opened with [`Reader::open_encrypted`]:
+//!
+//! ```no_run
+//! use opendaff::{encryption, writer, Reader};
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! # let dataset: opendaff::Dataset = unimplemented!();
+//! // Publisher
+//! let key = encryption::generate_key();
+//! writer::WriterBuilder::from_dataset(&dataset)?
+//!     .encryption_key(key)
+//!     .write_iter("hrir.daffx", dataset.elements_per_record(), dataset.records.iter().map(|r| &r.channels))?;
+//!
+//! // Licensee
+//! let reader = Reader::open_encrypted("hrir.daffx", &key)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A container starts with [`MAGIC`], followed by the 24-byte nonce, the offset of the data
+//! block in the file and the size of the unencrypted parts (both `u64`, little-endian), the
+//! unencrypted parts and finally the encrypted data block with its 16-byte tag. Everything
+//! before the encrypted data is the associated data of the cipher.
+
+use std::fs;
+use std::path::Path;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::parser::Layout;
+use crate::{Error, Result};
+
+/// 256-bit key of an encrypted container
+pub type Key = [u8; 32];
+
+/// First bytes of an encrypted container
+pub const MAGIC: &[u8; 8] = b"DAFFENC1";
+
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+/// Size of the container header up to the unencrypted parts
+const HEADER_SIZE: usize = MAGIC.len() + NONCE_SIZE + 16;
+
+/// Generate a random key from the random number generator of the operating system
+pub fn generate_key() -> Key {
+    XChaCha20Poly1305::generate_key(&mut OsRng).into()
+}
+
+/// Whether the data is an encrypted container rather than a DAFF file
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypt DAFF data, returning the container
+///
+/// A fresh random nonce is used for every call, so encrypting the same file twice yields
+/// different containers.
+pub fn encrypt(bytes: &[u8], key: &Key) -> Result<Vec<u8>> {
+    if is_encrypted(bytes) {
+        return Err(Error::new("The data is already encrypted"));
+    }
+    let data = Layout::parse(bytes)?.data;
+    let clear_size = bytes.len() - data.len();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut container = Vec::with_capacity(HEADER_SIZE + bytes.len() + TAG_SIZE);
+    container.extend_from_slice(MAGIC);
+    container.extend_from_slice(&nonce);
+    container.extend_from_slice(&(data.start as u64).to_le_bytes());
+    container.extend_from_slice(&(clear_size as u64).to_le_bytes());
+    container.extend_from_slice(&bytes[..data.start]);
+    container.extend_from_slice(&bytes[data.end..]);
+    let sealed = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &bytes[data],
+                aad: &container,
+            },
+        )
+        .map_err(|_| Error::new("Failed to encrypt the data block"))?;
+    container.extend(sealed);
+    Ok(container)
+}
+
+/// Encrypt a DAFF file, writing the container to `output`
+pub fn encrypt_file(input: impl AsRef<Path>, output: impl AsRef<Path>, key: &Key) -> Result<()> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let container = encrypt(&read(input)?, key)?;
+    fs::write(output, container)
+        .map_err(|e| Error::new(format!("Failed to write '{}': {}", output.display(), e)))
+}
+
+/// Decrypt a container, returning the DAFF data
+///
+/// Fails if the key is wrong or any byte of the container was modified.
+pub fn decrypt(container: &[u8], key: &Key) -> Result<Vec<u8>> {
+    if !is_encrypted(container) {
+        return Err(Error::new("The data is no encrypted DAFF container"));
+    }
+    let truncated = || Error::new("The encrypted DAFF container is truncated");
+    let field = |position: usize| {
+        container
+            .get(position..position + 8)
+            .map(|field| u64::from_le_bytes(field.try_into().unwrap()))
+            .ok_or_else(truncated)
+    };
+    let data_start = field(MAGIC.len() + NONCE_SIZE)?;
+    let clear_size = field(MAGIC.len() + NONCE_SIZE + 8)?;
+    let sealed_start = usize::try_from(clear_size)
+        .ok()
+        .and_then(|size| size.checked_add(HEADER_SIZE))
+        .filter(|&start| start + TAG_SIZE <= container.len())
+        .ok_or_else(truncated)?;
+    if data_start > clear_size {
+        return Err(Error::new("The encrypted DAFF container is corrupt"));
+    }
+
+    let nonce = XNonce::from_slice(&container[MAGIC.len()..MAGIC.len() + NONCE_SIZE]);
+    let data = XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            nonce,
+            Payload {
+                msg: &container[sealed_start..],
+                aad: &container[..sealed_start],
+            },
+        )
+        .map_err(|_| {
+            Error::new("Failed to decrypt the DAFF container: wrong key or modified container")
+        })?;
+
+    let clear = &container[HEADER_SIZE..sealed_start];
+    let (before, after) = clear.split_at(data_start as usize);
+    let mut bytes = Vec::with_capacity(clear.len() + data.len());
+    bytes.extend_from_slice(before);
+    bytes.extend(data);
+    bytes.extend_from_slice(after);
+    Ok(bytes)
+}
+
+/// Decrypt a container file, returning the DAFF data, see [`decrypt`]
+pub fn decrypt_file(path: impl AsRef<Path>, key: &Key) -> Result<Vec<u8>> {
+    decrypt(&read(path.as_ref())?, key)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{dataset, temp_path};
+    use crate::writer::WriterBuilder;
+    use crate::{Dataset, Reader};
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_encrypt_on_write() {
+        let key = generate_key();
+        let dataset = dataset();
+        let path = temp_path("encrypted.daff");
+        WriterBuilder::from_dataset(&dataset)
+            .unwrap()
+            .metadata("LICENSE", "Proprietary")
+            .encryption_key(key)
+            .write_iter(
+                &path,
+                dataset.elements_per_record(),
+                dataset.records.iter().map(|r| &r.channels),
+            )
+            .unwrap();
+
+        let container = fs::read(&path).unwrap();
+        assert!(is_encrypted(&container));
+        assert!(Reader::open(&path).is_err());
+        // The samples are not stored in the clear
+        let sample = (0.25f32).to_le_bytes();
+        assert!(!container.windows(4).any(|w| w == sample));
+        assert!(container.windows(11).any(|w| w == b"Proprietary"));
+
+        let reader = Reader::open_encrypted(&path, &key).unwrap();
+        assert_eq!(
+            Dataset::from_reader(&reader).unwrap().records,
+            dataset.records
+        );
+        assert!(Reader::open_encrypted(&path, &[0; 32]).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_and_tampering() {
+        let key = [3; 32];
+        let path = temp_path("plain.daff");
+        crate::writer::write_dataset(&path, &dataset()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let container = encrypt(&bytes, &key).unwrap();
+        assert_ne!(container, encrypt(&bytes, &key).unwrap());
+        assert_eq!(decrypt(&container, &key).unwrap(), bytes);
+        assert!(encrypt(&container, &key).is_err());
+        assert!(decrypt(&bytes, &key).is_err());
+
+        // Every part of the container is authenticated
+        for position in [MAGIC.len(), HEADER_SIZE + 4, container.len() - 1] {
+            let mut modified = container.clone();
+            modified[position] ^= 1;
+            assert!(decrypt(&modified, &key).is_err());
+        }
+        assert!(decrypt(&container[..container.len() - 1], &key).is_err());
+        assert!(decrypt(&container[..HEADER_SIZE - 1], &key).is_err());
+    }
+}
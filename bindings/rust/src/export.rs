@@ -0,0 +1,616 @@
+//! Text exports of datasets
+//!
+//! All exporters format numbers independently of the system locale: the decimal separator is
+//! always `.` and no digit grouping is applied, so exported files can be exchanged between
+//! systems with different regional settings. The number of decimal places is controlled by
+//! [`ExportOptions::precision`].
+//!
+//! Simulation engines often misbehave on zero or denormal magnitudes, which occur in measured
+//! spectra. [`ExportOptions::magnitude_floor_db`] clamps magnitudes to a floor and
+//! [`ExportOptions::flag_underflows`] marks the values that were affected.
+//!
+//! [`ndjson_records`] streams the records of an open file as newline-delimited JSON, one
+//! record at a time, for ingesting large libraries into analytics databases.
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use crate::dataset::RecordReader;
+use crate::grid;
+use crate::{ContentHeader, Dataset, Error, MetadataValue, Quantization, Reader, Result};
+
+/// Options shared by all exporters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    /// Maximum number of decimal places of exported values (trailing zeros are dropped)
+    pub precision: usize,
+    /// Lowest exported magnitude in dB re 1, e.g. -100 dB
+    ///
+    /// Smaller magnitudes of magnitude spectra and complex spectra (MPS and DFT, keeping
+    /// their phase) are raised to the floor. Impulse responses and phase spectra are exported
+    /// unchanged.
+    pub magnitude_floor_db: Option<f32>,
+    /// Mark underflows in the export: magnitudes below the floor or, without a floor, zero
+    /// and subnormal magnitudes
+    ///
+    /// CSV gets an `underflows` column with the number of underflows of each line, JSON an
+    /// `underflows` array with the number per channel of each record.
+    pub flag_underflows: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            precision: 6,
+            magnitude_floor_db: None,
+            flag_underflows: false,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Format a number with the configured precision, independent of the system locale
+    ///
+    /// Non-finite values are written as `nan`, `inf` and `-inf`.
+    pub fn format(&self, value: f64) -> String {
+        if value.is_nan() {
+            return "nan".to_string();
+        }
+        if value.is_infinite() {
+            return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+        }
+
+        let mut text = format!("{:.*}", self.precision, value);
+        if text.contains('.') {
+            let trimmed = text.trim_end_matches('0').trim_end_matches('.').len();
+            text.truncate(trimmed);
+        }
+        if text == "-0" {
+            text.remove(0);
+        }
+        text
+    }
+
+    /// Channel data as exported: magnitudes clamped to the floor, and the number of
+    /// underflows
+    fn condition<'a>(&self, header: &ContentHeader, data: &'a [f32]) -> (Cow<'a, [f32]>, usize) {
+        let complex = match header {
+            ContentHeader::MagnitudeSpectrum { .. } => false,
+            ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => {
+                true
+            }
+            _ => return (Cow::Borrowed(data), 0),
+        };
+        let floor = self.magnitude_floor_db.map(|db| 10f32.powf(db / 20.0));
+        let underflow = |magnitude: f32| match floor {
+            Some(floor) => magnitude < floor,
+            None => magnitude == 0.0 || magnitude.is_subnormal(),
+        };
+
+        let mut data = Cow::Borrowed(data);
+        let mut underflows = 0;
+        let step = if complex { 2 } else { 1 };
+        for i in (0..data.len()).step_by(step) {
+            let magnitude = if complex {
+                data[i].hypot(data[i + 1])
+            } else {
+                data[i].abs()
+            };
+            if !underflow(magnitude) {
+                continue;
+            }
+            underflows += 1;
+            let Some(floor) = floor else {
+                continue;
+            };
+            let values = data.to_mut();
+            if !complex {
+                values[i] = floor;
+            } else if magnitude > 0.0 {
+                let scale = floor / magnitude;
+                values[i] *= scale;
+                values[i + 1] *= scale;
+            } else {
+                (values[i], values[i + 1]) = (floor, 0.0);
+            }
+        }
+        (data, underflows)
+    }
+}
+
+/// Number of magnitudes of a dataset that count as underflows under the given options (see
+/// [`ExportOptions::flag_underflows`])
+///
+/// With a [floor](ExportOptions::magnitude_floor_db), these are the values that the exporters
+/// clamp.
+pub fn count_underflows(dataset: &Dataset, options: &ExportOptions) -> usize {
+    dataset
+        .records
+        .iter()
+        .flat_map(|record| &record.channels)
+        .map(|data| options.condition(&dataset.header, data).1)
+        .sum()
+}
+
+/// Write a dataset as CSV
+///
+/// The first line holds the column names `alpha,beta,channel` followed by one column per
+/// element: sample indices for impulse responses, frequencies for spectra (with `re`/`im`
+/// columns for complex spectra) and DFT bins. Each following line holds one channel of one
+/// record.
+pub fn to_csv<W: Write>(dataset: &Dataset, options: &ExportOptions, mut writer: W) -> Result<()> {
+    let mut header = vec![
+        "alpha".to_string(),
+        "beta".to_string(),
+        "channel".to_string(),
+    ];
+    header.extend(element_labels(
+        &dataset.header,
+        dataset.elements_per_record(),
+        options,
+    ));
+    if options.flag_underflows {
+        header.push("underflows".to_string());
+    }
+    writeln!(writer, "{}", header.join(",")).map_err(write_error)?;
+
+    for record in &dataset.records {
+        for (channel, data) in record.channels.iter().enumerate() {
+            let (data, underflows) = options.condition(&dataset.header, data);
+            let mut line = vec![
+                options.format(record.alpha as f64),
+                options.format(record.beta as f64),
+                channel.to_string(),
+            ];
+            line.extend(data.iter().map(|&x| options.format(x as f64)));
+            if options.flag_underflows {
+                line.push(underflows.to_string());
+            }
+            writeln!(writer, "{}", line.join(",")).map_err(write_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a dataset as JSON
+///
+/// Non-finite values, which JSON cannot represent, are written as `null`, as is the grid of
+/// datasets with an irregular layout. Metadata is written as an object of its keys.
+pub fn to_json<W: Write>(dataset: &Dataset, options: &ExportOptions, mut writer: W) -> Result<()> {
+    let number = |value: f64| {
+        if value.is_finite() {
+            options.format(value)
+        } else {
+            "null".to_string()
+        }
+    };
+    let array = |values: &[f32]| {
+        let items: Vec<String> = values.iter().map(|&x| number(x as f64)).collect();
+        format!("[{}]", items.join(","))
+    };
+
+    let header = match &dataset.header {
+        ContentHeader::ImpulseResponse { samplerate } => {
+            format!("\"samplerate\":{}", number(*samplerate))
+        }
+        ContentHeader::MagnitudeSpectrum { frequencies }
+        | ContentHeader::PhaseSpectrum { frequencies }
+        | ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+            format!("\"frequencies\":{}", array(frequencies))
+        }
+        ContentHeader::DftSpectrum {
+            samplerate,
+            transform_size,
+        } => format!(
+            "\"samplerate\":{},\"transform_size\":{}",
+            number(*samplerate),
+            transform_size
+        ),
+    };
+    let grid = match dataset.grid.equiangular() {
+        Some(grid) => format!(
+            "{{\"alpha_points\":{},\"alpha_start\":{},\"alpha_end\":{},\
+             \"beta_points\":{},\"beta_start\":{},\"beta_end\":{}}}",
+            grid.alpha_points,
+            number(grid.alpha_start as f64),
+            number(grid.alpha_end as f64),
+            grid.beta_points,
+            number(grid.beta_start as f64),
+            number(grid.beta_end as f64),
+        ),
+        None => "null".to_string(),
+    };
+    let orientation = &dataset.orientation;
+    let metadata: Vec<String> = dataset
+        .metadata
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                MetadataValue::Bool(b) => b.to_string(),
+                MetadataValue::Int(i) => i.to_string(),
+                MetadataValue::Float(f) => number(*f),
+                MetadataValue::String(s) => json_string(s),
+            };
+            format!("{}:{}", json_string(key), value)
+        })
+        .collect();
+
+    write!(
+        writer,
+        "{{\"content_type\":\"{}\",\"quantization\":\"{}\",{},\"grid\":{},\
+         \"orientation\":{{\"yaw\":{},\"pitch\":{},\"roll\":{}}},\"metadata\":{{{}}},\
+         \"records\":[",
+        dataset.content_type(),
+        quantization_name(dataset.quantization),
+        header,
+        grid,
+        number(orientation.yaw as f64),
+        number(orientation.pitch as f64),
+        number(orientation.roll as f64),
+        metadata.join(","),
+    )
+    .map_err(write_error)?;
+
+    for (index, record) in dataset.records.iter().enumerate() {
+        let (channels, underflows): (Vec<String>, Vec<String>) = record
+            .channels
+            .iter()
+            .map(|c| {
+                let (data, underflows) = options.condition(&dataset.header, c);
+                (array(&data), underflows.to_string())
+            })
+            .unzip();
+        let underflows = if options.flag_underflows {
+            format!(",\"underflows\":[{}]", underflows.join(","))
+        } else {
+            String::new()
+        };
+        write!(
+            writer,
+            "{}{{\"alpha\":{},\"beta\":{},\"channels\":[{}]{}}}",
+            if index > 0 { "," } else { "" },
+            number(record.alpha as f64),
+            number(record.beta as f64),
+            channels.join(","),
+            underflows
+        )
+        .map_err(write_error)?;
+    }
+    writeln!(writer, "]}}").map_err(write_error)
+}
+
+/// Options of [`ndjson_records`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NdjsonOptions {
+    /// Formatting, magnitude floor and underflow flags of the exported values
+    pub format: ExportOptions,
+    /// Include the channel data of every record, not only its statistics
+    pub include_data: bool,
+    /// Value of a `source` field added to every line, e.g. the path of the file, so lines
+    /// from many files can be told apart after ingestion
+    pub source: Option<String>,
+}
+
+/// Stream the records of the file opened by `reader` as newline-delimited JSON
+///
+/// Every line is a JSON object describing one record: its index, its direction in the data
+/// view (`alpha`, `beta`) and the object view (`azimuth`, `elevation`), and statistics per
+/// channel (`min`, `max`, `mean` and `rms` of the exported values; of the magnitudes for
+/// complex spectra). With [`NdjsonOptions::include_data`], `data` holds the channel data like
+/// [`to_json`]. Only one record is held in memory at a time, so files of any size can be
+/// exported. Returns the number of written records.
+///
+/// ```no_run
+/// use opendaff::export::{self, NdjsonOptions};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let reader = opendaff::Reader::open("hrir.daff")?;
+/// let output = std::io::BufWriter::new(std::fs::File::create("hrir.ndjson").unwrap());
+/// let options = NdjsonOptions { source: Some("hrir.daff".to_string()), ..NdjsonOptions::default() };
+/// export::ndjson_records(&reader, output, &options)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn ndjson_records<W: Write>(
+    reader: &Reader,
+    mut writer: W,
+    options: &NdjsonOptions,
+) -> Result<usize> {
+    let content = RecordReader::new(reader)?;
+    let header = content.header()?;
+    let orientation = reader.orientation()?;
+    let format = &options.format;
+    let number = |value: f64| {
+        if value.is_finite() {
+            format.format(value)
+        } else {
+            "null".to_string()
+        }
+    };
+    let complex = matches!(
+        header,
+        ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. }
+    );
+    let source = options
+        .source
+        .as_deref()
+        .map(|source| format!("\"source\":{},", json_string(source)))
+        .unwrap_or_default();
+
+    for index in 0..content.num_records() {
+        let record = content.record(index)?;
+        let (azimuth, elevation) = grid::to_object_view(&orientation, record.alpha, record.beta);
+        let mut channels = Vec::with_capacity(record.channels.len());
+        let mut data = Vec::new();
+        for values in &record.channels {
+            let (values, underflows) = format.condition(&header, values);
+            let statistics: Vec<f64> = if complex {
+                values
+                    .chunks_exact(2)
+                    .map(|c| (c[0] as f64).hypot(c[1] as f64))
+                    .collect()
+            } else {
+                values.iter().map(|&x| x as f64).collect()
+            };
+            let count = statistics.len().max(1) as f64;
+            let min = statistics.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = statistics.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mean = statistics.iter().sum::<f64>() / count;
+            let rms = (statistics.iter().map(|x| x * x).sum::<f64>() / count).sqrt();
+            let underflows = if format.flag_underflows {
+                format!(",\"underflows\":{}", underflows)
+            } else {
+                String::new()
+            };
+            channels.push(format!(
+                "{{\"min\":{},\"max\":{},\"mean\":{},\"rms\":{}{}}}",
+                number(min),
+                number(max),
+                number(mean),
+                number(rms),
+                underflows
+            ));
+            if options.include_data {
+                let items: Vec<String> = values.iter().map(|&x| number(x as f64)).collect();
+                data.push(format!("[{}]", items.join(",")));
+            }
+        }
+        let data = if options.include_data {
+            format!(",\"data\":[{}]", data.join(","))
+        } else {
+            String::new()
+        };
+        writeln!(
+            writer,
+            "{{{}\"record\":{},\"alpha\":{},\"beta\":{},\"azimuth\":{},\"elevation\":{},\
+             \"channels\":[{}]{}}}",
+            source,
+            index,
+            number(record.alpha as f64),
+            number(record.beta as f64),
+            number(azimuth as f64),
+            number(elevation as f64),
+            channels.join(","),
+            data
+        )
+        .map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)?;
+    Ok(content.num_records())
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Column labels of the `count` values of a channel: sample indices, frequencies or the real
+/// and imaginary parts of the bins
+pub(crate) fn element_labels(
+    header: &ContentHeader,
+    count: usize,
+    options: &ExportOptions,
+) -> Vec<String> {
+    match header {
+        ContentHeader::ImpulseResponse { .. } => (0..count).map(|i| i.to_string()).collect(),
+        ContentHeader::MagnitudeSpectrum { frequencies }
+        | ContentHeader::PhaseSpectrum { frequencies } => frequencies
+            .iter()
+            .map(|&f| options.format(f as f64))
+            .collect(),
+        ContentHeader::MagnitudePhaseSpectrum { frequencies } => frequencies
+            .iter()
+            .flat_map(|&f| {
+                let f = options.format(f as f64);
+                [format!("re({})", f), format!("im({})", f)]
+            })
+            .collect(),
+        ContentHeader::DftSpectrum { .. } => (0..count / 2)
+            .flat_map(|k| [format!("re({})", k), format!("im({})", k)])
+            .collect(),
+    }
+}
+
+fn quantization_name(quantization: Quantization) -> &'static str {
+    match quantization {
+        Quantization::Int16 => "Int16",
+        Quantization::Int24 => "Int24",
+        Quantization::Float32 => "Float32",
+    }
+}
+
+fn write_error(error: std::io::Error) -> Error {
+    Error::new(format!("Failed to write export: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ring, temp_path};
+
+    fn dataset() -> Dataset {
+        let grid = ring(2);
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0, 2000.0],
+            },
+            grid,
+            1,
+            |alpha, _, _| vec![0.5, alpha / 1000.0],
+        )
+    }
+
+    #[test]
+    fn test_format_is_locale_independent() {
+        let options = ExportOptions {
+            precision: 3,
+            ..ExportOptions::default()
+        };
+        assert_eq!(options.format(1234.5678), "1234.568");
+        assert_eq!(options.format(0.5), "0.5");
+        assert_eq!(options.format(2.0), "2");
+        assert_eq!(options.format(-0.0001), "0");
+        assert_eq!(options.format(f64::NEG_INFINITY), "-inf");
+        let options = ExportOptions {
+            precision: 0,
+            ..ExportOptions::default()
+        };
+        assert_eq!(options.format(100.0), "100");
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            precision: 2,
+            ..ExportOptions::default()
+        };
+        to_csv(&dataset(), &options, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "alpha,beta,channel,1000,2000\n0,90,0,0.5,0\n180,90,0,0.5,0.18\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let mut dataset = dataset();
+        dataset.metadata.insert(
+            "DESCRIPTION".to_string(),
+            MetadataValue::String("Say \"hi\"\n".to_string()),
+        );
+        dataset
+            .metadata
+            .insert("SUBJECT".to_string(), MetadataValue::Int(7));
+        let mut output = Vec::new();
+        to_json(&dataset, &ExportOptions::default(), &mut output).unwrap();
+        let json = String::from_utf8(output).unwrap();
+        assert!(json.contains("\"metadata\":{\"DESCRIPTION\":\"Say \\\"hi\\\"\\n\",\"SUBJECT\":7}"));
+        assert!(json.starts_with("{\"content_type\":\"Magnitude Spectrum\""));
+        assert!(json.contains("\"frequencies\":[1000,2000]"));
+        assert!(json.ends_with("{\"alpha\":180,\"beta\":90,\"channels\":[[0.5,0.18]]}]}\n"));
+    }
+
+    #[test]
+    fn test_magnitude_floor() {
+        let mut dataset = dataset();
+        dataset.records[0].channels[0] = vec![0.0, f32::MIN_POSITIVE / 2.0];
+        let flagged = ExportOptions {
+            flag_underflows: true,
+            ..ExportOptions::default()
+        };
+        // Without a floor, zero and subnormal magnitudes are flagged but kept
+        assert_eq!(count_underflows(&dataset, &flagged), 2);
+        let mut output = Vec::new();
+        to_csv(&dataset, &flagged, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        assert!(csv.starts_with("alpha,beta,channel,1000,2000,underflows\n0,90,0,0,0,2\n"));
+        assert!(csv.ends_with("180,90,0,0.5,0.18,0\n"));
+
+        // -40 dB clamps everything below 0.01
+        let floored = ExportOptions {
+            magnitude_floor_db: Some(-40.0),
+            ..flagged
+        };
+        assert_eq!(count_underflows(&dataset, &floored), 2);
+        let mut output = Vec::new();
+        to_json(&dataset, &floored, &mut output).unwrap();
+        let json = String::from_utf8(output).unwrap();
+        assert!(json
+            .contains("{\"alpha\":0,\"beta\":90,\"channels\":[[0.01,0.01]],\"underflows\":[2]}"));
+        let mut output = Vec::new();
+        let unflagged = ExportOptions {
+            flag_underflows: false,
+            ..floored
+        };
+        to_json(&dataset, &unflagged, &mut output).unwrap();
+        assert!(!String::from_utf8(output).unwrap().contains("underflows"));
+
+        // Complex spectra keep their phase
+        dataset.header = ContentHeader::MagnitudePhaseSpectrum {
+            frequencies: vec![1000.0],
+        };
+        dataset.records[0].channels[0] = vec![0.0, -0.001];
+        dataset.records[1].channels[0] = vec![0.0, 0.0];
+        let mut output = Vec::new();
+        to_csv(&dataset, &floored, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        assert!(csv.contains("\n0,90,0,0,-0.01,1\n180,90,0,0.01,0,1\n"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_ndjson_records() {
+        let path = temp_path("ndjson.daff");
+        crate::writer::write_dataset(&path, &dataset()).unwrap();
+        let reader = Reader::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut output = Vec::new();
+        let count = ndjson_records(&reader, &mut output, &NdjsonOptions::default()).unwrap();
+        assert_eq!(count, 2);
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["record"], 1);
+        assert_eq!(lines[1]["alpha"], 180);
+        assert_eq!(lines[1]["channels"][0]["min"], 0.18);
+        assert_eq!(lines[1]["channels"][0]["max"], 0.5);
+        assert_eq!(lines[1]["channels"][0]["mean"], 0.34);
+        assert!(lines[1].get("data").is_none());
+        assert!(lines[1].get("source").is_none());
+
+        let options = NdjsonOptions {
+            format: ExportOptions {
+                flag_underflows: true,
+                ..ExportOptions::default()
+            },
+            include_data: true,
+            source: Some("ndjson.daff".to_string()),
+        };
+        let mut output = Vec::new();
+        ndjson_records(&reader, &mut output, &options).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(String::from_utf8(output).unwrap().lines().next().unwrap())
+                .unwrap();
+        assert_eq!(first["source"], "ndjson.daff");
+        assert_eq!(first["data"], serde_json::json!([[0.5, 0]]));
+        assert_eq!(first["channels"][0]["underflows"], 1);
+    }
+}
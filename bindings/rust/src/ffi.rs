@@ -1,6 +1,15 @@
 //! Raw FFI bindings to the OpenDAFF C wrapper library.
 //!
 //! This module contains unsafe FFI declarations. Use the safe wrappers in the parent module instead.
+//!
+//! Unwinding never crosses this boundary: every wrapper function catches C++ exceptions and
+//! reports them through `RustDAFF_GetLastError` (or aborts if the wrapper is built with
+//! `DAFFRUST_ABORT_ON_EXCEPTION`), and the C++ side never calls back into Rust, so Rust
+//! panics stay on the Rust side.
+//!
+//! The `abort-on-exception` feature sets `DAFFRUST_ABORT_ON_EXCEPTION` from cargo: the build
+//! script then compiles the wrapper itself with the define and links it statically instead of
+//! the prebuilt library, e.g. to get a core dump at the point of failure.
 
 use std::os::raw::{c_char, c_double, c_float, c_int};
 
@@ -22,6 +31,7 @@ extern "C" {
     pub fn RustDAFF_Create() -> *mut RustDAFFReaderHandle;
     pub fn RustDAFF_Destroy(handle: *mut RustDAFFReaderHandle);
     pub fn RustDAFF_OpenFile(handle: *mut RustDAFFReaderHandle, filename: *const c_char) -> bool;
+    pub fn RustDAFF_OpenBytes(handle: *mut RustDAFFReaderHandle, data: *const u8, size: usize) -> bool;
     pub fn RustDAFF_Close(handle: *mut RustDAFFReaderHandle);
     pub fn RustDAFF_IsValid(handle: *const RustDAFFReaderHandle) -> bool;
 
@@ -30,10 +40,15 @@ extern "C" {
     pub fn RustDAFF_GetQuantization(handle: *const RustDAFFReaderHandle) -> c_int;
     pub fn RustDAFF_GetNumChannels(handle: *const RustDAFFReaderHandle) -> c_int;
     pub fn RustDAFF_GetNumRecords(handle: *const RustDAFFReaderHandle) -> c_int;
+    pub fn RustDAFF_GetFileFormatVersion(handle: *const RustDAFFReaderHandle) -> c_int;
     pub fn RustDAFF_GetAlphaResolution(handle: *const RustDAFFReaderHandle) -> c_float;
     pub fn RustDAFF_GetBetaResolution(handle: *const RustDAFFReaderHandle) -> c_float;
     pub fn RustDAFF_GetAlphaPoints(handle: *const RustDAFFReaderHandle) -> c_int;
     pub fn RustDAFF_GetBetaPoints(handle: *const RustDAFFReaderHandle) -> c_int;
+    pub fn RustDAFF_GetAlphaStart(handle: *const RustDAFFReaderHandle) -> c_float;
+    pub fn RustDAFF_GetAlphaEnd(handle: *const RustDAFFReaderHandle) -> c_float;
+    pub fn RustDAFF_GetBetaStart(handle: *const RustDAFFReaderHandle) -> c_float;
+    pub fn RustDAFF_GetBetaEnd(handle: *const RustDAFFReaderHandle) -> c_float;
     pub fn RustDAFF_GetOrientationYPR(
         handle: *const RustDAFFReaderHandle,
         yaw: *mut c_float,
@@ -57,6 +72,61 @@ extern "C" {
         key: *const c_char,
         value: *mut bool,
     ) -> bool;
+    pub fn RustDAFF_GetMetadataInt(
+        handle: *const RustDAFFReaderHandle,
+        key: *const c_char,
+        value: *mut c_int,
+    ) -> bool;
+    pub fn RustDAFF_GetMetadataType(
+        handle: *const RustDAFFReaderHandle,
+        key: *const c_char,
+    ) -> c_int;
+    pub fn RustDAFF_GetMetadataDouble(
+        handle: *const RustDAFFReaderHandle,
+        key: *const c_char,
+        value: *mut c_double,
+    ) -> bool;
+    pub fn RustDAFF_GetNumMetadataKeys(handle: *const RustDAFFReaderHandle) -> c_int;
+    pub fn RustDAFF_GetMetadataKey(
+        handle: *const RustDAFFReaderHandle,
+        index: c_int,
+    ) -> *const c_char;
+
+    // Per-record metadata operations
+    pub fn RustDAFF_GetNumRecordMetadataKeys(handle: *const RustDAFFReaderHandle, record_index: c_int) -> c_int;
+    pub fn RustDAFF_GetRecordMetadataKey(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        index: c_int,
+    ) -> *const c_char;
+    pub fn RustDAFF_GetRecordMetadataType(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        key: *const c_char,
+    ) -> c_int;
+    pub fn RustDAFF_GetRecordMetadataString(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        key: *const c_char,
+    ) -> *const c_char;
+    pub fn RustDAFF_GetRecordMetadataBool(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        key: *const c_char,
+        value: *mut bool,
+    ) -> bool;
+    pub fn RustDAFF_GetRecordMetadataInt(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        key: *const c_char,
+        value: *mut c_int,
+    ) -> bool;
+    pub fn RustDAFF_GetRecordMetadataDouble(
+        handle: *const RustDAFFReaderHandle,
+        record_index: c_int,
+        key: *const c_char,
+        value: *mut c_double,
+    ) -> bool;
 
     // Content access - Impulse Response (IR)
     pub fn RustDAFF_GetContentIR(
@@ -88,6 +158,11 @@ extern "C" {
         handle: *const RustDAFFReaderHandle,
     ) -> *mut RustDAFFContentHandle;
     pub fn RustDAFF_ContentMS_GetNumFrequencies(content: *const RustDAFFContentHandle) -> c_int;
+    pub fn RustDAFF_ContentMS_GetFrequencies(
+        content: *const RustDAFFContentHandle,
+        frequencies: *mut c_float,
+        buffer_size: c_int,
+    ) -> bool;
     pub fn RustDAFF_ContentMS_GetNearestNeighbour(
         content: *const RustDAFFContentHandle,
         phi: c_double,
@@ -112,6 +187,11 @@ extern "C" {
         handle: *const RustDAFFReaderHandle,
     ) -> *mut RustDAFFContentHandle;
     pub fn RustDAFF_ContentPS_GetNumFrequencies(content: *const RustDAFFContentHandle) -> c_int;
+    pub fn RustDAFF_ContentPS_GetFrequencies(
+        content: *const RustDAFFContentHandle,
+        frequencies: *mut c_float,
+        buffer_size: c_int,
+    ) -> bool;
     pub fn RustDAFF_ContentPS_GetNearestNeighbour(
         content: *const RustDAFFContentHandle,
         phi: c_double,
@@ -136,6 +216,11 @@ extern "C" {
         handle: *const RustDAFFReaderHandle,
     ) -> *mut RustDAFFContentHandle;
     pub fn RustDAFF_ContentMPS_GetNumFrequencies(content: *const RustDAFFContentHandle) -> c_int;
+    pub fn RustDAFF_ContentMPS_GetFrequencies(
+        content: *const RustDAFFContentHandle,
+        frequencies: *mut c_float,
+        buffer_size: c_int,
+    ) -> bool;
     pub fn RustDAFF_ContentMPS_GetNearestNeighbour(
         content: *const RustDAFFContentHandle,
         phi: c_double,
@@ -162,6 +247,8 @@ extern "C" {
     ) -> *mut RustDAFFContentHandle;
     pub fn RustDAFF_ContentDFT_GetNumDFTCoeffs(content: *const RustDAFFContentHandle) -> c_int;
     pub fn RustDAFF_ContentDFT_IsSymmetric(content: *const RustDAFFContentHandle) -> bool;
+    pub fn RustDAFF_ContentDFT_GetTransformSize(content: *const RustDAFFContentHandle) -> c_int;
+    pub fn RustDAFF_ContentDFT_GetSamplerate(content: *const RustDAFFContentHandle) -> c_double;
     pub fn RustDAFF_ContentDFT_GetNearestNeighbour(
         content: *const RustDAFFContentHandle,
         phi: c_double,
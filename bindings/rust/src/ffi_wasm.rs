@@ -0,0 +1,424 @@
+//! Stand-ins for the FFI bindings on `wasm32`, where the C++ library is not available
+//!
+//! Every function fails like the wrapper does without an open file, so
+//! [`Reader::new`](crate::Reader::new) reports [`UNAVAILABLE`] and no other function is
+//! reached. DAFF data is read with the pure-Rust [`parser`](crate::parser) instead.
+
+#![allow(non_snake_case)]
+
+use std::os::raw::{c_char, c_double, c_float, c_int};
+
+#[repr(C)]
+pub struct RustDAFFReaderHandle {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct RustDAFFContentHandle {
+    _private: [u8; 0],
+}
+
+/// Error reported by every function
+const UNAVAILABLE: &[u8] =
+    b"The C++ library is not available on this target, use opendaff::parser::ParsedFile\0";
+
+// Error handling
+pub unsafe fn RustDAFF_GetLastError() -> *const c_char {
+    UNAVAILABLE.as_ptr().cast()
+}
+
+// Reader operations
+pub unsafe fn RustDAFF_Create() -> *mut RustDAFFReaderHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_Destroy(_handle: *mut RustDAFFReaderHandle) {}
+pub unsafe fn RustDAFF_OpenFile(
+    _handle: *mut RustDAFFReaderHandle,
+    _filename: *const c_char,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_OpenBytes(
+    _handle: *mut RustDAFFReaderHandle,
+    _data: *const u8,
+    _size: usize,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_Close(_handle: *mut RustDAFFReaderHandle) {}
+pub unsafe fn RustDAFF_IsValid(_handle: *const RustDAFFReaderHandle) -> bool {
+    false
+}
+
+// File properties
+pub unsafe fn RustDAFF_GetContentType(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetQuantization(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetNumChannels(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetNumRecords(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetFileFormatVersion(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetAlphaResolution(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetBetaResolution(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetAlphaPoints(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetBetaPoints(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetAlphaStart(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetAlphaEnd(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetBetaStart(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetBetaEnd(_handle: *const RustDAFFReaderHandle) -> c_float {
+    0.0
+}
+pub unsafe fn RustDAFF_GetOrientationYPR(
+    _handle: *const RustDAFFReaderHandle,
+    _yaw: *mut c_float,
+    _pitch: *mut c_float,
+    _roll: *mut c_float,
+) -> c_int {
+    -1
+}
+
+// Metadata operations
+pub unsafe fn RustDAFF_HasMetadata(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetMetadataString(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+) -> *const c_char {
+    std::ptr::null()
+}
+pub unsafe fn RustDAFF_GetMetadataFloat(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+    _value: *mut c_float,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetMetadataBool(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+    _value: *mut bool,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetMetadataInt(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+    _value: *mut c_int,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetMetadataType(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetMetadataDouble(
+    _handle: *const RustDAFFReaderHandle,
+    _key: *const c_char,
+    _value: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetNumMetadataKeys(_handle: *const RustDAFFReaderHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetMetadataKey(
+    _handle: *const RustDAFFReaderHandle,
+    _index: c_int,
+) -> *const c_char {
+    std::ptr::null()
+}
+
+// Per-record metadata operations
+pub unsafe fn RustDAFF_GetNumRecordMetadataKeys(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetRecordMetadataKey(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _index: c_int,
+) -> *const c_char {
+    std::ptr::null()
+}
+pub unsafe fn RustDAFF_GetRecordMetadataType(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _key: *const c_char,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_GetRecordMetadataString(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _key: *const c_char,
+) -> *const c_char {
+    std::ptr::null()
+}
+pub unsafe fn RustDAFF_GetRecordMetadataBool(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _key: *const c_char,
+    _value: *mut bool,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetRecordMetadataInt(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _key: *const c_char,
+    _value: *mut c_int,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_GetRecordMetadataDouble(
+    _handle: *const RustDAFFReaderHandle,
+    _record_index: c_int,
+    _key: *const c_char,
+    _value: *mut c_double,
+) -> bool {
+    false
+}
+
+// Content access - Impulse Response (IR)
+pub unsafe fn RustDAFF_GetContentIR(
+    _handle: *const RustDAFFReaderHandle,
+) -> *mut RustDAFFContentHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_ContentIR_GetFilterLength(_content: *const RustDAFFContentHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentIR_GetSamplerate(_content: *const RustDAFFContentHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentIR_GetNearestNeighbour(
+    _content: *const RustDAFFContentHandle,
+    _phi: c_double,
+    _theta: c_double,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentIR_GetRecordCoords(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _alpha: *mut c_double,
+    _beta: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentIR_GetFilterCoeffs(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _channel: c_int,
+    _coeffs: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+
+// Content access - Magnitude Spectrum (MS)
+pub unsafe fn RustDAFF_GetContentMS(
+    _handle: *const RustDAFFReaderHandle,
+) -> *mut RustDAFFContentHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_ContentMS_GetNumFrequencies(
+    _content: *const RustDAFFContentHandle,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentMS_GetFrequencies(
+    _content: *const RustDAFFContentHandle,
+    _frequencies: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentMS_GetNearestNeighbour(
+    _content: *const RustDAFFContentHandle,
+    _phi: c_double,
+    _theta: c_double,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentMS_GetRecordCoords(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _alpha: *mut c_double,
+    _beta: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentMS_GetMagnitudes(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _channel: c_int,
+    _magnitudes: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+
+// Content access - Phase Spectrum (PS)
+pub unsafe fn RustDAFF_GetContentPS(
+    _handle: *const RustDAFFReaderHandle,
+) -> *mut RustDAFFContentHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_ContentPS_GetNumFrequencies(
+    _content: *const RustDAFFContentHandle,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentPS_GetFrequencies(
+    _content: *const RustDAFFContentHandle,
+    _frequencies: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentPS_GetNearestNeighbour(
+    _content: *const RustDAFFContentHandle,
+    _phi: c_double,
+    _theta: c_double,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentPS_GetRecordCoords(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _alpha: *mut c_double,
+    _beta: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentPS_GetPhases(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _channel: c_int,
+    _phases: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+
+// Content access - Magnitude-Phase Spectrum (MPS)
+pub unsafe fn RustDAFF_GetContentMPS(
+    _handle: *const RustDAFFReaderHandle,
+) -> *mut RustDAFFContentHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_ContentMPS_GetNumFrequencies(
+    _content: *const RustDAFFContentHandle,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentMPS_GetFrequencies(
+    _content: *const RustDAFFContentHandle,
+    _frequencies: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentMPS_GetNearestNeighbour(
+    _content: *const RustDAFFContentHandle,
+    _phi: c_double,
+    _theta: c_double,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentMPS_GetRecordCoords(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _alpha: *mut c_double,
+    _beta: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentMPS_GetCoefficients(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _channel: c_int,
+    _magnitudes: *mut c_float,
+    _phases: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
+
+// Content access - DFT
+pub unsafe fn RustDAFF_GetContentDFT(
+    _handle: *const RustDAFFReaderHandle,
+) -> *mut RustDAFFContentHandle {
+    std::ptr::null_mut()
+}
+pub unsafe fn RustDAFF_ContentDFT_GetNumDFTCoeffs(_content: *const RustDAFFContentHandle) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentDFT_IsSymmetric(_content: *const RustDAFFContentHandle) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentDFT_GetTransformSize(
+    _content: *const RustDAFFContentHandle,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentDFT_GetSamplerate(
+    _content: *const RustDAFFContentHandle,
+) -> c_double {
+    0.0
+}
+pub unsafe fn RustDAFF_ContentDFT_GetNearestNeighbour(
+    _content: *const RustDAFFContentHandle,
+    _phi: c_double,
+    _theta: c_double,
+) -> c_int {
+    -1
+}
+pub unsafe fn RustDAFF_ContentDFT_GetRecordCoords(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _alpha: *mut c_double,
+    _beta: *mut c_double,
+) -> bool {
+    false
+}
+pub unsafe fn RustDAFF_ContentDFT_GetDFTCoeffs(
+    _content: *const RustDAFFContentHandle,
+    _record_index: c_int,
+    _channel: c_int,
+    _coeffs: *mut c_float,
+    _buffer_size: c_int,
+) -> bool {
+    false
+}
@@ -0,0 +1,2388 @@
+//! Spherical sampling grids
+//!
+//! DAFF files store their records on a regular equiangular grid in the data view: `alpha_points`
+//! azimuthal positions between `alpha_start` and `alpha_end`, and `beta_points` elevation rings
+//! between `beta_start` (0° = south pole) and `beta_end` (180° = north pole). The poles hold a
+//! single record only.
+//!
+//! For processing, datasets can also be [resampled](resample) to Gauss-Legendre and Lebedev
+//! grids, which integrate spherical harmonics exactly with fewer points. Datasets whose
+//! records no longer form any such grid (e.g. after [`filter_records`]) use an
+//! [`Grid::Irregular`] layout, in which every record carries its own direction.
+
+use std::fmt;
+
+use crate::analysis::{self, Interp, SpectrumDomain};
+use crate::sh;
+use crate::{ContentType, Dataset, Error, Orientation, Record, Result};
+
+/// Number of points of the supported Lebedev grids
+pub const LEBEDEV_POINTS: [usize; 7] = [6, 14, 26, 38, 50, 74, 86];
+
+/// Sampling layout of a dataset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grid {
+    /// Regular equiangular grid, records in DAFF storage order
+    Equiangular(EquiangularGrid),
+    /// Gauss-Legendre grid for spherical harmonics up to `order`
+    ///
+    /// `order + 1` beta rings at the Gauss-Legendre nodes, from south to north, with
+    /// `2 (order + 1)` alpha points each, starting at 0°.
+    GaussLegendre {
+        /// Spherical harmonic order integrated exactly (for products of two functions)
+        order: usize,
+    },
+    /// Lebedev grid with the given number of points (one of [`LEBEDEV_POINTS`])
+    Lebedev {
+        /// Number of points
+        points: usize,
+    },
+    /// Arbitrary set of directions, given by the records themselves
+    Irregular,
+}
+
+impl Grid {
+    /// Gauss-Legendre grid integrating spherical harmonics up to the given order exactly
+    pub fn gauss_legendre(order: usize) -> Self {
+        Grid::GaussLegendre { order }
+    }
+
+    /// Lebedev grid with the given number of points
+    pub fn lebedev(points: usize) -> Result<Self> {
+        if !LEBEDEV_POINTS.contains(&points) {
+            return Err(Error::new(format!(
+                "No Lebedev grid with {} points, supported are {:?}",
+                points, LEBEDEV_POINTS
+            )));
+        }
+        Ok(Grid::Lebedev { points })
+    }
+
+    /// The equiangular grid, `None` for other layouts
+    pub fn equiangular(&self) -> Option<&EquiangularGrid> {
+        match self {
+            Grid::Equiangular(grid) => Some(grid),
+            _ => None,
+        }
+    }
+
+    /// Number of records, `None` for irregular layouts
+    pub fn num_records(&self) -> Option<usize> {
+        match self {
+            Grid::Equiangular(grid) => Some(grid.num_records()),
+            Grid::GaussLegendre { order } => Some(2 * (order + 1) * (order + 1)),
+            Grid::Lebedev { points } => LEBEDEV_POINTS.contains(points).then_some(*points),
+            Grid::Irregular => None,
+        }
+    }
+
+    /// Data view coordinates (alpha, beta) of all records in order, `None` for irregular
+    /// layouts
+    pub fn directions(&self) -> Option<Vec<(f32, f32)>> {
+        self.points()
+            .map(|points| points.into_iter().map(|(direction, _)| direction).collect())
+    }
+
+    /// Solid angle (in steradians) represented by each record, summing up to 4π on a full
+    /// sphere; `None` for irregular layouts
+    ///
+    /// For equiangular grids see [`EquiangularGrid::quadrature_weights`]; Gauss-Legendre and
+    /// Lebedev grids use their quadrature weights.
+    pub fn quadrature_weights(&self) -> Option<Vec<f64>> {
+        match self {
+            Grid::Equiangular(grid) => Some(grid.quadrature_weights()),
+            _ => self
+                .points()
+                .map(|points| points.into_iter().map(|(_, weight)| weight).collect()),
+        }
+    }
+
+    /// Directions and quadrature weights of all records
+    fn points(&self) -> Option<Vec<((f32, f32), f64)>> {
+        match self {
+            Grid::Equiangular(grid) => {
+                Some(grid.directions().zip(grid.quadrature_weights()).collect())
+            }
+            Grid::GaussLegendre { order } => Some(gauss_legendre_points(order + 1)),
+            Grid::Lebedev { points } => lebedev_points(*points),
+            Grid::Irregular => None,
+        }
+    }
+}
+
+impl From<EquiangularGrid> for Grid {
+    fn from(grid: EquiangularGrid) -> Self {
+        Grid::Equiangular(grid)
+    }
+}
+
+impl TryFrom<Grid> for EquiangularGrid {
+    type Error = Error;
+
+    /// DAFF files can only store equiangular grids
+    fn try_from(grid: Grid) -> Result<Self> {
+        match grid {
+            Grid::Equiangular(grid) => Ok(grid),
+            other => Err(Error::new(format!(
+                "DAFF files can only store equiangular grids, not a {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Grid::Equiangular(grid) => write!(f, "{}", grid),
+            Grid::GaussLegendre { order } => write!(
+                f,
+                "Gauss-Legendre grid of order {} ({} records)",
+                order,
+                2 * (order + 1) * (order + 1)
+            ),
+            Grid::Lebedev { points } => write!(f, "Lebedev grid ({} records)", points),
+            Grid::Irregular => write!(f, "irregular grid"),
+        }
+    }
+}
+
+/// Regular equiangular sampling grid as described by the DAFF main header
+///
+/// All angles are in degrees and refer to the data view (alpha, beta).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EquiangularGrid {
+    /// Number of points in alpha direction
+    pub alpha_points: usize,
+    /// First alpha angle
+    pub alpha_start: f32,
+    /// Last alpha angle (360° if the full circle is covered)
+    pub alpha_end: f32,
+    /// Number of points in beta direction (including the poles)
+    pub beta_points: usize,
+    /// First beta angle (0° = south pole)
+    pub beta_start: f32,
+    /// Last beta angle (180° = north pole)
+    pub beta_end: f32,
+}
+
+impl EquiangularGrid {
+    /// Full-sphere grid with the given alpha and beta resolution in degrees
+    ///
+    /// Both resolutions have to divide the full circle (alpha) and the half circle (beta)
+    /// into whole steps. The grid includes both poles.
+    pub fn with_resolution(alpha_resolution: f32, beta_resolution: f32) -> Result<Self> {
+        Ok(Self {
+            alpha_points: whole_steps(360.0, alpha_resolution, "alpha")?,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points: whole_steps(180.0, beta_resolution, "beta")? + 1,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        })
+    }
+
+    /// Covered alpha range in degrees, taking a wrap-around at 0° into account
+    pub fn alpha_span(&self) -> f32 {
+        if self.alpha_end > self.alpha_start {
+            self.alpha_end - self.alpha_start
+        } else {
+            360.0 - self.alpha_start + self.alpha_end
+        }
+    }
+
+    /// Covered beta range in degrees
+    pub fn beta_span(&self) -> f32 {
+        self.beta_end - self.beta_start
+    }
+
+    /// Angular distance between two neighbouring alpha points in degrees
+    ///
+    /// Follows the DAFF reader: if the full circle is covered, the last point does not
+    /// coincide with the first one.
+    pub fn alpha_resolution(&self) -> f32 {
+        if self.alpha_points < 2 {
+            return 0.0;
+        }
+        let span = self.alpha_span();
+        if span == 360.0 {
+            span / self.alpha_points as f32
+        } else {
+            span / (self.alpha_points - 1) as f32
+        }
+    }
+
+    /// Angular distance between two neighbouring beta rings in degrees
+    pub fn beta_resolution(&self) -> f32 {
+        if self.beta_points < 2 {
+            0.0
+        } else {
+            self.beta_span() / (self.beta_points - 1) as f32
+        }
+    }
+
+    /// Whether the south pole (beta = 0°) is part of the grid
+    pub fn has_south_pole(&self) -> bool {
+        self.beta_start == 0.0
+    }
+
+    /// Whether the north pole (beta = 180°) is part of the grid
+    pub fn has_north_pole(&self) -> bool {
+        self.beta_end == 180.0 && (self.beta_points > 1 || !self.has_south_pole())
+    }
+
+    /// Part of the sphere covered by the records
+    pub fn coverage(&self) -> Coverage {
+        // Tolerance for rounded angles stored in files
+        const EPSILON: f32 = 1e-3;
+        if self.alpha_span() >= 360.0 - EPSILON
+            && self.beta_start <= EPSILON
+            && self.beta_end >= 180.0 - EPSILON
+        {
+            Coverage::FullSphere
+        } else {
+            Coverage::Partial {
+                alpha_start: self.alpha_start,
+                alpha_end: self.alpha_end,
+                beta_start: self.beta_start,
+                beta_end: self.beta_end,
+            }
+        }
+    }
+
+    /// Number of records stored for this grid (a single record per pole)
+    pub fn num_records(&self) -> usize {
+        let mut rings = self.beta_points;
+        let mut records = 0;
+        if self.has_south_pole() && rings > 0 {
+            rings -= 1;
+            records += 1;
+        }
+        if self.has_north_pole() && rings > 0 {
+            rings -= 1;
+            records += 1;
+        }
+        records + rings * self.alpha_points
+    }
+
+    /// Data view coordinates (alpha, beta) of the given record index
+    ///
+    /// Computed directly from the index, so walking a grid of any size record by record
+    /// stays linear.
+    pub fn record_coords(&self, record_index: usize) -> Option<(f32, f32)> {
+        if record_index >= self.num_records() {
+            return None;
+        }
+        let south_pole = self.has_south_pole() && self.beta_points > 0;
+        let (ring, alpha) = if south_pole && record_index == 0 {
+            (0, 0)
+        } else {
+            let index = record_index - south_pole as usize;
+            match index.checked_div(self.alpha_points) {
+                Some(ring) => (south_pole as usize + ring, index % self.alpha_points),
+                // Only the north pole follows
+                None => (self.beta_points - 1, 0),
+            }
+        };
+        Some(self.coords(ring, alpha))
+    }
+
+    /// Iterate over the (alpha, beta) coordinates of all records in storage order
+    pub fn directions(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.layout().map(move |(b, a)| self.coords(b, a))
+    }
+
+    /// Data view coordinates of alpha point `a` on ring `b`
+    fn coords(&self, b: usize, a: usize) -> (f32, f32) {
+        if b == 0 && self.has_south_pole() {
+            (0.0, 0.0)
+        } else {
+            (
+                wrap_alpha(self.alpha_start + a as f32 * self.alpha_resolution()),
+                self.beta_start + b as f32 * self.beta_resolution(),
+            )
+        }
+    }
+
+    /// Solid angle (in steradians) represented by each record, in storage order
+    ///
+    /// Every record covers the cell between the midpoints to its neighbours in alpha and beta
+    /// direction; the poles cover the cap up to half the beta resolution. On a full sphere the
+    /// weights sum up to 4π. Cells at the borders of partial grids extend half a resolution
+    /// step beyond the first and last point (but not beyond the poles).
+    pub fn quadrature_weights(&self) -> Vec<f64> {
+        let alpha_width = if self.alpha_span() == 360.0 {
+            std::f64::consts::TAU / self.alpha_points.max(1) as f64
+        } else {
+            (self.alpha_resolution() as f64).to_radians()
+        };
+        let half_step = self.beta_resolution() as f64 / 2.0;
+
+        self.layout()
+            .map(|(ring, _)| {
+                let beta = self.beta_start as f64 + ring as f64 * self.beta_resolution() as f64;
+                let lower = (beta - half_step).max(0.0).to_radians();
+                let upper = (beta + half_step).min(180.0).to_radians();
+                let band = lower.cos() - upper.cos();
+                if self.is_pole_ring(ring) {
+                    std::f64::consts::TAU * band
+                } else {
+                    alpha_width * band
+                }
+            })
+            .collect()
+    }
+
+    /// Iterate over the (beta ring, alpha point) indices of all records in storage order
+    pub(crate) fn layout(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.beta_points).flat_map(move |b| {
+            let points = if self.is_pole_ring(b) {
+                1
+            } else {
+                self.alpha_points
+            };
+            (0..points).map(move |a| (b, a))
+        })
+    }
+
+    pub(crate) fn is_pole_ring(&self, ring: usize) -> bool {
+        (ring == 0 && self.has_south_pole())
+            || (ring + 1 == self.beta_points && self.has_north_pole())
+    }
+
+    /// Grid spanned by a contiguous range of rings and a contiguous run of alpha points
+    fn sub_grid(&self, rings: (usize, usize), alpha: (usize, usize)) -> Self {
+        let alpha_resolution = self.alpha_resolution();
+        let beta_resolution = self.beta_resolution();
+        let (first_alpha, alpha_points) = alpha;
+        let (alpha_start, alpha_end) = if alpha_points == self.alpha_points {
+            (self.alpha_start, self.alpha_end)
+        } else {
+            let start = wrap_alpha(self.alpha_start + first_alpha as f32 * alpha_resolution);
+            (
+                start,
+                wrap_alpha(start + (alpha_points - 1) as f32 * alpha_resolution),
+            )
+        };
+        Self {
+            alpha_points,
+            alpha_start,
+            alpha_end,
+            beta_points: rings.1 - rings.0 + 1,
+            beta_start: self.beta_start + rings.0 as f32 * beta_resolution,
+            beta_end: self.beta_start + rings.1 as f32 * beta_resolution,
+        }
+    }
+}
+
+impl fmt::Display for EquiangularGrid {
+    /// Describes the resolution, covered range and number of records
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}° x {}° equiangular grid, alpha {}°..{}°, beta {}°..{}° ({} records)",
+            self.alpha_resolution(),
+            self.beta_resolution(),
+            self.alpha_start,
+            self.alpha_end,
+            self.beta_start,
+            self.beta_end,
+            self.num_records()
+        )
+    }
+}
+
+/// Part of the sphere covered by an equiangular grid, see [`EquiangularGrid::coverage`]
+///
+/// Directions outside of a partial coverage are answered with the nearest record at its
+/// border, so renderers check them to warn about extrapolated data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coverage {
+    /// The records cover all directions
+    FullSphere,
+    /// The records cover a window of the sphere, with angles in degrees (data view)
+    ///
+    /// The alpha range wraps around 0° if `alpha_start` is greater than `alpha_end`, like in
+    /// a [`Region::Window`].
+    Partial {
+        /// First alpha angle
+        alpha_start: f32,
+        /// Last alpha angle
+        alpha_end: f32,
+        /// First beta angle (0° = south pole)
+        beta_start: f32,
+        /// Last beta angle (180° = north pole)
+        beta_end: f32,
+    },
+}
+
+impl Coverage {
+    /// Whether the records cover all directions
+    pub fn is_full_sphere(&self) -> bool {
+        *self == Coverage::FullSphere
+    }
+
+    /// Whether a direction in the data view lies within the covered part
+    pub fn contains(&self, alpha: f32, beta: f32) -> bool {
+        match *self {
+            Coverage::FullSphere => true,
+            Coverage::Partial {
+                alpha_start,
+                alpha_end,
+                beta_start,
+                beta_end,
+            } => Region::Window {
+                alpha_start,
+                alpha_end,
+                beta_start,
+                beta_end,
+            }
+            .contains(&Orientation::default(), alpha, beta),
+        }
+    }
+
+    /// Whether a direction in the object view, e.g. a source position passed to the nearest
+    /// neighbour search, lies within the covered part
+    pub fn contains_direction(
+        &self,
+        orientation: &Orientation,
+        azimuth: f32,
+        elevation: f32,
+    ) -> bool {
+        let (alpha, beta) = to_data_view(orientation, azimuth, elevation);
+        self.contains(alpha, beta)
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Coverage::FullSphere => write!(f, "full sphere"),
+            Coverage::Partial {
+                alpha_start,
+                alpha_end,
+                beta_start,
+                beta_end,
+            } => write!(
+                f,
+                "alpha {}°..{}°, beta {}°..{}°",
+                alpha_start, alpha_end, beta_start, beta_end
+            ),
+        }
+    }
+}
+
+/// Directions and weights of a Gauss-Legendre grid with the given number of rings
+fn gauss_legendre_points(rings: usize) -> Vec<((f32, f32), f64)> {
+    let alpha_points = 2 * rings;
+    let alpha_weight = std::f64::consts::TAU / alpha_points as f64;
+    let mut nodes: Vec<(f64, f64)> = (0..rings)
+        .map(|i| {
+            // Newton iteration for the i-th root of the Legendre polynomial P_rings
+            let mut x = (std::f64::consts::PI * (i as f64 + 0.75) / (rings as f64 + 0.5)).cos();
+            let mut derivative = 1.0;
+            for _ in 0..100 {
+                let (mut p0, mut p1) = (1.0, x);
+                for k in 2..=rings {
+                    let p2 = ((2 * k - 1) as f64 * x * p1 - (k - 1) as f64 * p0) / k as f64;
+                    p0 = p1;
+                    p1 = p2;
+                }
+                derivative = rings as f64 * (x * p1 - p0) / (x * x - 1.0);
+                let step = p1 / derivative;
+                x -= step;
+                if step.abs() < 1e-15 {
+                    break;
+                }
+            }
+            (x, 2.0 / ((1.0 - x * x) * derivative * derivative))
+        })
+        .collect();
+    // Ascending beta, i.e. from the south pole (cos beta = -1 in the data view convention)
+    nodes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    nodes
+        .iter()
+        .flat_map(|&(x, weight)| {
+            let beta = (-x).acos().to_degrees().clamp(0.0, 180.0) as f32;
+            (0..alpha_points).map(move |a| {
+                let alpha = (a as f64 * 360.0 / alpha_points as f64) as f32;
+                ((alpha, beta), weight * alpha_weight)
+            })
+        })
+        .collect()
+}
+
+/// Orbits of the octahedral group generating the Lebedev grids
+#[derive(Clone, Copy)]
+enum Orbit {
+    /// The 6 vertices of the octahedron
+    A1,
+    /// The 12 edge midpoints
+    A2,
+    /// The 8 face centers
+    A3,
+    /// 24 points (±l, ±l, ±m)
+    B(f64),
+    /// 24 points (±p, ±q, 0)
+    C(f64),
+}
+
+/// Orbits and weights (relative to the full sphere) of the supported Lebedev grids
+fn lebedev_rule(points: usize) -> Option<&'static [(Orbit, f64)]> {
+    use Orbit::*;
+    let rule: &'static [(Orbit, f64)] = match points {
+        6 => &[(A1, 1.0 / 6.0)],
+        14 => &[(A1, 1.0 / 15.0), (A3, 3.0 / 40.0)],
+        26 => &[(A1, 1.0 / 21.0), (A2, 4.0 / 105.0), (A3, 27.0 / 840.0)],
+        38 => &[
+            (A1, 1.0 / 105.0),
+            (A3, 9.0 / 280.0),
+            (C(0.4597008433809831), 1.0 / 35.0),
+        ],
+        50 => &[
+            (A1, 4.0 / 315.0),
+            (A2, 64.0 / 2835.0),
+            (A3, 27.0 / 1280.0),
+            (B(0.3015113445777636), 14641.0 / 725760.0),
+        ],
+        74 => &[
+            (A1, 0.5130671797338464e-3),
+            (A2, 0.1660406956574204e-1),
+            (A3, -0.2958603896103896e-1),
+            (B(0.4803844614152614), 0.2657620708215946e-1),
+            (C(0.3207726489807764), 0.1652217099371571e-1),
+        ],
+        86 => &[
+            (A1, 0.1154401154401154e-1),
+            (A3, 0.1194390908585628e-1),
+            (B(0.3696028464541502), 0.111105557106034e-1),
+            (B(0.6943540066026664), 0.1187650129453714e-1),
+            (C(0.3742430390903412), 0.1181230374690448e-1),
+        ],
+        _ => return None,
+    };
+    Some(rule)
+}
+
+/// Directions and weights of a Lebedev grid
+fn lebedev_points(points: usize) -> Option<Vec<((f32, f32), f64)>> {
+    let mut vectors = Vec::with_capacity(points);
+    for &(orbit, weight) in lebedev_rule(points)? {
+        let generators: Vec<[f64; 3]> = match orbit {
+            Orbit::A1 => vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Orbit::A2 => {
+                let a = std::f64::consts::FRAC_1_SQRT_2;
+                vec![[0.0, a, a], [a, 0.0, a], [a, a, 0.0]]
+            }
+            Orbit::A3 => {
+                let a = 1.0 / 3f64.sqrt();
+                vec![[a, a, a]]
+            }
+            Orbit::B(l) => {
+                let m = (1.0 - 2.0 * l * l).sqrt();
+                vec![[l, l, m], [l, m, l], [m, l, l]]
+            }
+            Orbit::C(p) => {
+                let q = (1.0 - p * p).sqrt();
+                vec![
+                    [p, q, 0.0],
+                    [q, p, 0.0],
+                    [p, 0.0, q],
+                    [q, 0.0, p],
+                    [0.0, p, q],
+                    [0.0, q, p],
+                ]
+            }
+        };
+        for generator in generators {
+            // All sign combinations of the non-zero coordinates
+            for signs in 0..8 {
+                let vector: [f64; 3] = std::array::from_fn(|i| {
+                    if signs & (1 << i) != 0 {
+                        -generator[i]
+                    } else {
+                        generator[i]
+                    }
+                });
+                let redundant = (0..3).any(|i| signs & (1 << i) != 0 && generator[i] == 0.0);
+                if !redundant {
+                    vectors.push((vector, weight));
+                }
+            }
+        }
+    }
+
+    let sphere = 4.0 * std::f64::consts::PI;
+    Some(
+        vectors
+            .into_iter()
+            .map(|([x, y, z], weight)| {
+                let alpha = y.atan2(x).to_degrees().rem_euclid(360.0);
+                let alpha = if alpha >= 360.0 - 1e-9 { 0.0 } else { alpha };
+                let beta = (-z).clamp(-1.0, 1.0).acos().to_degrees();
+                ((alpha as f32, beta as f32), weight * sphere)
+            })
+            .collect(),
+    )
+}
+
+/// Object view coordinates (azimuth, elevation) of a data view direction (alpha, beta)
+///
+/// Applies the yaw-pitch-roll orientation of the dataset like the DAFF reader does. All
+/// angles are in degrees.
+pub fn to_object_view(orientation: &Orientation, alpha: f32, beta: f32) -> (f32, f32) {
+    let (sy, cy) = (orientation.yaw as f64).to_radians().sin_cos();
+    let (sp, cp) = (orientation.pitch as f64).to_radians().sin_cos();
+    let (sr, cr) = (orientation.roll as f64).to_radians().sin_cos();
+    let t1 = cy * cr - sy * sp * sr;
+    let t2 = cy * sr + sy * sp * cr;
+    let t3 = sy * cp;
+    let t4 = -sy * cr - cy * sp * sr;
+    let t5 = -sy * sr + cy * sp * cr;
+    let t6 = cy * cp;
+    let t7 = cp * sr;
+    let t8 = cp * cr;
+    let t9 = sp;
+
+    let (sa, ca) = (alpha as f64).to_radians().sin_cos();
+    let (se, ce) = (beta as f64 - 90.0).to_radians().sin_cos();
+    let azimuth =
+        (t1 * sa * ce + t7 * se + t4 * ca * ce).atan2(t3 * sa * ce + t9 * se + t6 * ca * ce);
+    let elevation = -(t2 * sa * ce - t8 * se + t5 * ca * ce)
+        .clamp(-1.0, 1.0)
+        .asin();
+    (azimuth.to_degrees() as f32, elevation.to_degrees() as f32)
+}
+
+/// Data view coordinates (alpha, beta) of an object view direction (azimuth, elevation)
+///
+/// Inverse of [`to_object_view`]. All angles are in degrees; alpha is in [0°, 360°).
+pub fn to_data_view(orientation: &Orientation, azimuth: f32, elevation: f32) -> (f32, f32) {
+    DataViewRotation::new(orientation).apply(azimuth, elevation)
+}
+
+/// Precomputed rotation of [`to_data_view`] for converting many directions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DataViewRotation([f64; 9]);
+
+impl DataViewRotation {
+    pub(crate) fn new(orientation: &Orientation) -> Self {
+        let (sy, cy) = (orientation.yaw as f64).to_radians().sin_cos();
+        let (sp, cp) = (orientation.pitch as f64).to_radians().sin_cos();
+        let (sr, cr) = (orientation.roll as f64).to_radians().sin_cos();
+        Self([
+            cy * cr - sy * sp * sr,
+            cy * sr + sy * sp * cr,
+            sy * cp,
+            -sy * cr - cy * sp * sr,
+            -sy * sr + cy * sp * cr,
+            cy * cp,
+            cp * sr,
+            cp * cr,
+            sp,
+        ])
+    }
+
+    /// Data view coordinates (alpha, beta) of an object view direction (azimuth, elevation)
+    #[inline]
+    pub(crate) fn apply(&self, azimuth: f32, elevation: f32) -> (f32, f32) {
+        let [t1, t2, t3, t4, t5, t6, t7, t8, t9] = self.0;
+        // The rotation of to_object_view is orthogonal, so its transpose undoes it
+        let (sa, ca) = (azimuth as f64).to_radians().sin_cos();
+        let (se, ce) = (elevation as f64).to_radians().sin_cos();
+        let (u0, u1, u2) = (sa * ce, ca * ce, -se);
+        let x = t1 * u0 + t3 * u1 + t2 * u2;
+        let y = t7 * u0 + t9 * u1 - t8 * u2;
+        let z = t4 * u0 + t6 * u1 + t5 * u2;
+        let alpha = wrap_alpha((x.atan2(z).to_degrees() as f32).rem_euclid(360.0));
+        let beta = y.clamp(-1.0, 1.0).asin().to_degrees() + 90.0;
+        (alpha, beta as f32)
+    }
+}
+
+/// Reduce a dataset to the records whose direction satisfies a predicate
+///
+/// The predicate receives the object view azimuth and elevation in degrees, e.g.
+/// `|_, el| el >= -40.0`. If the remaining records still form an equiangular grid (complete
+/// rings or a common contiguous alpha range on a contiguous range of rings), the result keeps
+/// an equiangular grid, otherwise it switches to [`Grid::Irregular`].
+pub fn filter_records<F>(dataset: &Dataset, mut predicate: F) -> Dataset
+where
+    F: FnMut(f32, f32) -> bool,
+{
+    let keep: Vec<bool> = dataset
+        .records
+        .iter()
+        .map(|r| {
+            let (azimuth, elevation) = to_object_view(&dataset.orientation, r.alpha, r.beta);
+            predicate(azimuth, elevation)
+        })
+        .collect();
+
+    let regular = match dataset.grid {
+        Grid::Equiangular(grid) if grid.num_records() == dataset.records.len() => {
+            regular_subset(&grid, &keep)
+        }
+        _ => None,
+    };
+    let (grid, order) = match regular {
+        Some((grid, order)) => (Grid::Equiangular(grid), order),
+        None => (
+            Grid::Irregular,
+            (0..keep.len()).filter(|&i| keep[i]).collect(),
+        ),
+    };
+
+    subset(dataset, grid, &order)
+}
+
+/// Region of the sphere selected by [`crop`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    /// Window between two alpha and two beta angles in degrees (data view)
+    ///
+    /// The alpha range wraps around 0° if `alpha_start` is greater than `alpha_end`, e.g.
+    /// 330°..30°, and covers the full circle if `alpha_end` is 360° or more past
+    /// `alpha_start`, e.g. 0°..360°. Poles lie within the window if their beta angle does.
+    Window {
+        /// First alpha angle
+        alpha_start: f32,
+        /// Last alpha angle
+        alpha_end: f32,
+        /// First beta angle (0° = south pole)
+        beta_start: f32,
+        /// Last beta angle
+        beta_end: f32,
+    },
+    /// Cone around a direction in the object view
+    Cone {
+        /// Azimuth of the cone axis in degrees
+        azimuth: f32,
+        /// Elevation of the cone axis in degrees
+        elevation: f32,
+        /// Angle between the axis and the edge of the cone in degrees
+        half_angle: f32,
+    },
+}
+
+impl Region {
+    /// Whether a record direction (data view) lies within the region
+    pub fn contains(&self, orientation: &Orientation, alpha: f32, beta: f32) -> bool {
+        // Tolerance for directions on the boundary
+        const EPSILON: f32 = 1e-3;
+        match *self {
+            Region::Window {
+                alpha_start,
+                alpha_end,
+                beta_start,
+                beta_end,
+            } => {
+                if beta < beta_start - EPSILON || beta > beta_end + EPSILON {
+                    return false;
+                }
+                if beta <= EPSILON
+                    || beta >= 180.0 - EPSILON
+                    || alpha_end - alpha_start >= 360.0 - EPSILON
+                {
+                    return true;
+                }
+                let offset = |a: f32| (a - alpha_start).rem_euclid(360.0);
+                let width = offset(alpha_end);
+                let position = offset(alpha);
+                position <= width + EPSILON || position >= 360.0 - EPSILON
+            }
+            Region::Cone {
+                azimuth,
+                elevation,
+                half_angle,
+            } => {
+                let axis = object_vector(azimuth, elevation);
+                let (azimuth, elevation) = to_object_view(orientation, alpha, beta);
+                let direction = object_vector(azimuth, elevation);
+                let cos = axis.iter().zip(&direction).map(|(a, b)| a * b).sum::<f64>();
+                cos.clamp(-1.0, 1.0).acos().to_degrees() <= (half_angle + EPSILON) as f64
+            }
+        }
+    }
+}
+
+/// Crop a dataset to a region of the sphere
+///
+/// Keeps the smallest window of the equiangular grid (a contiguous range of rings and a
+/// contiguous alpha run) that contains every record within the region, so the result still
+/// has an equiangular grid with updated ranges and can be written to a DAFF file. Regions
+/// that are not aligned with the grid, like cones, therefore keep some records outside of
+/// them. The dataset needs a complete equiangular grid.
+///
+/// ```
+/// use opendaff::grid::{self, Region};
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let dataset = Dataset::from_fn(
+///     ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+///     EquiangularGrid::with_resolution(10.0, 10.0)?,
+///     1,
+///     |_, _, _| vec![1.0],
+/// );
+/// let front = grid::crop(
+///     &dataset,
+///     &Region::Cone { azimuth: 0.0, elevation: 0.0, half_angle: 30.0 },
+/// )?;
+/// assert_eq!(front.num_records(), 7 * 7);
+/// # Ok(())
+/// # }
+/// ```
+pub fn crop(dataset: &Dataset, region: &Region) -> Result<Dataset> {
+    let grid = match dataset.grid {
+        Grid::Equiangular(grid) if grid.num_records() == dataset.records.len() => grid,
+        _ => return Err(Error::new("Cropping requires a complete equiangular grid")),
+    };
+    let positions: Vec<(usize, usize)> = grid.layout().collect();
+    let selected: Vec<(usize, usize)> = positions
+        .iter()
+        .zip(&dataset.records)
+        .filter(|(_, r)| region.contains(&dataset.orientation, r.alpha, r.beta))
+        .map(|(&position, _)| position)
+        .collect();
+    let (Some(first_ring), Some(last_ring)) = (
+        selected.iter().map(|p| p.0).min(),
+        selected.iter().map(|p| p.0).max(),
+    ) else {
+        return Err(Error::new("No record lies within the region"));
+    };
+
+    let mut alpha: Vec<usize> = selected
+        .iter()
+        .filter(|p| !grid.is_pole_ring(p.0))
+        .map(|p| p.1)
+        .collect();
+    alpha.sort_unstable();
+    alpha.dedup();
+    let (first_alpha, alpha_points) =
+        covering_run(&alpha, grid.alpha_points, grid.alpha_span() == 360.0);
+    let keep: Vec<bool> = positions
+        .iter()
+        .map(|&(ring, a)| {
+            (first_ring..=last_ring).contains(&ring)
+                && (grid.is_pole_ring(ring)
+                    || (a + grid.alpha_points - first_alpha) % grid.alpha_points < alpha_points)
+        })
+        .collect();
+
+    let (grid, order) = regular_subset(&grid, &keep)
+        .ok_or_else(|| Error::new("Cropped records do not form an equiangular grid"))?;
+    Ok(subset(dataset, Grid::Equiangular(grid), &order))
+}
+
+/// Dataset with the given records of another one on a new grid
+///
+/// Record directions follow an equiangular grid, which only changes the alpha angle of the
+/// north pole.
+fn subset(dataset: &Dataset, grid: Grid, order: &[usize]) -> Dataset {
+    let mut records: Vec<Record> = order.iter().map(|&i| dataset.records[i].clone()).collect();
+    if let Grid::Equiangular(grid) = grid {
+        for (record, (alpha, beta)) in records.iter_mut().zip(grid.directions()) {
+            (record.alpha, record.beta) = (alpha, beta);
+        }
+    }
+    Dataset {
+        header: dataset.header.clone(),
+        quantization: dataset.quantization,
+        grid,
+        orientation: dataset.orientation,
+        metadata: dataset.metadata.clone(),
+        records,
+    }
+}
+
+/// Coarser grid suggested by [`recommend_resolution`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recommendation {
+    /// Recommended grid, covering the same range as the dataset's grid with a subset of its
+    /// points
+    pub grid: EquiangularGrid,
+    /// Mean error of reconstructing the records that the grid leaves out in dB (-∞ if it
+    /// keeps all records)
+    pub error_db: f32,
+}
+
+/// Neighbours used to reconstruct left out records when recommending a resolution
+const RECOMMENDATION_NEIGHBOURS: usize = 4;
+
+/// Suggest the coarsest grid that represents a dataset within an error budget
+///
+/// Tries every grid that keeps every n-th alpha point and every m-th beta ring of the
+/// dataset's equiangular grid. The records left out are reconstructed from the kept ones
+/// by inverse distance weighting of the four closest records (see
+/// [`analysis::reconstruction_error`](crate::analysis::reconstruction_error)) and their
+/// mean error must not exceed `max_error_db`. Of all grids within the budget, the one with
+/// the fewest records is recommended; the dataset's own grid always qualifies.
+///
+/// The dataset needs a complete equiangular grid. Phase spectra are not supported.
+pub fn recommend_resolution(dataset: &Dataset, max_error_db: f32) -> Result<Recommendation> {
+    recommend(dataset, max_error_db).map(|(recommendation, _)| recommendation)
+}
+
+/// Recommended grid and the indices of its records in the dataset
+fn recommend(dataset: &Dataset, max_error_db: f32) -> Result<(Recommendation, Vec<usize>)> {
+    let grid = match dataset.grid {
+        Grid::Equiangular(grid) if grid.num_records() == dataset.records.len() => grid,
+        _ => {
+            return Err(Error::new(
+                "Resolution recommendations require a complete equiangular grid",
+            ))
+        }
+    };
+    if max_error_db.is_nan() {
+        return Err(Error::new("Invalid error budget"));
+    }
+    if dataset.content_type() == ContentType::PhaseSpectrum {
+        return Err(Error::new(
+            "Interpolation errors of phase spectra are not supported",
+        ));
+    }
+
+    let full_circle = grid.alpha_span() == 360.0;
+    let alpha_steps = if full_circle {
+        grid.alpha_points
+    } else {
+        grid.alpha_points.saturating_sub(1)
+    };
+    let beta_steps = grid.beta_points.saturating_sub(1);
+    let factors = |steps: usize| (1..=steps.max(1)).filter(move |f| steps % f == 0);
+
+    // Coarsest candidates first, the first one within the budget wins
+    let mut candidates: Vec<(EquiangularGrid, Vec<usize>)> = factors(alpha_steps)
+        .flat_map(|a| factors(beta_steps).map(move |b| (a, b)))
+        .filter(|&factors| factors != (1, 1))
+        .filter_map(|(a, b)| subsample(&grid, a, b))
+        .collect();
+    candidates.sort_by_key(|(coarse, _)| coarse.num_records());
+    let method = Interp::InverseDistance {
+        neighbours: RECOMMENDATION_NEIGHBOURS,
+    };
+    for (coarse, order) in candidates {
+        let sparse = subset(dataset, Grid::Equiangular(coarse), &order);
+        let report = crate::analysis::reconstruction_error(dataset, &sparse, method)?;
+
+        let mut kept = vec![false; dataset.records.len()];
+        for &i in &order {
+            kept[i] = true;
+        }
+        let errors: Vec<f64> = report
+            .directions
+            .iter()
+            .zip(&kept)
+            .filter(|(_, &k)| !k)
+            .flat_map(|(d, _)| d.channels.iter().map(|&e| 10f64.powf(e as f64 / 10.0)))
+            .collect();
+        let error_db = (10.0 * (errors.iter().sum::<f64>() / errors.len() as f64).log10()) as f32;
+        if error_db <= max_error_db {
+            let recommendation = Recommendation {
+                grid: coarse,
+                error_db,
+            };
+            return Ok((recommendation, order));
+        }
+    }
+
+    let recommendation = Recommendation {
+        grid,
+        error_db: f32::NEG_INFINITY,
+    };
+    Ok((recommendation, (0..dataset.records.len()).collect()))
+}
+
+/// Reduce a dataset to the coarsest grid within an error budget
+///
+/// Combines [`recommend_resolution`] with picking the records of the recommended grid.
+///
+/// ```
+/// use opendaff::grid;
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// // Smooth directivity measured on a dense grid
+/// let dense = Dataset::from_fn(
+///     ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+///     EquiangularGrid::with_resolution(10.0, 10.0)?,
+///     1,
+///     |alpha, _, _| vec![2.0 + alpha.to_radians().cos()],
+/// );
+/// let sparse = grid::downsample_to_error_budget(&dense, -30.0)?;
+/// assert!(sparse.num_records() < dense.num_records());
+/// # Ok(())
+/// # }
+/// ```
+pub fn downsample_to_error_budget(dataset: &Dataset, max_error_db: f32) -> Result<Dataset> {
+    let (recommendation, order) = recommend(dataset, max_error_db)?;
+    Ok(subset(
+        dataset,
+        Grid::Equiangular(recommendation.grid),
+        &order,
+    ))
+}
+
+/// Grid keeping every `alpha_factor`-th alpha point and every `beta_factor`-th ring, and the
+/// indices of its records in the original grid
+fn subsample(
+    grid: &EquiangularGrid,
+    alpha_factor: usize,
+    beta_factor: usize,
+) -> Option<(EquiangularGrid, Vec<usize>)> {
+    let coarse = EquiangularGrid {
+        alpha_points: if grid.alpha_span() == 360.0 {
+            grid.alpha_points / alpha_factor
+        } else {
+            (grid.alpha_points - 1) / alpha_factor + 1
+        },
+        beta_points: (grid.beta_points - 1) / beta_factor + 1,
+        ..*grid
+    };
+    let index: std::collections::HashMap<(usize, usize), usize> =
+        grid.layout().enumerate().map(|(i, p)| (p, i)).collect();
+    let order = coarse
+        .layout()
+        .map(|(ring, a)| {
+            let ring = ring * beta_factor;
+            let a = if grid.is_pole_ring(ring) {
+                0
+            } else {
+                a * alpha_factor
+            };
+            index.get(&(ring, a)).copied()
+        })
+        .collect::<Option<Vec<usize>>>()?;
+
+    // Guard against rounding effects (the alpha angle of a pole is arbitrary)
+    let directions: Vec<(f32, f32)> = grid.directions().collect();
+    let matches = coarse.layout().zip(coarse.directions()).zip(&order).all(
+        |(((ring, _), (alpha, beta)), &i)| {
+            (coarse.is_pole_ring(ring) || angle_eq(alpha, directions[i].0))
+                && angle_eq(beta, directions[i].1)
+        },
+    );
+    matches.then_some((coarse, order))
+}
+
+/// Number of steps of the given resolution in a span of degrees, if it divides the span
+fn whole_steps(span: f32, resolution: f32, name: &str) -> Result<usize> {
+    let steps = span / resolution;
+    if !resolution.is_finite()
+        || resolution <= 0.0
+        || steps.round() < 1.0
+        || (steps - steps.round()).abs() > 1e-3
+    {
+        return Err(Error::new(format!(
+            "A {} resolution of {}° does not divide {}° into whole steps",
+            name, resolution, span
+        )));
+    }
+    Ok(steps.round() as usize)
+}
+
+/// How [`downsample_grid`] derives the records of the coarser grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Downsampling {
+    /// Take the record closest to each point of the coarser grid
+    #[default]
+    Nearest,
+    /// Average all records closest to each point of the coarser grid, weighted by their solid
+    /// angle
+    ///
+    /// Magnitude spectra are averaged in the power domain, complex spectra and impulse
+    /// responses linearly. Averaging impulse responses with different delays blurs them, so
+    /// time-align them first. Phase spectra cannot be averaged.
+    Average,
+}
+
+/// Reduce a dataset to a coarser equiangular grid covering the same range
+///
+/// The resolutions in degrees have to divide the covered alpha and beta ranges into whole
+/// steps and must not be finer than the current ones. E.g. a 1° measurement becomes a 5°
+/// set for mobile use with `downsample_grid(&dense, 5.0, 5.0, Downsampling::Nearest)`, to be
+/// written with [`writer::write_dataset`](crate::writer::write_dataset). Grids with a single
+/// alpha point or beta ring keep it; the corresponding resolution is ignored.
+pub fn downsample_grid(
+    dataset: &Dataset,
+    alpha_resolution: f32,
+    beta_resolution: f32,
+    method: Downsampling,
+) -> Result<Dataset> {
+    let Grid::Equiangular(grid) = dataset.grid else {
+        return Err(Error::new("Downsampling requires an equiangular grid"));
+    };
+    if grid.num_records() != dataset.num_records() {
+        return Err(Error::new(format!(
+            "The grid describes {} records, the dataset holds {}",
+            grid.num_records(),
+            dataset.num_records()
+        )));
+    }
+    let coarser = |current: f32, resolution: f32, name: &str| {
+        if resolution < current - 1e-3 {
+            return Err(Error::new(format!(
+                "A {} resolution of {}° is finer than the current {}°",
+                name, resolution, current
+            )));
+        }
+        Ok(())
+    };
+
+    let full_circle = grid.alpha_span() == 360.0;
+    let alpha_points = if grid.alpha_points < 2 {
+        grid.alpha_points
+    } else {
+        coarser(grid.alpha_resolution(), alpha_resolution, "alpha")?;
+        let steps = whole_steps(grid.alpha_span(), alpha_resolution, "alpha")?;
+        if full_circle {
+            steps
+        } else {
+            steps + 1
+        }
+    };
+    let beta_points = if grid.beta_points < 2 {
+        grid.beta_points
+    } else {
+        coarser(grid.beta_resolution(), beta_resolution, "beta")?;
+        whole_steps(grid.beta_span(), beta_resolution, "beta")? + 1
+    };
+    let coarse = EquiangularGrid {
+        alpha_points,
+        beta_points,
+        ..grid
+    };
+
+    // Index of the closest point of the `to` grid along each axis
+    let closest = |from: &EquiangularGrid, to: &EquiangularGrid, (ring, a): (usize, usize)| {
+        let step = |index: usize, from: f32, to: f32, points: usize| {
+            if points < 2 {
+                return 0;
+            }
+            ((index as f32 * from / to).round() as usize).min(points - 1)
+        };
+        let ring = step(
+            ring,
+            from.beta_resolution(),
+            to.beta_resolution(),
+            to.beta_points,
+        );
+        let a = if to.is_pole_ring(ring) || to.alpha_points < 2 {
+            0
+        } else if full_circle {
+            (a as f32 * from.alpha_resolution() / to.alpha_resolution()).round() as usize
+                % to.alpha_points
+        } else {
+            step(
+                a,
+                from.alpha_resolution(),
+                to.alpha_resolution(),
+                to.alpha_points,
+            )
+        };
+        (ring, a)
+    };
+
+    let records = match method {
+        Downsampling::Nearest => {
+            let index: std::collections::HashMap<(usize, usize), usize> =
+                grid.layout().enumerate().map(|(i, p)| (p, i)).collect();
+            coarse
+                .layout()
+                .zip(coarse.directions())
+                .map(|(position, (alpha, beta))| {
+                    let position = closest(&coarse, &grid, position);
+                    let mut record = dataset.records[index[&position]].clone();
+                    (record.alpha, record.beta) = (alpha, beta);
+                    record
+                })
+                .collect()
+        }
+        Downsampling::Average => {
+            average_cells(dataset, &grid, &coarse, |p| closest(&grid, &coarse, p))?
+        }
+    };
+
+    Ok(Dataset {
+        header: dataset.header.clone(),
+        quantization: dataset.quantization,
+        grid: Grid::Equiangular(coarse),
+        orientation: dataset.orientation,
+        metadata: dataset.metadata.clone(),
+        records,
+    })
+}
+
+/// Records of the `coarse` grid averaging the records of `grid` that `cell` assigns to them
+fn average_cells<F>(
+    dataset: &Dataset,
+    grid: &EquiangularGrid,
+    coarse: &EquiangularGrid,
+    cell: F,
+) -> Result<Vec<Record>>
+where
+    F: Fn((usize, usize)) -> (usize, usize),
+{
+    let power = match dataset.content_type() {
+        ContentType::MagnitudeSpectrum => true,
+        ContentType::PhaseSpectrum => return Err(Error::new("Phase spectra cannot be averaged")),
+        _ => false,
+    };
+    let weights = if grid.beta_points > 1 {
+        grid.quadrature_weights()
+    } else {
+        vec![1.0; grid.num_records()]
+    };
+    let index: std::collections::HashMap<(usize, usize), usize> =
+        coarse.layout().enumerate().map(|(i, p)| (p, i)).collect();
+
+    let (num_channels, num_elements) = (dataset.num_channels(), dataset.elements_per_record());
+    let mut sums = vec![vec![vec![0.0f64; num_elements]; num_channels]; coarse.num_records()];
+    let mut totals = vec![0.0f64; coarse.num_records()];
+    for ((position, record), &weight) in grid.layout().zip(&dataset.records).zip(&weights) {
+        let target = index[&cell(position)];
+        totals[target] += weight;
+        for (sum, channel) in sums[target].iter_mut().zip(&record.channels) {
+            for (s, &x) in sum.iter_mut().zip(channel) {
+                let x = x as f64;
+                *s += weight * if power { x * x } else { x };
+            }
+        }
+    }
+
+    Ok(coarse
+        .directions()
+        .zip(sums)
+        .zip(totals)
+        .map(|(((alpha, beta), sums), total)| Record {
+            alpha,
+            beta,
+            channels: sums
+                .into_iter()
+                .map(|sum| {
+                    sum.into_iter()
+                        .map(|s| {
+                            let mean = s / total;
+                            (if power { mean.sqrt() } else { mean }) as f32
+                        })
+                        .collect()
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Per-record solid-angle weights for spherical integration of a dataset
+///
+/// See [`Grid::quadrature_weights`]. Energy averages over directions (e.g. for
+/// diffuse-field equalization or the directivity index) are `Σ wᵢ·xᵢ / Σ wᵢ`. Irregular
+/// layouts and equiangular grids with a single beta ring are not supported.
+pub fn quadrature_weights(dataset: &Dataset) -> Result<Vec<f64>> {
+    if let Grid::Equiangular(grid) = dataset.grid {
+        if grid.beta_points < 2 {
+            return Err(Error::new(
+                "Quadrature weights require at least two beta rings",
+            ));
+        }
+    }
+    match dataset.grid.quadrature_weights() {
+        Some(weights) if weights.len() == dataset.records.len() => Ok(weights),
+        _ => Err(Error::new(
+            "Quadrature weights require a regular sampling grid",
+        )),
+    }
+}
+
+/// Relative Tikhonov regularization of spherical harmonic resampling
+pub(crate) const SH_REGULARIZATION: f64 = 1e-3;
+
+/// Resample a dataset to another sampling grid
+///
+/// Fits spherical harmonics of the given order to all records (per channel and element,
+/// least squares with light regularization) and evaluates them at the directions of the
+/// target grid. The source needs at least `(order + 1)²` well distributed records. Phase
+/// spectra cannot be resampled this way, since their values wrap around.
+///
+/// ```
+/// use opendaff::grid::{self, Grid};
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let source = Dataset::from_fn(
+///     ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+///     EquiangularGrid::with_resolution(10.0, 10.0)?,
+///     1,
+///     |_, _, _| vec![1.0],
+/// );
+/// let resampled = grid::resample(&source, &Grid::lebedev(86)?, 5)?;
+/// assert_eq!(resampled.num_records(), 86);
+/// # Ok(())
+/// # }
+/// ```
+pub fn resample(dataset: &Dataset, target: &Grid, order: usize) -> Result<Dataset> {
+    if dataset.content_type() == ContentType::PhaseSpectrum {
+        return Err(Error::new(
+            "Phase spectra cannot be resampled with spherical harmonics",
+        ));
+    }
+    let directions = target
+        .directions()
+        .ok_or_else(|| Error::new("Cannot resample to an irregular grid"))?;
+    Ok(Dataset {
+        header: dataset.header.clone(),
+        quantization: dataset.quantization,
+        grid: *target,
+        orientation: dataset.orientation,
+        metadata: dataset.metadata.clone(),
+        records: evaluate_sh(dataset, &directions, order)?,
+    })
+}
+
+/// Rotate a dataset by an orientation offset and rebake its records
+///
+/// The result keeps the grid, header and orientation of the dataset. Its records hold the
+/// data the original dataset would show if `offset` were its orientation (applied in the data
+/// view, beneath the dataset's own orientation), so a measurement taken on a rig mounted with
+/// a known misalignment can be brought back into the reference frame and written with
+/// [`writer::write_dataset`](crate::writer::write_dataset).
+///
+/// Rotated directions rarely hit a grid point exactly. [`Interp::NearestNeighbour`] takes the
+/// closest record, the other methods interpolate in the given `domain` like
+/// [`analysis::interpolate`].
+///
+/// ```no_run
+/// use opendaff::analysis::{Interp, SpectrumDomain};
+/// use opendaff::{grid, writer, Dataset, Orientation, Reader};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let reader = Reader::open("hrtf.daff")?;
+/// let dataset = Dataset::from_reader(&reader)?;
+/// // The dummy head was turned 2° to the left on the turntable
+/// let offset = Orientation { yaw: -2.0, ..Orientation::default() };
+/// let method = Interp::InverseDistance { neighbours: 3 };
+/// let rotated = grid::rotate(&dataset, &offset, method, SpectrumDomain::UnwrappedPhase)?;
+/// writer::write_dataset("hrtf_aligned.daff", &rotated)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn rotate(
+    dataset: &Dataset,
+    offset: &Orientation,
+    method: Interp,
+    domain: SpectrumDomain,
+) -> Result<Dataset> {
+    let identity = Orientation::default();
+    let sources: Vec<(f32, f32)> = dataset
+        .records
+        .iter()
+        .map(|r| {
+            let (azimuth, elevation) = to_object_view(&identity, r.alpha, r.beta);
+            to_data_view(offset, azimuth, elevation)
+        })
+        .collect();
+    let channels = analysis::interpolate_many(dataset, &sources, method, domain)?;
+
+    Ok(Dataset {
+        header: dataset.header.clone(),
+        quantization: dataset.quantization,
+        grid: dataset.grid,
+        orientation: dataset.orientation,
+        metadata: dataset.metadata.clone(),
+        records: dataset
+            .records
+            .iter()
+            .zip(channels)
+            .map(|(record, channels)| Record {
+                alpha: record.alpha,
+                beta: record.beta,
+                channels,
+            })
+            .collect(),
+    })
+}
+
+/// Method used by [`extrapolate_lower_cap`] to fill the unmeasured records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapExtrapolation {
+    /// Repeat the lowest measured ring; the pole takes the record at the first alpha point
+    NearestRing,
+    /// Fit spherical harmonics of the given order to all measured records (per channel and
+    /// element, least squares with light regularization) and evaluate them in the cap
+    SphericalHarmonics {
+        /// Maximum spherical harmonic order
+        order: usize,
+    },
+}
+
+/// Fill the unmeasured cap around the south pole (beta = 0°) of a dataset
+///
+/// Measurement rigs usually cannot reach the lowest elevations, so many HRTF datasets start
+/// at a beta angle above 0°. This adds the missing rings (with the dataset's beta resolution)
+/// and the south pole record, so the dataset covers the full sphere. Datasets that already
+/// include the south pole are returned unchanged.
+///
+/// The dataset must have an equiangular grid covering the full alpha circle whose lowest ring
+/// lies on a multiple of the beta resolution. Phase spectra cannot be extrapolated with
+/// spherical harmonics, since their values wrap around.
+pub fn extrapolate_lower_cap(dataset: &Dataset, method: CapExtrapolation) -> Result<Dataset> {
+    let grid = match dataset.grid {
+        Grid::Equiangular(grid) if grid.num_records() == dataset.records.len() => grid,
+        _ => return Err(Error::new("Cap extrapolation requires an equiangular grid")),
+    };
+    if grid.has_south_pole() {
+        return Ok(dataset.clone());
+    }
+    if grid.alpha_span() != 360.0 {
+        return Err(Error::new("Cap extrapolation requires a full alpha circle"));
+    }
+    let resolution = grid.beta_resolution();
+    if resolution <= 0.0 {
+        return Err(Error::new(
+            "Cap extrapolation requires at least two beta rings",
+        ));
+    }
+    let missing = grid.beta_start / resolution;
+    if (missing - missing.round()).abs() > 1e-3 {
+        return Err(Error::new(format!(
+            "Lowest ring at beta = {}° is not a multiple of the beta resolution {}°",
+            grid.beta_start, resolution
+        )));
+    }
+
+    let cap = EquiangularGrid {
+        beta_points: missing.round() as usize,
+        beta_start: 0.0,
+        beta_end: grid.beta_start - resolution,
+        ..grid
+    };
+    let mut records: Vec<Record> = match method {
+        CapExtrapolation::NearestRing => cap
+            .layout()
+            .zip(cap.directions())
+            .map(|((_, a), (alpha, beta))| Record {
+                alpha,
+                beta,
+                channels: dataset.records[a].channels.clone(),
+            })
+            .collect(),
+        CapExtrapolation::SphericalHarmonics { order } => {
+            if dataset.content_type() == ContentType::PhaseSpectrum {
+                return Err(Error::new(
+                    "Phase spectra cannot be extrapolated with spherical harmonics",
+                ));
+            }
+            let directions: Vec<_> = cap.directions().collect();
+            evaluate_sh(dataset, &directions, order)?
+        }
+    };
+    records.extend(dataset.records.iter().cloned());
+
+    Ok(Dataset {
+        header: dataset.header.clone(),
+        quantization: dataset.quantization,
+        grid: Grid::Equiangular(EquiangularGrid {
+            beta_points: cap.beta_points + grid.beta_points,
+            beta_start: 0.0,
+            ..grid
+        }),
+        orientation: dataset.orientation,
+        metadata: dataset.metadata.clone(),
+        records,
+    })
+}
+
+/// Records at the given directions, evaluated from a spherical harmonic fit to all records
+/// of the dataset
+fn evaluate_sh(dataset: &Dataset, targets: &[(f32, f32)], order: usize) -> Result<Vec<Record>> {
+    let expansion = ShCoefficients::fit(dataset, order)?;
+    Ok(targets
+        .iter()
+        .map(|&(alpha, beta)| {
+            let basis = sh::real_basis(order, alpha, beta);
+            Record {
+                alpha,
+                beta,
+                channels: (0..expansion.num_channels)
+                    .map(|channel| expansion.evaluate_basis(&basis, channel))
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Spherical harmonic expansion of every channel and element of a dataset
+///
+/// Evaluating the expansion yields the data at any direction, smoothly interpolated between
+/// the records. Fitting is costly for high orders and many elements, so the coefficients can
+/// be cached with a [`LookupIndex`](crate::index::LookupIndex).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShCoefficients {
+    order: usize,
+    num_channels: usize,
+    elements_per_record: usize,
+    orientation: Orientation,
+    /// Coefficients per channel and element, `(order + 1)²` each
+    coefficients: Vec<f64>,
+}
+
+impl ShCoefficients {
+    /// Fit spherical harmonics of the given order to all records (least squares with light
+    /// regularization)
+    ///
+    /// Phase spectra cannot be expanded, since their values wrap around.
+    pub fn fit(dataset: &Dataset, order: usize) -> Result<Self> {
+        if dataset.content_type() == ContentType::PhaseSpectrum {
+            return Err(Error::new(
+                "Phase spectra cannot be expanded into spherical harmonics",
+            ));
+        }
+        let directions: Vec<(f32, f32)> =
+            dataset.records.iter().map(|r| (r.alpha, r.beta)).collect();
+        let fit = sh::Fit::new(order, &directions, SH_REGULARIZATION)?;
+
+        let (num_channels, elements_per_record) =
+            (dataset.num_channels(), dataset.elements_per_record());
+        let mut coefficients =
+            Vec::with_capacity(num_channels * elements_per_record * sh::num_coefficients(order));
+        let mut values = vec![0.0f64; dataset.records.len()];
+        for channel in 0..num_channels {
+            for element in 0..elements_per_record {
+                for (value, record) in values.iter_mut().zip(&dataset.records) {
+                    *value = record.channels[channel][element] as f64;
+                }
+                coefficients.extend(fit.coefficients(&values));
+            }
+        }
+        Ok(Self {
+            order,
+            num_channels,
+            elements_per_record,
+            orientation: dataset.orientation,
+            coefficients,
+        })
+    }
+
+    /// Restore an expansion from its parts, e.g. when loading a cache
+    pub(crate) fn from_parts(
+        order: usize,
+        num_channels: usize,
+        elements_per_record: usize,
+        orientation: Orientation,
+        coefficients: Vec<f64>,
+    ) -> Result<Self> {
+        if coefficients.len() != num_channels * elements_per_record * sh::num_coefficients(order) {
+            return Err(Error::new(
+                "Number of spherical harmonic coefficients does not match their layout",
+            ));
+        }
+        Ok(Self {
+            order,
+            num_channels,
+            elements_per_record,
+            orientation,
+            coefficients,
+        })
+    }
+
+    /// Maximum spherical harmonic order
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Number of channels
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Number of elements per channel
+    pub fn elements_per_record(&self) -> usize {
+        self.elements_per_record
+    }
+
+    /// Default orientation of the expanded dataset
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// All coefficients, `(order + 1)²` per channel and element, channels outermost
+    pub fn as_slice(&self) -> &[f64] {
+        &self.coefficients
+    }
+
+    /// Coefficients of one element of a channel, indexed by `n * n + n + m`
+    pub fn coefficients(&self, channel: usize, element: usize) -> &[f64] {
+        let size = sh::num_coefficients(self.order);
+        let start = (channel * self.elements_per_record + element) * size;
+        &self.coefficients[start..start + size]
+    }
+
+    /// Data of a channel at an object view direction (azimuth, elevation in degrees)
+    pub fn evaluate(&self, azimuth: f32, elevation: f32, channel: usize) -> Vec<f32> {
+        let (alpha, beta) = to_data_view(&self.orientation, azimuth, elevation);
+        self.evaluate_basis(&sh::real_basis(self.order, alpha, beta), channel)
+    }
+
+    fn evaluate_basis(&self, basis: &[f64], channel: usize) -> Vec<f32> {
+        (0..self.elements_per_record)
+            .map(|element| sh::evaluate(basis, self.coefficients(channel, element)) as f32)
+            .collect()
+    }
+}
+
+/// Equiangular grid formed by the kept records and the original record index of each of
+/// its records, if the kept records form such a grid
+pub(crate) fn regular_subset(
+    grid: &EquiangularGrid,
+    keep: &[bool],
+) -> Option<(EquiangularGrid, Vec<usize>)> {
+    let mut index = std::collections::BTreeMap::new();
+    for (i, (position, &k)) in grid.layout().zip(keep).enumerate() {
+        if k {
+            index.insert(position, i);
+        }
+    }
+    let first_ring = index.keys().next()?.0;
+    let last_ring = index.keys().next_back()?.0;
+
+    // All non-pole rings in the range must keep the same alpha points
+    let mut alpha: Option<Vec<usize>> = None;
+    for ring in first_ring..=last_ring {
+        let points: Vec<usize> = index
+            .range((ring, 0)..=(ring, usize::MAX))
+            .map(|(p, _)| p.1)
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        if grid.is_pole_ring(ring) {
+            continue;
+        }
+        match &alpha {
+            Some(expected) if *expected != points => return None,
+            _ => alpha = Some(points),
+        }
+    }
+    let alpha = match alpha {
+        Some(points) => contiguous_run(&points, grid.alpha_points, grid.alpha_span() == 360.0)?,
+        None => (0, grid.alpha_points),
+    };
+
+    let candidate = grid.sub_grid((first_ring, last_ring), alpha);
+    let order = candidate
+        .layout()
+        .map(|(ring, a)| {
+            let ring = first_ring + ring;
+            let a = if grid.is_pole_ring(ring) {
+                0
+            } else {
+                (alpha.0 + a) % grid.alpha_points
+            };
+            index.get(&(ring, a)).copied()
+        })
+        .collect::<Option<Vec<usize>>>()?;
+
+    // Guard against rounding effects: the new grid must reproduce the record directions (the
+    // alpha angle of a pole is arbitrary)
+    let directions: Vec<(f32, f32)> = grid.directions().collect();
+    let matches = order.len() == index.len()
+        && candidate
+            .layout()
+            .zip(candidate.directions())
+            .zip(&order)
+            .all(|(((ring, _), (alpha, beta)), &i)| {
+                (candidate.is_pole_ring(ring) || angle_eq(alpha, directions[i].0))
+                    && angle_eq(beta, directions[i].1)
+            });
+    matches.then_some((candidate, order))
+}
+
+/// First index and length of the shortest (cyclic) run covering a sorted set of alpha indices
+///
+/// An empty set is covered by all points.
+fn covering_run(points: &[usize], count: usize, cyclic: bool) -> (usize, usize) {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return (0, count);
+    };
+    if !cyclic {
+        return (first, last - first + 1);
+    }
+    // Skip the largest gap between neighbouring points, the run covers everything else
+    let wrap_gap = first + count - last;
+    let largest = points
+        .windows(2)
+        .map(|w| (w[1] - w[0], w[1]))
+        .max_by_key(|&(gap, _)| gap)
+        .filter(|&(gap, _)| gap > wrap_gap);
+    match largest {
+        Some((gap, start)) => (start, count - gap + 1),
+        None => (first, last - first + 1),
+    }
+}
+
+/// First index and length of a sorted set of alpha indices forming a (cyclic) run
+fn contiguous_run(points: &[usize], count: usize, cyclic: bool) -> Option<(usize, usize)> {
+    if points.len() == count {
+        return Some((0, count));
+    }
+    let gaps: Vec<usize> = (0..points.len().saturating_sub(1))
+        .filter(|&i| points[i + 1] != points[i] + 1)
+        .collect();
+    match gaps.as_slice() {
+        [] => Some((points[0], points.len())),
+        // Run wrapping around the end of a full circle
+        [gap] if cyclic && points[0] == 0 && points[points.len() - 1] == count - 1 => {
+            Some((points[gap + 1], points.len()))
+        }
+        _ => None,
+    }
+}
+
+/// Cartesian unit vector of an object view direction
+pub(crate) fn object_vector(azimuth: f32, elevation: f32) -> [f64; 3] {
+    let (sa, ca) = (azimuth as f64).to_radians().sin_cos();
+    let (se, ce) = (elevation as f64).to_radians().sin_cos();
+    [ca * ce, sa * ce, se]
+}
+
+fn wrap_alpha(alpha: f32) -> f32 {
+    if alpha >= 360.0 {
+        alpha - 360.0
+    } else {
+        alpha
+    }
+}
+
+pub(crate) fn angle_eq(a: f32, b: f32) -> bool {
+    let d = (a - b).abs();
+    d < 1e-3 || (d - 360.0).abs() < 1e-3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_sphere(alpha_points: usize, beta_points: usize) -> EquiangularGrid {
+        EquiangularGrid {
+            alpha_points,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        }
+    }
+
+    #[test]
+    fn test_full_sphere_record_count() {
+        // 5° x 5°: 72 alpha points, 37 beta rings, single records at the poles
+        let grid = full_sphere(72, 37);
+        assert_eq!(grid.alpha_resolution(), 5.0);
+        assert_eq!(grid.beta_resolution(), 5.0);
+        assert_eq!(grid.num_records(), 2 + 35 * 72);
+        assert_eq!(grid.directions().count(), grid.num_records());
+    }
+
+    #[test]
+    fn test_record_coords_match_storage_order() {
+        let grid = full_sphere(4, 3);
+        assert_eq!(grid.record_coords(0), Some((0.0, 0.0)));
+        assert_eq!(grid.record_coords(2), Some((90.0, 90.0)));
+        assert_eq!(grid.record_coords(5), Some((0.0, 180.0)));
+        assert_eq!(grid.record_coords(6), None);
+
+        let upper = EquiangularGrid {
+            beta_start: 45.0,
+            ..full_sphere(8, 4)
+        };
+        let lower = EquiangularGrid {
+            beta_end: 90.0,
+            ..full_sphere(8, 3)
+        };
+        let poles = full_sphere(0, 2);
+        for grid in [full_sphere(72, 37), upper, lower, poles] {
+            let coords: Vec<_> = (0..grid.num_records())
+                .map(|i| grid.record_coords(i).unwrap())
+                .collect();
+            assert_eq!(coords, grid.directions().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_partial_alpha_range() {
+        let grid = EquiangularGrid {
+            alpha_points: 7,
+            alpha_start: 270.0,
+            alpha_end: 90.0,
+            beta_points: 1,
+            beta_start: 90.0,
+            beta_end: 90.0,
+        };
+        assert_eq!(grid.alpha_span(), 180.0);
+        assert_eq!(grid.alpha_resolution(), 30.0);
+        assert_eq!(grid.num_records(), 7);
+    }
+
+    #[test]
+    fn test_coverage() {
+        assert!(full_sphere(72, 37).coverage().is_full_sphere());
+        let upper = EquiangularGrid {
+            beta_start: 90.0,
+            ..full_sphere(72, 19)
+        };
+        let coverage = upper.coverage();
+        assert!(!coverage.is_full_sphere());
+        assert_eq!(coverage.to_string(), "alpha 0°..360°, beta 90°..180°");
+        assert!(coverage.contains(123.0, 135.0));
+        assert!(!coverage.contains(123.0, 45.0));
+        // Data view elevation 0° is the horizontal plane
+        let orientation = Orientation::default();
+        assert!(coverage.contains_direction(&orientation, 30.0, 10.0));
+        assert!(!coverage.contains_direction(&orientation, 30.0, -10.0));
+
+        let front = EquiangularGrid {
+            alpha_points: 7,
+            alpha_start: 270.0,
+            alpha_end: 90.0,
+            ..full_sphere(8, 19)
+        };
+        assert!(front.coverage().contains(300.0, 90.0));
+        assert!(!front.coverage().contains(180.0, 90.0));
+    }
+
+    fn dataset(grid: EquiangularGrid) -> Dataset {
+        Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            grid,
+            1,
+            |alpha, beta, _| vec![alpha, beta],
+        )
+    }
+
+    /// Mean of x^a y^b z^c over the unit sphere, computed with the grid's quadrature
+    fn quadrature_mean(grid: &Grid, [a, b, c]: [i32; 3]) -> f64 {
+        let directions = grid.directions().unwrap();
+        let weights = grid.quadrature_weights().unwrap();
+        let sum: f64 = directions
+            .iter()
+            .zip(&weights)
+            .map(|(&(alpha, beta), w)| {
+                let (sa, ca) = (alpha as f64).to_radians().sin_cos();
+                let (sb, cb) = (beta as f64).to_radians().sin_cos();
+                w * (sb * ca).powi(a) * (sb * sa).powi(b) * (-cb).powi(c)
+            })
+            .sum();
+        sum / (4.0 * std::f64::consts::PI)
+    }
+
+    /// Exact mean of x^a y^b z^c over the unit sphere
+    fn exact_mean([a, b, c]: [i32; 3]) -> f64 {
+        let double_factorial = |n: i32| (1..=n).rev().step_by(2).map(f64::from).product::<f64>();
+        if a % 2 == 1 || b % 2 == 1 || c % 2 == 1 {
+            return 0.0;
+        }
+        double_factorial(a - 1) * double_factorial(b - 1) * double_factorial(c - 1)
+            / double_factorial(a + b + c + 1)
+    }
+
+    fn check_exact(grid: &Grid, degree: i32) {
+        let weights = grid.quadrature_weights().unwrap();
+        assert_eq!(Some(weights.len()), grid.num_records());
+        for a in 0..=degree {
+            for b in 0..=degree - a {
+                for c in 0..=degree - a - b {
+                    let mean = quadrature_mean(grid, [a, b, c]);
+                    let exact = exact_mean([a, b, c]);
+                    assert!(
+                        (mean - exact).abs() < 1e-5,
+                        "{} x^{} y^{} z^{}",
+                        grid,
+                        a,
+                        b,
+                        c
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_resolution() {
+        let grid = EquiangularGrid::with_resolution(5.0, 5.0).unwrap();
+        assert_eq!((grid.alpha_points, grid.beta_points), (72, 37));
+        assert_eq!(grid.num_records(), 2522);
+        assert_eq!(
+            grid.to_string(),
+            "5° x 5° equiangular grid, alpha 0°..360°, beta 0°..180° (2522 records)"
+        );
+        assert!(EquiangularGrid::with_resolution(7.0, 5.0).is_err());
+        assert!(EquiangularGrid::with_resolution(5.0, 0.0).is_err());
+        assert!(EquiangularGrid::with_resolution(720.0, 5.0).is_err());
+    }
+
+    #[test]
+    fn test_gauss_legendre_grid() {
+        for order in 0..6 {
+            let grid = Grid::gauss_legendre(order);
+            let directions = grid.directions().unwrap();
+            assert_eq!(directions.len(), 2 * (order + 1) * (order + 1));
+            assert!(directions.windows(2).all(|w| w[0].1 <= w[1].1));
+            check_exact(&grid, 2 * order as i32 + 1);
+        }
+        assert_eq!(
+            Grid::gauss_legendre(3).to_string(),
+            "Gauss-Legendre grid of order 3 (32 records)"
+        );
+    }
+
+    #[test]
+    fn test_lebedev_grid() {
+        for (points, degree) in LEBEDEV_POINTS.iter().zip([3, 5, 7, 9, 11, 13, 15]) {
+            let grid = Grid::lebedev(*points).unwrap();
+            let directions = grid.directions().unwrap();
+            assert_eq!(directions.len(), *points);
+            assert!(directions.iter().all(
+                |&(alpha, beta)| (0.0..360.0).contains(&alpha) && (0.0..=180.0).contains(&beta)
+            ));
+            check_exact(&grid, degree);
+        }
+        assert!(Grid::lebedev(100).is_err());
+        assert_eq!(Grid::Lebedev { points: 100 }.directions(), None);
+        assert_eq!(Grid::Irregular.quadrature_weights(), None);
+        assert!(EquiangularGrid::try_from(Grid::lebedev(6).unwrap()).is_err());
+        assert_eq!(
+            EquiangularGrid::try_from(Grid::from(full_sphere(4, 3))),
+            Ok(full_sphere(4, 3))
+        );
+    }
+
+    #[test]
+    fn test_resample() {
+        // Spherical harmonic of order one, reproduced exactly by a fit of order one
+        let field = |alpha: f32, beta: f32| {
+            let (sa, ca) = (alpha as f64).to_radians().sin_cos();
+            let (sb, cb) = (beta as f64).to_radians().sin_cos();
+            (1.0 + 0.5 * cb + 0.25 * sb * ca - 0.125 * sb * sa) as f32
+        };
+        let source = Dataset::from_fn(
+            crate::ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0],
+            },
+            full_sphere(12, 7),
+            2,
+            |alpha, beta, channel| vec![field(alpha, beta) * (channel + 1) as f32],
+        );
+
+        let target = Grid::gauss_legendre(3);
+        let resampled = resample(&source, &target, 1).unwrap();
+        assert_eq!(resampled.grid, target);
+        assert_eq!(resampled.num_records(), 32);
+        for record in &resampled.records {
+            let expected = field(record.alpha, record.beta);
+            assert!((record.channels[0][0] - expected).abs() < 1e-2);
+            assert!((record.channels[1][0] - 2.0 * expected).abs() < 2e-2);
+        }
+        assert_eq!(quadrature_weights(&resampled).unwrap().len(), 32);
+
+        assert!(resample(&source, &Grid::Irregular, 1).is_err());
+        assert!(resample(&source, &target, 20).is_err());
+        let mut phases = source.clone();
+        phases.header = crate::ContentHeader::PhaseSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(resample(&phases, &target, 1).is_err());
+    }
+
+    #[test]
+    fn test_to_object_view() {
+        let identity = Orientation::default();
+        let (azimuth, elevation) = to_object_view(&identity, 30.0, 120.0);
+        assert!((azimuth - 30.0).abs() < 1e-4 && (elevation - 30.0).abs() < 1e-4);
+
+        let yawed = Orientation {
+            yaw: 90.0,
+            ..Orientation::default()
+        };
+        let (azimuth, elevation) = to_object_view(&yawed, 0.0, 90.0);
+        assert!((azimuth + 90.0).abs() < 1e-4 && elevation.abs() < 1e-4);
+        let (alpha, beta) = to_data_view(&yawed, -90.0, 0.0);
+        assert!(alpha.abs() < 1e-4 && (beta - 90.0).abs() < 1e-4);
+
+        let tilted = Orientation {
+            yaw: 30.0,
+            pitch: -60.0,
+            roll: 15.0,
+        };
+        for (alpha, beta) in [(10.0, 20.0), (200.0, 95.0), (315.0, 170.0)] {
+            let (azimuth, elevation) = to_object_view(&tilted, alpha, beta);
+            let back = to_data_view(&tilted, azimuth, elevation);
+            assert!((back.0 - alpha).abs() < 1e-3 && (back.1 - beta).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_filter_by_elevation_keeps_grid() {
+        // 30° x 30°; keep elevation >= -40°, i.e. beta >= 50°
+        let filtered = filter_records(&dataset(full_sphere(12, 7)), |_, el| el >= -40.0);
+        let grid = filtered.grid.equiangular().expect("regular grid");
+        assert_eq!(grid.beta_start, 60.0);
+        assert_eq!(grid.beta_points, 5);
+        assert_eq!(filtered.num_records(), 1 + 4 * 12);
+        assert!(filtered
+            .records
+            .iter()
+            .zip(grid.directions())
+            .all(|(r, (alpha, beta))| r.alpha == alpha && r.beta == beta));
+    }
+
+    #[test]
+    fn test_filter_frontal_sector_wraps_around() {
+        // Azimuth -60°..60° wraps around 0° and is reordered to start at 300°
+        let filtered = filter_records(&dataset(full_sphere(12, 7)), |az, el| {
+            az.abs() <= 60.0 + 1e-3 && el.abs() < 45.0
+        });
+        let grid = filtered.grid.equiangular().expect("regular grid");
+        assert_eq!((grid.alpha_start, grid.alpha_end), (300.0, 60.0));
+        assert_eq!(grid.alpha_points, 5);
+        assert_eq!(grid.beta_points, 3);
+        assert_eq!(filtered.records[0].alpha, 300.0);
+        assert_eq!(filtered.records[0].channels[0][0], 300.0);
+    }
+
+    #[test]
+    fn test_filter_irregular_selection() {
+        let filtered = filter_records(&dataset(full_sphere(12, 7)), |az, el| az > el);
+        assert_eq!(filtered.grid, Grid::Irregular);
+        assert!(filtered.records.iter().all(|r| r.alpha > r.beta - 90.0));
+    }
+
+    #[test]
+    fn test_crop_window() {
+        let data = dataset(full_sphere(12, 7));
+        // Alpha 300°..60° wraps around 0°, beta 0°..60° includes the south pole
+        let window = Region::Window {
+            alpha_start: 300.0,
+            alpha_end: 60.0,
+            beta_start: 0.0,
+            beta_end: 60.0,
+        };
+        let cropped = crop(&data, &window).unwrap();
+        let grid = cropped.grid.equiangular().expect("regular grid");
+        assert_eq!(
+            (grid.alpha_start, grid.alpha_end, grid.alpha_points),
+            (300.0, 60.0, 5)
+        );
+        assert_eq!(
+            (grid.beta_start, grid.beta_end, grid.beta_points),
+            (0.0, 60.0, 3)
+        );
+        assert_eq!(cropped.num_records(), 1 + 2 * 5);
+        assert_eq!(cropped.records[1].channels[0][0], 300.0);
+        assert!(cropped
+            .records
+            .iter()
+            .zip(grid.directions())
+            .all(|(r, (alpha, beta))| r.alpha == alpha && r.beta == beta));
+
+        let empty = Region::Window {
+            alpha_start: 10.0,
+            alpha_end: 20.0,
+            beta_start: 70.0,
+            beta_end: 80.0,
+        };
+        assert!(crop(&data, &empty).is_err());
+        let full = Region::Window {
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        };
+        assert_eq!(crop(&data, &full).unwrap(), data);
+        let mut irregular = data.clone();
+        irregular.grid = Grid::Irregular;
+        assert!(crop(&irregular, &window).is_err());
+    }
+
+    #[test]
+    fn test_crop_cone_keeps_bounding_window() {
+        // Cone towards the left (azimuth 90°) on a 30° x 30° grid
+        let cone = Region::Cone {
+            azimuth: 90.0,
+            elevation: 0.0,
+            half_angle: 35.0,
+        };
+        let data = dataset(full_sphere(12, 7));
+        let cropped = crop(&data, &cone).unwrap();
+        let grid = cropped.grid.equiangular().expect("regular grid");
+        assert_eq!((grid.alpha_start, grid.alpha_end), (60.0, 120.0));
+        assert_eq!((grid.beta_start, grid.beta_end), (60.0, 120.0));
+        // The corners of the window lie outside of the cone
+        assert_eq!(cropped.num_records(), 9);
+        let inside = cropped
+            .records
+            .iter()
+            .filter(|r| cone.contains(&data.orientation, r.alpha, r.beta))
+            .count();
+        assert_eq!(inside, 5);
+
+        // A cone around the pole covers full rings
+        let up = Region::Cone {
+            azimuth: 0.0,
+            elevation: 90.0,
+            half_angle: 40.0,
+        };
+        let cap = crop(&data, &up).unwrap();
+        let grid = cap.grid.equiangular().unwrap();
+        assert_eq!(
+            (grid.alpha_points, grid.beta_start, grid.beta_end),
+            (12, 150.0, 180.0)
+        );
+    }
+
+    #[test]
+    fn test_recommend_resolution() {
+        let dense = Dataset::from_fn(
+            crate::ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0, 4000.0],
+            },
+            EquiangularGrid::with_resolution(10.0, 10.0).unwrap(),
+            1,
+            |alpha, beta, _| {
+                let front = alpha.to_radians().cos() * beta.to_radians().sin();
+                vec![1.5 + front, 1.0 + 0.2 * front]
+            },
+        );
+
+        let relaxed = recommend_resolution(&dense, -20.0).unwrap();
+        assert!(relaxed.grid.num_records() < dense.num_records());
+        assert!(relaxed.error_db <= -20.0);
+        let strict = recommend_resolution(&dense, -40.0).unwrap();
+        assert!(strict.grid.num_records() > relaxed.grid.num_records());
+        assert!(strict.error_db <= -40.0);
+        // Coarse grids cover the same range with whole multiples of the resolution
+        for grid in [relaxed.grid, strict.grid] {
+            assert_eq!((grid.alpha_start, grid.alpha_end), (0.0, 360.0));
+            assert_eq!((grid.beta_start, grid.beta_end), (0.0, 180.0));
+            assert_eq!(36 % grid.alpha_points, 0);
+            assert_eq!(18 % (grid.beta_points - 1), 0);
+        }
+
+        let exact = recommend_resolution(&dense, -300.0).unwrap();
+        assert_eq!(exact.grid, *dense.grid.equiangular().unwrap());
+        assert_eq!(exact.error_db, f32::NEG_INFINITY);
+
+        let sparse = downsample_to_error_budget(&dense, -20.0).unwrap();
+        assert_eq!(sparse.grid, Grid::Equiangular(relaxed.grid));
+        assert!(sparse
+            .records
+            .iter()
+            .zip(relaxed.grid.directions())
+            .all(|(r, (alpha, beta))| r.alpha == alpha && r.beta == beta));
+        assert!(sparse.records.iter().all(|r| dense.records.contains(r)));
+
+        let mut irregular = dense.clone();
+        irregular.grid = Grid::Irregular;
+        assert!(recommend_resolution(&irregular, -20.0).is_err());
+        assert!(recommend_resolution(&dense, f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_downsample_grid() {
+        let dense = dataset(full_sphere(72, 37));
+        let nearest = downsample_grid(&dense, 15.0, 30.0, Downsampling::Nearest).unwrap();
+        assert_eq!(nearest.grid, Grid::Equiangular(full_sphere(24, 7)));
+        assert_eq!(nearest.num_records(), 2 + 5 * 24);
+        assert!(nearest
+            .records
+            .iter()
+            .all(|r| r.channels[0] == [r.alpha, r.beta]));
+
+        // Every point averages the records around it, at most half a step away
+        let average = downsample_grid(&dense, 15.0, 30.0, Downsampling::Average).unwrap();
+        assert_eq!(average.grid, nearest.grid);
+        for record in &average.records[1..average.num_records() - 1] {
+            let (alpha, beta) = (record.channels[0][0], record.channels[0][1]);
+            // The cells at 0° average across the wrap-around
+            assert!(
+                record.alpha == 0.0 || (alpha - record.alpha).abs() <= 7.5,
+                "{:?}",
+                record
+            );
+            assert!((beta - record.beta).abs() <= 15.0, "{:?}", record);
+        }
+
+        // Magnitudes are averaged in the power domain
+        let ring = EquiangularGrid {
+            beta_points: 1,
+            beta_start: 90.0,
+            beta_end: 90.0,
+            ..full_sphere(4, 1)
+        };
+        let spectra = Dataset::from_fn(
+            crate::ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0],
+            },
+            ring,
+            1,
+            |alpha, _, _| {
+                vec![if alpha % 180.0 == 0.0 {
+                    2f32.sqrt()
+                } else {
+                    0.0
+                }]
+            },
+        );
+        let half = downsample_grid(&spectra, 180.0, 0.0, Downsampling::Average).unwrap();
+        assert_eq!(half.num_records(), 2);
+        assert!(half
+            .records
+            .iter()
+            .all(|r| (r.channels[0][0] - 1.0).abs() < 1e-6));
+
+        assert!(downsample_grid(&dense, 2.0, 30.0, Downsampling::Nearest).is_err());
+        assert!(downsample_grid(&dense, 15.0, 35.0, Downsampling::Nearest).is_err());
+        let mut irregular = dense.clone();
+        irregular.grid = Grid::Irregular;
+        assert!(downsample_grid(&irregular, 15.0, 30.0, Downsampling::Nearest).is_err());
+        let mut phases = spectra;
+        phases.header = crate::ContentHeader::PhaseSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(downsample_grid(&phases, 180.0, 0.0, Downsampling::Average).is_err());
+    }
+
+    #[test]
+    fn test_covering_run() {
+        assert_eq!(covering_run(&[0, 1, 11], 12, true), (11, 3));
+        assert_eq!(covering_run(&[2, 3, 7], 12, true), (2, 6));
+        assert_eq!(covering_run(&[0, 1, 11], 12, false), (0, 12));
+        assert_eq!(covering_run(&[], 12, true), (0, 12));
+        assert_eq!(covering_run(&[0, 6], 12, true), (0, 7));
+    }
+
+    fn hrtf_rig() -> EquiangularGrid {
+        // 30° x 30°, lowest ring at beta = 60° (elevation -30°)
+        EquiangularGrid {
+            beta_start: 60.0,
+            beta_points: 5,
+            ..full_sphere(12, 7)
+        }
+    }
+
+    #[test]
+    fn test_extrapolate_lower_cap_nearest_ring() {
+        let measured = dataset(hrtf_rig());
+        let full = extrapolate_lower_cap(&measured, CapExtrapolation::NearestRing).unwrap();
+
+        let grid = full.grid.equiangular().unwrap();
+        assert_eq!(*grid, full_sphere(12, 7));
+        assert_eq!(full.num_records(), grid.num_records());
+        // Pole and the ring at beta = 30° repeat the ring at beta = 60°
+        assert_eq!(full.records[0].channels[0], vec![0.0, 60.0]);
+        assert_eq!((full.records[3].alpha, full.records[3].beta), (60.0, 30.0));
+        assert_eq!(full.records[3].channels[0], vec![60.0, 60.0]);
+        assert_eq!(full.records[13..], measured.records[..]);
+    }
+
+    #[test]
+    fn test_extrapolate_lower_cap_spherical_harmonics() {
+        let measured = Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            hrtf_rig(),
+            1,
+            |_, beta, _| vec![1.0 + (beta as f64).to_radians().cos() as f32],
+        );
+        let full =
+            extrapolate_lower_cap(&measured, CapExtrapolation::SphericalHarmonics { order: 1 })
+                .unwrap();
+        assert!((full.records[0].channels[0][0] - 2.0).abs() < 0.05);
+
+        let mut phases = measured.clone();
+        phases.header = crate::ContentHeader::PhaseSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(
+            extrapolate_lower_cap(&phases, CapExtrapolation::SphericalHarmonics { order: 1 })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_quadrature_weights() {
+        let grid = full_sphere(72, 37);
+        let weights = grid.quadrature_weights();
+        let total: f64 = weights.iter().sum();
+        assert!((total - 4.0 * std::f64::consts::PI).abs() < 1e-9);
+        // Pole cap of 2.5° vs. a cell on the equator (5° x 5°)
+        let pole = std::f64::consts::TAU * (1.0 - 2.5f64.to_radians().cos());
+        assert!((weights[0] - pole).abs() < 1e-12);
+        let equator = 1 + 17 * 72;
+        let cell = 5f64.to_radians() * 2.0 * 2.5f64.to_radians().sin();
+        assert!((weights[equator] - cell).abs() < 1e-12);
+
+        // The measured part of an HRTF rig excludes the south cap
+        let rig = dataset(hrtf_rig());
+        let partial: f64 = quadrature_weights(&rig).unwrap().iter().sum();
+        let cap = std::f64::consts::TAU * (1.0 - 45f64.to_radians().cos());
+        assert!((partial - (4.0 * std::f64::consts::PI - cap)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let original = dataset(full_sphere(12, 7));
+        let yawed = Orientation {
+            yaw: 90.0,
+            ..Orientation::default()
+        };
+        let rotated = rotate(
+            &original,
+            &yawed,
+            Interp::NearestNeighbour,
+            SpectrumDomain::default(),
+        )
+        .unwrap();
+        assert_eq!(rotated.grid, original.grid);
+        assert_eq!(rotated.num_records(), original.num_records());
+        // Read with the offset as orientation, the original shows the rotated records
+        for record in &rotated.records {
+            let (azimuth, elevation) =
+                to_object_view(&Orientation::default(), record.alpha, record.beta);
+            let (alpha, beta) = to_data_view(&yawed, azimuth, elevation);
+            let source = &record.channels[0];
+            let pole = !(1e-3..=180.0 - 1e-3).contains(&beta);
+            assert!(angle_eq(source[0], alpha) || pole);
+            assert!((source[1] - beta).abs() < 1e-3);
+        }
+        // Record 25 is alpha 0 on the horizontal ring, a yaw of 90° fetches alpha 90
+        assert_eq!(rotated.records[25].channels[0], [90.0, 90.0]);
+
+        let back = Orientation {
+            yaw: -90.0,
+            ..Orientation::default()
+        };
+        let restored = rotate(
+            &rotated,
+            &back,
+            Interp::NearestNeighbour,
+            SpectrumDomain::default(),
+        )
+        .unwrap();
+        assert_eq!(restored.records, original.records);
+
+        // Halfway between two records, both contribute equally
+        let half = Orientation {
+            yaw: 15.0,
+            ..Orientation::default()
+        };
+        let method = Interp::InverseDistance { neighbours: 2 };
+        let rotated = rotate(&original, &half, method, SpectrumDomain::default()).unwrap();
+        let channel = &rotated.records[28].channels[0];
+        assert!((channel[0] - 105.0).abs() < 1e-3, "{:?}", channel);
+        assert!((channel[1] - 90.0).abs() < 1e-3);
+    }
+}
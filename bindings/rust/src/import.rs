@@ -0,0 +1,498 @@
+//! Import of measurements stored outside of DAFF files
+//!
+//! Measurement campaigns, e.g. with microphone arrays or turntable rigs, often leave one WAV
+//! file per direction with the angles encoded in the file name. [`wav_folder`] collects such
+//! a directory into an impulse response [`Dataset`] on an equiangular grid, which
+//! [`writer::write_dataset`](crate::writer::write_dataset) turns into a DAFF file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::grid::{self, EquiangularGrid, Grid};
+use crate::metadata::Metadata;
+use crate::wav;
+use crate::{writer, ContentHeader, Dataset, Error, Orientation, Quantization, Record, Result};
+
+/// Angle encoded by a placeholder of a [`FilenamePattern`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Angle {
+    Azimuth,
+    Elevation,
+    Alpha,
+    Beta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Angle(Angle),
+}
+
+/// Pattern of file names that encode a direction, e.g. `az{azimuth}_el{elevation}.wav`
+///
+/// The placeholders `{azimuth}` and `{elevation}` take object view angles, `{alpha}` and
+/// `{beta}` data view angles, all in degrees. A pattern needs either both object view or both
+/// data view placeholders. Angles are decimal numbers with an optional sign, such as `-30`,
+/// `045` or `7.5`. Literal parts are matched case-sensitively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenamePattern {
+    parts: Vec<Part>,
+}
+
+impl FromStr for FilenamePattern {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::new(format!("Invalid pattern '{}': {}", pattern, reason));
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            let Some(open) = rest.find('{') else {
+                parts.push(Part::Literal(rest.to_string()));
+                break;
+            };
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed placeholder"))?;
+            let angle = match &rest[open + 1..open + close] {
+                "azimuth" => Angle::Azimuth,
+                "elevation" => Angle::Elevation,
+                "alpha" => Angle::Alpha,
+                "beta" => Angle::Beta,
+                name => return Err(invalid(&format!("unknown placeholder '{{{}}}'", name))),
+            };
+            if matches!(parts.last(), Some(Part::Angle(_))) {
+                return Err(invalid("placeholders must be separated by text"));
+            }
+            parts.push(Part::Angle(angle));
+            rest = &rest[open + close + 1..];
+        }
+
+        let count = |angle| parts.iter().filter(|&p| *p == Part::Angle(angle)).count();
+        let object = (count(Angle::Azimuth), count(Angle::Elevation));
+        let data = (count(Angle::Alpha), count(Angle::Beta));
+        match (object, data) {
+            ((1, 1), (0, 0)) | ((0, 0), (1, 1)) => Ok(Self { parts }),
+            _ => Err(invalid(
+                "needs {azimuth} and {elevation} or {alpha} and {beta}, each once",
+            )),
+        }
+    }
+}
+
+impl FilenamePattern {
+    /// Data view direction (alpha, beta) encoded by a file name, `None` if the name does not
+    /// match the pattern
+    pub fn direction(&self, name: &str) -> Option<(f32, f32)> {
+        let mut angles = [0.0f32; 4];
+        let mut rest = name;
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rest = rest.strip_prefix(literal.as_str())?,
+                Part::Angle(angle) => {
+                    let length = number_length(rest);
+                    angles[*angle as usize] = rest[..length].parse().ok()?;
+                    rest = &rest[length..];
+                }
+            }
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+
+        let [azimuth, elevation, alpha, beta] = angles;
+        let (alpha, beta) = if self.parts.contains(&Part::Angle(Angle::Azimuth)) {
+            if !(-90.0..=90.0).contains(&elevation) {
+                return None;
+            }
+            grid::to_data_view(&Orientation::default(), azimuth, elevation)
+        } else {
+            if !(0.0..=180.0).contains(&beta) {
+                return None;
+            }
+            (alpha.rem_euclid(360.0), beta)
+        };
+        // Snap rounding errors of the conversion to the poles
+        let beta = if beta.abs() < 1e-3 {
+            0.0
+        } else if (beta - 180.0).abs() < 1e-3 {
+            180.0
+        } else {
+            beta
+        };
+        Some((alpha, beta))
+    }
+}
+
+/// Length of the decimal number at the start of a text: sign, digits and a fraction
+fn number_length(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let digits = |start: usize| {
+        bytes[start..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let sign = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let integer = sign + digits(sign);
+    match bytes.get(integer) {
+        Some(b'.') if digits(integer + 1) > 0 => integer + 1 + digits(integer + 1),
+        _ => integer,
+    }
+}
+
+/// Collect a directory of WAV files into an impulse response dataset
+///
+/// Every file whose name matches `pattern` holds the impulse responses of one direction, one
+/// channel per WAV channel; other files are ignored. All files need the same sampling rate
+/// and number of channels. Shorter responses are zero-padded to the longest one. The
+/// directions must form an equiangular grid, i.e. a common set of alpha angles on equally
+/// spaced beta rings, with single records at the poles.
+///
+/// ```no_run
+/// use opendaff::import;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let dataset = import::wav_folder("campaign/array_3", &"az{azimuth}_el{elevation}.wav".parse()?)?;
+/// println!("{} records on a {}", dataset.num_records(), dataset.grid);
+/// # Ok(())
+/// # }
+/// ```
+pub fn wav_folder(directory: impl AsRef<Path>, pattern: &FilenamePattern) -> Result<Dataset> {
+    let directory = directory.as_ref();
+    let entries = fs::read_dir(directory).map_err(|e| {
+        Error::new(format!(
+            "Failed to read directory '{}': {}",
+            directory.display(),
+            e
+        ))
+    })?;
+    let mut files = BTreeMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| Error::new(format!("Failed to read directory entry: {}", e)))?
+            .path();
+        let Some(direction) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| pattern.direction(name))
+        else {
+            continue;
+        };
+        if path.is_file() {
+            files.insert(path, direction);
+        }
+    }
+    if files.is_empty() {
+        return Err(Error::new(format!(
+            "No file in '{}' matches the pattern",
+            directory.display()
+        )));
+    }
+
+    let mut format: Option<(u32, usize, &Path)> = None;
+    let mut records = Vec::with_capacity(files.len());
+    for (path, &(alpha, beta)) in &files {
+        let wav = wav::read(path)?;
+        if wav.channels.is_empty() {
+            return Err(Error::new(format!("{}: No channels", path.display())));
+        }
+        let (samplerate, channels, first) =
+            *format.get_or_insert((wav.samplerate, wav.channels.len(), path));
+        if (samplerate, channels) != (wav.samplerate, wav.channels.len()) {
+            return Err(Error::new(format!(
+                "{}: {} channels at {} Hz, but {} has {} channels at {} Hz",
+                path.display(),
+                wav.channels.len(),
+                wav.samplerate,
+                first.display(),
+                channels,
+                samplerate
+            )));
+        }
+        records.push(Record {
+            alpha,
+            beta,
+            channels: wav.channels,
+        });
+    }
+    let length = records
+        .iter()
+        .flat_map(|r| &r.channels)
+        .map(Vec::len)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    for channel in records.iter_mut().flat_map(|r| r.channels.iter_mut()) {
+        channel.resize(length, 0.0);
+    }
+
+    let (grid, order) = assemble(&records).ok_or_else(|| {
+        Error::new(format!(
+            "The directions of the {} files in '{}' do not form an equiangular grid",
+            records.len(),
+            directory.display()
+        ))
+    })?;
+    let mut records: Vec<Option<Record>> = records.into_iter().map(Some).collect();
+    let records = order
+        .iter()
+        .zip(grid.directions())
+        .map(|(&i, (alpha, beta))| {
+            let record = records[i].take().expect("every file is used once");
+            Record {
+                alpha,
+                beta,
+                ..record
+            }
+        })
+        .collect();
+
+    Ok(Dataset {
+        header: ContentHeader::ImpulseResponse {
+            samplerate: format.expect("at least one file").0 as f64,
+        },
+        quantization: Quantization::Float32,
+        grid: Grid::Equiangular(grid),
+        orientation: Orientation::default(),
+        metadata: Metadata::new(),
+        records,
+    })
+}
+
+/// Collect a directory of WAV files with [`wav_folder`] and write it as DAFF file
+pub fn wav_folder_to_daff(
+    directory: impl AsRef<Path>,
+    pattern: &FilenamePattern,
+    output: impl AsRef<Path>,
+) -> Result<()> {
+    writer::write_dataset(output, &wav_folder(directory, pattern)?)
+}
+
+/// Equiangular grid through the record directions and the record index of each grid point
+fn assemble(records: &[Record]) -> Option<(EquiangularGrid, Vec<usize>)> {
+    let distinct = |mut angles: Vec<f32>| {
+        angles.sort_by(f32::total_cmp);
+        angles.dedup_by(|a, b| grid::angle_eq(*a, *b));
+        angles
+    };
+    let betas = distinct(records.iter().map(|r| r.beta).collect());
+    let alphas = distinct(
+        records
+            .iter()
+            .filter(|r| r.beta != 0.0 && r.beta != 180.0)
+            .map(|r| r.alpha)
+            .collect(),
+    );
+    // Smallest distance between neighbouring angles, cyclic for alpha
+    let resolution = |angles: &[f32], cyclic: bool| {
+        let gaps = angles.windows(2).map(|w| w[1] - w[0]);
+        let wrap = cyclic.then(|| angles[0] + 360.0 - angles[angles.len() - 1]);
+        gaps.chain(wrap).fold(f32::INFINITY, f32::min)
+    };
+
+    let (beta_start, beta_end) = (betas[0], betas[betas.len() - 1]);
+    let beta_points = if betas.len() > 1 {
+        ((beta_end - beta_start) / resolution(&betas, false)).round() as usize + 1
+    } else {
+        1
+    };
+    let (alpha_points, alpha_start) = match alphas.len() {
+        0 => (1, 0.0),
+        1 => (1, alphas[0]),
+        _ => {
+            let alpha_resolution = resolution(&alphas, true);
+            let points = (360.0 / alpha_resolution).round() as usize;
+            if !grid::angle_eq(360.0 / points as f32, alpha_resolution) {
+                return None;
+            }
+            (points, alphas[0].rem_euclid(alpha_resolution))
+        }
+    };
+    let parent = EquiangularGrid {
+        alpha_points,
+        alpha_start,
+        alpha_end: if alpha_points == 1 || alpha_start != 0.0 {
+            alpha_start
+        } else {
+            360.0
+        },
+        beta_points,
+        beta_start,
+        beta_end,
+    };
+
+    // Mark the grid points that have a record
+    let index: BTreeMap<(usize, usize), usize> = parent
+        .layout()
+        .enumerate()
+        .map(|(i, position)| (position, i))
+        .collect();
+    let mut record_at = vec![None; index.len()];
+    for (r, record) in records.iter().enumerate() {
+        let ring = match parent.beta_resolution() {
+            resolution if resolution > 0.0 => {
+                ((record.beta - beta_start) / resolution).round() as usize
+            }
+            _ => 0,
+        };
+        let a = match parent.alpha_resolution() {
+            _ if parent.is_pole_ring(ring) => 0,
+            resolution if resolution > 0.0 => {
+                ((record.alpha - alpha_start).rem_euclid(360.0) / resolution).round() as usize
+                    % alpha_points
+            }
+            _ => 0,
+        };
+        let slot = &mut record_at[*index.get(&(ring, a))?];
+        if slot.replace(r).is_some() {
+            return None;
+        }
+    }
+    let keep: Vec<bool> = record_at.iter().map(Option::is_some).collect();
+    let (grid, order) = grid::regular_subset(&parent, &keep)?;
+    let order: Vec<usize> = order
+        .into_iter()
+        .map(|i| record_at[i].expect("kept points have a record"))
+        .collect();
+
+    // Every record must be used and lie on its grid point
+    let matches = order.len() == records.len()
+        && grid.layout().zip(grid.directions()).zip(&order).all(
+            |(((ring, _), (alpha, beta)), &r)| {
+                (grid.is_pole_ring(ring) || grid::angle_eq(alpha, records[r].alpha))
+                    && grid::angle_eq(beta, records[r].beta)
+            },
+        );
+    matches.then_some((grid, order))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::wav::Wav;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = temp_path(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_wav(dir: &Path, name: &str, samplerate: u32, samples: Vec<f32>) {
+        let wav = Wav {
+            samplerate,
+            channels: vec![samples.clone(), samples.iter().map(|x| -x).collect()],
+        };
+        wav::write(&dir.join(name), &wav).unwrap();
+    }
+
+    #[test]
+    fn test_filename_pattern() {
+        let pattern: FilenamePattern = "az{azimuth}_el{elevation}.wav".parse().unwrap();
+        let (alpha, beta) = pattern.direction("az90_el0.wav").unwrap();
+        assert!(grid::angle_eq(alpha, 90.0) && grid::angle_eq(beta, 90.0));
+        let (alpha, beta) = pattern.direction("az-30_el+45.wav").unwrap();
+        assert!(grid::angle_eq(alpha, 330.0) && grid::angle_eq(beta, 135.0));
+        assert_eq!(pattern.direction("az7.5_el-90.wav").unwrap().1, 0.0);
+        assert_eq!(pattern.direction("az90_el0.WAV"), None);
+        assert_eq!(pattern.direction("az90_el100.wav"), None);
+        assert_eq!(pattern.direction("az_el0.wav"), None);
+        assert_eq!(pattern.direction("notes.txt"), None);
+
+        let pattern: FilenamePattern = "HRIR {beta} {alpha}.wav".parse().unwrap();
+        assert_eq!(pattern.direction("HRIR 045 370.wav"), Some((10.0, 45.0)));
+
+        for invalid in [
+            "az{azimuth}.wav",
+            "{azimuth}{elevation}.wav",
+            "az{azimuth}_el{beta}.wav",
+            "az{azimuth}_el{elevation.wav",
+            "{phi}_{theta}.wav",
+        ] {
+            assert!(invalid.parse::<FilenamePattern>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_wav_folder() {
+        let dir = temp_dir("wav_folder");
+        // 90° x 45° grid from -45° to +90° elevation; the pole once
+        let mut count = 0;
+        for elevation in [-45, 0, 45] {
+            for azimuth in [0, 90, 180, 270] {
+                let samples = vec![azimuth as f32 / 360.0, elevation as f32 / 90.0];
+                write_wav(
+                    &dir,
+                    &format!("az{}_el{}.wav", azimuth, elevation),
+                    48000,
+                    samples,
+                );
+                count += 1;
+            }
+        }
+        write_wav(&dir, "az0_el90.wav", 48000, vec![1.0, 1.0, 1.0]);
+        fs::write(dir.join("README.txt"), "measured 2024").unwrap();
+        let pattern: FilenamePattern = "az{azimuth}_el{elevation}.wav".parse().unwrap();
+
+        let dataset = wav_folder(&dir, &pattern).unwrap();
+        let grid = dataset.grid.equiangular().unwrap();
+        assert_eq!(dataset.num_records(), count + 1);
+        assert_eq!((grid.alpha_points, grid.beta_points), (4, 4));
+        assert_eq!((grid.beta_start, grid.beta_end), (45.0, 180.0));
+        assert_eq!(dataset.num_channels(), 2);
+        assert_eq!(dataset.elements_per_record(), 3);
+        for record in &dataset.records[..count] {
+            let expected = [record.alpha / 360.0, (record.beta - 90.0) / 90.0, 0.0];
+            assert_eq!(record.channels[0], expected);
+            assert_eq!(record.channels[1], expected.map(|x| -x));
+        }
+        assert_eq!(dataset.records[count].channels[0], [1.0; 3]);
+
+        let output = dir.join("array.daff");
+        wav_folder_to_daff(&dir, &pattern, &output).unwrap();
+        assert!(output.is_file());
+
+        // A missing direction breaks the grid
+        fs::remove_file(dir.join("az90_el0.wav")).unwrap();
+        assert!(wav_folder(&dir, &pattern).is_err());
+        // So do mixed sampling rates
+        write_wav(&dir, "az90_el0.wav", 44100, vec![0.0]);
+        assert!(wav_folder(&dir, &pattern).is_err());
+        let other: FilenamePattern = "left_{alpha}_{beta}.wav".parse().unwrap();
+        assert!(wav_folder(&dir, &other).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wav_folder_partial_circle() {
+        // Frontal half circle on the horizontal plane in data view angles
+        let dir = temp_dir("wav_folder_partial");
+        for alpha in [270, 300, 330, 0, 30, 60, 90] {
+            write_wav(
+                &dir,
+                &format!("{}_90.wav", alpha),
+                44100,
+                vec![alpha as f32],
+            );
+        }
+        let pattern: FilenamePattern = "{alpha}_{beta}.wav".parse().unwrap();
+        let dataset = wav_folder(&dir, &pattern).unwrap();
+        let grid = dataset.grid.equiangular().unwrap();
+        assert_eq!(grid.alpha_points, 7);
+        assert_eq!((grid.alpha_start, grid.alpha_end), (270.0, 90.0));
+        assert_eq!(grid.beta_points, 1);
+        let alphas: Vec<f32> = dataset.records.iter().map(|r| r.channels[0][0]).collect();
+        assert_eq!(alphas, [270.0, 300.0, 330.0, 0.0, 30.0, 60.0, 90.0]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
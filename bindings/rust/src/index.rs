@@ -0,0 +1,405 @@
+//! Precomputed lookup structures cached next to a DAFF file
+//!
+//! Building [`DirectivityTable`]s and fitting [`ShCoefficients`] reads and processes every
+//! record, which takes long for dense datasets. A [`LookupIndex`] bundles these structures
+//! and stores them in a `.daffidx` file beside the dataset, so later runs only map the cache
+//! into memory. The index remembers size and modification time of the DAFF file it was built
+//! from and is rebuilt by [`LookupIndex::load_or_build`] when the file changes.
+//!
+//! ```no_run
+//! use opendaff::index::LookupIndex;
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! // Slow on the first run, then read from loudspeaker.daffidx
+//! let index = LookupIndex::load_or_build("loudspeaker.daff", Some(8))?;
+//! if let Some(table) = index.directivity(0) {
+//!     let gain = table.band_gain(30.0, 10.0, 4);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::grid::ShCoefficients;
+use crate::writer::{put_f32, put_i32, put_u64, Input};
+use crate::{ContentType, Dataset, DirectivityTable, Error, Orientation, Reader, Result};
+
+/// File extension of lookup index caches
+pub const INDEX_EXTENSION: &str = "daffidx";
+
+const MAGIC: &[u8; 8] = b"DAFFIDX\0";
+const VERSION: i32 = 1;
+
+/// Identity of a source file: its size and modification time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// Size in bytes
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl Fingerprint {
+    /// Fingerprint of a file as it is now
+    pub fn of(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let error = |e: std::io::Error| {
+            Error::new(format!("Failed to inspect '{}': {}", path.display(), e))
+        };
+        let metadata = fs::metadata(path).map_err(error)?;
+        let modified = metadata.modified().map_err(error)?;
+        let modified = modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Ok(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Precomputed lookup structures of a dataset
+///
+/// Holds one [`DirectivityTable`] per channel for magnitude spectra and, if requested, the
+/// [`ShCoefficients`] of the dataset (for any content type but phase spectra).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupIndex {
+    source: Option<Fingerprint>,
+    directivity: Vec<DirectivityTable>,
+    sh: Option<ShCoefficients>,
+}
+
+impl LookupIndex {
+    /// Precompute the lookup structures of a dataset
+    ///
+    /// `sh_order` selects the order of the spherical harmonic expansion, `None` skips it.
+    pub fn from_dataset(dataset: &Dataset, sh_order: Option<usize>) -> Result<Self> {
+        let directivity = if dataset.content_type() == ContentType::MagnitudeSpectrum {
+            (0..dataset.num_channels())
+                .map(|channel| DirectivityTable::from_dataset(dataset, channel))
+                .collect::<Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        let sh = sh_order
+            .map(|order| ShCoefficients::fit(dataset, order))
+            .transpose()?;
+        Ok(Self {
+            source: None,
+            directivity,
+            sh,
+        })
+    }
+
+    /// Read a DAFF file and precompute its lookup structures
+    pub fn build(daff_path: impl AsRef<Path>, sh_order: Option<usize>) -> Result<Self> {
+        let daff_path = daff_path.as_ref();
+        let source = Fingerprint::of(daff_path)?;
+        let reader = Reader::open(daff_path)?;
+        let dataset = Dataset::from_reader(&reader)?;
+        let mut index = Self::from_dataset(&dataset, sh_order)?;
+        index.source = Some(source);
+        Ok(index)
+    }
+
+    /// Load the cached index of a DAFF file, or build and cache it if it is missing, stale
+    /// or was built with another spherical harmonic order
+    ///
+    /// The cache is written to [`index_path`](LookupIndex::index_path). If it cannot be
+    /// written (e.g. on read-only media), the freshly built index is still returned.
+    pub fn load_or_build(daff_path: impl AsRef<Path>, sh_order: Option<usize>) -> Result<Self> {
+        let daff_path = daff_path.as_ref();
+        let index_path = Self::index_path(daff_path);
+        if let Ok(index) = Self::load(&index_path) {
+            let order = index.sh.as_ref().map(ShCoefficients::order);
+            if order == sh_order && index.is_current(daff_path) {
+                return Ok(index);
+            }
+        }
+        let index = Self::build(daff_path, sh_order)?;
+        let _ = index.save(&index_path);
+        Ok(index)
+    }
+
+    /// Path of the cache belonging to a DAFF file (`hrir.daff` → `hrir.daffidx`)
+    pub fn index_path(daff_path: impl AsRef<Path>) -> PathBuf {
+        daff_path.as_ref().with_extension(INDEX_EXTENSION)
+    }
+
+    /// Load an index from a `.daffidx` file
+    ///
+    /// The file is memory-mapped with the `mmap` feature and read into memory without it. Its
+    /// tables are copied out in bulk; nothing is recomputed.
+    pub fn load(index_path: impl AsRef<Path>) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        let error = |e: std::io::Error| {
+            Error::new(format!("Failed to read '{}': {}", index_path.display(), e))
+        };
+        #[cfg(feature = "mmap")]
+        let map = {
+            let file = fs::File::open(index_path).map_err(error)?;
+            // Safety: the mapped file must not be truncated or modified while it is decoded.
+            // Index files are only ever replaced by renaming (see save), which leaves the
+            // mapped file intact, and the mapping is dropped right after decoding
+            unsafe { memmap2::Mmap::map(&file) }.map_err(error)?
+        };
+        #[cfg(not(feature = "mmap"))]
+        let map = fs::read(index_path).map_err(error)?;
+        Self::decode(&map).map_err(|e| match e {
+            Error::Message(message) => Error::new(format!(
+                "Invalid index '{}': {}",
+                index_path.display(),
+                message
+            )),
+            other => other,
+        })
+    }
+
+    /// Write the index to a `.daffidx` file
+    ///
+    /// The file is replaced atomically, so readers never see a partial index.
+    pub fn save(&self, index_path: impl AsRef<Path>) -> Result<()> {
+        let index_path = index_path.as_ref();
+        let mut temp = index_path.as_os_str().to_owned();
+        temp.push(".part");
+        let error = |e: std::io::Error| {
+            Error::new(format!("Failed to write '{}': {}", index_path.display(), e))
+        };
+        fs::write(&temp, self.encode()).map_err(error)?;
+        fs::rename(&temp, index_path).map_err(error)
+    }
+
+    /// Fingerprint of the DAFF file the index was built from, if it was built from a file
+    pub fn source(&self) -> Option<Fingerprint> {
+        self.source
+    }
+
+    /// Whether the index was built from the current version of the given DAFF file
+    pub fn is_current(&self, daff_path: impl AsRef<Path>) -> bool {
+        self.source.is_some() && Fingerprint::of(daff_path).ok() == self.source
+    }
+
+    /// Directivity tables of all channels (empty unless the dataset is a magnitude spectrum)
+    pub fn directivity_tables(&self) -> &[DirectivityTable] {
+        &self.directivity
+    }
+
+    /// Directivity table of a channel
+    pub fn directivity(&self, channel: usize) -> Option<&DirectivityTable> {
+        self.directivity.get(channel)
+    }
+
+    /// Spherical harmonic expansion of the dataset, if it was requested
+    pub fn sh(&self) -> Option<&ShCoefficients> {
+        self.sh.as_ref()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        put_i32(&mut bytes, VERSION);
+        match self.source {
+            Some(source) => {
+                bytes.push(1);
+                put_u64(&mut bytes, source.size);
+                put_u64(&mut bytes, source.modified);
+            }
+            None => bytes.push(0),
+        }
+
+        put_u64(&mut bytes, self.directivity.len() as u64);
+        for table in &self.directivity {
+            table.encode(&mut bytes);
+        }
+
+        match &self.sh {
+            Some(sh) => {
+                bytes.push(1);
+                put_u64(&mut bytes, sh.order() as u64);
+                put_u64(&mut bytes, sh.num_channels() as u64);
+                put_u64(&mut bytes, sh.elements_per_record() as u64);
+                let orientation = sh.orientation();
+                for angle in [orientation.yaw, orientation.pitch, orientation.roll] {
+                    put_f32(&mut bytes, angle);
+                }
+                put_u64(&mut bytes, sh.as_slice().len() as u64);
+                sh.as_slice()
+                    .iter()
+                    .for_each(|c| bytes.extend_from_slice(&c.to_le_bytes()));
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let Some(data) = bytes.strip_prefix(MAGIC) else {
+            return Err(Error::new("not a DAFF lookup index"));
+        };
+        let mut input = Input::new(data);
+        let version = input.i32()?;
+        if version != VERSION {
+            return Err(Error::new(format!("unsupported version {}", version)));
+        }
+        let flag = |input: &mut Input| match input.array::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(Error::new("invalid flag")),
+        };
+        let count = |value: u64| usize::try_from(value).map_err(|_| Error::new("invalid size"));
+
+        let source = if flag(&mut input)? {
+            Some(Fingerprint {
+                size: input.u64()?,
+                modified: input.u64()?,
+            })
+        } else {
+            None
+        };
+
+        let num_tables = input.sequence_len(1)?;
+        let directivity = (0..num_tables)
+            .map(|_| DirectivityTable::decode(&mut input))
+            .collect::<Result<_>>()?;
+
+        let sh = if flag(&mut input)? {
+            let order = count(input.u64()?)?;
+            let num_channels = count(input.u64()?)?;
+            let elements_per_record = count(input.u64()?)?;
+            let orientation = Orientation {
+                yaw: input.f32()?,
+                pitch: input.f32()?,
+                roll: input.f32()?,
+            };
+            let coefficients = input.f64s()?;
+            Some(ShCoefficients::from_parts(
+                order,
+                num_channels,
+                elements_per_record,
+                orientation,
+                coefficients,
+            )?)
+        } else {
+            None
+        };
+        if !input.is_empty() {
+            return Err(Error::new("trailing data"));
+        }
+        Ok(Self {
+            source,
+            directivity,
+            sh,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::{ContentHeader, EquiangularGrid, MsWriterBuilder};
+
+    fn dataset() -> Dataset {
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![250.0, 1000.0, 4000.0],
+            },
+            EquiangularGrid::with_resolution(15.0, 15.0).unwrap(),
+            2,
+            |alpha, beta, channel| {
+                let front =
+                    1.5 + (alpha as f64).to_radians().cos() * (beta as f64).to_radians().sin();
+                vec![(front * (channel + 1) as f64) as f32; 3]
+            },
+        )
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut index = LookupIndex::from_dataset(&dataset(), Some(2)).unwrap();
+        index.source = Some(Fingerprint {
+            size: 1234,
+            modified: 5678,
+        });
+        assert_eq!(index.directivity_tables().len(), 2);
+        assert_eq!(index.sh().unwrap().as_slice().len(), 2 * 3 * 9);
+        let bytes = index.encode();
+        assert_eq!(LookupIndex::decode(&bytes).unwrap(), index);
+
+        assert!(LookupIndex::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(LookupIndex::decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(LookupIndex::decode(b"DAFFIDX").is_err());
+        let mut corrupt = bytes.clone();
+        corrupt[8] = 2;
+        assert!(LookupIndex::decode(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_sh_coefficients_interpolate() {
+        let index = LookupIndex::from_dataset(&dataset(), Some(1)).unwrap();
+        let sh = index.sh().unwrap();
+        // The data is a first-order function of the direction, so order 1 reproduces it
+        let front = sh.evaluate(0.0, 0.0, 1);
+        assert!(front.iter().all(|&v| (v - 5.0).abs() < 0.05), "{:?}", front);
+        let side = sh.evaluate(90.0, 0.0, 0);
+        assert!(side.iter().all(|&v| (v - 1.5).abs() < 0.05), "{:?}", side);
+
+        let phases = Dataset {
+            header: ContentHeader::PhaseSpectrum {
+                frequencies: vec![250.0, 1000.0, 4000.0],
+            },
+            ..dataset()
+        };
+        assert!(LookupIndex::from_dataset(&phases, Some(1)).is_err());
+        assert!(LookupIndex::from_dataset(&phases, None).is_ok());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_load_or_build() {
+        let daff = temp_path("index.daff");
+        let dataset = dataset();
+        let records: Vec<_> = dataset.records.iter().map(|r| r.channels.clone()).collect();
+        let grid = *dataset.grid.equiangular().unwrap();
+        let builder = MsWriterBuilder::new(grid, 2, vec![250.0, 1000.0, 4000.0]);
+        builder.write(&daff, &records).unwrap();
+
+        let index_path = LookupIndex::index_path(&daff);
+        assert_eq!(index_path, daff.with_extension("daffidx"));
+        let built = LookupIndex::load_or_build(&daff, None).unwrap();
+        assert!(built.is_current(&daff));
+        assert_eq!(LookupIndex::load(&index_path).unwrap(), built);
+        assert_eq!(
+            built.directivity(1).unwrap(),
+            &DirectivityTable::from_dataset(&dataset, 1).unwrap()
+        );
+        assert!(built.sh().is_none());
+
+        // Another order rebuilds the cache
+        let with_sh = LookupIndex::load_or_build(&daff, Some(1)).unwrap();
+        assert_eq!(with_sh.sh().unwrap().order(), 1);
+        assert_eq!(LookupIndex::load(&index_path).unwrap(), with_sh);
+
+        // A changed source file invalidates it
+        let coarse = EquiangularGrid::with_resolution(30.0, 30.0).unwrap();
+        let records = vec![vec![vec![1.0f32; 3]; 2]; coarse.num_records()];
+        MsWriterBuilder::new(coarse, 2, vec![250.0, 1000.0, 4000.0])
+            .write(&daff, &records)
+            .unwrap();
+        assert!(!with_sh.is_current(&daff));
+        let rebuilt = LookupIndex::load_or_build(&daff, Some(1)).unwrap();
+        assert!(rebuilt.is_current(&daff));
+        assert_eq!(
+            rebuilt.directivity(0).unwrap().band_gain(10.0, 20.0, 2),
+            1.0
+        );
+
+        std::fs::write(&index_path, b"garbage").unwrap();
+        assert!(LookupIndex::load(&index_path).is_err());
+        assert!(LookupIndex::load_or_build(&daff, Some(1)).is_ok());
+        std::fs::remove_file(&daff).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+    }
+}
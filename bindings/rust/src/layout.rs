@@ -0,0 +1,308 @@
+//! Byte layout of DAFF files
+//!
+//! A [`FileLayout`] tells where the parts of a DAFF file are, e.g. for hex inspectors or
+//! tools repairing damaged files. All numbers are little endian. A file starts with the file
+//! header, followed by the file block table:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 2    | Signature `FW`                         |
+//! | 2      | 4    | File format version (i32), 170 for 1.7 |
+//! | 6      | 4    | Number of file blocks (i32)            |
+//! | 10     | 20 n | File block entries                     |
+//!
+//! Each file block entry holds the [block ID](BlockKind) (i32), the offset of the block from
+//! the start of the file (u64) and its size (u64). The blocks are:
+//!
+//! - the main header: content type, quantization, number of channels, records and elements
+//!   per record, global metadata index, grid and orientation, 15 32-bit fields;
+//! - the content header with the content-specific values, e.g. the sample rate;
+//! - the record descriptors, one per record and channel, records first. Impulse responses
+//!   store the metadata index (i32), the offset of the samples from the start of the data
+//!   block (u64), the number of leading zeros (i32) and of stored samples (i32); the other
+//!   content types the metadata index and the offset only;
+//! - the data block with the record payloads;
+//! - the metadata block with the global metadata set first.
+//!
+//! Files in a [legacy](crate::legacy) format version store 32-bit offsets and sizes in 12-byte
+//! block entries and use different record descriptors; the layout describes them as stored.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use crate::writer::{self, Input};
+use crate::{legacy, ContentType, Error, Result};
+
+/// The kind of a file block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockKind {
+    /// Main header
+    MainHeader,
+    /// Content header
+    ContentHeader,
+    /// Record descriptors ("record index")
+    RecordDescriptors,
+    /// Record data
+    Data,
+    /// Metadata sets
+    Metadata,
+}
+
+impl BlockKind {
+    /// ID of the block in the file block table
+    pub fn id(self) -> i32 {
+        match self {
+            BlockKind::MainHeader => writer::MAIN_HEADER_ID,
+            BlockKind::ContentHeader => writer::CONTENT_HEADER_ID,
+            BlockKind::RecordDescriptors => writer::RECORD_DESC_ID,
+            BlockKind::Data => writer::DATA_ID,
+            BlockKind::Metadata => writer::METADATA_ID,
+        }
+    }
+
+    /// Block kind with a file block ID, `None` for unknown IDs
+    pub fn from_id(id: i32) -> Option<Self> {
+        [
+            BlockKind::MainHeader,
+            BlockKind::ContentHeader,
+            BlockKind::RecordDescriptors,
+            BlockKind::Data,
+            BlockKind::Metadata,
+        ]
+        .into_iter()
+        .find(|kind| kind.id() == id)
+    }
+}
+
+/// An entry of the file block table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileBlock {
+    /// Block ID
+    pub id: i32,
+    /// Offset from the start of the file in bytes
+    pub offset: u64,
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl FileBlock {
+    /// Kind of the block, `None` for unknown IDs
+    pub fn kind(&self) -> Option<BlockKind> {
+        BlockKind::from_id(self.id)
+    }
+
+    /// Bytes covered by the block
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.size
+    }
+}
+
+/// Byte offsets and sizes of the parts of a DAFF file
+///
+/// Returned by [`Reader::layout`](crate::Reader::layout) or read from a stream with
+/// [`FileLayout::read`]. Offsets are relative to the start of the DAFF data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLayout {
+    version: i32,
+    size: u64,
+    blocks: Vec<FileBlock>,
+    content_type: ContentType,
+    num_channels: u64,
+    num_records: u64,
+}
+
+impl FileLayout {
+    /// Read the layout of the DAFF data starting at the current position of a stream
+    ///
+    /// Only the file header, the block table and the start of the main header are read.
+    /// Blocks exceeding the stream are reported as errors.
+    pub fn read<R: Read + Seek>(mut stream: R) -> Result<Self> {
+        let error = |e: io::Error| Error::new(format!("Failed to read DAFF data: {}", e));
+        let start = stream.stream_position().map_err(error)?;
+        let size = stream
+            .seek(SeekFrom::End(0))
+            .map_err(error)?
+            .saturating_sub(start);
+        stream.seek(SeekFrom::Start(start)).map_err(error)?;
+
+        let mut header = [0u8; writer::FILE_HEADER_SIZE as usize];
+        stream.read_exact(&mut header).map_err(error)?;
+        let version =
+            legacy::detect_version(&header).ok_or_else(|| Error::new("Not a DAFF file"))?;
+        let is_legacy = legacy::is_legacy(version);
+        if !is_legacy && version != legacy::CURRENT_VERSION {
+            return Err(Error::new(format!(
+                "Unsupported file format version {}",
+                version
+            )));
+        }
+        let entry_size = if is_legacy {
+            legacy::BLOCK_ENTRY_SIZE as u64
+        } else {
+            writer::FILE_BLOCK_ENTRY_SIZE
+        };
+        let num_blocks = Input::new(&header[6..]).count()? as u64;
+        if num_blocks.saturating_mul(entry_size) > size - header.len() as u64 {
+            return Err(Error::new("Invalid file block table"));
+        }
+        let mut table = vec![0u8; (num_blocks * entry_size) as usize];
+        stream.read_exact(&mut table).map_err(error)?;
+        let mut input = Input::new(&table);
+        let mut blocks = Vec::new();
+        for _ in 0..num_blocks {
+            let id = input.i32()?;
+            let (offset, block_size) = if is_legacy {
+                (input.count()? as u64, input.count()? as u64)
+            } else {
+                (input.u64()?, input.u64()?)
+            };
+            if offset.saturating_add(block_size) > size {
+                return Err(Error::new(format!("File block {} exceeds the file", id)));
+            }
+            blocks.push(FileBlock {
+                id,
+                offset,
+                size: block_size,
+            });
+        }
+
+        // Content type, quantization, number of channels and records
+        let main_header = blocks
+            .iter()
+            .find(|block| block.kind() == Some(BlockKind::MainHeader))
+            .filter(|block| block.size >= 16)
+            .ok_or_else(|| Error::new("File has no main header block"))?;
+        let mut fields = [0u8; 16];
+        stream
+            .seek(SeekFrom::Start(start + main_header.offset))
+            .map_err(error)?;
+        stream.read_exact(&mut fields).map_err(error)?;
+        let mut input = Input::new(&fields);
+        let content_type = ContentType::from_i32(input.i32()?)
+            .ok_or_else(|| Error::new("Invalid content type"))?;
+        input.i32()?;
+        Ok(Self {
+            version,
+            size,
+            blocks,
+            content_type,
+            num_channels: input.count()? as u64,
+            num_records: input.count()? as u64,
+        })
+    }
+
+    /// File format version
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Size of the DAFF data (the file) in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes covered by the file header and the file block table
+    pub fn header(&self) -> Range<u64> {
+        let entry_size = if legacy::is_legacy(self.version) {
+            legacy::BLOCK_ENTRY_SIZE as u64
+        } else {
+            writer::FILE_BLOCK_ENTRY_SIZE
+        };
+        0..writer::FILE_HEADER_SIZE + self.blocks.len() as u64 * entry_size
+    }
+
+    /// Entries of the file block table, in the order they are stored
+    pub fn blocks(&self) -> &[FileBlock] {
+        &self.blocks
+    }
+
+    /// The block of a kind, `None` if the file has none
+    pub fn block(&self, kind: BlockKind) -> Option<&FileBlock> {
+        self.blocks.iter().find(|block| block.kind() == Some(kind))
+    }
+
+    /// Size of a record descriptor in bytes
+    pub fn descriptor_size(&self) -> u64 {
+        let ir = self.content_type == ContentType::ImpulseResponse;
+        match (legacy::is_legacy(self.version), ir) {
+            (false, true) => writer::IR_DESC_SIZE,
+            (false, false) => writer::DEFAULT_DESC_SIZE,
+            (true, true) => legacy::IR_DESC_SIZE as u64,
+            (true, false) => legacy::DEFAULT_DESC_SIZE as u64,
+        }
+    }
+
+    /// Bytes covered by the descriptor of a record channel, `None` for invalid indices or if
+    /// the descriptor lies outside of the record descriptor block
+    pub fn descriptor(&self, record: usize, channel: usize) -> Option<Range<u64>> {
+        if record as u64 >= self.num_records || channel as u64 >= self.num_channels {
+            return None;
+        }
+        let block = self.block(BlockKind::RecordDescriptors)?;
+        let index = record as u64 * self.num_channels + channel as u64;
+        let start = block.offset + index * self.descriptor_size();
+        let end = start + self.descriptor_size();
+        (end <= block.range().end).then_some(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::grid::EquiangularGrid;
+    use crate::parser::Layout;
+    use crate::test_util::temp_path;
+    use crate::{ContentHeader, Dataset, Reader};
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_layout() {
+        let path = temp_path("layout.daff");
+        let header = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        let grid = EquiangularGrid::with_resolution(30.0, 45.0).unwrap();
+        let dataset = Dataset::from_fn(header, grid, 2, |_, _, channel| vec![channel as f32; 4]);
+        writer::write_dataset(&path, &dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        let mut reader = Reader::open(&path).unwrap();
+        let layout = reader.layout().unwrap();
+        assert_eq!(layout.version(), legacy::CURRENT_VERSION);
+        assert_eq!(layout.size(), bytes.len() as u64);
+        assert_eq!(layout.header(), 0..10 + 5 * 20);
+        assert_eq!(layout.blocks().len(), 5);
+        assert_eq!(layout.descriptor_size(), 20);
+
+        let parsed = Layout::parse(&bytes).unwrap();
+        let range = |kind| {
+            let range = layout.block(kind).unwrap().range();
+            range.start as usize..range.end as usize
+        };
+        assert_eq!(range(BlockKind::RecordDescriptors), parsed.descriptors);
+        assert_eq!(range(BlockKind::Data), parsed.data);
+        assert_eq!(Some(range(BlockKind::Metadata)), parsed.metadata);
+        let last = grid.num_records() - 1;
+        let descriptor = layout.descriptor(last, 1).unwrap();
+        assert_eq!(
+            descriptor.end as usize,
+            parsed.descriptors.start + 2 * 20 * grid.num_records()
+        );
+        assert_eq!(layout.descriptor(last + 1, 0), None);
+        assert_eq!(layout.descriptor(0, 2), None);
+
+        // Offsets are relative to the start of the DAFF data
+        let mut stream = Cursor::new([vec![0; 7], bytes.clone()].concat());
+        stream.set_position(7);
+        assert_eq!(FileLayout::read(&mut stream).unwrap(), layout);
+        assert!(FileLayout::read(Cursor::new(&bytes[..bytes.len() - 1])).is_err());
+
+        reader.close();
+        assert!(matches!(reader.layout(), Err(Error::Closed)));
+        reader.open_bytes(&bytes).unwrap();
+        assert_eq!(reader.layout().unwrap(), layout);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
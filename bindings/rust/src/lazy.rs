@@ -0,0 +1,359 @@
+//! Lazily loaded DAFF files
+//!
+//! Opening a file with the [`Reader`](crate::Reader) reads its whole data block, which takes
+//! seconds for datasets of several gigabytes. A [`LazyFile`] reads only the headers, the
+//! record descriptors and the metadata up front and loads the payloads of a record from disk
+//! the first time it is accessed, so a renderer can start right away and only ever loads the
+//! directions it uses.
+//!
+//! ```no_run
+//! # fn main() -> opendaff::Result<()> {
+//! let file = opendaff::lazy::LazyFile::open("hrir.daff")?;
+//! let record = file.grid_lookup()?.nearest_neighbour(90.0, 0.0);
+//! let left = file.channel(record, 0)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unlike a [`MappedFile`](crate::mapped::MappedFile), a lazy file does not depend on memory
+//! mapping, so it also works for files on network shares that cannot be mapped reliably, and
+//! loaded records stay decoded. Any storage with random access can be read lazily by
+//! implementing [`Source`] for it, like the [`HttpSource`](crate::remote::HttpSource) of the
+//! `http` feature does for files on web servers.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::grid::EquiangularGrid;
+use crate::lookup::GridLookup;
+use crate::metadata::Metadata;
+use crate::parser::{ByteOrder, Layout, ParsedFile};
+use crate::writer::{Input, DATA_ID, FILE_BLOCK_ENTRY_SIZE, FILE_HEADER_SIZE};
+use crate::{legacy, ContentHeader, ContentType, Error, Orientation, Quantization, Record, Result};
+
+/// Storage with random access that a [`LazyFile`] reads from
+pub trait Source {
+    /// Total size of the data in bytes
+    fn size(&mut self) -> io::Result<u64>;
+
+    /// Fill `buffer` with the bytes starting at `offset`
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()>;
+}
+
+impl Source for File {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buffer)
+    }
+}
+
+/// A DAFF file whose records are loaded on first access
+///
+/// Loaded records are kept for the lifetime of the file. All accessors take `&self`, so the
+/// file can be shared between threads; records are loaded by whichever thread needs them
+/// first. The file must not be modified while it is open.
+#[derive(Debug)]
+pub struct LazyFile<S = File> {
+    /// Path or URL of the file, for messages
+    name: String,
+    source: Mutex<S>,
+    /// The file with everything but the data block read
+    headers: ParsedFile,
+    records: Vec<OnceLock<Record>>,
+}
+
+impl LazyFile {
+    /// Open a DAFF file, reading and validating its headers, record descriptors and metadata
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| Error::new(format!("Failed to open '{}': {}", path.display(), e)))?;
+        Self::from_source(path.display().to_string(), file)
+    }
+}
+
+impl<S: Source> LazyFile<S> {
+    /// Read and validate the headers, record descriptors and metadata of a DAFF file from a
+    /// source; `name` identifies the file in error messages
+    pub fn from_source(name: impl Into<String>, mut source: S) -> Result<Self> {
+        let name = name.into();
+        let image = read_headers(&mut source)
+            .map_err(|e| Error::new(format!("Failed to read '{}': {}", name, e)))?;
+        let headers = ParsedFile::new(image)
+            .map_err(|e| Error::new(format!("Invalid DAFF file '{}': {}", name, e)))?;
+        Ok(Self {
+            records: (0..headers.num_records())
+                .map(|_| OnceLock::new())
+                .collect(),
+            name,
+            source: Mutex::new(source),
+            headers,
+        })
+    }
+
+    /// Path or URL of the file
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Headers and block locations of the file
+    pub fn layout(&self) -> &Layout {
+        self.headers.layout()
+    }
+
+    /// Content type and content-specific header values
+    pub fn header(&self) -> &ContentHeader {
+        self.headers.header()
+    }
+
+    /// Content type of the file
+    pub fn content_type(&self) -> ContentType {
+        self.headers.content_type()
+    }
+
+    /// Quantization of the stored data
+    pub fn quantization(&self) -> Quantization {
+        self.headers.quantization()
+    }
+
+    /// Sampling grid of the records
+    pub fn grid(&self) -> &EquiangularGrid {
+        self.headers.grid()
+    }
+
+    /// Constant-time record lookups on the grid of the file
+    pub fn grid_lookup(&self) -> Result<GridLookup> {
+        GridLookup::new(self.grid(), self.orientation())
+    }
+
+    /// Default orientation of the file
+    pub fn orientation(&self) -> Orientation {
+        self.headers.orientation()
+    }
+
+    /// Number of channels
+    pub fn num_channels(&self) -> usize {
+        self.headers.num_channels()
+    }
+
+    /// Number of records
+    pub fn num_records(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Number of values per record and channel, with interleaved real/imaginary values for
+    /// MPS and DFT content
+    pub fn elements_per_record(&self) -> usize {
+        self.headers.elements_per_record()
+    }
+
+    /// Global metadata, with keys in upper case like the C++ reader reports them
+    pub fn metadata(&self) -> Result<Metadata> {
+        self.headers.metadata().map_err(Error::from)
+    }
+
+    /// Whether a record has been loaded
+    pub fn is_loaded(&self, index: usize) -> bool {
+        self.records.get(index).is_some_and(|r| r.get().is_some())
+    }
+
+    /// Number of records loaded so far
+    pub fn loaded_records(&self) -> usize {
+        self.records.iter().filter(|r| r.get().is_some()).count()
+    }
+
+    /// Direction and data of all channels of a record, loading it if necessary
+    pub fn record(&self, index: usize) -> Result<&Record> {
+        let slot = self
+            .records
+            .get(index)
+            .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+        if let Some(record) = slot.get() {
+            return Ok(record);
+        }
+        let record = self.load_record(index)?;
+        // Another thread may have loaded the record meanwhile; both read the same data
+        Ok(slot.get_or_init(|| record))
+    }
+
+    /// Data of one record channel, loading the record if necessary
+    pub fn channel(&self, record: usize, channel: usize) -> Result<&[f32]> {
+        let record = self.record(record)?;
+        record
+            .channels
+            .get(channel)
+            .map(Vec::as_slice)
+            .ok_or_else(|| Error::new(format!("Invalid channel {}", channel)))
+    }
+
+    /// Load records that are about to be needed, e.g. all records of a region before
+    /// playback starts
+    pub fn load(&self, records: &[usize]) -> Result<()> {
+        for &index in records {
+            self.record(index)?;
+        }
+        Ok(())
+    }
+
+    /// Read and decode the payloads of all channels of a record
+    fn load_record(&self, index: usize) -> Result<Record> {
+        let (alpha, beta) = self
+            .grid()
+            .record_coords(index)
+            .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+        let layout = self.layout();
+        let image = self.headers.bytes();
+        let mut source = self.source.lock().unwrap_or_else(|e| e.into_inner());
+        let mut payload = Vec::new();
+        let channels = (0..self.num_channels())
+            .map(|channel| {
+                // The layout locates the payload in the unread part of the buffer
+                let (leading_zeros, unread) = layout.payload(image, index, channel)?;
+                let offset = unread.as_ptr() as usize - image.as_ptr() as usize;
+                payload.resize(unread.len(), 0);
+                source.read_at(offset as u64, &mut payload).map_err(|e| {
+                    Error::new(format!(
+                        "Failed to read record {} of '{}': {}",
+                        index, self.name, e
+                    ))
+                })?;
+                let mut data = vec![0.0; self.elements_per_record()];
+                layout.decode_payload(leading_zeros, &payload, &mut data)?;
+                Ok(data)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Record {
+            alpha,
+            beta,
+            channels,
+        })
+    }
+}
+
+/// Read the headers, record descriptors and metadata of DAFF data into a zeroed buffer of the
+/// size of the data, at their positions in the data
+///
+/// Operating systems provide such buffers without committing memory to the parts that are
+/// never written, so the unread data block costs address space only. Of legacy files only the
+/// file header is read, their block tables differ.
+pub(crate) fn read_headers(source: &mut impl Source) -> io::Result<Vec<u8>> {
+    let size = usize::try_from(source.size()?)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "The data is too large"))?;
+    let mut image = vec![0u8; size];
+
+    let header_size = (FILE_HEADER_SIZE as usize).min(size);
+    source.read_at(0, &mut image[..header_size])?;
+    if !legacy::detect_version(&image[..header_size]).is_some_and(legacy::is_legacy) {
+        for (id, range) in blocks(source, &mut image)? {
+            if id != DATA_ID {
+                source.read_at(range.start as u64, &mut image[range])?;
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Read the file block table following the file header into `image`, returning the ID and
+/// the bytes of every block within the file
+fn blocks(source: &mut impl Source, image: &mut [u8]) -> io::Result<Vec<(i32, Range<usize>)>> {
+    let start = FILE_HEADER_SIZE as usize;
+    let byte_order = ByteOrder::detect(image).unwrap_or(ByteOrder::LittleEndian);
+    // Invalid block tables are reported by the parser
+    let end = image
+        .get(start - 4..start)
+        .and_then(|count| Input::with_byte_order(count, byte_order).count().ok())
+        .and_then(|n| n.checked_mul(FILE_BLOCK_ENTRY_SIZE as usize))
+        .and_then(|size| size.checked_add(start))
+        .filter(|&end| end <= image.len());
+    let Some(end) = end else {
+        return Ok(Vec::new());
+    };
+    source.read_at(start as u64, &mut image[start..end])?;
+
+    let mut input = Input::with_byte_order(&image[start..end], byte_order);
+    let mut blocks = Vec::new();
+    while let (Ok(id), Ok(offset), Ok(size)) = (input.i32(), input.u64(), input.u64()) {
+        let range = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(size).ok())
+            .and_then(|(offset, size)| Some(offset..offset.checked_add(size)?))
+            .filter(|range| range.end <= image.len());
+        blocks.extend(range.map(|range| (id, range)));
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::swap_byte_order;
+    use crate::test_util::write_file;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_loads_on_access() {
+        let ir = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        let mps = ContentHeader::MagnitudePhaseSpectrum {
+            frequencies: vec![250.0, 1000.0, 4000.0],
+        };
+        for (name, header, quantization) in [
+            ("lazy-int16.daff", ir, Quantization::Int16),
+            ("lazy-mps.daff", mps, Quantization::Float32),
+        ] {
+            let path = write_file(name, header, quantization);
+            let parsed = ParsedFile::new(std::fs::read(&path).unwrap()).unwrap();
+            let file = LazyFile::open(&path).unwrap();
+            assert_eq!(file.header(), parsed.header());
+            assert_eq!(file.grid(), parsed.grid());
+            assert_eq!(file.metadata().unwrap(), parsed.metadata().unwrap());
+            assert_eq!(file.loaded_records(), 0);
+
+            assert_eq!(file.channel(7, 1).unwrap(), &*parsed.channel(7, 1).unwrap());
+            assert!(file.is_loaded(7) && !file.is_loaded(8));
+            assert_eq!(file.record(7).unwrap(), &parsed.record(7).unwrap());
+            file.load(&[0, 1, 7]).unwrap();
+            assert_eq!(file.loaded_records(), 3);
+            assert!(file.channel(7, 2).is_err());
+            assert!(file.record(file.num_records()).is_err());
+            assert!(!file.is_loaded(file.num_records()));
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_byte_order_and_damage() {
+        let path = write_file(
+            "lazy-big.daff",
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            Quantization::Int24,
+        );
+        let little = std::fs::read(&path).unwrap();
+        std::fs::write(&path, swap_byte_order(&little).unwrap()).unwrap();
+        let big = LazyFile::open(&path).unwrap();
+        assert_eq!(big.layout().byte_order, ByteOrder::BigEndian);
+        let parsed = ParsedFile::new(little.as_slice()).unwrap();
+        for index in 0..big.num_records() {
+            assert_eq!(big.record(index).unwrap(), &parsed.record(index).unwrap());
+        }
+
+        // Truncated data is detected when the headers are read
+        std::fs::write(&path, &little[..little.len() - 3]).unwrap();
+        assert!(LazyFile::open(&path).is_err());
+        std::fs::write(&path, &little[..4]).unwrap();
+        assert!(LazyFile::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(LazyFile::open(&path).is_err());
+    }
+}
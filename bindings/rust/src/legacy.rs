@@ -0,0 +1,473 @@
+//! DAFF files written before version 1.7
+//!
+//! Files with a file format version below 170 use the layout described in `FILEFORMAT.md`
+//! of the OpenDAFF repository: file block entries with 32-bit offsets and sizes, record
+//! descriptors without metadata index and with data offsets relative to the start of the
+//! file, and a scaling factor for every impulse response record channel.
+//!
+//! The C++ library only reads version 1.7, so [`Reader`](crate::Reader) detects legacy files
+//! when they are opened and [upgrades](upgrade) them in memory first.
+//! [`Reader::file_format_version`](crate::Reader::file_format_version) still reports the
+//! version of the file. For consumers that only read the legacy layout, [`downgrade`]
+//! converts version 1.7 data back to version 1.05, which is what the writer does for
+//! [`FormatVersion::V1_05`](crate::writer::FormatVersion::V1_05).
+
+use std::ops::Range;
+
+use crate::parser::ByteOrder;
+use crate::writer::{self, put_i32, put_u64, Input};
+use crate::{ContentType, Error, Quantization, Result};
+
+pub use opendaff_core::format::CURRENT_VERSION;
+
+/// Version number of the legacy layout written by [`downgrade`]
+pub const LEGACY_VERSION: i32 = 105;
+
+/// Size of a legacy file block table entry: ID, offset and size
+pub(crate) const BLOCK_ENTRY_SIZE: usize = 4 + 4 + 4;
+/// Size of a legacy impulse response record channel descriptor
+pub(crate) const IR_DESC_SIZE: usize = 4 + 4 + 4 + 8;
+/// Size of a legacy record channel descriptor of all other content types
+pub(crate) const DEFAULT_DESC_SIZE: usize = 8;
+/// Alignment of the record descriptor and data blocks in upgraded data
+const BLOCK_ALIGNMENT: usize = 16;
+
+/// Whether a file format version number denotes the legacy layout
+pub fn is_legacy(version: i32) -> bool {
+    (1..CURRENT_VERSION).contains(&version)
+}
+
+/// File format version from the file header of DAFF data, `None` if it is not DAFF data
+pub fn detect_version(bytes: &[u8]) -> Option<i32> {
+    match bytes {
+        [b'F', b'W', a, b, c, d, ..] => Some(i32::from_le_bytes([*a, *b, *c, *d])),
+        _ => None,
+    }
+}
+
+/// Record channel as described by a legacy record descriptor
+struct Channel {
+    leading_zeros: i32,
+    length: i32,
+    scaling: f32,
+    payload: Range<usize>,
+}
+
+/// Convert DAFF data in the legacy layout to version 1.7
+///
+/// Headers, samples and metadata are kept. Integer impulse responses with a scaling factor
+/// other than 1 are stored as 32-bit floats, since version 1.7 has no scaling factors.
+pub fn upgrade(bytes: &[u8]) -> Result<Vec<u8>> {
+    let version = detect_version(bytes).ok_or_else(|| Error::new("Not a DAFF file"))?;
+    if !is_legacy(version) {
+        return Err(Error::new(format!(
+            "File format version {} is not a legacy version",
+            version
+        )));
+    }
+    let mut input = Input::new(&bytes[6..]);
+    let mut blocks = Vec::new();
+    for _ in 0..input.count()? {
+        let entry = input.take(BLOCK_ENTRY_SIZE)?;
+        let mut entry = Input::new(entry);
+        let id = entry.i32()?;
+        let offset = entry.count()?;
+        let size = entry.count()?;
+        if offset.saturating_add(size) > bytes.len() {
+            return Err(Error::new(format!("File block {} exceeds the file", id)));
+        }
+        blocks.push((id, offset..offset + size));
+    }
+    let block = |id: i32, name: &str| {
+        blocks
+            .iter()
+            .find(|(block_id, _)| *block_id == id)
+            .map(|(_, range)| range.clone())
+            .ok_or_else(|| Error::new(format!("File has no {} block", name)))
+    };
+
+    let mut main_header = bytes[block(writer::MAIN_HEADER_ID, "main header")?].to_vec();
+    let mut input = Input::new(&main_header);
+    let content_type = ContentType::from_i32(input.i32()?);
+    let quantization = Quantization::from_i32(input.i32()?);
+    let (Some(content_type), Some(quantization)) = (content_type, quantization) else {
+        return Err(Error::new("Invalid content type or quantization"));
+    };
+    let num_channels = input.count()?;
+    let num_records = input.count()?;
+    let stored_elements = input.count()?;
+    main_header.truncate(writer::MAIN_HEADER_SIZE as usize);
+    if main_header.len() < writer::MAIN_HEADER_SIZE as usize {
+        return Err(Error::new("Main header is too small"));
+    }
+
+    let content_header = &bytes[block(writer::CONTENT_HEADER_ID, "content header")?];
+    let frequencies_at = match content_type {
+        ContentType::MagnitudeSpectrum | ContentType::MagnitudePhaseSpectrum => Some(4),
+        ContentType::PhaseSpectrum => Some(0),
+        _ => None,
+    };
+    if let Some(at) = frequencies_at {
+        // Version 1.7 lists the support frequencies in the content header
+        let mut input = Input::new(content_header.get(at..).unwrap_or_default());
+        let count = input.count()?;
+        input
+            .take(count.saturating_mul(4))
+            .map_err(|_| Error::new("Content header lists no support frequencies"))?;
+    }
+
+    let ir = content_type == ContentType::ImpulseResponse;
+    let (value_size, values) = match content_type {
+        ContentType::ImpulseResponse => (writer::quantization_size(quantization), stored_elements),
+        ContentType::MagnitudeSpectrum | ContentType::PhaseSpectrum => (4, stored_elements),
+        _ => (4, 2 * stored_elements),
+    };
+    let desc_size = if ir { IR_DESC_SIZE } else { DEFAULT_DESC_SIZE };
+    let descriptors = block(writer::RECORD_DESC_ID, "record descriptor")?;
+    let count = num_records
+        .checked_mul(num_channels)
+        .filter(|count| count.saturating_mul(desc_size) <= descriptors.len())
+        .ok_or_else(|| Error::new("Record descriptor block is too small"))?;
+    let data = block(writer::DATA_ID, "data")?;
+    let mut input = Input::new(&bytes[descriptors]);
+    let channels = (0..count)
+        .map(|index| {
+            let (leading_zeros, length, scaling) = if ir {
+                (input.i32()?, input.i32()?, input.f32()?)
+            } else {
+                (0, values as i32, 1.0)
+            };
+            let invalid = || {
+                Error::new(format!(
+                    "Record {} channel {} exceeds the data block",
+                    index / num_channels,
+                    index % num_channels
+                ))
+            };
+            let start = usize::try_from(input.u64()?).map_err(|_| invalid())?;
+            let (Ok(zeros), Ok(len)) = (usize::try_from(leading_zeros), usize::try_from(length))
+            else {
+                return Err(invalid());
+            };
+            if zeros.saturating_add(len) > values {
+                return Err(invalid());
+            }
+            let end = start.saturating_add(len * value_size);
+            if start < data.start || end > data.end {
+                return Err(invalid());
+            }
+            Ok(Channel {
+                leading_zeros,
+                length,
+                scaling,
+                payload: start..end,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Scaled integer samples cannot be stored without scaling factor
+    let to_float = quantization != Quantization::Float32
+        && channels.iter().any(|channel| channel.scaling != 1.0);
+    if to_float {
+        main_header[4..8].copy_from_slice(&(Quantization::Float32 as i32).to_le_bytes());
+    }
+
+    let mut records = Vec::new();
+    let mut samples = Vec::new();
+    let mut decoded = vec![0.0; values];
+    for channel in &channels {
+        put_i32(&mut records, -1);
+        put_u64(&mut records, samples.len() as u64);
+        if ir {
+            put_i32(&mut records, channel.leading_zeros);
+            put_i32(&mut records, channel.length);
+        }
+        let payload = &bytes[channel.payload.clone()];
+        if to_float {
+            let decoded = &mut decoded[..channel.length as usize];
+            writer::decode_into(payload, quantization, ByteOrder::LittleEndian, decoded);
+            for value in decoded.iter() {
+                samples.extend_from_slice(&(value * channel.scaling).to_le_bytes());
+            }
+        } else {
+            samples.extend_from_slice(payload);
+        }
+    }
+
+    let metadata = match block(writer::METADATA_ID, "metadata") {
+        Ok(range) if !range.is_empty() => {
+            let metadata = &bytes[range];
+            let mut input = Input::new(metadata);
+            while !input.is_empty() {
+                writer::decode_metadata(&mut input)
+                    .map_err(|e| Error::new(format!("Invalid legacy metadata: {}", e)))?;
+            }
+            metadata.to_vec()
+        }
+        _ => 0i32.to_le_bytes().to_vec(),
+    };
+
+    let align = |offset: usize| (offset + BLOCK_ALIGNMENT - 1) / BLOCK_ALIGNMENT * BLOCK_ALIGNMENT;
+    let main_offset =
+        writer::FILE_HEADER_SIZE as usize + 5 * writer::FILE_BLOCK_ENTRY_SIZE as usize;
+    let content_offset = main_offset + main_header.len();
+    let records_offset = align(content_offset + content_header.len());
+    let samples_offset = align(records_offset + records.len());
+    let metadata_offset = samples_offset + samples.len();
+
+    let mut upgraded = Vec::with_capacity(metadata_offset + metadata.len());
+    upgraded.extend_from_slice(b"FW");
+    put_i32(&mut upgraded, CURRENT_VERSION);
+    put_i32(&mut upgraded, 5);
+    for (id, offset, size) in [
+        (writer::MAIN_HEADER_ID, main_offset, main_header.len()),
+        (
+            writer::CONTENT_HEADER_ID,
+            content_offset,
+            content_header.len(),
+        ),
+        (writer::RECORD_DESC_ID, records_offset, records.len()),
+        (writer::DATA_ID, samples_offset, samples.len()),
+        (writer::METADATA_ID, metadata_offset, metadata.len()),
+    ] {
+        put_i32(&mut upgraded, id);
+        put_u64(&mut upgraded, offset as u64);
+        put_u64(&mut upgraded, size as u64);
+    }
+    upgraded.extend_from_slice(&main_header);
+    upgraded.extend_from_slice(content_header);
+    upgraded.resize(records_offset, 0);
+    upgraded.extend_from_slice(&records);
+    upgraded.resize(samples_offset, 0);
+    upgraded.extend_from_slice(&samples);
+    upgraded.extend_from_slice(&metadata);
+    Ok(upgraded)
+}
+
+/// Convert DAFF data in version 1.7 to the legacy layout of version 1.05
+///
+/// Headers, samples and global metadata are kept, and every impulse response record channel
+/// gets a scaling factor of 1. Fails for data with record metadata, which the legacy layout
+/// cannot store, and for data beyond the 2 GiB its 32-bit offsets address.
+pub fn downgrade(bytes: &[u8]) -> Result<Vec<u8>> {
+    let version = detect_version(bytes).ok_or_else(|| Error::new("Not a DAFF file"))?;
+    if version != CURRENT_VERSION {
+        return Err(Error::new(format!(
+            "File format version {} cannot be downgraded",
+            version
+        )));
+    }
+    let mut input = Input::new(&bytes[6..]);
+    let mut blocks = Vec::new();
+    for _ in 0..input.count()? {
+        let id = input.i32()?;
+        let (offset, size) = (input.u64()?, input.u64()?);
+        let block = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(size).ok())
+            .and_then(|(offset, size)| bytes.get(offset..offset.checked_add(size)?))
+            .ok_or_else(|| Error::new(format!("File block {} exceeds the file", id)))?;
+        blocks.push((id, block));
+    }
+    let block = |id: i32, name: &str| {
+        blocks
+            .iter()
+            .find(|(block_id, _)| *block_id == id)
+            .map(|(_, block)| *block)
+            .ok_or_else(|| Error::new(format!("File has no {} block", name)))
+    };
+    let main_header = block(writer::MAIN_HEADER_ID, "main header")?;
+    let content_header = block(writer::CONTENT_HEADER_ID, "content header")?;
+    let descriptors = block(writer::RECORD_DESC_ID, "record descriptor")?;
+    let data = block(writer::DATA_ID, "data")?;
+    let metadata = block(writer::METADATA_ID, "metadata")?;
+
+    let ir =
+        ContentType::from_i32(Input::new(main_header).i32()?) == Some(ContentType::ImpulseResponse);
+    let (current_size, legacy_size) = if ir {
+        (writer::IR_DESC_SIZE as usize, IR_DESC_SIZE)
+    } else {
+        (writer::DEFAULT_DESC_SIZE as usize, DEFAULT_DESC_SIZE)
+    };
+    let count = descriptors.len() / current_size;
+    let header_size = 10 + 5 * BLOCK_ENTRY_SIZE;
+    let records_offset = header_size + main_header.len() + content_header.len();
+    let data_offset = records_offset + count * legacy_size;
+    let metadata_offset = data_offset + data.len();
+    let end = metadata_offset + metadata.len();
+    if i32::try_from(end).is_err() {
+        return Err(Error::new(format!(
+            "Version {} cannot store files of {} bytes",
+            LEGACY_VERSION, end
+        )));
+    }
+
+    let mut legacy = Vec::with_capacity(end);
+    legacy.extend_from_slice(b"FW");
+    put_i32(&mut legacy, LEGACY_VERSION);
+    put_i32(&mut legacy, 5);
+    for (id, offset, size) in [
+        (writer::MAIN_HEADER_ID, header_size, main_header.len()),
+        (
+            writer::CONTENT_HEADER_ID,
+            header_size + main_header.len(),
+            content_header.len(),
+        ),
+        (writer::RECORD_DESC_ID, records_offset, count * legacy_size),
+        (writer::DATA_ID, data_offset, data.len()),
+        (writer::METADATA_ID, metadata_offset, metadata.len()),
+    ] {
+        put_i32(&mut legacy, id);
+        put_i32(&mut legacy, offset as i32);
+        put_i32(&mut legacy, size as i32);
+    }
+    legacy.extend_from_slice(main_header);
+    legacy.extend_from_slice(content_header);
+    for descriptor in descriptors.chunks_exact(current_size) {
+        let mut input = Input::new(descriptor);
+        if input.i32()? != -1 {
+            return Err(Error::new(format!(
+                "Version {} cannot store record metadata",
+                LEGACY_VERSION
+            )));
+        }
+        let offset = input.u64()?;
+        if offset > data.len() as u64 {
+            return Err(Error::new("Record descriptor exceeds the data block"));
+        }
+        if ir {
+            // Leading zeros and length, followed by the scaling factor
+            legacy.extend_from_slice(input.take(8)?);
+            legacy.extend_from_slice(&1.0f32.to_le_bytes());
+        }
+        put_u64(&mut legacy, data_offset as u64 + offset);
+    }
+    legacy.extend_from_slice(data);
+    // Only the global set, record metadata has been ruled out above
+    legacy.extend_from_slice(metadata);
+    Ok(legacy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::{writer, ContentHeader, Dataset, EquiangularGrid, Reader};
+    /// Bytes of a small file in the current version
+    fn current(name: &str, header: ContentHeader, quantization: Quantization) -> Vec<u8> {
+        let path = temp_path(name);
+        let mut dataset = Dataset::from_fn(
+            header,
+            EquiangularGrid::with_resolution(30.0, 45.0).unwrap(),
+            2,
+            |alpha, beta, channel| vec![alpha / 360.0, beta / 180.0, channel as f32 * 0.5, 0.25],
+        );
+        dataset.quantization = quantization;
+        dataset
+            .metadata
+            .insert("DESCRIPTION".to_string(), "Legacy".into());
+        writer::write_dataset(&path, &dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    /// Set the scaling factor of all impulse response record channels of legacy data
+    fn scale(legacy: &mut [u8], scaling: f32) {
+        let entry = 10 + 2 * BLOCK_ENTRY_SIZE;
+        let field = |at: usize| i32::from_le_bytes(legacy[at..at + 4].try_into().unwrap());
+        let (offset, size) = (field(entry + 4) as usize, field(entry + 8) as usize);
+        for descriptor in legacy[offset..offset + size].chunks_exact_mut(IR_DESC_SIZE) {
+            descriptor[8..12].copy_from_slice(&scaling.to_le_bytes());
+        }
+    }
+
+    fn open(bytes: &[u8]) -> Reader {
+        let mut reader = Reader::new().unwrap();
+        reader.open_bytes(bytes).unwrap();
+        reader
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_upgrade() {
+        let ms = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![125.0, 250.0, 500.0, 1000.0],
+        };
+        let ir = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        for (name, header, quantization) in [
+            ("legacy-ms.daff", ms, Quantization::Float32),
+            ("legacy-ir.daff", ir, Quantization::Int16),
+        ] {
+            let bytes = current(name, header, quantization);
+            let legacy = downgrade(&bytes).unwrap();
+            assert_eq!(detect_version(&legacy), Some(105));
+            assert!(upgrade(&bytes).is_err());
+            assert!(downgrade(&legacy).is_err());
+            assert_eq!(upgrade(&legacy).unwrap(), bytes);
+
+            let reader = open(&legacy);
+            assert_eq!(reader.file_format_version().unwrap(), 105);
+            assert_eq!(open(&bytes).file_format_version().unwrap(), CURRENT_VERSION);
+            assert_eq!(
+                Dataset::from_reader(&reader).unwrap(),
+                Dataset::from_reader(&open(&bytes)).unwrap()
+            );
+            for end in [legacy.len() - 1, 100, 6] {
+                assert!(upgrade(&legacy[..end]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_scaled_integer_samples() {
+        let header = ContentHeader::ImpulseResponse {
+            samplerate: 48000.0,
+        };
+        let bytes = current("legacy-scaled.daff", header, Quantization::Int24);
+        let mut legacy = downgrade(&bytes).unwrap();
+        scale(&mut legacy, 0.5);
+        let reader = open(&legacy);
+        assert_eq!(reader.quantization(), Some(Quantization::Float32));
+        let scaled = Dataset::from_reader(&reader).unwrap();
+        let original = Dataset::from_reader(&open(&bytes)).unwrap();
+        for (scaled, original) in scaled.records.iter().zip(&original.records) {
+            for (a, b) in scaled
+                .channels
+                .iter()
+                .flatten()
+                .zip(original.channels.iter().flatten())
+            {
+                assert_eq!(*a, b * 0.5);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_open_legacy_file() {
+        let header = ContentHeader::PhaseSpectrum {
+            frequencies: vec![100.0, 200.0, 300.0, 400.0],
+        };
+        let legacy = downgrade(&current("legacy-ps.daff", header, Quantization::Float32)).unwrap();
+        let path = temp_path("legacy-file.daff");
+        std::fs::write(&path, &legacy).unwrap();
+
+        let mut reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.file_format_version().unwrap(), 105);
+        assert!(reader.open_file(&path).is_err());
+        let dataset = Dataset::from_reader(&reader).unwrap();
+        reader.close();
+        assert!(reader.file_format_version().is_err());
+
+        let mut stream = std::io::Cursor::new([legacy.clone(), vec![0xff; 7]].concat());
+        reader.open_stream(&mut stream).unwrap();
+        assert_eq!(stream.position(), legacy.len() as u64);
+        assert_eq!(reader.file_format_version().unwrap(), 105);
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
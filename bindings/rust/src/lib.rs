@@ -22,8 +22,7 @@
 //! use opendaff::{Reader, ContentType};
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let mut reader = Reader::new()?;
-//!     reader.open_file("path/to/file.daff")?;
+//!     let reader = Reader::open("path/to/file.daff")?;
 //!
 //!     let content_type = reader.content_type();
 //!     println!("Content Type: {:?}", content_type);
@@ -48,26 +47,130 @@
 //! }
 //! ```
 
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi;
+#[cfg(target_arch = "wasm32")]
+#[path = "ffi_wasm.rs"]
 mod ffi;
 
+pub mod analysis;
+pub mod audition;
+pub mod bandsplit;
+#[cfg(feature = "manifest")]
+pub mod cache;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+pub mod dataset;
+pub mod decibel;
+pub mod diff;
+pub mod directivity;
+pub mod dsp;
+pub mod embedded;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod export;
+pub mod grid;
+pub mod import;
+pub mod index;
+pub mod layout;
+pub mod lazy;
+pub mod legacy;
+pub mod listening;
+pub mod lookup;
+#[cfg(feature = "mmap")]
+pub mod mapped;
+pub mod merge;
+pub mod metadata;
+pub mod parser;
+#[cfg(feature = "manifest")]
+pub mod pipeline;
+pub mod pool;
+#[cfg(feature = "protobuf")]
+pub mod proto;
+#[cfg(feature = "digest")]
+pub mod provenance;
+#[cfg(feature = "http")]
+pub mod remote;
+pub mod render;
+pub mod repair;
+#[cfg(feature = "service")]
+pub mod service;
+mod sh;
+#[cfg(feature = "signing")]
+pub mod signature;
+pub mod subjects;
+#[cfg(test)]
+mod test_util;
+pub mod trajectory;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+mod wav;
+pub mod writer;
+
+pub use dataset::{ContentHeader, Dataset, IrSnapshot, MagnitudeUnit, Record};
+pub use opendaff_core::{ContentType, MetadataType, Orientation, Quantization, Section};
+pub use directivity::DirectivityTable;
+pub use export::ExportOptions;
+pub use grid::{EquiangularGrid, Grid, ShCoefficients};
+pub use layout::FileLayout;
+pub use lookup::GridLookup;
+pub use metadata::{MetadataValue, SchemaProfile, Violation};
+#[cfg(feature = "digest")]
+pub use provenance::{Provenance, ProvenanceKey};
+pub use subjects::{Subject, SubjectCollection};
+pub use writer::{
+    DftWriterBuilder, Dither, FnProvider, FormatVersion, IrWriterBuilder, Monitor, MpsWriterBuilder, MsWriterBuilder,
+    PsWriterBuilder, RecordProvider, Writer, WriterBuilder, WriterSpec,
+};
+
+use std::cell::{OnceCell, RefCell};
+use std::collections::hash_map::{Entry, HashMap};
 use std::error::Error as StdError;
 use std::ffi::{CStr, CString};
 use std::fmt;
-use std::marker::PhantomData;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::Arc;
 
 /// Result type for DAFF operations
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Error type for DAFF operations
-#[derive(Debug, Clone)]
-pub struct Error {
-    message: String,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error reported by the DAFF library or the bindings
+    Message(String),
+    /// Metadata key or value that cannot be represented in a DAFF file
+    InvalidMetadata {
+        /// Offending key
+        key: String,
+        /// Description of the violation
+        reason: String,
+    },
+    /// The reader has no open file, because none was opened yet or it has been closed
+    Closed,
+    /// DAFF data with an invalid field, or a block outside of the data
+    Corrupt {
+        /// Section holding the field
+        section: Section,
+        /// Offset of the field from the start of the data in bytes
+        offset: usize,
+        /// Description of the defect
+        reason: String,
+    },
 }
 
 impl Error {
     fn new(message: impl Into<String>) -> Self {
-        Self {
-            message: message.into(),
+        Error::Message(message.into())
+    }
+
+    fn invalid_metadata(key: &str, reason: impl Into<String>) -> Self {
+        Error::InvalidMetadata {
+            key: key.to_string(),
+            reason: reason.into(),
         }
     }
 
@@ -88,133 +191,526 @@ impl Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DAFF error: {}", self.message)
+        match self {
+            Error::Message(message) => write!(f, "DAFF error: {}", message),
+            Error::InvalidMetadata { key, reason } => {
+                write!(f, "DAFF error: invalid metadata '{}': {}", key, reason)
+            }
+            Error::Closed => write!(f, "DAFF error: no file is open"),
+            Error::Corrupt {
+                section,
+                offset,
+                reason,
+            } => write!(f, "DAFF error: {} in the {} at byte {}", reason, section, offset),
+        }
     }
 }
 
 impl StdError for Error {}
 
-/// Content types supported by DAFF files
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(i32)]
-pub enum ContentType {
-    /// Impulse response
-    ImpulseResponse = 1,
-    /// Magnitude spectrum
-    MagnitudeSpectrum = 2,
-    /// Phase spectrum
-    PhaseSpectrum = 3,
-    /// Magnitude-phase spectrum
-    MagnitudePhaseSpectrum = 4,
-    /// DFT coefficients
-    DftSpectrum = 5,
+impl From<opendaff_core::Error> for Error {
+    fn from(error: opendaff_core::Error) -> Self {
+        match error {
+            opendaff_core::Error::Malformed(message) => Error::Message(message),
+            opendaff_core::Error::InvalidMetadata { key, reason } => {
+                Error::InvalidMetadata { key, reason }
+            }
+            opendaff_core::Error::Corrupt {
+                section,
+                offset,
+                reason,
+            } => Error::Corrupt {
+                section,
+                offset,
+                reason,
+            },
+            error => Error::new(error.to_string()),
+        }
+    }
 }
 
-impl ContentType {
-    fn from_i32(value: i32) -> Option<Self> {
-        match value {
-            1 => Some(ContentType::ImpulseResponse),
-            2 => Some(ContentType::MagnitudeSpectrum),
-            3 => Some(ContentType::PhaseSpectrum),
-            4 => Some(ContentType::MagnitudePhaseSpectrum),
-            5 => Some(ContentType::DftSpectrum),
-            _ => None,
+/// Callback receiving a file name
+type FileHook = dyn Fn(&str) + Send + Sync;
+/// Callback receiving the content type, record index and channel of decoded data
+type RecordHook = dyn Fn(ContentType, i32, i32) + Send + Sync;
+
+/// Lifecycle callbacks of a reader
+#[derive(Clone, Default)]
+struct Hooks {
+    on_open: Option<Arc<FileHook>>,
+    on_close: Option<Arc<FileHook>>,
+    on_record_decoded: Option<Arc<RecordHook>>,
+}
+
+impl Hooks {
+    fn record_decoded(&self, content_type: ContentType, record_index: i32, channel: i32) {
+        if let Some(hook) = &self.on_record_decoded {
+            hook(content_type, record_index, channel);
         }
     }
 }
 
-impl fmt::Display for ContentType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ContentType::ImpulseResponse => write!(f, "Impulse Response"),
-            ContentType::MagnitudeSpectrum => write!(f, "Magnitude Spectrum"),
-            ContentType::PhaseSpectrum => write!(f, "Phase Spectrum"),
-            ContentType::MagnitudePhaseSpectrum => write!(f, "Magnitude-Phase Spectrum"),
-            ContentType::DftSpectrum => write!(f, "DFT Spectrum"),
+/// Properties of an open DAFF file, read at once with [`Reader::properties`]
+///
+/// Angles are given in degrees in the data view. The [`Display`](fmt::Display)
+/// implementation summarizes them in one line, e.g. for log messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Properties {
+    /// Type of the stored content
+    pub content_type: ContentType,
+    /// Quantization of the stored data
+    pub quantization: Quantization,
+    /// Number of channels
+    pub num_channels: usize,
+    /// Number of records
+    pub num_records: usize,
+    /// Number of points in alpha direction
+    pub alpha_points: usize,
+    /// Angle between neighbouring alpha points
+    pub alpha_resolution: f32,
+    /// First alpha angle
+    pub alpha_start: f32,
+    /// Last alpha angle
+    pub alpha_end: f32,
+    /// Number of points in beta direction
+    pub beta_points: usize,
+    /// Angle between neighbouring beta points
+    pub beta_resolution: f32,
+    /// First beta angle
+    pub beta_start: f32,
+    /// Last beta angle
+    pub beta_end: f32,
+    /// Orientation of the data view in the object view
+    pub orientation: Orientation,
+    /// File format version, e.g. 170 for DAFF 1.7
+    pub file_format_version: i32,
+}
+
+impl Properties {
+    /// The equiangular grid described by the properties
+    pub fn grid(&self) -> EquiangularGrid {
+        EquiangularGrid {
+            alpha_points: self.alpha_points,
+            alpha_start: self.alpha_start,
+            alpha_end: self.alpha_end,
+            beta_points: self.beta_points,
+            beta_start: self.beta_start,
+            beta_end: self.beta_end,
         }
     }
 }
 
-/// Quantization type for DAFF data
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(i32)]
-pub enum Quantization {
-    /// 8-bit integer
-    Int8 = 1,
-    /// 16-bit integer
-    Int16 = 2,
-    /// 24-bit integer
-    Int24 = 3,
-    /// 32-bit integer
-    Int32 = 4,
-    /// 32-bit float
-    Float32 = 5,
-    /// 64-bit float
-    Float64 = 6,
+impl fmt::Display for Properties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Orientation { yaw, pitch, roll } = self.orientation;
+        write!(
+            f,
+            "{}, {:?}, {} channels, {}, orientation {}°/{}°/{}°, DAFF {}.{}",
+            self.content_type,
+            self.quantization,
+            self.num_channels,
+            self.grid(),
+            yaw,
+            pitch,
+            roll,
+            self.file_format_version / 100,
+            self.file_format_version % 100 / 10
+        )
+    }
 }
 
-impl Quantization {
-    fn from_i32(value: i32) -> Option<Self> {
-        match value {
-            1 => Some(Quantization::Int8),
-            2 => Some(Quantization::Int16),
-            3 => Some(Quantization::Int24),
-            4 => Some(Quantization::Int32),
-            5 => Some(Quantization::Float32),
-            6 => Some(Quantization::Float64),
-            _ => None,
+/// Builder for a [`Reader`] with lifecycle callbacks
+///
+/// ```no_run
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let decoded = Arc::new(AtomicUsize::new(0));
+/// let counter = Arc::clone(&decoded);
+/// let reader = opendaff::Reader::builder()
+///     .on_open(|filename| println!("Opened {}", filename))
+///     .on_record_decoded(move |_, _, _| {
+///         counter.fetch_add(1, Ordering::Relaxed);
+///     })
+///     .open("path/to/file.daff")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ReaderBuilder {
+    hooks: Hooks,
+}
+
+impl ReaderBuilder {
+    /// Create a builder without callbacks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `hook` with the file name after a file has been opened successfully
+    pub fn on_open(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.hooks.on_open = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with the file name when an open file is closed, explicitly or when the
+    /// reader is dropped
+    pub fn on_close(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.hooks.on_close = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with the content type, record index and channel whenever the data of a
+    /// record channel has been decoded
+    pub fn on_record_decoded(
+        mut self,
+        hook: impl Fn(ContentType, i32, i32) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_record_decoded = Some(Arc::new(hook));
+        self
+    }
+
+    /// Create the reader
+    pub fn build(self) -> Result<Reader> {
+        unsafe {
+            let handle = ffi::RustDAFF_Create();
+            if handle.is_null() {
+                Err(Error::from_last_error())
+            } else {
+                Ok(Reader {
+                    handle,
+                    hooks: self.hooks,
+                    filename: None,
+                    lookup: None,
+                    legacy_version: None,
+                    layout: None,
+                    size: None,
+                })
+            }
         }
     }
-}
 
-/// Orientation in yaw-pitch-roll (degrees)
-#[derive(Debug, Clone, Copy)]
-pub struct Orientation {
-    /// Yaw angle in degrees
-    pub yaw: f32,
-    /// Pitch angle in degrees
-    pub pitch: f32,
-    /// Roll angle in degrees
-    pub roll: f32,
+    /// Create the reader and open a DAFF file, see [`Reader::open`]
+    pub fn open(self, path: impl AsRef<Path>) -> Result<Reader> {
+        let mut reader = self.build()?;
+        reader.open_file(path)?;
+        Ok(reader)
+    }
 }
 
 /// Main DAFF reader interface
 pub struct Reader {
     handle: *mut ffi::RustDAFFReaderHandle,
+    hooks: Hooks,
+    /// Name of the open file
+    filename: Option<String>,
+    /// Index arithmetic for the grid of the open file
+    lookup: Option<GridLookup>,
+    /// File format version of an open legacy file, which the C++ library reads upgraded
+    legacy_version: Option<i32>,
+    /// Byte layout of the open file
+    layout: Option<FileLayout>,
+    /// Size of the open file in bytes
+    size: Option<u64>,
 }
 
 impl Reader {
     /// Create a new DAFF reader
     pub fn new() -> Result<Self> {
+        ReaderBuilder::new().build()
+    }
+
+    /// Create a reader and open a DAFF file
+    ///
+    /// The returned reader is always [valid](Reader::is_valid). Equivalent to
+    /// [`new`](Reader::new) followed by [`open_file`](Reader::open_file).
+    ///
+    /// ```no_run
+    /// # fn main() -> opendaff::Result<()> {
+    /// let reader = opendaff::Reader::open("hrir.daff")?;
+    /// println!("{} records", reader.num_records());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        ReaderBuilder::new().open(path)
+    }
+
+    /// Create a reader with lifecycle callbacks
+    pub fn builder() -> ReaderBuilder {
+        ReaderBuilder::new()
+    }
+
+    /// Open a DAFF file
+    ///
+    /// Files in a [legacy](legacy) format version are detected and upgraded in memory. The
+    /// headers and record descriptors are validated before the DAFF library reads them;
+    /// defects are reported as [`Error::Corrupt`].
+    ///
+    /// Only the headers, record descriptors and metadata are read for the validation, then
+    /// the DAFF library reads the file itself, so the data block is held in memory once. The
+    /// file must not be modified while it is opened. The hooks receive the name converted
+    /// lossily to UTF-8.
+    pub fn open_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let filename = path.to_string_lossy();
+        let io_error = |e| Error::new(format!("Failed to read '{}': {}", filename, e));
+        let mut file = std::fs::File::open(path).map_err(io_error)?;
+        let image = lazy::read_headers(&mut file).map_err(io_error)?;
+        if legacy::detect_version(&image).is_some_and(legacy::is_legacy) {
+            self.open_legacy(&std::fs::read(path).map_err(io_error)?)?;
+        } else {
+            opendaff_core::Layout::parse(&image)?;
+            self.open_path(path)?;
+        }
+        self.filename = Some(filename.into_owned());
+        self.lookup = self.detect_grid_lookup();
+        self.layout = FileLayout::read(io::Cursor::new(&image)).ok();
+        self.size = Some(image.len() as u64);
+        if let (Some(hook), Some(filename)) = (&self.hooks.on_open, &self.filename) {
+            hook(filename);
+        }
+        Ok(())
+    }
+
+    /// Let the DAFF library read a validated file
+    ///
+    /// On Unix the path is passed as raw bytes, so file names that are not valid UTF-8 can be
+    /// opened as well. The library takes paths in the ANSI code page elsewhere, so files with
+    /// other than ASCII names are read here and opened from memory.
+    fn open_path(&mut self, path: &Path) -> Result<()> {
+        #[cfg(unix)]
+        let native = Some(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()));
+        #[cfg(not(unix))]
+        let native = path.to_str().filter(|path| path.is_ascii()).map(str::as_bytes);
+
+        let Some(native) = native else {
+            let bytes = std::fs::read(path)
+                .map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))?;
+            return self.deserialize(&bytes);
+        };
+        let c_path = CString::new(native).map_err(|_| Error::new("Invalid filename"))?;
         unsafe {
-            let handle = ffi::RustDAFF_Create();
-            if handle.is_null() {
-                Err(Error::from_last_error())
-            } else {
-                Ok(Self { handle })
+            if !ffi::RustDAFF_OpenFile(self.handle, c_path.as_ptr()) {
+                return Err(Error::from_last_error());
             }
         }
+        Ok(())
     }
 
-    /// Open a DAFF file
-    pub fn open_file(&mut self, filename: &str) -> Result<()> {
-        let c_filename = CString::new(filename)
-            .map_err(|_| Error::new("Invalid filename"))?;
+    /// Open a DAFF file without blocking the async runtime
+    ///
+    /// Reading and validating a large file takes a while, so [`open_file`](Reader::open_file)
+    /// runs on a blocking task of the tokio runtime, keeping e.g. the other requests of a
+    /// server responsive. The open hook is called on that task.
+    ///
+    /// ```no_run
+    /// # async fn serve() -> opendaff::Result<()> {
+    /// let mut reader = opendaff::Reader::new()?;
+    /// reader.open_file_async("hrir.daff").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn open_file_async(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        if self.is_valid() {
+            return Err(Error::new("A file is already opened"));
+        }
+        let mut reader = ReaderBuilder { hooks: self.hooks.clone() }.build()?;
+        let path = path.as_ref().to_path_buf();
+        let reader = tokio::task::spawn_blocking(move || {
+            reader.open_file(path)?;
+            Ok::<_, Error>(reader)
+        })
+        .await
+        .map_err(|e| Error::new(format!("Opening the file failed: {}", e)))??;
+        // The replaced reader has no file open, so dropping it calls no hooks
+        *self = reader;
+        Ok(())
+    }
+
+    /// Create a reader and open an encrypted DAFF container
+    ///
+    /// The container is decrypted in memory; like [`open_bytes`](Reader::open_bytes), the
+    /// open and close hooks are not called. Fails if the key is wrong or the container was
+    /// modified. See [`encryption`].
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: impl AsRef<Path>, key: &encryption::Key) -> Result<Self> {
+        let bytes = encryption::decrypt_file(path, key)?;
+        let mut reader = Reader::new()?;
+        reader.open_bytes(&bytes)?;
+        Ok(reader)
+    }
+
+    /// Open DAFF data held in memory, e.g. embedded with `include_bytes!` or received over
+    /// the network
+    ///
+    /// The data is copied, so the slice does not need to outlive the reader. Behaves like an
+    /// opened file otherwise, but the open and close hooks are not called.
+    ///
+    /// ```no_run
+    /// # fn main() -> opendaff::Result<()> {
+    /// # fn receive() -> Vec<u8> { Vec::new() }
+    /// let bytes: Vec<u8> = receive();
+    /// let mut reader = opendaff::Reader::new()?;
+    /// reader.open_bytes(&bytes)?;
+    /// let ir = reader.content_ir()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if legacy::detect_version(bytes).is_some_and(legacy::is_legacy) {
+            self.open_legacy(bytes)?;
+        } else {
+            self.deserialize(bytes)?;
+        }
+        self.filename = None;
+        self.lookup = self.detect_grid_lookup();
+        self.layout = FileLayout::read(io::Cursor::new(bytes)).ok();
+        self.size = Some(bytes.len() as u64);
+        Ok(())
+    }
 
+    /// Validate DAFF data in the current format version and open it with the C++ library
+    fn deserialize(&mut self, bytes: &[u8]) -> Result<()> {
+        opendaff_core::Layout::parse(bytes)?;
         unsafe {
-            if ffi::RustDAFF_OpenFile(self.handle, c_filename.as_ptr()) {
-                Ok(())
-            } else {
-                Err(Error::from_last_error())
+            if !ffi::RustDAFF_OpenBytes(self.handle, bytes.as_ptr(), bytes.len()) {
+                return Err(Error::from_last_error());
             }
         }
+        Ok(())
+    }
+
+    /// Upgrade DAFF data in a legacy format version and open it
+    fn open_legacy(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.is_valid() {
+            return Err(Error::new("A file is already opened"));
+        }
+        self.deserialize(&legacy::upgrade(bytes)?)?;
+        self.legacy_version = legacy::detect_version(bytes);
+        Ok(())
+    }
+
+    /// Open DAFF data from a stream, e.g. an entry of an archive, a decrypted container or a
+    /// file of a virtual file system
+    ///
+    /// The data starts at the current position of the stream. Only the bytes up to the end of
+    /// the last file block are read into memory, so other data may follow; afterwards the
+    /// stream is positioned behind the DAFF data. Like [`open_bytes`](Reader::open_bytes), the
+    /// open and close hooks are not called.
+    pub fn open_stream<R: Read + Seek>(&mut self, mut stream: R) -> Result<()> {
+        let error = |e: io::Error| Error::new(format!("Failed to read DAFF data: {}", e));
+        let start = stream.stream_position().map_err(error)?;
+        let available = stream.seek(SeekFrom::End(0)).map_err(error)?.saturating_sub(start);
+        stream.seek(SeekFrom::Start(start)).map_err(error)?;
+
+        // File header (signature, version, number of file blocks) and file block table
+        let mut header = [0u8; 10];
+        stream.read_exact(&mut header).map_err(error)?;
+        let num_blocks = i32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+        // Legacy block tables store 32-bit offsets and sizes
+        let legacy = legacy::detect_version(&header).is_some_and(legacy::is_legacy);
+        let entry_size = if legacy { 12 } else { 20 };
+        let table_size = u64::try_from(num_blocks)
+            .ok()
+            .filter(|&n| n > 0 && n.saturating_mul(entry_size) <= available - header.len() as u64)
+            .ok_or_else(|| Error::new("Invalid file block table"))?
+            * entry_size;
+        let mut table = vec![0u8; table_size as usize];
+        stream.read_exact(&mut table).map_err(error)?;
+        let table_end = header.len() as u64 + table_size;
+        let end = table.chunks(entry_size as usize).fold(table_end, |end, entry| {
+            let (offset, size) = if legacy {
+                let offset = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                let size = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+                (offset as u64, size as u64)
+            } else {
+                let offset = u64::from_le_bytes(entry[4..12].try_into().unwrap());
+                let size = u64::from_le_bytes(entry[12..20].try_into().unwrap());
+                (offset, size)
+            };
+            end.max(offset.saturating_add(size))
+        });
+        if end > available {
+            return Err(Error::new(format!(
+                "DAFF data extends {} bytes beyond the end of the stream",
+                end - available
+            )));
+        }
+
+        let mut bytes = Vec::with_capacity(end as usize);
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&table);
+        stream
+            .take(end - bytes.len() as u64)
+            .read_to_end(&mut bytes)
+            .map_err(error)?;
+        self.open_bytes(&bytes)
     }
 
     /// Close the currently open file
+    ///
+    /// Closing twice is harmless. Afterwards, accessors that return a [`Result`] fail with
+    /// [`Error::Closed`]; content objects borrow the reader, so none can outlive the file.
     pub fn close(&mut self) {
         unsafe {
             ffi::RustDAFF_Close(self.handle);
         }
+        self.closed();
+    }
+
+    /// Fail with [`Error::Closed`] unless a file is open
+    fn ensure_open(&self) -> Result<()> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(Error::Closed)
+        }
+    }
+
+    /// Lookup for the grid of the open file, `None` if the grid does not describe its records
+    fn detect_grid_lookup(&self) -> Option<GridLookup> {
+        let grid = self.grid().ok()?;
+        if grid.num_records() != self.num_records().max(0) as usize {
+            return None;
+        }
+        GridLookup::new(&grid, self.orientation().ok()?).ok()
+    }
+
+    /// Constant-time record lookups on the grid of the open file
+    ///
+    /// The `nearest_neighbour` methods of the content types use it when available, without
+    /// calling into the C++ library. `None` if no file is open.
+    pub fn grid_lookup(&self) -> Option<&GridLookup> {
+        self.lookup.as_ref()
+    }
+
+    /// Nearest neighbours of many directions, checking for the grid lookup only once
+    fn nearest_neighbours(
+        &self,
+        directions: &[(f64, f64)],
+        single: impl Fn(f64, f64) -> i32,
+    ) -> Vec<i32> {
+        match self.grid_lookup() {
+            Some(lookup) => directions
+                .iter()
+                .map(|&(phi, theta)| lookup.nearest_neighbour(phi as f32, theta as f32) as i32)
+                .collect(),
+            None => directions.iter().map(|&(phi, theta)| single(phi, theta)).collect(),
+        }
+    }
+
+    /// Notify the close hook if a file was open
+    fn closed(&mut self) {
+        self.lookup = None;
+        self.legacy_version = None;
+        self.layout = None;
+        self.size = None;
+        if let (Some(filename), Some(hook)) = (self.filename.take(), &self.hooks.on_close) {
+            hook(&filename);
+        }
     }
 
     /// Check if a file is currently open and valid
@@ -247,6 +743,39 @@ impl Reader {
         }
     }
 
+    /// File format version of the open file, e.g. 170 for DAFF 1.7
+    ///
+    /// Files in a [legacy](legacy) version report the version they were written in, although
+    /// the C++ library reads them upgraded to the current version.
+    pub fn file_format_version(&self) -> Result<i32> {
+        self.ensure_open()?;
+        Ok(self
+            .legacy_version
+            .unwrap_or_else(|| unsafe { ffi::RustDAFF_GetFileFormatVersion(self.handle) }))
+    }
+
+    /// Size of the open file in bytes
+    ///
+    /// For checking downloads against the size published with a dataset. Data opened with
+    /// [`open_bytes`](Reader::open_bytes) or [`open_stream`](Reader::open_stream) reports the
+    /// size of the DAFF data; [legacy](legacy) files report their size as stored.
+    pub fn file_size_bytes(&self) -> Result<u64> {
+        self.ensure_open()?;
+        self.size.ok_or_else(|| Error::new("The size of the open file is unknown"))
+    }
+
+    /// Byte offsets and sizes of the file header, the file blocks and the record descriptors
+    /// of the open file, see [`layout`]
+    ///
+    /// Describes the file as stored, also for [legacy](legacy) files. Offsets of data opened
+    /// with [`open_stream`](Reader::open_stream) are relative to the start of the DAFF data.
+    pub fn layout(&self) -> Result<FileLayout> {
+        self.ensure_open()?;
+        self.layout
+            .clone()
+            .ok_or_else(|| Error::new("The layout of the open file could not be read"))
+    }
+
     /// Get the number of records
     pub fn num_records(&self) -> i32 {
         unsafe {
@@ -282,8 +811,119 @@ impl Reader {
         }
     }
 
+    /// Get the first alpha angle in degrees, -1 without open file
+    pub fn alpha_start(&self) -> f32 {
+        unsafe {
+            ffi::RustDAFF_GetAlphaStart(self.handle)
+        }
+    }
+
+    /// Get the last alpha angle in degrees (360° if the full circle is covered), -1 without
+    /// open file
+    pub fn alpha_end(&self) -> f32 {
+        unsafe {
+            ffi::RustDAFF_GetAlphaEnd(self.handle)
+        }
+    }
+
+    /// Get the first beta angle in degrees (0° = south pole), -1 without open file
+    pub fn beta_start(&self) -> f32 {
+        unsafe {
+            ffi::RustDAFF_GetBetaStart(self.handle)
+        }
+    }
+
+    /// Get the last beta angle in degrees (180° = north pole), -1 without open file
+    pub fn beta_end(&self) -> f32 {
+        unsafe {
+            ffi::RustDAFF_GetBetaEnd(self.handle)
+        }
+    }
+
+    /// Get the covered alpha range in degrees, see [`EquiangularGrid::alpha_span`]; -1
+    /// without open file
+    ///
+    /// Less than 360° for datasets that do not cover all azimuths.
+    pub fn alpha_span(&self) -> f32 {
+        self.grid().map_or(-1.0, |grid| grid.alpha_span())
+    }
+
+    /// Get the covered beta range in degrees, -1 without open file
+    ///
+    /// Less than 180° for datasets that do not reach both poles, e.g. without the lower cap.
+    pub fn beta_span(&self) -> f32 {
+        self.grid().map_or(-1.0, |grid| grid.beta_span())
+    }
+
+    /// Get the equiangular sampling grid of the open file (data view, degrees)
+    ///
+    /// Its [`Display`](std::fmt::Display) implementation describes resolution, covered range
+    /// and number of records.
+    pub fn grid(&self) -> Result<EquiangularGrid> {
+        self.ensure_open()?;
+        unsafe {
+            Ok(EquiangularGrid {
+                alpha_points: ffi::RustDAFF_GetAlphaPoints(self.handle).max(0) as usize,
+                alpha_start: ffi::RustDAFF_GetAlphaStart(self.handle),
+                alpha_end: ffi::RustDAFF_GetAlphaEnd(self.handle),
+                beta_points: ffi::RustDAFF_GetBetaPoints(self.handle).max(0) as usize,
+                beta_start: ffi::RustDAFF_GetBetaStart(self.handle),
+                beta_end: ffi::RustDAFF_GetBetaEnd(self.handle),
+            })
+        }
+    }
+
+    /// All properties of the open file at once
+    ///
+    /// ```no_run
+    /// # fn main() -> opendaff::Result<()> {
+    /// let reader = opendaff::Reader::open("hrir.daff")?;
+    /// println!("{}", reader.properties()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn properties(&self) -> Result<Properties> {
+        self.ensure_open()?;
+        let grid = self.grid()?;
+        Ok(Properties {
+            content_type: self.content_type(),
+            quantization: self
+                .quantization()
+                .ok_or_else(|| Error::new("The open file has an unknown quantization"))?,
+            num_channels: self.num_channels().max(0) as usize,
+            num_records: self.num_records().max(0) as usize,
+            alpha_points: grid.alpha_points,
+            alpha_resolution: self.alpha_resolution(),
+            alpha_start: grid.alpha_start,
+            alpha_end: grid.alpha_end,
+            beta_points: grid.beta_points,
+            beta_resolution: self.beta_resolution(),
+            beta_start: grid.beta_start,
+            beta_end: grid.beta_end,
+            orientation: self.orientation()?,
+            file_format_version: self.file_format_version()?,
+        })
+    }
+
+    /// Part of the sphere covered by the records of the open file
+    ///
+    /// ```no_run
+    /// # fn main() -> opendaff::Result<()> {
+    /// let reader = opendaff::Reader::open("hrir.daff")?;
+    /// let coverage = reader.coverage()?;
+    /// if !coverage.contains_direction(&reader.orientation()?, 0.0, -60.0) {
+    ///     eprintln!("Directions below the horizon are not measured ({})", coverage);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn coverage(&self) -> Result<grid::Coverage> {
+        Ok(self.grid()?.coverage())
+    }
+
     /// Get orientation in yaw-pitch-roll
     pub fn orientation(&self) -> Result<Orientation> {
+        self.ensure_open()?;
         let mut yaw = 0.0f32;
         let mut pitch = 0.0f32;
         let mut roll = 0.0f32;
@@ -292,14 +932,14 @@ impl Reader {
             if ffi::RustDAFF_GetOrientationYPR(self.handle, &mut yaw, &mut pitch, &mut roll) == 0 {
                 Ok(Orientation { yaw, pitch, roll })
             } else {
-                Err(Error::new("Failed to get orientation"))
+                Err(Error::from_last_error())
             }
         }
     }
 
     /// Check if metadata key exists
     pub fn has_metadata(&self, key: &str) -> bool {
-        let Ok(c_key) = CString::new(key) else {
+        let Ok(c_key) = metadata::key_to_cstring(key) else {
             return false;
         };
 
@@ -308,27 +948,38 @@ impl Reader {
         }
     }
 
+    /// Get the value type of a metadata key, `None` if the key does not exist
+    pub fn metadata_type(&self, key: &str) -> Option<MetadataType> {
+        let c_key = metadata::key_to_cstring(key).ok()?;
+
+        unsafe {
+            MetadataType::from_i32(ffi::RustDAFF_GetMetadataType(self.handle, c_key.as_ptr()))
+        }
+    }
+
     /// Get metadata value as string
+    ///
+    /// Strings are decoded as UTF-8. Values that are not valid UTF-8 were written by legacy
+    /// tools in Latin-1 and are decoded accordingly, so umlauts in author names or
+    /// descriptions survive. Non-string values are converted to their text representation.
     pub fn metadata_string(&self, key: &str) -> Result<String> {
-        let c_key = CString::new(key)
-            .map_err(|_| Error::new("Invalid key"))?;
+        self.ensure_open()?;
+        let c_key = metadata::key_to_cstring(key)?;
 
         unsafe {
             let c_str = ffi::RustDAFF_GetMetadataString(self.handle, c_key.as_ptr());
             if c_str.is_null() {
                 Err(Error::new(format!("Metadata key '{}' not found", key)))
             } else {
-                Ok(CStr::from_ptr(c_str)
-                    .to_string_lossy()
-                    .into_owned())
+                Ok(metadata::decode_string(CStr::from_ptr(c_str).to_bytes()))
             }
         }
     }
 
     /// Get metadata value as float
     pub fn metadata_float(&self, key: &str) -> Result<f32> {
-        let c_key = CString::new(key)
-            .map_err(|_| Error::new("Invalid key"))?;
+        self.ensure_open()?;
+        let c_key = metadata::key_to_cstring(key)?;
         let mut value = 0.0f32;
 
         unsafe {
@@ -342,8 +993,8 @@ impl Reader {
 
     /// Get metadata value as boolean
     pub fn metadata_bool(&self, key: &str) -> Result<bool> {
-        let c_key = CString::new(key)
-            .map_err(|_| Error::new("Invalid key"))?;
+        self.ensure_open()?;
+        let c_key = metadata::key_to_cstring(key)?;
         let mut value = false;
 
         unsafe {
@@ -355,8 +1006,163 @@ impl Reader {
         }
     }
 
+    /// Get metadata value as integer
+    ///
+    /// Only keys stored as integers are accepted; use [`metadata_float`](Reader::metadata_float)
+    /// to read integers and floating-point numbers alike.
+    pub fn metadata_int(&self, key: &str) -> Result<i32> {
+        self.ensure_open()?;
+        let c_key = metadata::key_to_cstring(key)?;
+        let mut value = 0;
+
+        unsafe {
+            if ffi::RustDAFF_GetMetadataInt(self.handle, c_key.as_ptr(), &mut value) {
+                Ok(value)
+            } else {
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
+    /// Get the names of all metadata keys (upper case)
+    pub fn metadata_keys(&self) -> Vec<String> {
+        unsafe {
+            let count = ffi::RustDAFF_GetNumMetadataKeys(self.handle);
+            (0..count.max(0))
+                .filter_map(|i| {
+                    let c_str = ffi::RustDAFF_GetMetadataKey(self.handle, i);
+                    (!c_str.is_null()).then(|| metadata::decode_string(CStr::from_ptr(c_str).to_bytes()))
+                })
+                .collect()
+        }
+    }
+
+    /// Get all metadata values with their native types, by (upper case) key name
+    ///
+    /// Saves looking up the type of every key to pick the matching getter.
+    pub fn metadata(&self) -> Result<metadata::Metadata> {
+        self.ensure_open()?;
+        metadata::read_all(self)
+    }
+
+    /// Get a metadata value with its native type
+    pub fn metadata_value(&self, key: &str) -> Result<metadata::MetadataValue> {
+        self.ensure_open()?;
+        use metadata::MetadataValue;
+
+        match self.metadata_type(key) {
+            Some(MetadataType::Bool) => self.metadata_bool(key).map(MetadataValue::Bool),
+            Some(MetadataType::Int) => self.metadata_int(key).map(MetadataValue::Int),
+            Some(MetadataType::Float) => {
+                let c_key = metadata::key_to_cstring(key)?;
+                let mut value = 0.0f64;
+                unsafe {
+                    if ffi::RustDAFF_GetMetadataDouble(self.handle, c_key.as_ptr(), &mut value) {
+                        Ok(MetadataValue::Float(value))
+                    } else {
+                        Err(Error::new(format!("Metadata key '{}' not found", key)))
+                    }
+                }
+            }
+            Some(MetadataType::String) => self.metadata_string(key).map(MetadataValue::String),
+            None => Err(Error::new(format!("Metadata key '{}' not found", key))),
+        }
+    }
+
+    /// Get the metadata of a record with their native types, by (upper case) key name
+    ///
+    /// DAFF 1.7 files can store a metadata set per record, e.g. the measurement annotations of
+    /// a direction. Records without metadata (and all records of older files) yield an empty
+    /// map.
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.ensure_open()?;
+        use metadata::MetadataValue;
+
+        let (handle, record) = (self.handle, record_index);
+        let decode = |c_str: *const std::os::raw::c_char| unsafe {
+            metadata::decode_string(CStr::from_ptr(c_str).to_bytes())
+        };
+
+        unsafe {
+            let count = ffi::RustDAFF_GetNumRecordMetadataKeys(handle, record);
+            if count < 0 {
+                return Err(Error::from_last_error());
+            }
+            let mut values = metadata::Metadata::new();
+            for i in 0..count {
+                let c_str = ffi::RustDAFF_GetRecordMetadataKey(handle, record, i);
+                if c_str.is_null() {
+                    return Err(Error::from_last_error());
+                }
+                let c_key = CStr::from_ptr(c_str).to_owned();
+                let key = c_key.as_ptr();
+                let value = match MetadataType::from_i32(ffi::RustDAFF_GetRecordMetadataType(handle, record, key)) {
+                    Some(MetadataType::Bool) => {
+                        let mut value = false;
+                        ffi::RustDAFF_GetRecordMetadataBool(handle, record, key, &mut value)
+                            .then_some(MetadataValue::Bool(value))
+                    }
+                    Some(MetadataType::Int) => {
+                        let mut value = 0;
+                        ffi::RustDAFF_GetRecordMetadataInt(handle, record, key, &mut value)
+                            .then_some(MetadataValue::Int(value))
+                    }
+                    Some(MetadataType::Float) => {
+                        let mut value = 0.0f64;
+                        ffi::RustDAFF_GetRecordMetadataDouble(handle, record, key, &mut value)
+                            .then_some(MetadataValue::Float(value))
+                    }
+                    Some(MetadataType::String) => {
+                        let c_str = ffi::RustDAFF_GetRecordMetadataString(handle, record, key);
+                        (!c_str.is_null()).then(|| MetadataValue::String(decode(c_str)))
+                    }
+                    None => None,
+                };
+                values.insert(decode(c_key.as_ptr()), value.ok_or_else(Error::from_last_error)?);
+            }
+            Ok(values)
+        }
+    }
+
+    /// Unit of the magnitudes of magnitude spectra and magnitude-phase spectra, `None` for
+    /// other content
+    ///
+    /// Declared by the metadata or detected from the magnitudes, see [`MagnitudeUnit`].
+    /// Detection reads the records until it finds a negative magnitude, i.e. all of them for
+    /// linear magnitudes.
+    pub fn magnitude_unit(&self) -> Result<Option<MagnitudeUnit>> {
+        self.ensure_open()?;
+        let magnitudes: Box<dyn Fn(i32, i32) -> Result<Vec<f32>> + '_> = match self.content_type() {
+            ContentType::MagnitudeSpectrum => {
+                let ms = self.content_ms()?;
+                Box::new(move |record, channel| ms.magnitudes(record, channel))
+            }
+            ContentType::MagnitudePhaseSpectrum => {
+                let mps = self.content_mps()?;
+                Box::new(move |record, channel| Ok(mps.coefficients(record, channel)?.0))
+            }
+            _ => return Ok(None),
+        };
+
+        if self.metadata_type(dataset::MAGNITUDE_UNIT_KEY) == Some(MetadataType::String) {
+            let name = self.metadata_string(dataset::MAGNITUDE_UNIT_KEY)?;
+            if let Some(unit) = MagnitudeUnit::from_name(&name) {
+                return Ok(Some(unit));
+            }
+        }
+        for record in 0..self.num_records() {
+            for channel in 0..self.num_channels() {
+                if MagnitudeUnit::detect(&magnitudes(record, channel)?) == MagnitudeUnit::Decibel {
+                    return Ok(Some(MagnitudeUnit::Decibel));
+                }
+            }
+        }
+        Ok(Some(MagnitudeUnit::Linear))
+    }
+
     /// Get impulse response content
     pub fn content_ir(&self) -> Result<ContentIR<'_>> {
+        self.ensure_open()?;
         unsafe {
             let content = ffi::RustDAFF_GetContentIR(self.handle);
             if content.is_null() {
@@ -364,7 +1170,8 @@ impl Reader {
             } else {
                 Ok(ContentIR {
                     handle: content,
-                    _phantom: PhantomData,
+                    reader: self,
+                    scratch: RefCell::new(Vec::new()),
                 })
             }
         }
@@ -372,6 +1179,7 @@ impl Reader {
 
     /// Get magnitude spectrum content
     pub fn content_ms(&self) -> Result<ContentMS<'_>> {
+        self.ensure_open()?;
         unsafe {
             let content = ffi::RustDAFF_GetContentMS(self.handle);
             if content.is_null() {
@@ -379,7 +1187,8 @@ impl Reader {
             } else {
                 Ok(ContentMS {
                     handle: content,
-                    _phantom: PhantomData,
+                    reader: self,
+                    directivity: OnceCell::new(),
                 })
             }
         }
@@ -387,6 +1196,7 @@ impl Reader {
 
     /// Get phase spectrum content
     pub fn content_ps(&self) -> Result<ContentPS<'_>> {
+        self.ensure_open()?;
         unsafe {
             let content = ffi::RustDAFF_GetContentPS(self.handle);
             if content.is_null() {
@@ -394,7 +1204,7 @@ impl Reader {
             } else {
                 Ok(ContentPS {
                     handle: content,
-                    _phantom: PhantomData,
+                    reader: self,
                 })
             }
         }
@@ -402,6 +1212,7 @@ impl Reader {
 
     /// Get magnitude-phase spectrum content
     pub fn content_mps(&self) -> Result<ContentMPS<'_>> {
+        self.ensure_open()?;
         unsafe {
             let content = ffi::RustDAFF_GetContentMPS(self.handle);
             if content.is_null() {
@@ -409,7 +1220,7 @@ impl Reader {
             } else {
                 Ok(ContentMPS {
                     handle: content,
-                    _phantom: PhantomData,
+                    reader: self,
                 })
             }
         }
@@ -417,6 +1228,7 @@ impl Reader {
 
     /// Get DFT content
     pub fn content_dft(&self) -> Result<ContentDFT<'_>> {
+        self.ensure_open()?;
         unsafe {
             let content = ffi::RustDAFF_GetContentDFT(self.handle);
             if content.is_null() {
@@ -424,7 +1236,7 @@ impl Reader {
             } else {
                 Ok(ContentDFT {
                     handle: content,
-                    _phantom: PhantomData,
+                    reader: self,
                 })
             }
         }
@@ -436,6 +1248,7 @@ impl Drop for Reader {
         unsafe {
             ffi::RustDAFF_Destroy(self.handle);
         }
+        self.closed();
     }
 }
 
@@ -445,7 +1258,9 @@ unsafe impl Sync for Reader {}
 /// Impulse Response content
 pub struct ContentIR<'a> {
     handle: *mut ffi::RustDAFFContentHandle,
-    _phantom: PhantomData<&'a ()>,
+    reader: &'a Reader,
+    /// Buffer that prefetched records are read into, kept between calls
+    scratch: RefCell<Vec<f32>>,
 }
 
 impl<'a> ContentIR<'a> {
@@ -461,11 +1276,23 @@ impl<'a> ContentIR<'a> {
 
     /// Find the nearest neighbour record for given angles
     ///
+    /// Answered by the [grid lookup](Reader::grid_lookup) of the reader if available, which
+    /// corrects two errors of the C++ lookup at the alpha wrap-around (see
+    /// [`lookup`](crate::lookup#differences-from-the-c-reader)).
+    ///
     /// # Arguments
-    /// * `phi` - Azimuth angle in radians [0, 2π)
-    /// * `theta` - Elevation angle in radians [-π/2, π/2]
+    /// * `phi` - Azimuth angle in degrees (object view)
+    /// * `theta` - Elevation angle in degrees [-90°, 90°]
     pub fn nearest_neighbour(&self, phi: f64, theta: f64) -> i32 {
-        unsafe { ffi::RustDAFF_ContentIR_GetNearestNeighbour(self.handle, phi, theta) }
+        match self.reader.grid_lookup() {
+            Some(lookup) => lookup.nearest_neighbour(phi as f32, theta as f32) as i32,
+            None => unsafe { ffi::RustDAFF_ContentIR_GetNearestNeighbour(self.handle, phi, theta) },
+        }
+    }
+
+    /// Find the nearest neighbour records for many directions (azimuth, elevation in degrees)
+    pub fn nearest_neighbours(&self, directions: &[(f64, f64)]) -> Vec<i32> {
+        self.reader.nearest_neighbours(directions, |phi, theta| self.nearest_neighbour(phi, theta))
     }
 
     /// Get record coordinates
@@ -484,9 +1311,131 @@ impl<'a> ContentIR<'a> {
             ) {
                 Ok((alpha, beta))
             } else {
-                Err(Error::new("Failed to get record coordinates"))
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
+    /// Get the metadata of a record, see [`Reader::record_metadata`]
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.reader.record_metadata(record_index)
+    }
+
+    /// Copy the records in `range` (all records for `..`) into an owned snapshot
+    ///
+    /// The snapshot does not borrow the reader and can be handed to worker threads.
+    pub fn snapshot(&self, range: impl RangeBounds<i32>) -> Result<IrSnapshot> {
+        let num_records = self.reader.num_records();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => num_records,
+        };
+        if start < 0 || start > end || end > num_records {
+            return Err(Error::new(format!(
+                "Invalid record range {}..{} for {} records",
+                start, end, num_records
+            )));
+        }
+
+        let num_channels = self.reader.num_channels();
+        let records = (start..end)
+            .map(|index| {
+                let (alpha, beta) = self.record_coords(index)?;
+                let channels = (0..num_channels)
+                    .map(|channel| self.filter_coeffs(index, channel))
+                    .collect::<Result<_>>()?;
+                Ok(Record {
+                    alpha: alpha as f32,
+                    beta: beta as f32,
+                    channels,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(IrSnapshot::new(
+            self.samplerate() as f64,
+            self.filter_length() as usize,
+            start as usize,
+            records,
+        ))
+    }
+
+    /// Impulse responses of one channel towards many directions (azimuth, elevation in
+    /// degrees), interpolated bilinearly between the records at the corners of their
+    /// [grid cells](GridLookup::bilinear)
+    ///
+    /// The samples are averaged as stored, without aligning the delays of the records. Each
+    /// record is decoded once per call, however many directions use it.
+    pub fn interpolated_irs(
+        &self,
+        directions: &[(f64, f64)],
+        channel: i32,
+    ) -> Result<Vec<Vec<f32>>> {
+        let lookup = self
+            .reader
+            .grid_lookup()
+            .ok_or_else(|| Error::new("Interpolation requires an equiangular grid"))?;
+        let mut decoded: HashMap<usize, Vec<f32>> = HashMap::new();
+        directions
+            .iter()
+            .map(|&(phi, theta)| {
+                let (cell, weights) = lookup.bilinear(phi as f32, theta as f32);
+                let mut ir = vec![0.0f32; self.filter_length().max(0) as usize];
+                for (index, weight) in cell.into_iter().zip(weights) {
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    let record = match decoded.entry(index) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            entry.insert(self.filter_coeffs(index as i32, channel)?)
+                        }
+                    };
+                    for (sample, &value) in ir.iter_mut().zip(record.iter()) {
+                        *sample += weight * value;
+                    }
+                }
+                Ok(ir)
+            })
+            .collect()
+    }
+
+    /// Read the data of records that are about to be needed, e.g. for directions predicted
+    /// from head motion
+    ///
+    /// The C++ library keeps all records in memory, so this is only a copy of every record
+    /// channel into a buffer kept in the content; no read-ahead hint is given to the OS. The
+    /// copy pages in memory the OS has swapped out and loads the records into the CPU caches,
+    /// so the following [`filter_coeffs`](ContentIR::filter_coeffs) calls do not stall the
+    /// audio thread. Repeated calls do not allocate, and the record decoded hook is not
+    /// called. To have the OS read records of a file on disk ahead of time, open it as a
+    /// `mapped::MappedFile` (feature `mmap`) and use its `prefetch`.
+    pub fn prefetch(&self, record_indices: &[i32]) -> Result<()> {
+        let length = self.filter_length().max(0);
+        let mut scratch = self.scratch.borrow_mut();
+        scratch.resize(length as usize, 0.0);
+        for &record_index in record_indices {
+            for channel in 0..self.reader.num_channels() {
+                let read = unsafe {
+                    ffi::RustDAFF_ContentIR_GetFilterCoeffs(
+                        self.handle,
+                        record_index,
+                        channel,
+                        scratch.as_mut_ptr(),
+                        length,
+                    )
+                };
+                if !read {
+                    return Err(Error::from_last_error());
+                }
             }
         }
+        Ok(())
     }
 
     /// Get filter coefficients for a given record and channel
@@ -502,9 +1451,10 @@ impl<'a> ContentIR<'a> {
                 coeffs.as_mut_ptr(),
                 length as i32,
             ) {
+                self.reader.hooks.record_decoded(ContentType::ImpulseResponse, record_index, channel);
                 Ok(coeffs)
             } else {
-                Err(Error::new("Failed to get filter coefficients"))
+                Err(Error::from_last_error())
             }
         }
     }
@@ -513,7 +1463,9 @@ impl<'a> ContentIR<'a> {
 /// Magnitude Spectrum content
 pub struct ContentMS<'a> {
     handle: *mut ffi::RustDAFFContentHandle,
-    _phantom: PhantomData<&'a ()>,
+    reader: &'a Reader,
+    /// Gain table of the first channel, built by the first band_gain call
+    directivity: OnceCell<DirectivityTable>,
 }
 
 impl<'a> ContentMS<'a> {
@@ -522,9 +1474,35 @@ impl<'a> ContentMS<'a> {
         unsafe { ffi::RustDAFF_ContentMS_GetNumFrequencies(self.handle) }
     }
 
+    /// Get the support frequencies in Hz
+    pub fn frequencies(&self) -> Result<Vec<f32>> {
+        let length = self.num_frequencies().max(0) as usize;
+        let mut frequencies = vec![0.0f32; length];
+
+        unsafe {
+            if ffi::RustDAFF_ContentMS_GetFrequencies(
+                self.handle,
+                frequencies.as_mut_ptr(),
+                length as i32,
+            ) {
+                Ok(frequencies)
+            } else {
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
     /// Find the nearest neighbour record for given angles
     pub fn nearest_neighbour(&self, phi: f64, theta: f64) -> i32 {
-        unsafe { ffi::RustDAFF_ContentMS_GetNearestNeighbour(self.handle, phi, theta) }
+        match self.reader.grid_lookup() {
+            Some(lookup) => lookup.nearest_neighbour(phi as f32, theta as f32) as i32,
+            None => unsafe { ffi::RustDAFF_ContentMS_GetNearestNeighbour(self.handle, phi, theta) },
+        }
+    }
+
+    /// Find the nearest neighbour records for many directions (azimuth, elevation in degrees)
+    pub fn nearest_neighbours(&self, directions: &[(f64, f64)]) -> Vec<i32> {
+        self.reader.nearest_neighbours(directions, |phi, theta| self.nearest_neighbour(phi, theta))
     }
 
     /// Get record coordinates
@@ -541,11 +1519,16 @@ impl<'a> ContentMS<'a> {
             ) {
                 Ok((alpha, beta))
             } else {
-                Err(Error::new("Failed to get record coordinates"))
+                Err(Error::from_last_error())
             }
         }
     }
 
+    /// Get the metadata of a record, see [`Reader::record_metadata`]
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.reader.record_metadata(record_index)
+    }
+
     /// Get magnitude values for a given record and channel
     pub fn magnitudes(&self, record_index: i32, channel: i32) -> Result<Vec<f32>> {
         let length = self.num_frequencies() as usize;
@@ -559,18 +1542,56 @@ impl<'a> ContentMS<'a> {
                 magnitudes.as_mut_ptr(),
                 length as i32,
             ) {
+                self.reader.hooks.record_decoded(ContentType::MagnitudeSpectrum, record_index, channel);
                 Ok(magnitudes)
             } else {
-                Err(Error::new("Failed to get magnitudes"))
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
+    /// Interpolated gain of a frequency band of the first channel towards an object view
+    /// direction (azimuth `phi`, elevation `theta` in degrees)
+    ///
+    /// The first call reads all records into a [`DirectivityTable`]; later calls only
+    /// interpolate in memory. Keep the content object (or the table) around for repeated
+    /// lookups.
+    pub fn band_gain(&self, phi: f64, theta: f64, band: usize) -> Result<f32> {
+        let table = match self.directivity.get() {
+            Some(table) => table,
+            None => {
+                let table = self.directivity_table(0)?;
+                self.directivity.get_or_init(|| table)
             }
+        };
+        if band >= table.num_bands() {
+            return Err(Error::new(format!(
+                "Band {} out of range ({} bands)",
+                band,
+                table.num_bands()
+            )));
         }
+        Ok(table.band_gain(phi as f32, theta as f32, band))
+    }
+
+    /// Read the magnitudes of a channel into a table for fast per-band lookups
+    pub fn directivity_table(&self, channel: i32) -> Result<DirectivityTable> {
+        let records = (0..self.reader.num_records())
+            .map(|r| self.magnitudes(r, channel))
+            .collect::<Result<Vec<_>>>()?;
+        DirectivityTable::new(
+            &self.reader.grid()?,
+            self.reader.orientation()?,
+            self.frequencies()?,
+            records.iter().map(Vec::as_slice),
+        )
     }
 }
 
 /// Phase Spectrum content
 pub struct ContentPS<'a> {
     handle: *mut ffi::RustDAFFContentHandle,
-    _phantom: PhantomData<&'a ()>,
+    reader: &'a Reader,
 }
 
 impl<'a> ContentPS<'a> {
@@ -579,9 +1600,35 @@ impl<'a> ContentPS<'a> {
         unsafe { ffi::RustDAFF_ContentPS_GetNumFrequencies(self.handle) }
     }
 
+    /// Get the support frequencies in Hz
+    pub fn frequencies(&self) -> Result<Vec<f32>> {
+        let length = self.num_frequencies().max(0) as usize;
+        let mut frequencies = vec![0.0f32; length];
+
+        unsafe {
+            if ffi::RustDAFF_ContentPS_GetFrequencies(
+                self.handle,
+                frequencies.as_mut_ptr(),
+                length as i32,
+            ) {
+                Ok(frequencies)
+            } else {
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
     /// Find the nearest neighbour record for given angles
     pub fn nearest_neighbour(&self, phi: f64, theta: f64) -> i32 {
-        unsafe { ffi::RustDAFF_ContentPS_GetNearestNeighbour(self.handle, phi, theta) }
+        match self.reader.grid_lookup() {
+            Some(lookup) => lookup.nearest_neighbour(phi as f32, theta as f32) as i32,
+            None => unsafe { ffi::RustDAFF_ContentPS_GetNearestNeighbour(self.handle, phi, theta) },
+        }
+    }
+
+    /// Find the nearest neighbour records for many directions (azimuth, elevation in degrees)
+    pub fn nearest_neighbours(&self, directions: &[(f64, f64)]) -> Vec<i32> {
+        self.reader.nearest_neighbours(directions, |phi, theta| self.nearest_neighbour(phi, theta))
     }
 
     /// Get record coordinates
@@ -598,11 +1645,16 @@ impl<'a> ContentPS<'a> {
             ) {
                 Ok((alpha, beta))
             } else {
-                Err(Error::new("Failed to get record coordinates"))
+                Err(Error::from_last_error())
             }
         }
     }
 
+    /// Get the metadata of a record, see [`Reader::record_metadata`]
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.reader.record_metadata(record_index)
+    }
+
     /// Get phase values for a given record and channel
     pub fn phases(&self, record_index: i32, channel: i32) -> Result<Vec<f32>> {
         let length = self.num_frequencies() as usize;
@@ -616,9 +1668,10 @@ impl<'a> ContentPS<'a> {
                 phases.as_mut_ptr(),
                 length as i32,
             ) {
+                self.reader.hooks.record_decoded(ContentType::PhaseSpectrum, record_index, channel);
                 Ok(phases)
             } else {
-                Err(Error::new("Failed to get phases"))
+                Err(Error::from_last_error())
             }
         }
     }
@@ -627,7 +1680,7 @@ impl<'a> ContentPS<'a> {
 /// Magnitude-Phase Spectrum content
 pub struct ContentMPS<'a> {
     handle: *mut ffi::RustDAFFContentHandle,
-    _phantom: PhantomData<&'a ()>,
+    reader: &'a Reader,
 }
 
 impl<'a> ContentMPS<'a> {
@@ -636,9 +1689,35 @@ impl<'a> ContentMPS<'a> {
         unsafe { ffi::RustDAFF_ContentMPS_GetNumFrequencies(self.handle) }
     }
 
+    /// Get the support frequencies in Hz
+    pub fn frequencies(&self) -> Result<Vec<f32>> {
+        let length = self.num_frequencies().max(0) as usize;
+        let mut frequencies = vec![0.0f32; length];
+
+        unsafe {
+            if ffi::RustDAFF_ContentMPS_GetFrequencies(
+                self.handle,
+                frequencies.as_mut_ptr(),
+                length as i32,
+            ) {
+                Ok(frequencies)
+            } else {
+                Err(Error::from_last_error())
+            }
+        }
+    }
+
     /// Find the nearest neighbour record for given angles
     pub fn nearest_neighbour(&self, phi: f64, theta: f64) -> i32 {
-        unsafe { ffi::RustDAFF_ContentMPS_GetNearestNeighbour(self.handle, phi, theta) }
+        match self.reader.grid_lookup() {
+            Some(lookup) => lookup.nearest_neighbour(phi as f32, theta as f32) as i32,
+            None => unsafe { ffi::RustDAFF_ContentMPS_GetNearestNeighbour(self.handle, phi, theta) },
+        }
+    }
+
+    /// Find the nearest neighbour records for many directions (azimuth, elevation in degrees)
+    pub fn nearest_neighbours(&self, directions: &[(f64, f64)]) -> Vec<i32> {
+        self.reader.nearest_neighbours(directions, |phi, theta| self.nearest_neighbour(phi, theta))
     }
 
     /// Get record coordinates
@@ -655,11 +1734,16 @@ impl<'a> ContentMPS<'a> {
             ) {
                 Ok((alpha, beta))
             } else {
-                Err(Error::new("Failed to get record coordinates"))
+                Err(Error::from_last_error())
             }
         }
     }
 
+    /// Get the metadata of a record, see [`Reader::record_metadata`]
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.reader.record_metadata(record_index)
+    }
+
     /// Get magnitude and phase coefficients for a given record and channel
     ///
     /// Returns (magnitudes, phases) as separate vectors
@@ -677,9 +1761,10 @@ impl<'a> ContentMPS<'a> {
                 phases.as_mut_ptr(),
                 length as i32,
             ) {
+                self.reader.hooks.record_decoded(ContentType::MagnitudePhaseSpectrum, record_index, channel);
                 Ok((magnitudes, phases))
             } else {
-                Err(Error::new("Failed to get coefficients"))
+                Err(Error::from_last_error())
             }
         }
     }
@@ -688,7 +1773,7 @@ impl<'a> ContentMPS<'a> {
 /// DFT Spectrum content
 pub struct ContentDFT<'a> {
     handle: *mut ffi::RustDAFFContentHandle,
-    _phantom: PhantomData<&'a ()>,
+    reader: &'a Reader,
 }
 
 impl<'a> ContentDFT<'a> {
@@ -702,9 +1787,27 @@ impl<'a> ContentDFT<'a> {
         unsafe { ffi::RustDAFF_ContentDFT_IsSymmetric(self.handle) }
     }
 
+    /// Get the DFT transform size
+    pub fn transform_size(&self) -> i32 {
+        unsafe { ffi::RustDAFF_ContentDFT_GetTransformSize(self.handle) }
+    }
+
+    /// Get the sample rate in Hz
+    pub fn samplerate(&self) -> f64 {
+        unsafe { ffi::RustDAFF_ContentDFT_GetSamplerate(self.handle) }
+    }
+
     /// Find the nearest neighbour record for given angles
     pub fn nearest_neighbour(&self, phi: f64, theta: f64) -> i32 {
-        unsafe { ffi::RustDAFF_ContentDFT_GetNearestNeighbour(self.handle, phi, theta) }
+        match self.reader.grid_lookup() {
+            Some(lookup) => lookup.nearest_neighbour(phi as f32, theta as f32) as i32,
+            None => unsafe { ffi::RustDAFF_ContentDFT_GetNearestNeighbour(self.handle, phi, theta) },
+        }
+    }
+
+    /// Find the nearest neighbour records for many directions (azimuth, elevation in degrees)
+    pub fn nearest_neighbours(&self, directions: &[(f64, f64)]) -> Vec<i32> {
+        self.reader.nearest_neighbours(directions, |phi, theta| self.nearest_neighbour(phi, theta))
     }
 
     /// Get record coordinates
@@ -721,11 +1824,16 @@ impl<'a> ContentDFT<'a> {
             ) {
                 Ok((alpha, beta))
             } else {
-                Err(Error::new("Failed to get record coordinates"))
+                Err(Error::from_last_error())
             }
         }
     }
 
+    /// Get the metadata of a record, see [`Reader::record_metadata`]
+    pub fn record_metadata(&self, record_index: i32) -> Result<metadata::Metadata> {
+        self.reader.record_metadata(record_index)
+    }
+
     /// Get DFT coefficients for a given record and channel
     ///
     /// Returns interleaved real/imaginary values: [real0, imag0, real1, imag1, ...]
@@ -741,9 +1849,10 @@ impl<'a> ContentDFT<'a> {
                 coeffs.as_mut_ptr(),
                 length as i32,
             ) {
+                self.reader.hooks.record_decoded(ContentType::DftSpectrum, record_index, channel);
                 Ok(coeffs)
             } else {
-                Err(Error::new("Failed to get DFT coefficients"))
+                Err(Error::from_last_error())
             }
         }
     }
@@ -752,12 +1861,387 @@ impl<'a> ContentDFT<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::temp_path;
 
     #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
     fn test_reader_creation() {
         let reader = Reader::new();
         assert!(reader.is_ok());
     }
 
-    // Additional tests would require actual DAFF files
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let path = temp_path("open.daff");
+        let dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| vec![alpha / 360.0],
+        );
+        writer::write_dataset(&path, &dataset).unwrap();
+        let filename = path.to_str().unwrap();
+
+        let reader = Reader::open(filename).unwrap();
+        assert!(reader.is_valid());
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&opened);
+        let reader = Reader::builder()
+            .on_open(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .open(filename)
+            .unwrap();
+        assert!(reader.is_valid());
+        assert_eq!(opened.load(Ordering::Relaxed), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Reader::open(filename).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_content_type_values() {
+        // Discriminants are the DAFF_CONTENT_TYPES values of DAFFDefs.h
+        let frequencies = vec![500.0, 1000.0];
+        let cases = [
+            (ContentHeader::ImpulseResponse { samplerate: 44100.0 }, 4, 0),
+            (ContentHeader::MagnitudeSpectrum { frequencies: frequencies.clone() }, 2, 1),
+            (ContentHeader::PhaseSpectrum { frequencies: frequencies.clone() }, 2, 2),
+            (ContentHeader::MagnitudePhaseSpectrum { frequencies }, 4, 3),
+            (ContentHeader::DftSpectrum { samplerate: 44100.0, transform_size: 8 }, 10, 4),
+        ];
+
+        let path = temp_path("content-type-values.daff");
+        for (header, length, value) in cases {
+            let dataset = Dataset::from_fn(
+                header,
+                EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+                1,
+                |_, _, _| vec![0.5; length],
+            );
+            writer::write_dataset(&path, &dataset).unwrap();
+            let reader = Reader::open(&path).unwrap();
+
+            assert_eq!(unsafe { ffi::RustDAFF_GetContentType(reader.handle) }, value);
+            assert_eq!(reader.content_type() as i32, value);
+            assert_eq!(reader.content_type(), dataset.content_type());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_quantization_values() {
+        // Discriminants are the DAFF_QUANTIZATIONS values of DAFFDefs.h
+        let cases = [(Quantization::Int16, 0), (Quantization::Int24, 1), (Quantization::Float32, 2)];
+
+        let grid = EquiangularGrid::with_resolution(90.0, 90.0).unwrap();
+        let records = vec![vec![vec![0.5, -0.25, 0.0, 0.125]]; grid.num_records()];
+        let path = temp_path("quantization-values.daff");
+        for (quantization, value) in cases {
+            IrWriterBuilder::new(grid, 1, 44100.0)
+                .quantization(quantization)
+                .write(&path, &records)
+                .unwrap();
+            let reader = Reader::open(&path).unwrap();
+
+            assert_eq!(unsafe { ffi::RustDAFF_GetQuantization(reader.handle) }, value);
+            assert_eq!(quantization as i32, value);
+            assert_eq!(reader.quantization(), Some(quantization));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open_non_utf8_path() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut name = temp_path("").into_os_string().into_vec();
+        // Latin-1 encoded 'é', which is not valid UTF-8
+        name.push(0xE9);
+        let path = std::path::PathBuf::from(OsString::from_vec(name));
+        let dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| vec![alpha / 360.0],
+        );
+        writer::write_dataset(&path, &dataset).unwrap();
+        assert!(path.to_str().is_none());
+
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open_file_async() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let path = temp_path("open-async.daff");
+        let dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| vec![alpha / 360.0],
+        );
+        writer::write_dataset(&path, &dataset).unwrap();
+        let filename = path.to_str().unwrap();
+
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&opened);
+        let mut reader = Reader::builder()
+            .on_open(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build()
+            .unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            reader.open_file_async(filename).await.unwrap();
+            assert!(reader.open_file_async(filename).await.is_err());
+        });
+        assert!(reader.is_valid());
+        assert_eq!(opened.load(Ordering::Relaxed), 1);
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+
+        reader.close();
+        std::fs::remove_file(&path).unwrap();
+        runtime.block_on(async {
+            assert!(reader.open_file_async(filename).await.is_err());
+        });
+        assert!(!reader.is_valid());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open_bytes() {
+        let path = temp_path("open-bytes.daff");
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum { frequencies: vec![125.0, 1000.0] },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |alpha, beta, channel| vec![alpha / 360.0, beta / 180.0 + channel as f32],
+        );
+        dataset.quantization = Quantization::Float32;
+        dataset.metadata.insert("DESCRIPTION".to_string(), MetadataValue::String("in memory".to_string()));
+        writer::write_dataset(&path, &dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut reader = Reader::new().unwrap();
+        reader.open_bytes(&bytes).unwrap();
+        assert!(reader.is_valid());
+        assert!(reader.grid_lookup().is_some());
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+        assert!(reader.open_bytes(&bytes).is_err());
+        reader.close();
+        assert!(!reader.is_valid());
+
+        // Truncated data and block offsets beyond the end are rejected
+        for length in [0, 5, 60, bytes.len() - 1] {
+            assert!(reader.open_bytes(&bytes[..length]).is_err(), "{} bytes", length);
+            assert!(!reader.is_valid());
+        }
+        let mut corrupted = bytes.clone();
+        corrupted[14..22].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(reader.open_bytes(&corrupted).is_err());
+        reader.open_bytes(&bytes).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open_file_validates_headers() {
+        let path = temp_path("open-file-validates.daff");
+        let dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 44100.0 },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| vec![alpha / 360.0, 0.5, -0.5, 0.0],
+        );
+        writer::write_dataset(&path, &dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        let mut reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.file_size_bytes().unwrap(), bytes.len() as u64);
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+        reader.close();
+
+        // Defects of the headers and descriptors are found before the library reads the file
+        let layout = opendaff_core::Layout::parse(&bytes).unwrap();
+        let mut corrupted = bytes.clone();
+        let descriptor = layout.descriptors.start + 4;
+        corrupted[descriptor..descriptor + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &corrupted).unwrap();
+        assert!(matches!(reader.open_file(&path), Err(Error::Corrupt { .. })));
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(reader.open_file(&path), Err(Error::Corrupt { .. })));
+        assert!(!reader.is_valid());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_open_stream() {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 44100.0 },
+            EquiangularGrid::with_resolution(45.0, 45.0).unwrap(),
+            1,
+            |alpha, beta, _| vec![alpha / 360.0, beta / 180.0],
+        );
+        dataset.quantization = Quantization::Float32;
+        let path = temp_path("open-stream.daff");
+        writer::write_dataset(&path, &dataset).unwrap();
+        let daff = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Embedded between other data, as in an archive
+        let mut archive = b"header".to_vec();
+        archive.extend_from_slice(&daff);
+        archive.extend_from_slice(b"trailer");
+        let mut stream = io::Cursor::new(archive.clone());
+        stream.set_position(6);
+        let mut reader = Reader::new().unwrap();
+        reader.open_stream(&mut stream).unwrap();
+        assert_eq!(stream.position(), 6 + daff.len() as u64);
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), dataset);
+        assert_eq!(reader.file_size_bytes().unwrap(), daff.len() as u64);
+        reader.close();
+
+        let mut truncated = io::Cursor::new(&archive[..archive.len() - 8]);
+        truncated.set_position(6);
+        assert!(reader.open_stream(&mut truncated).is_err());
+        assert!(reader.open_stream(io::Cursor::new(b"FW")).is_err());
+        assert!(!reader.is_valid());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_interpolated_irs() {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 48000.0 },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, beta, _| vec![alpha / 360.0, beta / 180.0, 0.0, 0.0],
+        );
+        dataset.quantization = Quantization::Float32;
+        let path = temp_path("interpolated-irs.daff");
+        writer::write_dataset(&path, &dataset).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let content = reader.content_ir().unwrap();
+        let irs = content.interpolated_irs(&[(45.0, 15.0), (60.0, 30.0), (15.0, 0.0)], 0).unwrap();
+        let expected = [
+            [45.0 / 360.0, 105.0 / 180.0],
+            [60.0 / 360.0, 120.0 / 180.0],
+            [15.0 / 360.0, 0.5],
+        ];
+        for (ir, expected) in irs.iter().zip(expected) {
+            assert_eq!(ir.len(), 4);
+            assert!((ir[0] - expected[0]).abs() < 1e-6, "{:?}", ir);
+            assert!((ir[1] - expected[1]).abs() < 1e-6, "{:?}", ir);
+        }
+        assert!(content.interpolated_irs(&[(0.0, 0.0)], 1).is_err());
+        assert_eq!(content.interpolated_irs(&[], 0).unwrap(), Vec::<Vec<f32>>::new());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_prefetch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 48000.0 },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |alpha, beta, channel| vec![alpha / 360.0, beta / 180.0, channel as f32],
+        );
+        let path = temp_path("prefetch.daff");
+        writer::write_dataset(&path, &dataset).unwrap();
+        let decoded = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&decoded);
+        let reader = Reader::builder()
+            .on_record_decoded(move |_, _, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let content = reader.content_ir().unwrap();
+        let last = reader.num_records() - 1;
+        content.prefetch(&[0, 5, last]).unwrap();
+        content.prefetch(&[]).unwrap();
+        assert!(content.prefetch(&[reader.num_records()]).is_err());
+        assert!(content.prefetch(&[-1]).is_err());
+        assert_eq!(decoded.load(Ordering::Relaxed), 0);
+
+        // The records are copied into the scratch buffer, which keeps its allocation
+        let scratch = content.scratch.borrow().as_ptr();
+        content.prefetch(&[last, 0]).unwrap();
+        assert_eq!(content.scratch.borrow().as_ptr(), scratch);
+        assert_eq!(*content.scratch.borrow(), content.filter_coeffs(0, 1).unwrap());
+        assert_eq!(decoded.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "calls into the C++ library")]
+    fn test_grid_lookup_matches_reader() {
+        let path = temp_path("grid-lookup.daff");
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse { samplerate: 44100.0 },
+            EquiangularGrid::with_resolution(15.0, 10.0).unwrap(),
+            1,
+            |_, _, _| vec![0.5; 4],
+        );
+        dataset.orientation.yaw = 30.0;
+        writer::write_dataset(&path, &dataset).unwrap();
+
+        let mut reader = Reader::open(&path).unwrap();
+        let lookup = *reader.grid_lookup().unwrap();
+        let content = reader.content_ir().unwrap();
+        for azimuth in (-180..180).step_by(11) {
+            for elevation in (-80..90).step_by(9) {
+                let (azimuth, elevation) = (azimuth as f32 + 0.4, elevation as f32 + 0.3);
+                // The C++ reader continues on the next ring just below 360°, a documented
+                // difference covered by lookup::tests::test_matches_cpp_reader
+                let (alpha, _) = grid::to_data_view(&dataset.orientation, azimuth, elevation);
+                if alpha > 352.5 {
+                    continue;
+                }
+                let expected = unsafe {
+                    ffi::RustDAFF_ContentIR_GetNearestNeighbour(
+                        content.handle,
+                        azimuth as f64,
+                        elevation as f64,
+                    )
+                };
+                assert_eq!(lookup.nearest_neighbour(azimuth, elevation) as i32, expected);
+                assert_eq!(content.nearest_neighbour(azimuth as f64, elevation as f64), expected);
+            }
+        }
+        let directions = [(10.0, 0.0), (-95.5, 42.0), (180.0, -90.0)];
+        let expected: Vec<i32> = directions
+            .iter()
+            .map(|&(phi, theta)| content.nearest_neighbour(phi, theta))
+            .collect();
+        assert_eq!(content.nearest_neighbours(&directions), expected);
+
+        reader.close();
+        assert!(reader.grid_lookup().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
 }
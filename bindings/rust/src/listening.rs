@@ -0,0 +1,575 @@
+//! A/B listening tests comparing two datasets
+//!
+//! An [`AbTest`] renders every [`Stimulus`] through both datasets with
+//! [`audition::render`](crate::audition::render), normalizes each rendering to the same
+//! integrated loudness so that level differences do not decide the comparison, and presents
+//! the pairs in a randomized order, with the dataset played first drawn at random for every
+//! trial. The harness stays independent of any audio stack: the application plays
+//! [`AbTest::presentation`] (or the WAV files from [`AbTest::write_trial_wav`]), passes the
+//! listener's answer to [`AbTest::respond`] and finally saves the results with
+//! [`AbTest::save_csv`].
+//!
+//! ```no_run
+//! use opendaff::listening::{AbTest, ListeningOptions, Response, Stimulus};
+//! use opendaff::Dataset;
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! # let (measured, modelled): (Dataset, Dataset) = unimplemented!();
+//! let stimuli = vec![
+//!     Stimulus::from_wav("noise.wav", 30.0, 0.0)?,
+//!     Stimulus::from_wav("speech.wav", -90.0, 20.0)?,
+//! ];
+//! let mut test = AbTest::new(&measured, &modelled, stimuli, &ListeningOptions::default())?
+//!     .with_labels("measured", "modelled");
+//! while let Some(trial) = test.next_trial() {
+//!     test.write_trial_wav(trial, "trial.wav")?;
+//!     // ... play trial.wav and ask which interval sounded more natural
+//!     test.respond(trial, Response::First)?;
+//! }
+//! test.save_csv("subject01.csv")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::analysis;
+use crate::audition::{self, AuditionOptions};
+use crate::wav::{self, Wav};
+use crate::{ContentHeader, Dataset, Error, Result};
+
+/// A signal played from a fixed direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stimulus {
+    /// Name in the results
+    pub name: String,
+    /// Mono signal at the sampling rate of the datasets
+    pub signal: Vec<f32>,
+    /// Object view direction (azimuth, elevation in degrees) of the source
+    pub direction: (f32, f32),
+    /// Sampling rate of the signal in Hz if known, checked against the datasets
+    pub samplerate: Option<f64>,
+}
+
+impl Stimulus {
+    pub fn new(name: impl Into<String>, signal: Vec<f32>, azimuth: f32, elevation: f32) -> Self {
+        Self {
+            name: name.into(),
+            signal,
+            direction: (azimuth, elevation),
+            samplerate: None,
+        }
+    }
+
+    /// Read a stimulus from a WAV file, named after the file and mixed down to mono
+    pub fn from_wav(path: impl AsRef<Path>, azimuth: f32, elevation: f32) -> Result<Self> {
+        let path = path.as_ref();
+        let input = wav::read(path)?;
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |s| s.to_string_lossy().into_owned(),
+        );
+        let mut stimulus = Self::new(name, input.mono(), azimuth, elevation);
+        stimulus.samplerate = Some(input.samplerate as f64);
+        Ok(stimulus)
+    }
+}
+
+/// Settings of an [`AbTest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListeningOptions {
+    /// Settings of the renderer
+    pub audition: AuditionOptions,
+    /// Loudness of every rendering in LUFS
+    pub loudness: f32,
+    /// Number of times every stimulus is presented
+    pub repetitions: usize,
+    /// Seed of the randomization; the same seed reproduces the same order
+    pub seed: u64,
+    /// Silence between the two intervals of a trial in seconds
+    pub gap: f64,
+}
+
+impl Default for ListeningOptions {
+    fn default() -> Self {
+        Self {
+            audition: AuditionOptions::default(),
+            loudness: -23.0,
+            repetitions: 1,
+            seed: 0,
+            gap: 0.5,
+        }
+    }
+}
+
+/// One of the two compared datasets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition {
+    A,
+    B,
+}
+
+impl Condition {
+    fn index(self) -> usize {
+        match self {
+            Condition::A => 0,
+            Condition::B => 1,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Condition::A => Condition::B,
+            Condition::B => Condition::A,
+        }
+    }
+}
+
+/// A stimulus presented through both datasets, one after the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trial {
+    /// Index of the stimulus
+    pub stimulus: usize,
+    /// Repetition of the stimulus, starting at 0
+    pub repetition: usize,
+    /// Dataset played in the first interval
+    pub first: Condition,
+}
+
+impl Trial {
+    /// Dataset played in the second interval
+    pub fn second(&self) -> Condition {
+        self.first.other()
+    }
+}
+
+/// Answer of the listener to a trial
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Response {
+    First,
+    Second,
+    NoPreference,
+}
+
+/// Randomized A/B comparison of two datasets over a set of stimuli
+#[derive(Debug, Clone)]
+pub struct AbTest {
+    labels: [String; 2],
+    samplerate: f64,
+    gap: usize,
+    stimuli: Vec<Stimulus>,
+    /// Level-aligned left and right ear signals per stimulus and condition
+    renderings: Vec<[[Vec<f32>; 2]; 2]>,
+    gains_db: Vec<[f32; 2]>,
+    trials: Vec<Trial>,
+    responses: Vec<Option<Response>>,
+}
+
+impl AbTest {
+    /// Render the stimuli through both datasets and draw the order of the trials
+    ///
+    /// Both datasets must hold impulse responses at the same sampling rate. Renderings are
+    /// scaled to [`ListeningOptions::loudness`]; silent ones are left as they are.
+    pub fn new(
+        a: &Dataset,
+        b: &Dataset,
+        stimuli: Vec<Stimulus>,
+        options: &ListeningOptions,
+    ) -> Result<Self> {
+        let (samplerate, samplerate_b) = (samplerate(a)?, samplerate(b)?);
+        if samplerate_b != samplerate {
+            return Err(Error::new(format!(
+                "Dataset A is sampled at {} Hz, dataset B at {} Hz",
+                samplerate, samplerate_b
+            )));
+        }
+        if stimuli.is_empty() || options.repetitions == 0 {
+            return Err(Error::new("A listening test needs at least one trial"));
+        }
+
+        let mut renderings = Vec::with_capacity(stimuli.len());
+        let mut gains_db = Vec::with_capacity(stimuli.len());
+        for stimulus in &stimuli {
+            if let Some(rate) = stimulus.samplerate.filter(|&rate| rate != samplerate) {
+                return Err(Error::new(format!(
+                    "Stimulus '{}' is sampled at {} Hz, the datasets at {} Hz",
+                    stimulus.name, rate, samplerate
+                )));
+            }
+            let direction = stimulus.direction;
+            let mut rendered = [[Vec::new(), Vec::new()], [Vec::new(), Vec::new()]];
+            let mut gains = [0.0; 2];
+            for (index, dataset) in [a, b].into_iter().enumerate() {
+                let mut ears =
+                    audition::render(&stimulus.signal, dataset, |_| direction, &options.audition)?;
+                let loudness = analysis::lufs([&ears], samplerate)?;
+                if loudness.is_finite() {
+                    gains[index] = options.loudness - loudness;
+                    let gain = 10f32.powf(gains[index] / 20.0);
+                    ears.iter_mut().flatten().for_each(|x| *x *= gain);
+                }
+                rendered[index] = ears;
+            }
+            renderings.push(rendered);
+            gains_db.push(gains);
+        }
+
+        let mut random = SplitMix(options.seed);
+        let mut trials: Vec<Trial> = (0..options.repetitions)
+            .flat_map(|repetition| {
+                (0..stimuli.len()).map(move |stimulus| Trial {
+                    stimulus,
+                    repetition,
+                    first: Condition::A,
+                })
+            })
+            .collect();
+        for trial in &mut trials {
+            if random.next() & 1 == 1 {
+                trial.first = Condition::B;
+            }
+        }
+        // Fisher-Yates shuffle
+        for i in (1..trials.len()).rev() {
+            let j = (random.next() % (i as u64 + 1)) as usize;
+            trials.swap(i, j);
+        }
+
+        Ok(Self {
+            labels: ["A".to_string(), "B".to_string()],
+            samplerate,
+            gap: (options.gap.max(0.0) * samplerate).round() as usize,
+            responses: vec![None; trials.len()],
+            stimuli,
+            renderings,
+            gains_db,
+            trials,
+        })
+    }
+
+    /// Name the datasets in the results (`A` and `B` by default)
+    pub fn with_labels(mut self, a: impl Into<String>, b: impl Into<String>) -> Self {
+        self.labels = [a.into(), b.into()];
+        self
+    }
+
+    /// Name of a dataset in the results
+    pub fn label(&self, condition: Condition) -> &str {
+        &self.labels[condition.index()]
+    }
+
+    /// Sampling rate of the datasets and renderings in Hz
+    pub fn samplerate(&self) -> f64 {
+        self.samplerate
+    }
+
+    pub fn stimuli(&self) -> &[Stimulus] {
+        &self.stimuli
+    }
+
+    /// Trials in presentation order
+    pub fn trials(&self) -> &[Trial] {
+        &self.trials
+    }
+
+    /// Gain in dB applied to the rendering of a stimulus to reach the target loudness
+    pub fn gain_db(&self, stimulus: usize, condition: Condition) -> f32 {
+        self.gains_db[stimulus][condition.index()]
+    }
+
+    /// Level-aligned left and right ear signals of a stimulus rendered through a dataset
+    pub fn rendering(&self, stimulus: usize, condition: Condition) -> &[Vec<f32>; 2] {
+        &self.renderings[stimulus][condition.index()]
+    }
+
+    /// Ear signals of the first and second interval of a trial
+    pub fn presentation(&self, trial: usize) -> [&[Vec<f32>; 2]; 2] {
+        let trial = self.trials[trial];
+        [
+            self.rendering(trial.stimulus, trial.first),
+            self.rendering(trial.stimulus, trial.second()),
+        ]
+    }
+
+    /// Write both intervals of a trial, separated by the gap, as a stereo WAV file
+    pub fn write_trial_wav(&self, trial: usize, path: impl AsRef<Path>) -> Result<()> {
+        self.check_trial(trial)?;
+        let [first, second] = self.presentation(trial);
+        let channels = (0..2)
+            .map(|ear| {
+                let mut channel = first[ear].clone();
+                channel.resize(channel.len() + self.gap, 0.0);
+                channel.extend_from_slice(&second[ear]);
+                channel
+            })
+            .collect();
+        wav::write(
+            path.as_ref(),
+            &Wav {
+                samplerate: self.samplerate.round() as u32,
+                channels,
+            },
+        )
+    }
+
+    /// First trial without a response, if any
+    pub fn next_trial(&self) -> Option<usize> {
+        self.responses.iter().position(Option::is_none)
+    }
+
+    /// Record the answer to a trial, replacing an earlier one
+    pub fn respond(&mut self, trial: usize, response: Response) -> Result<()> {
+        self.check_trial(trial)?;
+        self.responses[trial] = Some(response);
+        Ok(())
+    }
+
+    /// Answers in presentation order
+    pub fn responses(&self) -> &[Option<Response>] {
+        &self.responses
+    }
+
+    /// Dataset preferred in a trial, if answered with a preference
+    pub fn preferred(&self, trial: usize) -> Option<Condition> {
+        let first = self.trials.get(trial)?.first;
+        match self.responses[trial]? {
+            Response::First => Some(first),
+            Response::Second => Some(first.other()),
+            Response::NoPreference => None,
+        }
+    }
+
+    /// Whether every trial has been answered
+    pub fn is_complete(&self) -> bool {
+        self.next_trial().is_none()
+    }
+
+    /// Write the trials and answers as CSV
+    ///
+    /// One row per trial in presentation order with the columns `trial`, `stimulus`,
+    /// `repetition`, `azimuth`, `elevation`, `first`, `second`, `gain_first_db`,
+    /// `gain_second_db`, `response` (`first`, `second`, `none` or empty if unanswered) and
+    /// `preferred` (label of the preferred dataset).
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        let io = |e: std::io::Error| Error::new(format!("Failed to write results: {}", e));
+        writeln!(
+            writer,
+            "trial,stimulus,repetition,azimuth,elevation,first,second,gain_first_db,\
+             gain_second_db,response,preferred"
+        )
+        .map_err(io)?;
+        for (index, trial) in self.trials.iter().enumerate() {
+            let stimulus = &self.stimuli[trial.stimulus];
+            let response = match self.responses[index] {
+                Some(Response::First) => "first",
+                Some(Response::Second) => "second",
+                Some(Response::NoPreference) => "none",
+                None => "",
+            };
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{:.2},{:.2},{},{}",
+                index + 1,
+                field(&stimulus.name),
+                trial.repetition + 1,
+                stimulus.direction.0,
+                stimulus.direction.1,
+                field(self.label(trial.first)),
+                field(self.label(trial.second())),
+                self.gain_db(trial.stimulus, trial.first),
+                self.gain_db(trial.stimulus, trial.second()),
+                response,
+                self.preferred(index)
+                    .map_or(Cow::Borrowed(""), |c| field(self.label(c))),
+            )
+            .map_err(io)?;
+        }
+        writer.flush().map_err(io)
+    }
+
+    /// Write the trials and answers to a CSV file, see [`write_csv`](AbTest::write_csv)
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| Error::new(format!("Failed to create {}: {}", path.display(), e)))?;
+        self.write_csv(BufWriter::new(file))
+    }
+
+    fn check_trial(&self, trial: usize) -> Result<()> {
+        if trial >= self.trials.len() {
+            return Err(Error::new(format!(
+                "Trial {} is out of range (0..{})",
+                trial,
+                self.trials.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Sampling rate of an impulse response dataset
+fn samplerate(dataset: &Dataset) -> Result<f64> {
+    match dataset.header {
+        ContentHeader::ImpulseResponse { samplerate } => Ok(samplerate),
+        _ => Err(Error::new(format!(
+            "Listening tests require impulse responses, not {}",
+            dataset.content_type()
+        ))),
+    }
+}
+
+/// A CSV field, quoted if necessary
+fn field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// SplitMix64 pseudo-random generator
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::EquiangularGrid;
+    use crate::metadata::{self, MetadataValue};
+    use crate::test_util::temp_path;
+
+    fn dataset(gain: f32) -> Dataset {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |_, _, channel| {
+                let mut ir = vec![0.0; 8];
+                ir[channel] = gain;
+                ir
+            },
+        );
+        for (channel, label) in ["Left", "Right"].into_iter().enumerate() {
+            dataset.metadata.insert(
+                metadata::channel_label_key(channel),
+                MetadataValue::String(label.to_string()),
+            );
+        }
+        dataset
+    }
+
+    fn noise(length: usize, seed: u64) -> Vec<f32> {
+        let mut random = SplitMix(seed);
+        (0..length)
+            .map(|_| (random.next() >> 40) as f32 / (1u64 << 23) as f32 - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_ab_test() {
+        let stimuli = vec![
+            Stimulus::new("noise", noise(48000, 1), 30.0, 0.0),
+            Stimulus::new("noise, shifted", noise(48000, 2), -90.0, 30.0),
+        ];
+        let options = ListeningOptions {
+            repetitions: 3,
+            seed: 5,
+            ..Default::default()
+        };
+        let quiet = dataset(0.1);
+        let loud = dataset(0.8);
+        let mut test = AbTest::new(&quiet, &loud, stimuli.clone(), &options)
+            .unwrap()
+            .with_labels("quiet", "loud");
+        assert_eq!(test.trials().len(), 6);
+
+        // Both renderings reach the target loudness
+        for stimulus in 0..2 {
+            for condition in [Condition::A, Condition::B] {
+                let loudness = analysis::lufs([test.rendering(stimulus, condition)], 48000.0);
+                assert!((loudness.unwrap() + 23.0).abs() < 0.01);
+            }
+            let difference =
+                test.gain_db(stimulus, Condition::A) - test.gain_db(stimulus, Condition::B);
+            assert!((difference - 20.0 * 8f32.log10()).abs() < 0.01);
+        }
+
+        // The same seed reproduces the order, every combination is presented
+        let again = AbTest::new(&quiet, &loud, stimuli, &options).unwrap();
+        assert_eq!(again.trials(), test.trials());
+        for stimulus in 0..2 {
+            for repetition in 0..3 {
+                assert_eq!(
+                    test.trials()
+                        .iter()
+                        .filter(|t| t.stimulus == stimulus && t.repetition == repetition)
+                        .count(),
+                    1
+                );
+            }
+        }
+
+        assert_eq!(test.next_trial(), Some(0));
+        test.respond(0, Response::First).unwrap();
+        test.respond(2, Response::NoPreference).unwrap();
+        assert!(test.respond(6, Response::First).is_err());
+        assert_eq!(test.next_trial(), Some(1));
+        assert_eq!(test.preferred(0), Some(test.trials()[0].first));
+        assert_eq!(test.preferred(1), None);
+        assert!(!test.is_complete());
+
+        let mut csv = Vec::new();
+        test.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("trial,stimulus,"));
+        let first = test.label(test.trials()[0].first);
+        assert!(
+            lines[1].ends_with(&format!(",first,{}", first)),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[3].ends_with(",none,"), "{}", lines[3]);
+        assert!(lines[2].ends_with(",,"), "{}", lines[2]);
+        assert!(csv.contains("\"noise, shifted\""));
+    }
+
+    #[test]
+    fn test_trial_wav() {
+        let path = temp_path("trial.wav");
+        let test = AbTest::new(
+            &dataset(1.0),
+            &dataset(0.5),
+            vec![Stimulus::new("click", vec![1.0; 100], 0.0, 0.0)],
+            &ListeningOptions::default(),
+        )
+        .unwrap();
+        test.write_trial_wav(0, &path).unwrap();
+        let written = wav::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.samplerate, 48000);
+        assert_eq!(written.channels[0].len(), 2 * 107 + 24000);
+        assert!(test.write_trial_wav(1, &path).is_err());
+
+        let mut magnitude = dataset(1.0);
+        magnitude.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![1000.0],
+        };
+        assert!(AbTest::new(&dataset(1.0), &magnitude, Vec::new(), &Default::default()).is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! Constant-time record lookups on equiangular grids
+//!
+//! Renderers look up the record towards a source for every block, often for many sources at
+//! once. On the regular grids DAFF files are sampled on, the record closest to a direction
+//! follows from the angles by index arithmetic alone. A [`GridLookup`] precomputes the few
+//! values needed for that, so a lookup neither allocates nor calls into the C++ library.
+//!
+//! [`Reader`](crate::Reader) builds a lookup when a file is opened and answers the
+//! `nearest_neighbour` queries of all content types with it:
+//!
+//! ```no_run
+//! # fn main() -> opendaff::Result<()> {
+//! let reader = opendaff::Reader::open("hrir.daff")?;
+//! let lookup = reader.grid_lookup().expect("equiangular grid");
+//! let record = lookup.nearest_neighbour(30.0, 0.0);
+//! let left = reader.content_ir()?.filter_coeffs(record as i32, 0)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Differences from the C++ reader
+//!
+//! Lookups return the same record as `DAFFReader::getNearestNeighbour` of the C++ library,
+//! except in two cases where the C++ reader picks a record that is not the nearest one:
+//!
+//! - On grids covering the full circle, directions less than half an alpha step below the
+//!   first alpha point (e.g. azimuths in (355°, 360°) on a 10° grid) get the index one past
+//!   the last alpha point from the C++ reader, which is the first record of the *next ring*
+//!   (or past the end of the records on the last ring). The lookup wraps to the first alpha
+//!   point of the ring the direction is on.
+//! - On partial grids wrapping around 0° (`alpha_start > alpha_end`, e.g. 300° to 60°), the
+//!   C++ reader treats no direction as within the alpha range and snaps every query to the
+//!   start or end column. The lookup picks the closest alpha point within the range.
+
+use crate::grid::{DataViewRotation, EquiangularGrid};
+use crate::{Error, Orientation, Result};
+
+/// Nearest neighbour and cell lookups on an equiangular grid
+///
+/// Directions are given in the object view (azimuth and elevation in degrees) and rotated by
+/// the orientation of the dataset. Outside of partial grids, lookups snap to the closest
+/// border like the DAFF reader does. See the [module documentation](self) for the two cases
+/// in which the C++ reader returns a different record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLookup {
+    /// `None` for the identity orientation, which needs no rotation
+    rotation: Option<DataViewRotation>,
+    alpha_start: f32,
+    alpha_span: f32,
+    alpha_points: usize,
+    full_circle: bool,
+    /// Inverse alpha resolution, 0 for a single alpha point
+    alpha_scale: f32,
+    beta_start: f32,
+    beta_points: usize,
+    /// Inverse beta resolution, 0 for a single ring
+    beta_scale: f32,
+    south_pole: bool,
+    north_pole: bool,
+}
+
+impl GridLookup {
+    /// Lookup for a grid with the given orientation
+    pub fn new(grid: &EquiangularGrid, orientation: Orientation) -> Result<Self> {
+        if grid.alpha_points == 0 || grid.beta_points == 0 {
+            return Err(Error::new("Grid lookups require a non-empty grid"));
+        }
+        let inverse = |resolution: f32| {
+            if resolution > 0.0 {
+                1.0 / resolution
+            } else {
+                0.0
+            }
+        };
+        Ok(Self {
+            rotation: (orientation != Orientation::default())
+                .then(|| DataViewRotation::new(&orientation)),
+            alpha_start: grid.alpha_start,
+            alpha_span: grid.alpha_span(),
+            alpha_points: grid.alpha_points,
+            full_circle: grid.alpha_span() == 360.0,
+            alpha_scale: inverse(grid.alpha_resolution()),
+            beta_start: grid.beta_start,
+            beta_points: grid.beta_points,
+            beta_scale: inverse(grid.beta_resolution()),
+            south_pole: grid.has_south_pole(),
+            north_pole: grid.has_north_pole(),
+        })
+    }
+
+    /// Index of the record closest to an object view direction (degrees)
+    ///
+    /// Like the DAFF reader, picks the closest ring first and then the closest point on it.
+    ///
+    /// Non-finite angles still yield a valid record index.
+    #[inline]
+    pub fn nearest_neighbour(&self, azimuth: f32, elevation: f32) -> usize {
+        let (alpha, beta) = self.data_view(azimuth, elevation);
+        let last_ring = (self.beta_points - 1) as f32;
+        let ring = ((beta - self.beta_start) * self.beta_scale)
+            .round()
+            .clamp(0.0, last_ring) as usize;
+
+        let offset = self.alpha_offset(alpha);
+        let point = if self.full_circle {
+            (offset * self.alpha_scale).round() as usize % self.alpha_points
+        } else {
+            ((offset * self.alpha_scale).round() as usize).min(self.alpha_points - 1)
+        };
+        self.record_index(ring, point)
+    }
+
+    /// Indices of the four records at the corners of the grid cell containing an object view
+    /// direction (degrees)
+    ///
+    /// The corners are ordered like the quads of the DAFF reader: lower alpha and lower beta
+    /// first, then upper beta, upper alpha and upper beta, and upper alpha and lower beta. At
+    /// the borders of partial grids and at the poles, corners coincide.
+    #[inline]
+    pub fn cell(&self, azimuth: f32, elevation: f32) -> [usize; 4] {
+        self.bilinear(azimuth, elevation).0
+    }
+
+    /// The [cell](GridLookup::cell) containing an object view direction (degrees) with the
+    /// weights of its corners for bilinear interpolation in alpha and beta
+    ///
+    /// The weights sum up to 1.
+    #[inline]
+    pub fn bilinear(&self, azimuth: f32, elevation: f32) -> ([usize; 4], [f32; 4]) {
+        let (alpha, beta) = self.data_view(azimuth, elevation);
+        let last_ring = self.beta_points - 1;
+        let b = ((beta - self.beta_start) * self.beta_scale).clamp(0.0, last_ring as f32);
+        let b0 = b as usize;
+        let b1 = (b0 + 1).min(last_ring);
+        let wb = b - b0 as f32;
+
+        let a = self.alpha_offset(alpha) * self.alpha_scale;
+        let (a0, a1, wa) = if self.full_circle {
+            let a0 = a as usize % self.alpha_points;
+            (a0, (a0 + 1) % self.alpha_points, a.fract())
+        } else {
+            let a = a.min((self.alpha_points - 1) as f32);
+            let a0 = a as usize;
+            (a0, (a0 + 1).min(self.alpha_points - 1), a - a0 as f32)
+        };
+        // Non-finite angles fall back to the first corner
+        let (wa, wb) = if wa.is_finite() && wb.is_finite() {
+            (wa, wb)
+        } else {
+            (0.0, 0.0)
+        };
+        (
+            [
+                self.record_index(b0, a0),
+                self.record_index(b1, a0),
+                self.record_index(b1, a1),
+                self.record_index(b0, a1),
+            ],
+            [
+                (1.0 - wa) * (1.0 - wb),
+                (1.0 - wa) * wb,
+                wa * wb,
+                wa * (1.0 - wb),
+            ],
+        )
+    }
+
+    /// Data view coordinates of an object view direction, beta within [0°, 180°]
+    #[inline]
+    fn data_view(&self, azimuth: f32, elevation: f32) -> (f32, f32) {
+        match &self.rotation {
+            Some(rotation) => rotation.apply(azimuth, elevation),
+            None => {
+                // Elevations beyond the poles continue on the opposite side
+                let beta = (elevation + 90.0).rem_euclid(360.0);
+                if beta > 180.0 {
+                    (azimuth + 180.0, 360.0 - beta)
+                } else {
+                    (azimuth, beta)
+                }
+            }
+        }
+    }
+
+    /// Alpha distance from the first grid point in [0°, 360°); beyond the covered range of
+    /// partial grids, the offset of the closer end
+    #[inline]
+    fn alpha_offset(&self, alpha: f32) -> f32 {
+        let offset = (alpha - self.alpha_start).rem_euclid(360.0);
+        if self.full_circle || offset <= self.alpha_span {
+            offset
+        } else if offset - self.alpha_span < 360.0 - offset {
+            self.alpha_span
+        } else {
+            0.0
+        }
+    }
+
+    /// Storage index of alpha point `point` on ring `ring`; poles hold a single record
+    #[inline]
+    fn record_index(&self, ring: usize, point: usize) -> usize {
+        if !self.south_pole {
+            let pole = self.north_pole && ring + 1 == self.beta_points;
+            ring * self.alpha_points + if pole { 0 } else { point }
+        } else if ring == 0 {
+            0
+        } else {
+            let pole = self.north_pole && ring + 1 == self.beta_points;
+            1 + (ring - 1) * self.alpha_points + if pole { 0 } else { point }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distance between two data view directions, first in beta, then in alpha
+    fn distance((a1, b1): (f32, f32), (a2, b2): (f32, f32)) -> (f32, f32) {
+        let alpha = (a1 - a2).rem_euclid(360.0);
+        let alpha = if b1 == 0.0 || b1 == 180.0 {
+            0.0
+        } else {
+            alpha.min(360.0 - alpha)
+        };
+        ((b1 - b2).abs(), alpha)
+    }
+
+    #[test]
+    fn test_nearest_neighbour() {
+        let grids = [
+            EquiangularGrid::with_resolution(10.0, 15.0).unwrap(),
+            EquiangularGrid {
+                alpha_points: 7,
+                alpha_start: 300.0,
+                alpha_end: 60.0,
+                beta_points: 5,
+                beta_start: 40.0,
+                beta_end: 120.0,
+            },
+        ];
+        for grid in grids {
+            let lookup = GridLookup::new(&grid, Orientation::default()).unwrap();
+            let directions: Vec<_> = grid.directions().collect();
+            // Off-grid directions avoid ties between neighbours
+            for azimuth in (-180..180).step_by(7) {
+                for elevation in (-89..90).step_by(6) {
+                    let (azimuth, elevation) = (azimuth as f32 + 0.3, elevation as f32 + 0.2);
+                    let alpha = azimuth.rem_euclid(360.0);
+                    let beta = elevation + 90.0;
+                    let nearest = lookup.nearest_neighbour(azimuth, elevation);
+                    let offset = (alpha - grid.alpha_start).rem_euclid(360.0);
+                    let inside = offset <= grid.alpha_span()
+                        && beta >= grid.beta_start
+                        && beta <= grid.beta_end;
+                    if inside {
+                        let best = directions
+                            .iter()
+                            .map(|&d| distance(d, (alpha, beta)))
+                            .min_by(|a, b| a.partial_cmp(b).unwrap())
+                            .unwrap();
+                        assert_eq!(
+                            distance(directions[nearest], (alpha, beta)),
+                            best,
+                            "{} at ({}, {})",
+                            nearest,
+                            azimuth,
+                            elevation
+                        );
+                    } else {
+                        assert!(nearest < grid.num_records());
+                    }
+                }
+            }
+        }
+
+        let lookup = GridLookup::new(&grids[0], Orientation::default()).unwrap();
+        assert_eq!(lookup.nearest_neighbour(0.0, -90.0), 0);
+        assert_eq!(
+            lookup.nearest_neighbour(123.0, 90.0),
+            grids[0].num_records() - 1
+        );
+        assert_eq!(
+            lookup.nearest_neighbour(-1.0, 0.0),
+            lookup.nearest_neighbour(0.0, 0.0)
+        );
+        assert_eq!(
+            lookup.nearest_neighbour(f32::NAN, 0.0),
+            lookup.nearest_neighbour(0.0, 0.0)
+        );
+    }
+
+    /// Great circle distance between two data view directions in degrees
+    fn angle((a1, b1): (f32, f32), (a2, b2): (f32, f32)) -> f32 {
+        let vector = |alpha: f32, beta: f32| {
+            let (alpha, beta) = (alpha.to_radians(), beta.to_radians());
+            [
+                beta.sin() * alpha.cos(),
+                beta.sin() * alpha.sin(),
+                -beta.cos(),
+            ]
+        };
+        let (u, v) = (vector(a1, b1), vector(a2, b2));
+        let dot: f32 = u.iter().zip(&v).map(|(x, y)| x * y).sum();
+        dot.clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "compares with the C++ library")]
+    fn test_matches_cpp_reader() {
+        use crate::test_util::temp_path;
+        use crate::{ContentHeader, Dataset, Reader};
+
+        let grids = [
+            EquiangularGrid::with_resolution(10.0, 15.0).unwrap(),
+            EquiangularGrid {
+                alpha_points: 7,
+                alpha_start: 300.0,
+                alpha_end: 60.0,
+                beta_points: 5,
+                beta_start: 40.0,
+                beta_end: 120.0,
+            },
+            EquiangularGrid {
+                alpha_points: 10,
+                alpha_start: 0.0,
+                alpha_end: 90.0,
+                beta_points: 7,
+                beta_start: 30.0,
+                beta_end: 150.0,
+            },
+        ];
+        for grid in grids {
+            let dataset = Dataset::from_fn(
+                ContentHeader::ImpulseResponse {
+                    samplerate: 44100.0,
+                },
+                grid,
+                1,
+                |_, _, _| vec![1.0],
+            );
+            let path = temp_path("lookup.daff");
+            crate::writer::write_dataset(&path, &dataset).unwrap();
+            let reader = Reader::open(&path).unwrap();
+            let content = reader.content_ir().unwrap();
+            let lookup = GridLookup::new(&grid, Orientation::default()).unwrap();
+            let half_step = grid.alpha_resolution() / 2.0;
+            let wraps = grid.alpha_end < grid.alpha_start;
+
+            let mut differences = 0;
+            for azimuth in (-180..180).step_by(7) {
+                for elevation in (-89..90).step_by(6) {
+                    let (azimuth, elevation) = (azimuth as f32 + 0.3, elevation as f32 + 0.2);
+                    let ours = lookup.nearest_neighbour(azimuth, elevation);
+                    let cpp = unsafe {
+                        crate::ffi::RustDAFF_ContentIR_GetNearestNeighbour(
+                            content.handle,
+                            azimuth as f64,
+                            elevation as f64,
+                        )
+                    };
+                    if ours as i32 == cpp {
+                        continue;
+                    }
+                    differences += 1;
+
+                    // Only the documented cases differ, and there the lookup is closer
+                    let direction = (azimuth.rem_euclid(360.0), elevation + 90.0);
+                    let offset = (direction.0 - grid.alpha_start).rem_euclid(360.0);
+                    let documented = if grid.alpha_span() == 360.0 {
+                        offset > 360.0 - half_step
+                    } else {
+                        wraps && offset <= grid.alpha_span()
+                    };
+                    assert!(documented, "({}, {})", azimuth, elevation);
+                    let ours = grid.record_coords(ours).unwrap();
+                    if let Some(cpp) = usize::try_from(cpp)
+                        .ok()
+                        .and_then(|cpp| grid.record_coords(cpp))
+                    {
+                        assert!(
+                            angle(ours, direction) < angle(cpp, direction),
+                            "({}, {})",
+                            azimuth,
+                            elevation
+                        );
+                    }
+                }
+            }
+            // The full circle differs on its seam, the wrapping grid wherever the C++ reader
+            // snaps a direction within its alpha range to a border
+            let expected = match (grid.alpha_span() == 360.0, wraps) {
+                (true, _) => 27,
+                (false, true) => 420,
+                (false, false) => 0,
+            };
+            assert_eq!(differences, expected, "{:?}", grid);
+        }
+    }
+
+    #[test]
+    fn test_cell() {
+        let grid = EquiangularGrid::with_resolution(10.0, 10.0).unwrap();
+        let lookup = GridLookup::new(&grid, Orientation::default()).unwrap();
+        let cell = lookup.cell(15.0, -5.0);
+        let coords = cell.map(|index| grid.record_coords(index).unwrap());
+        assert_eq!(
+            coords,
+            [(10.0, 80.0), (10.0, 90.0), (20.0, 90.0), (20.0, 80.0)]
+        );
+
+        // Wrapping around the full circle and collapsing at the north pole
+        let coords = lookup
+            .cell(-5.0, 85.0)
+            .map(|index| grid.record_coords(index).unwrap());
+        assert_eq!(
+            coords,
+            [(350.0, 170.0), (0.0, 180.0), (0.0, 180.0), (0.0, 170.0)]
+        );
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let grid = EquiangularGrid::with_resolution(10.0, 10.0).unwrap();
+        let lookup = GridLookup::new(&grid, Orientation::default()).unwrap();
+        let (cell, weights) = lookup.bilinear(12.5, -5.0);
+        assert_eq!(cell, lookup.cell(12.5, -5.0));
+        for (weight, expected) in weights.iter().zip([0.375, 0.375, 0.125, 0.125]) {
+            assert!((weight - expected).abs() < 1e-6, "{:?}", weights);
+        }
+
+        // On a grid point, that point takes all the weight
+        let (cell, weights) = lookup.bilinear(30.0, 20.0);
+        assert_eq!(cell[0], lookup.nearest_neighbour(30.0, 20.0));
+        assert_eq!(weights, [1.0, 0.0, 0.0, 0.0]);
+        let (_, weights) = lookup.bilinear(f32::NAN, 0.0);
+        assert_eq!(weights.iter().sum::<f32>(), 1.0);
+    }
+}
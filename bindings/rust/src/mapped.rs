@@ -0,0 +1,267 @@
+//! Memory-mapped DAFF files
+//!
+//! The C++ reader loads the whole data block of a file into memory when it is opened. For
+//! HRIR databases of several hundred megabytes that is a lot of resident memory, most of which
+//! a renderer never touches. A [`MappedFile`] instead maps the file and parses only the
+//! headers and record descriptors. Record payloads are decoded straight from the mapping when
+//! they are requested, so pages are loaded on demand and shared through the OS page cache
+//! between processes using the same file.
+//!
+//! ```no_run
+//! # fn main() -> opendaff::Result<()> {
+//! let file = opendaff::mapped::MappedFile::open("hrir.daff")?;
+//! let mut samples = vec![0.0; file.elements_per_record()];
+//! file.read_channel(0, 0, &mut samples)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs::File;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+
+use crate::parser::ParsedFile;
+use crate::{Error, Result};
+
+/// A DAFF file mapped into memory
+///
+/// Provides the headers and record data of a file without the C++ library; the accessors
+/// of [`ParsedFile`] are available through `Deref`. The file must not be truncated or
+/// modified in place while it is mapped; replacing it by renaming a new file over it, as the
+/// [`Writer`](crate::Writer) does, is fine.
+#[derive(Debug)]
+pub struct MappedFile {
+    path: PathBuf,
+    file: ParsedFile<Mmap>,
+}
+
+impl MappedFile {
+    /// Map a DAFF file and parse its headers
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let open_error = |e| Error::new(format!("Failed to open '{}': {}", path.display(), e));
+        let file = File::open(&path).map_err(open_error)?;
+        // Safety: the mapping is only read, and the documentation of this type requires the
+        // file to stay unchanged while it is mapped
+        let map = unsafe { Mmap::map(&file) }.map_err(open_error)?;
+        let file = ParsedFile::new(map)
+            .map_err(|e| Error::new(format!("Invalid DAFF file '{}': {}", path.display(), e)))?;
+        Ok(Self { path, file })
+    }
+
+    /// Path of the mapped file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Ask the OS to load the data of records that are about to be needed, e.g. for
+    /// directions predicted from head motion
+    ///
+    /// Issues `madvise(MADV_WILLNEED)` for the payloads of all channels, so the pages are read
+    /// in the background and later accesses do not wait for the disk. On platforms without
+    /// `madvise`, touches the pages instead. Payloads that follow each other in the file are
+    /// advised as one range; nothing is allocated.
+    pub fn prefetch(&self, records: &[usize]) -> Result<()> {
+        self.payload_ranges(records, |range| {
+            self.advise_will_need(range.start, range.len())
+        })
+    }
+
+    /// Call `visit` with the bytes of the payloads of all channels of records, merging payloads
+    /// that follow each other in the file into one range
+    fn payload_ranges(
+        &self,
+        records: &[usize],
+        mut visit: impl FnMut(Range<usize>) -> Result<()>,
+    ) -> Result<()> {
+        let bytes = self.file.bytes();
+        let mut pending = 0..0;
+        for &record in records {
+            for channel in 0..self.num_channels() {
+                let (_, payload) = self.file.layout().payload(bytes, record, channel)?;
+                let start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+                if start != pending.end {
+                    if !pending.is_empty() {
+                        visit(pending.clone())?;
+                    }
+                    pending.start = start;
+                }
+                pending.end = start + payload.len();
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        visit(pending)
+    }
+
+    #[cfg(unix)]
+    fn advise_will_need(&self, offset: usize, len: usize) -> Result<()> {
+        self.file
+            .get_ref()
+            .advise_range(memmap2::Advice::WillNeed, offset, len)
+            .map_err(|e| {
+                Error::new(format!(
+                    "Failed to prefetch '{}': {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+    }
+
+    #[cfg(not(unix))]
+    fn advise_will_need(&self, offset: usize, len: usize) -> Result<()> {
+        const PAGE_SIZE: usize = 4096;
+        for byte in self.file.bytes()[offset..offset + len]
+            .iter()
+            .step_by(PAGE_SIZE)
+        {
+            std::hint::black_box(*byte);
+        }
+        Ok(())
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = ParsedFile<Mmap>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::parser::Layout;
+    use crate::test_util::write_file;
+    use crate::{ContentHeader, Dataset, Quantization, Reader};
+
+    fn read(path: &Path) -> Dataset {
+        let reader = Reader::open(path).unwrap();
+        Dataset::from_reader(&reader).unwrap()
+    }
+
+    fn assert_close(mapped: &Dataset, read: &Dataset) {
+        assert_eq!(mapped.header, read.header);
+        assert_eq!(mapped.grid, read.grid);
+        assert_eq!(mapped.metadata, read.metadata);
+        assert_eq!(mapped.records.len(), read.records.len());
+        for (a, b) in mapped.records.iter().zip(&read.records) {
+            assert_eq!((a.alpha, a.beta), (b.alpha, b.beta));
+            for (a, b) in a.channels.iter().flatten().zip(b.channels.iter().flatten()) {
+                assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_matches_reader() {
+        let ir = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        let mps = ContentHeader::MagnitudePhaseSpectrum {
+            frequencies: vec![100.0, 1000.0, 10000.0],
+        };
+        for (name, header, quantization) in [
+            ("mapped-int16.daff", ir.clone(), Quantization::Int16),
+            ("mapped-int24.daff", ir, Quantization::Int24),
+            ("mapped-mps.daff", mps, Quantization::Float32),
+        ] {
+            let path = write_file(name, header, quantization);
+            let file = MappedFile::open(&path).unwrap();
+            assert_eq!(file.quantization(), quantization);
+            assert_close(&file.to_dataset().unwrap(), &read(&path));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_leading_zeros() {
+        let header = ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        };
+        let path = write_file("mapped-sparse.daff", header, Quantization::Float32);
+        // Store only three samples of record 1, channel 0, starting at sample 2
+        let mut bytes = std::fs::read(&path).unwrap();
+        let layout = Layout::parse(&bytes).unwrap();
+        let desc = layout.descriptors.start + 2 * layout.desc_size + 12;
+        bytes[desc..desc + 4].copy_from_slice(&2i32.to_le_bytes());
+        bytes[desc + 4..desc + 8].copy_from_slice(&3i32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        let file = MappedFile::open(&path).unwrap();
+        let channel = file.channel(1, 0).unwrap();
+        assert!(matches!(channel, Cow::Owned(_)));
+        assert_eq!(channel[..2], [0.0, 0.0]);
+        assert_close(&file.to_dataset().unwrap(), &read(&path));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_copy() {
+        let header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![100.0, 200.0, 400.0, 800.0, 1600.0],
+        };
+        let path = write_file("mapped-ms.daff", header, Quantization::Float32);
+        let file = MappedFile::open(&path).unwrap();
+        let channel = file.channel(3, 1).unwrap();
+        if cfg!(target_endian = "little") {
+            assert!(matches!(channel, Cow::Borrowed(_)));
+        }
+        let mut data = vec![0.0; file.elements_per_record()];
+        file.read_channel(3, 1, &mut data).unwrap();
+        assert_eq!(*channel, data[..]);
+        assert!(file.read_channel(3, 1, &mut data[1..]).is_err());
+        assert!(file.channel(file.num_records(), 0).is_err());
+        assert!(file.channel(0, 2).is_err());
+
+        file.prefetch(&[0, 3, file.num_records() - 1]).unwrap();
+        assert!(file.prefetch(&[file.num_records()]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_merges_adjacent_payloads() {
+        let header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![100.0, 200.0, 400.0, 800.0, 1600.0],
+        };
+        let path = write_file("mapped-prefetch.daff", header, Quantization::Float32);
+        let file = MappedFile::open(&path).unwrap();
+        let record = |index| {
+            let bytes = file.bytes();
+            let payload = |channel| {
+                let (_, payload) = file.layout().payload(bytes, index, channel).unwrap();
+                let start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+                start..start + payload.len()
+            };
+            payload(0).start..payload(1).end
+        };
+        let ranges = |records: &[usize]| {
+            let mut ranges = Vec::new();
+            file.payload_ranges(records, |range| {
+                ranges.push(range);
+                Ok(())
+            })
+            .unwrap();
+            ranges
+        };
+
+        // The writer stores the channels of a record and consecutive records back to back
+        assert_eq!(record(2).end, record(3).start);
+        assert_eq!(ranges(&[2, 3, 4]), vec![record(2).start..record(4).end]);
+        assert_eq!(ranges(&[2, 4]), [record(2), record(4)]);
+        assert_eq!(ranges(&[4, 3]), [record(4), record(3)]);
+        assert_eq!(ranges(&[3, 3]), [record(3), record(3)]);
+        assert!(ranges(&[]).is_empty());
+        file.prefetch(&[2, 3, 4]).unwrap();
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
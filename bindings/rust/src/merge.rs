@@ -0,0 +1,1252 @@
+//! Combining several datasets into one
+//!
+//! Measurements are often split over several files: one per part of the sphere (e.g. upper
+//! and lower hemisphere measured in separate sessions) or one per channel (e.g. one file per
+//! ear). [`merge`] and [`merge_files`] combine them into a single dataset after checking that
+//! the parts fit together. For read-only use, `VirtualReader` (with the `mmap` feature)
+//! presents such files as one dataset without copying their data.
+//!
+//! Datasets measured on different grids, e.g. a dense grid in front of the listener and a
+//! coarse one around it, are combined with [`resample_and_merge`], which interpolates both
+//! onto a common grid and crossfades between them.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+#[cfg(feature = "mmap")]
+use std::ops::Range;
+use std::path::Path;
+
+use crate::analysis::{self, Interp, SpectrumDomain};
+use crate::decibel;
+use crate::grid::{self, EquiangularGrid, Grid};
+#[cfg(feature = "mmap")]
+use crate::mapped::MappedFile;
+use crate::metadata::{self, Metadata};
+#[cfg(feature = "mmap")]
+use crate::ContentHeader;
+use crate::{
+    ContentType, Dataset, Error, MagnitudeUnit, Orientation, Quantization, Reader, Record, Result,
+};
+
+/// Resolution of metadata keys that the parts of a merge set to different values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataConflict {
+    /// Fail the merge
+    #[default]
+    Error,
+    /// Keep the value of the first part that sets the key
+    KeepFirst,
+    /// Keep the value of the last part that sets the key
+    KeepLast,
+}
+
+/// Merge datasets covering different parts of the sphere or different channels
+///
+/// If all parts share the same record directions, their channels are concatenated in the
+/// order of the parts and the channel labels are renumbered accordingly. Otherwise the
+/// records of all parts are combined; records that several parts contain must hold the same
+/// data. The merged records get an equiangular grid if they form one on the common
+/// resolution of the parts, and an [irregular](Grid::Irregular) layout otherwise.
+///
+/// All parts need the same content header, default orientation and number of elements (and
+/// of channels when combining records). Differing quantizations result in 32-bit floats.
+///
+/// ```
+/// use opendaff::grid::{self, Region};
+/// use opendaff::merge::{self, MetadataConflict};
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let sphere = Dataset::from_fn(
+///     ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] },
+///     EquiangularGrid::with_resolution(30.0, 30.0)?,
+///     1,
+///     |alpha, beta, _| vec![1.0 + alpha + beta],
+/// );
+/// let window = |beta_start, beta_end| Region::Window {
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_start,
+///     beta_end,
+/// };
+/// let lower = grid::crop(&sphere, &window(0.0, 90.0))?;
+/// let upper = grid::crop(&sphere, &window(90.0, 180.0))?;
+/// assert_eq!(merge::merge(&[lower, upper], MetadataConflict::Error)?, sphere);
+/// # Ok(())
+/// # }
+/// ```
+pub fn merge(parts: &[Dataset], conflicts: MetadataConflict) -> Result<Dataset> {
+    let Some(first) = parts.first() else {
+        return Err(Error::new("No datasets to merge"));
+    };
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        let mismatch = if part.header != first.header {
+            Some("content header")
+        } else if part.orientation != first.orientation {
+            Some("orientation")
+        } else if part.elements_per_record() != first.elements_per_record() {
+            Some("number of elements")
+        } else {
+            None
+        };
+        if let Some(mismatch) = mismatch {
+            return Err(Error::new(format!(
+                "Dataset {} differs from the first one in its {}",
+                index + 1,
+                mismatch
+            )));
+        }
+    }
+    let quantization = if parts.iter().all(|p| p.quantization == first.quantization) {
+        first.quantization
+    } else {
+        Quantization::Float32
+    };
+
+    let same_directions = parts.iter().all(|p| {
+        p.records.len() == first.records.len()
+            && p.records
+                .iter()
+                .zip(&first.records)
+                .all(|(a, b)| grid::angle_eq(a.alpha, b.alpha) && grid::angle_eq(a.beta, b.beta))
+    });
+    let (grid, records, metadata) = if same_directions && parts.len() > 1 {
+        merge_channels(parts, conflicts)?
+    } else {
+        merge_records(parts, conflicts)?
+    };
+
+    Ok(Dataset {
+        header: first.header.clone(),
+        quantization,
+        grid,
+        orientation: first.orientation,
+        metadata,
+        records,
+    })
+}
+
+/// Load and [`merge`] DAFF files
+pub fn merge_files<P: AsRef<Path>>(paths: &[P], conflicts: MetadataConflict) -> Result<Dataset> {
+    let parts = paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            let reader = Reader::open(path)?;
+            Dataset::from_reader(&reader).map_err(|e| match e {
+                Error::Message(m) => Error::new(format!("{}: {}", path.display(), m)),
+                e => e,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    merge(&parts, conflicts)
+}
+
+/// Interpolation of both datasets onto the target grid of [`resample_and_merge`]
+const REGRID_METHOD: Interp = Interp::InverseDistance { neighbours: 3 };
+
+/// Part of the sphere that [`resample_and_merge`] takes from the first dataset
+///
+/// Angles are in degrees in the object view. Between the inner and the outer boundary the
+/// datasets are crossfaded with a raised cosine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendRegion {
+    /// Cone around a direction: the first dataset up to `inner` degrees from the axis, the
+    /// second one from `outer` degrees on
+    Cone {
+        /// Azimuth of the cone axis
+        azimuth: f32,
+        /// Elevation of the cone axis
+        elevation: f32,
+        /// Angle from the axis up to which only the first dataset is used
+        inner: f32,
+        /// Angle from the axis from which on only the second dataset is used
+        outer: f32,
+    },
+    /// Elevation band: the first dataset at `upper` degrees and above, the second one at
+    /// `lower` degrees and below
+    Elevation {
+        /// Elevation up to which only the second dataset is used
+        lower: f32,
+        /// Elevation from which on only the first dataset is used
+        upper: f32,
+    },
+}
+
+impl BlendRegion {
+    /// Weight of the first dataset at a direction (data view), between 0 and 1
+    pub fn weight(&self, orientation: &Orientation, alpha: f32, beta: f32) -> f32 {
+        let (azimuth, elevation) = grid::to_object_view(orientation, alpha, beta);
+        // Position within the crossfade: 0 at the side of the first dataset, 1 at the other
+        let position = match *self {
+            BlendRegion::Cone {
+                azimuth: axis_azimuth,
+                elevation: axis_elevation,
+                inner,
+                outer,
+            } => {
+                let axis = grid::object_vector(axis_azimuth, axis_elevation);
+                let direction = grid::object_vector(azimuth, elevation);
+                let cos = axis.iter().zip(&direction).map(|(a, b)| a * b).sum::<f64>();
+                let angle = cos.clamp(-1.0, 1.0).acos().to_degrees();
+                crossfade_position(angle, inner as f64, outer as f64)
+            }
+            BlendRegion::Elevation { lower, upper } => {
+                1.0 - crossfade_position(elevation as f64, lower as f64, upper as f64)
+            }
+        };
+        (0.5 * (1.0 + (PI * position).cos())) as f32
+    }
+
+    fn validate(&self) -> Result<()> {
+        let (start, end) = match *self {
+            BlendRegion::Cone {
+                azimuth,
+                elevation,
+                inner,
+                outer,
+            } => {
+                if !(azimuth.is_finite() && elevation.is_finite()) {
+                    return Err(Error::new(format!(
+                        "Invalid cone axis {}°/{}°",
+                        azimuth, elevation
+                    )));
+                }
+                (inner, outer)
+            }
+            BlendRegion::Elevation { lower, upper } => (lower, upper),
+        };
+        if !(start.is_finite() && end.is_finite() && start <= end) {
+            return Err(Error::new(format!(
+                "Invalid crossfade from {}° to {}°",
+                start, end
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Relative position of `x` between `start` and `end`, clamped to 0..=1
+fn crossfade_position(x: f64, start: f64, end: f64) -> f64 {
+    if x <= start {
+        0.0
+    } else if x >= end {
+        1.0
+    } else {
+        (x - start) / (end - start)
+    }
+}
+
+/// Combine two datasets measured on different grids by regridding and crossfading them
+///
+/// Both datasets are interpolated (inverse distance weighting of the three closest records,
+/// complex spectra with unwrapped phases, see [`analysis::interpolate`]) onto the directions
+/// of `target`, an equiangular, Gauss-Legendre or Lebedev grid in the data view of the
+/// datasets. The first dataset is used within the blend region, the second one outside of
+/// it, and the interpolated records are crossfaded in between: impulse responses and complex
+/// spectra by their values, magnitude spectra by their linear magnitudes and phase spectra
+/// along the shorter way around the circle. Every dataset only has to cover the part of the
+/// sphere it contributes to, including the crossfade.
+///
+/// Both datasets need the same content header, orientation and number of channels and
+/// elements. Metadata keys of the first dataset take precedence; differing quantizations
+/// result in 32-bit floats.
+///
+/// ```
+/// use opendaff::grid::Grid;
+/// use opendaff::merge::{self, BlendRegion};
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let header = ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] };
+/// let measured = |resolution, magnitude| -> opendaff::Result<Dataset> {
+///     let grid = EquiangularGrid::with_resolution(resolution, resolution)?;
+///     Ok(Dataset::from_fn(header.clone(), grid, 1, move |_, _, _| vec![magnitude]))
+/// };
+/// let frontal = measured(5.0, 2.0)?;
+/// let surround = measured(30.0, 1.0)?;
+/// let target = Grid::Equiangular(EquiangularGrid::with_resolution(10.0, 10.0)?);
+/// let blend = BlendRegion::Cone { azimuth: 0.0, elevation: 0.0, inner: 30.0, outer: 50.0 };
+/// let merged = merge::resample_and_merge(&frontal, &surround, &target, &blend)?;
+/// assert_eq!(merged.num_records(), 614);
+/// # Ok(())
+/// # }
+/// ```
+pub fn resample_and_merge(
+    a: &Dataset,
+    b: &Dataset,
+    target: &Grid,
+    blend: &BlendRegion,
+) -> Result<Dataset> {
+    let mismatch = if a.header != b.header {
+        Some("content header")
+    } else if a.orientation != b.orientation {
+        Some("orientation")
+    } else if a.num_channels() != b.num_channels() {
+        Some("number of channels")
+    } else if a.elements_per_record() != b.elements_per_record() {
+        Some("number of elements")
+    } else {
+        None
+    };
+    if let Some(mismatch) = mismatch {
+        return Err(Error::new(format!(
+            "The datasets differ in their {}",
+            mismatch
+        )));
+    }
+    blend.validate()?;
+    let directions = target
+        .directions()
+        .ok_or_else(|| Error::new("Cannot merge onto an irregular grid"))?;
+
+    let weights: Vec<f32> = directions
+        .iter()
+        .map(|&(alpha, beta)| blend.weight(&a.orientation, alpha, beta))
+        .collect();
+    // Interpolate each dataset only where it contributes
+    let regrid = |dataset: &Dataset, used: &dyn Fn(f32) -> bool| -> Result<_> {
+        let indices: Vec<usize> = (0..directions.len())
+            .filter(|&i| used(weights[i]))
+            .collect();
+        let points: Vec<(f32, f32)> = indices.iter().map(|&i| directions[i]).collect();
+        let channels = if points.is_empty() {
+            Vec::new()
+        } else {
+            analysis::interpolate_many(
+                dataset,
+                &points,
+                REGRID_METHOD,
+                SpectrumDomain::UnwrappedPhase,
+            )?
+        };
+        Ok(indices
+            .into_iter()
+            .zip(channels)
+            .collect::<HashMap<usize, Vec<Vec<f32>>>>())
+    };
+    let mut first = regrid(a, &|w| w > 0.0)?;
+    let mut second = regrid(b, &|w| w < 1.0)?;
+
+    let content = a.content_type();
+    let decibels = (
+        a.magnitude_unit() == Some(MagnitudeUnit::Decibel),
+        b.magnitude_unit() == Some(MagnitudeUnit::Decibel),
+    );
+    let records = directions
+        .iter()
+        .enumerate()
+        .map(|(index, &(alpha, beta))| {
+            let channels = match (first.remove(&index), second.remove(&index)) {
+                (Some(x), None) => x,
+                (None, Some(y)) => y,
+                (Some(x), Some(y)) => {
+                    let w = weights[index];
+                    x.into_iter()
+                        .zip(y)
+                        .map(|(x, y)| crossfade(content, decibels, x, y, w))
+                        .collect()
+                }
+                (None, None) => unreachable!("every direction is interpolated at least once"),
+            };
+            Record {
+                alpha,
+                beta,
+                channels,
+            }
+        })
+        .collect();
+
+    let mut metadata = a.metadata.clone();
+    combine_metadata(&mut metadata, &b.metadata, MetadataConflict::KeepFirst)?;
+    Ok(Dataset {
+        header: a.header.clone(),
+        quantization: if a.quantization == b.quantization {
+            a.quantization
+        } else {
+            Quantization::Float32
+        },
+        grid: *target,
+        orientation: a.orientation,
+        metadata,
+        records,
+    })
+}
+
+/// Crossfade the data of a channel, `weight` of `x` and the rest of `y`
+fn crossfade(
+    content: ContentType,
+    decibels: (bool, bool),
+    mut x: Vec<f32>,
+    mut y: Vec<f32>,
+    weight: f32,
+) -> Vec<f32> {
+    match content {
+        ContentType::PhaseSpectrum => {
+            for (x, y) in x.iter_mut().zip(&y) {
+                let difference = (y - *x) as f64;
+                let difference = difference - 2.0 * PI * (difference / (2.0 * PI)).round();
+                let phase = *x as f64 + (1.0 - weight as f64) * difference;
+                *x = (phase - 2.0 * PI * (phase / (2.0 * PI)).round()) as f32;
+            }
+            x
+        }
+        ContentType::MagnitudeSpectrum if decibels.0 || decibels.1 => {
+            if decibels.0 {
+                decibel::from_db_slice(&mut x);
+            }
+            if decibels.1 {
+                decibel::from_db_slice(&mut y);
+            }
+            let mut blended = blend(x, &y, weight);
+            if decibels.0 {
+                decibel::to_db_slice(&mut blended);
+            }
+            blended
+        }
+        _ => blend(x, &y, weight),
+    }
+}
+
+fn blend(mut x: Vec<f32>, y: &[f32], weight: f32) -> Vec<f32> {
+    for (x, y) in x.iter_mut().zip(y) {
+        *x = weight * *x + (1.0 - weight) * y;
+    }
+    x
+}
+
+/// How a [`VirtualReader`] combines its files
+#[cfg(feature = "mmap")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concatenation {
+    /// The files share their content header; their channels follow each other
+    Channels,
+    /// The files hold spectra at different frequencies; their bands follow each other
+    Bands,
+}
+
+/// Several DAFF files on the same grid, read as one dataset
+///
+/// Files with the same content header are combined channel by channel, like [`merge`] does,
+/// e.g. one file per ear. Spectra at different frequencies are combined band by band, e.g.
+/// magnitude spectra measured per frequency band. The files are [mapped](MappedFile) and
+/// records are decoded from them on request, so opening a large set costs no more memory
+/// than its headers.
+///
+/// ```no_run
+/// use opendaff::merge::{Concatenation, VirtualReader};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let reader = VirtualReader::concat(&["low.ms.daff", "mid.ms.daff", "high.ms.daff"])?;
+/// assert_eq!(reader.concatenation(), Concatenation::Bands);
+/// let mut magnitudes = vec![0.0; reader.elements_per_record()];
+/// reader.read_channel(0, 0, &mut magnitudes)?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct VirtualReader {
+    parts: Vec<MappedFile>,
+    concatenation: Concatenation,
+    header: ContentHeader,
+    /// Part and channel within it of every channel
+    channels: Vec<(usize, usize)>,
+    /// Elements of a record that every part holds
+    elements: Vec<Range<usize>>,
+}
+
+#[cfg(feature = "mmap")]
+impl VirtualReader {
+    /// Map DAFF files and check that they can be read as one dataset
+    ///
+    /// All files need the same grid and default orientation. Files with the same content
+    /// header are concatenated by channel. Otherwise the files must hold magnitude, phase or
+    /// magnitude-phase spectra with the same number of channels, whose frequencies
+    /// increase from one file to the next.
+    pub fn concat<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let parts = paths
+            .iter()
+            .map(MappedFile::open)
+            .collect::<Result<Vec<_>>>()?;
+        let Some(first) = parts.first() else {
+            return Err(Error::new("No files to concatenate"));
+        };
+        for part in &parts[1..] {
+            let mismatch = if !same_grid(part.grid(), first.grid()) {
+                Some("grid")
+            } else if part.orientation() != first.orientation() {
+                Some("orientation")
+            } else if part.content_type() != first.content_type() {
+                Some("content type")
+            } else {
+                None
+            };
+            if let Some(mismatch) = mismatch {
+                return Err(Error::new(format!(
+                    "'{}' differs from '{}' in its {}",
+                    part.path().display(),
+                    first.path().display(),
+                    mismatch
+                )));
+            }
+        }
+
+        if parts.iter().all(|p| p.header() == first.header()) {
+            let channels = parts
+                .iter()
+                .enumerate()
+                .flat_map(|(index, part)| (0..part.num_channels()).map(move |c| (index, c)))
+                .collect();
+            let elements = vec![0..first.elements_per_record(); parts.len()];
+            return Ok(Self {
+                header: first.header().clone(),
+                parts,
+                concatenation: Concatenation::Channels,
+                channels,
+                elements,
+            });
+        }
+
+        let mut frequencies: Vec<f32> = Vec::new();
+        let mut elements = Vec::with_capacity(parts.len());
+        for part in &parts {
+            let bands = match part.header() {
+                ContentHeader::MagnitudeSpectrum { frequencies }
+                | ContentHeader::PhaseSpectrum { frequencies }
+                | ContentHeader::MagnitudePhaseSpectrum { frequencies } => frequencies,
+                _ => {
+                    return Err(Error::new(format!(
+                        "'{}' differs from '{}' in its content header",
+                        part.path().display(),
+                        first.path().display()
+                    )))
+                }
+            };
+            if part.num_channels() != first.num_channels() {
+                return Err(Error::new(format!(
+                    "'{}' has {} channels, '{}' {}",
+                    part.path().display(),
+                    part.num_channels(),
+                    first.path().display(),
+                    first.num_channels()
+                )));
+            }
+            if let (Some(&last), Some(&next)) = (frequencies.last(), bands.first()) {
+                if next <= last {
+                    return Err(Error::new(format!(
+                        "Frequencies of '{}' do not follow those of the previous file",
+                        part.path().display()
+                    )));
+                }
+            }
+            frequencies.extend_from_slice(bands);
+            let start = elements.last().map_or(0, |r: &Range<usize>| r.end);
+            elements.push(start..start + part.elements_per_record());
+        }
+        let header = match first.header() {
+            ContentHeader::MagnitudeSpectrum { .. } => {
+                ContentHeader::MagnitudeSpectrum { frequencies }
+            }
+            ContentHeader::PhaseSpectrum { .. } => ContentHeader::PhaseSpectrum { frequencies },
+            _ => ContentHeader::MagnitudePhaseSpectrum { frequencies },
+        };
+        Ok(Self {
+            header,
+            channels: (0..first.num_channels()).map(|c| (0, c)).collect(),
+            parts,
+            concatenation: Concatenation::Bands,
+            elements,
+        })
+    }
+
+    /// The mapped files, in the order they were given
+    pub fn parts(&self) -> &[MappedFile] {
+        &self.parts
+    }
+
+    /// How the files are combined
+    pub fn concatenation(&self) -> Concatenation {
+        self.concatenation
+    }
+
+    /// Part and channel within it that a channel is read from
+    ///
+    /// For [concatenated bands](Concatenation::Bands), every part holds a share of each
+    /// channel; the first part is reported.
+    pub fn channel_source(&self, channel: usize) -> Option<(usize, usize)> {
+        self.channels.get(channel).copied()
+    }
+
+    /// Elements of each record channel that a part provides
+    ///
+    /// For [concatenated channels](Concatenation::Channels), every part provides all of them.
+    pub fn element_range(&self, part: usize) -> Option<Range<usize>> {
+        self.elements.get(part).cloned()
+    }
+
+    /// Combined content header
+    pub fn header(&self) -> &ContentHeader {
+        &self.header
+    }
+
+    /// Common sampling grid of the files
+    pub fn grid(&self) -> &EquiangularGrid {
+        self.parts[0].grid()
+    }
+
+    /// Common default orientation of the files
+    pub fn orientation(&self) -> Orientation {
+        self.parts[0].orientation()
+    }
+
+    /// Number of channels of the combined dataset
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Number of records
+    pub fn num_records(&self) -> usize {
+        self.parts[0].num_records()
+    }
+
+    /// Number of values per record and channel of the combined dataset
+    pub fn elements_per_record(&self) -> usize {
+        self.elements.iter().map(|r| r.end).max().unwrap_or(0)
+    }
+
+    /// Decode one record channel into `data`, which must hold
+    /// [`elements_per_record`](VirtualReader::elements_per_record) values
+    pub fn read_channel(&self, record: usize, channel: usize, data: &mut [f32]) -> Result<()> {
+        let &(part, source) = self.channels.get(channel).ok_or_else(|| {
+            Error::new(format!(
+                "Invalid channel {} of {}",
+                channel,
+                self.num_channels()
+            ))
+        })?;
+        match self.concatenation {
+            Concatenation::Channels => Ok(self.parts[part].read_channel(record, source, data)?),
+            Concatenation::Bands => {
+                if data.len() != self.elements_per_record() {
+                    return Err(Error::new(format!(
+                        "Buffer holds {} values, records have {}",
+                        data.len(),
+                        self.elements_per_record()
+                    )));
+                }
+                for (part, range) in self.parts.iter().zip(&self.elements) {
+                    part.read_channel(record, channel, &mut data[range.clone()])?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Data of one record channel
+    pub fn channel(&self, record: usize, channel: usize) -> Result<Vec<f32>> {
+        let mut data = vec![0.0; self.elements_per_record()];
+        self.read_channel(record, channel, &mut data)?;
+        Ok(data)
+    }
+
+    /// Metadata of all files, with channel labels renumbered for concatenated channels
+    pub fn metadata(&self, conflicts: MetadataConflict) -> Result<Metadata> {
+        let mut metadata = Metadata::new();
+        let mut offset = 0;
+        for part in &self.parts {
+            let mut renumbered = part.metadata()?;
+            if self.concatenation == Concatenation::Channels {
+                let labels: Vec<_> = (0..part.num_channels())
+                    .map(|c| renumbered.remove(&metadata::channel_label_key(c)))
+                    .collect();
+                for (channel, label) in labels.into_iter().enumerate() {
+                    if let Some(label) = label {
+                        renumbered.insert(metadata::channel_label_key(offset + channel), label);
+                    }
+                }
+                offset += part.num_channels();
+            }
+            combine_metadata(&mut metadata, &renumbered, conflicts)?;
+        }
+        Ok(metadata)
+    }
+
+    /// Copy the combined data into a [`Dataset`], i.e. perform the physical merge
+    pub fn to_dataset(&self, conflicts: MetadataConflict) -> Result<Dataset> {
+        let first = self.parts[0].quantization();
+        let quantization = if self.parts.iter().all(|p| p.quantization() == first) {
+            first
+        } else {
+            Quantization::Float32
+        };
+        let grid = *self.grid();
+        let records = (0..self.num_records())
+            .map(|index| {
+                let (alpha, beta) = grid
+                    .record_coords(index)
+                    .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+                let channels = (0..self.num_channels())
+                    .map(|channel| self.channel(index, channel))
+                    .collect::<Result<_>>()?;
+                Ok(Record {
+                    alpha,
+                    beta,
+                    channels,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Dataset {
+            header: self.header.clone(),
+            quantization,
+            grid: Grid::Equiangular(grid),
+            orientation: self.orientation(),
+            metadata: self.metadata(conflicts)?,
+            records,
+        })
+    }
+}
+
+/// Whether two grids sample the same directions
+#[cfg(feature = "mmap")]
+fn same_grid(a: &EquiangularGrid, b: &EquiangularGrid) -> bool {
+    a.alpha_points == b.alpha_points
+        && a.beta_points == b.beta_points
+        && grid::angle_eq(a.alpha_start, b.alpha_start)
+        && grid::angle_eq(a.alpha_end, b.alpha_end)
+        && grid::angle_eq(a.beta_start, b.beta_start)
+        && grid::angle_eq(a.beta_end, b.beta_end)
+}
+
+/// Concatenate the channels of parts with the same record directions
+fn merge_channels(
+    parts: &[Dataset],
+    conflicts: MetadataConflict,
+) -> Result<(Grid, Vec<Record>, Metadata)> {
+    let mut records = parts[0].records.clone();
+    for part in &parts[1..] {
+        for (record, other) in records.iter_mut().zip(&part.records) {
+            record.channels.extend(other.channels.iter().cloned());
+        }
+    }
+
+    // Labels move to the channel numbers of the merged dataset
+    let mut offset = 0;
+    let mut metadata = Metadata::new();
+    for part in parts {
+        let num_channels = part.num_channels();
+        let mut renumbered = part.metadata.clone();
+        let labels: Vec<_> = (0..num_channels)
+            .map(|c| renumbered.remove(&metadata::channel_label_key(c)))
+            .collect();
+        for (channel, label) in labels.into_iter().enumerate() {
+            if let Some(label) = label {
+                renumbered.insert(metadata::channel_label_key(offset + channel), label);
+            }
+        }
+        combine_metadata(&mut metadata, &renumbered, conflicts)?;
+        offset += num_channels;
+    }
+    Ok((parts[0].grid, records, metadata))
+}
+
+/// Combine the records of parts covering different directions
+fn merge_records(
+    parts: &[Dataset],
+    conflicts: MetadataConflict,
+) -> Result<(Grid, Vec<Record>, Metadata)> {
+    let num_channels = parts[0].num_channels();
+    if let Some(index) = parts.iter().position(|p| p.num_channels() != num_channels) {
+        return Err(Error::new(format!(
+            "Dataset {} has {} channels, the first one {}",
+            index + 1,
+            parts[index].num_channels(),
+            num_channels
+        )));
+    }
+
+    let mut records: Vec<Record> = Vec::new();
+    for record in parts.iter().flat_map(|p| &p.records) {
+        let duplicate = records
+            .iter()
+            .find(|r| grid::angle_eq(r.alpha, record.alpha) && grid::angle_eq(r.beta, record.beta));
+        match duplicate {
+            Some(existing) if existing.channels != record.channels => {
+                return Err(Error::new(format!(
+                    "Datasets hold different data for alpha {}°, beta {}°",
+                    record.alpha, record.beta
+                )))
+            }
+            Some(_) => {}
+            None => records.push(record.clone()),
+        }
+    }
+
+    let mut metadata = Metadata::new();
+    for part in parts {
+        combine_metadata(&mut metadata, &part.metadata, conflicts)?;
+    }
+
+    let grids: Option<Vec<EquiangularGrid>> = parts
+        .iter()
+        .map(|p| p.grid.equiangular().copied())
+        .collect();
+    if let Some((grid, order)) = grids.and_then(|grids| regular_union(&grids, &records)) {
+        let records = order.iter().map(|&i| records[i].clone()).collect();
+        return Ok((Grid::Equiangular(grid), records, metadata));
+    }
+    Ok((Grid::Irregular, records, metadata))
+}
+
+/// Equiangular grid formed by the merged records and the record index of each of its points,
+/// if the records form such a grid on the common resolution of the parts
+fn regular_union(
+    grids: &[EquiangularGrid],
+    records: &[Record],
+) -> Option<(EquiangularGrid, Vec<usize>)> {
+    let common = |resolutions: Vec<f32>| {
+        let first = *resolutions.first()?;
+        resolutions
+            .iter()
+            .all(|&r| (r - first).abs() < 1e-3)
+            .then_some(first)
+    };
+    let alpha_resolution = common(
+        grids
+            .iter()
+            .filter(|g| g.alpha_points > 1)
+            .map(|g| g.alpha_resolution())
+            .collect(),
+    )?;
+    let beta_resolution = common(
+        grids
+            .iter()
+            .filter(|g| g.beta_points > 1)
+            .map(|g| g.beta_resolution())
+            .collect(),
+    )?;
+
+    // Full-circle parent grid on the common resolution spanning the beta range of all records
+    let alpha_points = (360.0 / alpha_resolution).round() as usize;
+    let beta_start = records.iter().map(|r| r.beta).fold(f32::INFINITY, f32::min);
+    let beta_end = records
+        .iter()
+        .map(|r| r.beta)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let beta_steps = ((beta_end - beta_start) / beta_resolution).round() as usize;
+    let alpha_start = grids[0].alpha_start.rem_euclid(alpha_resolution);
+    let parent = EquiangularGrid {
+        alpha_points,
+        alpha_start,
+        alpha_end: if alpha_start == 0.0 {
+            360.0
+        } else {
+            alpha_start
+        },
+        beta_points: beta_steps + 1,
+        beta_start,
+        beta_end,
+    };
+    if !grid::angle_eq(parent.alpha_resolution(), alpha_resolution) {
+        return None;
+    }
+
+    // Match the records to the points of the parent grid
+    let index: HashMap<(usize, usize), usize> = parent
+        .layout()
+        .enumerate()
+        .map(|(i, position)| (position, i))
+        .collect();
+    let directions: Vec<(f32, f32)> = parent.directions().collect();
+    let mut record_at = vec![None; directions.len()];
+    for (r, record) in records.iter().enumerate() {
+        let ring = ((record.beta - beta_start) / beta_resolution).round() as usize;
+        let pole = record.beta <= 1e-3 || record.beta >= 180.0 - 1e-3;
+        let a = if pole {
+            0
+        } else {
+            ((record.alpha - alpha_start).rem_euclid(360.0) / alpha_resolution).round() as usize
+                % alpha_points
+        };
+        let point = *index.get(&(ring, a))?;
+        let (alpha, beta) = directions[point];
+        if !(grid::angle_eq(beta, record.beta) && (pole || grid::angle_eq(alpha, record.alpha))) {
+            return None;
+        }
+        record_at[point] = Some(r);
+    }
+
+    let keep: Vec<bool> = record_at.iter().map(Option::is_some).collect();
+    let (grid, order) = grid::regular_subset(&parent, &keep)?;
+    let order = order
+        .into_iter()
+        .map(|point| record_at[point])
+        .collect::<Option<Vec<usize>>>()?;
+    Some((grid, order))
+}
+
+/// Add the entries of `other` to `metadata`, resolving keys set to different values
+fn combine_metadata(
+    metadata: &mut Metadata,
+    other: &Metadata,
+    conflicts: MetadataConflict,
+) -> Result<()> {
+    for (key, value) in other {
+        match metadata.get(key) {
+            Some(existing) if existing != value => match conflicts {
+                MetadataConflict::Error => {
+                    return Err(Error::new(format!(
+                        "Conflicting values for metadata key '{}'",
+                        key
+                    )))
+                }
+                MetadataConflict::KeepFirst => {}
+                MetadataConflict::KeepLast => {
+                    metadata.insert(key.clone(), value.clone());
+                }
+            },
+            Some(_) => {}
+            None => {
+                metadata.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Region;
+    use crate::test_util::temp_path;
+    use crate::{ContentHeader, MetadataValue};
+
+    fn sphere() -> Dataset {
+        let grid = EquiangularGrid {
+            alpha_points: 12,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points: 7,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        };
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![500.0, 1000.0],
+            },
+            grid,
+            1,
+            |alpha, beta, _| vec![alpha, beta],
+        )
+    }
+
+    fn window(alpha_start: f32, alpha_end: f32, beta_start: f32, beta_end: f32) -> Region {
+        Region::Window {
+            alpha_start,
+            alpha_end,
+            beta_start,
+            beta_end,
+        }
+    }
+
+    fn label(text: &str) -> MetadataValue {
+        MetadataValue::String(text.into())
+    }
+
+    #[test]
+    fn test_merge_directions() {
+        let data = sphere();
+        // Both halves contain the ring at 90°
+        let lower = grid::crop(&data, &window(0.0, 360.0, 0.0, 90.0)).unwrap();
+        let upper = grid::crop(&data, &window(0.0, 360.0, 90.0, 180.0)).unwrap();
+        let merged = merge(&[upper.clone(), lower.clone()], MetadataConflict::Error).unwrap();
+        assert_eq!(merged, data);
+
+        // Overlapping parts with a gap in alpha result in a smaller regular grid
+        let west = grid::crop(&data, &window(0.0, 90.0, 60.0, 120.0)).unwrap();
+        let east = grid::crop(&data, &window(60.0, 150.0, 60.0, 120.0)).unwrap();
+        let merged = merge(&[west, east], MetadataConflict::Error).unwrap();
+        let grid = merged.grid.equiangular().expect("regular grid");
+        assert_eq!(
+            (grid.alpha_start, grid.alpha_end, grid.alpha_points),
+            (0.0, 150.0, 6)
+        );
+        assert_eq!(merged.num_records(), 3 * 6);
+
+        // Parts that do not form a regular grid together
+        let west = grid::crop(&data, &window(0.0, 60.0, 60.0, 90.0)).unwrap();
+        let east = grid::crop(&data, &window(90.0, 120.0, 90.0, 120.0)).unwrap();
+        let merged = merge(&[west, east], MetadataConflict::Error).unwrap();
+        assert_eq!(merged.grid, Grid::Irregular);
+        assert_eq!(merged.num_records(), 2 * 3 + 2 * 2);
+
+        let mut different = upper;
+        different.records[0].channels[0][0] += 1.0;
+        assert!(merge(&[lower, different], MetadataConflict::Error).is_err());
+    }
+
+    #[test]
+    fn test_merge_channels() {
+        let mut left = sphere();
+        left.metadata
+            .insert(metadata::channel_label_key(0), label("Left ear"));
+        left.metadata.insert("SUBJECT".into(), label("KEMAR"));
+        let mut right = left.clone();
+        right.quantization = Quantization::Int16;
+        right
+            .metadata
+            .insert(metadata::channel_label_key(0), label("Right ear"));
+
+        let merged = merge(&[left, right], MetadataConflict::Error).unwrap();
+        assert_eq!(merged.num_channels(), 2);
+        assert_eq!(merged.quantization, Quantization::Float32);
+        assert_eq!(merged.grid, sphere().grid);
+        assert_eq!(
+            merged.metadata.get(&metadata::channel_label_key(1)),
+            Some(&label("Right ear"))
+        );
+        assert_eq!(merged.metadata.get("SUBJECT"), Some(&label("KEMAR")));
+    }
+
+    #[test]
+    fn test_metadata_conflicts() {
+        let data = sphere();
+        let mut lower = grid::crop(&data, &window(0.0, 360.0, 0.0, 90.0)).unwrap();
+        let mut upper = grid::crop(&data, &window(0.0, 360.0, 120.0, 180.0)).unwrap();
+        lower
+            .metadata
+            .insert("SESSION".into(), MetadataValue::Int(1));
+        upper
+            .metadata
+            .insert("SESSION".into(), MetadataValue::Int(2));
+        let parts = [lower, upper];
+
+        assert!(merge(&parts, MetadataConflict::Error).is_err());
+        let first = merge(&parts, MetadataConflict::KeepFirst).unwrap();
+        assert_eq!(first.metadata.get("SESSION"), Some(&MetadataValue::Int(1)));
+        let last = merge(&parts, MetadataConflict::KeepLast).unwrap();
+        assert_eq!(last.metadata.get("SESSION"), Some(&MetadataValue::Int(2)));
+    }
+
+    #[test]
+    fn test_incompatible_parts() {
+        assert!(merge(&[], MetadataConflict::Error).is_err());
+
+        let data = sphere();
+        let mut other = data.clone();
+        other.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![500.0, 2000.0],
+        };
+        assert!(merge(&[data.clone(), other], MetadataConflict::Error).is_err());
+
+        let mut stereo = grid::crop(&data, &window(0.0, 360.0, 120.0, 180.0)).unwrap();
+        for record in &mut stereo.records {
+            record.channels.push(record.channels[0].clone());
+        }
+        assert!(merge(&[data, stereo], MetadataConflict::Error).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_merge_files() {
+        let data = sphere();
+        let lower_path = temp_path("merge_lower.daff");
+        let upper_path = temp_path("merge_upper.daff");
+        let lower = grid::crop(&data, &window(0.0, 360.0, 0.0, 90.0)).unwrap();
+        let upper = grid::crop(&data, &window(0.0, 360.0, 90.0, 180.0)).unwrap();
+        crate::writer::write_dataset(&lower_path, &lower).unwrap();
+        crate::writer::write_dataset(&upper_path, &upper).unwrap();
+
+        let merged = merge_files(&[&lower_path, &upper_path], MetadataConflict::Error).unwrap();
+        assert_eq!(merged.grid, data.grid);
+        assert_eq!(merged.num_records(), data.num_records());
+        assert!(merge_files(&[temp_path("merge_missing.daff")], MetadataConflict::Error).is_err());
+
+        std::fs::remove_file(lower_path).unwrap();
+        std::fs::remove_file(upper_path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_virtual_reader() {
+        let mut left = sphere();
+        left.metadata
+            .insert(metadata::channel_label_key(0), label("Left ear"));
+        let mut right = left.clone();
+        right
+            .metadata
+            .insert(metadata::channel_label_key(0), label("Right ear"));
+        let mut high = sphere();
+        high.header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![2000.0, 4000.0],
+        };
+        for record in &mut high.records {
+            record.channels[0].iter_mut().for_each(|v| *v *= 2.0);
+        }
+        let paths: Vec<_> = ["left", "right", "high"]
+            .iter()
+            .map(|name| temp_path(&format!("virtual_{}.daff", name)))
+            .collect();
+        for (path, dataset) in paths.iter().zip([&left, &right, &high]) {
+            crate::writer::write_dataset(path, dataset).unwrap();
+        }
+
+        // Same frequencies: channels follow each other
+        let channels = VirtualReader::concat(&paths[..2]).unwrap();
+        assert_eq!(channels.concatenation(), Concatenation::Channels);
+        assert_eq!(channels.num_channels(), 2);
+        assert_eq!(channels.channel_source(1), Some((1, 0)));
+        assert_eq!(
+            channels.to_dataset(MetadataConflict::Error).unwrap(),
+            merge(&[left.clone(), right], MetadataConflict::Error).unwrap()
+        );
+
+        // Higher frequencies: bands follow each other
+        let bands = VirtualReader::concat(&[&paths[0], &paths[2]]).unwrap();
+        assert_eq!(bands.concatenation(), Concatenation::Bands);
+        assert_eq!(
+            bands.header(),
+            &ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![500.0, 1000.0, 2000.0, 4000.0],
+            }
+        );
+        assert_eq!(bands.num_channels(), 1);
+        assert_eq!(bands.element_range(1), Some(2..4));
+        let record = 20;
+        let expected = [
+            left.records[record].channels[0].clone(),
+            high.records[record].channels[0].clone(),
+        ]
+        .concat();
+        assert_eq!(bands.channel(record, 0).unwrap(), expected);
+        assert!(bands.channel(record, 1).is_err());
+        assert!(bands.read_channel(record, 0, &mut [0.0; 2]).is_err());
+        let dataset = bands.to_dataset(MetadataConflict::Error).unwrap();
+        assert_eq!(dataset.records[record].channels[0], expected);
+
+        // Bands must increase, the grid must match
+        assert!(VirtualReader::concat(&[&paths[2], &paths[0]]).is_err());
+        let coarse = Dataset::from_fn(
+            left.header.clone(),
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            1,
+            |_, _, _| vec![0.0, 0.0],
+        );
+        let coarse_path = temp_path("virtual_coarse.daff");
+        crate::writer::write_dataset(&coarse_path, &coarse).unwrap();
+        assert!(VirtualReader::concat(&[&paths[0], &coarse_path]).is_err());
+        assert!(VirtualReader::concat::<std::path::PathBuf>(&[]).is_err());
+
+        for path in paths.iter().chain([&coarse_path]) {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_resample_and_merge() {
+        let constant = |resolution: f32, value: f32| {
+            Dataset::from_fn(
+                ContentHeader::MagnitudeSpectrum {
+                    frequencies: vec![500.0, 1000.0],
+                },
+                EquiangularGrid::with_resolution(resolution, resolution).unwrap(),
+                2,
+                move |_, _, channel| vec![value, value + channel as f32],
+            )
+        };
+        let mut frontal = constant(10.0, 2.0);
+        frontal.metadata.insert("SESSION".into(), label("frontal"));
+        let mut surround = constant(30.0, 1.0);
+        surround
+            .metadata
+            .insert("SESSION".into(), label("surround"));
+        surround.metadata.insert("ROOM".into(), label("anechoic"));
+        let target = EquiangularGrid::with_resolution(15.0, 15.0).unwrap();
+        let blend = BlendRegion::Cone {
+            azimuth: 0.0,
+            elevation: 0.0,
+            inner: 30.0,
+            outer: 60.0,
+        };
+        let merged =
+            resample_and_merge(&frontal, &surround, &Grid::Equiangular(target), &blend).unwrap();
+        assert_eq!(merged.grid, Grid::Equiangular(target));
+        assert_eq!(merged.num_records(), target.num_records());
+        assert_eq!(merged.metadata["SESSION"], label("frontal"));
+        assert_eq!(merged.metadata["ROOM"], label("anechoic"));
+        for record in &merged.records {
+            let w = blend.weight(&merged.orientation, record.alpha, record.beta);
+            let expected = 2.0 * w + (1.0 - w);
+            assert!((record.channels[0][0] - expected).abs() < 1e-5);
+            assert!((record.channels[1][1] - expected - 1.0).abs() < 1e-5);
+        }
+        let front = grid::to_data_view(&merged.orientation, 0.0, 0.0);
+        let (rear, side) = (
+            grid::to_data_view(&merged.orientation, 180.0, 0.0),
+            grid::to_data_view(&merged.orientation, 45.0, 0.0),
+        );
+        assert_eq!(blend.weight(&merged.orientation, front.0, front.1), 1.0);
+        assert_eq!(blend.weight(&merged.orientation, rear.0, rear.1), 0.0);
+        assert!((blend.weight(&merged.orientation, side.0, side.1) - 0.5).abs() < 1e-5);
+
+        let band = BlendRegion::Elevation {
+            lower: -10.0,
+            upper: 10.0,
+        };
+        let at = |elevation| {
+            let (alpha, beta) = grid::to_data_view(&merged.orientation, 90.0, elevation);
+            band.weight(&merged.orientation, alpha, beta)
+        };
+        assert_eq!((at(20.0), at(-20.0)), (1.0, 0.0));
+        assert!((at(0.0) - 0.5).abs() < 1e-5);
+
+        // Mismatching datasets, crossfades and target grids
+        let mono = Dataset::from_fn(
+            frontal.header.clone(),
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |_, _, _| vec![1.0, 1.0],
+        );
+        let target = Grid::Equiangular(target);
+        assert!(resample_and_merge(&frontal, &mono, &target, &blend).is_err());
+        let reversed = BlendRegion::Cone {
+            azimuth: 0.0,
+            elevation: 0.0,
+            inner: 60.0,
+            outer: 30.0,
+        };
+        assert!(resample_and_merge(&frontal, &surround, &target, &reversed).is_err());
+        assert!(resample_and_merge(&frontal, &surround, &Grid::Irregular, &blend).is_err());
+    }
+
+    #[test]
+    fn test_crossfade() {
+        // Phases take the shorter way around the circle
+        let phases = crossfade(
+            ContentType::PhaseSpectrum,
+            (false, false),
+            vec![3.0],
+            vec![-3.0],
+            0.5,
+        );
+        assert!((phases[0].abs() - std::f32::consts::PI).abs() < 1e-5);
+        // Magnitudes in dB are crossfaded as linear magnitudes, in the unit of the first
+        let levels = crossfade(
+            ContentType::MagnitudeSpectrum,
+            (true, false),
+            vec![20.0 * 2f32.log10()],
+            vec![1.0],
+            0.5,
+        );
+        assert!((levels[0] - 20.0 * 1.5f32.log10()).abs() < 1e-4);
+        let magnitudes = crossfade(
+            ContentType::MagnitudeSpectrum,
+            (false, true),
+            vec![2.0],
+            vec![0.0],
+            0.25,
+        );
+        assert!((magnitudes[0] - 1.25).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,642 @@
+//! Metadata values, encoding, schema validation and anonymization
+//!
+//! DAFF stores metadata keys and string values as NUL-terminated byte strings. This module
+//! defines how Rust strings map onto them: strings are written as UTF-8, and values that are
+//! not valid UTF-8 on read are taken as Latin-1, the encoding of files written by legacy
+//! tools. Keys and values containing NUL bytes cannot be represented and are rejected with
+//! [`Error::InvalidMetadata`].
+//!
+//! # Schema profiles
+//!
+//! A [`SchemaProfile`] declares which metadata keys a DAFF file must (or may) carry and of
+//! which type their values are, e.g. the conventions of a lab or an institutional archive.
+//! [`validate`] checks an opened file against a profile and reports every [`Violation`].
+//!
+//! # Profile format
+//!
+//! Profiles are written in TOML and parsed with the `manifest` feature. Each table below `keys` declares one key; `type` is one of
+//! `bool`, `int`, `float`, `string` or `any` (default), `required` defaults to `true`.
+//!
+//! ```toml
+//! name = "ITA HRTF archive"
+//!
+//! [keys.SUBJECT_ID]
+//! type = "string"
+//!
+//! [keys.MEASUREMENT_DISTANCE]
+//! type = "float"
+//!
+//! [keys.COMMENT]
+//! type = "string"
+//! required = false
+//! ```
+//!
+//! Key names are case-insensitive (ASCII letters only), like DAFF metadata keys. A `float`
+//! key also accepts integer values, since the DAFF reader converts them transparently.
+//!
+//! # Anonymization
+//!
+//! [`scrub`] removes or hashes personally identifying entries of a dataset's metadata
+//! according to a [`ScrubPolicy`], e.g. before publishing research data. Hashing needs the
+//! `digest` feature.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+#[cfg(feature = "manifest")]
+use std::fs;
+#[cfg(feature = "manifest")]
+use std::path::Path;
+
+#[cfg(feature = "digest")]
+use sha2::{Digest, Sha256};
+
+pub use opendaff_core::{Metadata, MetadataValue};
+
+use crate::{Dataset, Error, MetadataType, Reader, Result};
+
+/// Prefix of the metadata keys holding channel labels, followed by the 1-based channel number
+pub const CHANNEL_LABEL_PREFIX: &str = "LABEL_CHANNEL_";
+
+/// Metadata key of the label of a (0-based) channel, as read by the DAFF library
+pub fn channel_label_key(channel: usize) -> String {
+    format!("{}{}", CHANNEL_LABEL_PREFIX, channel + 1)
+}
+
+/// Check that a key name can be stored in a DAFF file
+pub fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        return Err(Error::invalid_metadata(key, "key must not be empty"));
+    }
+    if key.contains('\0') {
+        return Err(Error::invalid_metadata(key, "key contains a NUL character"));
+    }
+    Ok(())
+}
+
+/// Check that a string value can be stored in a DAFF file
+///
+/// Values of any length are supported, as long as they do not contain NUL characters.
+pub fn check_string(key: &str, value: &str) -> Result<()> {
+    check_key(key)?;
+    if let Some(position) = value.find('\0') {
+        return Err(Error::invalid_metadata(
+            key,
+            format!("value contains a NUL character at byte {}", position),
+        ));
+    }
+    Ok(())
+}
+
+/// Convert a key name for lookups in the DAFF library
+pub(crate) fn key_to_cstring(key: &str) -> Result<CString> {
+    check_key(key)?;
+    CString::new(key).map_err(|_| Error::invalid_metadata(key, "key contains a NUL character"))
+}
+
+/// Decode a metadata string read from a file: UTF-8, falling back to Latin-1
+pub(crate) fn decode_string(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+/// Read all metadata of the file opened by `reader`
+pub fn read_all(reader: &Reader) -> Result<Metadata> {
+    reader
+        .metadata_keys()
+        .into_iter()
+        .map(|key| {
+            let value = reader.metadata_value(&key)?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Rule for a single metadata key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRule {
+    /// Expected value type, `None` accepts any type
+    pub value_type: Option<MetadataType>,
+    /// Whether the key must be present
+    pub required: bool,
+}
+
+/// Set of metadata rules, e.g. the conventions of a lab or archive
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SchemaProfile {
+    /// Human-readable profile name
+    pub name: String,
+    rules: BTreeMap<String, KeyRule>,
+}
+
+impl SchemaProfile {
+    /// Create an empty profile
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            rules: BTreeMap::new(),
+        }
+    }
+
+    /// Require a key, optionally of a specific type
+    pub fn require(self, key: &str, value_type: Option<MetadataType>) -> Self {
+        self.rule(
+            key,
+            KeyRule {
+                value_type,
+                required: true,
+            },
+        )
+    }
+
+    /// Allow a key of a specific type without requiring it
+    pub fn optional(self, key: &str, value_type: MetadataType) -> Self {
+        self.rule(
+            key,
+            KeyRule {
+                value_type: Some(value_type),
+                required: false,
+            },
+        )
+    }
+
+    /// Add or replace the rule for a key
+    pub fn rule(mut self, key: &str, rule: KeyRule) -> Self {
+        self.rules.insert(key.to_uppercase(), rule);
+        self
+    }
+
+    /// Rules by (uppercase) key name
+    pub fn rules(&self) -> &BTreeMap<String, KeyRule> {
+        &self.rules
+    }
+
+    /// Parse a profile from its TOML representation
+    #[cfg(feature = "manifest")]
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e| Error::new(format!("Invalid profile: {}", e)))?;
+
+        let mut profile = Self::default();
+        for (field, value) in &table {
+            match (field.as_str(), value) {
+                ("name", toml::Value::String(name)) => profile.name = name.clone(),
+                ("keys", toml::Value::Table(keys)) => {
+                    for (key, rule) in keys {
+                        let rule = parse_rule(key, rule)?;
+                        profile = profile.rule(key, rule);
+                    }
+                }
+                _ => {
+                    return Err(Error::new(format!(
+                        "Invalid profile: unexpected field '{}'",
+                        field
+                    )))
+                }
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Load a profile from a TOML file
+    #[cfg(feature = "manifest")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))?;
+        Self::from_toml(&text)
+    }
+}
+
+/// A deviation of a file's metadata from a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A required key is not present
+    Missing {
+        /// Key name
+        key: String,
+        /// Type the key should have
+        expected: Option<MetadataType>,
+    },
+    /// A key is present but has the wrong type
+    WrongType {
+        /// Key name
+        key: String,
+        /// Type required by the profile
+        expected: MetadataType,
+        /// Type found in the file
+        found: MetadataType,
+    },
+}
+
+impl Violation {
+    /// Name of the offending key
+    pub fn key(&self) -> &str {
+        match self {
+            Violation::Missing { key, .. } | Violation::WrongType { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Missing {
+                key,
+                expected: Some(expected),
+            } => write!(f, "missing required {} key '{}'", expected, key),
+            Violation::Missing {
+                key,
+                expected: None,
+            } => write!(f, "missing required key '{}'", key),
+            Violation::WrongType {
+                key,
+                expected,
+                found,
+            } => write!(f, "key '{}' is {} but must be {}", key, found, expected),
+        }
+    }
+}
+
+/// Check the metadata of the file opened by `reader` against a profile
+///
+/// Returns all violations in key order; an empty list means the file conforms.
+pub fn validate(reader: &Reader, profile: &SchemaProfile) -> Result<Vec<Violation>> {
+    if !reader.is_valid() {
+        return Err(Error::Closed);
+    }
+
+    let violations = profile
+        .rules
+        .iter()
+        .filter_map(|(key, rule)| match reader.metadata_type(key) {
+            None if rule.required => Some(Violation::Missing {
+                key: key.clone(),
+                expected: rule.value_type,
+            }),
+            None => None,
+            Some(found) => match rule.value_type {
+                Some(expected) if !accepts(expected, found) => Some(Violation::WrongType {
+                    key: key.clone(),
+                    expected,
+                    found,
+                }),
+                _ => None,
+            },
+        })
+        .collect();
+    Ok(violations)
+}
+
+/// What [`scrub`] does with a metadata entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubAction {
+    /// Leave the entry unchanged
+    Keep,
+    /// Remove the entry
+    Remove,
+    /// Replace the value by a salted SHA-256 hash of its text (`sha256:<hex>`), so that
+    /// entries with equal values (e.g. the same subject) remain linkable
+    #[cfg(feature = "digest")]
+    Hash,
+}
+
+/// Rules deciding which metadata entries [`scrub`] removes or hashes
+///
+/// Rules are matched against the upper case key name in the order they were added; the first
+/// matching rule decides. Patterns may contain `*` as wildcard, e.g. `*NAME*`. Keys matched by
+/// no rule get the default action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrubPolicy {
+    rules: Vec<(String, ScrubAction)>,
+    default_action: ScrubAction,
+    #[cfg(feature = "digest")]
+    salt: String,
+}
+
+impl Default for ScrubPolicy {
+    /// Keep technical fields, hash subject IDs and remove names, contact data and dates
+    ///
+    /// Without the `digest` feature, subject IDs are removed instead.
+    fn default() -> Self {
+        #[cfg(feature = "digest")]
+        let subject_id = ScrubAction::Hash;
+        #[cfg(not(feature = "digest"))]
+        let subject_id = ScrubAction::Remove;
+        Self::new(ScrubAction::Keep)
+            .rule("SUBJECT_ID", subject_id)
+            .rule("*NAME*", ScrubAction::Remove)
+            .rule("*AUTHOR*", ScrubAction::Remove)
+            .rule("*CREATOR*", ScrubAction::Remove)
+            .rule("*OPERATOR*", ScrubAction::Remove)
+            .rule("*CONTACT*", ScrubAction::Remove)
+            .rule("*EMAIL*", ScrubAction::Remove)
+            .rule("*MAIL*", ScrubAction::Remove)
+            .rule("*PHONE*", ScrubAction::Remove)
+            .rule("*ADDRESS*", ScrubAction::Remove)
+            .rule("*BIRTH*", ScrubAction::Remove)
+            .rule("*AGE", ScrubAction::Remove)
+            .rule("*SEX*", ScrubAction::Remove)
+            .rule("*GENDER*", ScrubAction::Remove)
+            .rule("*DATE*", ScrubAction::Remove)
+            .rule("*TIME*", ScrubAction::Remove)
+    }
+}
+
+impl ScrubPolicy {
+    /// Create a policy without rules
+    pub fn new(default_action: ScrubAction) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_action,
+            #[cfg(feature = "digest")]
+            salt: String::new(),
+        }
+    }
+
+    /// Append a rule for keys matching `pattern`
+    pub fn rule(mut self, pattern: &str, action: ScrubAction) -> Self {
+        self.rules.push((pattern.to_uppercase(), action));
+        self
+    }
+
+    /// Set the salt prepended to values before hashing
+    ///
+    /// Without a secret salt, hashes of short values such as subject IDs can be reversed by
+    /// trying all candidates.
+    #[cfg(feature = "digest")]
+    pub fn salt(mut self, salt: impl Into<String>) -> Self {
+        self.salt = salt.into();
+        self
+    }
+
+    /// Action for the given key
+    pub fn action(&self, key: &str) -> ScrubAction {
+        let key = key.to_uppercase();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| wildcard_match(pattern, &key))
+            .map_or(self.default_action, |(_, action)| *action)
+    }
+}
+
+/// Remove or hash personally identifying metadata of a dataset
+///
+/// Returns the affected keys with the applied action, in key order.
+pub fn scrub(dataset: &mut Dataset, policy: &ScrubPolicy) -> Vec<(String, ScrubAction)> {
+    let mut applied = Vec::new();
+    let keys: Vec<String> = dataset.metadata.keys().cloned().collect();
+    for key in keys {
+        match policy.action(&key) {
+            ScrubAction::Keep => continue,
+            ScrubAction::Remove => {
+                dataset.metadata.remove(&key);
+                applied.push((key, ScrubAction::Remove));
+            }
+            #[cfg(feature = "digest")]
+            ScrubAction::Hash => {
+                let value = &dataset.metadata[&key];
+                let digest = Sha256::new()
+                    .chain_update(policy.salt.as_bytes())
+                    .chain_update(value.to_string().as_bytes())
+                    .finalize();
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                dataset.metadata.insert(
+                    key.clone(),
+                    MetadataValue::String(format!("sha256:{}", hex)),
+                );
+                applied.push((key, ScrubAction::Hash));
+            }
+        }
+    }
+    applied
+}
+
+/// Match a key against a pattern with `*` wildcards
+fn wildcard_match(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn accepts(expected: MetadataType, found: MetadataType) -> bool {
+    expected == found || (expected == MetadataType::Float && found == MetadataType::Int)
+}
+
+#[cfg(feature = "manifest")]
+fn parse_rule(key: &str, value: &toml::Value) -> Result<KeyRule> {
+    let invalid = |what: &str| Error::new(format!("Invalid profile: key '{}': {}", key, what));
+    let table = value
+        .as_table()
+        .ok_or_else(|| invalid("expected a table"))?;
+
+    let mut rule = KeyRule {
+        value_type: None,
+        required: true,
+    };
+    for (field, value) in table {
+        match (field.as_str(), value) {
+            ("type", toml::Value::String(name)) => {
+                rule.value_type = match name.to_lowercase().as_str() {
+                    "bool" => Some(MetadataType::Bool),
+                    "int" => Some(MetadataType::Int),
+                    "float" => Some(MetadataType::Float),
+                    "string" => Some(MetadataType::String),
+                    "any" => None,
+                    _ => return Err(invalid(&format!("unknown type '{}'", name))),
+                }
+            }
+            ("required", toml::Value::Boolean(required)) => rule.required = *required,
+            _ => return Err(invalid(&format!("unexpected field '{}'", field))),
+        }
+    }
+    Ok(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ring;
+
+    #[test]
+    fn test_decode_string() {
+        assert_eq!(decode_string("Jürgen Müller".as_bytes()), "Jürgen Müller");
+        // Latin-1 encoded "Jürgen" from legacy writers
+        assert_eq!(decode_string(b"J\xfcrgen"), "Jürgen");
+
+        let long = "ä".repeat(100_000);
+        assert_eq!(decode_string(long.as_bytes()), long);
+    }
+
+    #[test]
+    fn test_check_rejects_unrepresentable_metadata() {
+        assert!(check_string("AUTHOR", "Jürgen Müller").is_ok());
+        assert!(matches!(check_key(""), Err(Error::InvalidMetadata { .. })));
+        assert_eq!(
+            check_string("AUTHOR", "a\0b"),
+            Err(Error::InvalidMetadata {
+                key: "AUTHOR".to_string(),
+                reason: "value contains a NUL character at byte 1".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("SUBJECT_ID", "SUBJECT_ID"));
+        assert!(!wildcard_match("SUBJECT_ID", "SUBJECT_IDS"));
+        assert!(wildcard_match("*NAME*", "SUBJECT_NAME"));
+        assert!(wildcard_match("*NAME*", "NAME"));
+        assert!(wildcard_match("*AGE", "SUBJECT_AGE"));
+        assert!(!wildcard_match("*AGE", "AGE_GROUP_COUNT"));
+        assert!(wildcard_match("A*B*C", "AXXBYYC"));
+        assert!(!wildcard_match("A*B*C", "AXXC"));
+    }
+
+    #[test]
+    #[cfg(feature = "digest")]
+    fn test_scrub_default_policy() {
+        let mut dataset = Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            ring(1),
+            1,
+            |_, _, _| vec![1.0],
+        );
+        let entries = [
+            ("SUBJECT_ID", MetadataValue::String("P0815".to_string())),
+            (
+                "SUBJECT_NAME",
+                MetadataValue::String("Jürgen Müller".to_string()),
+            ),
+            (
+                "MEASUREMENT_DATE",
+                MetadataValue::String("2016-03-01".to_string()),
+            ),
+            ("SAMPLERATE", MetadataValue::Float(44100.0)),
+            ("DISTANCE", MetadataValue::Float(1.2)),
+        ];
+        dataset.metadata = entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+        let mut other = dataset.clone();
+
+        let applied = scrub(&mut dataset, &ScrubPolicy::default());
+        assert_eq!(
+            applied,
+            vec![
+                ("MEASUREMENT_DATE".to_string(), ScrubAction::Remove),
+                ("SUBJECT_ID".to_string(), ScrubAction::Hash),
+                ("SUBJECT_NAME".to_string(), ScrubAction::Remove),
+            ]
+        );
+        assert_eq!(dataset.metadata.len(), 3);
+        assert_eq!(
+            dataset.metadata["SAMPLERATE"],
+            MetadataValue::Float(44100.0)
+        );
+        let MetadataValue::String(hashed) = &dataset.metadata["SUBJECT_ID"] else {
+            panic!("hashed value must be a string");
+        };
+        assert!(hashed.starts_with("sha256:") && hashed.len() == 7 + 64);
+
+        // Same subject, same salt: linkable; different salt: different hash
+        let mut copy = other.clone();
+        scrub(&mut copy, &ScrubPolicy::default());
+        assert_eq!(copy.metadata["SUBJECT_ID"], dataset.metadata["SUBJECT_ID"]);
+        scrub(&mut other, &ScrubPolicy::default().salt("secret"));
+        assert_ne!(other.metadata["SUBJECT_ID"], dataset.metadata["SUBJECT_ID"]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "digest"))]
+    fn test_scrub_without_hashing() {
+        let mut dataset = Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            ring(1),
+            1,
+            |_, _, _| vec![1.0],
+        );
+        dataset.metadata = [
+            ("SUBJECT_ID", MetadataValue::String("P0815".to_string())),
+            ("DISTANCE", MetadataValue::Float(1.2)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let applied = scrub(&mut dataset, &ScrubPolicy::default());
+        assert_eq!(
+            applied,
+            vec![("SUBJECT_ID".to_string(), ScrubAction::Remove)]
+        );
+        assert_eq!(dataset.metadata.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "manifest")]
+    fn test_profile_from_toml() {
+        let profile = SchemaProfile::from_toml(
+            r#"
+            name = "Lab"
+
+            [keys.subject_id]
+            type = "string"
+
+            [keys.COMMENT]
+            type = "string"
+            required = false
+
+            [keys.DATE]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(profile.name, "Lab");
+        assert_eq!(
+            profile.rules()["SUBJECT_ID"],
+            KeyRule {
+                value_type: Some(MetadataType::String),
+                required: true
+            }
+        );
+        assert!(!profile.rules()["COMMENT"].required);
+        assert_eq!(profile.rules()["DATE"].value_type, None);
+    }
+
+    #[test]
+    #[cfg(feature = "manifest")]
+    fn test_profile_rejects_unknown_fields() {
+        assert!(SchemaProfile::from_toml("[keys.A]\ntype = \"double\"").is_err());
+        assert!(SchemaProfile::from_toml("[keys.A]\nmandatory = true").is_err());
+        assert!(SchemaProfile::from_toml("version = 2").is_err());
+    }
+
+    #[test]
+    fn test_float_accepts_int() {
+        assert!(accepts(MetadataType::Float, MetadataType::Int));
+        assert!(!accepts(MetadataType::Int, MetadataType::Float));
+        assert!(!accepts(MetadataType::String, MetadataType::Bool));
+    }
+}
@@ -0,0 +1,247 @@
+//! Pure-Rust parser for DAFF data
+//!
+//! [`ParsedFile`] reads the headers and record data of DAFF files in memory without the C++
+//! library. It works on any byte storage, e.g. a `Vec<u8>` downloaded by a browser, a
+//! `&'static [u8]` from `include_bytes!` or a memory mapping (see
+//! [`MappedFile`](crate::mapped::MappedFile)), and needs no file system access, so it is also
+//! available on `wasm32-unknown-unknown`, where the C++ library is not.
+//!
+//! ```no_run
+//! # fn main() -> opendaff::Result<()> {
+//! # let bytes: Vec<u8> = Vec::new();
+//! let file = opendaff::parser::ParsedFile::new(bytes)?;
+//! let mut samples = vec![0.0; file.elements_per_record()];
+//! file.read_channel(0, 0, &mut samples)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The parser itself lives in the `no_std` crate [`opendaff_core`], for targets without an
+//! operating system. [`ParsedFile`] adds the grid and dataset types of this crate; the
+//! accessors of the core parser are available through `Deref`.
+//!
+//! DAFF files are little endian. Files written byte-swapped, e.g. by tools on a big-endian
+//! host that skipped the conversion, are recognized by their file header and read as well;
+//! [`swap_byte_order`] converts them. Values are decoded independently of the byte order of
+//! the host.
+
+use std::ops::Deref;
+
+pub use opendaff_core::{swap_byte_order, ByteOrder, Layout, MainHeader};
+
+use crate::dataset::{Dataset, Record};
+use crate::grid::{EquiangularGrid, Grid};
+use crate::legacy;
+use crate::{Error, Result};
+
+/// DAFF data in memory, with its headers parsed
+///
+/// Record data is decoded from the storage when it is requested.
+#[derive(Debug, Clone)]
+pub struct ParsedFile<S = Vec<u8>> {
+    file: opendaff_core::ParsedFile<S>,
+    grid: EquiangularGrid,
+}
+
+impl<S: AsRef<[u8]>> ParsedFile<S> {
+    /// Parse the headers of DAFF data, validating every header field and record descriptor
+    ///
+    /// Damaged data is reported as [`Error::Corrupt`] with the section and byte offset of the
+    /// first invalid field.
+    pub fn new(data: S) -> Result<Self> {
+        if let Some(version) =
+            legacy::detect_version(data.as_ref()).filter(|&v| legacy::is_legacy(v))
+        {
+            return Err(Error::new(format!(
+                "File format version {} predates DAFF 1.7, convert it with legacy::upgrade",
+                version
+            )));
+        }
+        let file = opendaff_core::ParsedFile::new(data)?;
+        let main = file.main_header();
+        let grid = EquiangularGrid {
+            alpha_points: main.alpha_points,
+            alpha_start: main.alpha_start,
+            alpha_end: main.alpha_end,
+            beta_points: main.beta_points,
+            beta_start: main.beta_start,
+            beta_end: main.beta_end,
+        };
+        Ok(Self { file, grid })
+    }
+
+    /// Give back the storage of the data
+    pub fn into_inner(self) -> S {
+        self.file.into_inner()
+    }
+
+    /// Sampling grid of the records
+    pub fn grid(&self) -> &EquiangularGrid {
+        &self.grid
+    }
+
+    /// Direction and data of all channels of a record
+    pub fn record(&self, index: usize) -> Result<Record> {
+        let (alpha, beta) = self
+            .grid
+            .record_coords(index)
+            .ok_or_else(|| Error::new(format!("Invalid record index {}", index)))?;
+        let channels = (0..self.num_channels())
+            .map(|channel| Ok(self.channel(index, channel)?.into_owned()))
+            .collect::<Result<_>>()?;
+        Ok(Record {
+            alpha,
+            beta,
+            channels,
+        })
+    }
+
+    /// Copy the whole file into a [`Dataset`]
+    pub fn to_dataset(&self) -> Result<Dataset> {
+        Ok(Dataset {
+            header: self.header().clone(),
+            quantization: self.quantization(),
+            grid: Grid::Equiangular(self.grid),
+            orientation: self.orientation(),
+            metadata: self.metadata()?,
+            records: (0..self.num_records())
+                .map(|index| self.record(index))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl<S> Deref for ParsedFile<S> {
+    type Target = opendaff_core::ParsedFile<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, temp_path};
+    use crate::{writer, ContentHeader, ContentType, MetadataValue, Quantization, Section};
+    use std::borrow::Cow;
+
+    /// Bytes of a file with a dataset
+    fn encode(name: &str, dataset: &Dataset) -> Vec<u8> {
+        let path = temp_path(name);
+        writer::write_dataset(&path, dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    /// Bytes of a small IR file with two channels
+    fn bytes(name: &str) -> Vec<u8> {
+        let mut dataset = test_util::dataset();
+        dataset.quantization = Quantization::Int16;
+        encode(name, &dataset)
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        let bytes = bytes("parser-bytes.daff");
+        let file = ParsedFile::new(bytes.as_slice()).unwrap();
+        assert_eq!(file.content_type(), ContentType::ImpulseResponse);
+        assert_eq!(file.num_channels(), 2);
+        assert_eq!(file.elements_per_record(), 3);
+        let record = file.record(5).unwrap();
+        let (alpha, beta) = file.grid().record_coords(5).unwrap();
+        assert_eq!((record.alpha, record.beta), (alpha, beta));
+        assert!((record.channels[0][0] - alpha / 360.0).abs() < 1e-4);
+        assert!((record.channels[1][2] - 0.5).abs() < 1e-4);
+
+        let owned = ParsedFile::new(bytes.clone()).unwrap();
+        assert_eq!(owned.to_dataset().unwrap(), file.to_dataset().unwrap());
+        assert_eq!(owned.into_inner(), bytes);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_files() {
+        let bytes = bytes("parser-corrupt.daff");
+        let layout = Layout::parse(&bytes).unwrap();
+        assert!(Layout::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Layout::parse(&bytes[..layout.data.start]).is_err());
+        assert!(Layout::parse(&[]).is_err());
+
+        // A record pointing past the data block is reported with its descriptor
+        let mut corrupt = bytes.clone();
+        let desc = layout.descriptors.start + layout.desc_size + 4;
+        corrupt[desc..desc + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            ParsedFile::new(corrupt).unwrap_err(),
+            Error::Corrupt {
+                section: Section::RecordDescriptors,
+                offset: desc - 4,
+                reason: "Record 0 channel 1 exceeds the data block".to_string(),
+            }
+        );
+
+        let mut corrupt = bytes;
+        corrupt[2..6].copy_from_slice(&171i32.to_le_bytes());
+        assert!(ParsedFile::new(corrupt).is_err());
+    }
+
+    #[test]
+    fn test_byte_order() {
+        let mut int24 = ParsedFile::new(bytes("parser-int24.daff"))
+            .unwrap()
+            .to_dataset()
+            .unwrap();
+        int24.quantization = Quantization::Int24;
+        let header = ContentHeader::MagnitudePhaseSpectrum {
+            frequencies: vec![125.0, 1000.0, 8000.0],
+        };
+        let grid = EquiangularGrid::with_resolution(30.0, 45.0).unwrap();
+        let mut mps = Dataset::from_fn(header, grid, 2, |alpha, beta, channel| {
+            vec![
+                alpha / 360.0,
+                -beta / 180.0,
+                0.25,
+                channel as f32,
+                1.0,
+                -0.5,
+            ]
+        });
+        mps.metadata
+            .insert("Gain".into(), MetadataValue::Float(-3.5));
+        mps.metadata.insert("Subject".into(), MetadataValue::Int(7));
+        mps.metadata
+            .insert("Mirrored".into(), MetadataValue::Bool(true));
+        mps.metadata
+            .insert("Note".into(), MetadataValue::String("swap".into()));
+
+        for little in [
+            bytes("parser-int16.daff"),
+            encode("parser-int24.daff", &int24),
+            encode("parser-mps.daff", &mps),
+        ] {
+            let big = swap_byte_order(&little).unwrap();
+            assert_ne!(big, little);
+            assert_eq!(ByteOrder::detect(&little), Some(ByteOrder::LittleEndian));
+            assert_eq!(ByteOrder::detect(&big), Some(ByteOrder::BigEndian));
+            assert_eq!(swap_byte_order(&big).unwrap(), little);
+
+            let little = ParsedFile::new(little).unwrap();
+            let big = ParsedFile::new(big).unwrap();
+            assert_eq!(big.byte_order(), ByteOrder::BigEndian);
+            assert_eq!(big.to_dataset().unwrap(), little.to_dataset().unwrap());
+            // Only data in the byte order of the host can be borrowed
+            for file in [&little, &big] {
+                let borrowed = matches!(file.channel(1, 1).unwrap(), Cow::Borrowed(_));
+                let native = file.byte_order() == ByteOrder::native();
+                assert_eq!(
+                    borrowed,
+                    native && file.quantization() == Quantization::Float32
+                );
+            }
+        }
+        assert_eq!(ByteOrder::detect(b"FW"), None);
+        assert_eq!(ByteOrder::detect(b"FW\0\0\0\0"), None);
+        assert_eq!(ByteOrder::native().swapped().swapped(), ByteOrder::native());
+    }
+}
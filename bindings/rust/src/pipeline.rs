@@ -0,0 +1,1158 @@
+//! Declarative processing pipelines
+//!
+//! A [`Pipeline`] is an ordered list of processing [`Stage`]s applied to a [`Dataset`]. A
+//! [`Batch`] runs a pipeline over many DAFF files (import → stages → export) and can be
+//! described in a TOML file, which is what the `daff-batch` tool executes:
+//!
+//! ```toml
+//! # Files or directories (all *.daff files inside), relative to this file
+//! inputs = ["measurements", "extra/subject_07.daff"]
+//! output = "processed"
+//! format = "json"        # "json" or "csv"
+//! precision = 6          # decimal places of exported values
+//! magnitude_floor = -100 # clamp exported magnitudes to this level in dB (default: none)
+//! flag_underflows = true # count magnitudes below the floor per line (default: false)
+//! jobs = 4               # files processed in parallel (default: number of CPUs)
+//! hash = true            # store a reproducibility hash (default: false)
+//! cache = "cache"        # reuse results of unchanged inputs (default: none)
+//!
+//! [[stage]]
+//! kind = "trim"
+//! length = 256
+//! align = "peak"         # "start" (default) or "peak"
+//! lead = 16              # samples in front of the earliest peak
+//!
+//! [[stage]]
+//! kind = "resample"
+//! samplerate = 48000
+//!
+//! [[stage]]
+//! kind = "normalize"
+//! peak = -1.0            # dB re 1
+//!
+//! [[stage]]
+//! kind = "downsample"
+//! alpha_resolution = 5   # degrees
+//! beta_resolution = 5
+//! method = "average"     # "nearest" (default) or "average"
+//!
+//! [[stage]]
+//! kind = "magnitude"     # impulse responses to magnitude spectra
+//! bands = "octave"       # "third-octave" (default) or "octave"
+//!
+//! [[stage]]
+//! kind = "reference"     # relative to the record towards a direction
+//! azimuth = 0            # degrees (default: 0)
+//! elevation = 0          # degrees (default: 0)
+//! ```
+//!
+//! # Custom stages
+//!
+//! Other crates can add their own processing by implementing [`PipelineStage`]. Such stages
+//! can be added to a [`Pipeline`] directly, or registered under a `kind` name in a
+//! [`StageRegistry`] to make them available in pipeline files. [`batch_main`] runs the
+//! `daff-batch` command line with a given registry, so a custom tool is a few lines:
+//!
+//! ```no_run
+//! use std::fmt;
+//! use std::process::ExitCode;
+//!
+//! use opendaff::pipeline::{self, PipelineStage, StageRegistry};
+//! use opendaff::{Dataset, Result};
+//!
+//! struct Invert;
+//!
+//! impl fmt::Display for Invert {
+//!     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+//!         write!(f, "invert polarity")
+//!     }
+//! }
+//!
+//! impl PipelineStage for Invert {
+//!     fn apply(&self, dataset: &mut Dataset) -> Result<()> {
+//!         for record in &mut dataset.records {
+//!             for value in record.channels.iter_mut().flatten() {
+//!                 *value = -*value;
+//!             }
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn main() -> ExitCode {
+//!     let registry = StageRegistry::default().register("invert", |_| Ok(Box::new(Invert)));
+//!     pipeline::batch_main(&registry)
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+
+use crate::cache::Store;
+use crate::dsp::{self, Alignment, FrequencySupport};
+use crate::export::{self, ExportOptions};
+use crate::grid::{self, Downsampling};
+use crate::provenance::{self, ProvenanceKey};
+use crate::{Dataset, Error, Reader, Result};
+
+/// A processing step of a [`Pipeline`]
+///
+/// The [`Display`](fmt::Display) output describes the stage and its parameters; it is shown
+/// in dry runs and error messages and recorded as processing step in the metadata of the
+/// processed dataset. It should therefore name all parameters that affect the result.
+pub trait PipelineStage: fmt::Display + Send + Sync {
+    /// Apply the stage to a dataset
+    fn apply(&self, dataset: &mut Dataset) -> Result<()>;
+}
+
+/// Built-in processing steps
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    /// Bring all impulse responses to the same length, see [`dsp::pad_to`]
+    Trim {
+        /// Filter length in samples
+        length: usize,
+        /// Alignment of the responses
+        alignment: Alignment,
+    },
+    /// Resample impulse responses, see [`dsp::resample`]
+    Resample {
+        /// New sampling rate in Hz
+        samplerate: f64,
+    },
+    /// Scale to a common peak level, see [`dsp::normalize`]
+    Normalize {
+        /// Target peak level in dB re 1
+        peak_db: f32,
+    },
+    /// Reduce to a coarser grid, see [`grid::downsample_grid`]
+    Downsample {
+        /// Alpha resolution in degrees
+        alpha_resolution: f32,
+        /// Beta resolution in degrees
+        beta_resolution: f32,
+        /// Selection or averaging of the records
+        method: Downsampling,
+    },
+    /// Convert impulse responses to band magnitudes, see [`dsp::to_magnitude_spectrum`]
+    Magnitude {
+        /// Band centre frequencies
+        support: FrequencySupport,
+    },
+    /// Normalize relative to a reference direction, see [`dsp::normalize_to_direction`]
+    Reference {
+        /// Azimuth of the reference direction in degrees
+        azimuth: f32,
+        /// Elevation of the reference direction in degrees
+        elevation: f32,
+    },
+}
+
+impl PipelineStage for Stage {
+    fn apply(&self, dataset: &mut Dataset) -> Result<()> {
+        match *self {
+            Stage::Trim { length, alignment } => dsp::pad_to(dataset, length, alignment),
+            Stage::Magnitude { ref support } => dsp::to_magnitude_spectrum(dataset, support),
+            Stage::Resample { samplerate } => dsp::resample(dataset, samplerate),
+            Stage::Normalize { peak_db } => dsp::normalize(dataset, peak_db).map(|_| ()),
+            Stage::Reference { azimuth, elevation } => {
+                dsp::normalize_to_direction(dataset, azimuth, elevation).map(|_| ())
+            }
+            Stage::Downsample {
+                alpha_resolution,
+                beta_resolution,
+                method,
+            } => {
+                *dataset =
+                    grid::downsample_grid(dataset, alpha_resolution, beta_resolution, method)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Trim {
+                length,
+                alignment: Alignment::Start,
+            } => write!(f, "trim to {} samples", length),
+            Stage::Trim {
+                length,
+                alignment: Alignment::Peak { lead },
+            } => write!(
+                f,
+                "trim to {} samples, {} samples before the earliest peak",
+                length, lead
+            ),
+            Stage::Resample { samplerate } => write!(f, "resample to {} Hz", samplerate),
+            Stage::Normalize { peak_db } => write!(f, "normalize to {} dB peak", peak_db),
+            Stage::Reference { azimuth, elevation } => write!(
+                f,
+                "normalize relative to azimuth {}°, elevation {}°",
+                azimuth, elevation
+            ),
+            Stage::Downsample {
+                alpha_resolution,
+                beta_resolution,
+                method,
+            } => {
+                let method = match method {
+                    Downsampling::Nearest => "nearest records",
+                    Downsampling::Average => "averaged records",
+                };
+                write!(
+                    f,
+                    "downsample to {}° x {}° ({})",
+                    alpha_resolution, beta_resolution, method
+                )
+            }
+            Stage::Magnitude { support } => match support {
+                FrequencySupport::Octave => write!(f, "magnitude spectra in octave bands"),
+                FrequencySupport::ThirdOctave => {
+                    write!(f, "magnitude spectra in third-octave bands")
+                }
+                FrequencySupport::Custom(frequencies) => {
+                    write!(f, "magnitude spectra in {} bands", frequencies.len())
+                }
+            },
+        }
+    }
+}
+
+/// An ordered list of processing stages
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn PipelineStage>>,
+    hash: bool,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a stage
+    pub fn stage(mut self, stage: impl PipelineStage + 'static) -> Self {
+        self.stages.push(Arc::new(stage));
+        self
+    }
+
+    /// Also store a [`reproducibility_hash`](provenance::reproducibility_hash) of the result
+    /// in the `PROCESSING HASH` metadata entry
+    pub fn reproducibility_hash(mut self, enabled: bool) -> Self {
+        self.hash = enabled;
+        self
+    }
+
+    /// Stages in processing order
+    pub fn stages(&self) -> impl Iterator<Item = &dyn PipelineStage> {
+        self.stages.iter().map(|stage| stage.as_ref())
+    }
+
+    /// Number of stages
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Check whether the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// SHA-256 hash identifying the processing of this pipeline (`sha256:<hex>`)
+    ///
+    /// The hash covers the crate version and the description of every stage, which names
+    /// all parameters that affect the result (see [`PipelineStage`]). Pipelines with the
+    /// same fingerprint produce the same result from the same input, which is what the
+    /// [cache](crate::cache) relies on.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut part = |text: &str| {
+            hasher.update((text.len() as u64).to_le_bytes());
+            hasher.update(text.as_bytes());
+        };
+        part(env!("CARGO_PKG_NAME"));
+        part(env!("CARGO_PKG_VERSION"));
+        for stage in &self.stages {
+            part(&stage.to_string());
+        }
+        part(if self.hash { "hash" } else { "" });
+
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!("sha256:{}", hex)
+    }
+
+    /// Apply all stages to a dataset, stopping at the first failing stage
+    ///
+    /// Each applied stage is recorded in the dataset's metadata (see
+    /// [`provenance::record_step`]).
+    pub fn run(&self, dataset: &mut Dataset) -> Result<()> {
+        for (index, stage) in self.stages.iter().enumerate() {
+            stage
+                .apply(dataset)
+                .map_err(|e| Error::new(format!("Stage {} ({}): {}", index + 1, stage, e)))?;
+            provenance::record_step(&mut dataset.metadata, &stage.to_string())?;
+        }
+        if self.hash {
+            let hash = provenance::reproducibility_hash(dataset);
+            ProvenanceKey::ProcessingHash.set(&mut dataset.metadata, hash)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.stages.iter().map(|stage| stage.to_string()))
+            .finish()
+    }
+}
+
+/// Parameters of a stage in a pipeline file
+///
+/// Every parameter read by a stage factory is marked as used; parameters no factory asked
+/// for are reported as errors, so typos in pipeline files do not go unnoticed.
+pub struct StageParams<'a> {
+    kind: &'a str,
+    table: &'a toml::Table,
+    used: RefCell<HashSet<&'a str>>,
+}
+
+impl<'a> StageParams<'a> {
+    /// Kind name of the stage
+    pub fn kind(&self) -> &'a str {
+        self.kind
+    }
+
+    /// A non-negative integer parameter
+    pub fn integer(&self, key: &str) -> Result<Option<usize>> {
+        self.get(key)
+            .map(|value| match value {
+                toml::Value::Integer(i) if *i >= 0 => Ok(*i as usize),
+                _ => Err(self.invalid(format!("'{}' must be a non-negative integer", key))),
+            })
+            .transpose()
+    }
+
+    /// A numeric parameter (integer or floating point)
+    pub fn number(&self, key: &str) -> Result<Option<f64>> {
+        self.get(key)
+            .map(|value| match value {
+                toml::Value::Integer(i) => Ok(*i as f64),
+                toml::Value::Float(f) => Ok(*f),
+                _ => Err(self.invalid(format!("'{}' must be a number", key))),
+            })
+            .transpose()
+    }
+
+    /// A string parameter
+    pub fn string(&self, key: &str) -> Result<Option<&'a str>> {
+        self.get(key)
+            .map(|value| match value {
+                toml::Value::String(s) => Ok(s.as_str()),
+                _ => Err(self.invalid(format!("'{}' must be a string", key))),
+            })
+            .transpose()
+    }
+
+    /// A boolean parameter
+    pub fn boolean(&self, key: &str) -> Result<Option<bool>> {
+        self.get(key)
+            .map(|value| match value {
+                toml::Value::Boolean(b) => Ok(*b),
+                _ => Err(self.invalid(format!("'{}' must be true or false", key))),
+            })
+            .transpose()
+    }
+
+    /// Error for a missing required parameter
+    pub fn missing(&self, key: &str) -> Error {
+        self.invalid(format!("'{}' is required", key))
+    }
+
+    /// Error for invalid parameters of this stage
+    pub fn invalid(&self, reason: impl fmt::Display) -> Error {
+        Error::new(format!("Invalid pipeline: {} stage: {}", self.kind, reason))
+    }
+
+    fn get(&self, key: &str) -> Option<&'a toml::Value> {
+        let (key, value) = self.table.get_key_value(key)?;
+        self.used.borrow_mut().insert(key.as_str());
+        Some(value)
+    }
+
+    fn unused(&self) -> Option<&'a str> {
+        let used = self.used.borrow();
+        self.table
+            .keys()
+            .map(String::as_str)
+            .find(|key| *key != "kind" && !used.contains(key))
+    }
+}
+
+/// Factory creating a stage from its parameters in a pipeline file
+pub type StageFactory = dyn Fn(&StageParams) -> Result<Box<dyn PipelineStage>> + Send + Sync;
+
+/// Stage kinds available in pipeline files
+///
+/// The default registry contains the built-in stages `trim`, `resample`, `normalize`,
+/// `downsample`, `magnitude` and `reference`.
+#[derive(Clone)]
+pub struct StageRegistry {
+    factories: BTreeMap<String, Arc<StageFactory>>,
+}
+
+impl Default for StageRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .register("trim", |params| Ok(Box::new(parse_trim(params)?)))
+            .register("resample", |params| {
+                Ok(Box::new(Stage::Resample {
+                    samplerate: params
+                        .number("samplerate")?
+                        .ok_or_else(|| params.missing("samplerate"))?,
+                }))
+            })
+            .register("normalize", |params| {
+                Ok(Box::new(Stage::Normalize {
+                    peak_db: params
+                        .number("peak")?
+                        .ok_or_else(|| params.missing("peak"))? as f32,
+                }))
+            })
+            .register("downsample", |params| {
+                Ok(Box::new(parse_downsample(params)?))
+            })
+            .register("magnitude", |params| {
+                let support = match params.string("bands")? {
+                    None | Some("third-octave") => FrequencySupport::ThirdOctave,
+                    Some("octave") => FrequencySupport::Octave,
+                    Some(_) => {
+                        return Err(params.invalid("bands must be \"third-octave\" or \"octave\""))
+                    }
+                };
+                Ok(Box::new(Stage::Magnitude { support }))
+            })
+            .register("reference", |params| {
+                Ok(Box::new(Stage::Reference {
+                    azimuth: params.number("azimuth")?.unwrap_or(0.0) as f32,
+                    elevation: params.number("elevation")?.unwrap_or(0.0) as f32,
+                }))
+            })
+    }
+}
+
+impl StageRegistry {
+    /// Create a registry without any stages
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Register a stage kind, replacing a previous registration of the same name
+    pub fn register<F>(mut self, kind: &str, factory: F) -> Self
+    where
+        F: Fn(&StageParams) -> Result<Box<dyn PipelineStage>> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind.to_string(), Arc::new(factory));
+        self
+    }
+
+    /// Registered stage kinds in alphabetical order
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    fn create(&self, value: &toml::Value) -> Result<Arc<dyn PipelineStage>> {
+        let toml::Value::Table(table) = value else {
+            return Err(Error::new("Invalid pipeline: stages must be tables"));
+        };
+        let kind = match table.get("kind") {
+            Some(toml::Value::String(kind)) => kind.as_str(),
+            _ => return Err(Error::new("Invalid pipeline: stage without kind")),
+        };
+        let factory = self.factories.get(kind).ok_or_else(|| {
+            Error::new(format!("Invalid pipeline: unknown stage kind '{}'", kind))
+        })?;
+
+        let params = StageParams {
+            kind,
+            table,
+            used: RefCell::new(HashSet::new()),
+        };
+        let stage = factory(&params)?;
+        if let Some(key) = params.unused() {
+            return Err(params.invalid(format!("unexpected field '{}'", key)));
+        }
+        Ok(Arc::from(stage))
+    }
+}
+
+impl fmt::Debug for StageRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.kinds()).finish()
+    }
+}
+
+/// File format written by a [`Batch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// JSON, see [`export::to_json`]
+    #[default]
+    Json,
+    /// CSV, see [`export::to_csv`]
+    Csv,
+}
+
+impl OutputFormat {
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A pipeline run over many files
+#[derive(Debug, Clone)]
+pub struct Batch {
+    /// Input files and directories; directories contribute all `*.daff` files they contain
+    pub inputs: Vec<PathBuf>,
+    /// Directory receiving the exported files
+    pub output: PathBuf,
+    /// Export format
+    pub format: OutputFormat,
+    /// Export options
+    pub export: ExportOptions,
+    /// Processing applied to each file
+    pub pipeline: Pipeline,
+    /// Maximum number of files processed in parallel (0: number of available CPUs)
+    pub jobs: usize,
+    /// Directory of a [cache](crate::cache) of processed datasets, `None` to process every
+    /// file
+    pub cache: Option<PathBuf>,
+}
+
+impl Batch {
+    /// Parse a batch description from TOML (see the [module documentation](self))
+    ///
+    /// Only the built-in stages are available. Relative paths are kept as given;
+    /// [`Batch::load`] resolves them against the file's directory.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Self::from_toml_with(text, &StageRegistry::default())
+    }
+
+    /// Parse a batch description from TOML, creating the stages with the given registry
+    pub fn from_toml_with(text: &str, registry: &StageRegistry) -> Result<Self> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e| Error::new(format!("Invalid pipeline: {}", e)))?;
+
+        let mut batch = Batch {
+            inputs: Vec::new(),
+            output: PathBuf::new(),
+            format: OutputFormat::default(),
+            export: ExportOptions::default(),
+            pipeline: Pipeline::new(),
+            jobs: 0,
+            cache: None,
+        };
+        for (field, value) in &table {
+            match (field.as_str(), value) {
+                ("inputs", toml::Value::Array(inputs)) => {
+                    for input in inputs {
+                        let toml::Value::String(input) = input else {
+                            return Err(Error::new("Invalid pipeline: inputs must be paths"));
+                        };
+                        batch.inputs.push(PathBuf::from(input));
+                    }
+                }
+                ("output", toml::Value::String(output)) => batch.output = PathBuf::from(output),
+                ("format", toml::Value::String(format)) => {
+                    batch.format = match format.as_str() {
+                        "json" => OutputFormat::Json,
+                        "csv" => OutputFormat::Csv,
+                        _ => {
+                            return Err(Error::new(format!(
+                                "Invalid pipeline: unknown format '{}'",
+                                format
+                            )))
+                        }
+                    }
+                }
+                ("precision", toml::Value::Integer(i)) if *i >= 0 => {
+                    batch.export.precision = *i as usize
+                }
+                ("magnitude_floor", toml::Value::Integer(i)) => {
+                    batch.export.magnitude_floor_db = Some(*i as f32)
+                }
+                ("magnitude_floor", toml::Value::Float(f)) if f.is_finite() => {
+                    batch.export.magnitude_floor_db = Some(*f as f32)
+                }
+                ("flag_underflows", toml::Value::Boolean(flag)) => {
+                    batch.export.flag_underflows = *flag
+                }
+                ("jobs", toml::Value::Integer(i)) if *i >= 0 => batch.jobs = *i as usize,
+                ("cache", toml::Value::String(cache)) => batch.cache = Some(PathBuf::from(cache)),
+                ("hash", toml::Value::Boolean(hash)) => {
+                    batch.pipeline = batch.pipeline.reproducibility_hash(*hash)
+                }
+                ("stage", toml::Value::Array(stages)) => {
+                    for stage in stages {
+                        batch.pipeline.stages.push(registry.create(stage)?);
+                    }
+                }
+                _ => {
+                    return Err(Error::new(format!(
+                        "Invalid pipeline: unexpected field '{}'",
+                        field
+                    )))
+                }
+            }
+        }
+
+        if batch.inputs.is_empty() {
+            return Err(Error::new("Invalid pipeline: no inputs given"));
+        }
+        if batch.output.as_os_str().is_empty() {
+            return Err(Error::new("Invalid pipeline: no output directory given"));
+        }
+        Ok(batch)
+    }
+
+    /// Load a batch description from a TOML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with(path, &StageRegistry::default())
+    }
+
+    /// Load a batch description from a TOML file, creating the stages with the given registry
+    pub fn load_with(path: impl AsRef<Path>, registry: &StageRegistry) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))?;
+        let mut batch = Self::from_toml_with(&text, registry)?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for input in &mut batch.inputs {
+            *input = base.join(&*input);
+        }
+        batch.output = base.join(&batch.output);
+        if let Some(cache) = &mut batch.cache {
+            *cache = base.join(&*cache);
+        }
+        Ok(batch)
+    }
+
+    /// Input files and the paths their results are written to
+    ///
+    /// Fails if an input does not exist or two inputs would be written to the same file.
+    pub fn plan(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut files = Vec::new();
+        for input in &self.inputs {
+            if input.is_dir() {
+                let mut found = daff_files(input)?;
+                found.sort();
+                files.extend(found);
+            } else if input.is_file() {
+                files.push(input.clone());
+            } else {
+                return Err(Error::new(format!(
+                    "Input '{}' does not exist",
+                    input.display()
+                )));
+            }
+        }
+
+        let mut outputs = HashSet::new();
+        files
+            .into_iter()
+            .map(|file| {
+                let name = file.file_stem().unwrap_or_default();
+                let output = self
+                    .output
+                    .join(name)
+                    .with_extension(self.format.extension());
+                if !outputs.insert(output.clone()) {
+                    return Err(Error::new(format!(
+                        "Several inputs would be written to '{}'",
+                        output.display()
+                    )));
+                }
+                Ok((file, output))
+            })
+            .collect()
+    }
+
+    /// Process a single file: import, run the pipeline and export
+    ///
+    /// With a [`cache`](Batch::cache), the processed dataset is taken from the cache if the
+    /// file was processed by the same pipeline before.
+    pub fn process(&self, input: &Path, output: &Path) -> Result<()> {
+        let dataset = match &self.cache {
+            Some(cache) => Store::open(cache)?.process(input, &self.pipeline)?,
+            None => {
+                let mut reader = Reader::open(input)?;
+                let mut dataset = Dataset::from_reader(&reader)?;
+                reader.close();
+
+                self.pipeline.run(&mut dataset)?;
+                dataset
+            }
+        };
+
+        let file = File::create(output)
+            .map_err(|e| Error::new(format!("Failed to create '{}': {}", output.display(), e)))?;
+        let writer = BufWriter::new(file);
+        match self.format {
+            OutputFormat::Json => export::to_json(&dataset, &self.export, writer),
+            OutputFormat::Csv => export::to_csv(&dataset, &self.export, writer),
+        }
+    }
+
+    /// Run the batch over all planned files
+    ///
+    /// Files are processed in parallel by up to [`Batch::jobs`] threads. A failing file does
+    /// not stop the others; the result of each file is returned in plan order.
+    pub fn run(&self) -> Result<Vec<(PathBuf, Result<PathBuf>)>> {
+        let plan = self.plan()?;
+        fs::create_dir_all(&self.output).map_err(|e| {
+            Error::new(format!(
+                "Failed to create '{}': {}",
+                self.output.display(),
+                e
+            ))
+        })?;
+
+        let jobs = match self.jobs {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            jobs => jobs,
+        }
+        .min(plan.len())
+        .max(1);
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; plan.len()]);
+        std::thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((input, output)) = plan.get(index) else {
+                        break;
+                    };
+                    let result = self.process(input, output).map(|_| output.clone());
+                    results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+                });
+            }
+        });
+
+        let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        Ok(plan
+            .into_iter()
+            .zip(results)
+            .map(|((input, _), result)| {
+                let result =
+                    result.unwrap_or_else(|| Err(Error::new("Processing thread panicked")));
+                (input, result)
+            })
+            .collect())
+    }
+}
+
+const USAGE: &str = "Usage: daff-batch [--dry-run] [--jobs N] <pipeline.toml>";
+
+/// Run the `daff-batch` command line with the stages of the given registry
+///
+/// Parses the process arguments, prints progress to stdout and errors to stderr, and returns
+/// the process exit code.
+pub fn batch_main(registry: &StageRegistry) -> ExitCode {
+    let mut dry_run = false;
+    let mut jobs = None;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" | "-n" => dry_run = true,
+            "--jobs" | "-j" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => jobs = Some(n),
+                None => {
+                    eprintln!("--jobs requires a number\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                let kinds: Vec<&str> = registry.kinds().collect();
+                println!("Stages: {}", kinds.join(", "));
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument '{}'\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let mut batch = match Batch::load_with(&path, registry) {
+        Ok(batch) => batch,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(jobs) = jobs {
+        batch.jobs = jobs;
+    }
+
+    if dry_run {
+        let plan = match batch.plan() {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        println!("Stages:");
+        for (index, stage) in batch.pipeline.stages().enumerate() {
+            println!("  {}. {}", index + 1, stage);
+        }
+        println!("Files:");
+        for (input, output) in &plan {
+            println!("  {} -> {}", input.display(), output.display());
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let results = match batch.run() {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut failed = 0;
+    for (input, result) in &results {
+        match result {
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {}", input.display(), e);
+            }
+        }
+    }
+    println!(
+        "{} of {} files processed",
+        results.len() - failed,
+        results.len()
+    );
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn daff_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(directory).map_err(|e| {
+        Error::new(format!(
+            "Failed to read directory '{}': {}",
+            directory.display(),
+            e
+        ))
+    })?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| Error::new(format!("Failed to read directory entry: {}", e)))?
+            .path();
+        let is_daff = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("daff"));
+        if is_daff && path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn parse_trim(params: &StageParams) -> Result<Stage> {
+    let length = params
+        .integer("length")?
+        .ok_or_else(|| params.missing("length"))?;
+    let lead = params.integer("lead")?;
+    let alignment = match (params.string("align")?, lead) {
+        (None | Some("start"), None) => Alignment::Start,
+        (Some("peak"), lead) => Alignment::Peak {
+            lead: lead.unwrap_or(0),
+        },
+        _ => {
+            return Err(
+                params.invalid("align must be \"start\" or \"peak\" (lead requires \"peak\")")
+            )
+        }
+    };
+    Ok(Stage::Trim { length, alignment })
+}
+
+fn parse_downsample(params: &StageParams) -> Result<Stage> {
+    let resolution = |key| {
+        params
+            .number(key)?
+            .map(|r| r as f32)
+            .ok_or_else(|| params.missing(key))
+    };
+    let method = match params.string("method")? {
+        None | Some("nearest") => Downsampling::Nearest,
+        Some("average") => Downsampling::Average,
+        Some(_) => return Err(params.invalid("method must be \"nearest\" or \"average\"")),
+    };
+    Ok(Stage::Downsample {
+        alpha_resolution: resolution("alpha_resolution")?,
+        beta_resolution: resolution("beta_resolution")?,
+        method,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ring;
+
+    const PIPELINE: &str = r#"
+        inputs = ["measurements", "single.daff"]
+        output = "out"
+        format = "csv"
+        precision = 3
+        magnitude_floor = -100
+        flag_underflows = true
+        jobs = 2
+        cache = "cache"
+
+        [[stage]]
+        kind = "trim"
+        length = 256
+        align = "peak"
+        lead = 16
+
+        [[stage]]
+        kind = "resample"
+        samplerate = 48000
+
+        [[stage]]
+        kind = "normalize"
+        peak = -1.5
+
+        [[stage]]
+        kind = "downsample"
+        alpha_resolution = 10
+        beta_resolution = 7.5
+
+        [[stage]]
+        kind = "magnitude"
+        bands = "octave"
+
+        [[stage]]
+        kind = "reference"
+        azimuth = 90
+    "#;
+
+    #[test]
+    fn test_batch_from_toml() {
+        let batch = Batch::from_toml(PIPELINE).unwrap();
+        assert_eq!(
+            batch.inputs,
+            [PathBuf::from("measurements"), PathBuf::from("single.daff")]
+        );
+        assert_eq!(batch.format, OutputFormat::Csv);
+        assert_eq!(batch.export.precision, 3);
+        assert_eq!(batch.export.magnitude_floor_db, Some(-100.0));
+        assert!(batch.export.flag_underflows);
+        assert_eq!(batch.jobs, 2);
+        assert_eq!(batch.cache, Some(PathBuf::from("cache")));
+        let stages: Vec<String> = batch.pipeline.stages().map(|s| s.to_string()).collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::Trim {
+                    length: 256,
+                    alignment: Alignment::Peak { lead: 16 }
+                }
+                .to_string(),
+                Stage::Resample {
+                    samplerate: 48000.0
+                }
+                .to_string(),
+                Stage::Normalize { peak_db: -1.5 }.to_string(),
+                Stage::Downsample {
+                    alpha_resolution: 10.0,
+                    beta_resolution: 7.5,
+                    method: Downsampling::Nearest
+                }
+                .to_string(),
+                Stage::Magnitude {
+                    support: FrequencySupport::Octave
+                }
+                .to_string(),
+                Stage::Reference {
+                    azimuth: 90.0,
+                    elevation: 0.0
+                }
+                .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_from_toml_rejects_mistakes() {
+        let base = "inputs = [\"a.daff\"]\noutput = \"out\"\n";
+        assert!(Batch::from_toml(base).is_ok());
+        assert!(Batch::from_toml("output = \"out\"").is_err());
+        assert!(Batch::from_toml(&format!("{}format = \"sofa\"", base)).is_err());
+        assert!(Batch::from_toml(&format!("{}[[stage]]\nkind = \"smooth\"", base)).is_err());
+        assert!(Batch::from_toml(&format!("{}[[stage]]\nkind = \"resample\"", base)).is_err());
+        assert!(Batch::from_toml(&format!(
+            "{}[[stage]]\nkind = \"trim\"\nlength = 8\nlead = 2",
+            base
+        ))
+        .is_err());
+        assert!(Batch::from_toml(&format!(
+            "{}[[stage]]\nkind = \"normalize\"\npeak = 0\ngain = 1",
+            base
+        ))
+        .is_err());
+        assert!(Batch::from_toml(&format!(
+            "{}[[stage]]\nkind = \"downsample\"\nalpha_resolution = 5\nbeta_resolution = 5\nmethod = \"median\"",
+            base
+        ))
+        .is_err());
+    }
+
+    struct Gain(f32);
+
+    impl fmt::Display for Gain {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "gain {}", self.0)
+        }
+    }
+
+    impl PipelineStage for Gain {
+        fn apply(&self, dataset: &mut Dataset) -> Result<()> {
+            for record in &mut dataset.records {
+                record
+                    .channels
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|x| *x *= self.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_stage() {
+        let registry = StageRegistry::default().register("gain", |params| {
+            let factor = params.number("factor")?.unwrap_or(1.0);
+            Ok(Box::new(Gain(factor as f32)))
+        });
+        assert_eq!(
+            registry.kinds().collect::<Vec<_>>(),
+            [
+                "downsample",
+                "gain",
+                "magnitude",
+                "normalize",
+                "reference",
+                "resample",
+                "trim"
+            ]
+        );
+
+        let base = "inputs = [\"a.daff\"]\noutput = \"out\"\n";
+        let batch = Batch::from_toml_with(
+            &format!("{}[[stage]]\nkind = \"gain\"\nfactor = 2", base),
+            &registry,
+        )
+        .unwrap();
+        assert_eq!(format!("{:?}", batch.pipeline), "[\"gain 2\"]");
+        assert!(Batch::from_toml(&format!("{}[[stage]]\nkind = \"gain\"", base)).is_err());
+        assert!(Batch::from_toml_with(
+            &format!("{}[[stage]]\nkind = \"gain\"\nfactr = 2", base),
+            &registry
+        )
+        .is_err());
+
+        let pipeline = Pipeline::new()
+            .stage(Gain(0.5))
+            .stage(Stage::Normalize { peak_db: 0.0 });
+        let mut dataset = Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            ring(2),
+            1,
+            |alpha, _, _| vec![alpha / 360.0, 0.0],
+        );
+        pipeline.run(&mut dataset).unwrap();
+        assert_eq!(pipeline.len(), 2);
+        assert_eq!(dataset.records[1].channels[0], [1.0, 0.0]);
+        assert_eq!(
+            dataset.metadata["PROCESSING STEP 1"],
+            crate::MetadataValue::String(format!(
+                "opendaff {}: gain 0.5",
+                env!("CARGO_PKG_VERSION")
+            ))
+        );
+        assert!(!dataset.metadata.contains_key("PROCESSING HASH"));
+    }
+
+    #[test]
+    fn test_reproducibility_hash() {
+        let input = Dataset::from_fn(
+            crate::ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            ring(4),
+            2,
+            |alpha, _, c| vec![0.0, alpha / 360.0, c as f32],
+        );
+        let pipeline = |peak_db| {
+            Pipeline::new()
+                .stage(Stage::Normalize { peak_db })
+                .reproducibility_hash(true)
+        };
+        let process = |pipeline: Pipeline| {
+            let mut dataset = input.clone();
+            pipeline.run(&mut dataset).unwrap();
+            dataset.metadata["PROCESSING HASH"].clone()
+        };
+
+        let hash = process(pipeline(-1.0));
+        assert_eq!(process(pipeline(-1.0)), hash);
+        assert_ne!(process(pipeline(-2.0)), hash);
+    }
+}
@@ -0,0 +1,416 @@
+//! Memory-budgeted pool of datasets
+//!
+//! Applications juggling many DAFF files, e.g. the directivities of every instrument of an
+//! orchestra simulation, rarely need all of them at once. A [`DatasetPool`] knows the files by
+//! name and loads a dataset on first use. While the datasets kept in memory exceed the
+//! pool's budget, the least recently used ones are evicted and reloaded the next time they
+//! are requested. Datasets that must never be reloaded, e.g. those audible right now, can be
+//! [pinned](DatasetPool::pin).
+//!
+//! ```no_run
+//! use opendaff::pool::DatasetPool;
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! let pool = DatasetPool::new(512 << 20);
+//! pool.register("violin", "directivities/violin.daff")?;
+//! pool.register("trumpet", "directivities/trumpet.daff")?;
+//! pool.pin("violin", true)?;
+//!
+//! let trumpet = pool.get("trumpet")?; // loaded now, resident until evicted
+//! println!("{} records", trumpet.num_records());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The pool is shared by reference between threads. Files are loaded outside its lock, so
+//! other threads keep being served while a dataset loads.
+
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{ContentHeader, Dataset, Error, Reader, Result};
+
+/// Estimated memory used by a dataset in bytes
+///
+/// Counts the record data and the bookkeeping of records, channels and the content header;
+/// metadata is negligible in comparison and not counted.
+pub fn memory_size(dataset: &Dataset) -> usize {
+    let header = match &dataset.header {
+        ContentHeader::MagnitudeSpectrum { frequencies }
+        | ContentHeader::PhaseSpectrum { frequencies }
+        | ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+            frequencies.len() * mem::size_of::<f32>()
+        }
+        ContentHeader::ImpulseResponse { .. } | ContentHeader::DftSpectrum { .. } => 0,
+    };
+    let records: usize = dataset
+        .records
+        .iter()
+        .map(|record| {
+            mem::size_of_val(record)
+                + record
+                    .channels
+                    .iter()
+                    .map(|channel| mem::size_of_val(channel) + mem::size_of_val(&channel[..]))
+                    .sum::<usize>()
+        })
+        .sum();
+    mem::size_of::<Dataset>() + header + records
+}
+
+/// Counters of a [`DatasetPool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Requests served from memory
+    pub hits: u64,
+    /// Files loaded, including concurrent loads of the same dataset by several threads
+    pub loads: u64,
+    /// Datasets evicted to stay within the budget
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    dataset: Option<Arc<Dataset>>,
+    /// Estimated size of the resident dataset, 0 if it is not resident
+    size: usize,
+    /// Value of the use counter at the last request
+    last_used: u64,
+    pinned: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    resident: usize,
+    /// Counts requests, orders entries by recency
+    clock: u64,
+    stats: PoolStats,
+}
+
+/// Named datasets loaded on demand within a memory budget, see the
+/// [module documentation](self)
+#[derive(Debug)]
+pub struct DatasetPool {
+    budget: Mutex<usize>,
+    state: Mutex<State>,
+}
+
+impl DatasetPool {
+    /// Create an empty pool keeping at most about `budget` bytes of datasets in memory
+    ///
+    /// Sizes are estimated with [`memory_size`].
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget: Mutex::new(budget),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Memory budget in bytes
+    pub fn budget(&self) -> usize {
+        *self.budget.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Change the memory budget, evicting datasets until the resident ones fit
+    pub fn set_budget(&self, budget: usize) {
+        *self.budget.lock().unwrap_or_else(|e| e.into_inner()) = budget;
+        let mut state = self.state();
+        self.evict_to_budget(&mut state, None);
+    }
+
+    /// Make a DAFF file available under a name, without loading it
+    ///
+    /// Registering a name again points it to the new file and drops the dataset loaded from
+    /// the old one. Fails if the file does not exist.
+    pub fn register(&self, name: &str, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(Error::new(format!(
+                "'{}' does not exist or is not a file",
+                path.display()
+            )));
+        }
+        let mut state = self.state();
+        let pinned = state.entries.get(name).is_some_and(|entry| entry.pinned);
+        let previous = state.entries.insert(
+            name.to_string(),
+            Entry {
+                path: path.to_path_buf(),
+                dataset: None,
+                size: 0,
+                last_used: 0,
+                pinned,
+            },
+        );
+        state.resident -= previous.map_or(0, |entry| entry.size);
+        Ok(())
+    }
+
+    /// Forget a name and drop its dataset; returns whether the name was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut state = self.state();
+        match state.entries.remove(name) {
+            Some(entry) => {
+                state.resident -= entry.size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Registered names, in no particular order
+    pub fn names(&self) -> Vec<String> {
+        self.state().entries.keys().cloned().collect()
+    }
+
+    /// The dataset registered under a name, loading it if it is not resident
+    ///
+    /// Loading may evict the least recently used datasets that are not pinned. The requested
+    /// dataset itself stays resident even if it alone exceeds the budget. Evicted datasets
+    /// are only freed once the last handle returned by this method is dropped.
+    pub fn get(&self, name: &str) -> Result<Arc<Dataset>> {
+        let path = {
+            let mut state = self.state();
+            state.clock += 1;
+            let clock = state.clock;
+            let entry = entry_mut(&mut state, name)?;
+            entry.last_used = clock;
+            if let Some(dataset) = &entry.dataset {
+                let dataset = Arc::clone(dataset);
+                state.stats.hits += 1;
+                return Ok(dataset);
+            }
+            entry.path.clone()
+        };
+
+        let dataset = Arc::new(load(&path)?);
+        let size = memory_size(&dataset);
+
+        let mut state = self.state();
+        state.stats.loads += 1;
+        let Some(entry) = state
+            .entries
+            .get_mut(name)
+            .filter(|entry| entry.path == path)
+        else {
+            // Unregistered or pointed to another file in the meantime
+            return Ok(dataset);
+        };
+        if let Some(resident) = &entry.dataset {
+            // Another thread loaded it in the meantime
+            return Ok(Arc::clone(resident));
+        }
+        entry.dataset = Some(Arc::clone(&dataset));
+        entry.size = size;
+        state.resident += size;
+        self.evict_to_budget(&mut state, Some(name));
+        Ok(dataset)
+    }
+
+    /// Keep a dataset resident (once loaded) regardless of the budget, or release it again
+    pub fn pin(&self, name: &str, pinned: bool) -> Result<()> {
+        let mut state = self.state();
+        entry_mut(&mut state, name)?.pinned = pinned;
+        if !pinned {
+            self.evict_to_budget(&mut state, None);
+        }
+        Ok(())
+    }
+
+    /// Drop a resident dataset now; returns whether it was resident
+    ///
+    /// Pinned datasets are evicted as well, and stay pinned for their next load.
+    pub fn evict(&self, name: &str) -> Result<bool> {
+        let mut state = self.state();
+        let entry = entry_mut(&mut state, name)?;
+        let size = mem::take(&mut entry.size);
+        let resident = entry.dataset.take().is_some();
+        state.resident -= size;
+        Ok(resident)
+    }
+
+    /// Whether the dataset of a name is currently in memory
+    pub fn is_resident(&self, name: &str) -> bool {
+        self.state()
+            .entries
+            .get(name)
+            .is_some_and(|entry| entry.dataset.is_some())
+    }
+
+    /// Estimated memory used by the resident datasets in bytes
+    pub fn resident_bytes(&self) -> usize {
+        self.state().resident
+    }
+
+    /// Hits, loads and evictions so far
+    pub fn stats(&self) -> PoolStats {
+        self.state().stats
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Evict unpinned datasets, least recently used first, until the resident ones fit the
+    /// budget; `keep` is never evicted
+    fn evict_to_budget(&self, state: &mut State, keep: Option<&str>) {
+        let budget = self.budget();
+        while state.resident > budget {
+            let victim = state
+                .entries
+                .iter()
+                .filter(|(name, entry)| {
+                    entry.dataset.is_some() && !entry.pinned && Some(name.as_str()) != keep
+                })
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone());
+            let Some(victim) = victim else {
+                break;
+            };
+            let entry = state
+                .entries
+                .get_mut(&victim)
+                .expect("victim is registered");
+            entry.dataset = None;
+            state.resident -= mem::take(&mut entry.size);
+            state.stats.evictions += 1;
+        }
+    }
+}
+
+fn entry_mut<'a>(state: &'a mut State, name: &str) -> Result<&'a mut Entry> {
+    state
+        .entries
+        .get_mut(name)
+        .ok_or_else(|| Error::new(format!("No dataset registered as '{}'", name)))
+}
+
+fn load(path: &Path) -> Result<Dataset> {
+    let reader = Reader::open(path)?;
+    Dataset::from_reader(&reader).map_err(|e| match e {
+        Error::Message(m) => Error::new(format!("{}: {}", path.display(), m)),
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::writer;
+    use crate::EquiangularGrid;
+
+    fn dataset(length: usize) -> Dataset {
+        Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |_, _, _| vec![0.5; length],
+        )
+    }
+
+    #[test]
+    fn test_memory_size() {
+        let short = memory_size(&dataset(16));
+        let long = memory_size(&dataset(32));
+        let values = dataset(16).num_records() * 2 * 16;
+        assert_eq!(long - short, values * mem::size_of::<f32>());
+        assert!(short > values * mem::size_of::<f32>());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_pool_evicts_least_recently_used() {
+        let paths: Vec<PathBuf> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let path = temp_path(&format!("pool-{}.daff", name));
+                writer::write_dataset(&path, &dataset(64)).unwrap();
+                path
+            })
+            .collect();
+        let size = memory_size(&dataset(64));
+
+        // Room for two datasets
+        let pool = DatasetPool::new(2 * size + size / 2);
+        for (name, path) in ["a", "b", "c"].iter().zip(&paths) {
+            pool.register(name, path).unwrap();
+        }
+        assert!(pool.register("d", "missing.daff").is_err());
+        assert!(pool.get("d").is_err());
+        assert_eq!(pool.resident_bytes(), 0);
+
+        let a = pool.get("a").unwrap();
+        assert_eq!(*a, dataset(64));
+        pool.get("b").unwrap();
+        assert!(Arc::ptr_eq(&a, &pool.get("a").unwrap()));
+        assert_eq!(pool.resident_bytes(), 2 * size);
+
+        // "b" is the least recently used
+        pool.get("c").unwrap();
+        assert!(pool.is_resident("a") && !pool.is_resident("b") && pool.is_resident("c"));
+        assert_eq!(pool.resident_bytes(), 2 * size);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 1,
+                loads: 3,
+                evictions: 1
+            }
+        );
+
+        // Pinned datasets stay, the others make room
+        pool.pin("c", true).unwrap();
+        pool.get("b").unwrap();
+        pool.get("a").unwrap();
+        assert!(pool.is_resident("a") && !pool.is_resident("b") && pool.is_resident("c"));
+        pool.set_budget(size);
+        assert!(!pool.is_resident("a") && pool.is_resident("c"));
+        pool.pin("c", false).unwrap();
+        assert!(pool.is_resident("c"));
+
+        // A dataset larger than the budget is still served
+        pool.set_budget(0);
+        assert_eq!(pool.resident_bytes(), 0);
+        assert_eq!(pool.get("b").unwrap().num_records(), a.num_records());
+        assert!(pool.is_resident("b"));
+        assert!(pool.evict("b").unwrap());
+        assert!(!pool.evict("b").unwrap());
+        assert!(pool.unregister("a"));
+        assert!(!pool.unregister("a"));
+        assert_eq!(pool.resident_bytes(), 0);
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_pool_shared_between_threads() {
+        let path = temp_path("pool-shared.daff");
+        writer::write_dataset(&path, &dataset(8)).unwrap();
+        let pool = DatasetPool::new(usize::MAX);
+        pool.register("shared", &path).unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        assert_eq!(pool.get("shared").unwrap().num_channels(), 2);
+                    }
+                });
+            }
+        });
+        let stats = pool.stats();
+        assert_eq!(stats.hits + stats.loads, 40);
+        assert!(stats.loads >= 1);
+        assert_eq!(pool.resident_bytes(), memory_size(&dataset(8)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,756 @@
+//! Protobuf messages describing datasets
+//!
+//! With the `protobuf` feature, distributed systems, e.g. the nodes of a room acoustic
+//! simulation, can exchange what a dataset contains without shipping the file: its
+//! [`FileProperties`] (content type, quantization, header, grid, orientation), metadata and
+//! the directions of its records. The messages mirror the schema in `proto/opendaff.proto`,
+//! so other languages generate compatible code from it, and implement [`prost::Message`] for
+//! encoding and decoding:
+//!
+//! ```no_run
+//! use opendaff::proto::{DatasetDescription, Message};
+//! use opendaff::Reader;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let reader = Reader::open("hrir.daff")?;
+//! let bytes = DatasetDescription::from_reader(&reader)?.encode_to_vec();
+//!
+//! // On the receiving side
+//! let description = DatasetDescription::decode(bytes.as_slice())?;
+//! let properties = description.properties.as_ref().expect("properties are always sent");
+//! println!("{} on a {}", properties.to_header()?.content_type(), properties.to_grid()?);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use prost::Message;
+
+use crate::metadata::{self, Metadata};
+use crate::{ContentHeader, Dataset, Error, MetadataValue, Reader, Result};
+
+/// Content type, numbered like [`crate::ContentType`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ContentType {
+    /// Impulse response
+    ImpulseResponse = 0,
+    /// Magnitude spectrum
+    MagnitudeSpectrum = 1,
+    /// Phase spectrum
+    PhaseSpectrum = 2,
+    /// Magnitude-phase spectrum
+    MagnitudePhaseSpectrum = 3,
+    /// DFT coefficients
+    DftSpectrum = 4,
+}
+
+impl From<crate::ContentType> for ContentType {
+    fn from(content_type: crate::ContentType) -> Self {
+        match content_type {
+            crate::ContentType::ImpulseResponse => ContentType::ImpulseResponse,
+            crate::ContentType::MagnitudeSpectrum => ContentType::MagnitudeSpectrum,
+            crate::ContentType::PhaseSpectrum => ContentType::PhaseSpectrum,
+            crate::ContentType::MagnitudePhaseSpectrum => ContentType::MagnitudePhaseSpectrum,
+            crate::ContentType::DftSpectrum => ContentType::DftSpectrum,
+        }
+    }
+}
+
+impl From<ContentType> for crate::ContentType {
+    fn from(content_type: ContentType) -> Self {
+        match content_type {
+            ContentType::ImpulseResponse => crate::ContentType::ImpulseResponse,
+            ContentType::MagnitudeSpectrum => crate::ContentType::MagnitudeSpectrum,
+            ContentType::PhaseSpectrum => crate::ContentType::PhaseSpectrum,
+            ContentType::MagnitudePhaseSpectrum => crate::ContentType::MagnitudePhaseSpectrum,
+            ContentType::DftSpectrum => crate::ContentType::DftSpectrum,
+        }
+    }
+}
+
+/// Quantization, numbered like [`crate::Quantization`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Quantization {
+    /// 16-bit signed integer
+    Int16 = 0,
+    /// 24-bit signed integer
+    Int24 = 1,
+    /// 32-bit float
+    Float32 = 2,
+}
+
+impl From<crate::Quantization> for Quantization {
+    fn from(quantization: crate::Quantization) -> Self {
+        match quantization {
+            crate::Quantization::Int16 => Quantization::Int16,
+            crate::Quantization::Int24 => Quantization::Int24,
+            crate::Quantization::Float32 => Quantization::Float32,
+        }
+    }
+}
+
+impl From<Quantization> for crate::Quantization {
+    fn from(quantization: Quantization) -> Self {
+        match quantization {
+            Quantization::Int16 => crate::Quantization::Int16,
+            Quantization::Int24 => crate::Quantization::Int24,
+            Quantization::Float32 => crate::Quantization::Float32,
+        }
+    }
+}
+
+/// Header of impulse response content
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ImpulseResponseHeader {
+    /// Sample rate in Hz
+    #[prost(double, tag = "1")]
+    pub samplerate: f64,
+}
+
+/// Header of magnitude, phase and magnitude-phase spectra
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SpectrumHeader {
+    /// Support frequencies in Hz
+    #[prost(float, repeated, tag = "1")]
+    pub frequencies: Vec<f32>,
+}
+
+/// Header of DFT content
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DftHeader {
+    /// Sample rate in Hz
+    #[prost(double, tag = "1")]
+    pub samplerate: f64,
+    /// DFT transform size
+    #[prost(uint32, tag = "2")]
+    pub transform_size: u32,
+}
+
+/// Regular grid in the data view, see [`crate::EquiangularGrid`]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EquiangularGrid {
+    /// Number of points in alpha direction
+    #[prost(uint32, tag = "1")]
+    pub alpha_points: u32,
+    /// First alpha angle
+    #[prost(float, tag = "2")]
+    pub alpha_start: f32,
+    /// Last alpha angle
+    #[prost(float, tag = "3")]
+    pub alpha_end: f32,
+    /// Number of points in beta direction (including the poles)
+    #[prost(uint32, tag = "4")]
+    pub beta_points: u32,
+    /// First beta angle (0° = south pole)
+    #[prost(float, tag = "5")]
+    pub beta_start: f32,
+    /// Last beta angle (180° = north pole)
+    #[prost(float, tag = "6")]
+    pub beta_end: f32,
+}
+
+impl From<&crate::EquiangularGrid> for EquiangularGrid {
+    fn from(grid: &crate::EquiangularGrid) -> Self {
+        Self {
+            alpha_points: grid.alpha_points as u32,
+            alpha_start: grid.alpha_start,
+            alpha_end: grid.alpha_end,
+            beta_points: grid.beta_points as u32,
+            beta_start: grid.beta_start,
+            beta_end: grid.beta_end,
+        }
+    }
+}
+
+impl From<&EquiangularGrid> for crate::EquiangularGrid {
+    fn from(grid: &EquiangularGrid) -> Self {
+        Self {
+            alpha_points: grid.alpha_points as usize,
+            alpha_start: grid.alpha_start,
+            alpha_end: grid.alpha_end,
+            beta_points: grid.beta_points as usize,
+            beta_start: grid.beta_start,
+            beta_end: grid.beta_end,
+        }
+    }
+}
+
+/// Layout whose directions are given by the record descriptors
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IrregularGrid {}
+
+/// Sampling layout, see [`crate::Grid`]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Grid {
+    /// The layout, `None` if the sender did not set it
+    #[prost(oneof = "grid::Layout", tags = "1, 2, 3, 4")]
+    pub layout: Option<grid::Layout>,
+}
+
+/// Nested types of [`Grid`]
+pub mod grid {
+    /// Layout of a [`Grid`](super::Grid)
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Layout {
+        /// Regular equiangular grid
+        #[prost(message, tag = "1")]
+        Equiangular(super::EquiangularGrid),
+        /// Gauss-Legendre grid of the given spherical harmonic order
+        #[prost(uint32, tag = "2")]
+        GaussLegendreOrder(u32),
+        /// Lebedev grid with the given number of points
+        #[prost(uint32, tag = "3")]
+        LebedevPoints(u32),
+        /// Arbitrary directions
+        #[prost(message, tag = "4")]
+        Irregular(super::IrregularGrid),
+    }
+}
+
+impl From<&crate::Grid> for Grid {
+    fn from(grid: &crate::Grid) -> Self {
+        let layout = match grid {
+            crate::Grid::Equiangular(grid) => grid::Layout::Equiangular(grid.into()),
+            crate::Grid::GaussLegendre { order } => grid::Layout::GaussLegendreOrder(*order as u32),
+            crate::Grid::Lebedev { points } => grid::Layout::LebedevPoints(*points as u32),
+            crate::Grid::Irregular => grid::Layout::Irregular(IrregularGrid {}),
+        };
+        Self {
+            layout: Some(layout),
+        }
+    }
+}
+
+impl TryFrom<&Grid> for crate::Grid {
+    type Error = Error;
+
+    /// Fails for a missing layout and unsupported Lebedev grids
+    fn try_from(grid: &Grid) -> Result<Self> {
+        match &grid.layout {
+            Some(grid::Layout::Equiangular(grid)) => Ok(crate::Grid::Equiangular(grid.into())),
+            Some(grid::Layout::GaussLegendreOrder(order)) => {
+                Ok(crate::Grid::gauss_legendre(*order as usize))
+            }
+            Some(grid::Layout::LebedevPoints(points)) => crate::Grid::lebedev(*points as usize),
+            Some(grid::Layout::Irregular(_)) => Ok(crate::Grid::Irregular),
+            None => Err(Error::new("Grid message without a layout")),
+        }
+    }
+}
+
+/// Orientation in yaw-pitch-roll (degrees)
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Orientation {
+    /// Yaw angle in degrees
+    #[prost(float, tag = "1")]
+    pub yaw: f32,
+    /// Pitch angle in degrees
+    #[prost(float, tag = "2")]
+    pub pitch: f32,
+    /// Roll angle in degrees
+    #[prost(float, tag = "3")]
+    pub roll: f32,
+}
+
+impl From<crate::Orientation> for Orientation {
+    fn from(orientation: crate::Orientation) -> Self {
+        Self {
+            yaw: orientation.yaw,
+            pitch: orientation.pitch,
+            roll: orientation.roll,
+        }
+    }
+}
+
+impl From<&Orientation> for crate::Orientation {
+    fn from(orientation: &Orientation) -> Self {
+        Self {
+            yaw: orientation.yaw,
+            pitch: orientation.pitch,
+            roll: orientation.roll,
+        }
+    }
+}
+
+/// Properties of a DAFF file that do not depend on its records' data
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileProperties {
+    /// Content type
+    #[prost(enumeration = "ContentType", tag = "1")]
+    pub content_type: i32,
+    /// Quantization of the stored data
+    #[prost(enumeration = "Quantization", tag = "2")]
+    pub quantization: i32,
+    /// Number of channels
+    #[prost(uint32, tag = "3")]
+    pub num_channels: u32,
+    /// Number of records
+    #[prost(uint32, tag = "4")]
+    pub num_records: u32,
+    /// Stored values per record and channel; real and imaginary parts count separately
+    #[prost(uint32, tag = "5")]
+    pub elements_per_record: u32,
+    /// Content header
+    #[prost(oneof = "file_properties::Header", tags = "6, 7, 8")]
+    pub header: Option<file_properties::Header>,
+    /// Sampling grid
+    #[prost(message, optional, tag = "9")]
+    pub grid: Option<Grid>,
+    /// Orientation
+    #[prost(message, optional, tag = "10")]
+    pub orientation: Option<Orientation>,
+}
+
+/// Nested types of [`FileProperties`]
+pub mod file_properties {
+    /// Content header of [`FileProperties`](super::FileProperties)
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Header {
+        /// Impulse responses
+        #[prost(message, tag = "6")]
+        ImpulseResponse(super::ImpulseResponseHeader),
+        /// Magnitude, phase and magnitude-phase spectra
+        #[prost(message, tag = "7")]
+        Spectrum(super::SpectrumHeader),
+        /// DFT coefficients
+        #[prost(message, tag = "8")]
+        Dft(super::DftHeader),
+    }
+}
+
+impl From<&ContentHeader> for file_properties::Header {
+    fn from(header: &ContentHeader) -> Self {
+        use file_properties::Header;
+        match header {
+            ContentHeader::ImpulseResponse { samplerate } => {
+                Header::ImpulseResponse(ImpulseResponseHeader {
+                    samplerate: *samplerate,
+                })
+            }
+            ContentHeader::MagnitudeSpectrum { frequencies }
+            | ContentHeader::PhaseSpectrum { frequencies }
+            | ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+                Header::Spectrum(SpectrumHeader {
+                    frequencies: frequencies.clone(),
+                })
+            }
+            ContentHeader::DftSpectrum {
+                samplerate,
+                transform_size,
+            } => Header::Dft(DftHeader {
+                samplerate: *samplerate,
+                transform_size: *transform_size as u32,
+            }),
+        }
+    }
+}
+
+impl FileProperties {
+    /// Content type, fails for values unknown to this version
+    pub fn to_content_type(&self) -> Result<crate::ContentType> {
+        ContentType::try_from(self.content_type)
+            .map(Into::into)
+            .map_err(|_| Error::new(format!("Unknown content type {}", self.content_type)))
+    }
+
+    /// Quantization, fails for values unknown to this version
+    pub fn to_quantization(&self) -> Result<crate::Quantization> {
+        Quantization::try_from(self.quantization)
+            .map(Into::into)
+            .map_err(|_| Error::new(format!("Unknown quantization {}", self.quantization)))
+    }
+
+    /// Content header, fails if it is missing or does not fit the content type
+    pub fn to_header(&self) -> Result<ContentHeader> {
+        use file_properties::Header;
+        let content_type = self.to_content_type()?;
+        let header = match (content_type, &self.header) {
+            (crate::ContentType::ImpulseResponse, Some(Header::ImpulseResponse(header))) => {
+                ContentHeader::ImpulseResponse {
+                    samplerate: header.samplerate,
+                }
+            }
+            (crate::ContentType::MagnitudeSpectrum, Some(Header::Spectrum(header))) => {
+                ContentHeader::MagnitudeSpectrum {
+                    frequencies: header.frequencies.clone(),
+                }
+            }
+            (crate::ContentType::PhaseSpectrum, Some(Header::Spectrum(header))) => {
+                ContentHeader::PhaseSpectrum {
+                    frequencies: header.frequencies.clone(),
+                }
+            }
+            (crate::ContentType::MagnitudePhaseSpectrum, Some(Header::Spectrum(header))) => {
+                ContentHeader::MagnitudePhaseSpectrum {
+                    frequencies: header.frequencies.clone(),
+                }
+            }
+            (crate::ContentType::DftSpectrum, Some(Header::Dft(header))) => {
+                ContentHeader::DftSpectrum {
+                    samplerate: header.samplerate,
+                    transform_size: header.transform_size as usize,
+                }
+            }
+            (content_type, _) => {
+                return Err(Error::new(format!(
+                    "File properties lack the header of {} content",
+                    content_type
+                )))
+            }
+        };
+        Ok(header)
+    }
+
+    /// Sampling grid, fails if it is missing or invalid
+    pub fn to_grid(&self) -> Result<crate::Grid> {
+        self.grid
+            .as_ref()
+            .ok_or_else(|| Error::new("File properties lack the grid"))?
+            .try_into()
+    }
+
+    /// Orientation, the default orientation if it is missing
+    pub fn to_orientation(&self) -> crate::Orientation {
+        self.orientation
+            .as_ref()
+            .map(Into::into)
+            .unwrap_or_default()
+    }
+}
+
+/// Metadata key and value
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetadataEntry {
+    /// Key
+    #[prost(string, tag = "1")]
+    pub key: String,
+    /// Value, `None` if the sender did not set it
+    #[prost(oneof = "metadata_entry::Value", tags = "2, 3, 4, 5")]
+    pub value: Option<metadata_entry::Value>,
+}
+
+/// Nested types of [`MetadataEntry`]
+pub mod metadata_entry {
+    /// Value of a [`MetadataEntry`](super::MetadataEntry)
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        /// Boolean
+        #[prost(bool, tag = "2")]
+        BoolValue(bool),
+        /// Integer number
+        #[prost(int32, tag = "3")]
+        IntValue(i32),
+        /// Floating-point number
+        #[prost(double, tag = "4")]
+        FloatValue(f64),
+        /// String
+        #[prost(string, tag = "5")]
+        StringValue(String),
+    }
+}
+
+impl MetadataEntry {
+    /// Entry for the given key and value
+    pub fn new(key: &str, value: &MetadataValue) -> Self {
+        use metadata_entry::Value;
+        let value = match value {
+            MetadataValue::Bool(b) => Value::BoolValue(*b),
+            MetadataValue::Int(i) => Value::IntValue(*i),
+            MetadataValue::Float(f) => Value::FloatValue(*f),
+            MetadataValue::String(s) => Value::StringValue(s.clone()),
+        };
+        Self {
+            key: key.to_string(),
+            value: Some(value),
+        }
+    }
+
+    /// The value, fails if it is missing
+    pub fn to_value(&self) -> Result<MetadataValue> {
+        use metadata_entry::Value;
+        match &self.value {
+            Some(Value::BoolValue(b)) => Ok(MetadataValue::Bool(*b)),
+            Some(Value::IntValue(i)) => Ok(MetadataValue::Int(*i)),
+            Some(Value::FloatValue(f)) => Ok(MetadataValue::Float(*f)),
+            Some(Value::StringValue(s)) => Ok(MetadataValue::String(s.clone())),
+            None => Err(Error::invalid_metadata(&self.key, "no value")),
+        }
+    }
+}
+
+/// Direction of a record in the data view (degrees)
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecordDescriptor {
+    /// Record index in storage order
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    /// Alpha angle in degrees
+    #[prost(float, tag = "2")]
+    pub alpha: f32,
+    /// Beta angle in degrees (0° = south pole)
+    #[prost(float, tag = "3")]
+    pub beta: f32,
+}
+
+/// File properties, metadata and record directions of a dataset
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DatasetDescription {
+    /// File properties
+    #[prost(message, optional, tag = "1")]
+    pub properties: Option<FileProperties>,
+    /// Metadata entries
+    #[prost(message, repeated, tag = "2")]
+    pub metadata: Vec<MetadataEntry>,
+    /// Records in storage order
+    #[prost(message, repeated, tag = "3")]
+    pub records: Vec<RecordDescriptor>,
+}
+
+impl From<&Dataset> for DatasetDescription {
+    fn from(dataset: &Dataset) -> Self {
+        let properties = FileProperties {
+            content_type: ContentType::from(dataset.content_type()) as i32,
+            quantization: Quantization::from(dataset.quantization) as i32,
+            num_channels: dataset.num_channels() as u32,
+            num_records: dataset.num_records() as u32,
+            elements_per_record: dataset.elements_per_record() as u32,
+            header: Some((&dataset.header).into()),
+            grid: Some((&dataset.grid).into()),
+            orientation: Some(dataset.orientation.into()),
+        };
+        let records = dataset.records.iter().map(|r| (r.alpha, r.beta));
+        Self::new(properties, &dataset.metadata, records)
+    }
+}
+
+impl DatasetDescription {
+    fn new(
+        properties: FileProperties,
+        metadata: &Metadata,
+        directions: impl Iterator<Item = (f32, f32)>,
+    ) -> Self {
+        Self {
+            properties: Some(properties),
+            metadata: metadata
+                .iter()
+                .map(|(key, value)| MetadataEntry::new(key, value))
+                .collect(),
+            records: directions
+                .enumerate()
+                .map(|(index, (alpha, beta))| RecordDescriptor {
+                    index: index as u32,
+                    alpha,
+                    beta,
+                })
+                .collect(),
+        }
+    }
+
+    /// Describe the file currently opened by `reader` without decoding its records
+    pub fn from_reader(reader: &Reader) -> Result<Self> {
+        if !reader.is_valid() {
+            return Err(Error::Closed);
+        }
+
+        let num_records = reader.num_records().max(0);
+        let mut directions = Vec::with_capacity(num_records as usize);
+        let mut push = |coords: Result<(f64, f64)>| -> Result<()> {
+            let (alpha, beta) = coords?;
+            directions.push((alpha as f32, beta as f32));
+            Ok(())
+        };
+        let (header, elements_per_record) = match reader.content_type() {
+            crate::ContentType::ImpulseResponse => {
+                let ir = reader.content_ir()?;
+                for r in 0..num_records {
+                    push(ir.record_coords(r))?;
+                }
+                let header = ContentHeader::ImpulseResponse {
+                    samplerate: ir.samplerate() as f64,
+                };
+                (header, ir.filter_length())
+            }
+            crate::ContentType::MagnitudeSpectrum => {
+                let ms = reader.content_ms()?;
+                for r in 0..num_records {
+                    push(ms.record_coords(r))?;
+                }
+                let header = ContentHeader::MagnitudeSpectrum {
+                    frequencies: ms.frequencies()?,
+                };
+                (header, ms.num_frequencies())
+            }
+            crate::ContentType::PhaseSpectrum => {
+                let ps = reader.content_ps()?;
+                for r in 0..num_records {
+                    push(ps.record_coords(r))?;
+                }
+                let header = ContentHeader::PhaseSpectrum {
+                    frequencies: ps.frequencies()?,
+                };
+                (header, ps.num_frequencies())
+            }
+            crate::ContentType::MagnitudePhaseSpectrum => {
+                let mps = reader.content_mps()?;
+                for r in 0..num_records {
+                    push(mps.record_coords(r))?;
+                }
+                let header = ContentHeader::MagnitudePhaseSpectrum {
+                    frequencies: mps.frequencies()?,
+                };
+                (header, 2 * mps.num_frequencies())
+            }
+            crate::ContentType::DftSpectrum => {
+                let dft = reader.content_dft()?;
+                for r in 0..num_records {
+                    push(dft.record_coords(r))?;
+                }
+                let header = ContentHeader::DftSpectrum {
+                    samplerate: dft.samplerate(),
+                    transform_size: dft.transform_size().max(0) as usize,
+                };
+                (header, 2 * dft.num_dft_coeffs())
+            }
+        };
+
+        let quantization = reader
+            .quantization()
+            .ok_or_else(|| Error::new("Unknown quantization"))?;
+        let properties = FileProperties {
+            content_type: ContentType::from(reader.content_type()) as i32,
+            quantization: Quantization::from(quantization) as i32,
+            num_channels: reader.num_channels().max(0) as u32,
+            num_records: num_records as u32,
+            elements_per_record: elements_per_record.max(0) as u32,
+            header: Some((&header).into()),
+            grid: Some((&crate::Grid::from(reader.grid()?)).into()),
+            orientation: Some(reader.orientation()?.into()),
+        };
+        Ok(Self::new(
+            properties,
+            &metadata::read_all(reader)?,
+            directions.into_iter(),
+        ))
+    }
+
+    /// Metadata as a map, fails for entries without a value or with invalid keys
+    pub fn to_metadata(&self) -> Result<Metadata> {
+        self.metadata
+            .iter()
+            .map(|entry| {
+                metadata::check_key(&entry.key)?;
+                Ok((entry.key.clone(), entry.to_value()?))
+            })
+            .collect()
+    }
+
+    /// Data view coordinates (alpha, beta) of the records in storage order
+    pub fn directions(&self) -> Vec<(f32, f32)> {
+        self.records.iter().map(|r| (r.alpha, r.beta)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::writer;
+    use crate::EquiangularGrid as DaffGrid;
+
+    fn dataset() -> Dataset {
+        let mut dataset = Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![125.0, 1000.0, 8000.0],
+            },
+            DaffGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |alpha, beta, channel| vec![1.0, alpha / 360.0, beta / 180.0 + channel as f32],
+        );
+        dataset.orientation = crate::Orientation {
+            yaw: 90.0,
+            pitch: 0.0,
+            roll: -10.0,
+        };
+        dataset.metadata.insert(
+            "DESCRIPTION".to_string(),
+            MetadataValue::String("Loudspeaker".to_string()),
+        );
+        dataset
+            .metadata
+            .insert("SUBJECT".to_string(), MetadataValue::Int(7));
+        dataset
+    }
+
+    fn assert_describes(description: &DatasetDescription, dataset: &Dataset) {
+        let properties = description.properties.as_ref().unwrap();
+        assert_eq!(
+            properties.to_content_type().unwrap(),
+            dataset.content_type()
+        );
+        assert_eq!(properties.to_quantization().unwrap(), dataset.quantization);
+        assert_eq!(properties.num_channels, 2);
+        assert_eq!(properties.num_records as usize, dataset.num_records());
+        assert_eq!(properties.elements_per_record, 3);
+        assert_eq!(properties.to_header().unwrap(), dataset.header);
+        assert_eq!(properties.to_grid().unwrap(), dataset.grid);
+        assert_eq!(properties.to_orientation(), dataset.orientation);
+        assert_eq!(description.to_metadata().unwrap(), dataset.metadata);
+        let directions: Vec<_> = dataset.records.iter().map(|r| (r.alpha, r.beta)).collect();
+        assert_eq!(description.directions(), directions);
+    }
+
+    #[test]
+    fn test_description_round_trip() {
+        let dataset = dataset();
+        let description = DatasetDescription::from(&dataset);
+        let decoded = DatasetDescription::decode(description.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, description);
+        assert_describes(&decoded, &dataset);
+
+        for grid in [
+            crate::Grid::gauss_legendre(3),
+            crate::Grid::lebedev(26).unwrap(),
+            crate::Grid::Irregular,
+        ] {
+            assert_eq!(crate::Grid::try_from(&Grid::from(&grid)).unwrap(), grid);
+        }
+        assert!(crate::Grid::try_from(&Grid { layout: None }).is_err());
+        assert!(crate::Grid::try_from(&Grid {
+            layout: Some(grid::Layout::LebedevPoints(7)),
+        })
+        .is_err());
+
+        let mut properties = description.properties.clone().unwrap();
+        properties.content_type = ContentType::ImpulseResponse as i32;
+        assert!(properties.to_header().is_err());
+        properties.content_type = 9;
+        assert!(properties.to_content_type().is_err());
+
+        let mut invalid = description.clone();
+        invalid.metadata[0].value = None;
+        assert!(matches!(
+            invalid.to_metadata(),
+            Err(Error::InvalidMetadata { .. })
+        ));
+        invalid.metadata[0] = MetadataEntry::new("", &MetadataValue::Bool(true));
+        assert!(invalid.to_metadata().is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_description_from_reader() {
+        let path = temp_path("proto_description.daff");
+        let dataset = dataset();
+        writer::write_dataset(&path, &dataset).unwrap();
+        let mut reader = Reader::open(&path).unwrap();
+        let description = DatasetDescription::from_reader(&reader).unwrap();
+        let loaded = Dataset::from_reader(&reader).unwrap();
+        assert_describes(&description, &loaded);
+        assert_eq!(description, DatasetDescription::from(&loaded));
+        reader.close();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(DatasetDescription::from_reader(&reader), Err(Error::Closed));
+    }
+}
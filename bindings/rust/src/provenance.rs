@@ -0,0 +1,521 @@
+//! License and provenance metadata
+//!
+//! DAFF files describe their origin with free-form metadata. This module defines the
+//! well-known keys ([`ProvenanceKey`]) and a typed view on them ([`Provenance`]), so tools
+//! agree on names and formats. Key names follow the style of the Matlab export scripts
+//! (words separated by spaces, e.g. `CREATION DATE`); keys used by older scripts are still
+//! recognized when reading.
+//!
+//! Processing applied by a [`Pipeline`](crate::pipeline::Pipeline) is recorded as numbered
+//! `PROCESSING STEP <n>` entries, each naming the crate version and the stage with its
+//! parameters, optionally followed by a [`reproducibility_hash`] of the result.
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::metadata::{Metadata, MetadataValue};
+use crate::{ContentHeader, Dataset, Error, Reader, Result};
+
+/// Prefix of the numbered processing step keys (`PROCESSING STEP 1`, `PROCESSING STEP 2`, ...)
+pub const PROCESSING_STEP_PREFIX: &str = "PROCESSING STEP ";
+
+/// Well-known provenance metadata keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProvenanceKey {
+    /// Free-text description of the content
+    Description,
+    /// License of the data, preferably an SPDX identifier (e.g. `CC-BY-4.0`)
+    License,
+    /// Digital object identifier of the published dataset
+    Doi,
+    /// Author(s) of the data
+    Author,
+    /// Institution that created the data
+    Institution,
+    /// Creation date, preferably ISO 8601 (`YYYY-MM-DD`)
+    CreationDate,
+    /// Description of the measurement setup (rig, microphones, room, distance)
+    MeasurementSetup,
+    /// Script or tool that generated the file
+    GenerationScript,
+    /// Software used to create the data, as `name version` entries separated by `;`
+    SoftwareVersions,
+    /// Publication to cite when using the data
+    Reference,
+    /// Hash of the processed data and its processing steps, see [`reproducibility_hash`]
+    ProcessingHash,
+}
+
+impl ProvenanceKey {
+    /// All well-known keys
+    pub const ALL: [ProvenanceKey; 11] = [
+        ProvenanceKey::Description,
+        ProvenanceKey::License,
+        ProvenanceKey::Doi,
+        ProvenanceKey::Author,
+        ProvenanceKey::Institution,
+        ProvenanceKey::CreationDate,
+        ProvenanceKey::MeasurementSetup,
+        ProvenanceKey::GenerationScript,
+        ProvenanceKey::SoftwareVersions,
+        ProvenanceKey::Reference,
+        ProvenanceKey::ProcessingHash,
+    ];
+
+    /// Metadata key name
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProvenanceKey::Description => "DESCRIPTION",
+            ProvenanceKey::License => "LICENSE",
+            ProvenanceKey::Doi => "DOI",
+            ProvenanceKey::Author => "AUTHOR",
+            ProvenanceKey::Institution => "INSTITUTION",
+            ProvenanceKey::CreationDate => "CREATION DATE",
+            ProvenanceKey::MeasurementSetup => "MEASUREMENT SETUP",
+            ProvenanceKey::GenerationScript => "GENERATION SCRIPT",
+            ProvenanceKey::SoftwareVersions => "SOFTWARE VERSIONS",
+            ProvenanceKey::Reference => "REFERENCE",
+            ProvenanceKey::ProcessingHash => "PROCESSING HASH",
+        }
+    }
+
+    /// Alternative key names found in existing files
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            ProvenanceKey::CreationDate => &["GENERATION DATE"],
+            ProvenanceKey::GenerationScript => &["CONVERTER SCRIPT"],
+            _ => &[],
+        }
+    }
+
+    /// Look up a key by its name or one of its aliases (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_uppercase();
+        Self::ALL
+            .into_iter()
+            .find(|key| key.name() == name || key.aliases().contains(&name.as_str()))
+    }
+
+    /// String value of this key in a metadata set (also looking at aliases)
+    pub fn get<'a>(&self, metadata: &'a Metadata) -> Option<&'a str> {
+        std::iter::once(self.name())
+            .chain(self.aliases().iter().copied())
+            .find_map(|name| match metadata.get(name) {
+                Some(MetadataValue::String(value)) => Some(value.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Set the string value of this key, replacing entries stored under an alias
+    pub fn set(&self, metadata: &mut Metadata, value: impl Into<String>) -> Result<()> {
+        let value = value.into();
+        crate::metadata::check_string(self.name(), &value)?;
+        for alias in self.aliases() {
+            metadata.remove(*alias);
+        }
+        metadata.insert(self.name().to_string(), MetadataValue::String(value));
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProvenanceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Digital object identifier in its canonical form (`10.<registrant>/<suffix>`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Doi(String);
+
+impl Doi {
+    /// Parse a DOI, also accepting `doi:` and `https://doi.org/` prefixes
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let doi = [
+            "https://doi.org/",
+            "http://doi.org/",
+            "https://dx.doi.org/",
+            "doi:",
+        ]
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .unwrap_or(text);
+        let valid = doi.starts_with("10.")
+            && doi
+                .split_once('/')
+                .is_some_and(|(registrant, suffix)| registrant.len() > 3 && !suffix.is_empty())
+            && !doi.chars().any(char::is_whitespace);
+        if valid {
+            Ok(Self(doi.to_string()))
+        } else {
+            Err(Error::invalid_metadata(
+                ProvenanceKey::Doi.name(),
+                format!("'{}' is not a DOI", text),
+            ))
+        }
+    }
+
+    /// The DOI without prefix
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Resolver URL of the DOI
+    pub fn url(&self) -> String {
+        format!("https://doi.org/{}", self.0)
+    }
+}
+
+impl fmt::Display for Doi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A software package and its version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Software {
+    /// Package name
+    pub name: String,
+    /// Version string
+    pub version: String,
+}
+
+/// Typed view on the provenance metadata of a dataset
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Provenance {
+    /// Free-text description of the content
+    pub description: Option<String>,
+    /// License of the data
+    pub license: Option<String>,
+    /// DOI of the published dataset
+    pub doi: Option<Doi>,
+    /// Author(s) of the data
+    pub author: Option<String>,
+    /// Institution that created the data
+    pub institution: Option<String>,
+    /// Creation date
+    pub creation_date: Option<String>,
+    /// Description of the measurement setup
+    pub measurement_setup: Option<String>,
+    /// Script or tool that generated the file
+    pub generation_script: Option<String>,
+    /// Software used to create the data
+    pub software: Vec<Software>,
+    /// Publication to cite
+    pub reference: Option<String>,
+    /// Processing steps in the order they were applied
+    pub processing: Vec<String>,
+    /// Reproducibility hash of the processed data
+    pub processing_hash: Option<String>,
+}
+
+impl Provenance {
+    /// Extract the provenance entries of a metadata set
+    ///
+    /// Fails with [`Error::InvalidMetadata`] if the DOI is malformed.
+    pub fn from_metadata(metadata: &Metadata) -> Result<Self> {
+        let get = |key: ProvenanceKey| key.get(metadata).map(str::to_string);
+        Ok(Self {
+            description: get(ProvenanceKey::Description),
+            license: get(ProvenanceKey::License),
+            doi: ProvenanceKey::Doi
+                .get(metadata)
+                .map(Doi::parse)
+                .transpose()?,
+            author: get(ProvenanceKey::Author),
+            institution: get(ProvenanceKey::Institution),
+            creation_date: get(ProvenanceKey::CreationDate),
+            measurement_setup: get(ProvenanceKey::MeasurementSetup),
+            generation_script: get(ProvenanceKey::GenerationScript),
+            software: ProvenanceKey::SoftwareVersions
+                .get(metadata)
+                .map(parse_software)
+                .unwrap_or_default(),
+            reference: get(ProvenanceKey::Reference),
+            processing: processing_steps(metadata)
+                .into_iter()
+                .map(|(_, step)| step.to_string())
+                .collect(),
+            processing_hash: get(ProvenanceKey::ProcessingHash),
+        })
+    }
+
+    /// Read the provenance of the file opened by `reader`
+    pub fn from_reader(reader: &Reader) -> Result<Self> {
+        Self::from_metadata(&crate::metadata::read_all(reader)?)
+    }
+
+    /// Write all set entries into a metadata set; unset entries are left untouched
+    ///
+    /// Processing steps, if any are set, replace all steps in the metadata.
+    pub fn write_to(&self, metadata: &mut Metadata) -> Result<()> {
+        let entries = [
+            (ProvenanceKey::Description, self.description.clone()),
+            (ProvenanceKey::License, self.license.clone()),
+            (ProvenanceKey::Doi, self.doi.as_ref().map(Doi::to_string)),
+            (ProvenanceKey::Author, self.author.clone()),
+            (ProvenanceKey::Institution, self.institution.clone()),
+            (ProvenanceKey::CreationDate, self.creation_date.clone()),
+            (
+                ProvenanceKey::MeasurementSetup,
+                self.measurement_setup.clone(),
+            ),
+            (
+                ProvenanceKey::GenerationScript,
+                self.generation_script.clone(),
+            ),
+            (
+                ProvenanceKey::SoftwareVersions,
+                (!self.software.is_empty()).then(|| format_software(&self.software)),
+            ),
+            (ProvenanceKey::Reference, self.reference.clone()),
+            (ProvenanceKey::ProcessingHash, self.processing_hash.clone()),
+        ];
+        for (key, value) in entries {
+            if let Some(value) = value {
+                key.set(metadata, value)?;
+            }
+        }
+
+        if !self.processing.is_empty() {
+            for step in &self.processing {
+                crate::metadata::check_string(PROCESSING_STEP_PREFIX, step)?;
+            }
+            metadata.retain(|key, _| step_number(key).is_none());
+            for (index, step) in self.processing.iter().enumerate() {
+                metadata.insert(
+                    format!("{}{}", PROCESSING_STEP_PREFIX, index + 1),
+                    MetadataValue::String(step.clone()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append a processing step to the metadata of a dataset
+///
+/// The step is stored under the next free `PROCESSING STEP <n>` key and prefixed with the
+/// crate name and version, e.g. `opendaff 1.8.0: resample to 48000 Hz`.
+pub fn record_step(metadata: &mut Metadata, description: &str) -> Result<()> {
+    let number = processing_steps(metadata)
+        .last()
+        .map_or(1, |(number, _)| number + 1);
+    let value = format!(
+        "{} {}: {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        description
+    );
+    let key = format!("{}{}", PROCESSING_STEP_PREFIX, number);
+    crate::metadata::check_string(&key, &value)?;
+    metadata.insert(key, MetadataValue::String(value));
+    Ok(())
+}
+
+/// SHA-256 hash over the data and processing history of a dataset (`sha256:<hex>`)
+///
+/// The hash covers the content header, quantization, grid, orientation, all records and the
+/// processing steps, but no other metadata, so it survives edits of descriptive entries (or
+/// [scrubbing](crate::metadata::scrub)). Running the same processing on the same input with
+/// the same crate version reproduces the hash, which lets recipients verify a distributed
+/// file against its stated processing chain.
+pub fn reproducibility_hash(dataset: &Dataset) -> String {
+    let mut hasher = Sha256::new();
+    let mut floats = |values: &[f64]| {
+        for value in values {
+            hasher.update(value.to_le_bytes());
+        }
+    };
+    match &dataset.header {
+        ContentHeader::ImpulseResponse { samplerate } => floats(&[0.0, *samplerate]),
+        ContentHeader::MagnitudeSpectrum { frequencies } => {
+            floats(&[1.0]);
+            floats(&frequencies.iter().map(|&f| f as f64).collect::<Vec<_>>());
+        }
+        ContentHeader::PhaseSpectrum { frequencies } => {
+            floats(&[2.0]);
+            floats(&frequencies.iter().map(|&f| f as f64).collect::<Vec<_>>());
+        }
+        ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+            floats(&[3.0]);
+            floats(&frequencies.iter().map(|&f| f as f64).collect::<Vec<_>>());
+        }
+        ContentHeader::DftSpectrum {
+            samplerate,
+            transform_size,
+        } => floats(&[4.0, *samplerate, *transform_size as f64]),
+    }
+    floats(&[dataset.quantization as i32 as f64]);
+    if let Some(grid) = dataset.grid.equiangular() {
+        floats(&[
+            grid.alpha_points as f64,
+            grid.alpha_start as f64,
+            grid.alpha_end as f64,
+            grid.beta_points as f64,
+            grid.beta_start as f64,
+            grid.beta_end as f64,
+        ]);
+    }
+    let orientation = &dataset.orientation;
+    floats(&[
+        orientation.yaw as f64,
+        orientation.pitch as f64,
+        orientation.roll as f64,
+    ]);
+
+    for record in &dataset.records {
+        hasher.update(record.alpha.to_le_bytes());
+        hasher.update(record.beta.to_le_bytes());
+        for channel in &record.channels {
+            hasher.update((channel.len() as u64).to_le_bytes());
+            for value in channel {
+                hasher.update(value.to_le_bytes());
+            }
+        }
+    }
+    for (_, step) in processing_steps(&dataset.metadata) {
+        hasher.update((step.len() as u64).to_le_bytes());
+        hasher.update(step.as_bytes());
+    }
+
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Processing steps of a metadata set, ordered by step number
+fn processing_steps(metadata: &Metadata) -> Vec<(usize, &str)> {
+    let mut steps: Vec<(usize, &str)> = metadata
+        .iter()
+        .filter_map(|(key, value)| match (step_number(key), value) {
+            (Some(number), MetadataValue::String(step)) => Some((number, step.as_str())),
+            _ => None,
+        })
+        .collect();
+    steps.sort_by_key(|(number, _)| *number);
+    steps
+}
+
+fn step_number(key: &str) -> Option<usize> {
+    key.strip_prefix(PROCESSING_STEP_PREFIX)?.parse().ok()
+}
+
+fn parse_software(text: &str) -> Vec<Software> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.rsplit_once(' ') {
+            Some((name, version)) => Software {
+                name: name.trim().to_string(),
+                version: version.to_string(),
+            },
+            None => Software {
+                name: entry.to_string(),
+                version: String::new(),
+            },
+        })
+        .collect()
+}
+
+fn format_software(software: &[Software]) -> String {
+    software
+        .iter()
+        .map(|s| format!("{} {}", s.name, s.version).trim().to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doi_parse() {
+        let doi = Doi::parse("https://doi.org/10.5281/zenodo.1234").unwrap();
+        assert_eq!(doi.as_str(), "10.5281/zenodo.1234");
+        assert_eq!(Doi::parse("doi:10.5281/zenodo.1234").unwrap(), doi);
+        assert!(matches!(
+            Doi::parse("zenodo 1234"),
+            Err(Error::InvalidMetadata { .. })
+        ));
+        assert!(Doi::parse("10.5281/").is_err());
+    }
+
+    #[test]
+    fn test_provenance_round_trip() {
+        let provenance = Provenance {
+            license: Some("CC-BY-4.0".to_string()),
+            doi: Some(Doi::parse("10.5281/zenodo.1234").unwrap()),
+            software: vec![
+                Software {
+                    name: "opendaff".to_string(),
+                    version: "1.8.0".to_string(),
+                },
+                Software {
+                    name: "Matlab".to_string(),
+                    version: "R2016a".to_string(),
+                },
+            ],
+            ..Provenance::default()
+        };
+
+        let mut metadata = Metadata::new();
+        metadata.insert(
+            "GENERATION DATE".to_string(),
+            MetadataValue::String("19-Sep-2016".to_string()),
+        );
+        provenance.write_to(&mut metadata).unwrap();
+        assert_eq!(
+            metadata["SOFTWARE VERSIONS"],
+            MetadataValue::String("opendaff 1.8.0; Matlab R2016a".to_string())
+        );
+
+        let read = Provenance::from_metadata(&metadata).unwrap();
+        assert_eq!(read.creation_date.as_deref(), Some("19-Sep-2016"));
+        assert_eq!(
+            read,
+            Provenance {
+                creation_date: read.creation_date.clone(),
+                ..provenance
+            }
+        );
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(
+            ProvenanceKey::from_name("Creation date"),
+            Some(ProvenanceKey::CreationDate)
+        );
+        assert_eq!(
+            ProvenanceKey::from_name("generation date"),
+            Some(ProvenanceKey::CreationDate)
+        );
+        assert_eq!(ProvenanceKey::from_name("SAMPLERATE"), None);
+    }
+
+    #[test]
+    fn test_processing_steps() {
+        let mut metadata = Metadata::new();
+        record_step(&mut metadata, "trim to 256 samples").unwrap();
+        record_step(&mut metadata, "resample to 48000 Hz").unwrap();
+        assert_eq!(
+            metadata["PROCESSING STEP 2"],
+            MetadataValue::String(format!(
+                "opendaff {}: resample to 48000 Hz",
+                env!("CARGO_PKG_VERSION")
+            ))
+        );
+
+        let provenance = Provenance::from_metadata(&metadata).unwrap();
+        assert_eq!(provenance.processing.len(), 2);
+        assert!(provenance.processing[0].ends_with("trim to 256 samples"));
+    }
+}
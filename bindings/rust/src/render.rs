@@ -0,0 +1,865 @@
+//! Helpers for real-time rendering
+//!
+//! Head-tracked renderers query the dataset many times per second with directions that
+//! change continuously. Switching filters on every small movement causes needless crossfades
+//! and lookups; the types in this module reduce that churn.
+//!
+//! Renderers address channels by their [`ChannelRole`] rather than by index, so datasets that
+//! store the ears in a different order are not silently swapped.
+//!
+//! Source directivities given as magnitude spectra rarely justify a full impulse response
+//! convolution. A [`DirectionalEq`] turns the band gains towards the listener into a short
+//! FIR equalizer and morphs between the filters as the direction changes.
+//!
+//! Convolving quiet signals with the decaying tails of measured impulse responses produces
+//! subnormal numbers, which most CPUs process in microcode at a fraction of the usual speed.
+//! The filter loops of this crate flush them to zero in software with [`flush_denormals`];
+//! whole datasets can be cleaned up in advance with
+//! [`dsp::flush_denormals`](crate::dsp::flush_denormals).
+
+use std::fmt;
+
+use crate::directivity::DirectivityTable;
+use crate::grid::to_object_view;
+use crate::metadata::{self, Metadata, MetadataValue};
+use crate::{Dataset, Error, Record, Result};
+
+/// Meaning of a channel for rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelRole {
+    /// Signal at the left ear
+    LeftEar,
+    /// Signal at the right ear
+    RightEar,
+    /// Capsule of a microphone array, by the number given in its label
+    Capsule(usize),
+    /// No known meaning
+    Unknown,
+}
+
+impl ChannelRole {
+    /// Infer the role from a channel label
+    ///
+    /// Recognizes labels like `Left`, `L`, `left ear`, `Right`, `R`, and numbered capsules
+    /// such as `Capsule 3` or `mic_3`. Case, separators and surrounding text around the side
+    /// are ignored; labels naming both sides are [`Unknown`](ChannelRole::Unknown).
+    pub fn from_label(label: &str) -> Self {
+        let label = label.to_lowercase();
+        let words: Vec<&str> = label
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let left = words.iter().any(|&w| w == "left" || w == "l");
+        let right = words.iter().any(|&w| w == "right" || w == "r");
+        match (left, right) {
+            (true, false) => return ChannelRole::LeftEar,
+            (false, true) => return ChannelRole::RightEar,
+            (true, true) => return ChannelRole::Unknown,
+            (false, false) => {}
+        }
+
+        let number = |prefix: &str| {
+            words.iter().enumerate().find_map(|(i, word)| {
+                let rest = word.strip_prefix(prefix)?;
+                let digits = if rest.is_empty() {
+                    words.get(i + 1)?
+                } else {
+                    rest
+                };
+                digits.parse().ok()
+            })
+        };
+        ["capsule", "microphone", "mic"]
+            .iter()
+            .find_map(|prefix| number(prefix))
+            .map_or(ChannelRole::Unknown, ChannelRole::Capsule)
+    }
+}
+
+impl fmt::Display for ChannelRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelRole::LeftEar => write!(f, "left ear"),
+            ChannelRole::RightEar => write!(f, "right ear"),
+            ChannelRole::Capsule(number) => write!(f, "capsule {}", number),
+            ChannelRole::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Roles of all channels of a dataset
+///
+/// Usually inferred from the channel labels in the metadata; individual roles can be
+/// overridden for files with missing or misleading labels.
+///
+/// ```
+/// use opendaff::render::{ChannelMap, ChannelRole};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// // Unlabeled binaural file, right ear stored first
+/// let channels = ChannelMap::new(vec![ChannelRole::Unknown; 2])
+///     .with_role(0, ChannelRole::RightEar)?
+///     .with_role(1, ChannelRole::LeftEar)?;
+/// assert_eq!(channels.ears()?, (1, 0));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMap {
+    roles: Vec<ChannelRole>,
+}
+
+impl ChannelMap {
+    /// Create a map from the roles of all channels in order
+    pub fn new(roles: Vec<ChannelRole>) -> Self {
+        Self { roles }
+    }
+
+    /// Infer the roles of `num_channels` channels from their `LABEL_CHANNEL_<n>` metadata
+    ///
+    /// Channels without a string label are [`Unknown`](ChannelRole::Unknown); the channel
+    /// order alone is never taken as a hint.
+    pub fn from_metadata(metadata: &Metadata, num_channels: usize) -> Self {
+        let roles = (0..num_channels)
+            .map(
+                |channel| match metadata.get(&metadata::channel_label_key(channel)) {
+                    Some(MetadataValue::String(label)) => ChannelRole::from_label(label),
+                    _ => ChannelRole::Unknown,
+                },
+            )
+            .collect();
+        Self { roles }
+    }
+
+    /// Infer the roles of the channels of a dataset from its metadata
+    pub fn from_dataset(dataset: &Dataset) -> Self {
+        Self::from_metadata(&dataset.metadata, dataset.num_channels())
+    }
+
+    /// Override the role of a channel
+    pub fn with_role(mut self, channel: usize, role: ChannelRole) -> Result<Self> {
+        let num_channels = self.roles.len();
+        let slot = self.roles.get_mut(channel).ok_or_else(|| {
+            Error::new(format!(
+                "Invalid channel {} of {} channels",
+                channel, num_channels
+            ))
+        })?;
+        *slot = role;
+        Ok(self)
+    }
+
+    /// Roles of all channels in order
+    pub fn roles(&self) -> &[ChannelRole] {
+        &self.roles
+    }
+
+    /// Role of a channel, [`Unknown`](ChannelRole::Unknown) for channels out of range
+    pub fn role(&self, channel: usize) -> ChannelRole {
+        self.roles
+            .get(channel)
+            .copied()
+            .unwrap_or(ChannelRole::Unknown)
+    }
+
+    /// The channel with the given role
+    ///
+    /// Fails if no channel or more than one channel has the role, since either would make
+    /// the renderer pick a wrong signal.
+    pub fn channel(&self, role: ChannelRole) -> Result<usize> {
+        let mut channels = self
+            .roles
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| **r == role)
+            .map(|(c, _)| c);
+        match (channels.next(), channels.next()) {
+            (Some(channel), None) => Ok(channel),
+            (None, _) => Err(Error::new(format!("No channel is the {}", role))),
+            (Some(_), Some(_)) => Err(Error::new(format!("More than one channel is the {}", role))),
+        }
+    }
+
+    /// Channel indices of the left and right ear
+    pub fn ears(&self) -> Result<(usize, usize)> {
+        Ok((
+            self.channel(ChannelRole::LeftEar)?,
+            self.channel(ChannelRole::RightEar)?,
+        ))
+    }
+}
+
+/// Data of the left and right ear of a record, selected by channel role
+pub fn ear_channels<'a>(
+    record: &'a Record,
+    channels: &ChannelMap,
+) -> Result<(&'a [f32], &'a [f32])> {
+    if record.channels.len() != channels.roles.len() {
+        return Err(Error::new(format!(
+            "The channel map describes {} channels, the record has {}",
+            channels.roles.len(),
+            record.channels.len()
+        )));
+    }
+    let (left, right) = channels.ears()?;
+    Ok((&record.channels[left], &record.channels[right]))
+}
+
+/// Change of the selected grid cell reported by [`DirectionQuantizer::update`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellChange {
+    /// Previously selected record, `None` for the first update after construction or reset
+    pub from: Option<usize>,
+    /// Newly selected record
+    pub to: usize,
+}
+
+impl CellChange {
+    /// Whether the renderer has to crossfade from the previous filter
+    ///
+    /// The first selection has nothing to fade from and can be loaded directly.
+    pub fn needs_crossfade(&self) -> bool {
+        self.from.is_some()
+    }
+}
+
+/// Hysteresis quantizer from continuous directions to grid records
+///
+/// Every record owns the cell of directions closer to it than to any other record. The
+/// quantizer keeps the current record until the tracked direction is closer to another one
+/// by more than the hysteresis angle, so a listener hovering at a cell border does not toggle
+/// between two filters. Directions are given in the object view (azimuth and elevation in
+/// degrees), as delivered by head trackers.
+#[derive(Debug, Clone)]
+pub struct DirectionQuantizer {
+    /// Unit vectors of all record directions
+    points: Vec<[f64; 3]>,
+    /// Hysteresis in degrees
+    hysteresis: f32,
+    current: Option<usize>,
+}
+
+impl DirectionQuantizer {
+    /// Create a quantizer for the given object view directions (azimuth, elevation in degrees)
+    ///
+    /// Record indices reported by the quantizer refer to the position in `directions`.
+    pub fn new<I>(directions: I, hysteresis: f32) -> Result<Self>
+    where
+        I: IntoIterator<Item = (f32, f32)>,
+    {
+        if !hysteresis.is_finite() || hysteresis < 0.0 {
+            return Err(Error::new("Hysteresis must be a non-negative angle"));
+        }
+        let points: Vec<_> = directions
+            .into_iter()
+            .map(|(azimuth, elevation)| unit_vector(azimuth, elevation))
+            .collect();
+        if points.is_empty() {
+            return Err(Error::new(
+                "Direction quantizer needs at least one direction",
+            ));
+        }
+        Ok(Self {
+            points,
+            hysteresis,
+            current: None,
+        })
+    }
+
+    /// Create a quantizer for the records of a dataset
+    ///
+    /// The data view directions of the records are converted to the object view using the
+    /// orientation of the dataset.
+    pub fn from_dataset(dataset: &Dataset, hysteresis: f32) -> Result<Self> {
+        let directions = dataset
+            .records
+            .iter()
+            .map(|record| to_object_view(&dataset.orientation, record.alpha, record.beta));
+        Self::new(directions, hysteresis)
+    }
+
+    /// Hysteresis angle in degrees
+    pub fn hysteresis(&self) -> f32 {
+        self.hysteresis
+    }
+
+    /// Number of records the quantizer selects from
+    pub fn num_records(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Currently selected record, `None` before the first update
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Forget the current selection, e.g. after the renderer dropped its filters
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Feed a new tracked direction (azimuth, elevation in degrees)
+    ///
+    /// Returns the change of the selected record, or `None` if the current record is kept and
+    /// the renderer can continue without swapping filters.
+    pub fn update(&mut self, azimuth: f32, elevation: f32) -> Option<CellChange> {
+        let point = unit_vector(azimuth, elevation);
+        let (nearest, nearest_angle) = self
+            .points
+            .iter()
+            .map(|p| angle(p, &point))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("quantizer has at least one direction");
+
+        if let Some(current) = self.current {
+            if current == nearest {
+                return None;
+            }
+            let current_angle = angle(&self.points[current], &point);
+            if current_angle <= nearest_angle + self.hysteresis as f64 {
+                return None;
+            }
+        }
+        let change = CellChange {
+            from: self.current,
+            to: nearest,
+        };
+        self.current = Some(nearest);
+        Some(change)
+    }
+}
+
+/// Direction-dependent equalizer derived from a magnitude spectrum directivity
+///
+/// For every direction the band gains of the [`DirectivityTable`] are interpolated over
+/// logarithmic frequency and sampled at the bins of a linear-phase FIR filter with an odd
+/// number of taps (frequency sampling design). The filter delays the signal by
+/// [`latency`](DirectionalEq::latency) samples.
+///
+/// [`set_direction`](DirectionalEq::set_direction) designs the filter for a new direction;
+/// the next call to [`process`](DirectionalEq::process) crossfades from the previous filter
+/// to the new one over the block, so moving sources do not click. The first direction is
+/// loaded without a crossfade. Designing a filter allocates nothing, processing allocates
+/// only while the blocks grow.
+///
+/// ```
+/// use opendaff::render::DirectionalEq;
+/// use opendaff::{ContentHeader, Dataset, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// // A source that is 6 dB quieter towards the back
+/// let dataset = Dataset::from_fn(
+///     ContentHeader::MagnitudeSpectrum { frequencies: vec![125.0, 1000.0, 8000.0] },
+///     EquiangularGrid::with_resolution(30.0, 30.0)?,
+///     1,
+///     |alpha, _, _| vec![1.0 - 0.5 * alpha.min(360.0 - alpha) / 180.0; 3],
+/// );
+/// let mut eq = DirectionalEq::from_dataset(&dataset, 0, 48000.0, 31)?;
+/// eq.set_direction(180.0, 0.0);
+/// let mut block = vec![1.0f32; 256];
+/// eq.process(&mut block);
+/// assert!((block[255] - 0.5).abs() < 1e-3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirectionalEq {
+    table: DirectivityTable,
+    /// Lower band and weight of the upper band for every sampled frequency bin
+    bins: Vec<(usize, f32)>,
+    /// Cosine of every tap and bin, `taps` values per bin
+    basis: Vec<f32>,
+    /// Band gains at the sampled bins
+    gains: Vec<f32>,
+    coefficients: Vec<f32>,
+    /// Filter to crossfade to in the next block
+    target: Vec<f32>,
+    pending: bool,
+    direction: Option<(f32, f32)>,
+    /// Previous `taps - 1` input samples followed by the current block
+    input: Vec<f32>,
+}
+
+impl DirectionalEq {
+    /// Create an equalizer with `taps` coefficients for signals at the given sampling rate
+    ///
+    /// Until the first [`set_direction`](DirectionalEq::set_direction), the filter is a
+    /// plain delay.
+    pub fn new(table: DirectivityTable, samplerate: f32, taps: usize) -> Result<Self> {
+        if taps % 2 == 0 {
+            return Err(Error::new(format!(
+                "Linear-phase equalizers need an odd number of taps, not {}",
+                taps
+            )));
+        }
+        if !samplerate.is_finite() || samplerate <= 0.0 {
+            return Err(Error::new(format!("Invalid sampling rate {}", samplerate)));
+        }
+        if table.num_bands() == 0 {
+            return Err(Error::new("Directivity table has no frequency bands"));
+        }
+
+        let frequencies = table.frequencies();
+        let bins: Vec<(usize, f32)> = (0..=taps / 2)
+            .map(|k| {
+                let f = k as f32 * samplerate / taps as f32;
+                let upper = frequencies.partition_point(|&band| band <= f);
+                if upper == 0 {
+                    (0, 0.0)
+                } else if upper == frequencies.len() {
+                    (upper - 1, 0.0)
+                } else {
+                    let (f0, f1) = (frequencies[upper - 1], frequencies[upper]);
+                    let weight = if f0 > 0.0 {
+                        (f / f0).ln() / (f1 / f0).ln()
+                    } else {
+                        (f - f0) / (f1 - f0)
+                    };
+                    (upper - 1, weight)
+                }
+            })
+            .collect();
+        let center = (taps / 2) as f64;
+        let basis = (0..bins.len())
+            .flat_map(|k| {
+                (0..taps).map(move |n| {
+                    let phase = std::f64::consts::TAU * k as f64 * (n as f64 - center);
+                    (phase / taps as f64).cos() as f32
+                })
+            })
+            .collect();
+        let mut coefficients = vec![0.0; taps];
+        coefficients[taps / 2] = 1.0;
+
+        Ok(Self {
+            table,
+            gains: vec![0.0; bins.len()],
+            bins,
+            basis,
+            target: coefficients.clone(),
+            coefficients,
+            pending: false,
+            direction: None,
+            input: vec![0.0; taps - 1],
+        })
+    }
+
+    /// Create an equalizer for one channel of a magnitude spectrum dataset, see
+    /// [`DirectivityTable::from_dataset`]
+    pub fn from_dataset(
+        dataset: &Dataset,
+        channel: usize,
+        samplerate: f32,
+        taps: usize,
+    ) -> Result<Self> {
+        Self::new(
+            DirectivityTable::from_dataset(dataset, channel)?,
+            samplerate,
+            taps,
+        )
+    }
+
+    /// Number of filter coefficients
+    pub fn taps(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    /// Delay of the filter in samples
+    pub fn latency(&self) -> usize {
+        self.taps() / 2
+    }
+
+    /// Coefficients of the filter that was applied last
+    pub fn coefficients(&self) -> &[f32] {
+        &self.coefficients
+    }
+
+    /// Direction of the current filter (azimuth, elevation in degrees), `None` before the
+    /// first [`set_direction`](DirectionalEq::set_direction)
+    pub fn direction(&self) -> Option<(f32, f32)> {
+        self.direction
+    }
+
+    /// Design the filter towards an object view direction (azimuth, elevation in degrees)
+    ///
+    /// Repeating the current direction does nothing.
+    pub fn set_direction(&mut self, azimuth: f32, elevation: f32) {
+        if self.direction == Some((azimuth, elevation)) {
+            return;
+        }
+        let bands = self.table.num_bands();
+        for (gain, &(band, weight)) in self.gains.iter_mut().zip(&self.bins) {
+            let lower = self.table.band_gain(azimuth, elevation, band);
+            *gain = if weight > 0.0 {
+                let upper = self
+                    .table
+                    .band_gain(azimuth, elevation, (band + 1).min(bands - 1));
+                lower + (upper - lower) * weight
+            } else {
+                lower
+            };
+        }
+
+        // Real, even spectrum: h[n] = (G0 + 2 Σ Gk cos(2πk(n - c)/N)) / N
+        let taps = self.taps();
+        self.target.fill(0.0);
+        for (k, (&gain, basis)) in self
+            .gains
+            .iter()
+            .zip(self.basis.chunks_exact(taps))
+            .enumerate()
+        {
+            let gain = if k == 0 { gain } else { 2.0 * gain } / taps as f32;
+            for (h, &b) in self.target.iter_mut().zip(basis) {
+                *h += gain * b;
+            }
+        }
+        flush_denormals(&mut self.target);
+        if self.direction.is_none() {
+            self.coefficients.copy_from_slice(&self.target);
+        } else {
+            self.pending = true;
+        }
+        self.direction = Some((azimuth, elevation));
+    }
+
+    /// Filter a block of samples in place
+    ///
+    /// If the direction changed since the last block, the output crossfades linearly from the
+    /// previous filter to the new one over this block. Subnormal input samples and results are
+    /// flushed to zero.
+    pub fn process(&mut self, block: &mut [f32]) {
+        let taps = self.taps();
+        self.input.truncate(taps - 1);
+        self.input.extend_from_slice(block);
+        flush_denormals(&mut self.input[taps - 1..]);
+        let fade = self.pending && !block.is_empty();
+        let step = 1.0 / block.len().max(1) as f32;
+        for (i, output) in block.iter_mut().enumerate() {
+            // input[i + taps - 1] is the current sample
+            let window = &self.input[i..i + taps];
+            let filter = |coefficients: &[f32]| -> f32 {
+                coefficients
+                    .iter()
+                    .zip(window.iter().rev())
+                    .map(|(h, x)| h * x)
+                    .sum()
+            };
+            *output = if fade {
+                let t = (i + 1) as f32 * step;
+                filter(&self.coefficients) * (1.0 - t) + filter(&self.target) * t
+            } else {
+                filter(&self.coefficients)
+            };
+        }
+        if fade {
+            self.coefficients.copy_from_slice(&self.target);
+            self.pending = false;
+        }
+        flush_denormals(block);
+        let len = self.input.len();
+        self.input.copy_within(len - (taps - 1).., 0);
+    }
+
+    /// Clear the filter state, e.g. when the source restarts
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.input.resize(self.taps() - 1, 0.0);
+        if self.pending {
+            self.coefficients.copy_from_slice(&self.target);
+            self.pending = false;
+        }
+    }
+}
+
+/// Set subnormal values to zero, returning how many were flushed
+pub fn flush_denormals(samples: &mut [f32]) -> usize {
+    let mut flushed = 0;
+    for sample in samples {
+        if sample.is_subnormal() {
+            *sample = 0.0;
+            flushed += 1;
+        }
+    }
+    flushed
+}
+
+fn unit_vector(azimuth: f32, elevation: f32) -> [f64; 3] {
+    let (sa, ca) = (azimuth as f64).to_radians().sin_cos();
+    let (se, ce) = (elevation as f64).to_radians().sin_cos();
+    [ca * ce, sa * ce, se]
+}
+
+/// Angle between two unit vectors in degrees
+fn angle(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    dot.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContentHeader, EquiangularGrid};
+
+    #[test]
+    fn test_channel_role_from_label() {
+        for label in ["Left", "L", "left ear", "Ear_left", "HRIR (left)"] {
+            assert_eq!(
+                ChannelRole::from_label(label),
+                ChannelRole::LeftEar,
+                "{}",
+                label
+            );
+        }
+        for label in ["RIGHT", "r", "right-ear"] {
+            assert_eq!(
+                ChannelRole::from_label(label),
+                ChannelRole::RightEar,
+                "{}",
+                label
+            );
+        }
+        assert_eq!(
+            ChannelRole::from_label("Capsule 3"),
+            ChannelRole::Capsule(3)
+        );
+        assert_eq!(ChannelRole::from_label("mic_12"), ChannelRole::Capsule(12));
+        assert_eq!(ChannelRole::from_label("Mic7"), ChannelRole::Capsule(7));
+        for label in ["", "Channel 1", "left/right", "leftover", "mic"] {
+            assert_eq!(
+                ChannelRole::from_label(label),
+                ChannelRole::Unknown,
+                "{}",
+                label
+            );
+        }
+    }
+
+    #[test]
+    fn test_channel_map() {
+        let mut metadata = Metadata::new();
+        metadata.insert(
+            "LABEL_CHANNEL_1".to_string(),
+            MetadataValue::String("Right".to_string()),
+        );
+        metadata.insert(
+            "LABEL_CHANNEL_2".to_string(),
+            MetadataValue::String("Left".to_string()),
+        );
+        metadata.insert("LABEL_CHANNEL_3".to_string(), MetadataValue::Int(3));
+        let channels = ChannelMap::from_metadata(&metadata, 3);
+        assert_eq!(
+            channels.roles(),
+            [
+                ChannelRole::RightEar,
+                ChannelRole::LeftEar,
+                ChannelRole::Unknown
+            ]
+        );
+        assert_eq!(channels.ears().unwrap(), (1, 0));
+        assert_eq!(channels.role(5), ChannelRole::Unknown);
+        assert!(channels.channel(ChannelRole::Capsule(1)).is_err());
+
+        let record = Record {
+            alpha: 0.0,
+            beta: 90.0,
+            channels: vec![vec![2.0], vec![1.0], vec![0.0]],
+        };
+        let (left, right) = ear_channels(&record, &channels).unwrap();
+        assert_eq!((left, right), (&[1.0][..], &[2.0][..]));
+
+        // Unlabeled channels are never guessed from their order
+        let unlabeled = ChannelMap::from_metadata(&Metadata::new(), 2);
+        assert!(unlabeled.ears().is_err());
+        let both_left = unlabeled
+            .clone()
+            .with_role(0, ChannelRole::LeftEar)
+            .unwrap()
+            .with_role(1, ChannelRole::LeftEar)
+            .unwrap();
+        assert!(both_left.ears().is_err());
+        assert!(unlabeled.with_role(2, ChannelRole::LeftEar).is_err());
+        assert!(ear_channels(&record, &ChannelMap::new(vec![ChannelRole::LeftEar])).is_err());
+    }
+
+    #[test]
+    fn test_hysteresis_suppresses_border_toggling() {
+        let mut quantizer =
+            DirectionQuantizer::new([(0.0, 0.0), (10.0, 0.0), (20.0, 0.0)], 2.0).unwrap();
+        assert_eq!(quantizer.current(), None);
+
+        let first = quantizer.update(4.0, 0.0).unwrap();
+        assert_eq!(first, CellChange { from: None, to: 0 });
+        assert!(!first.needs_crossfade());
+
+        // Hovering around the border at 5° keeps the first record
+        for azimuth in [5.5, 4.5, 6.0, 5.0, 5.9] {
+            assert_eq!(quantizer.update(azimuth, 0.5), None);
+        }
+        let change = quantizer.update(6.5, 0.0).unwrap();
+        assert_eq!(
+            change,
+            CellChange {
+                from: Some(0),
+                to: 1
+            }
+        );
+        assert!(change.needs_crossfade());
+
+        // The way back needs the same margin
+        assert_eq!(quantizer.update(4.5, 0.0), None);
+        assert_eq!(quantizer.update(3.5, 0.0).unwrap().to, 0);
+
+        // Large jumps switch directly to the nearest record
+        assert_eq!(
+            quantizer.update(19.0, 1.0),
+            Some(CellChange {
+                from: Some(0),
+                to: 2
+            })
+        );
+        assert_eq!(quantizer.current(), Some(2));
+
+        quantizer.reset();
+        assert_eq!(quantizer.update(19.0, 1.0).unwrap().from, None);
+    }
+
+    #[test]
+    fn test_wraps_around_azimuth() {
+        let mut quantizer = DirectionQuantizer::new([(0.0, 0.0), (180.0, 0.0)], 0.0).unwrap();
+        assert_eq!(quantizer.update(350.0, 0.0).unwrap().to, 0);
+        assert_eq!(quantizer.update(-20.0, 0.0), None);
+        assert_eq!(quantizer.update(200.0, 0.0).unwrap().to, 1);
+    }
+
+    #[test]
+    fn test_from_dataset() {
+        let grid = EquiangularGrid {
+            alpha_points: 4,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points: 3,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        };
+        let header = ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![1000.0],
+        };
+        let dataset = Dataset::from_fn(header, grid, 1, |_, _, _| vec![1.0]);
+
+        let mut quantizer = DirectionQuantizer::from_dataset(&dataset, 5.0).unwrap();
+        assert_eq!(quantizer.num_records(), 6);
+        assert_eq!(quantizer.hysteresis(), 5.0);
+        // Record 2 sits at alpha 90° on the horizontal ring, record 5 at the north pole
+        assert_eq!(quantizer.update(85.0, 3.0).unwrap().to, 2);
+        assert_eq!(quantizer.update(80.0, 85.0).unwrap().to, 5);
+        assert_eq!(quantizer.update(-80.0, -89.0).unwrap().to, 0);
+
+        assert!(DirectionQuantizer::new([], 1.0).is_err());
+        assert!(DirectionQuantizer::new([(0.0, 0.0)], -1.0).is_err());
+        assert!(DirectionQuantizer::new([(0.0, 0.0)], f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_flush_denormals() {
+        let tiny = f32::MIN_POSITIVE / 4.0;
+        let mut samples = [1.0, tiny, -tiny, f32::MIN_POSITIVE, 0.0];
+        assert_eq!(flush_denormals(&mut samples), 2);
+        assert_eq!(samples, [1.0, 0.0, 0.0, f32::MIN_POSITIVE, 0.0]);
+    }
+
+    fn directivity(gains: impl Fn(f32) -> Vec<f32>) -> Dataset {
+        Dataset::from_fn(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![250.0, 1000.0, 4000.0],
+            },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            1,
+            |alpha, _, _| gains(alpha),
+        )
+    }
+
+    /// Magnitude response of a linear-phase filter at a frequency relative to the sampling rate
+    fn response(coefficients: &[f32], frequency: f64) -> f64 {
+        let (re, im) = coefficients
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, &h)| {
+                let phase = std::f64::consts::TAU * frequency * n as f64;
+                (re + h as f64 * phase.cos(), im - h as f64 * phase.sin())
+            });
+        re.hypot(im)
+    }
+
+    #[test]
+    fn test_directional_eq_design() {
+        // Low-pass shape, independent of the direction
+        let dataset = directivity(|_| vec![1.0, 0.5, 0.1]);
+        assert!(DirectionalEq::from_dataset(&dataset, 0, 16000.0, 32).is_err());
+        assert!(DirectionalEq::from_dataset(&dataset, 1, 16000.0, 33).is_err());
+        let mut eq = DirectionalEq::from_dataset(&dataset, 0, 16000.0, 65).unwrap();
+        assert_eq!(eq.latency(), 32);
+        assert_eq!(eq.direction(), None);
+        assert_eq!(eq.coefficients()[32], 1.0);
+
+        eq.set_direction(40.0, 10.0);
+        assert_eq!(eq.direction(), Some((40.0, 10.0)));
+        let h = eq.coefficients();
+        for i in 0..32 {
+            assert!((h[i] - h[64 - i]).abs() < 1e-6, "not symmetric at {}", i);
+        }
+        // Exact at the bins (multiples of 16000 / 65 Hz), log-interpolated in between
+        let bin = |k: f64| k * 16000.0 / 65.0;
+        assert!((response(h, 0.0) - 1.0).abs() < 1e-4);
+        assert!((response(h, 1.0 / 65.0) - 1.0).abs() < 1e-4);
+        let k = 4.0;
+        let expected = 1.0 - 0.5 * (bin(k) / 250.0).ln() / 4f64.ln();
+        assert!((response(h, k / 65.0) - expected).abs() < 1e-4);
+        assert!((response(h, 17.0 / 65.0) - 0.1).abs() < 1e-4);
+        assert!((response(h, 32.0 / 65.0) - 0.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_directional_eq_morphs() {
+        // Full level towards the front, 0.25 towards the back
+        let dataset = directivity(|alpha| vec![1.0 - 0.75 * alpha.min(360.0 - alpha) / 180.0; 3]);
+        let mut eq = DirectionalEq::from_dataset(&dataset, 0, 48000.0, 15).unwrap();
+        let mut block = vec![1.0f32; 64];
+        eq.process(&mut block);
+        // Plain delay before the first direction
+        assert!(block[..7].iter().all(|&x| x == 0.0));
+        assert!(block[7..].iter().all(|&x| x == 1.0));
+
+        eq.set_direction(0.0, 0.0);
+        let mut block = vec![1.0f32; 64];
+        eq.process(&mut block);
+        assert!(block.iter().all(|&x| (x - 1.0).abs() < 1e-4));
+
+        eq.set_direction(180.0, 0.0);
+        let mut block = vec![1.0f32; 64];
+        eq.process(&mut block);
+        // Ramps down over the block instead of jumping
+        assert!(block.windows(2).all(|w| w[1] <= w[0] + 1e-6));
+        assert!((block[31] - (1.0 - 0.75 * 0.5)).abs() < 0.02);
+        assert!((block[63] - 0.25).abs() < 1e-4);
+        let mut block = vec![1.0f32; 64];
+        eq.process(&mut block);
+        assert!(block.iter().all(|&x| (x - 0.25).abs() < 1e-4));
+
+        eq.reset();
+        let mut block = vec![1.0f32; 16];
+        eq.process(&mut block);
+        assert!(block[..7].iter().all(|&x| x.abs() < 1e-4));
+        assert!((block[7] - 0.25).abs() < 1e-4);
+
+        // Subnormal input neither reaches the output nor lingers in the state
+        let tiny = f32::MIN_POSITIVE / 4.0;
+        let mut block = vec![tiny; 16];
+        eq.process(&mut block);
+        assert!(block.iter().all(|x| !x.is_subnormal()));
+        let mut block = vec![0.0f32; 16];
+        eq.process(&mut block);
+        assert_eq!(block, [0.0; 16]);
+    }
+}
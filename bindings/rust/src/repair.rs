@@ -0,0 +1,544 @@
+//! Repair of damaged DAFF files
+//!
+//! Measurement sessions that were interrupted, or files that were copied incompletely, often
+//! leave DAFF files that the reader rejects although most of their records are intact.
+//! [`attempt`] fixes the recoverable defects and writes a repaired copy next to the file:
+//!
+//! - a record count in the main header that does not match the grid;
+//! - trailing records whose descriptors or samples are missing, e.g. in a truncated file. The
+//!   samples that are still there are kept, the missing ones are set to zero;
+//! - a metadata block length that cuts off a metadata set or runs past the last one.
+//!
+//! The grid in the main header is trusted. Partial files of an interrupted
+//! [`Writer`](crate::Writer) session are continued with
+//! [`Writer::resume`](crate::Writer::resume) instead.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::grid::EquiangularGrid;
+use crate::parser::ParsedFile;
+use crate::writer::{self, put_i32, put_u64, Input};
+use crate::{legacy, ContentType, Error, Quantization, Result};
+
+/// Alignment of the record descriptor and data blocks in repaired data
+const BLOCK_ALIGNMENT: usize = 16;
+
+/// A defect that was fixed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fix {
+    /// The main header stated a record count other than the one of the grid
+    RecordCount {
+        /// Record count in the main header
+        stored: usize,
+        /// Record count of the grid
+        repaired: usize,
+    },
+    /// Records with missing descriptors or samples, which are now zero
+    MissingRecords {
+        /// Indices of the records, in ascending order
+        records: Vec<usize>,
+    },
+    /// The metadata block length did not match the metadata sets
+    MetadataLength {
+        /// Size of the block in the file block table
+        stored: u64,
+        /// Size of the complete metadata sets
+        repaired: u64,
+    },
+    /// Record descriptors referenced metadata sets that were lost and now reference none
+    MetadataReferences {
+        /// Number of record channel descriptors
+        removed: usize,
+    },
+}
+
+/// Outcome of a repair [attempt]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Fixed defects, empty if the file is intact
+    pub fixes: Vec<Fix>,
+    /// Path of the repaired copy, `None` if nothing had to be fixed
+    pub repaired: Option<PathBuf>,
+}
+
+impl RepairReport {
+    /// Whether the file had no recoverable defects
+    pub fn is_intact(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+/// Repair a DAFF file, writing the repaired copy to `<name>.repaired.daff` next to it
+///
+/// The file itself is not changed. Fails if the file is not repairable, e.g. if its main
+/// header is missing, or is in a [legacy](crate::legacy) format version.
+pub fn attempt(path: impl AsRef<Path>) -> Result<RepairReport> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)
+        .map_err(|e| Error::new(format!("Failed to read {}: {}", path.display(), e)))?;
+    let (repaired, fixes) = repair(&bytes)?;
+    if fixes.is_empty() {
+        return Ok(RepairReport {
+            fixes,
+            repaired: None,
+        });
+    }
+    let target = path.with_extension("repaired.daff");
+    fs::write(&target, repaired)
+        .map_err(|e| Error::new(format!("Failed to write {}: {}", target.display(), e)))?;
+    Ok(RepairReport {
+        fixes,
+        repaired: Some(target),
+    })
+}
+
+/// Record channel descriptor as it is written to the repaired data
+struct Descriptor {
+    metadata: i32,
+    offset: usize,
+    leading_zeros: i32,
+    length: i32,
+}
+
+/// Repair DAFF data in memory
+///
+/// Returns the repaired data and the fixed defects; intact data is returned unchanged.
+pub fn repair(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Fix>)> {
+    let version = legacy::detect_version(bytes).ok_or_else(|| Error::new("Not a DAFF file"))?;
+    if legacy::is_legacy(version) {
+        return Err(Error::new(format!(
+            "File format version {} predates DAFF 1.7 and has to be upgraded first",
+            version
+        )));
+    } else if version != legacy::CURRENT_VERSION {
+        return Err(Error::new(format!(
+            "Unsupported file format version {}",
+            version
+        )));
+    }
+    let mut input = Input::new(&bytes[6..]);
+    let mut blocks = Vec::new();
+    for _ in 0..input.count()? {
+        let id = input.i32()?;
+        let offset = usize::try_from(input.u64()?).unwrap_or(usize::MAX);
+        blocks.push((id, offset, input.u64()?));
+    }
+    // Blocks are cut off at the end of the data
+    let clamp = |offset: usize, size: u64| {
+        let start = offset.min(bytes.len());
+        let size = usize::try_from(size).unwrap_or(usize::MAX);
+        start..offset.saturating_add(size).min(bytes.len())
+    };
+    let find = |id: i32| {
+        blocks
+            .iter()
+            .find(|(block_id, ..)| *block_id == id)
+            .copied()
+    };
+    let block = |id: i32, name: &str| {
+        find(id)
+            .map(|(_, offset, size)| clamp(offset, size))
+            .ok_or_else(|| Error::new(format!("File has no {} block", name)))
+    };
+    let mut fixes = Vec::new();
+
+    let mut main_header = bytes[block(writer::MAIN_HEADER_ID, "main header")?].to_vec();
+    if main_header.len() < writer::MAIN_HEADER_SIZE as usize {
+        return Err(Error::new("Main header is incomplete"));
+    }
+    main_header.truncate(writer::MAIN_HEADER_SIZE as usize);
+    let mut input = Input::new(&main_header);
+    let content_type = ContentType::from_i32(input.i32()?);
+    let quantization = Quantization::from_i32(input.i32()?);
+    let (Some(content_type), Some(quantization)) = (content_type, quantization) else {
+        return Err(Error::new("Invalid content type or quantization"));
+    };
+    let num_channels = input.count()?;
+    let stored_records = input.count()?;
+    let stored_elements = input.count()?;
+    let _metadata_index = input.i32()?;
+    let grid = EquiangularGrid {
+        alpha_points: input.count()?,
+        alpha_start: input.f32()?,
+        alpha_end: input.f32()?,
+        beta_points: input.count()?,
+        beta_start: input.f32()?,
+        beta_end: input.f32()?,
+    };
+    let num_records = grid.num_records();
+    if num_records > i32::MAX as usize {
+        return Err(Error::new("Invalid grid in the main header"));
+    }
+    if num_channels == 0 || num_records == 0 || stored_elements == 0 {
+        return Err(Error::new(
+            "File has no channels, records or elements per record",
+        ));
+    }
+    if stored_records != num_records {
+        main_header[12..16].copy_from_slice(&(num_records as i32).to_le_bytes());
+        fixes.push(Fix::RecordCount {
+            stored: stored_records,
+            repaired: num_records,
+        });
+    }
+    let content_header = &bytes[block(writer::CONTENT_HEADER_ID, "content header")?];
+
+    let ir = content_type == ContentType::ImpulseResponse;
+    let (value_size, values) = match content_type {
+        ContentType::ImpulseResponse => (writer::quantization_size(quantization), stored_elements),
+        ContentType::MagnitudeSpectrum | ContentType::PhaseSpectrum => (4, stored_elements),
+        _ => (4, 2 * stored_elements),
+    };
+    let desc_size = if ir {
+        writer::IR_DESC_SIZE
+    } else {
+        writer::DEFAULT_DESC_SIZE
+    } as usize;
+    let descriptors = block(writer::RECORD_DESC_ID, "record descriptor")?;
+    let data = block(writer::DATA_ID, "data")?;
+    let mut samples = bytes[data].to_vec();
+
+    // Descriptors of records with missing samples point to a shared block of zeros at the
+    // end of the data block; partly stored samples are padded with zeros
+    let mut channels = Vec::new();
+    let mut missing = BTreeSet::new();
+    let mut padded_end = samples.len();
+    let mut input = Input::new(&bytes[descriptors]);
+    for index in 0..num_records.saturating_mul(num_channels) {
+        let descriptor = input.take(desc_size).ok().and_then(|descriptor| {
+            let mut input = Input::new(descriptor);
+            let metadata = input.i32().ok()?;
+            let offset = usize::try_from(input.u64().ok()?).ok()?;
+            let (leading_zeros, length) = if ir {
+                (input.i32().ok()?, input.i32().ok()?)
+            } else {
+                (0, values as i32)
+            };
+            let (zeros, len) = (
+                usize::try_from(leading_zeros).ok()?,
+                usize::try_from(length).ok()?,
+            );
+            (zeros.checked_add(len)? <= values && offset <= samples.len()).then_some(Descriptor {
+                metadata,
+                offset,
+                leading_zeros,
+                length,
+            })
+        });
+        let end = descriptor
+            .as_ref()
+            .map(|descriptor| descriptor.offset + descriptor.length as usize * value_size);
+        if end.map_or(true, |end| end > samples.len()) {
+            padded_end = padded_end.max(end.unwrap_or_default());
+            missing.insert(index / num_channels);
+        }
+        channels.push(descriptor);
+    }
+    let zeros_offset = (padded_end + value_size - 1) / value_size * value_size;
+    if channels.iter().any(Option::is_none) {
+        samples.resize(zeros_offset + values * value_size, 0);
+    } else {
+        samples.resize(padded_end, 0);
+    }
+    let mut channels: Vec<_> = channels
+        .into_iter()
+        .map(|descriptor| {
+            descriptor.unwrap_or(Descriptor {
+                metadata: -1,
+                offset: zeros_offset,
+                leading_zeros: 0,
+                length: values as i32,
+            })
+        })
+        .collect();
+    if !missing.is_empty() {
+        fixes.push(Fix::MissingRecords {
+            records: missing.into_iter().collect(),
+        });
+    }
+
+    let mut metadata = None;
+    if let Some((_, offset, stored)) = find(writer::METADATA_ID) {
+        // Sets are parsed up to the next block or the end of the data; the repaired block
+        // ends after the first set reaching the stored length, or after the last set
+        let next = blocks
+            .iter()
+            .map(|&(_, start, _)| start)
+            .filter(|&start| start > offset)
+            .min()
+            .unwrap_or(usize::MAX);
+        let region = clamp(offset, (next - offset) as u64);
+        let mut input = Input::new(&bytes[region.clone()]);
+        let mut length = 0;
+        let mut sets = 0i32;
+        while (length as u64) < stored && !input.is_empty() {
+            if writer::decode_metadata(&mut input).is_err() {
+                break;
+            }
+            length = region.len() - input.len();
+            sets += 1;
+        }
+        let mut block = bytes[region.start..region.start + length].to_vec();
+        if sets == 0 && stored > 0 {
+            // The global set is lost, an empty one takes its place
+            block = 0i32.to_le_bytes().to_vec();
+            sets = 1;
+        }
+        if block.len() as u64 != stored {
+            fixes.push(Fix::MetadataLength {
+                stored,
+                repaired: block.len() as u64,
+            });
+        }
+        let mut removed = 0;
+        for channel in &mut channels {
+            if channel.metadata >= sets {
+                channel.metadata = -1;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            fixes.push(Fix::MetadataReferences { removed });
+        }
+        metadata = Some(block);
+    }
+
+    if fixes.is_empty() {
+        return Ok((bytes.to_vec(), fixes));
+    }
+    let mut records = Vec::with_capacity(channels.len() * desc_size);
+    for channel in &channels {
+        put_i32(&mut records, channel.metadata);
+        put_u64(&mut records, channel.offset as u64);
+        if ir {
+            put_i32(&mut records, channel.leading_zeros);
+            put_i32(&mut records, channel.length);
+        }
+    }
+    let repaired = assemble(&main_header, content_header, &records, &samples, metadata);
+
+    // Defects that are not recoverable, e.g. in the content header, remain
+    let file = ParsedFile::new(repaired.as_slice())
+        .map_err(|e| Error::new(format!("File could not be repaired: {}", e)))?;
+    for record in 0..file.num_records() {
+        for channel in 0..file.num_channels() {
+            file.layout()
+                .payload(file.bytes(), record, channel)
+                .map_err(|e| Error::new(format!("File could not be repaired: {}", e)))?;
+        }
+    }
+    Ok((repaired, fixes))
+}
+
+/// Lay out the blocks of repaired data like [`legacy::upgrade`] does
+fn assemble(
+    main_header: &[u8],
+    content_header: &[u8],
+    records: &[u8],
+    samples: &[u8],
+    metadata: Option<Vec<u8>>,
+) -> Vec<u8> {
+    let align = |offset: usize| (offset + BLOCK_ALIGNMENT - 1) / BLOCK_ALIGNMENT * BLOCK_ALIGNMENT;
+    let num_blocks = 4 + metadata.is_some() as usize;
+    let main_offset =
+        writer::FILE_HEADER_SIZE as usize + num_blocks * writer::FILE_BLOCK_ENTRY_SIZE as usize;
+    let content_offset = main_offset + main_header.len();
+    let records_offset = align(content_offset + content_header.len());
+    let samples_offset = align(records_offset + records.len());
+    let metadata_offset = samples_offset + samples.len();
+    let metadata = metadata.unwrap_or_default();
+
+    let mut repaired = Vec::with_capacity(metadata_offset + metadata.len());
+    repaired.extend_from_slice(b"FW");
+    put_i32(&mut repaired, legacy::CURRENT_VERSION);
+    put_i32(&mut repaired, num_blocks as i32);
+    let blocks: [(i32, Range<usize>); 5] = [
+        (writer::MAIN_HEADER_ID, main_offset..content_offset),
+        (
+            writer::CONTENT_HEADER_ID,
+            content_offset..content_offset + content_header.len(),
+        ),
+        (
+            writer::RECORD_DESC_ID,
+            records_offset..records_offset + records.len(),
+        ),
+        (writer::DATA_ID, samples_offset..metadata_offset),
+        (
+            writer::METADATA_ID,
+            metadata_offset..metadata_offset + metadata.len(),
+        ),
+    ];
+    for (id, range) in &blocks[..num_blocks] {
+        put_i32(&mut repaired, *id);
+        put_u64(&mut repaired, range.start as u64);
+        put_u64(&mut repaired, range.len() as u64);
+    }
+    repaired.extend_from_slice(main_header);
+    repaired.extend_from_slice(content_header);
+    repaired.resize(records_offset, 0);
+    repaired.extend_from_slice(records);
+    repaired.resize(samples_offset, 0);
+    repaired.extend_from_slice(samples);
+    repaired.extend_from_slice(&metadata);
+    repaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{BlockKind, FileLayout};
+    use crate::test_util::{self, temp_path};
+    use crate::{Dataset, Reader};
+    use std::io::Cursor;
+
+    /// The shared dataset with a global metadata set, which repairs have to keep
+    fn dataset() -> Dataset {
+        let mut dataset = test_util::dataset();
+        dataset
+            .metadata
+            .insert("DESCRIPTION".to_string(), "Session 3".into());
+        dataset
+    }
+
+    /// Byte offset of a field of the file block table entry of a block
+    fn entry(bytes: &[u8], kind: BlockKind, field: usize) -> usize {
+        let layout = FileLayout::read(Cursor::new(bytes)).unwrap();
+        let index = layout
+            .blocks()
+            .iter()
+            .position(|block| block.kind() == Some(kind))
+            .unwrap();
+        10 + index * 20 + field
+    }
+
+    fn read(bytes: &[u8], record: i32, channel: i32) -> Vec<f32> {
+        let mut reader = Reader::new().unwrap();
+        reader.open_bytes(bytes).unwrap();
+        assert_eq!(reader.metadata_string("DESCRIPTION").unwrap(), "Session 3");
+        let ir = reader.content_ir().unwrap();
+        ir.filter_coeffs(record, channel).unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_attempt() {
+        let path = temp_path("repair.daff");
+        writer::write_dataset(&path, &dataset()).unwrap();
+        let report = attempt(&path).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.repaired, None);
+
+        // Wrong record count in the main header
+        let mut bytes = fs::read(&path).unwrap();
+        let original = bytes.clone();
+        let main = FileLayout::read(Cursor::new(&bytes))
+            .unwrap()
+            .block(BlockKind::MainHeader)
+            .unwrap()
+            .offset as usize;
+        bytes[main + 12..main + 16].copy_from_slice(&3i32.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let report = attempt(&path).unwrap();
+        let num_records = dataset().grid.num_records().unwrap();
+        assert_eq!(
+            report.fixes,
+            [Fix::RecordCount {
+                stored: 3,
+                repaired: num_records
+            }]
+        );
+        let repaired = report.repaired.unwrap();
+        assert_eq!(repaired, path.with_extension("repaired.daff"));
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        let repaired_bytes = fs::read(&repaired).unwrap();
+        assert_eq!(read(&repaired_bytes, 7, 1), read(&original, 7, 1));
+        fs::remove_file(&repaired).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_truncated() {
+        let path = temp_path("repair-truncated.daff");
+        writer::write_dataset(&path, &dataset()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let layout = FileLayout::read(Cursor::new(&bytes)).unwrap();
+        let data = layout.block(BlockKind::Data).unwrap().range();
+        let metadata = layout.block(BlockKind::Metadata).unwrap().size;
+
+        // The last record lost the last two samples of its second channel
+        let num_records = dataset().grid.num_records().unwrap();
+        let cut = data.end as usize - 2 * 4;
+        assert!(ParsedFile::new(&bytes[..cut]).is_err());
+        let (repaired, fixes) = repair(&bytes[..cut]).unwrap();
+        assert_eq!(
+            fixes,
+            [
+                Fix::MissingRecords {
+                    records: vec![num_records - 1]
+                },
+                Fix::MetadataLength {
+                    stored: metadata,
+                    repaired: 4
+                },
+            ]
+        );
+        let file = ParsedFile::new(repaired.as_slice()).unwrap();
+        assert!(file.metadata().unwrap().is_empty());
+        let last = file.record(num_records - 1).unwrap();
+        let original = ParsedFile::new(bytes.as_slice()).unwrap();
+        let expected = original.record(num_records - 1).unwrap();
+        assert_eq!(last.channels[0], expected.channels[0]);
+        assert_eq!(last.channels[1][..1], expected.channels[1][..1]);
+        // The stored values are non-zero, so the zeros were filled in
+        assert!(expected.channels[1][1..].iter().all(|&value| value != 0.0));
+        assert_eq!(last.channels[1][1..], [0.0, 0.0]);
+        assert_eq!(file.record(0).unwrap(), original.record(0).unwrap());
+
+        // The descriptor of the last record channel is missing
+        let descriptors = layout.block(BlockKind::RecordDescriptors).unwrap().range();
+        let mut truncated = bytes.clone();
+        let size = entry(&bytes, BlockKind::RecordDescriptors, 12);
+        truncated[size..size + 8]
+            .copy_from_slice(&(descriptors.end - descriptors.start - 20).to_le_bytes());
+        let (repaired, fixes) = repair(&truncated).unwrap();
+        assert!(fixes.contains(&Fix::MissingRecords {
+            records: vec![num_records - 1]
+        }));
+        let file = ParsedFile::new(repaired.as_slice()).unwrap();
+        let last = file.record(num_records - 1).unwrap();
+        assert_eq!(last.channels[0], expected.channels[0]);
+        assert_eq!(last.channels[1], [0.0; 3]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_metadata_length() {
+        let path = temp_path("repair-metadata.daff");
+        writer::write_dataset(&path, &dataset()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let size = entry(&bytes, BlockKind::Metadata, 12);
+        let stored = u64::from_le_bytes(bytes[size..size + 8].try_into().unwrap());
+
+        for wrong in [stored - 3, stored + 100] {
+            let mut damaged = bytes.clone();
+            damaged[size..size + 8].copy_from_slice(&wrong.to_le_bytes());
+            let (repaired, fixes) = repair(&damaged).unwrap();
+            assert_eq!(
+                fixes,
+                [Fix::MetadataLength {
+                    stored: wrong,
+                    repaired: stored
+                }]
+            );
+            assert_eq!(read(&repaired, 0, 0), read(&bytes, 0, 0));
+        }
+        assert!(repair(b"FW").is_err());
+    }
+}
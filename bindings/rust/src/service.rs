@@ -0,0 +1,384 @@
+//! HTTP handlers for a DAFF conversion service
+//!
+//! With the `service` feature, [`router`] provides ready-made [axum] handlers over the
+//! [`pipeline`](crate::pipeline) and [`export`](crate::export) APIs, so an internal conversion
+//! service is a few lines of setup:
+//!
+//! | Route                                  | Response                                                  |
+//! |----------------------------------------|-----------------------------------------------------------|
+//! | `POST /inspect`                        | JSON description of the file without its records          |
+//! | `POST /convert?format=csv`             | Export after the configured pipeline, JSON (default) or CSV |
+//! | `POST /extract?azimuth=30&elevation=0` | JSON export of the record closest to the direction        |
+//!
+//! Every request carries a DAFF file as its body.
+//!
+//! Files that cannot be read or processed are answered with `422 Unprocessable Entity` and
+//! the error message, invalid query parameters with `400 Bad Request`. The work runs on the
+//! blocking thread pool of the runtime. The blocking functions [`inspect`], [`convert`] and
+//! [`extract`] behind the handlers can be called directly to serve the same operations
+//! through other frameworks, e.g. a gRPC service.
+//!
+//! ```no_run
+//! use opendaff::pipeline::Batch;
+//! use opendaff::service::{self, ServiceConfig};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! // Stages and export options from a daff-batch pipeline file
+//! let config = ServiceConfig::from_batch(&Batch::load("pipeline.toml")?);
+//! let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build()?;
+//! runtime.block_on(async {
+//!     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+//!     axum::serve(listener, service::router(config)).await
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::DefaultBodyLimit;
+use axum::routing::post;
+use axum::Router;
+
+use crate::dsp;
+use crate::export::{self, ExportOptions};
+use crate::grid::Grid;
+use crate::pipeline::{Batch, OutputFormat, Pipeline};
+use crate::render::DirectionQuantizer;
+use crate::{Dataset, Error, Reader, Result};
+
+/// Default limit of the request body size in bytes
+pub const DEFAULT_MAX_UPLOAD: usize = 256 << 20;
+
+/// Processing applied by the service
+#[derive(Clone)]
+pub struct ServiceConfig {
+    /// Processing applied to uploaded files by `/convert`
+    pub pipeline: Pipeline,
+    /// Export format of `/convert` if the request does not ask for one
+    pub format: OutputFormat,
+    /// Export options of all routes
+    pub export: ExportOptions,
+    /// Maximum size of uploaded files in bytes
+    pub max_upload: usize,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            pipeline: Pipeline::new(),
+            format: OutputFormat::Json,
+            export: ExportOptions::default(),
+            max_upload: DEFAULT_MAX_UPLOAD,
+        }
+    }
+}
+
+impl ServiceConfig {
+    /// Take the pipeline, export format and options of a batch description
+    pub fn from_batch(batch: &Batch) -> Self {
+        Self {
+            pipeline: batch.pipeline.clone(),
+            format: batch.format,
+            export: batch.export,
+            ..Self::default()
+        }
+    }
+}
+
+/// Direction and channel selection of [`extract`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtractRequest {
+    /// Object view azimuth in degrees
+    pub azimuth: f32,
+    /// Object view elevation in degrees
+    pub elevation: f32,
+    /// Single channel to extract, all channels if `None`
+    pub channel: Option<usize>,
+}
+
+/// Describe a DAFF file as JSON
+///
+/// The description has the layout of [`export::to_json`] without the records, which are
+/// replaced by their count (`num_records`) and the number of channels (`num_channels`).
+pub fn inspect(data: &[u8]) -> Result<Vec<u8>> {
+    let mut dataset = load(data)?;
+    let (num_records, num_channels) = (dataset.num_records(), dataset.num_channels());
+    dataset.records.clear();
+
+    let mut json = Vec::new();
+    export::to_json(&dataset, &ExportOptions::default(), &mut json)?;
+    let mut description: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(&json)
+        .map_err(|e| Error::new(format!("Invalid JSON export: {}", e)))?;
+    description.remove("records");
+    description.insert("num_records".to_string(), num_records.into());
+    description.insert("num_channels".to_string(), num_channels.into());
+    serde_json::to_vec(&description).map_err(|e| Error::new(e.to_string()))
+}
+
+/// Run the configured pipeline on a DAFF file and export the result
+pub fn convert(data: &[u8], config: &ServiceConfig, format: OutputFormat) -> Result<Vec<u8>> {
+    let mut dataset = load(data)?;
+    config.pipeline.run(&mut dataset)?;
+    let mut output = Vec::new();
+    match format {
+        OutputFormat::Json => export::to_json(&dataset, &config.export, &mut output)?,
+        OutputFormat::Csv => export::to_csv(&dataset, &config.export, &mut output)?,
+    }
+    Ok(output)
+}
+
+/// Export the record of a DAFF file closest to a direction as JSON
+///
+/// The export has the layout of [`export::to_json`] with a single record and no grid.
+pub fn extract(data: &[u8], request: &ExtractRequest, options: &ExportOptions) -> Result<Vec<u8>> {
+    let mut dataset = load(data)?;
+    let nearest = DirectionQuantizer::from_dataset(&dataset, 0.0)?
+        .update(request.azimuth, request.elevation)
+        .ok_or_else(|| Error::new("The file has no record for the direction"))?
+        .to;
+    let record = dataset.records.swap_remove(nearest);
+    dataset.records = vec![record];
+    dataset.grid = Grid::Irregular;
+    if let Some(channel) = request.channel {
+        dsp::select_channels(&mut dataset, &[channel])?;
+    }
+    let mut output = Vec::new();
+    export::to_json(&dataset, options, &mut output)?;
+    Ok(output)
+}
+
+/// Router serving `/inspect`, `/convert` and `/extract` (see the [module documentation](self))
+pub fn router(config: ServiceConfig) -> Router {
+    let max_upload = config.max_upload;
+    Router::new()
+        .route("/inspect", post(handlers::inspect))
+        .route("/convert", post(handlers::convert))
+        .route("/extract", post(handlers::extract))
+        .layer(DefaultBodyLimit::max(max_upload))
+        .with_state(Arc::new(config))
+}
+
+/// Axum handlers of the [`router`] routes, for services that mount them on their own paths
+pub mod handlers {
+    use super::*;
+
+    use axum::body::Bytes;
+    use axum::extract::{Query, State};
+    use axum::http::{header, StatusCode};
+    use axum::response::{IntoResponse, Response};
+
+    /// `POST /inspect`, see [`inspect`](super::inspect)
+    pub async fn inspect(body: Bytes) -> Response {
+        run(JSON, move || super::inspect(&body)).await
+    }
+
+    /// `POST /convert`, see [`convert`](super::convert)
+    ///
+    /// The optional query parameter `format` (`json` or `csv`) overrides the configured
+    /// export format.
+    pub async fn convert(
+        State(config): State<Arc<ServiceConfig>>,
+        Query(params): Query<HashMap<String, String>>,
+        body: Bytes,
+    ) -> Response {
+        let format = match params.get("format").map(String::as_str) {
+            None => config.format,
+            Some("json") => OutputFormat::Json,
+            Some("csv") => OutputFormat::Csv,
+            Some(other) => return bad_request(format!("Unknown export format '{}'", other)),
+        };
+        let content_type = match format {
+            OutputFormat::Json => JSON,
+            OutputFormat::Csv => CSV,
+        };
+        run(content_type, move || super::convert(&body, &config, format)).await
+    }
+
+    /// `POST /extract`, see [`extract`](super::extract)
+    ///
+    /// Takes the direction from the query parameters `azimuth` and `elevation` (degrees,
+    /// both required) and an optional `channel`.
+    pub async fn extract(
+        State(config): State<Arc<ServiceConfig>>,
+        Query(params): Query<HashMap<String, String>>,
+        body: Bytes,
+    ) -> Response {
+        let angle = |key: &str| -> std::result::Result<f32, String> {
+            let value = params
+                .get(key)
+                .ok_or_else(|| format!("Query parameter '{}' is required", key))?;
+            value
+                .parse::<f32>()
+                .ok()
+                .filter(|angle| angle.is_finite())
+                .ok_or_else(|| format!("Invalid {} '{}'", key, value))
+        };
+        let channel = match params.get("channel").map(|c| c.parse::<usize>()) {
+            None => None,
+            Some(Ok(channel)) => Some(channel),
+            Some(Err(_)) => return bad_request("Invalid channel".to_string()),
+        };
+        let request = match (angle("azimuth"), angle("elevation")) {
+            (Ok(azimuth), Ok(elevation)) => ExtractRequest {
+                azimuth,
+                elevation,
+                channel,
+            },
+            (Err(message), _) | (_, Err(message)) => return bad_request(message),
+        };
+        run(JSON, move || {
+            super::extract(&body, &request, &config.export)
+        })
+        .await
+    }
+
+    const JSON: &str = "application/json";
+    const CSV: &str = "text/csv";
+
+    /// Run blocking work off the async executor and turn its result into a response
+    async fn run<F>(content_type: &'static str, work: F) -> Response
+    where
+        F: FnOnce() -> Result<Vec<u8>> + Send + 'static,
+    {
+        match tokio::task::spawn_blocking(work).await {
+            Ok(Ok(body)) => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+            Ok(Err(e)) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    fn bad_request(message: String) -> Response {
+        (StatusCode::BAD_REQUEST, message).into_response()
+    }
+}
+
+/// Read an uploaded DAFF file straight from the request body
+fn load(data: &[u8]) -> Result<Dataset> {
+    let mut reader = Reader::new()?;
+    reader.open_bytes(data)?;
+    Dataset::from_reader(&reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Stage;
+    use crate::test_util::temp_path;
+    use crate::{ContentHeader, EquiangularGrid};
+    use axum::body::Bytes;
+    use axum::extract::{Query, State};
+    use axum::http::StatusCode;
+    use std::fs;
+
+    fn upload() -> Vec<u8> {
+        let dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            EquiangularGrid::with_resolution(90.0, 90.0).unwrap(),
+            2,
+            |alpha, _, channel| vec![alpha / 360.0, channel as f32],
+        );
+        let path = temp_path("service.daff");
+        crate::writer::write_dataset(&path, &dataset).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        data
+    }
+
+    fn json(data: &[u8]) -> serde_json::Value {
+        serde_json::from_slice(data).unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_inspect_convert_extract() {
+        let data = upload();
+        let description = json(&inspect(&data).unwrap());
+        assert_eq!(description["content_type"], "Impulse Response");
+        assert_eq!(description["num_records"], 6);
+        assert_eq!(description["num_channels"], 2);
+        assert!(description.get("records").is_none());
+        assert!(inspect(b"not a DAFF file").is_err());
+
+        let config = ServiceConfig {
+            pipeline: Pipeline::new().stage(Stage::Normalize { peak_db: 0.0 }),
+            ..ServiceConfig::default()
+        };
+        let converted = json(&convert(&data, &config, OutputFormat::Json).unwrap());
+        assert_eq!(converted["records"].as_array().unwrap().len(), 6);
+        let csv = convert(&data, &config, OutputFormat::Csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 13);
+
+        let request = ExtractRequest {
+            azimuth: 90.0,
+            elevation: 5.0,
+            channel: Some(1),
+        };
+        let extracted = json(&extract(&data, &request, &ExportOptions::default()).unwrap());
+        let records = extracted["records"].as_array().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["alpha"], 90);
+        assert_eq!(records[0]["channels"], serde_json::json!([[0.25, 1]]));
+        let request = ExtractRequest {
+            channel: Some(2),
+            ..request
+        };
+        assert!(extract(&data, &request, &ExportOptions::default()).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_handlers() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let data = Bytes::from(upload());
+        let config = Arc::new(ServiceConfig::default());
+        let query = |pairs: &[(&str, &str)]| {
+            Query(
+                pairs
+                    .iter()
+                    .map(|&(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<HashMap<_, _>>(),
+            )
+        };
+
+        runtime.block_on(async {
+            let response = handlers::inspect(data.clone()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response = handlers::inspect(Bytes::from_static(b"garbage")).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let response = handlers::convert(
+                State(config.clone()),
+                query(&[("format", "csv")]),
+                data.clone(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], "text/csv");
+            let response = handlers::convert(
+                State(config.clone()),
+                query(&[("format", "xml")]),
+                data.clone(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let response = handlers::extract(
+                State(config.clone()),
+                query(&[("azimuth", "0"), ("elevation", "90")]),
+                data.clone(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response =
+                handlers::extract(State(config.clone()), query(&[("azimuth", "0")]), data).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        });
+        let _ = router(ServiceConfig::default());
+    }
+}
@@ -0,0 +1,204 @@
+//! Real spherical harmonics and regularized least-squares fitting
+//!
+//! Directions are given in the data view (alpha, beta) in degrees. Beta serves as polar angle,
+//! which only rotates the basis and does not affect fits or evaluations.
+
+use crate::{Error, Result};
+
+/// Number of basis functions up to the given order
+pub(crate) fn num_coefficients(order: usize) -> usize {
+    (order + 1) * (order + 1)
+}
+
+/// Orthonormal real spherical harmonics up to `order`, indexed by `n * n + n + m`
+pub(crate) fn real_basis(order: usize, alpha: f32, beta: f32) -> Vec<f64> {
+    let phi = (alpha as f64).to_radians();
+    let (sin_theta, cos_theta) = (beta as f64).to_radians().sin_cos();
+
+    // Fully normalized associated Legendre functions P[n][m], m >= 0
+    let mut p = vec![vec![0.0f64; order + 1]; order + 1];
+    p[0][0] = (1.0 / (4.0 * std::f64::consts::PI)).sqrt();
+    for m in 1..=order {
+        p[m][m] = ((2 * m + 1) as f64 / (2 * m) as f64).sqrt() * sin_theta * p[m - 1][m - 1];
+    }
+    for m in 0..order {
+        p[m + 1][m] = ((2 * m + 3) as f64).sqrt() * cos_theta * p[m][m];
+    }
+    for n in 2..=order {
+        let (lower, upper) = p.split_at_mut(n);
+        for (m, value) in upper[0].iter_mut().enumerate().take(n - 1) {
+            let (n2, m2) = ((n * n) as f64, (m * m) as f64);
+            let a = ((4.0 * n2 - 1.0) / (n2 - m2)).sqrt();
+            let b = (((n - 1) * (n - 1)) as f64 - m2) / (4.0 * ((n - 1) * (n - 1)) as f64 - 1.0);
+            *value = a * (cos_theta * lower[n - 1][m] - b.sqrt() * lower[n - 2][m]);
+        }
+    }
+
+    let mut basis = vec![0.0; num_coefficients(order)];
+    for n in 0..=order {
+        basis[n * n + n] = p[n][0];
+        for m in 1..=n {
+            let scaled = std::f64::consts::SQRT_2 * p[n][m];
+            basis[n * n + n + m] = scaled * (m as f64 * phi).cos();
+            basis[n * n + n - m] = scaled * (m as f64 * phi).sin();
+        }
+    }
+    basis
+}
+
+/// Regularized least-squares fit of spherical harmonic coefficients for a fixed set of
+/// sampling directions
+pub(crate) struct Fit {
+    basis: Vec<Vec<f64>>,
+    cholesky: Vec<Vec<f64>>,
+}
+
+impl Fit {
+    /// Prepare a fit of the given order over the directions
+    ///
+    /// `regularization` is relative to the mean diagonal of the normal equations (Tikhonov).
+    pub(crate) fn new(
+        order: usize,
+        directions: &[(f32, f32)],
+        regularization: f64,
+    ) -> Result<Self> {
+        let size = num_coefficients(order);
+        if directions.len() < size {
+            return Err(Error::new(format!(
+                "Spherical harmonic order {} needs at least {} directions, got {}",
+                order,
+                size,
+                directions.len()
+            )));
+        }
+
+        let basis: Vec<Vec<f64>> = directions
+            .iter()
+            .map(|&(alpha, beta)| real_basis(order, alpha, beta))
+            .collect();
+        let mut normal = vec![vec![0.0f64; size]; size];
+        for row in &basis {
+            for i in 0..size {
+                for j in 0..=i {
+                    normal[i][j] += row[i] * row[j];
+                }
+            }
+        }
+        let lambda = regularization * (0..size).map(|i| normal[i][i]).sum::<f64>() / size as f64;
+        for (i, row) in normal.iter_mut().enumerate() {
+            row[i] += lambda;
+        }
+
+        // Cholesky decomposition of the (symmetric, lower triangle) normal matrix
+        let mut l = vec![vec![0.0f64; size]; size];
+        for i in 0..size {
+            for j in 0..=i {
+                let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+                if i == j {
+                    let d = normal[i][i] - sum;
+                    if d <= 0.0 {
+                        return Err(Error::new("Spherical harmonic fit is ill-conditioned"));
+                    }
+                    l[i][i] = d.sqrt();
+                } else {
+                    l[i][j] = (normal[i][j] - sum) / l[j][j];
+                }
+            }
+        }
+
+        Ok(Self { basis, cholesky: l })
+    }
+
+    /// Coefficients best matching the values sampled at the fit's directions
+    pub(crate) fn coefficients(&self, values: &[f64]) -> Vec<f64> {
+        let size = self.cholesky.len();
+        let mut rhs = vec![0.0f64; size];
+        for (row, &value) in self.basis.iter().zip(values) {
+            for (r, b) in rhs.iter_mut().zip(row) {
+                *r += b * value;
+            }
+        }
+
+        let l = &self.cholesky;
+        let mut y = vec![0.0f64; size];
+        for i in 0..size {
+            y[i] = (rhs[i] - (0..i).map(|k| l[i][k] * y[k]).sum::<f64>()) / l[i][i];
+        }
+        let mut x = vec![0.0f64; size];
+        for i in (0..size).rev() {
+            x[i] = (y[i] - ((i + 1)..size).map(|k| l[k][i] * x[k]).sum::<f64>()) / l[i][i];
+        }
+        x
+    }
+
+    /// Leverage of every sampling direction, the diagonal of the fit's hat matrix
+    ///
+    /// Dividing the residual of a direction by `1 - leverage` yields the residual of a fit
+    /// that leaves that direction out.
+    pub(crate) fn leverages(&self) -> Vec<f64> {
+        let l = &self.cholesky;
+        let size = l.len();
+        self.basis
+            .iter()
+            .map(|row| {
+                let mut y = vec![0.0f64; size];
+                for i in 0..size {
+                    y[i] = (row[i] - (0..i).map(|k| l[i][k] * y[k]).sum::<f64>()) / l[i][i];
+                }
+                y.iter().map(|v| v * v).sum()
+            })
+            .collect()
+    }
+
+    /// Fitted values at the sampling directions for the given coefficients
+    pub(crate) fn fitted(&self, coefficients: &[f64]) -> Vec<f64> {
+        self.basis
+            .iter()
+            .map(|row| evaluate(row, coefficients))
+            .collect()
+    }
+}
+
+/// Evaluate a spherical harmonic expansion given its basis at the target direction
+pub(crate) fn evaluate(basis: &[f64], coefficients: &[f64]) -> f64 {
+    basis.iter().zip(coefficients).map(|(b, c)| b * c).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_reproduces_band_limited_function() {
+        let directions: Vec<(f32, f32)> = (1..8)
+            .flat_map(|b| (0..12).map(move |a| (a as f32 * 30.0, b as f32 * 22.5)))
+            .collect();
+        // Dipole along the polar axis plus a constant: 1 + cos(beta)
+        let values: Vec<f64> = directions
+            .iter()
+            .map(|&(_, beta)| 1.0 + (beta as f64).to_radians().cos())
+            .collect();
+
+        let fit = Fit::new(2, &directions, 1e-9).unwrap();
+        let coefficients = fit.coefficients(&values);
+        let pole = evaluate(&real_basis(2, 0.0, 0.0), &coefficients);
+        assert!((pole - 2.0).abs() < 1e-6);
+        assert!(Fit::new(10, &directions, 0.0).is_err());
+
+        // Order 3 terms in both angles
+        let f = |alpha: f32, beta: f32| {
+            let (a, b) = ((alpha as f64).to_radians(), (beta as f64).to_radians());
+            b.cos().powi(3)
+                + b.sin().powi(2) * b.cos() * (2.0 * a).cos()
+                + b.sin().powi(3) * (3.0 * a).sin()
+        };
+        let values: Vec<f64> = directions.iter().map(|&(a, b)| f(a, b)).collect();
+        let coefficients = Fit::new(4, &directions, 1e-12)
+            .unwrap()
+            .coefficients(&values);
+        for (alpha, beta) in [(0.0, 0.0), (45.0, 10.0), (100.0, 170.0)] {
+            let value = evaluate(&real_basis(4, alpha, beta), &coefficients);
+            assert!((value - f(alpha, beta)).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Signatures of DAFF files
+//!
+//! Institutions distributing licensed HRTF sets sign their files, so recipients can detect
+//! modified copies and confirm who published a file. [`sign`] stores an Ed25519 signature in
+//! the global metadata (as [`SIGNATURE_KEY`], together with the public key of the signer as
+//! [`SIGNER_KEY`]); [`verify`] checks it against the public key the recipient trusts. Files
+//! are signed as they are written with
+//! [`WriterBuilder::signing_key`](crate::writer::WriterBuilder::signing_key):
+//!
+//! ```no_run
+//! use opendaff::signature::{self, SignatureStatus, SigningKey, VerifyingKey};
+//! use opendaff::writer;
+//!
+//! # fn main() -> opendaff::Result<()> {
+//! # let (secret, public, dataset): ([u8; 32], [u8; 32], opendaff::Dataset) = unimplemented!();
+//! // Publisher
+//! let key = SigningKey::from_bytes(&secret);
+//! writer::WriterBuilder::from_dataset(&dataset)?
+//!     .signing_key(key)
+//!     .write_iter("hrir.daff", dataset.elements_per_record(), dataset.records.iter().map(|r| &r.channels))?;
+//!
+//! // Recipient
+//! let publisher = VerifyingKey::from_bytes(&public).expect("valid public key");
+//! let reader = signature::open_verified("hrir.daff", &publisher)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The signature covers every byte of the file up to the metadata block, except the size of
+//! that block in the block table, and all metadata sets apart from the two signature entries.
+//! Any change of the data, headers or metadata therefore invalidates it; so does appending
+//! bytes after the metadata block. Signing a signed file replaces the signature.
+
+use std::fs;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::metadata::{Metadata, MetadataValue};
+use crate::parser::{ByteOrder, Layout};
+use crate::writer::{
+    decode_metadata, encode_metadata, Input, FILE_BLOCK_ENTRY_SIZE, FILE_HEADER_SIZE, METADATA_ID,
+};
+use crate::{Error, Reader, Result};
+
+/// Metadata key of the signature (`ed25519:` followed by 128 hexadecimal digits)
+pub const SIGNATURE_KEY: &str = "SIGNATURE";
+
+/// Metadata key of the public key of the signer (64 hexadecimal digits)
+///
+/// Identifies the signer only: a forger can replace it along with the signature, so
+/// signatures are always verified against a key obtained from the publisher.
+pub const SIGNER_KEY: &str = "SIGNATURE PUBLIC KEY";
+
+/// Prefix of the signed digest, distinguishing it from other uses of the key
+const DOMAIN: &[u8] = b"OpenDAFF file signature 1\0";
+
+/// Outcome of verifying a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureStatus {
+    /// The file carries no signature
+    Unsigned,
+    /// The file was signed with the given key and has not been modified since
+    Valid,
+    /// The file was modified after signing, or signed with another key
+    Invalid,
+}
+
+/// Sign DAFF data, returning the signed data
+///
+/// The signature and public key are added to the global metadata. Fails for byte-swapped
+/// files (convert them with [`swap_byte_order`](crate::parser::swap_byte_order) first) and
+/// files whose metadata block is missing or not the last block.
+pub fn sign(bytes: &[u8], key: &SigningKey) -> Result<Vec<u8>> {
+    let layout = Layout::parse(bytes)?;
+    if layout.byte_order != ByteOrder::LittleEndian {
+        return Err(Error::new(
+            "Byte-swapped files cannot be signed, convert them to little-endian first",
+        ));
+    }
+    let mut sets = metadata_sets(bytes, &layout)?;
+    let signed = Signed::locate(bytes, &layout)?;
+    let signature = key.sign(&signed.digest(bytes, &sets)?);
+
+    let global = &mut sets[0];
+    global.insert(
+        SIGNATURE_KEY.to_string(),
+        MetadataValue::String(format!("ed25519:{}", hex(&signature.to_bytes()))),
+    );
+    global.insert(
+        SIGNER_KEY.to_string(),
+        MetadataValue::String(hex(key.verifying_key().as_bytes())),
+    );
+    let mut metadata = Vec::new();
+    for set in &sets {
+        metadata.extend(encode_metadata(set)?);
+    }
+
+    let mut signed_bytes = bytes[..signed.metadata_start].to_vec();
+    signed_bytes[signed.size_field..signed.size_field + 8]
+        .copy_from_slice(&(metadata.len() as u64).to_le_bytes());
+    signed_bytes.extend(metadata);
+    Ok(signed_bytes)
+}
+
+/// Sign a DAFF file in place, see [`sign`]
+///
+/// The signed file is written next to the original and renamed over it.
+pub fn sign_file(path: impl AsRef<Path>, key: &SigningKey) -> Result<()> {
+    let path = path.as_ref();
+    let signed = sign(&read(path)?, key)?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".part");
+    let write_error =
+        |e: std::io::Error| Error::new(format!("Failed to write '{}': {}", path.display(), e));
+    fs::write(&temp, signed).map_err(write_error)?;
+    fs::rename(&temp, path).map_err(write_error)
+}
+
+/// Verify the signature of DAFF data against the public key of the publisher
+///
+/// Fails only if the data is no valid DAFF file; modified files are reported as
+/// [`Invalid`](SignatureStatus::Invalid).
+pub fn verify(bytes: &[u8], key: &VerifyingKey) -> Result<SignatureStatus> {
+    let layout = Layout::parse(bytes)?;
+    let Some(range) = layout.metadata.clone() else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+    let sets = metadata_sets(bytes, &layout)?;
+    let Some(value) = entry(&sets[0], SIGNATURE_KEY) else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+    let signature = match value {
+        MetadataValue::String(text) => text
+            .strip_prefix("ed25519:")
+            .and_then(unhex)
+            .and_then(|bytes| Signature::from_slice(&bytes).ok()),
+        _ => None,
+    };
+    let Some(signature) = signature else {
+        return Ok(SignatureStatus::Invalid);
+    };
+    if range.end != bytes.len() {
+        return Ok(SignatureStatus::Invalid);
+    }
+    let digest = Signed::locate(bytes, &layout)?.digest(bytes, &sets)?;
+    Ok(match key.verify_strict(&digest, &signature) {
+        Ok(()) => SignatureStatus::Valid,
+        Err(_) => SignatureStatus::Invalid,
+    })
+}
+
+/// Verify the signature of a DAFF file, see [`verify`]
+pub fn verify_file(path: impl AsRef<Path>, key: &VerifyingKey) -> Result<SignatureStatus> {
+    verify(&read(path.as_ref())?, key)
+}
+
+/// Public key a DAFF file claims to be signed with, `None` if it names none
+///
+/// For telling which of several trusted keys to [`verify`] with; the claim itself proves
+/// nothing.
+pub fn signer(bytes: &[u8]) -> Result<Option<VerifyingKey>> {
+    let layout = Layout::parse(bytes)?;
+    if layout.metadata.is_none() {
+        return Ok(None);
+    }
+    let sets = metadata_sets(bytes, &layout)?;
+    Ok(match entry(&sets[0], SIGNER_KEY) {
+        Some(MetadataValue::String(text)) => unhex(text)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok()),
+        _ => None,
+    })
+}
+
+/// Open a DAFF file after verifying its signature
+///
+/// Fails unless the file carries a [valid](SignatureStatus::Valid) signature of `key`. The
+/// verified bytes are handed to the reader, so the file cannot be exchanged in between; like
+/// [`Reader::open_bytes`], the open and close hooks are not called.
+pub fn open_verified(path: impl AsRef<Path>, key: &VerifyingKey) -> Result<Reader> {
+    let path = path.as_ref();
+    let bytes = read(path)?;
+    match verify(&bytes, key)? {
+        SignatureStatus::Valid => {}
+        SignatureStatus::Unsigned => {
+            return Err(Error::new(format!("'{}' is not signed", path.display())))
+        }
+        SignatureStatus::Invalid => {
+            return Err(Error::new(format!(
+                "The signature of '{}' is invalid: the file was modified or signed with \
+                 another key",
+                path.display()
+            )))
+        }
+    }
+    let mut reader = Reader::new()?;
+    reader.open_bytes(&bytes)?;
+    Ok(reader)
+}
+
+/// The signed parts of a file
+struct Signed {
+    /// Position of the size of the metadata block in the block table
+    size_field: usize,
+    metadata_start: usize,
+}
+
+impl Signed {
+    fn locate(bytes: &[u8], layout: &Layout) -> Result<Self> {
+        let not_signable = |reason: &str| Error::new(format!("Cannot sign the file: {}", reason));
+        let metadata = layout
+            .metadata
+            .clone()
+            .ok_or_else(|| not_signable("it has no metadata block"))?;
+        if metadata.end != bytes.len() {
+            return Err(not_signable(
+                "the metadata block is not at the end of the file",
+            ));
+        }
+        // Parsing the layout checked the block table
+        let mut input = Input::with_byte_order(&bytes[6..], layout.byte_order);
+        for index in 0..input.count()? {
+            let entry = FILE_HEADER_SIZE + index as u64 * FILE_BLOCK_ENTRY_SIZE;
+            let id = input.i32()?;
+            input.take(16)?;
+            if id == METADATA_ID {
+                return Ok(Self {
+                    size_field: entry as usize + 12,
+                    metadata_start: metadata.start,
+                });
+            }
+        }
+        Err(not_signable("it has no metadata block"))
+    }
+
+    /// SHA-256 over the signed parts
+    fn digest(&self, bytes: &[u8], sets: &[Metadata]) -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        hasher.update(DOMAIN);
+        hasher.update(&bytes[..self.size_field]);
+        hasher.update([0; 8]);
+        hasher.update(&bytes[self.size_field + 8..self.metadata_start]);
+        for (index, set) in sets.iter().enumerate() {
+            let mut set = set.clone();
+            if index == 0 {
+                set.retain(|key, _| !is_signature_key(key));
+            }
+            hasher.update(encode_metadata(&set)?);
+        }
+        Ok(hasher.finalize().into())
+    }
+}
+
+/// The global metadata set followed by the sets of the records, with keys as stored
+fn metadata_sets(bytes: &[u8], layout: &Layout) -> Result<Vec<Metadata>> {
+    let mut sets = Vec::new();
+    if let Some(range) = layout.metadata.clone() {
+        let mut input = Input::with_byte_order(&bytes[range], layout.byte_order);
+        while !input.is_empty() {
+            sets.push(decode_metadata(&mut input)?);
+        }
+    }
+    if sets.is_empty() {
+        sets.push(Metadata::new());
+    }
+    Ok(sets)
+}
+
+/// Value of a metadata entry, with the key compared case-insensitively
+fn entry<'a>(metadata: &'a Metadata, key: &str) -> Option<&'a MetadataValue> {
+    metadata
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+}
+
+fn is_signature_key(key: &str) -> bool {
+    key.eq_ignore_ascii_case(SIGNATURE_KEY) || key.eq_ignore_ascii_case(SIGNER_KEY)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, temp_path};
+    use crate::writer::WriterBuilder;
+    use crate::{Dataset, Quantization};
+
+    fn dataset() -> Dataset {
+        let mut dataset = test_util::dataset();
+        dataset.quantization = Quantization::Int16;
+        dataset.metadata.insert(
+            "LICENSE".to_string(),
+            MetadataValue::String("CC-BY-4.0".to_string()),
+        );
+        dataset
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_sign_on_write() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let dataset = dataset();
+        let path = temp_path("signed.daff");
+        WriterBuilder::from_dataset(&dataset)
+            .unwrap()
+            .signing_key(key.clone())
+            .record_metadata(3, "MEASURED", true)
+            .write_iter(
+                &path,
+                dataset.elements_per_record(),
+                dataset.records.iter().map(|r| &r.channels),
+            )
+            .unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(
+            verify(&bytes, &key.verifying_key()).unwrap(),
+            SignatureStatus::Valid
+        );
+        assert_eq!(verify(&bytes, &other).unwrap(), SignatureStatus::Invalid);
+        assert_eq!(signer(&bytes).unwrap(), Some(key.verifying_key()));
+        let reader = open_verified(&path, &key.verifying_key()).unwrap();
+        let read = Dataset::from_reader(&reader).unwrap();
+        assert_eq!(read.records.len(), dataset.records.len());
+        assert_eq!(
+            read.metadata["LICENSE"],
+            MetadataValue::String("CC-BY-4.0".to_string())
+        );
+        assert!(read.metadata.contains_key(SIGNATURE_KEY));
+        assert!(open_verified(&path, &other).is_err());
+
+        // Signing again replaces the signature
+        sign_file(&path, &SigningKey::from_bytes(&[8; 32])).unwrap();
+        assert_eq!(verify_file(&path, &other).unwrap(), SignatureStatus::Valid);
+        assert_eq!(fs::read(&path).unwrap().len(), bytes.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampering() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public = key.verifying_key();
+        let dataset = dataset();
+        let path = temp_path("unsigned.daff");
+        crate::writer::write_dataset(&path, &dataset).unwrap();
+        let unsigned = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            verify(&unsigned, &public).unwrap(),
+            SignatureStatus::Unsigned
+        );
+        assert_eq!(signer(&unsigned).unwrap(), None);
+
+        let signed = sign(&unsigned, &key).unwrap();
+        assert_eq!(verify(&signed, &public).unwrap(), SignatureStatus::Valid);
+        let layout = Layout::parse(&signed).unwrap();
+
+        // A sample of the data
+        let mut modified = signed.clone();
+        modified[layout.data.start + 10] ^= 1;
+        assert_eq!(
+            verify(&modified, &public).unwrap(),
+            SignatureStatus::Invalid
+        );
+
+        // The license in the metadata
+        let position = signed.windows(9).position(|w| w == b"CC-BY-4.0").unwrap();
+        let mut modified = signed.clone();
+        modified[position + 6] = b'5';
+        assert_eq!(
+            verify(&modified, &public).unwrap(),
+            SignatureStatus::Invalid
+        );
+
+        // Bytes appended after the metadata
+        let mut modified = signed.clone();
+        modified.extend([0; 4]);
+        if Layout::parse(&modified).is_ok() {
+            assert_eq!(
+                verify(&modified, &public).unwrap(),
+                SignatureStatus::Invalid
+            );
+        }
+
+        // A damaged signature
+        let position = signed.windows(8).position(|w| w == b"ed25519:").unwrap();
+        let mut modified = signed.clone();
+        modified[position + 8] = b'x';
+        assert_eq!(
+            verify(&modified, &public).unwrap(),
+            SignatureStatus::Invalid
+        );
+
+        let swapped = crate::parser::swap_byte_order(&unsigned).unwrap();
+        assert!(sign(&swapped, &key).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Multi-subject collections of DAFF files
+//!
+//! HRTF databases usually consist of one or more DAFF files per measured subject. A
+//! [`SubjectCollection`] groups the files of a directory by the subject ID stored in their
+//! metadata ([`SUBJECT_ID_KEY`]) and attaches anthropometric measurements from sidecar files.
+//!
+//! # Anthropometry sidecars
+//!
+//! Measurements are read from a file next to the DAFF file with the same stem and the
+//! extension [`SIDECAR_EXTENSION`], e.g. `subject_003.anthropometry` for `subject_003.daff`.
+//! Each line holds a `key = value` pair with a numeric value; blank lines and lines starting
+//! with `#` are ignored. Keys are compared case-insensitively and stored in uppercase, like
+//! DAFF metadata keys.
+//!
+//! ```text
+//! # Head and pinna measurements in cm
+//! HEAD_WIDTH = 15.2
+//! PINNA_HEIGHT = 6.4
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Reader, Result};
+
+/// Metadata key holding the subject ID
+pub const SUBJECT_ID_KEY: &str = "SUBJECT_ID";
+
+/// File extension of anthropometry sidecar files
+pub const SIDECAR_EXTENSION: &str = "anthropometry";
+
+/// Anthropometric measurements by (uppercase) name
+pub type Anthropometry = BTreeMap<String, f64>;
+
+/// A subject and the DAFF files measured for it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Subject {
+    /// Subject ID as stored in the metadata
+    pub id: String,
+    /// DAFF files of this subject, sorted by path
+    pub files: Vec<PathBuf>,
+    /// Anthropometric measurements merged from all sidecars of the subject
+    pub anthropometry: Anthropometry,
+}
+
+/// A set of subjects, sorted by subject ID
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SubjectCollection {
+    subjects: Vec<Subject>,
+}
+
+impl SubjectCollection {
+    /// Create a collection from already assembled subjects
+    ///
+    /// Subjects with the same ID are merged.
+    pub fn from_subjects(subjects: impl IntoIterator<Item = Subject>) -> Self {
+        let mut merged: BTreeMap<String, Subject> = BTreeMap::new();
+        for subject in subjects {
+            let entry = merged.entry(subject.id.clone()).or_insert_with(|| Subject {
+                id: subject.id.clone(),
+                ..Subject::default()
+            });
+            entry.files.extend(subject.files);
+            entry.anthropometry.extend(subject.anthropometry);
+        }
+
+        let subjects = merged
+            .into_values()
+            .map(|mut subject| {
+                subject.files.sort();
+                subject.files.dedup();
+                subject
+            })
+            .collect();
+        Self { subjects }
+    }
+
+    /// Scan a directory (non-recursively) for DAFF files and group them by subject
+    ///
+    /// Files without a subject ID in their metadata are skipped. Files that cannot be
+    /// opened and malformed sidecars are reported as errors.
+    pub fn scan(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref();
+        let entries = fs::read_dir(directory).map_err(|e| {
+            Error::new(format!(
+                "Failed to read directory '{}': {}",
+                directory.display(),
+                e
+            ))
+        })?;
+
+        let mut subjects = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| Error::new(format!("Failed to read directory entry: {}", e)))?
+                .path();
+            let is_daff = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("daff"));
+            if !is_daff || !path.is_file() {
+                continue;
+            }
+
+            let Some(id) = subject_id(&path)? else {
+                continue;
+            };
+            let sidecar = path.with_extension(SIDECAR_EXTENSION);
+            let anthropometry = if sidecar.is_file() {
+                let text = fs::read_to_string(&sidecar).map_err(|e| {
+                    Error::new(format!("Failed to read '{}': {}", sidecar.display(), e))
+                })?;
+                parse_anthropometry(&text)?
+            } else {
+                Anthropometry::new()
+            };
+
+            subjects.push(Subject {
+                id,
+                files: vec![path],
+                anthropometry,
+            });
+        }
+
+        Ok(Self::from_subjects(subjects))
+    }
+
+    /// All subjects, sorted by ID
+    pub fn subjects(&self) -> &[Subject] {
+        &self.subjects
+    }
+
+    /// Number of subjects
+    pub fn len(&self) -> usize {
+        self.subjects.len()
+    }
+
+    /// Whether the collection holds no subjects
+    pub fn is_empty(&self) -> bool {
+        self.subjects.is_empty()
+    }
+
+    /// Look up a subject by ID
+    pub fn get(&self, id: &str) -> Option<&Subject> {
+        self.subjects
+            .binary_search_by(|s| s.id.as_str().cmp(id))
+            .ok()
+            .map(|i| &self.subjects[i])
+    }
+
+    /// Select the subject whose anthropometry best matches the target measurements
+    ///
+    /// Each measurement is normalized by its standard deviation across the collection, so
+    /// that measurements with different units and ranges contribute equally. Only keys that
+    /// both the target and a subject provide are compared; subjects sharing no key with the
+    /// target are not considered. Returns the best subject and its mean squared normalized
+    /// distance.
+    pub fn best_match(&self, target: &Anthropometry) -> Option<(&Subject, f64)> {
+        let target: Anthropometry = target
+            .iter()
+            .map(|(key, value)| (key.to_uppercase(), *value))
+            .collect();
+        let scales: BTreeMap<&str, f64> = target
+            .keys()
+            .map(|key| (key.as_str(), self.spread(key)))
+            .collect();
+
+        self.subjects
+            .iter()
+            .filter_map(|subject| {
+                let (sum, count) = target
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let measured = subject.anthropometry.get(key)?;
+                        let d = (measured - value) / scales[key.as_str()];
+                        Some(d * d)
+                    })
+                    .fold((0.0, 0usize), |(sum, count), d| (sum + d, count + 1));
+                (count > 0).then(|| (subject, sum / count as f64))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Standard deviation of a measurement across subjects (1.0 if it does not vary)
+    fn spread(&self, key: &str) -> f64 {
+        let values: Vec<f64> = self
+            .subjects
+            .iter()
+            .filter_map(|s| s.anthropometry.get(key).copied())
+            .collect();
+        if values.len() < 2 {
+            return 1.0;
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+        if variance > 0.0 {
+            variance.sqrt()
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Parse the content of an anthropometry sidecar file
+pub fn parse_anthropometry(text: &str) -> Result<Anthropometry> {
+    let mut anthropometry = Anthropometry::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| Error::new(format!("Line {}: expected 'key = value'", number + 1)))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(Error::new(format!("Line {}: empty key", number + 1)));
+        }
+        let value: f64 = value.trim().parse().map_err(|_| {
+            Error::new(format!(
+                "Line {}: value of '{}' is not a number",
+                number + 1,
+                key
+            ))
+        })?;
+        anthropometry.insert(key.to_uppercase(), value);
+    }
+    Ok(anthropometry)
+}
+
+/// Subject ID of a DAFF file, `None` if the file carries none
+fn subject_id(path: &Path) -> Result<Option<String>> {
+    let reader = Reader::open(path)?;
+
+    if !reader.has_metadata(SUBJECT_ID_KEY) {
+        return Ok(None);
+    }
+    // Numeric IDs are converted to their decimal representation
+    let id = reader.metadata_string(SUBJECT_ID_KEY)?;
+    Ok(Some(id.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(id: &str, measurements: &[(&str, f64)]) -> Subject {
+        Subject {
+            id: id.to_string(),
+            files: vec![PathBuf::from(format!("{}.daff", id))],
+            anthropometry: measurements
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_anthropometry() {
+        let parsed = parse_anthropometry("# cm\nhead_width = 15.5\n\nPINNA_HEIGHT=6\n").unwrap();
+        assert_eq!(parsed.get("HEAD_WIDTH"), Some(&15.5));
+        assert_eq!(parsed.get("PINNA_HEIGHT"), Some(&6.0));
+
+        assert!(parse_anthropometry("HEAD_WIDTH 15.5").is_err());
+        assert!(parse_anthropometry("HEAD_WIDTH = wide").is_err());
+    }
+
+    #[test]
+    fn test_subjects_are_merged_by_id() {
+        let collection = SubjectCollection::from_subjects([
+            subject("B", &[("HEAD_WIDTH", 15.0)]),
+            subject("A", &[]),
+            Subject {
+                files: vec![PathBuf::from("B_near.daff")],
+                ..subject("B", &[("PINNA_HEIGHT", 6.0)])
+            },
+        ]);
+
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.subjects()[0].id, "A");
+        let b = collection.get("B").unwrap();
+        assert_eq!(b.files.len(), 2);
+        assert_eq!(b.anthropometry.len(), 2);
+    }
+
+    #[test]
+    fn test_best_match_normalizes_measurements() {
+        let collection = SubjectCollection::from_subjects([
+            subject("A", &[("HEAD_WIDTH", 14.0), ("PINNA_HEIGHT", 5.0)]),
+            subject("B", &[("HEAD_WIDTH", 16.0), ("PINNA_HEIGHT", 7.0)]),
+            subject("C", &[]),
+        ]);
+        let target: Anthropometry = [("head_width".to_string(), 15.8)].into();
+
+        let (best, distance) = collection.best_match(&target).unwrap();
+        assert_eq!(best.id, "B");
+        assert!(distance < 0.1);
+        assert!(collection.best_match(&Anthropometry::new()).is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//! Fixtures shared by the unit tests
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{writer, ContentHeader, Dataset, EquiangularGrid, MetadataValue, Quantization};
+
+/// Path in the temporary directory ending in `name`, unique per process and call
+///
+/// Tests run in parallel threads and several of them may use the same name, so every call
+/// gets a path of its own.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "opendaff-{}-{}-{}",
+        std::process::id(),
+        count,
+        name
+    ))
+}
+
+/// Binaural impulse responses on a 30° × 45° grid whose values encode direction and channel
+///
+/// Records that are mixed up, mirrored or assigned to the wrong channel differ in their data.
+pub(crate) fn dataset() -> Dataset {
+    Dataset::from_fn(
+        ContentHeader::ImpulseResponse {
+            samplerate: 44100.0,
+        },
+        EquiangularGrid::with_resolution(30.0, 45.0).unwrap(),
+        2,
+        |alpha, beta, channel| vec![alpha / 360.0, beta / 180.0, channel as f32 * 0.5],
+    )
+}
+
+/// Write a two-channel file with the given content and quantization on a 30° × 45° grid to a
+/// [temporary path](temp_path) ending in `name`
+///
+/// Records hold five values, six for magnitude-phase spectra, which encode direction, channel
+/// and position; the file has a global metadata set.
+pub(crate) fn write_file(name: &str, header: ContentHeader, quantization: Quantization) -> PathBuf {
+    let elements = match header {
+        ContentHeader::MagnitudePhaseSpectrum { .. } => 6,
+        _ => 5,
+    };
+    let grid = EquiangularGrid::with_resolution(30.0, 45.0).unwrap();
+    let mut dataset = Dataset::from_fn(header, grid, 2, |alpha, beta, channel| {
+        (0..elements)
+            .map(|i| ((alpha + beta) / 1200.0 + 0.1 * i as f32) * (channel as f32 + 1.0) * 0.5)
+            .collect()
+    });
+    dataset.quantization = quantization;
+    dataset
+        .metadata
+        .insert("Description".into(), MetadataValue::String("Test".into()));
+    let path = temp_path(name);
+    writer::write_dataset(&path, &dataset).unwrap();
+    path
+}
+
+/// Grid of `points` directions evenly spaced in the horizontal plane
+pub(crate) fn ring(points: usize) -> EquiangularGrid {
+    EquiangularGrid {
+        alpha_points: points,
+        alpha_start: 0.0,
+        alpha_end: 360.0,
+        beta_points: 1,
+        beta_start: 90.0,
+        beta_end: 90.0,
+    }
+}
@@ -0,0 +1,371 @@
+//! Keyframed source trajectories for scripted listening tests
+//!
+//! A [`Trajectory`] lists source positions (azimuth, elevation and distance in the object
+//! view) at given times and interpolates linearly between them. Trajectories can be written
+//! by hand as JSON or CSV and are consumed by the offline renderer in
+//! [`audition`](crate::audition), so a listening test renders identically every time.
+//!
+//! JSON, read with the `json-trajectory` feature, lists the keyframes, either as a plain
+//! array or under a `keyframes` field:
+//!
+//! ```json
+//! { "keyframes": [
+//!     { "time": 0.0, "azimuth": 0.0, "elevation": 0.0, "distance": 2.0 },
+//!     { "time": 4.0, "azimuth": 360.0, "elevation": 30.0 }
+//! ] }
+//! ```
+//!
+//! CSV starts with a header row naming the columns; `distance` is optional and lines
+//! starting with `#` are ignored:
+//!
+//! ```text
+//! time,azimuth,elevation,distance
+//! 0.0,0.0,0.0,2.0
+//! 4.0,360.0,30.0,2.0
+//! ```
+//!
+//! Azimuths are not wrapped before interpolating, so going from 0° to 360° is a full turn
+//! to the left and going from 0° to -90° a quarter turn to the right.
+
+use std::fs;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+/// Distance in meters of keyframes that do not specify one
+pub const DEFAULT_DISTANCE: f32 = 1.0;
+
+/// Source position at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Time in seconds
+    pub time: f64,
+    /// Azimuth in degrees (object view)
+    pub azimuth: f32,
+    /// Elevation in degrees (object view)
+    pub elevation: f32,
+    /// Distance in meters
+    pub distance: f32,
+}
+
+impl Keyframe {
+    /// Keyframe at the default distance
+    pub fn new(time: f64, azimuth: f32, elevation: f32) -> Self {
+        Self {
+            time,
+            azimuth,
+            elevation,
+            distance: DEFAULT_DISTANCE,
+        }
+    }
+}
+
+/// Source positions over time, interpolated linearly between keyframes
+///
+/// Before the first and after the last keyframe the source stands still.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Trajectory {
+    /// Create a trajectory from keyframes in order of strictly increasing time
+    pub fn new(keyframes: Vec<Keyframe>) -> Result<Self> {
+        if keyframes.is_empty() {
+            return Err(Error::new("A trajectory needs at least one keyframe"));
+        }
+        for (index, keyframe) in keyframes.iter().enumerate() {
+            let values = [keyframe.azimuth, keyframe.elevation, keyframe.distance];
+            if !keyframe.time.is_finite() || values.iter().any(|v| !v.is_finite()) {
+                return Err(Error::new(format!(
+                    "Keyframe {} has a non-finite value",
+                    index
+                )));
+            }
+            if keyframe.distance <= 0.0 {
+                return Err(Error::new(format!(
+                    "Keyframe {} has a non-positive distance",
+                    index
+                )));
+            }
+        }
+        if let Some(index) = keyframes.windows(2).position(|w| w[1].time <= w[0].time) {
+            return Err(Error::new(format!(
+                "Keyframe {} does not come after the previous one",
+                index + 1
+            )));
+        }
+        Ok(Self { keyframes })
+    }
+
+    /// A source standing still at the given direction and the default distance
+    pub fn fixed(azimuth: f32, elevation: f32) -> Self {
+        Self {
+            keyframes: vec![Keyframe::new(0.0, azimuth, elevation)],
+        }
+    }
+
+    /// Keyframes in order of time
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Time of the last keyframe in seconds
+    pub fn end_time(&self) -> f64 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    /// Interpolated position at the given time in seconds
+    pub fn at(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return Keyframe {
+                time,
+                ..self.keyframes[0]
+            };
+        }
+        let previous = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return Keyframe { time, ..*previous };
+        };
+        let t = ((time - previous.time) / (next.time - previous.time)) as f32;
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Keyframe {
+            time,
+            azimuth: lerp(previous.azimuth, next.azimuth),
+            elevation: lerp(previous.elevation, next.elevation),
+            distance: lerp(previous.distance, next.distance),
+        }
+    }
+
+    /// Parse a trajectory from JSON
+    #[cfg(feature = "json-trajectory")]
+    pub fn from_json(text: &str) -> Result<Self> {
+        let invalid = |message: String| Error::new(format!("Invalid trajectory: {}", message));
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let keyframes = match &value {
+            serde_json::Value::Array(keyframes) => keyframes,
+            serde_json::Value::Object(object) => match object.get("keyframes") {
+                Some(serde_json::Value::Array(keyframes)) => keyframes,
+                _ => return Err(invalid("expected a 'keyframes' array".to_string())),
+            },
+            _ => return Err(invalid("expected an array of keyframes".to_string())),
+        };
+
+        let mut parsed = Vec::with_capacity(keyframes.len());
+        for (index, keyframe) in keyframes.iter().enumerate() {
+            let serde_json::Value::Object(fields) = keyframe else {
+                return Err(invalid(format!("keyframe {} is not an object", index)));
+            };
+            let mut time = None;
+            let mut azimuth = None;
+            let mut elevation = None;
+            let mut distance = DEFAULT_DISTANCE as f64;
+            for (field, value) in fields {
+                let number = value.as_f64().ok_or_else(|| {
+                    invalid(format!("'{}' of keyframe {} is not a number", field, index))
+                })?;
+                match field.as_str() {
+                    "time" => time = Some(number),
+                    "azimuth" => azimuth = Some(number),
+                    "elevation" => elevation = Some(number),
+                    "distance" => distance = number,
+                    _ => {
+                        return Err(invalid(format!(
+                            "unexpected field '{}' in keyframe {}",
+                            field, index
+                        )))
+                    }
+                }
+            }
+            let required = |value: Option<f64>, name: &str| {
+                value.ok_or_else(|| invalid(format!("keyframe {} has no '{}'", index, name)))
+            };
+            parsed.push(Keyframe {
+                time: required(time, "time")?,
+                azimuth: required(azimuth, "azimuth")? as f32,
+                elevation: required(elevation, "elevation")? as f32,
+                distance: distance as f32,
+            });
+        }
+        Self::new(parsed)
+    }
+
+    /// Parse a trajectory from CSV with a header row
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let Some((_, header)) = lines.next() else {
+            return Err(Error::new("Invalid trajectory: no header row"));
+        };
+        let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+        let column = |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name));
+        let (Some(time), Some(azimuth), Some(elevation)) =
+            (column("time"), column("azimuth"), column("elevation"))
+        else {
+            return Err(Error::new(
+                "Invalid trajectory: the header must name the time, azimuth and elevation columns",
+            ));
+        };
+        let distance = column("distance");
+
+        let mut keyframes = Vec::new();
+        for (number, line) in lines {
+            let values = line
+                .split(',')
+                .map(|v| v.trim().parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| Error::new(format!("Invalid trajectory: line {}: {}", number, e)))?;
+            if values.len() != columns.len() {
+                return Err(Error::new(format!(
+                    "Invalid trajectory: line {} has {} values, expected {}",
+                    number,
+                    values.len(),
+                    columns.len()
+                )));
+            }
+            keyframes.push(Keyframe {
+                time: values[time],
+                azimuth: values[azimuth] as f32,
+                elevation: values[elevation] as f32,
+                distance: distance.map_or(DEFAULT_DISTANCE, |c| values[c] as f32),
+            });
+        }
+        Self::new(keyframes)
+    }
+
+    /// Load a trajectory from a `.json` or `.csv` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::new(format!("Failed to read '{}': {}", path.display(), e)))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let trajectory = if extension.eq_ignore_ascii_case("json") {
+            #[cfg(feature = "json-trajectory")]
+            let trajectory = Self::from_json(&text);
+            #[cfg(not(feature = "json-trajectory"))]
+            let trajectory = Err(Error::new(
+                "Reading JSON trajectories requires the `json-trajectory` feature",
+            ));
+            trajectory
+        } else if extension.eq_ignore_ascii_case("csv") {
+            Self::from_csv(&text)
+        } else {
+            return Err(Error::new(format!(
+                "'{}' is neither a .json nor a .csv file",
+                path.display()
+            )));
+        };
+        trajectory.map_err(|e| match e {
+            Error::Message(message) => Error::new(format!("{}: {}", path.display(), message)),
+            other => other,
+        })
+    }
+}
+
+/// Anything that places a source over time
+///
+/// Implemented for [`Trajectory`] and for closures mapping the time in seconds to the
+/// object view direction (azimuth, elevation in degrees).
+pub trait Motion {
+    /// Object view direction (azimuth, elevation in degrees) at the given time in seconds
+    fn direction(&self, time: f64) -> (f32, f32);
+}
+
+impl<F: Fn(f64) -> (f32, f32)> Motion for F {
+    fn direction(&self, time: f64) -> (f32, f32) {
+        self(time)
+    }
+}
+
+impl Motion for Trajectory {
+    fn direction(&self, time: f64) -> (f32, f32) {
+        let position = self.at(time);
+        (position.azimuth, position.elevation)
+    }
+}
+
+impl Motion for &Trajectory {
+    fn direction(&self, time: f64) -> (f32, f32) {
+        (*self).direction(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolation() {
+        let trajectory = Trajectory::new(vec![
+            Keyframe::new(1.0, 0.0, 0.0),
+            Keyframe {
+                distance: 3.0,
+                ..Keyframe::new(3.0, 360.0, 30.0)
+            },
+        ])
+        .unwrap();
+        assert_eq!(trajectory.end_time(), 3.0);
+        assert_eq!(trajectory.direction(0.0), (0.0, 0.0));
+        assert_eq!(trajectory.at(2.5).azimuth, 270.0);
+        assert_eq!(trajectory.at(2.0).distance, 2.0);
+        assert_eq!(trajectory.direction(10.0), (360.0, 30.0));
+        assert_eq!(Trajectory::fixed(90.0, -10.0).direction(5.0), (90.0, -10.0));
+
+        assert!(Trajectory::new(Vec::new()).is_err());
+        assert!(Trajectory::new(vec![Keyframe::new(1.0, 0.0, 0.0); 2]).is_err());
+        assert!(Trajectory::new(vec![Keyframe::new(f64::NAN, 0.0, 0.0)]).is_err());
+        let behind = Keyframe {
+            distance: 0.0,
+            ..Keyframe::new(0.0, 180.0, 0.0)
+        };
+        assert!(Trajectory::new(vec![behind]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "json-trajectory")]
+    fn test_from_json() {
+        let trajectory = Trajectory::from_json(
+            r#"{ "keyframes": [
+                { "time": 0, "azimuth": 0, "elevation": 0, "distance": 2 },
+                { "time": 4, "azimuth": -90, "elevation": 30 }
+            ] }"#,
+        )
+        .unwrap();
+        assert_eq!(trajectory.keyframes()[1], Keyframe::new(4.0, -90.0, 30.0));
+        assert_eq!(trajectory.keyframes()[0].distance, 2.0);
+        let plain = Trajectory::from_json(r#"[{"time": 0, "azimuth": 45, "elevation": 0}]"#);
+        assert_eq!(plain.unwrap(), Trajectory::fixed(45.0, 0.0));
+
+        assert!(Trajectory::from_json("[]").is_err());
+        assert!(Trajectory::from_json(r#"[{"time": 0, "azimuth": 0}]"#).is_err());
+        assert!(
+            Trajectory::from_json(r#"[{"time": 0, "azimuth": 0, "elevation": "up"}]"#).is_err()
+        );
+        assert!(Trajectory::from_json(r#"{"frames": []}"#).is_err());
+        assert!(Trajectory::from_json("{").is_err());
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let trajectory = Trajectory::from_csv(
+            "# circle at ear level\nElevation, time, azimuth\n0, 0, 0\n\n0, 2.5, 180\n",
+        )
+        .unwrap();
+        assert_eq!(
+            trajectory.keyframes(),
+            [Keyframe::new(0.0, 0.0, 0.0), Keyframe::new(2.5, 180.0, 0.0)]
+        );
+        let near = Trajectory::from_csv("time,azimuth,elevation,distance\n0,0,0,0.5").unwrap();
+        assert_eq!(near.keyframes()[0].distance, 0.5);
+
+        assert!(Trajectory::from_csv("").is_err());
+        assert!(Trajectory::from_csv("time,azimuth\n0,0").is_err());
+        assert!(Trajectory::from_csv("time,azimuth,elevation\n0,0").is_err());
+        assert!(Trajectory::from_csv("time,azimuth,elevation\n0,x,0").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! JavaScript bindings for browsers
+//!
+//! With the `wasm-bindgen` feature, the crate exports a [`DaffFile`] class to JavaScript.
+//! It reads DAFF data with the pure-Rust [parser](crate::parser), so HRTF pickers and
+//! AudioWorklet renderers in the browser use the same lookups as native applications:
+//!
+//! ```js
+//! const bytes = await (await fetch("hrtf.daff")).arrayBuffer();
+//! const hrtf = DaffFile.fromArrayBuffer(bytes);
+//! const left = hrtf.getIr(30, 0, 0); // Float32Array
+//! console.log(hrtf.contentType, hrtf.metadata().DESCRIPTION);
+//! ```
+//!
+//! In an AudioWorklet, [`readIr`](DaffFile::read_ir) fills a preallocated `Float32Array`
+//! instead, so the audio thread does not allocate.
+
+use wasm_bindgen::prelude::*;
+
+use crate::lookup::GridLookup;
+use crate::parser::ParsedFile;
+use crate::{legacy, ContentHeader, ContentType, Error, MetadataValue, Result};
+
+/// A DAFF file in memory, exported to JavaScript
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct DaffFile {
+    file: ParsedFile,
+    lookup: GridLookup,
+}
+
+impl DaffFile {
+    /// Parse DAFF data, upgrading files in a legacy format version
+    pub fn parse(bytes: Vec<u8>) -> Result<Self> {
+        let bytes = match legacy::detect_version(&bytes) {
+            Some(version) if legacy::is_legacy(version) => legacy::upgrade(&bytes)?,
+            _ => bytes,
+        };
+        let file = ParsedFile::new(bytes)?;
+        let lookup = GridLookup::new(file.grid(), file.orientation())?;
+        Ok(Self { file, lookup })
+    }
+
+    /// The parsed file
+    pub fn file(&self) -> &ParsedFile {
+        &self.file
+    }
+
+    fn ir_record(&self, azimuth: f32, elevation: f32, channel: usize) -> Result<usize> {
+        if self.file.content_type() != ContentType::ImpulseResponse {
+            return Err(Error::new(format!(
+                "Impulse responses requested from {} content",
+                self.file.content_type()
+            )));
+        }
+        if channel >= self.file.num_channels() {
+            return Err(Error::new(format!("Invalid channel index {}", channel)));
+        }
+        Ok(self.lookup.nearest_neighbour(azimuth, elevation))
+    }
+
+    fn ir(&self, azimuth: f32, elevation: f32, channel: usize) -> Result<Vec<f32>> {
+        let record = self.ir_record(azimuth, elevation, channel)?;
+        Ok(self.file.channel(record, channel)?.into_owned())
+    }
+
+    fn read_ir_into(
+        &self,
+        azimuth: f32,
+        elevation: f32,
+        channel: usize,
+        data: &mut [f32],
+    ) -> Result<()> {
+        let record = self.ir_record(azimuth, elevation, channel)?;
+        Ok(self.file.read_channel(record, channel, data)?)
+    }
+}
+
+#[wasm_bindgen]
+impl DaffFile {
+    /// Parse DAFF data from a `Uint8Array`
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: Vec<u8>) -> std::result::Result<DaffFile, JsError> {
+        Self::parse(bytes).map_err(to_js)
+    }
+
+    /// Parse DAFF data from an `ArrayBuffer`, e.g. the body of a `fetch` response
+    #[wasm_bindgen(js_name = fromArrayBuffer)]
+    pub fn from_array_buffer(
+        buffer: &js_sys::ArrayBuffer,
+    ) -> std::result::Result<DaffFile, JsError> {
+        Self::parse(js_sys::Uint8Array::new(buffer).to_vec()).map_err(to_js)
+    }
+
+    /// Content type, e.g. "Impulse Response"
+    #[wasm_bindgen(getter, js_name = contentType)]
+    pub fn content_type(&self) -> String {
+        self.file.content_type().to_string()
+    }
+
+    /// Sampling rate in Hz of impulse responses and DFT spectra
+    #[wasm_bindgen(getter)]
+    pub fn samplerate(&self) -> Option<f64> {
+        match *self.file.header() {
+            ContentHeader::ImpulseResponse { samplerate }
+            | ContentHeader::DftSpectrum { samplerate, .. } => Some(samplerate),
+            _ => None,
+        }
+    }
+
+    /// Support frequencies in Hz of magnitude, phase and magnitude-phase spectra
+    #[wasm_bindgen(getter)]
+    pub fn frequencies(&self) -> Option<Vec<f32>> {
+        match self.file.header() {
+            ContentHeader::MagnitudeSpectrum { frequencies }
+            | ContentHeader::PhaseSpectrum { frequencies }
+            | ContentHeader::MagnitudePhaseSpectrum { frequencies } => Some(frequencies.clone()),
+            _ => None,
+        }
+    }
+
+    /// Number of channels
+    #[wasm_bindgen(getter, js_name = numChannels)]
+    pub fn num_channels(&self) -> usize {
+        self.file.num_channels()
+    }
+
+    /// Number of records
+    #[wasm_bindgen(getter, js_name = numRecords)]
+    pub fn num_records(&self) -> usize {
+        self.file.num_records()
+    }
+
+    /// Number of values per record and channel (the filter length for impulse responses)
+    #[wasm_bindgen(getter, js_name = elementsPerRecord)]
+    pub fn elements_per_record(&self) -> usize {
+        self.file.elements_per_record()
+    }
+
+    /// Index of the record closest to an object view direction (degrees)
+    #[wasm_bindgen(js_name = nearestNeighbour)]
+    pub fn nearest_neighbour(&self, azimuth: f32, elevation: f32) -> usize {
+        self.lookup.nearest_neighbour(azimuth, elevation)
+    }
+
+    /// Data of one record channel as a `Float32Array`
+    #[wasm_bindgen(js_name = getRecord)]
+    pub fn get_record(
+        &self,
+        record: usize,
+        channel: usize,
+    ) -> std::result::Result<Vec<f32>, JsError> {
+        Ok(self
+            .file
+            .channel(record, channel)
+            .map_err(to_js)?
+            .into_owned())
+    }
+
+    /// Impulse response of one channel towards an object view direction (degrees) as a
+    /// `Float32Array`
+    #[wasm_bindgen(js_name = getIr)]
+    pub fn get_ir(
+        &self,
+        azimuth: f32,
+        elevation: f32,
+        channel: usize,
+    ) -> std::result::Result<Vec<f32>, JsError> {
+        self.ir(azimuth, elevation, channel).map_err(to_js)
+    }
+
+    /// Copy the impulse response of one channel towards an object view direction (degrees)
+    /// into a `Float32Array` of [`elementsPerRecord`](DaffFile::elements_per_record) values
+    #[wasm_bindgen(js_name = readIr)]
+    pub fn read_ir(
+        &self,
+        azimuth: f32,
+        elevation: f32,
+        channel: usize,
+        data: &mut [f32],
+    ) -> std::result::Result<(), JsError> {
+        self.read_ir_into(azimuth, elevation, channel, data)
+            .map_err(to_js)
+    }
+
+    /// Global metadata as an object, with keys in upper case
+    pub fn metadata(&self) -> std::result::Result<js_sys::Object, JsError> {
+        let object = js_sys::Object::new();
+        for (key, value) in self.file.metadata().map_err(to_js)? {
+            let value = match value {
+                MetadataValue::Bool(value) => JsValue::from_bool(value),
+                MetadataValue::Int(value) => JsValue::from(value),
+                MetadataValue::Float(value) => JsValue::from_f64(value),
+                MetadataValue::String(value) => JsValue::from_str(&value),
+            };
+            js_sys::Reflect::set(&object, &JsValue::from_str(&key), &value)
+                .map_err(|_| JsError::new("Failed to set a metadata property"))?;
+        }
+        Ok(object)
+    }
+}
+
+fn to_js(error: impl Into<Error>) -> JsError {
+    JsError::new(&error.into().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::EquiangularGrid;
+    use crate::test_util::temp_path;
+    use crate::{writer, Dataset};
+
+    fn bytes(header: ContentHeader) -> Vec<u8> {
+        let grid = EquiangularGrid::with_resolution(30.0, 45.0).unwrap();
+        let dataset = Dataset::from_fn(header, grid, 2, |alpha, beta, channel| {
+            vec![alpha / 360.0, beta / 180.0, channel as f32]
+        });
+        let path = temp_path("wasm.daff");
+        writer::write_dataset(&path, &dataset).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_ir_lookup() {
+        let file = DaffFile::parse(bytes(ContentHeader::ImpulseResponse {
+            samplerate: 48000.0,
+        }))
+        .unwrap();
+        assert_eq!(file.samplerate(), Some(48000.0));
+        assert_eq!(file.frequencies(), None);
+        assert_eq!(file.num_channels(), 2);
+
+        let record = file.nearest_neighbour(90.0, 0.0);
+        let (alpha, beta) = file.file().grid().record_coords(record).unwrap();
+        let ir = file.ir(90.0, 0.0, 1).unwrap();
+        assert_eq!(ir, [alpha / 360.0, beta / 180.0, 1.0]);
+
+        let mut data = vec![0.0; file.elements_per_record()];
+        file.read_ir_into(90.0, 0.0, 1, &mut data).unwrap();
+        assert_eq!(data, ir);
+        assert!(file.read_ir_into(90.0, 0.0, 2, &mut data).is_err());
+        assert!(file.read_ir_into(90.0, 0.0, 0, &mut data[1..]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_spectra() {
+        let file = DaffFile::parse(bytes(ContentHeader::MagnitudeSpectrum {
+            frequencies: vec![250.0, 1000.0, 4000.0],
+        }))
+        .unwrap();
+        assert_eq!(file.frequencies(), Some(vec![250.0, 1000.0, 4000.0]));
+        assert!(file.ir(0.0, 0.0, 0).is_err());
+        assert!(DaffFile::parse(b"FW".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Minimal RIFF/WAVE reading and writing
+//!
+//! Reads uncompressed PCM (8, 16, 24 and 32 bit) and IEEE float (32 and 64 bit) files,
+//! including the `WAVE_FORMAT_EXTENSIBLE` variants, and writes 32-bit float files.
+
+use std::fs;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Decoded audio: sampling rate and one vector of samples per channel
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Wav {
+    pub(crate) samplerate: u32,
+    pub(crate) channels: Vec<Vec<f32>>,
+}
+
+impl Wav {
+    /// Average of all channels
+    pub(crate) fn mono(&self) -> Vec<f32> {
+        let length = self.channels.first().map_or(0, Vec::len);
+        let scale = 1.0 / self.channels.len().max(1) as f32;
+        (0..length)
+            .map(|i| self.channels.iter().map(|c| c[i]).sum::<f32>() * scale)
+            .collect()
+    }
+}
+
+/// Read a WAV file
+pub(crate) fn read(path: &Path) -> Result<Wav> {
+    let bytes = fs::read(path)
+        .map_err(|e| Error::new(format!("Failed to read WAV file {}: {}", path.display(), e)))?;
+    decode(&bytes).map_err(|e| match e {
+        Error::Message(message) => Error::new(format!("{}: {}", path.display(), message)),
+        other => other,
+    })
+}
+
+/// Write a WAV file with 32-bit float samples
+pub(crate) fn write(path: &Path, wav: &Wav) -> Result<()> {
+    fs::write(path, encode(wav)).map_err(|e| {
+        Error::new(format!(
+            "Failed to write WAV file {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Wav> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(Error::new("Not a RIFF/WAVE file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+        let start = position + 8;
+        // Writers that stream audio may leave the size of the last chunk unset
+        let end = start.saturating_add(size as usize).min(bytes.len());
+        match id {
+            b"fmt " => format = Some(&bytes[start..end]),
+            b"data" => data = Some(&bytes[start..end]),
+            _ => {}
+        }
+        position = end + (size as usize & 1);
+    }
+
+    let format = format.ok_or_else(|| Error::new("WAV file has no format chunk"))?;
+    let data = data.ok_or_else(|| Error::new("WAV file has no data chunk"))?;
+    if format.len() < 16 {
+        return Err(Error::new("WAV format chunk is too short"));
+    }
+    let u16_at = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+    let mut tag = u16_at(0);
+    let num_channels = u16_at(2) as usize;
+    let samplerate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = u16_at(14);
+    if tag == FORMAT_EXTENSIBLE {
+        if format.len() < 26 {
+            return Err(Error::new("WAV extensible format chunk is too short"));
+        }
+        tag = u16_at(24);
+    }
+    if num_channels == 0 || samplerate == 0 {
+        return Err(Error::new("WAV file has no channels or no sampling rate"));
+    }
+
+    let width = bits as usize / 8;
+    let sample: fn(&[u8]) -> f32 = match (tag, bits) {
+        (FORMAT_PCM, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (FORMAT_PCM, 24) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+        (FORMAT_PCM, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0,
+        (FORMAT_IEEE_FLOAT, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (FORMAT_IEEE_FLOAT, 64) => |b| f64::from_le_bytes(b[..8].try_into().unwrap()) as f32,
+        _ => {
+            return Err(Error::new(format!(
+                "Unsupported WAV encoding (format {}, {} bit)",
+                tag, bits
+            )))
+        }
+    };
+
+    let frame = width * num_channels;
+    let mut channels = vec![Vec::with_capacity(data.len() / frame); num_channels];
+    for frame in data.chunks_exact(frame) {
+        for (channel, bytes) in channels.iter_mut().zip(frame.chunks_exact(width)) {
+            channel.push(sample(bytes));
+        }
+    }
+    Ok(Wav {
+        samplerate,
+        channels,
+    })
+}
+
+pub(crate) fn encode(wav: &Wav) -> Vec<u8> {
+    let num_channels = wav.channels.len();
+    let length = wav.channels.first().map_or(0, Vec::len);
+    let data_size = (length * num_channels * 4) as u32;
+
+    let mut bytes = Vec::with_capacity(58 + data_size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(50 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&18u32.to_le_bytes());
+    bytes.extend_from_slice(&FORMAT_IEEE_FLOAT.to_le_bytes());
+    bytes.extend_from_slice(&(num_channels as u16).to_le_bytes());
+    bytes.extend_from_slice(&wav.samplerate.to_le_bytes());
+    bytes.extend_from_slice(&(wav.samplerate * num_channels as u32 * 4).to_le_bytes());
+    bytes.extend_from_slice(&(num_channels as u16 * 4).to_le_bytes());
+    bytes.extend_from_slice(&32u16.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+
+    // Non-PCM formats carry the number of frames in a fact chunk
+    bytes.extend_from_slice(b"fact");
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for i in 0..length {
+        for channel in &wav.channels {
+            bytes.extend_from_slice(&channel[i].to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_round_trip() {
+        let wav = Wav {
+            samplerate: 48000,
+            channels: vec![vec![0.5, -1.0, 0.25], vec![0.0, 1.0, -0.125]],
+        };
+        let bytes = encode(&wav);
+        assert_eq!(decode(&bytes).unwrap(), wav);
+        assert_eq!(decode(&bytes).unwrap().mono(), [0.25, 0.0, 0.0625]);
+    }
+
+    #[test]
+    fn test_decode_pcm() {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        bytes.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        bytes.extend_from_slice(b"fmt \x10\0\0\0");
+        bytes.extend_from_slice(&[1, 0, 1, 0]);
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&88200u32.to_le_bytes());
+        bytes.extend_from_slice(&[2, 0, 16, 0]);
+        bytes.extend_from_slice(b"data\x06\0\0\0");
+        for sample in [16384i16, -32768, 0] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        let wav = decode(&bytes).unwrap();
+        assert_eq!(wav.samplerate, 44100);
+        assert_eq!(wav.channels, [[0.5, -1.0, 0.0]]);
+
+        bytes[32] = 2;
+        assert!(decode(&bytes).is_err());
+        assert!(decode(b"RIFF\0\0\0\0AVI ").is_err());
+    }
+}
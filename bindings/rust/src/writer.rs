@@ -0,0 +1,3339 @@
+//! Native DAFF v1.7 serializer
+//!
+//! A [`Writer`] creates a DAFF file incrementally: [`Writer::create`] writes the headers and
+//! reserves space for the record descriptors, [`Writer::append_record`] streams one record
+//! at a time to disk in storage order, and [`Writer::finalize`] writes the metadata and fills
+//! in everything that is only known at the end (descriptors, peak values, block sizes). Only
+//! the descriptors are kept in memory, so measurement software can write files live during
+//! long sessions.
+//!
+//! # Crash safety
+//!
+//! While a file is written, the data goes to `<file>.part` next to the target and only
+//! [`finalize`](Writer::finalize) renames it to the target path, so an interrupted session
+//! never leaves a truncated file under the final name. Every appended record is handed to the
+//! operating system immediately and changes of the metadata are journaled to
+//! `<file>.journal`. After a crash, [`Writer::resume`] reopens the partial file, drops an
+//! incompletely written trailing record and continues with the next direction.
+//!
+//! # Live monitoring
+//!
+//! After each record the writer publishes the new size of the data block in the block table
+//! of the partial file, only after the record data itself has been written. A [`Monitor`] in
+//! another thread or process reads that size to find out how many records are complete and
+//! can read them while the writer keeps appending, e.g. for quality control displays during
+//! a measurement. There is a single writer; any number of monitors may watch a file.
+//!
+//! # Per-record metadata
+//!
+//! Besides the global metadata, DAFF 1.7 files can store a metadata set for each record,
+//! e.g. calibration data such as the microphone gain or the time a direction was measured.
+//! [`Writer::set_record_metadata`] (or [`WriterBuilder::record_metadata`]) attaches such a
+//! set to a record; identical sets of several records are stored once.
+//!
+//! All values are written little endian, as required by the file format.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "complex")]
+use num_complex::Complex;
+
+use crate::dataset::{ContentHeader, Dataset, Record};
+#[cfg(feature = "encryption")]
+use crate::encryption::Key;
+use crate::grid::EquiangularGrid;
+use crate::legacy;
+use crate::metadata::{self, Metadata, MetadataValue};
+use crate::parser::ByteOrder;
+use crate::{ContentType, Error, Orientation, Quantization, Reader, Result};
+#[cfg(feature = "signing")]
+use ed25519_dalek::SigningKey;
+
+pub(crate) use opendaff_core::format::{
+    CONTENT_HEADER_ID, DATA_ID, DEFAULT_DESC_SIZE, FILE_BLOCK_ENTRY_SIZE, FILE_HEADER_SIZE,
+    IR_DESC_SIZE, MAIN_HEADER_ID, MAIN_HEADER_SIZE, METADATA_ID, RECORD_DESC_ID,
+};
+pub(crate) use opendaff_core::input::{decode_into, decode_metadata, quantization_size, Input};
+
+/// Number of file blocks written (main header, content header, record descriptors, data,
+/// metadata)
+const NUM_FILE_BLOCKS: usize = 5;
+/// Alignment of the record descriptor and data blocks
+const BLOCK_ALIGNMENT: u64 = 16;
+/// Extension appended to the target path for the file while it is written
+const PART_EXTENSION: &str = "part";
+/// Extension appended to the target path for the metadata journal
+const JOURNAL_EXTENSION: &str = "journal";
+/// Position of the data block size in the block table, where the writer publishes progress
+const DATA_SIZE_POSITION: u64 = FILE_HEADER_SIZE + 3 * FILE_BLOCK_ENTRY_SIZE + 4 + 8;
+
+/// Version of the DAFF file format to write
+///
+/// Version 1.7 is the version read by the OpenDAFF library in this package and by the VA
+/// and RAVEN builds based on it. Version 1.05 is the [legacy](crate::legacy) layout of older
+/// builds; it has no record metadata and addresses at most 2 GiB.
+///
+/// Partial files always have the layout of version 1.7, so they can be
+/// [resumed](Writer::resume) and [monitored](Monitor) alike; their header names the target
+/// version, and [`Writer::finalize`] converts them to the legacy layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum FormatVersion {
+    /// DAFF 1.05 (version number 105)
+    V1_05,
+    /// DAFF 1.7 (version number 170)
+    #[default]
+    V1_7,
+}
+
+impl FormatVersion {
+    /// Version number stored in the file header
+    pub fn number(self) -> i32 {
+        match self {
+            FormatVersion::V1_05 => legacy::LEGACY_VERSION,
+            FormatVersion::V1_7 => legacy::CURRENT_VERSION,
+        }
+    }
+
+    /// Version with the given version number, if it can be written
+    pub fn from_number(number: i32) -> Option<Self> {
+        match number {
+            legacy::LEGACY_VERSION => Some(FormatVersion::V1_05),
+            legacy::CURRENT_VERSION => Some(FormatVersion::V1_7),
+            _ => None,
+        }
+    }
+
+    /// Whether files in this version can store metadata sets of individual records
+    pub fn supports_record_metadata(self) -> bool {
+        self == FormatVersion::V1_7
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FormatVersion::V1_05 => "1.05",
+            FormatVersion::V1_7 => "1.7",
+        })
+    }
+}
+
+/// Everything a [`Writer`] needs to know about a file before the first record
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriterSpec {
+    /// Content type and content-specific header values
+    pub header: ContentHeader,
+    /// Quantization of the stored data (spectra are always stored as 32-bit floats)
+    pub quantization: Quantization,
+    /// Sampling grid; records are written in its storage order
+    pub grid: EquiangularGrid,
+    /// Default orientation
+    pub orientation: Orientation,
+    /// File format version
+    pub format_version: FormatVersion,
+    /// Number of channels
+    pub num_channels: usize,
+    /// Number of values per record and channel, in the layout of
+    /// [`Record::channels`](crate::Record::channels): the filter length for impulse responses,
+    /// twice the number of coefficients for interleaved complex content
+    pub elements_per_record: usize,
+}
+
+impl WriterSpec {
+    /// Check the specification for values the file format cannot represent
+    pub fn validate(&self) -> Result<()> {
+        if self.num_channels == 0 {
+            return Err(Error::new("At least one channel is required"));
+        }
+        if self.elements_per_record == 0 {
+            return Err(Error::new("Records must not be empty"));
+        }
+        if self.quantization != Quantization::Float32
+            && self.header.content_type() != ContentType::ImpulseResponse
+        {
+            return Err(Error::new(
+                "Integer quantization is only supported for impulse responses",
+            ));
+        }
+        if self.format_version == FormatVersion::V1_05
+            && (self.record_size() as u128) * (self.grid.num_records() as u128) > i32::MAX as u128
+        {
+            return Err(Error::new(format!(
+                "Version {} cannot store more than 2 GiB of records",
+                self.format_version
+            )));
+        }
+
+        let grid = &self.grid;
+        if grid.alpha_points == 0 || grid.beta_points == 0 {
+            return Err(Error::new("The grid needs at least one point per angle"));
+        }
+        if !(0.0..360.0).contains(&grid.alpha_start) || !(0.0..=360.0).contains(&grid.alpha_end) {
+            return Err(Error::new(format!(
+                "Invalid alpha range [{}, {}]",
+                grid.alpha_start, grid.alpha_end
+            )));
+        }
+        if !(0.0..=180.0).contains(&grid.beta_start)
+            || !(0.0..=180.0).contains(&grid.beta_end)
+            || grid.beta_start > grid.beta_end
+        {
+            return Err(Error::new(format!(
+                "Invalid beta range [{}, {}]",
+                grid.beta_start, grid.beta_end
+            )));
+        }
+
+        let expected = match &self.header {
+            ContentHeader::ImpulseResponse { samplerate } => {
+                if !samplerate.is_finite() || *samplerate <= 0.0 {
+                    return Err(Error::new(format!("Invalid sampling rate {}", samplerate)));
+                }
+                None
+            }
+            ContentHeader::MagnitudeSpectrum { frequencies }
+            | ContentHeader::PhaseSpectrum { frequencies } => {
+                check_frequencies(frequencies)?;
+                Some(frequencies.len())
+            }
+            ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+                check_frequencies(frequencies)?;
+                Some(2 * frequencies.len())
+            }
+            ContentHeader::DftSpectrum {
+                samplerate,
+                transform_size,
+            } => {
+                if !samplerate.is_finite() || *samplerate <= 0.0 {
+                    return Err(Error::new(format!("Invalid sampling rate {}", samplerate)));
+                }
+                let symmetric = 2 * (transform_size / 2 + 1);
+                if self.elements_per_record != symmetric
+                    && self.elements_per_record != 2 * transform_size
+                {
+                    return Err(Error::new(format!(
+                        "DFT records of size {} hold {} or {} values, not {}",
+                        transform_size,
+                        symmetric,
+                        2 * transform_size,
+                        self.elements_per_record
+                    )));
+                }
+                None
+            }
+        };
+        if let Some(expected) = expected {
+            if self.elements_per_record != expected {
+                return Err(Error::new(format!(
+                    "Records must hold {} values, not {}",
+                    expected, self.elements_per_record
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of elements as stored in the main header (complex values count once)
+    fn stored_elements(&self) -> usize {
+        match self.header {
+            ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => {
+                self.elements_per_record / 2
+            }
+            _ => self.elements_per_record,
+        }
+    }
+
+    /// Size of a record on disk
+    fn record_size(&self) -> u64 {
+        let value_size = quantization_size(self.quantization) as u64;
+        (self.num_channels * self.elements_per_record) as u64 * value_size
+    }
+
+    fn desc_size(&self) -> u64 {
+        match self.header {
+            ContentHeader::ImpulseResponse { .. } => IR_DESC_SIZE,
+            _ => DEFAULT_DESC_SIZE,
+        }
+    }
+
+    fn content_header_size(&self) -> u64 {
+        match &self.header {
+            ContentHeader::ImpulseResponse { .. } => 12,
+            ContentHeader::MagnitudeSpectrum { frequencies }
+            | ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+                8 + 4 * frequencies.len() as u64
+            }
+            ContentHeader::PhaseSpectrum { frequencies } => 4 + 4 * frequencies.len() as u64,
+            ContentHeader::DftSpectrum { .. } => 16,
+        }
+    }
+}
+
+/// Source of record data that a [`Writer`] pulls from
+///
+/// Lets records be synthesized or loaded on demand instead of being collected up front.
+/// The writer asks for every channel of every record in storage order, passing the data
+/// view direction (alpha, beta) in degrees.
+///
+/// ```no_run
+/// use opendaff::writer::{FnProvider, WriterBuilder};
+/// use opendaff::{ContentHeader, EquiangularGrid};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid::with_resolution(1.0, 1.0)?;
+/// let header = ContentHeader::MagnitudeSpectrum { frequencies: vec![500.0, 1000.0] };
+/// // Cardioid directivity, computed for each record as it is written
+/// let mut cardioid = FnProvider::new(|alpha: f32, beta: f32, _| {
+///     let front = alpha.to_radians().cos() * beta.to_radians().sin();
+///     vec![0.5 * (1.0 + front); 2]
+/// });
+/// WriterBuilder::new(header, grid, 1).write_from("cardioid.daff", 2, &mut cardioid)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait RecordProvider {
+    /// Data of one channel of the record at the given direction
+    ///
+    /// The slice only needs to stay valid until the next call.
+    fn record(&mut self, alpha: f32, beta: f32, channel: usize) -> &[f32];
+}
+
+/// [`RecordProvider`] generating channel data with a closure
+///
+/// The closure receives alpha, beta and the channel index like the one of
+/// [`Dataset::from_fn`](crate::Dataset::from_fn).
+#[derive(Debug, Clone)]
+pub struct FnProvider<F> {
+    f: F,
+    data: Vec<f32>,
+}
+
+impl<F: FnMut(f32, f32, usize) -> Vec<f32>> FnProvider<F> {
+    /// Provide the records generated by `f`
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            data: Vec::new(),
+        }
+    }
+}
+
+impl<F: FnMut(f32, f32, usize) -> Vec<f32>> RecordProvider for FnProvider<F> {
+    fn record(&mut self, alpha: f32, beta: f32, channel: usize) -> &[f32] {
+        self.data = (self.f)(alpha, beta, channel);
+        &self.data
+    }
+}
+
+/// Channel data of a single record, as passed to [`Writer::append_record`]
+struct Channels<'a, C>(&'a [C]);
+
+impl<C: AsRef<[f32]>> RecordProvider for Channels<'_, C> {
+    fn record(&mut self, _alpha: f32, _beta: f32, channel: usize) -> &[f32] {
+        self.0[channel].as_ref()
+    }
+}
+
+/// Noise added before rounding to integer quantization
+///
+/// Rounding low-level signals such as the decaying tails of impulse responses produces
+/// distortion correlated with the signal. TPDF (triangular probability density) dither of
+/// ±1 LSB turns it into a constant, signal-independent noise floor. Float data is never
+/// dithered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest integer
+    #[default]
+    None,
+    /// Triangular noise from a pseudo-random generator; the same seed reproduces the same
+    /// file
+    Tpdf {
+        /// Seed of the generator
+        seed: u64,
+    },
+}
+
+/// Source of triangular dither noise (splitmix64)
+#[derive(Debug, Clone)]
+struct TpdfNoise {
+    state: u64,
+}
+
+impl TpdfNoise {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Triangular value in (-1, 1), in units of the least significant bit
+    fn sample(&mut self) -> f64 {
+        self.uniform() - self.uniform()
+    }
+}
+
+/// Byte positions of the blocks of a file written by a [`Writer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    content_header: u64,
+    record_desc: u64,
+    record_desc_size: u64,
+    data: u64,
+}
+
+impl Layout {
+    fn new(spec: &WriterSpec) -> Self {
+        let content_header =
+            FILE_HEADER_SIZE + NUM_FILE_BLOCKS as u64 * FILE_BLOCK_ENTRY_SIZE + MAIN_HEADER_SIZE;
+        let record_desc = align(content_header + spec.content_header_size());
+        let record_desc_size =
+            spec.grid.num_records() as u64 * spec.num_channels as u64 * spec.desc_size();
+        Self {
+            content_header,
+            record_desc,
+            record_desc_size,
+            data: align(record_desc + record_desc_size),
+        }
+    }
+}
+
+/// Incremental DAFF file writer
+///
+/// ```no_run
+/// use opendaff::writer::{FormatVersion, Writer, WriterSpec};
+/// use opendaff::{ContentHeader, EquiangularGrid, Orientation, Quantization};
+///
+/// # fn measure(alpha: f32, beta: f32) -> Vec<Vec<f32>> { vec![vec![0.0; 256]; 2] }
+/// # fn main() -> opendaff::Result<()> {
+/// let spec = WriterSpec {
+///     header: ContentHeader::ImpulseResponse { samplerate: 44100.0 },
+///     quantization: Quantization::Float32,
+///     grid: EquiangularGrid {
+///         alpha_points: 72,
+///         alpha_start: 0.0,
+///         alpha_end: 360.0,
+///         beta_points: 37,
+///         beta_start: 0.0,
+///         beta_end: 180.0,
+///     },
+///     orientation: Orientation::default(),
+///     format_version: FormatVersion::V1_7,
+///     num_channels: 2,
+///     elements_per_record: 256,
+/// };
+/// let mut writer = Writer::create("hrir.daff", spec)?;
+/// while let Some((alpha, beta)) = writer.next_direction() {
+///     writer.append_record(&measure(alpha, beta))?;
+/// }
+/// writer.finalize()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Writer {
+    file: BufWriter<File>,
+    path: PathBuf,
+    spec: WriterSpec,
+    layout: Layout,
+    metadata: Metadata,
+    /// Metadata sets of individual records, by record index
+    record_metadata: BTreeMap<usize, Metadata>,
+    /// Global and record metadata as stored in the journal
+    journaled: (Metadata, BTreeMap<usize, Metadata>),
+    /// Number of records appended, including those still buffered
+    records_written: usize,
+    /// Encoded records not yet handed to the operating system
+    buffer: Vec<u8>,
+    /// Size in bytes up to which records are buffered before they are written
+    buffer_budget: usize,
+    dither: Dither,
+    /// Noise generator for [`Dither::Tpdf`]
+    noise: Option<TpdfNoise>,
+    /// Current end of the data block (including buffered records), relative to its start
+    data_size: u64,
+    /// Largest absolute value (magnitude for complex content) written so far
+    peak: f32,
+    /// Key the file is signed with on [`finalize`](Writer::finalize)
+    #[cfg(feature = "signing")]
+    signing_key: Option<SigningKey>,
+    /// Key the file is encrypted with on [`finalize`](Writer::finalize)
+    #[cfg(feature = "encryption")]
+    encryption_key: Option<Key>,
+}
+
+impl Writer {
+    /// Start writing a file and write its headers
+    ///
+    /// The file is written to `<path>.part` and only appears under `path` after
+    /// [`finalize`](Writer::finalize). An existing partial file is overwritten.
+    pub fn create(path: impl AsRef<Path>, spec: WriterSpec) -> Result<Self> {
+        spec.validate()?;
+        let path = path.as_ref().to_path_buf();
+        let part = sibling(&path, PART_EXTENSION);
+        let file = File::create(&part)
+            .map_err(|e| Error::new(format!("Failed to create '{}': {}", part.display(), e)))?;
+        remove_if_exists(&sibling(&path, JOURNAL_EXTENSION))?;
+
+        let layout = Layout::new(&spec);
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            path,
+            records_written: 0,
+            buffer: Vec::new(),
+            buffer_budget: 0,
+            dither: Dither::None,
+            noise: None,
+            spec,
+            layout,
+            metadata: Metadata::new(),
+            record_metadata: BTreeMap::new(),
+            journaled: (Metadata::new(), BTreeMap::new()),
+            data_size: 0,
+            peak: 0.0,
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+        };
+        writer.write_headers(0, 0)?;
+        // Placeholder for the record descriptors, filled in by finalize
+        writer.write_zeros(layout.data - layout.record_desc)?;
+        writer.file.flush().map_err(write_error)?;
+        Ok(writer)
+    }
+
+    /// Continue an interrupted session from `<path>.part`
+    ///
+    /// Restores the specification from the file headers and the metadata from the journal. A
+    /// trailing record that was only partially written is discarded, so
+    /// [`next_direction`](Writer::next_direction) reports where to continue.
+    pub fn resume(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let part = sibling(&path, PART_EXTENSION);
+        let open_error = |e| Error::new(format!("Failed to open '{}': {}", part.display(), e));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&part)
+            .map_err(open_error)?;
+
+        let spec = read_spec(&mut file)?;
+        let layout = Layout::new(&spec);
+        let length = file.metadata().map_err(open_error)?.len();
+        if length < layout.data {
+            return Err(Error::new(format!(
+                "'{}' ends before its data block",
+                part.display()
+            )));
+        }
+        let record_size = spec.record_size();
+        let records = ((length - layout.data) / record_size).min(spec.grid.num_records() as u64);
+        let data_size = records * record_size;
+        file.set_len(layout.data + data_size).map_err(write_error)?;
+        file.seek(SeekFrom::Start(DATA_SIZE_POSITION))
+            .map_err(write_error)?;
+        file.write_all(&data_size.to_le_bytes())
+            .map_err(write_error)?;
+
+        // The peak is only stored for spectra, which are always 32-bit floats
+        let mut peak = 0.0f32;
+        if spec.quantization == Quantization::Float32 {
+            file.seek(SeekFrom::Start(layout.data))
+                .map_err(read_error)?;
+            let mut bytes = vec![0; record_size as usize];
+            let channel_size = bytes.len() / spec.num_channels;
+            for _ in 0..records {
+                file.read_exact(&mut bytes).map_err(read_error)?;
+                for channel in bytes.chunks(channel_size) {
+                    let data = decode(channel, Quantization::Float32);
+                    peak = peak.max(self::peak(&spec.header, &data));
+                }
+            }
+        }
+        file.seek(SeekFrom::End(0)).map_err(write_error)?;
+
+        let journal = sibling(&path, JOURNAL_EXTENSION);
+        let (metadata, record_metadata) = match fs::read(&journal) {
+            Ok(bytes) => decode_journal(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                (Metadata::new(), BTreeMap::new())
+            }
+            Err(e) => return Err(read_error(e)),
+        };
+
+        Ok(Self {
+            file: BufWriter::new(file),
+            path,
+            records_written: records as usize,
+            buffer: Vec::new(),
+            buffer_budget: 0,
+            dither: Dither::None,
+            noise: None,
+            spec,
+            layout,
+            journaled: (metadata.clone(), record_metadata.clone()),
+            metadata,
+            record_metadata,
+            data_size,
+            peak,
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+        })
+    }
+
+    /// Target path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Specification the file is written with
+    pub fn spec(&self) -> &WriterSpec {
+        &self.spec
+    }
+
+    /// Global metadata, written on [`finalize`](Writer::finalize)
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Metadata set of a record, `None` if it has none
+    pub fn record_metadata(&self, record_index: usize) -> Option<&Metadata> {
+        self.record_metadata.get(&record_index)
+    }
+
+    /// Attach a metadata set to a record, replacing an earlier one; an empty set removes it
+    ///
+    /// Records can be annotated before or after they are appended, until the file is
+    /// [finalized](Writer::finalize). Keys are stored as given. Fails for invalid record
+    /// indices, keys or values.
+    pub fn set_record_metadata(&mut self, record_index: usize, metadata: Metadata) -> Result<()> {
+        if record_index >= self.num_records() {
+            return Err(Error::new(format!(
+                "Record index {} out of range (0..{})",
+                record_index,
+                self.num_records()
+            )));
+        }
+        check_metadata(&metadata)?;
+        if !metadata.is_empty() && !self.spec.format_version.supports_record_metadata() {
+            return Err(Error::new(format!(
+                "Version {} cannot store record metadata",
+                self.spec.format_version
+            )));
+        }
+        if metadata.is_empty() {
+            self.record_metadata.remove(&record_index);
+        } else {
+            self.record_metadata.insert(record_index, metadata);
+        }
+        Ok(())
+    }
+
+    /// Total number of records of the file
+    pub fn num_records(&self) -> usize {
+        self.spec.grid.num_records()
+    }
+
+    /// Number of records written so far
+    pub fn records_written(&self) -> usize {
+        self.records_written
+    }
+
+    /// Dither applied when rounding to integer quantization
+    pub fn dither(&self) -> Dither {
+        self.dither
+    }
+
+    /// Dither the records appended from now on
+    ///
+    /// Only affects integer quantization. The setting is not stored in the file, so it has
+    /// to be set again after [`resume`](Writer::resume); setting it restarts the noise
+    /// generator.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+        self.noise = match dither {
+            Dither::None => None,
+            Dither::Tpdf { seed } => Some(TpdfNoise { state: seed }),
+        };
+    }
+
+    /// Sign the file with an Ed25519 key when it is finalized, or stop signing it with `None`
+    ///
+    /// The key is not stored in the partial file, so it has to be set again after
+    /// [`resume`](Writer::resume). See [`signature`](crate::signature).
+    #[cfg(feature = "signing")]
+    pub fn set_signing_key(&mut self, key: Option<SigningKey>) {
+        self.signing_key = key;
+    }
+
+    /// Encrypt the file when it is finalized, or stop encrypting it with `None`
+    ///
+    /// Until then the records are kept unencrypted in the partial file, and the key has to be
+    /// set again after [`resume`](Writer::resume). See [`encryption`](crate::encryption).
+    #[cfg(feature = "encryption")]
+    pub fn set_encryption_key(&mut self, key: Option<Key>) {
+        self.encryption_key = key;
+    }
+
+    /// Size in bytes up to which appended records are collected in memory
+    pub fn buffer_budget(&self) -> usize {
+        self.buffer_budget
+    }
+
+    /// Collect appended records in memory until they exceed `bytes`, then write them at once
+    ///
+    /// By default (a budget of 0) every record is handed to the operating system as soon as
+    /// it is appended. A larger budget reduces the number of writes for datasets with many
+    /// small records, at the cost of losing the buffered records if the process crashes
+    /// (they are still written when the writer is dropped).
+    /// Memory use of the writer is bounded by the budget plus one record, independent of the
+    /// number of records in the file.
+    pub fn set_buffer_budget(&mut self, bytes: usize) -> Result<()> {
+        self.buffer_budget = bytes;
+        if self.buffer.len() > bytes {
+            self.drain()?;
+        }
+        self.buffer.shrink_to(bytes);
+        Ok(())
+    }
+
+    /// Data view direction (alpha, beta) of the next record to append, or `None` when all
+    /// records have been written
+    pub fn next_direction(&self) -> Option<(f32, f32)> {
+        self.spec.grid.record_coords(self.records_written())
+    }
+
+    /// Append the next record in storage order
+    ///
+    /// Expects one data vector per channel in the layout of
+    /// [`Record::channels`](crate::Record::channels). Unless a
+    /// [buffer budget](Writer::set_buffer_budget) is set, the record is handed to the
+    /// operating system immediately and pending metadata changes are journaled.
+    pub fn append_record<C: AsRef<[f32]>>(&mut self, channels: &[C]) -> Result<()> {
+        if channels.len() != self.spec.num_channels {
+            return Err(Error::new(format!(
+                "Expected {} channels, got {}",
+                self.spec.num_channels,
+                channels.len()
+            )));
+        }
+        self.append_from(&mut Channels(channels))
+    }
+
+    /// Append the next record in storage order, pulling the data of each channel from a
+    /// provider
+    ///
+    /// The provider is asked for the channels of the record at
+    /// [`next_direction`](Writer::next_direction) in order. If a channel is invalid, nothing
+    /// of the record is written.
+    pub fn append_from<P: RecordProvider + ?Sized>(&mut self, provider: &mut P) -> Result<()> {
+        let Some((alpha, beta)) = self.next_direction() else {
+            return Err(Error::new(format!(
+                "All {} records have already been written",
+                self.num_records()
+            )));
+        };
+
+        let start = self.buffer.len();
+        let noise = self.noise.clone();
+        let mut record_peak = self.peak;
+        for channel in 0..self.spec.num_channels {
+            let data = provider.record(alpha, beta, channel);
+            let error = if data.len() != self.spec.elements_per_record {
+                Some(Error::new(format!(
+                    "Expected {} values per channel, got {}",
+                    self.spec.elements_per_record,
+                    data.len()
+                )))
+            } else if self.spec.header.content_type() == ContentType::MagnitudeSpectrum
+                && data.iter().any(|&m| m.is_nan() || m < 0.0)
+            {
+                Some(Error::new("Magnitudes must be non-negative numbers"))
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                self.buffer.truncate(start);
+                self.noise = noise;
+                return Err(error);
+            }
+            encode(
+                &mut self.buffer,
+                data,
+                self.spec.quantization,
+                self.noise.as_mut(),
+            );
+            record_peak = record_peak.max(peak(&self.spec.header, data));
+        }
+        self.peak = record_peak;
+        self.data_size += (self.buffer.len() - start) as u64;
+        self.records_written += 1;
+        if self.buffer.len() >= self.buffer_budget {
+            self.drain()?;
+        }
+        Ok(())
+    }
+
+    /// Append all remaining records from a provider
+    ///
+    /// Continues after the records written so far, so a [resumed](Writer::resume) session
+    /// only generates what is missing.
+    pub fn write_from<P: RecordProvider + ?Sized>(&mut self, provider: &mut P) -> Result<()> {
+        while self.records_written() < self.num_records() {
+            self.append_from(provider)?;
+        }
+        Ok(())
+    }
+
+    /// Append records in storage order until the iterator is exhausted
+    ///
+    /// Only one record is held at a time, so records can be generated or loaded lazily.
+    /// Returns the number of records appended.
+    pub fn append_records<I, R, C>(&mut self, records: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        let mut count = 0;
+        for record in records {
+            self.append_record(record.as_ref())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Append the next record of a magnitude-phase spectrum file from separate magnitudes
+    /// and phases (radians), one vector per channel each
+    ///
+    /// The values are converted to the real and imaginary parts stored in the file.
+    pub fn append_magnitude_phase_record<M, P>(
+        &mut self,
+        magnitudes: &[M],
+        phases: &[P],
+    ) -> Result<()>
+    where
+        M: AsRef<[f32]>,
+        P: AsRef<[f32]>,
+    {
+        if self.spec.header.content_type() != ContentType::MagnitudePhaseSpectrum {
+            return Err(Error::new(format!(
+                "Magnitudes and phases can only be written to magnitude-phase spectra, not {}",
+                self.spec.header.content_type()
+            )));
+        }
+        if magnitudes.len() != phases.len() {
+            return Err(Error::new(format!(
+                "Got magnitudes for {} channels but phases for {}",
+                magnitudes.len(),
+                phases.len()
+            )));
+        }
+        let mut channels = Vec::with_capacity(magnitudes.len());
+        for (magnitudes, phases) in magnitudes.iter().zip(phases) {
+            let (magnitudes, phases) = (magnitudes.as_ref(), phases.as_ref());
+            if magnitudes.len() != phases.len() {
+                return Err(Error::new(format!(
+                    "Got {} magnitudes but {} phases",
+                    magnitudes.len(),
+                    phases.len()
+                )));
+            }
+            if magnitudes.iter().any(|&m| m.is_nan() || m < 0.0) {
+                return Err(Error::new("Magnitudes must be non-negative numbers"));
+            }
+            let channel: Vec<f32> = magnitudes
+                .iter()
+                .zip(phases)
+                .flat_map(|(m, p)| [m * p.cos(), m * p.sin()])
+                .collect();
+            channels.push(channel);
+        }
+        self.append_record(&channels)
+    }
+
+    /// Journal the metadata and wait until all written data has reached the storage device
+    ///
+    /// Buffered records are written first. Records handed to the operating system already
+    /// survive a crash of the writing process; syncing also protects them against power loss.
+    pub fn sync(&mut self) -> Result<()> {
+        self.drain()?;
+        self.file.get_ref().sync_data().map_err(write_error)
+    }
+
+    /// Append the next record of complex coefficients (MPS or DFT content) in storage order
+    #[cfg(feature = "complex")]
+    pub fn append_complex_record<C: AsRef<[Complex<f32>]>>(
+        &mut self,
+        channels: &[C],
+    ) -> Result<()> {
+        let interleaved: Vec<Vec<f32>> = channels
+            .iter()
+            .map(|c| c.as_ref().iter().flat_map(|z| [z.re, z.im]).collect())
+            .collect();
+        self.append_record(&interleaved)
+    }
+
+    /// Write the metadata, complete the headers and record descriptors and move the file to
+    /// its target path
+    ///
+    /// Fails if not all records have been written; the partial file can then still be
+    /// [resumed](Writer::resume).
+    pub fn finalize(mut self) -> Result<()> {
+        self.drain()?;
+        if self.records_written() != self.num_records() {
+            return Err(Error::new(format!(
+                "Only {} of {} records have been written",
+                self.records_written(),
+                self.num_records()
+            )));
+        }
+
+        // The global set comes first, followed by the distinct sets of the records
+        let mut sets = vec![&self.metadata];
+        let mut set_indices = BTreeMap::new();
+        for (&record, metadata) in &self.record_metadata {
+            let set = match sets[1..].iter().position(|set| *set == metadata) {
+                Some(position) => position + 1,
+                None => {
+                    sets.push(metadata);
+                    sets.len() - 1
+                }
+            };
+            set_indices.insert(record, set as i32);
+        }
+        let mut metadata = Vec::new();
+        for set in sets {
+            metadata.extend(encode_metadata(set)?);
+        }
+        self.file.write_all(&metadata).map_err(write_error)?;
+
+        // Records and channels are stored contiguously, so the descriptors are generated one
+        // by one instead of being kept in memory
+        self.seek(self.layout.record_desc)?;
+        let channel_size = self.spec.record_size() / self.spec.num_channels as u64;
+        let length = self.spec.elements_per_record as i32;
+        let mut descriptor = Vec::with_capacity(self.spec.desc_size() as usize);
+        for index in 0..(self.records_written * self.spec.num_channels) as u64 {
+            descriptor.clear();
+            let record = index as usize / self.spec.num_channels;
+            put_i32(
+                &mut descriptor,
+                set_indices.get(&record).copied().unwrap_or(-1),
+            );
+            put_u64(&mut descriptor, index * channel_size);
+            if self.spec.header.content_type() == ContentType::ImpulseResponse {
+                // Full responses are stored: no leading zeros, full length
+                put_i32(&mut descriptor, 0);
+                put_i32(&mut descriptor, length);
+            }
+            self.file.write_all(&descriptor).map_err(write_error)?;
+        }
+
+        self.seek(0)?;
+        self.write_headers(self.data_size, metadata.len() as u64)?;
+        self.file.flush().map_err(write_error)?;
+        let rewritten = match self.spec.format_version {
+            FormatVersion::V1_05 => {
+                let mut part = fs::read(sibling(&self.path, PART_EXTENSION)).map_err(read_error)?;
+                part[2..6].copy_from_slice(&legacy::CURRENT_VERSION.to_le_bytes());
+                Some(legacy::downgrade(&part)?)
+            }
+            FormatVersion::V1_7 => None,
+        };
+        #[cfg(any(feature = "signing", feature = "encryption"))]
+        let rewritten = self.seal(rewritten)?;
+        if let Some(bytes) = rewritten {
+            let file = self.file.get_mut();
+            file.seek(SeekFrom::Start(0)).map_err(write_error)?;
+            file.write_all(&bytes).map_err(write_error)?;
+            file.set_len(bytes.len() as u64).map_err(write_error)?;
+        }
+        self.file.get_ref().sync_all().map_err(write_error)?;
+
+        fs::rename(sibling(&self.path, PART_EXTENSION), &self.path).map_err(write_error)?;
+        remove_if_exists(&sibling(&self.path, JOURNAL_EXTENSION))
+    }
+
+    /// The finished file signed and encrypted as configured, `None` if it stays as written
+    ///
+    /// `bytes` is the file if it has been converted already; otherwise the partial file is
+    /// read back, since signatures and encryption cover the whole file.
+    #[cfg(any(feature = "signing", feature = "encryption"))]
+    fn seal(&self, mut bytes: Option<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+        let read_part = || fs::read(sibling(&self.path, PART_EXTENSION)).map_err(read_error);
+        #[cfg(feature = "signing")]
+        if let Some(key) = &self.signing_key {
+            let unsigned = match bytes.take() {
+                Some(bytes) => bytes,
+                None => read_part()?,
+            };
+            bytes = Some(crate::signature::sign(&unsigned, key)?);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encryption_key {
+            let plain = match bytes.take() {
+                Some(bytes) => bytes,
+                None => read_part()?,
+            };
+            bytes = Some(crate::encryption::encrypt(&plain, key)?);
+        }
+        Ok(bytes)
+    }
+
+    /// Write the buffered records, publish the data size and journal the metadata
+    fn drain(&mut self) -> Result<()> {
+        self.file.write_all(&self.buffer).map_err(write_error)?;
+        self.buffer.clear();
+        self.file.flush().map_err(write_error)?;
+        self.publish()?;
+        self.write_journal()
+    }
+
+    /// Publish the current data size to monitors
+    ///
+    /// Must only be called after the record data has been handed to the operating system.
+    fn publish(&mut self) -> Result<()> {
+        self.seek(DATA_SIZE_POSITION)?;
+        self.file
+            .write_all(&self.data_size.to_le_bytes())
+            .map_err(write_error)?;
+        self.file.seek(SeekFrom::End(0)).map_err(write_error)?;
+        self.file.flush().map_err(write_error)
+    }
+
+    /// Store the metadata in the journal if it changed since the last call
+    ///
+    /// The journal is replaced atomically, so it always holds complete metadata sets.
+    fn write_journal(&mut self) -> Result<()> {
+        if self.metadata == self.journaled.0 && self.record_metadata == self.journaled.1 {
+            return Ok(());
+        }
+        let mut bytes = encode_metadata(&self.metadata)?;
+        for (&record, metadata) in &self.record_metadata {
+            put_i32(&mut bytes, record as i32);
+            bytes.extend(encode_metadata(metadata)?);
+        }
+        let journal = sibling(&self.path, JOURNAL_EXTENSION);
+        let temp = sibling(&journal, PART_EXTENSION);
+        fs::write(&temp, bytes).map_err(write_error)?;
+        fs::rename(&temp, &journal).map_err(write_error)?;
+        self.journaled = (self.metadata.clone(), self.record_metadata.clone());
+        Ok(())
+    }
+
+    /// Write file header, block table, main header and content header from the start of the
+    /// file
+    fn write_headers(&mut self, data_size: u64, metadata_size: u64) -> Result<()> {
+        let spec = &self.spec;
+        let layout = &self.layout;
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(b"FW");
+        put_i32(&mut bytes, spec.format_version.number());
+        put_i32(&mut bytes, NUM_FILE_BLOCKS as i32);
+        let main_header = layout.content_header - MAIN_HEADER_SIZE;
+        let data_end = layout.data + data_size;
+        for (id, offset, size) in [
+            (MAIN_HEADER_ID, main_header, MAIN_HEADER_SIZE),
+            (
+                CONTENT_HEADER_ID,
+                layout.content_header,
+                spec.content_header_size(),
+            ),
+            (RECORD_DESC_ID, layout.record_desc, layout.record_desc_size),
+            (DATA_ID, layout.data, data_size),
+            (METADATA_ID, data_end, metadata_size),
+        ] {
+            put_i32(&mut bytes, id);
+            put_u64(&mut bytes, offset);
+            put_u64(&mut bytes, size);
+        }
+
+        let grid = &spec.grid;
+        put_i32(&mut bytes, spec.header.content_type() as i32);
+        put_i32(&mut bytes, spec.quantization as i32);
+        put_i32(&mut bytes, spec.num_channels as i32);
+        put_i32(&mut bytes, grid.num_records() as i32);
+        put_i32(&mut bytes, spec.stored_elements() as i32);
+        // Global metadata is stored in the metadata block, not referenced by index
+        put_i32(&mut bytes, -1);
+        put_i32(&mut bytes, grid.alpha_points as i32);
+        put_f32(&mut bytes, grid.alpha_start);
+        put_f32(&mut bytes, grid.alpha_end);
+        put_i32(&mut bytes, grid.beta_points as i32);
+        put_f32(&mut bytes, grid.beta_start);
+        put_f32(&mut bytes, grid.beta_end);
+        put_f32(&mut bytes, spec.orientation.yaw);
+        put_f32(&mut bytes, spec.orientation.pitch);
+        put_f32(&mut bytes, spec.orientation.roll);
+
+        match &spec.header {
+            ContentHeader::ImpulseResponse { samplerate } => {
+                put_f32(&mut bytes, *samplerate as f32);
+                // Minimum filter offset and maximum effective filter length
+                put_i32(&mut bytes, 0);
+                put_i32(&mut bytes, spec.elements_per_record as i32);
+            }
+            ContentHeader::MagnitudeSpectrum { frequencies }
+            | ContentHeader::MagnitudePhaseSpectrum { frequencies } => {
+                put_f32(&mut bytes, self.peak);
+                put_i32(&mut bytes, frequencies.len() as i32);
+                frequencies.iter().for_each(|&f| put_f32(&mut bytes, f));
+            }
+            ContentHeader::PhaseSpectrum { frequencies } => {
+                put_i32(&mut bytes, frequencies.len() as i32);
+                frequencies.iter().for_each(|&f| put_f32(&mut bytes, f));
+            }
+            ContentHeader::DftSpectrum {
+                samplerate,
+                transform_size,
+            } => {
+                put_i32(&mut bytes, spec.stored_elements() as i32);
+                put_i32(&mut bytes, *transform_size as i32);
+                put_f32(&mut bytes, *samplerate as f32);
+                put_f32(&mut bytes, self.peak);
+            }
+        }
+
+        let padding = layout.record_desc - layout.content_header - spec.content_header_size();
+        bytes.resize(bytes.len() + padding as usize, 0);
+        self.file.write_all(&bytes).map_err(write_error)
+    }
+
+    fn write_zeros(&mut self, count: u64) -> Result<()> {
+        std::io::copy(&mut std::io::repeat(0).take(count), &mut self.file)
+            .map(|_| ())
+            .map_err(write_error)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<()> {
+        self.file
+            .seek(SeekFrom::Start(position))
+            .map(|_| ())
+            .map_err(write_error)
+    }
+}
+
+impl Drop for Writer {
+    /// Write buffered records so the partial file can be resumed; errors are ignored
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.drain();
+        }
+    }
+}
+
+/// Builder for files of any content type with metadata and channel labels
+///
+/// Collects everything that is written besides the records: quantization, default
+/// orientation, global metadata and channel labels (stored as `LABEL_CHANNEL_<n>` metadata,
+/// like the DAFF library expects). Metadata keys are stored in upper case. Keys, values and
+/// labels are checked when the file is created, before any record is written.
+///
+/// ```no_run
+/// use opendaff::writer::WriterBuilder;
+/// use opendaff::{ContentHeader, EquiangularGrid, Orientation, Quantization};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid::with_resolution(5.0, 5.0)?;
+/// let header = ContentHeader::ImpulseResponse { samplerate: 44100.0 };
+/// let mut writer = WriterBuilder::new(header, grid, 2)
+///     .quantization(Quantization::Int24)
+///     .orientation(Orientation { yaw: 0.0, pitch: -90.0, roll: 0.0 })
+///     .metadata("DESCRIPTION", "KEMAR HRIR, large pinnae")
+///     .metadata("MEASUREMENT_DISTANCE", 2.0)
+///     .channel_label(0, "Left ear")
+///     .channel_label(1, "Right ear")
+///     .create("hrir.daff", 256)?;
+/// while writer.next_direction().is_some() {
+///     writer.append_record(&[[0.0; 256], [0.0; 256]])?;
+/// }
+/// writer.finalize()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriterBuilder {
+    header: ContentHeader,
+    grid: EquiangularGrid,
+    num_channels: usize,
+    quantization: Quantization,
+    orientation: Orientation,
+    format_version: FormatVersion,
+    metadata: Metadata,
+    record_metadata: BTreeMap<usize, Metadata>,
+    labels: Vec<(usize, String)>,
+    dither: Dither,
+    buffer_budget: usize,
+    #[cfg(feature = "signing")]
+    signing_key: Option<SigningKey>,
+    #[cfg(feature = "encryption")]
+    encryption_key: Option<Key>,
+}
+
+impl WriterBuilder {
+    /// Start a file with the given content header, grid and number of channels
+    ///
+    /// Data is stored as 32-bit floats in the default orientation unless configured
+    /// otherwise.
+    pub fn new(header: ContentHeader, grid: EquiangularGrid, num_channels: usize) -> Self {
+        Self {
+            header,
+            grid,
+            num_channels,
+            quantization: Quantization::Float32,
+            orientation: Orientation::default(),
+            format_version: FormatVersion::default(),
+            metadata: Metadata::new(),
+            record_metadata: BTreeMap::new(),
+            labels: Vec::new(),
+            dither: Dither::None,
+            buffer_budget: 0,
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+        }
+    }
+
+    /// Start a file with the content header, grid, quantization, orientation and metadata of
+    /// a dataset
+    ///
+    /// Channel labels are part of the metadata and carry over as well. Fails for datasets
+    /// without an equiangular grid, which cannot be stored in a DAFF file.
+    pub fn from_dataset(dataset: &Dataset) -> Result<Self> {
+        let grid = *dataset
+            .grid
+            .equiangular()
+            .ok_or_else(|| Error::new("DAFF files require an equiangular grid"))?;
+        Ok(Self {
+            quantization: dataset.quantization,
+            orientation: dataset.orientation,
+            metadata: dataset.metadata.clone(),
+            ..Self::new(dataset.header.clone(), grid, dataset.num_channels())
+        })
+    }
+
+    /// Set the quantization of the stored data (integer types for impulse responses only)
+    pub fn quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// Dither when rounding to integer quantization
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Set the default orientation
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Set the file format version, see [`FormatVersion`]
+    ///
+    /// Shared by the builders of the individual content types. Version 1.05 cannot store
+    /// [record metadata](WriterBuilder::record_metadata).
+    pub fn format_version(mut self, version: FormatVersion) -> Self {
+        self.format_version = version;
+        self
+    }
+
+    /// Set a global metadata entry, replacing an earlier value of the same key
+    pub fn metadata(mut self, key: &str, value: impl Into<MetadataValue>) -> Self {
+        self.metadata.insert(key.to_ascii_uppercase(), value.into());
+        self
+    }
+
+    /// Set a metadata entry of a single record (in storage order), replacing an earlier value
+    /// of the same key, see [`Writer::set_record_metadata`]
+    pub fn record_metadata(
+        mut self,
+        record_index: usize,
+        key: &str,
+        value: impl Into<MetadataValue>,
+    ) -> Self {
+        self.record_metadata
+            .entry(record_index)
+            .or_default()
+            .insert(key.to_ascii_uppercase(), value.into());
+        self
+    }
+
+    /// Label a (0-based) channel, e.g. `"Left ear"`
+    pub fn channel_label(mut self, channel: usize, label: impl Into<String>) -> Self {
+        self.labels.retain(|(c, _)| *c != channel);
+        self.labels.push((channel, label.into()));
+        self
+    }
+
+    /// Collect records in memory up to the given number of bytes before writing them, see
+    /// [`Writer::set_buffer_budget`]
+    pub fn buffer_budget(mut self, bytes: usize) -> Self {
+        self.buffer_budget = bytes;
+        self
+    }
+
+    /// Sign the file with an Ed25519 key when it is finalized, see
+    /// [`signature`](crate::signature)
+    #[cfg(feature = "signing")]
+    pub fn signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Encrypt the file when it is finalized, see [`encryption`](crate::encryption)
+    ///
+    /// Combined with [`signing_key`](WriterBuilder::signing_key), the file is signed first,
+    /// so the signature is checked after decrypting it.
+    #[cfg(feature = "encryption")]
+    pub fn encryption_key(mut self, key: Key) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Writer specification for records of the given number of elements
+    ///
+    /// See [`WriterSpec::elements_per_record`] for the layout of complex content.
+    pub fn spec(&self, elements_per_record: usize) -> WriterSpec {
+        WriterSpec {
+            header: self.header.clone(),
+            quantization: self.quantization,
+            grid: self.grid,
+            orientation: self.orientation,
+            format_version: self.format_version,
+            num_channels: self.num_channels,
+            elements_per_record,
+        }
+    }
+
+    /// Global metadata of the file including the channel labels
+    pub fn build_metadata(&self) -> Result<Metadata> {
+        let mut metadata = self.metadata.clone();
+        for (channel, label) in &self.labels {
+            if *channel >= self.num_channels {
+                return Err(Error::new(format!(
+                    "Cannot label channel {} of {} channels",
+                    channel, self.num_channels
+                )));
+            }
+            metadata.insert(
+                metadata::channel_label_key(*channel),
+                MetadataValue::String(label.clone()),
+            );
+        }
+        check_metadata(&metadata)?;
+        Ok(metadata)
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>, elements_per_record: usize) -> Result<Writer> {
+        let metadata = self.build_metadata()?;
+        for (&record, metadata) in &self.record_metadata {
+            if record >= self.grid.num_records() {
+                return Err(Error::new(format!(
+                    "Cannot annotate record {} of {} records",
+                    record,
+                    self.grid.num_records()
+                )));
+            }
+            check_metadata(metadata)?;
+        }
+        if !self.record_metadata.is_empty() && !self.format_version.supports_record_metadata() {
+            return Err(Error::new(format!(
+                "Version {} cannot store record metadata",
+                self.format_version
+            )));
+        }
+        let mut writer = Writer::create(path, self.spec(elements_per_record))?;
+        writer.metadata = metadata;
+        writer.record_metadata = self.record_metadata.clone();
+        writer.set_dither(self.dither);
+        writer.set_buffer_budget(self.buffer_budget)?;
+        #[cfg(feature = "signing")]
+        writer.set_signing_key(self.signing_key.clone());
+        #[cfg(feature = "encryption")]
+        writer.set_encryption_key(self.encryption_key);
+        Ok(writer)
+    }
+
+    /// Write a complete file from an iterator over the records in storage order
+    ///
+    /// Records are consumed one at a time, so datasets larger than the available memory can
+    /// be written from a generator or another file. Fails if the iterator does not yield
+    /// exactly one record per grid point; the partial file can then be
+    /// [resumed](Writer::resume).
+    pub fn write_iter<I, R, C>(
+        &self,
+        path: impl AsRef<Path>,
+        elements_per_record: usize,
+        records: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        let mut writer = self.create(path, elements_per_record)?;
+        writer.append_records(records)?;
+        writer.finalize()
+    }
+
+    /// Write a complete file with records pulled from a provider
+    pub fn write_from<P: RecordProvider + ?Sized>(
+        &self,
+        path: impl AsRef<Path>,
+        elements_per_record: usize,
+        provider: &mut P,
+    ) -> Result<()> {
+        let mut writer = self.create(path, elements_per_record)?;
+        writer.write_from(provider)?;
+        writer.finalize()
+    }
+
+    /// Write a complete file from the data of all records in storage order, one vector per
+    /// channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        check_record_count(&self.grid, records.len())?;
+        let elements_per_record = records
+            .first()
+            .and_then(|record| record.as_ref().first())
+            .map_or(0, |channel| channel.as_ref().len());
+        let mut writer = self.create(path, elements_per_record)?;
+        for record in records {
+            writer.append_record(record.as_ref())?;
+        }
+        writer.finalize()
+    }
+}
+
+/// Write a dataset to a DAFF file, keeping its quantization, orientation and metadata
+///
+/// The dataset needs an equiangular grid, e.g. after [`grid::crop`](crate::grid::crop):
+///
+/// ```no_run
+/// use opendaff::grid::{self, Region};
+/// use opendaff::{writer, Dataset, Reader};
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let reader = Reader::open("hrir.daff")?;
+/// let dataset = Dataset::from_reader(&reader)?;
+/// let frontal = Region::Window { alpha_start: 300.0, alpha_end: 60.0, beta_start: 60.0, beta_end: 120.0 };
+/// writer::write_dataset("frontal.daff", &grid::crop(&dataset, &frontal)?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_dataset(path: impl AsRef<Path>, dataset: &Dataset) -> Result<()> {
+    WriterBuilder::from_dataset(dataset)?.write_iter(
+        path,
+        dataset.elements_per_record(),
+        dataset.records.iter().map(|r| &r.channels),
+    )
+}
+
+/// Re-quantize a DAFF file, e.g. to shrink a 32-bit float HRIR set to 16 bit for embedded
+/// targets
+///
+/// Reads all records of `input` and writes them to `output` with the given quantization,
+/// keeping grid, orientation, metadata and channel labels. Integer quantization is only
+/// available for impulse responses and fails if the data exceeds the integer range of
+/// [-1, 1] instead of clipping it (see [`dsp::normalize`](crate::dsp::normalize)).
+///
+/// ```no_run
+/// use opendaff::writer::{self, Dither};
+/// use opendaff::Quantization;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// writer::transcode("hrir.daff", "hrir16.daff", Quantization::Int16, Dither::Tpdf { seed: 1 })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn transcode(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    quantization: Quantization,
+    dither: Dither,
+) -> Result<()> {
+    let input = input.as_ref();
+    let mut reader = Reader::open(input)?;
+    let dataset = Dataset::from_reader(&reader)?;
+    reader.close();
+
+    if quantization != Quantization::Float32 {
+        let peak = dataset
+            .records
+            .iter()
+            .flat_map(|r| &r.channels)
+            .map(|c| peak(&dataset.header, c))
+            .fold(0.0f32, f32::max);
+        if peak > 1.0 {
+            return Err(Error::new(format!(
+                "{}: Peak value {} exceeds the integer range, normalize first",
+                input.display(),
+                peak
+            )));
+        }
+    }
+
+    WriterBuilder::from_dataset(&dataset)?
+        .quantization(quantization)
+        .dither(dither)
+        .write_iter(
+            output,
+            dataset.elements_per_record(),
+            dataset.records.iter().map(|r| &r.channels),
+        )
+}
+
+/// Builder for impulse response files
+///
+/// Collects the file properties that do not depend on the data. The filter length is taken
+/// from the records by [`write`](IrWriterBuilder::write), or given explicitly when records
+/// are appended incrementally through [`create`](IrWriterBuilder::create).
+///
+/// ```no_run
+/// use opendaff::writer::IrWriterBuilder;
+/// use opendaff::EquiangularGrid;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid {
+///     alpha_points: 36,
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_points: 19,
+///     beta_start: 0.0,
+///     beta_end: 180.0,
+/// };
+/// // One data vector per record and channel, records in storage order
+/// let records = vec![vec![vec![1.0, 0.5, 0.25]; 2]; grid.num_records()];
+/// IrWriterBuilder::new(grid, 2, 44100.0).write("hrir.daff", &records)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrWriterBuilder {
+    builder: WriterBuilder,
+}
+
+impl IrWriterBuilder {
+    /// Start a file with the given grid, number of channels and sampling rate in Hz
+    ///
+    /// Data is stored as 32-bit floats in the default orientation unless configured
+    /// otherwise.
+    pub fn new(grid: EquiangularGrid, num_channels: usize, samplerate: f64) -> Self {
+        let header = ContentHeader::ImpulseResponse { samplerate };
+        Self {
+            builder: WriterBuilder::new(header, grid, num_channels),
+        }
+    }
+
+    /// Set the quantization of the stored samples
+    pub fn quantization(self, quantization: Quantization) -> Self {
+        Self {
+            builder: self.builder.quantization(quantization),
+        }
+    }
+
+    /// Dither when rounding to integer quantization
+    pub fn dither(self, dither: Dither) -> Self {
+        Self {
+            builder: self.builder.dither(dither),
+        }
+    }
+
+    /// Set the default orientation
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self {
+            builder: self.builder.orientation(orientation),
+        }
+    }
+
+    /// Set the file format version, see [`WriterBuilder::format_version`]
+    pub fn format_version(self, version: FormatVersion) -> Self {
+        Self {
+            builder: self.builder.format_version(version),
+        }
+    }
+
+    /// Writer specification for filters of the given length
+    pub fn spec(&self, filter_length: usize) -> WriterSpec {
+        self.builder.spec(filter_length)
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>, filter_length: usize) -> Result<Writer> {
+        self.builder.create(path, filter_length)
+    }
+
+    /// Write a complete file from the filter coefficients of all records in storage order,
+    /// one vector per channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        self.builder.write(path, records)
+    }
+}
+
+/// Builder for magnitude spectrum files
+///
+/// Magnitudes are linear factors (not decibels) at the support frequencies, one vector per
+/// channel. They are always stored as 32-bit floats.
+///
+/// ```no_run
+/// use opendaff::writer::MsWriterBuilder;
+/// use opendaff::EquiangularGrid;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid {
+///     alpha_points: 72,
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_points: 37,
+///     beta_start: 0.0,
+///     beta_end: 180.0,
+/// };
+/// let frequencies = vec![125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0];
+/// let records = vec![vec![vec![1.0; frequencies.len()]]; grid.num_records()];
+/// MsWriterBuilder::new(grid, 1, frequencies).write("loudspeaker.daff", &records)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MsWriterBuilder {
+    builder: WriterBuilder,
+    elements_per_record: usize,
+}
+
+impl MsWriterBuilder {
+    /// Start a file with the given grid, number of channels and support frequencies in Hz
+    pub fn new(grid: EquiangularGrid, num_channels: usize, frequencies: Vec<f32>) -> Self {
+        let elements_per_record = frequencies.len();
+        let header = ContentHeader::MagnitudeSpectrum { frequencies };
+        Self {
+            builder: WriterBuilder::new(header, grid, num_channels),
+            elements_per_record,
+        }
+    }
+
+    /// Set the default orientation
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self {
+            builder: self.builder.orientation(orientation),
+            ..self
+        }
+    }
+
+    /// Set the file format version, see [`WriterBuilder::format_version`]
+    pub fn format_version(self, version: FormatVersion) -> Self {
+        Self {
+            builder: self.builder.format_version(version),
+            ..self
+        }
+    }
+
+    /// Writer specification of the file
+    pub fn spec(&self) -> WriterSpec {
+        self.builder.spec(self.elements_per_record)
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<Writer> {
+        self.builder.create(path, self.elements_per_record)
+    }
+
+    /// Write a complete file from the magnitudes of all records in storage order, one vector
+    /// per channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        write_records(path, self.spec(), records)
+    }
+}
+
+/// Builder for phase spectrum files
+///
+/// Phases are given in radians at the support frequencies, one vector per channel. They are
+/// always stored as 32-bit floats.
+///
+/// ```no_run
+/// use opendaff::writer::PsWriterBuilder;
+/// use opendaff::EquiangularGrid;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid {
+///     alpha_points: 72,
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_points: 37,
+///     beta_start: 0.0,
+///     beta_end: 180.0,
+/// };
+/// let frequencies = vec![125.0, 250.0, 500.0, 1000.0];
+/// let records = vec![vec![vec![0.0; frequencies.len()]]; grid.num_records()];
+/// PsWriterBuilder::new(grid, 1, frequencies).write("phases.daff", &records)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsWriterBuilder {
+    builder: WriterBuilder,
+    elements_per_record: usize,
+}
+
+impl PsWriterBuilder {
+    /// Start a file with the given grid, number of channels and support frequencies in Hz
+    pub fn new(grid: EquiangularGrid, num_channels: usize, frequencies: Vec<f32>) -> Self {
+        let elements_per_record = frequencies.len();
+        let header = ContentHeader::PhaseSpectrum { frequencies };
+        Self {
+            builder: WriterBuilder::new(header, grid, num_channels),
+            elements_per_record,
+        }
+    }
+
+    /// Set the default orientation
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self {
+            builder: self.builder.orientation(orientation),
+            ..self
+        }
+    }
+
+    /// Set the file format version, see [`WriterBuilder::format_version`]
+    pub fn format_version(self, version: FormatVersion) -> Self {
+        Self {
+            builder: self.builder.format_version(version),
+            ..self
+        }
+    }
+
+    /// Writer specification of the file
+    pub fn spec(&self) -> WriterSpec {
+        self.builder.spec(self.elements_per_record)
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<Writer> {
+        self.builder.create(path, self.elements_per_record)
+    }
+
+    /// Write a complete file from the phases of all records in storage order, one vector per
+    /// channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        write_records(path, self.spec(), records)
+    }
+}
+
+/// Builder for magnitude-phase spectrum files
+///
+/// The file stores complex coefficients at the support frequencies. They can be given as
+/// interleaved real and imaginary parts like in [`Record::channels`](crate::Record::channels),
+/// as separate magnitudes (linear) and phases (radians) with
+/// [`write_magnitude_phase`](MpsWriterBuilder::write_magnitude_phase), or with the `complex`
+/// feature as `num_complex::Complex<f32>` values.
+///
+/// ```no_run
+/// use opendaff::writer::MpsWriterBuilder;
+/// use opendaff::EquiangularGrid;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid {
+///     alpha_points: 72,
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_points: 37,
+///     beta_start: 0.0,
+///     beta_end: 180.0,
+/// };
+/// let frequencies = vec![125.0, 250.0, 500.0, 1000.0];
+/// let magnitudes = vec![vec![vec![1.0; frequencies.len()]]; grid.num_records()];
+/// let phases = vec![vec![vec![0.0; frequencies.len()]]; grid.num_records()];
+/// MpsWriterBuilder::new(grid, 1, frequencies).write_magnitude_phase(
+///     "loudspeaker.daff",
+///     &magnitudes,
+///     &phases,
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpsWriterBuilder {
+    builder: WriterBuilder,
+    elements_per_record: usize,
+}
+
+impl MpsWriterBuilder {
+    /// Start a file with the given grid, number of channels and support frequencies in Hz
+    pub fn new(grid: EquiangularGrid, num_channels: usize, frequencies: Vec<f32>) -> Self {
+        let elements_per_record = 2 * frequencies.len();
+        let header = ContentHeader::MagnitudePhaseSpectrum { frequencies };
+        Self {
+            builder: WriterBuilder::new(header, grid, num_channels),
+            elements_per_record,
+        }
+    }
+
+    /// Set the default orientation
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self {
+            builder: self.builder.orientation(orientation),
+            ..self
+        }
+    }
+
+    /// Set the file format version, see [`WriterBuilder::format_version`]
+    pub fn format_version(self, version: FormatVersion) -> Self {
+        Self {
+            builder: self.builder.format_version(version),
+            ..self
+        }
+    }
+
+    /// Writer specification of the file
+    pub fn spec(&self) -> WriterSpec {
+        self.builder.spec(self.elements_per_record)
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<Writer> {
+        self.builder.create(path, self.elements_per_record)
+    }
+
+    /// Write a complete file from the interleaved coefficients of all records in storage
+    /// order, one vector per channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        write_records(path, self.spec(), records)
+    }
+
+    /// Write a complete file from a magnitude and a phase spectrum of every record
+    ///
+    /// Both are given for all records in storage order, one vector per channel.
+    pub fn write_magnitude_phase<R, C>(
+        &self,
+        path: impl AsRef<Path>,
+        magnitudes: &[R],
+        phases: &[R],
+    ) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        check_record_count(&self.builder.grid, magnitudes.len())?;
+        check_record_count(&self.builder.grid, phases.len())?;
+        let mut writer = self.create(path)?;
+        for (magnitudes, phases) in magnitudes.iter().zip(phases) {
+            writer.append_magnitude_phase_record(magnitudes.as_ref(), phases.as_ref())?;
+        }
+        writer.finalize()
+    }
+
+    /// Write a complete file from the complex coefficients of all records in storage order,
+    /// one vector per channel
+    #[cfg(feature = "complex")]
+    pub fn write_complex<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[Complex<f32>]>,
+    {
+        check_record_count(&self.builder.grid, records.len())?;
+        let mut writer = self.create(path)?;
+        for record in records {
+            writer.append_complex_record(record.as_ref())?;
+        }
+        writer.finalize()
+    }
+}
+
+/// Builder for DFT spectrum files
+///
+/// Coefficients are given as interleaved real and imaginary parts, one vector per channel.
+/// By default only the non-redundant half of the spectrum of a real signal is stored
+/// (`transform_size / 2 + 1` coefficients from DC to Nyquist);
+/// [`full_spectrum`](DftWriterBuilder::full_spectrum) stores all coefficients. With the
+/// `complex` feature, [`write_complex`](DftWriterBuilder::write_complex) accepts
+/// `num_complex::Complex<f32>` values instead.
+///
+/// ```no_run
+/// use opendaff::writer::DftWriterBuilder;
+/// use opendaff::EquiangularGrid;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let grid = EquiangularGrid {
+///     alpha_points: 72,
+///     alpha_start: 0.0,
+///     alpha_end: 360.0,
+///     beta_points: 37,
+///     beta_start: 0.0,
+///     beta_end: 180.0,
+/// };
+/// let builder = DftWriterBuilder::new(grid, 2, 44100.0, 512);
+/// // Flat transfer functions: 257 coefficients 1 + 0i per channel
+/// let flat: Vec<f32> = (0..builder.num_coefficients()).flat_map(|_| [1.0, 0.0]).collect();
+/// let records = vec![vec![flat; 2]; grid.num_records()];
+/// builder.write("hrtf.daff", &records)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DftWriterBuilder {
+    builder: WriterBuilder,
+    samplerate: f64,
+    transform_size: usize,
+    symmetric: bool,
+}
+
+impl DftWriterBuilder {
+    /// Start a file with the given grid, number of channels, sampling rate in Hz and DFT size
+    pub fn new(
+        grid: EquiangularGrid,
+        num_channels: usize,
+        samplerate: f64,
+        transform_size: usize,
+    ) -> Self {
+        let header = ContentHeader::DftSpectrum {
+            samplerate,
+            transform_size,
+        };
+        Self {
+            builder: WriterBuilder::new(header, grid, num_channels),
+            samplerate,
+            transform_size,
+            symmetric: true,
+        }
+    }
+
+    /// Store all `transform_size` coefficients instead of the symmetric half
+    pub fn full_spectrum(mut self) -> Self {
+        self.symmetric = false;
+        self
+    }
+
+    /// Set the default orientation
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self {
+            builder: self.builder.orientation(orientation),
+            ..self
+        }
+    }
+
+    /// Set the file format version, see [`WriterBuilder::format_version`]
+    pub fn format_version(self, version: FormatVersion) -> Self {
+        Self {
+            builder: self.builder.format_version(version),
+            ..self
+        }
+    }
+
+    /// Number of complex coefficients stored per record and channel
+    pub fn num_coefficients(&self) -> usize {
+        if self.symmetric {
+            self.transform_size / 2 + 1
+        } else {
+            self.transform_size
+        }
+    }
+
+    /// Frequency spacing of the coefficients in Hz
+    pub fn frequency_resolution(&self) -> f64 {
+        self.samplerate / self.transform_size as f64
+    }
+
+    /// Writer specification of the file
+    pub fn spec(&self) -> WriterSpec {
+        self.builder.spec(2 * self.num_coefficients())
+    }
+
+    /// Start writing a file record by record
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<Writer> {
+        if self.transform_size == 0 {
+            return Err(Error::new("The transform size must be positive"));
+        }
+        self.builder.create(path, 2 * self.num_coefficients())
+    }
+
+    /// Write a complete file from the interleaved coefficients of all records in storage
+    /// order, one vector per channel
+    pub fn write<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[f32]>,
+    {
+        if self.transform_size == 0 {
+            return Err(Error::new("The transform size must be positive"));
+        }
+        write_records(path, self.spec(), records)
+    }
+
+    /// Write a complete file from the complex coefficients of all records in storage order,
+    /// one vector per channel
+    #[cfg(feature = "complex")]
+    pub fn write_complex<R, C>(&self, path: impl AsRef<Path>, records: &[R]) -> Result<()>
+    where
+        R: AsRef<[C]>,
+        C: AsRef<[Complex<f32>]>,
+    {
+        check_record_count(&self.builder.grid, records.len())?;
+        let mut writer = self.create(path)?;
+        for record in records {
+            writer.append_complex_record(record.as_ref())?;
+        }
+        writer.finalize()
+    }
+}
+
+fn check_record_count(grid: &EquiangularGrid, count: usize) -> Result<()> {
+    if count != grid.num_records() {
+        return Err(Error::new(format!(
+            "The grid has {} records, got {}",
+            grid.num_records(),
+            count
+        )));
+    }
+    Ok(())
+}
+
+/// Write a complete file with one record per grid point
+fn write_records<R, C>(path: impl AsRef<Path>, spec: WriterSpec, records: &[R]) -> Result<()>
+where
+    R: AsRef<[C]>,
+    C: AsRef<[f32]>,
+{
+    check_record_count(&spec.grid, records.len())?;
+    let mut writer = Writer::create(path, spec)?;
+    for record in records {
+        writer.append_record(record.as_ref())?;
+    }
+    writer.finalize()
+}
+
+/// Read-only access to the records of a file while a [`Writer`] is appending to it
+///
+/// ```no_run
+/// use opendaff::writer::Monitor;
+///
+/// # fn main() -> opendaff::Result<()> {
+/// let monitor = Monitor::open("hrir.daff")?;
+/// let available = monitor.records_available()?;
+/// if available > 0 {
+///     let latest = monitor.record(available - 1)?;
+///     println!("({}, {}): {:?}", latest.alpha, latest.beta, latest.channels[0]);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Monitor {
+    file: File,
+    spec: WriterSpec,
+    layout: Layout,
+}
+
+impl Monitor {
+    /// Open the partial file `<path>.part` of a file being written to `path`
+    ///
+    /// The monitor keeps working after the writer has finalized the file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let part = sibling(path.as_ref(), PART_EXTENSION);
+        let mut file = File::open(&part)
+            .map_err(|e| Error::new(format!("Failed to open '{}': {}", part.display(), e)))?;
+        let spec = read_spec(&mut file)?;
+        let layout = Layout::new(&spec);
+        Ok(Self { file, spec, layout })
+    }
+
+    /// Specification the file is written with
+    pub fn spec(&self) -> &WriterSpec {
+        &self.spec
+    }
+
+    /// Total number of records of the file
+    pub fn num_records(&self) -> usize {
+        self.spec.grid.num_records()
+    }
+
+    /// Number of records the writer has completed so far
+    pub fn records_available(&self) -> Result<usize> {
+        let mut bytes = [0; 8];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(DATA_SIZE_POSITION))
+            .map_err(read_error)?;
+        file.read_exact(&mut bytes).map_err(read_error)?;
+        let records = u64::from_le_bytes(bytes) / self.spec.record_size();
+        Ok((records as usize).min(self.num_records()))
+    }
+
+    /// Read a completed record
+    pub fn record(&self, index: usize) -> Result<Record> {
+        let available = self.records_available()?;
+        if index >= available {
+            return Err(Error::new(format!(
+                "Record {} has not been written yet ({} available)",
+                index, available
+            )));
+        }
+        let record_size = self.spec.record_size();
+        let mut bytes = vec![0; record_size as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(
+            self.layout.data + index as u64 * record_size,
+        ))
+        .map_err(read_error)?;
+        file.read_exact(&mut bytes).map_err(read_error)?;
+
+        let (alpha, beta) = self.spec.grid.record_coords(index).unwrap_or_default();
+        let channels = bytes
+            .chunks(bytes.len() / self.spec.num_channels)
+            .map(|channel| decode(channel, self.spec.quantization))
+            .collect();
+        Ok(Record {
+            alpha,
+            beta,
+            channels,
+        })
+    }
+}
+
+/// Reconstruct the specification from the headers of a partial file
+fn read_spec(file: &mut File) -> Result<WriterSpec> {
+    let table_end = FILE_HEADER_SIZE + NUM_FILE_BLOCKS as u64 * FILE_BLOCK_ENTRY_SIZE;
+    let mut bytes = vec![0; table_end as usize];
+    file.read_exact(&mut bytes).map_err(read_error)?;
+
+    let mut input = Input::new(&bytes);
+    let signature = input.take(2)?;
+    let version = input.i32()?;
+    let num_blocks = input.i32()?;
+    let format_version = FormatVersion::from_number(version)
+        .filter(|_| signature == b"FW" && num_blocks == NUM_FILE_BLOCKS as i32)
+        .ok_or_else(|| Error::new("Not a partial file written by the DAFF writer"))?;
+    let mut content_header = None;
+    for _ in 0..NUM_FILE_BLOCKS {
+        let id = input.i32()?;
+        let offset = input.u64()?;
+        let size = input.u64()?;
+        if id == CONTENT_HEADER_ID {
+            content_header = Some((offset, size));
+        }
+    }
+    let Some((content_offset, content_size)) = content_header else {
+        return Err(Error::new("Partial file has no content header"));
+    };
+    if content_offset != table_end + MAIN_HEADER_SIZE {
+        return Err(Error::new("Not a partial file written by the DAFF writer"));
+    }
+    bytes.resize((content_offset + content_size) as usize, 0);
+    file.read_exact(&mut bytes[table_end as usize..])
+        .map_err(read_error)?;
+
+    let mut input = Input::new(&bytes[table_end as usize..]);
+    let content_type = ContentType::from_i32(input.i32()?);
+    let quantization = Quantization::from_i32(input.i32()?);
+    let (Some(content_type), Some(quantization)) = (content_type, quantization) else {
+        return Err(Error::new("Invalid content type or quantization"));
+    };
+    let num_channels = input.count()?;
+    let _num_records = input.i32()?;
+    let stored_elements = input.count()?;
+    let _metadata_index = input.i32()?;
+    let grid = EquiangularGrid {
+        alpha_points: input.count()?,
+        alpha_start: input.f32()?,
+        alpha_end: input.f32()?,
+        beta_points: input.count()?,
+        beta_start: input.f32()?,
+        beta_end: input.f32()?,
+    };
+    let orientation = Orientation {
+        yaw: input.f32()?,
+        pitch: input.f32()?,
+        roll: input.f32()?,
+    };
+
+    let frequencies = |input: &mut Input| -> Result<Vec<f32>> {
+        let count = input.count()?;
+        (0..count).map(|_| Ok(input.f32()?)).collect()
+    };
+    let (header, elements_per_record) = match content_type {
+        ContentType::ImpulseResponse => (
+            ContentHeader::ImpulseResponse {
+                samplerate: input.f32()? as f64,
+            },
+            stored_elements,
+        ),
+        ContentType::MagnitudeSpectrum => {
+            input.f32()?;
+            (
+                ContentHeader::MagnitudeSpectrum {
+                    frequencies: frequencies(&mut input)?,
+                },
+                stored_elements,
+            )
+        }
+        ContentType::PhaseSpectrum => (
+            ContentHeader::PhaseSpectrum {
+                frequencies: frequencies(&mut input)?,
+            },
+            stored_elements,
+        ),
+        ContentType::MagnitudePhaseSpectrum => {
+            input.f32()?;
+            (
+                ContentHeader::MagnitudePhaseSpectrum {
+                    frequencies: frequencies(&mut input)?,
+                },
+                2 * stored_elements,
+            )
+        }
+        ContentType::DftSpectrum => {
+            input.i32()?;
+            let transform_size = input.count()?;
+            (
+                ContentHeader::DftSpectrum {
+                    transform_size,
+                    samplerate: input.f32()? as f64,
+                },
+                2 * stored_elements,
+            )
+        }
+    };
+
+    let spec = WriterSpec {
+        header,
+        quantization,
+        grid,
+        orientation,
+        format_version,
+        num_channels,
+        elements_per_record,
+    };
+    spec.validate()?;
+    Ok(spec)
+}
+
+/// Parse a journal: the global metadata set, followed by the record index and metadata set of
+/// every annotated record
+fn decode_journal(bytes: &[u8]) -> Result<(Metadata, BTreeMap<usize, Metadata>)> {
+    let mut input = Input::new(bytes);
+    let metadata = decode_metadata(&mut input)?;
+    let mut record_metadata = BTreeMap::new();
+    while !input.is_empty() {
+        let record = input.count()?;
+        record_metadata.insert(record, decode_metadata(&mut input)?);
+    }
+    Ok((metadata, record_metadata))
+}
+
+/// Check the keys and string values of a metadata set
+fn check_metadata(metadata: &Metadata) -> Result<()> {
+    for (key, value) in metadata {
+        match value {
+            MetadataValue::String(value) => metadata::check_string(key, value)?,
+            _ => metadata::check_key(key)?,
+        }
+    }
+    Ok(())
+}
+
+/// Serialize a metadata set: number of keys, then type, NUL-terminated key and value per key
+pub(crate) fn encode_metadata(metadata: &Metadata) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    put_i32(&mut bytes, metadata.len() as i32);
+    for (key, value) in metadata {
+        metadata::check_key(key)?;
+        if key.contains('\0') {
+            return Err(Error::invalid_metadata(key, "key contains a NUL character"));
+        }
+        put_i32(&mut bytes, value.value_type() as i32);
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+        match value {
+            MetadataValue::Bool(b) => put_i32(&mut bytes, *b as i32),
+            MetadataValue::Int(i) => put_i32(&mut bytes, *i),
+            MetadataValue::Float(f) => bytes.extend_from_slice(&f.to_le_bytes()),
+            MetadataValue::String(s) => {
+                metadata::check_string(key, s)?;
+                bytes.extend_from_slice(s.as_bytes());
+                bytes.push(0);
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Convert stored channel data back to floats
+fn decode(bytes: &[u8], quantization: Quantization) -> Vec<f32> {
+    let mut data = vec![0.0; bytes.len() / quantization_size(quantization)];
+    decode_into(bytes, quantization, ByteOrder::LittleEndian, &mut data);
+    data
+}
+
+/// Append channel data in the given quantization
+///
+/// Integer quantization maps the range [-1, 1] to the full integer range; values outside are
+/// clipped. With a noise source, TPDF dither is added before rounding.
+fn encode(
+    bytes: &mut Vec<u8>,
+    data: &[f32],
+    quantization: Quantization,
+    mut noise: Option<&mut TpdfNoise>,
+) {
+    let mut dither = || noise.as_mut().map_or(0.0, |n| n.sample());
+    match quantization {
+        Quantization::Float32 => data.iter().for_each(|&x| put_f32(bytes, x)),
+        Quantization::Int16 => data.iter().for_each(|&x| {
+            let value = (x as f64 * 32767.0 + dither())
+                .round()
+                .clamp(-32768.0, 32767.0) as i16;
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }),
+        Quantization::Int24 => data.iter().for_each(|&x| {
+            let value = (x as f64 * 8388607.0 + dither())
+                .round()
+                .clamp(-8388608.0, 8388607.0) as i32;
+            bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+        }),
+    }
+}
+
+/// Peak value of channel data as stored in the content header
+fn peak(header: &ContentHeader, data: &[f32]) -> f32 {
+    match header {
+        ContentHeader::MagnitudePhaseSpectrum { .. } | ContentHeader::DftSpectrum { .. } => {
+            data.chunks(2).map(|c| c[0].hypot(c[1])).fold(0.0, f32::max)
+        }
+        _ => data.iter().fold(0.0f32, |a, x| a.max(x.abs())),
+    }
+}
+
+fn check_frequencies(frequencies: &[f32]) -> Result<()> {
+    if frequencies.is_empty() {
+        return Err(Error::new("At least one support frequency is required"));
+    }
+    if frequencies[0] <= 0.0 || frequencies.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(Error::new(
+            "Support frequencies must be positive and strictly increasing",
+        ));
+    }
+    Ok(())
+}
+
+fn align(position: u64) -> u64 {
+    (position + BLOCK_ALIGNMENT - 1) / BLOCK_ALIGNMENT * BLOCK_ALIGNMENT
+}
+
+pub(crate) fn put_i32(bytes: &mut Vec<u8>, value: i32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn put_f32(bytes: &mut Vec<u8>, value: f32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+/// `path` with `extension` appended to its file name
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(write_error(e)),
+        _ => Ok(()),
+    }
+}
+
+fn write_error(error: std::io::Error) -> Error {
+    Error::new(format!("Failed to write DAFF file: {}", error))
+}
+
+fn read_error(error: std::io::Error) -> Error {
+    Error::new(format!("Failed to read partial DAFF file: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn grid() -> EquiangularGrid {
+        EquiangularGrid {
+            alpha_points: 4,
+            alpha_start: 0.0,
+            alpha_end: 360.0,
+            beta_points: 3,
+            beta_start: 0.0,
+            beta_end: 180.0,
+        }
+    }
+
+    fn read(path: &Path) -> Dataset {
+        let reader = Reader::open(path).unwrap();
+        Dataset::from_reader(&reader).unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_incremental_ir_round_trip() {
+        let path = temp_path("incremental.daff");
+        let spec = WriterSpec {
+            header: ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            quantization: Quantization::Float32,
+            grid: grid(),
+            orientation: Orientation {
+                yaw: 90.0,
+                pitch: 0.0,
+                roll: 0.0,
+            },
+            format_version: FormatVersion::V1_7,
+            num_channels: 2,
+            elements_per_record: 5,
+        };
+        let mut writer = Writer::create(&path, spec).unwrap();
+        writer.metadata_mut().insert(
+            "DESCRIPTION".to_string(),
+            MetadataValue::String("Test".into()),
+        );
+        writer
+            .metadata_mut()
+            .insert("DISTANCE".to_string(), MetadataValue::Float(1.5));
+        assert_eq!(writer.num_records(), 6);
+
+        let mut written = Vec::new();
+        while let Some((alpha, beta)) = writer.next_direction() {
+            let record = [
+                vec![alpha / 360.0, beta / 180.0, 0.0, -0.5, 0.25],
+                vec![0.0, 0.0, 1.0, 0.0, -1.0],
+            ];
+            writer.append_record(&record).unwrap();
+            written.push(record);
+        }
+        assert!(writer.append_record(&written[0]).is_err());
+        writer.finalize().unwrap();
+
+        let dataset = read(&path);
+        assert_eq!(
+            dataset.header,
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0
+            }
+        );
+        assert_eq!(dataset.grid.equiangular(), Some(&grid()));
+        assert_eq!(dataset.orientation.yaw, 90.0);
+        assert_eq!(
+            dataset.metadata["DESCRIPTION"],
+            MetadataValue::String("Test".into())
+        );
+        assert_eq!(dataset.metadata["DISTANCE"], MetadataValue::Float(1.5));
+        for (record, expected) in dataset.records.iter().zip(&written) {
+            assert_eq!(record.channels, expected);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tpdf_dither() {
+        // A quarter LSB rounds to zero without dither, but survives on average with it
+        let data = vec![0.25f32 / 32767.0; 20000];
+        let mean = |bytes: &[u8]| {
+            let values = decode(bytes, Quantization::Int16);
+            values.iter().map(|&x| x as f64 * 32767.0).sum::<f64>() / values.len() as f64
+        };
+        let mut plain = Vec::new();
+        encode(&mut plain, &data, Quantization::Int16, None);
+        assert_eq!(mean(&plain), 0.0);
+
+        let mut noise = TpdfNoise { state: 7 };
+        let mut dithered = Vec::new();
+        encode(&mut dithered, &data, Quantization::Int16, Some(&mut noise));
+        assert!((mean(&dithered) - 0.25).abs() < 0.02);
+        // Triangular noise of ±1 LSB never moves a sample by more than one step
+        let values = decode(&dithered, Quantization::Int16);
+        assert!(values.iter().all(|&x| (x * 32767.0).round().abs() <= 1.0));
+
+        let mut again = Vec::new();
+        let mut noise = TpdfNoise { state: 7 };
+        encode(&mut again, &data, Quantization::Int16, Some(&mut noise));
+        assert_eq!(again, dithered);
+        let mut float = Vec::new();
+        encode(&mut float, &data, Quantization::Float32, Some(&mut noise));
+        assert_eq!(decode(&float, Quantization::Float32), data);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "file system access")]
+    fn test_dithered_writer_is_reproducible() {
+        let records = vec![[vec![1e-5f32; 64], vec![-0.5; 64]]; 6];
+        let builder = IrWriterBuilder::new(grid(), 2, 48000.0)
+            .quantization(Quantization::Int24)
+            .dither(Dither::Tpdf { seed: 42 });
+        let first = temp_path("dither-a.daff");
+        let second = temp_path("dither-b.daff");
+        builder.write(&first, &records).unwrap();
+        builder.write(&second, &records).unwrap();
+        assert_eq!(
+            std::fs::read(&first).unwrap(),
+            std::fs::read(&second).unwrap()
+        );
+
+        let plain = temp_path("dither-c.daff");
+        builder
+            .clone()
+            .dither(Dither::None)
+            .write(&plain, &records)
+            .unwrap();
+        assert_ne!(
+            std::fs::read(&first).unwrap(),
+            std::fs::read(&plain).unwrap()
+        );
+
+        let writer = builder.create(&first, 64).unwrap();
+        assert_eq!(writer.dither(), Dither::Tpdf { seed: 42 });
+        drop(writer);
+        std::fs::remove_file(sibling(&first, PART_EXTENSION)).unwrap();
+        for path in [first, second, plain] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_spectra_and_quantization() {
+        let path = temp_path("spectra.daff");
+        let mut spec = WriterSpec {
+            header: ContentHeader::MagnitudePhaseSpectrum {
+                frequencies: vec![100.0, 1000.0],
+            },
+            quantization: Quantization::Float32,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 1,
+            elements_per_record: 4,
+        };
+        let mut writer = Writer::create(&path, spec.clone()).unwrap();
+        for _ in 0..writer.num_records() {
+            writer.append_record(&[[0.5, 0.0, 0.0, -2.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        let dataset = read(&path);
+        let channel = &dataset.records[3].channels[0];
+        assert!((channel[0] - 0.5).abs() < 1e-6 && (channel[3] + 2.0).abs() < 1e-6);
+
+        spec.quantization = Quantization::Int16;
+        assert!(Writer::create(&path, spec.clone()).is_err());
+        spec.quantization = Quantization::Float32;
+        spec.elements_per_record = 3;
+        assert!(Writer::create(&path, spec).is_err());
+
+        let spec = WriterSpec {
+            header: ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            quantization: Quantization::Int24,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 1,
+            elements_per_record: 3,
+        };
+        let mut writer = Writer::create(&path, spec).unwrap();
+        for _ in 0..writer.num_records() {
+            writer.append_record(&[[0.5, -1.0, 2.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        let dataset = read(&path);
+        assert_eq!(dataset.quantization, Quantization::Int24);
+        let channel = &dataset.records[0].channels[0];
+        assert!((channel[0] - 0.5).abs() < 1e-6);
+        assert_eq!(channel[1..], [-1.0, 1.0]);
+
+        let spec = WriterSpec {
+            header: ContentHeader::PhaseSpectrum {
+                frequencies: vec![250.0, 500.0],
+            },
+            quantization: Quantization::Float32,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 1,
+            elements_per_record: 2,
+        };
+        let mut writer = Writer::create(&path, spec.clone()).unwrap();
+        for _ in 0..writer.num_records() {
+            writer.append_record(&[[0.5, -3.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        let dataset = read(&path);
+        assert_eq!(dataset.header, spec.header);
+        assert_eq!(dataset.records[5].channels[0], [0.5, -3.0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_resume_after_interruption() {
+        let path = temp_path("resume.daff");
+        let spec = WriterSpec {
+            header: ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![100.0, 200.0],
+            },
+            quantization: Quantization::Float32,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 1,
+            elements_per_record: 2,
+        };
+        let mut writer = Writer::create(&path, spec.clone()).unwrap();
+        writer
+            .metadata_mut()
+            .insert("SESSION".to_string(), MetadataValue::Int(7));
+        writer.append_record(&[[4.0, 0.5]]).unwrap();
+        writer.append_record(&[[1.0, 1.0]]).unwrap();
+        assert!(writer.append_record(&[[0.0]]).is_err());
+        assert_eq!(writer.records_written(), 2);
+        // Simulate a crash while the third record is written
+        drop(writer);
+        let part = sibling(&path, PART_EXTENSION);
+        let mut file = OpenOptions::new().append(true).open(&part).unwrap();
+        file.write_all(&[0; 5]).unwrap();
+        drop(file);
+        assert!(!path.exists());
+
+        let writer = Writer::resume(&path).unwrap();
+        assert_eq!(writer.spec(), &spec);
+        assert_eq!(writer.records_written(), 2);
+        assert!(writer.finalize().is_err());
+
+        let mut writer = Writer::resume(&path).unwrap();
+        assert_eq!(writer.next_direction(), grid().record_coords(2));
+        while writer.next_direction().is_some() {
+            writer.append_record(&[[0.25, 2.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        assert!(!part.exists());
+        assert!(!sibling(&path, JOURNAL_EXTENSION).exists());
+
+        let reader = Reader::open(&path).unwrap();
+        let dataset = Dataset::from_reader(&reader).unwrap();
+        assert_eq!(dataset.metadata["SESSION"], MetadataValue::Int(7));
+        assert_eq!(dataset.records[0].channels[0], [4.0, 0.5]);
+        assert_eq!(dataset.records[5].channels[0], [0.25, 2.0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_monitor_follows_writer() {
+        let path = temp_path("monitor.daff");
+        let spec = WriterSpec {
+            header: ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            quantization: Quantization::Int16,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 2,
+            elements_per_record: 2,
+        };
+        let mut writer = Writer::create(&path, spec.clone()).unwrap();
+        let monitor = Monitor::open(&path).unwrap();
+        assert_eq!(monitor.spec(), &spec);
+        assert_eq!(monitor.records_available().unwrap(), 0);
+        assert!(monitor.record(0).is_err());
+
+        writer.append_record(&[[0.5, -0.5], [1.0, 0.0]]).unwrap();
+        assert_eq!(monitor.records_available().unwrap(), 1);
+        let record = monitor.record(0).unwrap();
+        assert_eq!((record.alpha, record.beta), (0.0, 0.0));
+        assert!((record.channels[0][0] - 0.5).abs() < 1e-4);
+        assert!((record.channels[0][1] + 0.5).abs() < 1e-4);
+        assert_eq!(record.channels[1], [1.0, 0.0]);
+
+        while writer.next_direction().is_some() {
+            writer.append_record(&[[0.0, 0.0], [0.0, 0.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        assert_eq!(monitor.records_available().unwrap(), monitor.num_records());
+        assert_eq!(monitor.record(0).unwrap(), record);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_writer_builder() {
+        let path = temp_path("builder.daff");
+        let builder = WriterBuilder::new(
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            grid(),
+            2,
+        )
+        .quantization(Quantization::Int16)
+        .orientation(Orientation {
+            yaw: 10.0,
+            pitch: -90.0,
+            roll: 0.0,
+        })
+        .metadata("Description", "dummy head")
+        .metadata("DISTANCE", 1.5)
+        .metadata("REPETITIONS", 3)
+        .metadata("CALIBRATED", true)
+        .channel_label(0, "Right")
+        .channel_label(1, "Left ear")
+        .channel_label(0, "Right ear");
+        let records = vec![[vec![0.5, -0.25], vec![0.0, 0.125]]; 6];
+        builder.write(&path, &records).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.quantization(), Some(Quantization::Int16));
+        assert_eq!(reader.orientation().unwrap().yaw, 10.0);
+        assert_eq!(reader.metadata_string("DESCRIPTION").unwrap(), "dummy head");
+        assert_eq!(reader.metadata_float("distance").unwrap(), 1.5);
+        assert!(reader.metadata_bool("CALIBRATED").unwrap());
+        assert_eq!(
+            reader.metadata_string("LABEL_CHANNEL_1").unwrap(),
+            "Right ear"
+        );
+        assert_eq!(
+            reader.metadata_string("LABEL_CHANNEL_2").unwrap(),
+            "Left ear"
+        );
+        let dataset = Dataset::from_reader(&reader).unwrap();
+        assert_eq!(dataset.metadata["REPETITIONS"], MetadataValue::Int(3));
+        let channel = &dataset.records[1].channels[0];
+        assert!((channel[0] - 0.5).abs() < 1e-4 && (channel[1] + 0.25).abs() < 1e-4);
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(builder
+            .clone()
+            .channel_label(2, "Center")
+            .create(&path, 2)
+            .is_err());
+        assert!(builder
+            .clone()
+            .metadata("COMMENT", "a\0b")
+            .create(&path, 2)
+            .is_err());
+        assert!(builder.metadata("", 1).create(&path, 2).is_err());
+        assert!(!path.exists() && !sibling(&path, PART_EXTENSION).exists());
+    }
+
+    /// Metadata sets of a finished file and the set index of every record
+    fn stored_record_metadata(path: &Path, num_channels: usize) -> (Vec<Metadata>, Vec<i32>) {
+        let bytes = std::fs::read(path).unwrap();
+        let mut input = Input::new(&bytes[6..]);
+        let mut blocks = BTreeMap::new();
+        for _ in 0..input.i32().unwrap() {
+            let id = input.i32().unwrap();
+            blocks.insert(
+                id,
+                (input.u64().unwrap() as usize, input.u64().unwrap() as usize),
+            );
+        }
+        let block = |id| {
+            let (offset, size) = blocks[&id];
+            &bytes[offset..offset + size]
+        };
+
+        let mut input = Input::new(block(METADATA_ID));
+        let mut sets = Vec::new();
+        while !input.is_empty() {
+            sets.push(decode_metadata(&mut input).unwrap());
+        }
+        let descriptors = block(RECORD_DESC_ID);
+        let record_size = descriptors.len() / (grid().num_records() * num_channels);
+        let indices = descriptors
+            .chunks(record_size * num_channels)
+            .map(|record| {
+                // Every channel descriptor carries the index of its record
+                let index = i32::from_le_bytes(record[..4].try_into().unwrap());
+                assert!(record
+                    .chunks(record_size)
+                    .all(|channel| channel[..4] == record[..4]));
+                index
+            })
+            .collect();
+        (sets, indices)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_record_metadata() {
+        let path = temp_path("record-metadata.daff");
+        let builder = WriterBuilder::new(
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            grid(),
+            2,
+        )
+        .metadata("DESCRIPTION", "calibrated")
+        .record_metadata(0, "mic_gain", 12.5)
+        .record_metadata(0, "TIMESTAMP", "2026-10-17T10:00:00Z")
+        .record_metadata(3, "MIC_GAIN", 11.0)
+        .record_metadata(5, "MIC_GAIN", 11.0);
+        let records = vec![[vec![0.5, -0.25], vec![0.0, 0.125]]; 6];
+        builder.write(&path, &records).unwrap();
+
+        let (sets, indices) = stored_record_metadata(&path, 2);
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0]["DESCRIPTION"], MetadataValue::from("calibrated"));
+        assert_eq!(indices, [1, -1, -1, 2, -1, 2]);
+        assert_eq!(sets[1]["MIC_GAIN"], MetadataValue::Float(12.5));
+        assert_eq!(
+            sets[1]["TIMESTAMP"],
+            MetadataValue::from("2026-10-17T10:00:00Z")
+        );
+        assert_eq!(sets[2].len(), 1);
+        assert_eq!(sets[2]["MIC_GAIN"], MetadataValue::Float(11.0));
+        // The DAFF library still finds the global metadata and the data
+        let dataset = read(&path);
+        assert_eq!(dataset.metadata, sets[0]);
+        assert_eq!(dataset.records[3].channels[0], [0.5, -0.25]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(builder
+            .clone()
+            .record_metadata(6, "MIC_GAIN", 1.0)
+            .create(&path, 2)
+            .is_err());
+        assert!(builder
+            .record_metadata(1, "COMMENT", "a\0b")
+            .create(&path, 2)
+            .is_err());
+        assert!(!path.exists() && !sibling(&path, PART_EXTENSION).exists());
+
+        // Annotations survive an interruption and can be changed until the file is finalized
+        let spec = WriterSpec {
+            header: ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![100.0, 200.0],
+            },
+            quantization: Quantization::Float32,
+            grid: grid(),
+            orientation: Orientation::default(),
+            format_version: FormatVersion::V1_7,
+            num_channels: 1,
+            elements_per_record: 2,
+        };
+        let annotation =
+            |gain: i32| Metadata::from([("GAIN".to_string(), MetadataValue::Int(gain))]);
+        let mut writer = Writer::create(&path, spec).unwrap();
+        assert!(writer.set_record_metadata(6, annotation(1)).is_err());
+        writer.set_record_metadata(0, annotation(1)).unwrap();
+        writer.set_record_metadata(4, annotation(2)).unwrap();
+        writer.append_record(&[[1.0, 1.0]]).unwrap();
+        drop(writer);
+
+        let mut writer = Writer::resume(&path).unwrap();
+        assert_eq!(writer.record_metadata(0), Some(&annotation(1)));
+        assert_eq!(writer.record_metadata(4), Some(&annotation(2)));
+        writer.set_record_metadata(4, Metadata::new()).unwrap();
+        assert_eq!(writer.record_metadata(4), None);
+        writer.set_record_metadata(2, annotation(1)).unwrap();
+        while writer.next_direction().is_some() {
+            writer.append_record(&[[1.0, 1.0]]).unwrap();
+        }
+        writer.finalize().unwrap();
+        let (sets, indices) = stored_record_metadata(&path, 1);
+        assert_eq!(sets, [Metadata::new(), annotation(1)]);
+        assert_eq!(indices, [1, -1, 1, -1, -1, -1]);
+        assert_eq!(read(&path).records.len(), 6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "file system access")]
+    fn test_streaming_writer() {
+        let path = temp_path("streaming.daff");
+        let grid = EquiangularGrid::with_resolution(30.0, 30.0).unwrap();
+        let builder = WriterBuilder::new(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            grid,
+            1,
+        )
+        .buffer_budget(64);
+        let record = |i: usize| [vec![i as f32; 4]];
+
+        // Records are buffered until they exceed the budget of four 16-byte records
+        let mut writer = builder.create(&path, 4).unwrap();
+        let monitor = Monitor::open(&path).unwrap();
+        assert_eq!(writer.append_records((0..3).map(record)).unwrap(), 3);
+        assert_eq!(writer.records_written(), 3);
+        assert_eq!(monitor.records_available().unwrap(), 0);
+        writer.append_record(&record(3)).unwrap();
+        assert_eq!(monitor.records_available().unwrap(), 4);
+        writer.append_record(&record(4)).unwrap();
+        writer.sync().unwrap();
+        assert_eq!(monitor.records_available().unwrap(), 5);
+
+        writer.set_buffer_budget(0).unwrap();
+        writer.append_record(&record(5)).unwrap();
+        assert_eq!(monitor.records_available().unwrap(), 6);
+        drop(monitor);
+        writer
+            .append_records((6..grid.num_records()).map(record))
+            .unwrap();
+        assert!(writer.append_record(&record(0)).is_err());
+        writer.finalize().unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let dataset = Dataset::from_reader(&reader).unwrap();
+        assert_eq!(dataset.records.len(), grid.num_records());
+        for (i, record) in dataset.records.iter().enumerate() {
+            assert_eq!(record.channels[0], [i as f32; 4]);
+        }
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+
+        // Too few records leave the partial file in place
+        assert!(builder.write_iter(&path, 4, (0..10).map(record)).is_err());
+        let writer = Writer::resume(&path).unwrap();
+        assert_eq!(writer.records_written(), 10);
+        drop(writer);
+        std::fs::remove_file(sibling(&path, PART_EXTENSION)).unwrap();
+
+        builder
+            .write_iter(&path, 4, (0..grid.num_records()).map(record))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_format_version() {
+        assert_eq!(FormatVersion::default(), FormatVersion::V1_7);
+        assert_eq!(FormatVersion::V1_7.to_string(), "1.7");
+        assert_eq!(FormatVersion::V1_05.to_string(), "1.05");
+        assert_eq!(FormatVersion::from_number(170), Some(FormatVersion::V1_7));
+        assert_eq!(FormatVersion::from_number(105), Some(FormatVersion::V1_05));
+        assert_eq!(FormatVersion::from_number(150), None);
+
+        let records: Vec<Vec<Vec<f32>>> = (0..grid().num_records())
+            .map(|index| vec![vec![index as f32 / 16.0, 0.5, -0.25], vec![0.125; 3]])
+            .collect();
+        let current = temp_path("format-version-current.daff");
+        let legacy = temp_path("format-version-legacy.daff");
+        for (path, version) in [
+            (&current, FormatVersion::V1_7),
+            (&legacy, FormatVersion::V1_05),
+        ] {
+            IrWriterBuilder::new(grid(), 2, 44100.0)
+                .quantization(Quantization::Int16)
+                .format_version(version)
+                .write(path, &records)
+                .unwrap();
+            let bytes = fs::read(path).unwrap();
+            assert_eq!(&bytes[..2], b"FW");
+            assert_eq!(
+                i32::from_le_bytes(bytes[2..6].try_into().unwrap()),
+                version.number()
+            );
+        }
+        // The legacy file is read through the upgrade path with the same content
+        let reader = Reader::open(&legacy).unwrap();
+        assert_eq!(reader.file_format_version().unwrap(), 105);
+        assert_eq!(Dataset::from_reader(&reader).unwrap(), read(&current));
+
+        let frequencies = vec![250.0, 500.0, 1000.0];
+        let builder = MsWriterBuilder::new(grid(), 1, frequencies.clone())
+            .format_version(FormatVersion::V1_05);
+        let spectra = vec![vec![vec![1.0, 0.5, 0.25]]; grid().num_records()];
+        builder.write(&legacy, &spectra).unwrap();
+        let dataset = read(&legacy);
+        assert_eq!(
+            dataset.header,
+            ContentHeader::MagnitudeSpectrum { frequencies }
+        );
+        assert_eq!(dataset.records[5].channels, spectra[5]);
+
+        // Record metadata has no place in the legacy layout
+        let builder = WriterBuilder::new(dataset.header.clone(), grid(), 1)
+            .format_version(FormatVersion::V1_05);
+        assert!(builder
+            .clone()
+            .record_metadata(0, "GAIN", 1.0)
+            .create(&legacy, 3)
+            .is_err());
+        let mut writer = builder.create(&legacy, 3).unwrap();
+        let mut metadata = Metadata::new();
+        metadata.insert("GAIN".to_string(), MetadataValue::Float(1.0));
+        assert!(writer.set_record_metadata(0, metadata).is_err());
+        drop(writer);
+        fs::remove_file(&current).unwrap();
+        fs::remove_file(&legacy).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_transcode() {
+        let input = temp_path("transcode-float.daff");
+        let output = temp_path("transcode-int16.daff");
+        let orientation = Orientation {
+            yaw: 30.0,
+            pitch: 0.0,
+            roll: 0.0,
+        };
+        let builder = WriterBuilder::new(
+            ContentHeader::ImpulseResponse {
+                samplerate: 48000.0,
+            },
+            grid(),
+            2,
+        )
+        .orientation(orientation)
+        .metadata("DESCRIPTION", "Dummy head")
+        .channel_label(1, "Right ear");
+        let records: Vec<_> = (0..grid().num_records())
+            .map(|i| vec![vec![i as f32 / 20.0, -0.5], vec![0.25; 2]])
+            .collect();
+        builder.write(&input, &records).unwrap();
+
+        transcode(&input, &output, Quantization::Int16, Dither::None).unwrap();
+        let source = read(&input);
+        let transcoded = read(&output);
+        assert_eq!(transcoded.quantization, Quantization::Int16);
+        assert_eq!(transcoded.grid, source.grid);
+        assert_eq!(transcoded.orientation, orientation);
+        assert_eq!(transcoded.metadata, source.metadata);
+        for (a, b) in transcoded.records.iter().zip(&source.records) {
+            for (x, y) in a.channels.iter().flatten().zip(b.channels.iter().flatten()) {
+                assert!((x - y).abs() <= 1.0 / 32768.0);
+            }
+        }
+        assert!(
+            std::fs::metadata(&output).unwrap().len() < std::fs::metadata(&input).unwrap().len()
+        );
+
+        // Back to floats without further loss
+        transcode(&output, &input, Quantization::Float32, Dither::None).unwrap();
+        assert_eq!(read(&input).records, transcoded.records);
+
+        // Data beyond the integer range is not clipped silently
+        let loud: Vec<_> = records.iter().map(|_| vec![vec![1.5, 0.0]; 2]).collect();
+        builder.write(&input, &loud).unwrap();
+        assert!(transcode(&input, &output, Quantization::Int24, Dither::None).is_err());
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_write_cropped_dataset() {
+        let path = temp_path("cropped.daff");
+        let dataset = Dataset::from_fn(
+            ContentHeader::ImpulseResponse {
+                samplerate: 44100.0,
+            },
+            EquiangularGrid::with_resolution(30.0, 30.0).unwrap(),
+            2,
+            |alpha, beta, channel| vec![alpha / 360.0, beta / 180.0, channel as f32],
+        );
+        let region = crate::grid::Region::Window {
+            alpha_start: 330.0,
+            alpha_end: 30.0,
+            beta_start: 90.0,
+            beta_end: 180.0,
+        };
+        let cropped = crate::grid::crop(&dataset, &region).unwrap();
+        write_dataset(&path, &cropped).unwrap();
+
+        let written = read(&path);
+        assert_eq!(written.grid, cropped.grid);
+        // The DAFF library does not wrap alpha angles beyond 360°
+        for (a, b) in written.records.iter().zip(&cropped.records) {
+            assert_eq!((a.alpha % 360.0, a.beta), (b.alpha, b.beta));
+            assert_eq!(a.channels, b.channels);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let mut irregular = cropped;
+        irregular.grid = crate::Grid::Irregular;
+        assert!(write_dataset(&path, &irregular).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_record_provider() {
+        let path = temp_path("provider.daff");
+        let grid = EquiangularGrid::with_resolution(30.0, 30.0).unwrap();
+        let builder = WriterBuilder::new(
+            ContentHeader::MagnitudeSpectrum {
+                frequencies: vec![1000.0, 2000.0],
+            },
+            grid,
+            2,
+        );
+        let mut provider =
+            FnProvider::new(|alpha, beta, channel| vec![alpha + beta, channel as f32]);
+
+        // Invalid channels leave the writer untouched
+        let mut writer = builder.create(&path, 2).unwrap();
+        let mut calls = 0;
+        let mut negative = FnProvider::new(|_, _, channel| {
+            calls += 1;
+            vec![if channel == 1 { -1.0 } else { 1.0 }; 2]
+        });
+        assert!(writer.append_from(&mut negative).is_err());
+        assert!(writer
+            .append_from(&mut FnProvider::new(|_, _, _| vec![1.0]))
+            .is_err());
+        assert_eq!(writer.records_written(), 0);
+        assert_eq!(calls, 2);
+
+        // Resumed sessions only pull the missing records
+        for _ in 0..5 {
+            writer.append_from(&mut provider).unwrap();
+        }
+        writer.sync().unwrap();
+        drop(writer);
+        let mut writer = Writer::resume(&path).unwrap();
+        let mut pulled = Vec::new();
+        writer
+            .write_from(&mut FnProvider::new(|alpha, beta, channel| {
+                pulled.push((alpha, beta));
+                vec![alpha + beta, channel as f32]
+            }))
+            .unwrap();
+        assert_eq!(pulled.len(), 2 * (grid.num_records() - 5));
+        assert_eq!(pulled[0], grid.directions().nth(5).unwrap());
+        assert!(writer.append_from(&mut provider).is_err());
+        writer.finalize().unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let dataset = Dataset::from_reader(&reader).unwrap();
+        for record in &dataset.records {
+            assert_eq!(record.channels[0], [record.alpha + record.beta, 0.0]);
+            assert_eq!(record.channels[1], [record.alpha + record.beta, 1.0]);
+        }
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+
+        builder.write_from(&path, 2, &mut provider).unwrap();
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(
+            Dataset::from_reader(&reader).unwrap().records,
+            dataset.records
+        );
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_ir_writer_builder() {
+        let path = temp_path("builder.daff");
+        let builder = IrWriterBuilder::new(grid(), 1, 32000.0).quantization(Quantization::Int16);
+        let records: Vec<_> = (0..6).map(|i| [vec![i as f32 / 8.0, 0.0, -0.5]]).collect();
+        assert!(builder.write(&path, &records[1..]).is_err());
+        builder.write(&path, &records).unwrap();
+
+        let dataset = read(&path);
+        assert_eq!(
+            dataset.header,
+            ContentHeader::ImpulseResponse {
+                samplerate: 32000.0
+            }
+        );
+        assert_eq!(dataset.quantization, Quantization::Int16);
+        for (record, expected) in dataset.records.iter().zip(&records) {
+            for (value, expected) in record.channels[0].iter().zip(&expected[0]) {
+                assert!((value - expected).abs() < 1e-4);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_ms_writer_builder() {
+        let path = temp_path("ms.daff");
+        let frequencies = vec![100.0, 1000.0, 10000.0];
+        let builder = MsWriterBuilder::new(grid(), 2, frequencies.clone());
+        let records: Vec<_> = (0..6)
+            .map(|i| [vec![1.0, 0.5, i as f32], vec![0.0, 2.0, 0.25]])
+            .collect();
+        builder.write(&path, &records).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let ms = reader.content_ms().unwrap();
+        assert_eq!(ms.frequencies().unwrap(), frequencies);
+        assert_eq!(ms.magnitudes(4, 0).unwrap(), [1.0, 0.5, 4.0]);
+        assert_eq!(ms.magnitudes(4, 1).unwrap(), [0.0, 2.0, 0.25]);
+        drop(reader);
+
+        let mut writer = builder.create(&path).unwrap();
+        assert!(writer.append_record(&[[1.0, -0.5, 1.0]; 2]).is_err());
+        assert!(writer.append_record(&[[1.0, f32::NAN, 1.0]; 2]).is_err());
+        assert_eq!(writer.records_written(), 0);
+        assert!(MsWriterBuilder::new(grid(), 1, vec![1000.0, 100.0])
+            .create(&path)
+            .is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_ps_writer_builder() {
+        let path = temp_path("ps.daff");
+        let frequencies = vec![500.0, 1000.0];
+        let records: Vec<_> = (0..6).map(|i| [vec![-3.0, i as f32 / 4.0]]).collect();
+        PsWriterBuilder::new(grid(), 1, frequencies.clone())
+            .write(&path, &records)
+            .unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.content_type(), ContentType::PhaseSpectrum);
+        let ps = reader.content_ps().unwrap();
+        assert_eq!(ps.frequencies().unwrap(), frequencies);
+        assert_eq!(ps.phases(2, 0).unwrap(), [-3.0, 0.5]);
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_mps_writer_builder() {
+        let path = temp_path("mps.daff");
+        let builder = MpsWriterBuilder::new(grid(), 2, vec![100.0, 1000.0, 10000.0]);
+        let magnitudes: Vec<_> = (0..6)
+            .map(|i| vec![vec![1.0, 0.5, i as f32], vec![2.0, 0.0, 0.25]])
+            .collect();
+        let phases: Vec<_> = (0..6)
+            .map(|_| vec![vec![0.0, 1.5, -0.5], vec![3.0, 0.0, -2.0]])
+            .collect();
+        builder
+            .write_magnitude_phase(&path, &magnitudes, &phases)
+            .unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let mps = reader.content_mps().unwrap();
+        assert_eq!(mps.num_frequencies(), 3);
+        for channel in 0..2 {
+            let (m, p) = mps.coefficients(4, channel as i32).unwrap();
+            for k in 0..3 {
+                assert!((m[k] - magnitudes[4][channel][k]).abs() < 1e-6);
+                if m[k] > 0.0 {
+                    assert!((p[k] - phases[4][channel][k]).abs() < 1e-5);
+                }
+            }
+        }
+        drop(reader);
+
+        assert!(builder
+            .write_magnitude_phase(&path, &magnitudes, &phases[..5])
+            .is_err());
+        let mut writer = builder.create(&path).unwrap();
+        assert!(writer
+            .append_magnitude_phase_record(&[[1.0, 1.0, 1.0]; 2], &[[0.0, 0.0]; 2])
+            .is_err());
+        assert!(writer
+            .append_magnitude_phase_record(&[[1.0, -1.0, 1.0]; 2], &[[0.0; 3]; 2])
+            .is_err());
+        assert!(writer
+            .append_magnitude_phase_record(&[[1.0; 3]; 2], &[[0.0; 3]; 1])
+            .is_err());
+        assert_eq!(writer.records_written(), 0);
+        let mut writer = PsWriterBuilder::new(grid(), 1, vec![100.0])
+            .create(&path)
+            .unwrap();
+        assert!(writer
+            .append_magnitude_phase_record(&[[1.0]], &[[0.0]])
+            .is_err());
+        std::fs::remove_file(sibling(&path, PART_EXTENSION)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_dft_writer_builder() {
+        let path = temp_path("dft.daff");
+        let builder = DftWriterBuilder::new(grid(), 1, 48000.0, 6);
+        assert_eq!(builder.num_coefficients(), 4);
+        assert_eq!(builder.frequency_resolution(), 8000.0);
+        let record = [vec![1.0, 0.0, 0.5, -0.5, 0.0, 0.25, 2.0, 0.0]];
+        builder.write(&path, &vec![record.clone(); 6]).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        let dft = reader.content_dft().unwrap();
+        assert!(dft.is_symmetric());
+        assert_eq!(dft.transform_size(), 6);
+        assert_eq!(dft.num_dft_coeffs(), 4);
+        assert_eq!(dft.samplerate(), 48000.0);
+        assert_eq!(dft.dft_coeffs(5, 0).unwrap(), record[0]);
+        drop(reader);
+
+        let full = DftWriterBuilder::new(grid(), 1, 48000.0, 6).full_spectrum();
+        assert_eq!(full.num_coefficients(), 6);
+        assert!(full.write(&path, &vec![record; 6]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "complex")]
+    #[test]
+    #[cfg_attr(miri, ignore = "reads back through the C++ library")]
+    fn test_dft_write_complex() {
+        let path = temp_path("dft-complex.daff");
+        let record = [vec![
+            Complex::new(1.0, 0.0),
+            Complex::new(0.0, -1.0),
+            Complex::new(0.5, 0.0),
+        ]];
+        DftWriterBuilder::new(grid(), 1, 44100.0, 4)
+            .write_complex(&path, &vec![record; 6])
+            .unwrap();
+        let dataset = read(&path);
+        assert_eq!(
+            dataset.records[2].channels[0],
+            [1.0, 0.0, 0.0, -1.0, 0.5, 0.0]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,7 +3,18 @@
 //! Note: These tests require actual DAFF files to run.
 //! Place test files in the testdata/ directory to enable these tests.
 
-use opendaff::Reader;
+use std::sync::{Arc, Mutex};
+
+use opendaff::embedded::EmbeddedDataset;
+use opendaff::metadata::{self, ScrubPolicy};
+use opendaff::{ContentType, Dataset, EquiangularGrid, Error, IrWriterBuilder, Reader};
+
+/// Example directivity shipped with the C++ deserializer tests
+const EXAMPLE_MS: &str = "../../tests/deserializertest/ExampleUnityOmni.v17.ms.daff";
+
+/// The same file, embedded in the test binary
+static EMBEDDED_MS: EmbeddedDataset =
+    opendaff::include_daff!("../../../tests/deserializertest/ExampleUnityOmni.v17.ms.daff");
 
 #[test]
 fn test_reader_creation() {
@@ -21,14 +32,366 @@ fn test_reader_invalid_file() {
     assert!(result.is_err(), "Should fail to open non-existent file");
 }
 
+#[test]
+fn test_queries_without_open_file() {
+    let reader = Reader::new().unwrap();
+    assert_eq!(reader.num_channels(), -1);
+    assert_eq!(reader.num_records(), -1);
+    assert_eq!(reader.alpha_start(), -1.0);
+    assert_eq!(reader.beta_span(), -1.0);
+    assert!(reader.orientation().is_err());
+    assert!(!reader.has_metadata("DESCRIPTION"));
+    assert!(reader.metadata_string("DESCRIPTION").is_err());
+    assert!(reader.metadata_keys().is_empty());
+    assert!(reader.content_ir().is_err());
+    assert!(reader.content_ms().is_err());
+}
+
+#[test]
+fn test_embedded_dataset() {
+    assert!(!EMBEDDED_MS.is_open());
+    assert_eq!(EMBEDDED_MS.bytes(), std::fs::read(EXAMPLE_MS).unwrap());
+
+    let reader = Reader::open(EXAMPLE_MS).unwrap();
+    let embedded = EMBEDDED_MS.reader().unwrap();
+    assert!(EMBEDDED_MS.is_open());
+    assert_eq!(embedded.content_type(), ContentType::MagnitudeSpectrum);
+    assert_eq!(
+        Dataset::from_reader(embedded).unwrap(),
+        Dataset::from_reader(&reader).unwrap()
+    );
+    // Later calls share the reader opened first
+    assert!(std::ptr::eq(embedded, EMBEDDED_MS.reader().unwrap()));
+
+    static INVALID: EmbeddedDataset = EmbeddedDataset::new(b"FW");
+    assert!(INVALID.reader().is_err());
+    assert!(INVALID.reader().is_err());
+    assert!(!INVALID.is_open());
+}
+
+#[test]
+fn test_access_after_close() {
+    let mut reader = Reader::new().unwrap();
+    assert_eq!(reader.orientation().unwrap_err(), Error::Closed);
+    reader.open_file(EXAMPLE_MS).unwrap();
+    assert!(reader.content_ms().is_ok());
+    reader.close();
+    reader.close();
+
+    assert!(!reader.is_valid());
+    assert!(matches!(reader.content_ms(), Err(Error::Closed)));
+    assert!(matches!(reader.content_ir(), Err(Error::Closed)));
+    assert_eq!(reader.orientation().unwrap_err(), Error::Closed);
+    assert_eq!(reader.metadata_string("DESCRIPTION").unwrap_err(), Error::Closed);
+    assert_eq!(reader.metadata().unwrap_err(), Error::Closed);
+    assert_eq!(reader.metadata_int("VERSION").unwrap_err(), Error::Closed);
+    assert_eq!(reader.record_metadata(0).unwrap_err(), Error::Closed);
+    assert_eq!(Dataset::from_reader(&reader).unwrap_err(), Error::Closed);
+    assert_eq!(reader.num_records(), -1);
+
+    reader.open_file(EXAMPLE_MS).unwrap();
+    assert!(reader.content_ms().is_ok());
+}
+
+#[test]
+fn test_properties() {
+    let mut reader = Reader::open(EXAMPLE_MS).unwrap();
+    let properties = reader.properties().unwrap();
+    assert_eq!(properties.content_type, ContentType::MagnitudeSpectrum);
+    assert_eq!(Some(properties.quantization), reader.quantization());
+    assert_eq!(properties.num_channels as i32, reader.num_channels());
+    assert_eq!(properties.num_records as i32, reader.num_records());
+    assert_eq!(properties.alpha_resolution, reader.alpha_resolution());
+    assert_eq!(properties.beta_points as i32, reader.beta_points());
+    assert_eq!(properties.grid(), reader.grid().unwrap());
+    assert_eq!(properties.alpha_start, reader.alpha_start());
+    assert_eq!(properties.alpha_end, reader.alpha_end());
+    assert_eq!(properties.beta_start, reader.beta_start());
+    assert_eq!(properties.beta_end, reader.beta_end());
+    assert_eq!(properties.orientation, reader.orientation().unwrap());
+    assert_eq!(properties.file_format_version, 170);
+    assert_eq!(reader.file_format_version().unwrap(), 170);
+    assert_eq!(reader.file_size_bytes().unwrap(), std::fs::metadata(EXAMPLE_MS).unwrap().len());
+    assert!(reader.coverage().unwrap().is_full_sphere());
+    assert!(properties.to_string().contains("Magnitude Spectrum"), "{}", properties);
+    assert!(properties.to_string().ends_with("DAFF 1.7"), "{}", properties);
+
+    reader.close();
+    assert_eq!(reader.properties().unwrap_err(), Error::Closed);
+    assert_eq!(reader.file_size_bytes().unwrap_err(), Error::Closed);
+}
+
+#[test]
+fn test_partial_sphere_ranges() {
+    let grid = EquiangularGrid { beta_start: 30.0, ..EquiangularGrid::with_resolution(90.0, 30.0).unwrap() };
+    let dataset = Dataset::from_fn(
+        opendaff::ContentHeader::ImpulseResponse { samplerate: 44100.0 },
+        grid,
+        1,
+        |alpha, beta, _| vec![alpha, beta],
+    );
+    let path = std::env::temp_dir().join(format!("opendaff-{}-partial.daff", std::process::id()));
+    opendaff::writer::write_dataset(&path, &dataset).unwrap();
+    let reader = Reader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((reader.alpha_start(), reader.alpha_end()), (0.0, 360.0));
+    assert_eq!((reader.beta_start(), reader.beta_end()), (30.0, 180.0));
+    assert_eq!(reader.alpha_span(), 360.0);
+    assert_eq!(reader.beta_span(), 150.0);
+    let coverage = reader.coverage().unwrap();
+    assert!(!coverage.is_full_sphere());
+    assert!(coverage.contains(0.0, 90.0));
+    assert!(!coverage.contains(0.0, 10.0));
+}
+
+#[test]
+fn test_magnitude_unit() {
+    use opendaff::{ContentHeader, MagnitudeUnit};
+
+    let reader = Reader::open(EXAMPLE_MS).unwrap();
+    assert_eq!(reader.magnitude_unit().unwrap(), Some(MagnitudeUnit::Linear));
+    assert_eq!(Reader::new().unwrap().magnitude_unit().unwrap_err(), Error::Closed);
+
+    // Magnitudes of a magnitude-phase spectrum stored in dB
+    let grid = EquiangularGrid::with_resolution(90.0, 90.0).unwrap();
+    let header = ContentHeader::MagnitudePhaseSpectrum { frequencies: vec![1000.0, 2000.0] };
+    let mut dataset = Dataset::from_fn(header, grid, 1, |_, _, _| vec![6.0, 0.0, 0.0, -20.0]);
+    dataset.set_magnitude_unit(MagnitudeUnit::Decibel);
+    let path = std::env::temp_dir().join(format!("opendaff-{}-mps-db.daff", std::process::id()));
+    opendaff::writer::write_dataset(&path, &dataset).unwrap();
+    let reader = Reader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(reader.magnitude_unit().unwrap(), Some(MagnitudeUnit::Decibel));
+    let loaded = Dataset::from_reader(&reader).unwrap();
+    assert_eq!(loaded.magnitude_unit(), Some(MagnitudeUnit::Linear));
+    let data = &loaded.records[0].channels[0];
+    assert!((data[0] - 10f32.powf(6.0 / 20.0)).abs() < 1e-3);
+    assert!(data[2].abs() < 1e-4 && (data[3] + 10.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_phase_spectrum_frequencies() {
+    use opendaff::writer::PsWriterBuilder;
+
+    // The frequency list follows the frequency count directly, unlike in magnitude spectra
+    let grid = EquiangularGrid::with_resolution(90.0, 90.0).unwrap();
+    let frequencies = vec![125.0, 250.0, 500.0, 1000.0, 2000.0];
+    let records = vec![vec![vec![0.5, -0.5, 1.0, -1.0, 3.0]]; grid.num_records()];
+    let path = std::env::temp_dir().join(format!("opendaff-{}-ps-frequencies.daff", std::process::id()));
+    PsWriterBuilder::new(grid, 1, frequencies.clone()).write(&path, &records).unwrap();
+    let reader = Reader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let content = reader.content_ps().unwrap();
+    assert_eq!(content.num_frequencies(), 5);
+    assert_eq!(content.frequencies().unwrap(), frequencies);
+    assert_eq!(content.phases(0, 0).unwrap(), records[0][0]);
+}
+
+#[test]
+fn test_invalid_indices_are_errors() {
+    let mut reader = Reader::open(EXAMPLE_MS).unwrap();
+    assert!(reader.open_file(EXAMPLE_MS).is_err());
+
+    let ms = reader.content_ms().unwrap();
+    let num_records = reader.num_records();
+    let num_channels = reader.num_channels();
+    for (record, channel) in [(-1, 0), (num_records, 0), (0, -1), (0, num_channels)] {
+        let error = ms.magnitudes(record, channel).unwrap_err();
+        assert!(error.to_string().contains("Invalid"), "{}", error);
+    }
+    assert!(ms.record_coords(num_records).is_err());
+    assert!(ms.magnitudes(num_records - 1, num_channels - 1).is_ok());
+    assert!(reader.content_ir().is_err());
+}
+
+#[test]
+fn test_file_size_bytes() {
+    let size = std::fs::metadata(EXAMPLE_MS).unwrap().len();
+    let mut reader = Reader::open(EXAMPLE_MS).unwrap();
+    assert_eq!(reader.file_size_bytes().unwrap(), size);
+    reader.close();
+    assert_eq!(reader.file_size_bytes().unwrap_err(), Error::Closed);
+
+    let bytes = std::fs::read(EXAMPLE_MS).unwrap();
+    reader.open_bytes(&bytes).unwrap();
+    assert_eq!(reader.file_size_bytes().unwrap(), size);
+    reader.close();
+
+    // Data following the DAFF data in a stream does not count
+    let mut stream = bytes;
+    stream.extend_from_slice(b"trailer");
+    reader.open_stream(std::io::Cursor::new(stream)).unwrap();
+    assert_eq!(reader.file_size_bytes().unwrap(), size);
+}
+
+#[test]
+fn test_reader_lifecycle_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let (open, close, decoded) = (events.clone(), events.clone(), events.clone());
+    let mut reader = Reader::builder()
+        .on_open(move |filename| open.lock().unwrap().push(format!("open {}", filename)))
+        .on_close(move |filename| close.lock().unwrap().push(format!("close {}", filename)))
+        .on_record_decoded(move |content_type, record, channel| {
+            let event = format!("{} {} {}", content_type, record, channel);
+            decoded.lock().unwrap().push(event);
+        })
+        .build()
+        .unwrap();
+    assert!(reader.open_file("nonexistent_file.daff").is_err());
+    reader.open_file(EXAMPLE_MS).unwrap();
+    reader.content_ms().unwrap().magnitudes(1, 0).unwrap();
+    reader.close();
+    reader.close();
+    reader.open_file(EXAMPLE_MS).unwrap();
+    reader.close();
+    // Data opened from memory has no file name to report
+    reader.open_bytes(&std::fs::read(EXAMPLE_MS).unwrap()).unwrap();
+    drop(reader);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            format!("open {}", EXAMPLE_MS),
+            "Magnitude Spectrum 1 0".to_string(),
+            format!("close {}", EXAMPLE_MS),
+            format!("open {}", EXAMPLE_MS),
+            format!("close {}", EXAMPLE_MS),
+        ]
+    );
+}
+
+#[test]
+fn test_metadata_map() {
+    let reader = Reader::open(EXAMPLE_MS).unwrap();
+    let values = reader.metadata().unwrap();
+
+    assert_eq!(values.len(), reader.metadata_keys().len());
+    for (key, value) in &values {
+        assert_eq!(reader.metadata_type(key), Some(value.value_type()));
+        assert_eq!(&reader.metadata_value(key).unwrap(), value);
+    }
+    let description = values["DESCRIPTION"].as_str().unwrap();
+    assert_eq!(description, reader.metadata_string("DESCRIPTION").unwrap());
+    assert!(reader.metadata_value("NO SUCH KEY").is_err());
+}
+
+#[test]
+fn test_metadata_int() {
+    use opendaff::MetadataValue;
+
+    let grid = EquiangularGrid::with_resolution(90.0, 90.0).unwrap();
+    let header = opendaff::ContentHeader::ImpulseResponse { samplerate: 44100.0 };
+    let mut dataset = Dataset::from_fn(header, grid, 1, |_, _, _| vec![1.0, 0.0]);
+    dataset.metadata.insert("SESSION".into(), MetadataValue::Int(-7));
+    dataset.metadata.insert("GAIN".into(), MetadataValue::Float(1.5));
+    let path = std::env::temp_dir().join(format!("opendaff-{}-int.daff", std::process::id()));
+    opendaff::writer::write_dataset(&path, &dataset).unwrap();
+    let reader = Reader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(reader.metadata_int("session").unwrap(), -7);
+    assert_eq!(reader.metadata_float("SESSION").unwrap(), -7.0);
+    assert_eq!(reader.metadata_value("SESSION").unwrap(), MetadataValue::Int(-7));
+    let error = reader.metadata_int("GAIN").unwrap_err();
+    assert!(error.to_string().contains("not an integer"), "{}", error);
+    assert!(reader.metadata_int("MISSING").is_err());
+}
+
+#[test]
+fn test_record_metadata() {
+    use opendaff::writer::WriterBuilder;
+    use opendaff::MetadataValue;
+
+    let grid = EquiangularGrid::with_resolution(90.0, 90.0).unwrap();
+    let header = opendaff::ContentHeader::MagnitudeSpectrum { frequencies: vec![1000.0] };
+    let dataset = Dataset::from_fn(header, grid, 1, |_, _, _| vec![1.0]);
+    let path = std::env::temp_dir().join(format!("opendaff-{}-record-metadata.daff", std::process::id()));
+    WriterBuilder::from_dataset(&dataset)
+        .unwrap()
+        .record_metadata(2, "MIC_GAIN", 1.25)
+        .record_metadata(2, "OPERATOR", "jd")
+        .record_metadata(2, "REPEATED", true)
+        .record_metadata(3, "TAKE", 4)
+        .write_iter(&path, dataset.elements_per_record(), dataset.records.iter().map(|r| &r.channels))
+        .unwrap();
+    let reader = Reader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let metadata = reader.record_metadata(2).unwrap();
+    assert_eq!(metadata.len(), 3);
+    assert_eq!(metadata["MIC_GAIN"], MetadataValue::Float(1.25));
+    assert_eq!(metadata["OPERATOR"], MetadataValue::String("jd".into()));
+    assert_eq!(metadata["REPEATED"], MetadataValue::Bool(true));
+    let ms = reader.content_ms().unwrap();
+    assert_eq!(ms.record_metadata(3).unwrap()["TAKE"], MetadataValue::Int(4));
+    assert!(ms.record_metadata(0).unwrap().is_empty());
+    assert!(reader.record_metadata(reader.num_records()).is_err());
+    assert!(reader.record_metadata(-1).is_err());
+    // Record metadata is separate from the global metadata
+    assert!(!reader.metadata().unwrap().contains_key("TAKE"));
+}
+
+#[test]
+fn test_dataset_from_example_file() -> Result<(), Box<dyn std::error::Error>> {
+    let reader = Reader::open(EXAMPLE_MS)?;
+    let mut dataset = Dataset::from_reader(&reader)?;
+
+    assert_eq!(dataset.content_type(), ContentType::MagnitudeSpectrum);
+    assert_eq!(dataset.num_records(), reader.num_records() as usize);
+    assert_eq!(dataset.elements_per_record(), 31);
+    assert!(dataset.metadata.contains_key("CREATION DATE"));
+
+    metadata::scrub(&mut dataset, &ScrubPolicy::default());
+    assert!(!dataset.metadata.contains_key("CREATION DATE"));
+    assert!(dataset.metadata.contains_key("DESCRIPTION"));
+
+    Ok(())
+}
+
 // Integration tests with actual files would go here
 // Uncomment and add test files to enable
 
+#[test]
+fn test_ir_snapshot_to_worker_thread() {
+    let path = std::env::temp_dir().join(format!("opendaff-{}-snapshot.daff", std::process::id()));
+    let grid = EquiangularGrid {
+        alpha_points: 4,
+        alpha_start: 0.0,
+        alpha_end: 360.0,
+        beta_points: 3,
+        beta_start: 0.0,
+        beta_end: 180.0,
+    };
+    let records: Vec<_> = (0..6).map(|i| [vec![i as f32, 0.5], vec![0.0, -1.0]]).collect();
+    IrWriterBuilder::new(grid, 2, 48000.0).write(&path, &records).unwrap();
+
+    let reader = Reader::open(&path).unwrap();
+    let ir = reader.content_ir().unwrap();
+    assert!(ir.snapshot(2..7).is_err());
+    assert_eq!(ir.snapshot(..).unwrap().range(), 0..6);
+    let snapshot = Arc::new(ir.snapshot(2..=4).unwrap());
+    drop(reader);
+    std::fs::remove_file(&path).unwrap();
+
+    let worker = {
+        let snapshot = Arc::clone(&snapshot);
+        std::thread::spawn(move || snapshot.filter_coeffs(3, 0).map(<[f32]>::to_vec))
+    };
+    assert_eq!(worker.join().unwrap(), Some(vec![3.0, 0.5]));
+    assert_eq!(snapshot.range(), 2..5);
+    assert_eq!(snapshot.samplerate(), 48000.0);
+    assert_eq!(snapshot.filter_length(), 2);
+    assert_eq!(snapshot.record(4).unwrap().channels[1], [0.0, -1.0]);
+    assert!(snapshot.record(1).is_none() && snapshot.record(5).is_none());
+}
+
 /*
 #[test]
 fn test_open_ir_file() -> Result<(), Box<dyn std::error::Error>> {
-    let mut reader = Reader::new()?;
-    reader.open_file("testdata/impulse_response.daff")?;
+    let mut reader = Reader::open("testdata/impulse_response.daff")?;
 
     assert!(reader.is_valid());
     assert_eq!(reader.content_type(), ContentType::ImpulseResponse);
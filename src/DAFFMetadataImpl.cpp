@@ -3,10 +3,19 @@
 #include <algorithm>
 #include <cassert>
 #include <iomanip>
+#include <locale>
 #include <sstream>
 
 #include "Utils.h"
 
+// Upper-case conversion restricted to ASCII, so that multi-byte (UTF-8) characters
+// in key names are neither altered by the current locale nor passed to toupper as
+// negative values
+static char toUpperASCII(char c)
+{
+	return (c >= 'a' && c <= 'z') ? (char)(c - 'a' + 'A') : c;
+}
+
 class DAFFMetadataKey {
   public:
 	int m_iType;
@@ -153,6 +162,7 @@ std::string DAFFMetadataImpl::getKeyString(const std::string& sKey) const
 		return "";
 
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 	switch (pKey->m_iType) {
 	case DAFF_BOOL:
 		return (dynamic_cast<const DAFFMetadataKeyBool*>(pKey)->m_bValue ? "yes" : "no");
@@ -216,6 +226,7 @@ double DAFFMetadataImpl::getKeyFloat(const std::string& sKey) const
 std::string DAFFMetadataImpl::toString() const
 {
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 	for (KeyMapConstIterator cit = m_mKeys.begin(); cit != m_mKeys.end(); ++cit) {
 		ss << cit->first << " = " << getKeyString(cit->first) << std::endl;
 	}
@@ -227,7 +238,7 @@ void DAFFMetadataImpl::insertKey(const std::string& sName, DAFFMetadataKey* pKey
 {
 	// For later key search: Ensure that the keyname is upper case
 	std::string sKeyUpper(sName);
-	std::transform(sKeyUpper.begin(), sKeyUpper.end(), sKeyUpper.begin(), (int (*)(int))::toupper);
+	std::transform(sKeyUpper.begin(), sKeyUpper.end(), sKeyUpper.begin(), toUpperASCII);
 
 	m_mKeys[sKeyUpper] = pKey;
 };
@@ -240,7 +251,7 @@ const DAFFMetadataKey* DAFFMetadataImpl::findKey(const std::string& sKey) const
 
 	// Convert the keyname to upper case
 	std::string sKeyUpper(sKey);
-	std::transform(sKeyUpper.begin(), sKeyUpper.end(), sKeyUpper.begin(), (int (*)(int))::toupper);
+	std::transform(sKeyUpper.begin(), sKeyUpper.end(), sKeyUpper.begin(), toUpperASCII);
 
 	// Search in the keymap
 	KeyMapConstIterator cit = m_mKeys.find(sKeyUpper);
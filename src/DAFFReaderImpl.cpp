@@ -6,6 +6,7 @@
 #include <cmath>
 #include <cstdio>
 #include <cstdlib>
+#include <locale>
 #include <sstream>
 
 #include "DAFFHeader.h"
@@ -527,7 +528,9 @@ int DAFFReaderImpl::loadContentHeader()
 		if (m_pContentHeaderPS->iNumFreqs <= 0)
 			return DAFF_FILE_CONTENT_INVALID_PARAMETER;
 
-		pfFreqs = (float*)((char*)m_pContentHeader + 8);
+		// Unlike magnitude spectra, the header has no maximum magnitude: the frequency list
+		// follows the number of frequencies directly
+		pfFreqs = (float*)((char*)m_pContentHeader + 4);
 
 		// Fix the endianness of the frequency list
 		DAFF::le2se_4byte(pfFreqs, m_pContentHeaderPS->iNumFreqs);
@@ -833,6 +836,7 @@ std::string DAFFReaderImpl::toString() const
 	assert(m_bDAFFObjectValid);
 
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 
 	float fVersion = getFileFormatVersion() / 1000.0F;
 	;
@@ -927,6 +931,7 @@ std::string DAFFReaderImpl::getChannelLabel(int iChannel) const
 
 	// Fetch the channel name from the metadata
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 	ss << "LABEL_CHANNEL_" << (iChannel + 1);
 	return ((m_vpMetadata.size() > 0) && (m_vpMetadata[0]->hasKey(ss.str())) ? m_vpMetadata[0]->getKeyString(ss.str())
 																			 : "");
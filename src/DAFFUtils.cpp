@@ -6,6 +6,7 @@
 #include <cmath>
 #include <cstring>
 #include <iomanip>
+#include <locale>
 
 // Define necessary roundf for Microsoft compilers
 #ifdef _MSC_VER
@@ -26,6 +27,7 @@ std::string DAFFUtils::Float2StrNice(float f, int precision, bool showpos, int l
 {
 	// First convert to fixed format (with enough digits)
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 	if (showpos)
 		ss << (f < 0.0f ? "-" : "+");
 	ss << std::fixed << std::setprecision(precision);
@@ -37,8 +39,10 @@ std::string DAFFUtils::Float2StrNice(float f, int precision, bool showpos, int l
 		ss << f;
 	std::string s = ss.str();
 
-	// Then remove trailing zeros...
+	// Then remove trailing zeros of the fractional part...
 	size_t n = s.length();
+	if (s.find('.') == std::string::npos)
+		return s;
 	while ((n > 0) && (s[n - 1] == '0'))
 		n--;
 
@@ -54,6 +58,7 @@ std::string DAFFUtils::Double2StrNice(double d, int precision, bool showpos, int
 {
 	// First convert to fixed format (with enough digits)
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 	if (showpos)
 		ss << (d < 0.0f ? "-" : "+");
 	ss << std::fixed << std::setprecision(precision);
@@ -65,8 +70,10 @@ std::string DAFFUtils::Double2StrNice(double d, int precision, bool showpos, int
 		ss << d;
 	std::string s = ss.str();
 
-	// Then remove trailing zeros...
+	// Then remove trailing zeros of the fractional part...
 	size_t n = s.length();
+	if (s.find('.') == std::string::npos)
+		return s;
 	while ((n > 0) && (s[n - 1] == '0'))
 		n--;
 
@@ -80,6 +87,7 @@ std::string DAFFUtils::Double2StrNice(double d, int precision, bool showpos, int
 std::string DAFFUtils::StrDirection(int iView, double dAngle1, double dAngle2, int precision, int leadingzeros)
 {
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 
 	switch (iView) {
 	case DAFF_DATA_VIEW:
@@ -106,6 +114,7 @@ std::string DAFFUtils::StrDirection(int iView, double dAngle1, double dAngle2, i
 std::string DAFFUtils::StrDirectionCompact(int iView, double dAngle1, double dAngle2, int precision, int leadingzeros)
 {
 	std::stringstream ss;
+	ss.imbue(std::locale::classic());
 
 	switch (iView) {
 	case DAFF_DATA_VIEW:
@@ -161,6 +170,7 @@ std::string DAFFUtils::StrError(int iErrorcode)
 
 	default: {
 		std::stringstream ss;
+		ss.imbue(std::locale::classic());
 		ss << "Undefined error code '" << iErrorcode << "' encountered";
 		return ss.str();
 	}